@@ -0,0 +1,158 @@
+//! Crawls a local site whose pages answer after randomized latencies, and compares the time the
+//! crawl takes with simulations of the same crawl with no overhead, one of which waits for every
+//! depth level to finish before starting the next one.
+//!
+//! Run with `cargo bench --bench pipeline`.
+
+use rustle::{Config, Crawler, Database, FixtureResponse, FixtureServer};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+
+/// The number of links of every page of the fixture site.
+const FAN_OUT: u64 = 4;
+
+/// The depth of the crawl of the fixture site.
+const DEPTH: u64 = 5;
+
+/// The number of pages fetched at once.
+const CONCURRENCY: usize = 64;
+
+/// The shortest time a page of the fixture site takes to answer.
+const MIN_LATENCY_MS: u64 = 5;
+
+/// The longest time a page of the fixture site takes to answer, reached by one page in twenty.
+const MAX_LATENCY_MS: u64 = 1000;
+
+fn main() {
+    let server = FixtureServer::start(|request| respond(&request.path))
+        .expect("Failed to start the fixture server");
+
+    let pages = (0..=DEPTH)
+        .map(|depth| FAN_OUT.pow(depth as u32))
+        .sum::<u64>();
+    println!(
+        "Fixture site: {} pages, {} links each, {}-{} ms per page, {} at once",
+        pages, FAN_OUT, MIN_LATENCY_MS, MAX_LATENCY_MS, CONCURRENCY
+    );
+    println!(
+        "Simulated without overhead: {:.2} s level-synchronized, {:.2} s pipelined",
+        simulate(true).as_secs_f64(),
+        simulate(false).as_secs_f64()
+    );
+
+    let config = Config {
+        origin_urls: vec![server.url("/page/0")],
+        depth: DEPTH,
+        concurrency: Some(CONCURRENCY),
+        max_concurrent_per_host: CONCURRENCY,
+        final_retry_limit: 0,
+        ..Default::default()
+    };
+
+    let crawler =
+        Crawler::with_storage(config.clone(), Box::new(Database::in_memory().unwrap())).unwrap();
+    let start = Instant::now();
+    let report = crawler.crawl(true).unwrap();
+    println!(
+        "Pipelined crawl: {:.2} s, {} pages",
+        start.elapsed().as_secs_f64(),
+        report.pages_fetched
+    );
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let crawler = Crawler::with_storage(config, Box::new(Database::in_memory().unwrap())).unwrap();
+    let start = Instant::now();
+    let report = runtime.block_on(crawler.crawl_async(true)).unwrap();
+    println!(
+        "Pipelined async crawl: {:.2} s, {} pages",
+        start.elapsed().as_secs_f64(),
+        report.pages_fetched
+    );
+}
+
+/// Gets the time a page of the fixture site takes to answer, spread pseudo-randomly between
+/// `MIN_LATENCY_MS` and `MAX_LATENCY_MS` so that every run gets the same latencies.
+///
+/// # Arguments
+///
+/// * `page` - The number of the page, the origin being 0 and the links of page `n` being
+///   `n * FAN_OUT + 1` to `n * FAN_OUT + FAN_OUT`.
+fn latency(page: u64) -> Duration {
+    let mut hash = page.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    hash ^= hash >> 31;
+    let millis = if hash.is_multiple_of(20) {
+        MAX_LATENCY_MS
+    } else {
+        MIN_LATENCY_MS + hash % (MAX_LATENCY_MS / 10)
+    };
+    return Duration::from_millis(millis);
+}
+
+/// Simulates a crawl of the fixture site with no overhead beyond the latency of its pages,
+/// fetching the shallowest pages first with `CONCURRENCY` of them in flight at once.
+///
+/// # Arguments
+///
+/// * `level_synchronized` - Whether the pages of a depth level wait for every page of the
+///   level above it to be fetched, rather than only for the page linking to them.
+fn simulate(level_synchronized: bool) -> Duration {
+    let mut now = Duration::ZERO;
+    let mut ready = BinaryHeap::from([Reverse((0, 0))]);
+    let mut next_level = Vec::new();
+    let mut in_flight = BinaryHeap::new();
+    loop {
+        while in_flight.len() < CONCURRENCY {
+            let Some(Reverse((depth, page))) = ready.pop() else {
+                break;
+            };
+            in_flight.push(Reverse((now + latency(page), depth, page)));
+        }
+
+        let Some(Reverse((finished, depth, page))) = in_flight.pop() else {
+            return now;
+        };
+        now = finished;
+        if depth < DEPTH {
+            let children = (1..=FAN_OUT).map(|child| Reverse((depth + 1, page * FAN_OUT + child)));
+            if level_synchronized {
+                next_level.extend(children);
+            } else {
+                ready.extend(children);
+            }
+        }
+        if in_flight.is_empty() && ready.is_empty() {
+            ready.extend(next_level.drain(..));
+        }
+    }
+}
+
+/// Answers a request for a path once the latency of the requested page has passed, with links
+/// to its children, or with a 404 for anything but a page.
+///
+/// # Arguments
+///
+/// * `path` - A string slice that holds the requested path.
+fn respond(path: &str) -> FixtureResponse {
+    let page = path
+        .strip_prefix("/page/")
+        .and_then(|page| page.parse::<u64>().ok());
+    return match page {
+        Some(page) => {
+            std::thread::sleep(latency(page));
+            let links: String = (1..=FAN_OUT)
+                .map(|child| format!("<a href=\"/page/{}\">Page</a>\n", page * FAN_OUT + child))
+                .collect();
+            FixtureResponse::html(
+                200,
+                format!(
+                    "<html><head><title>Page {page}</title></head><body>\n{links}</body></html>"
+                ),
+            )
+        }
+        None => FixtureResponse::html(404, "<html></html>"),
+    };
+}
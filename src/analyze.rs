@@ -0,0 +1,335 @@
+use crate::database::Database;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use url::Url;
+
+/// Thresholds that a pattern must meet before it is suggested as a canonicalization rule.
+///
+/// Requiring several independent pairs, and that most URLs showing the pattern have a matching
+/// variant, keeps one-off coincidences from turning into site-wide rules.
+pub struct SuggestionThresholds {
+    /// The minimum number of URL pairs supporting a suggestion.
+    pub min_evidence: usize,
+    /// The minimum fraction of URLs showing the pattern that have a stored variant with the same
+    /// content.
+    pub min_ratio: f64,
+}
+
+impl Default for SuggestionThresholds {
+    fn default() -> Self {
+        return SuggestionThresholds {
+            min_evidence: 3,
+            min_ratio: 0.5,
+        };
+    }
+}
+
+/// Represents a suggested canonicalization rule and the stored data supporting it.
+pub struct RuleSuggestion {
+    /// A human-readable description of the rule.
+    pub rule: String,
+    /// The number of URL pairs that the rule would merge.
+    pub evidence: usize,
+    /// The number of stored URLs showing the pattern, with or without a stored variant.
+    pub occurrences: usize,
+    /// A few of the URL pairs that the rule would merge.
+    pub examples: Vec<(String, String)>,
+}
+
+/// Implements the `Display` trait for the `RuleSuggestion` struct.
+///
+/// The formatted string shows the rule, its evidence counts, and the first example pair.
+impl std::fmt::Display for RuleSuggestion {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} ({} of {} URLs have a variant",
+            self.rule, self.evidence, self.occurrences
+        )?;
+        if let Some((from, to)) = self.examples.first() {
+            write!(fmt, ", e.g. {} = {}", from, to)?;
+        }
+        write!(fmt, ")")
+    }
+}
+
+/// The number of example pairs kept per suggestion.
+const MAX_EXAMPLES: usize = 3;
+
+/// Proposes canonicalization rules from the URLs stored in the database.
+///
+/// Two kinds of rules are proposed:
+/// - "strip parameter `key`", when URLs carrying `key` are also stored without it.
+/// - "trailing slash equivalent under /prefix/", when paths are stored both with and without a
+///   trailing slash.
+///
+/// A pair of URLs only counts as evidence when both are the same page: they share a content hash,
+/// or, when either hash is unknown, a title. Near-identical URLs serving different pages count
+/// against the rule instead.
+///
+/// The analysis is read-only; accepting a suggestion is left to the user.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the crawled sites.
+/// * `thresholds` - A reference to the `SuggestionThresholds` that suggestions must meet.
+///
+/// # Returns
+///
+/// A `Result<Vec<RuleSuggestion>>` sorted by evidence, strongest first.
+pub fn suggest_rules(
+    database: &Database,
+    thresholds: &SuggestionThresholds,
+) -> Result<Vec<RuleSuggestion>> {
+    let fingerprints = database
+        .list_fingerprints()?
+        .into_iter()
+        .map(|(url, content_hash, title)| (url, (content_hash, title)))
+        .collect::<BTreeMap<String, (Option<String>, Option<String>)>>();
+
+    // Checks whether a URL has a stored variant that is the same page
+    let same_page = |url: &str, variant: &str| {
+        let ((hash, title), (variant_hash, variant_title)) =
+            match (fingerprints.get(url), fingerprints.get(variant)) {
+                (Some(fingerprint), Some(variant_fingerprint)) => {
+                    (fingerprint, variant_fingerprint)
+                }
+                _ => return false,
+            };
+        return match (hash, variant_hash) {
+            (Some(hash), Some(variant_hash)) => hash == variant_hash,
+            _ => title.is_some() && title == variant_title,
+        };
+    };
+
+    // Collect the occurrences of every pattern, along with the pairs that are the same page
+    let mut patterns: BTreeMap<String, (usize, Vec<(String, String)>)> = BTreeMap::new();
+    for url in fingerprints.keys() {
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+
+        // Query parameters that could be stripped
+        let pairs = parsed
+            .query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<(String, String)>>();
+        for key in pairs.iter().map(|(k, _)| k).collect::<HashSet<&String>>() {
+            let mut stripped = parsed.clone();
+            let remaining = pairs.iter().filter(|(k, _)| k != key);
+            if pairs.iter().all(|(k, _)| k == key) {
+                stripped.set_query(None);
+            } else {
+                stripped.query_pairs_mut().clear().extend_pairs(remaining);
+            }
+
+            let entry = patterns
+                .entry(format!("strip parameter `{}`", key))
+                .or_default();
+            entry.0 += 1;
+            if same_page(url, stripped.as_str()) {
+                entry.1.push((url.clone(), stripped.to_string()));
+            }
+        }
+
+        // Trailing slashes that could be removed, grouped by the first path segment
+        let path = parsed.path();
+        if path.len() > 1 && path.ends_with('/') {
+            let prefix = match path[1..].find('/') {
+                Some(end) => &path[..end + 2],
+                None => "/",
+            };
+            let mut stripped = parsed.clone();
+            stripped.set_path(path.trim_end_matches('/'));
+
+            let entry = patterns
+                .entry(format!("trailing slash equivalent under {}", prefix))
+                .or_default();
+            entry.0 += 1;
+            if same_page(url, stripped.as_str()) {
+                entry.1.push((url.clone(), stripped.to_string()));
+            }
+        }
+    }
+
+    // Keep the patterns that meet the thresholds
+    let mut suggestions = patterns
+        .into_iter()
+        .filter(|(_, (occurrences, pairs))| {
+            pairs.len() >= thresholds.min_evidence
+                && pairs.len() as f64 / *occurrences as f64 >= thresholds.min_ratio
+        })
+        .map(|(rule, (occurrences, mut pairs))| {
+            let evidence = pairs.len();
+            pairs.sort();
+            pairs.truncate(MAX_EXAMPLES);
+            RuleSuggestion {
+                rule,
+                evidence,
+                occurrences,
+                examples: pairs,
+            }
+        })
+        .collect::<Vec<RuleSuggestion>>();
+    suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.evidence));
+
+    return Ok(suggestions);
+}
+
+/// Represents a page served as HTML from a path where uploaded files are stored.
+///
+/// Such pages usually point at a server that serves uploads with the type they were given,
+/// which lets anyone who can upload a file run scripts on the site (stored XSS).
+pub struct UploadHtmlFinding {
+    /// The URL of the page.
+    pub url: String,
+    /// The upload path pattern the URL matched.
+    pub pattern: String,
+    /// The media type declared by the `Content-Type` header of the response.
+    pub declared_type: String,
+    /// The media type sniffed from the first bytes of the body, if it matched a known signature.
+    pub sniffed_type: Option<String>,
+}
+
+/// Implements the `Display` trait for the `UploadHtmlFinding` struct.
+///
+/// The formatted string shows the URL, the pattern it matched, and the declared and sniffed types.
+impl std::fmt::Display for UploadHtmlFinding {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} is served as {} under {} (sniffed as {})",
+            self.url,
+            self.declared_type,
+            self.pattern,
+            self.sniffed_type.as_deref().unwrap_or("unknown")
+        )
+    }
+}
+
+/// Finds the pages served as HTML from paths where uploaded files are stored.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the crawled sites.
+/// * `patterns` - A slice of the path fragments of upload paths, such as `/uploads/`.
+/// * `html_content_types` - A slice of the media types treated as HTML.
+///
+/// # Returns
+///
+/// A `Result<Vec<UploadHtmlFinding>>` sorted by URL.
+pub fn find_upload_html(
+    database: &Database,
+    patterns: &[String],
+    html_content_types: &[String],
+) -> Result<Vec<UploadHtmlFinding>> {
+    let mut findings = Vec::new();
+    for url in database.list_by_content_type(html_content_types)? {
+        let path = match Url::parse(&url) {
+            Ok(parsed) => parsed.path().to_lowercase(),
+            Err(_) => continue,
+        };
+        let pattern = match patterns
+            .iter()
+            .find(|pattern| path.contains(&pattern.to_lowercase()))
+        {
+            Some(pattern) => pattern,
+            None => continue,
+        };
+
+        let site = match database.read_site(&url)? {
+            Some(site) => site,
+            None => continue,
+        };
+        findings.push(UploadHtmlFinding {
+            url,
+            pattern: pattern.clone(),
+            declared_type: site.content_type.unwrap_or_default(),
+            sniffed_type: site.sniffed_type,
+        });
+    }
+
+    return Ok(findings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::site::Site;
+
+    /// Creates an in-memory database holding one site for each `(url, content_hash, title)`.
+    fn database(sites: &[(&str, Option<&str>, Option<&str>)]) -> Database {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let sites = sites
+            .iter()
+            .map(|(url, content_hash, title)| Site {
+                url: url.to_string(),
+                content_hash: content_hash.map(str::to_string),
+                title: title.map(str::to_string),
+                ..Site::default()
+            })
+            .collect::<Vec<Site>>();
+        database.write_sites(&sites).unwrap();
+        return database;
+    }
+
+    #[test]
+    fn pairs_with_the_same_content_hash_are_evidence() {
+        let database = database(&[
+            ("https://example.com/product/1", Some("a"), None),
+            ("https://example.com/product/1?ref=home", Some("a"), None),
+            ("https://example.com/product/2", Some("b"), None),
+            ("https://example.com/product/2?ref=home", Some("b"), None),
+            ("https://example.com/product/3", Some("c"), None),
+            ("https://example.com/product/3?ref=home", Some("c"), None),
+        ]);
+        let suggestions = suggest_rules(&database, &SuggestionThresholds::default()).unwrap();
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].rule, "strip parameter `ref`");
+        assert_eq!(suggestions[0].evidence, 3);
+        assert_eq!(suggestions[0].occurrences, 3);
+    }
+
+    #[test]
+    fn titles_stand_in_for_unknown_content_hashes() {
+        let database = database(&[
+            ("https://example.com/product/1", None, Some("One")),
+            ("https://example.com/product/1/", Some("a"), Some("One")),
+            ("https://example.com/product/2", None, Some("Two")),
+            ("https://example.com/product/2/", None, Some("Two")),
+            ("https://example.com/product/3", Some("c"), Some("Three")),
+            ("https://example.com/product/3/", None, Some("Three")),
+        ]);
+        let suggestions = suggest_rules(&database, &SuggestionThresholds::default()).unwrap();
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(
+            suggestions[0].rule,
+            "trailing slash equivalent under /product/"
+        );
+        assert_eq!(suggestions[0].evidence, 3);
+    }
+
+    #[test]
+    fn variants_serving_different_pages_are_not_evidence() {
+        // `page` selects a different page, so stripping it would merge distinct pages
+        let database = database(&[
+            ("https://example.com/list", Some("1"), Some("List")),
+            ("https://example.com/list?page=2", Some("2"), Some("List")),
+            ("https://example.com/news", Some("3"), Some("News")),
+            ("https://example.com/news?page=2", Some("4"), Some("News")),
+            ("https://example.com/tags", None, Some("Tags")),
+            (
+                "https://example.com/tags?page=2",
+                None,
+                Some("Tags, page 2"),
+            ),
+        ]);
+        let suggestions = suggest_rules(&database, &SuggestionThresholds::default()).unwrap();
+
+        assert!(suggestions.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::config::{Config, Scope, Strategy};
+use crate::fetcher::Fetcher;
+use crate::handler::PageHandler;
+use crate::spider::Crawler;
+use crate::storage::{self, MemoryStorage, Storage};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds a `Crawler` from settings given in code rather than read from the config file.
+///
+/// Every setting that isn't given keeps its default from `Config::default`, except the origin
+/// URL, which must always be given. A `Config` read from the config file converts into a builder
+/// as well, so that both are validated the same way.
+///
+/// ```no_run
+/// use rustle::{CrawlerBuilder, Scope};
+/// use std::time::Duration;
+///
+/// let crawler = CrawlerBuilder::new()
+///     .origin("https://example.com")
+///     .depth(2)
+///     .database("example")
+///     .scope(Scope::Subdomains)
+///     .timeout(Duration::from_secs(10))
+///     .build()?;
+/// let report = crawler.crawl(false)?;
+/// # Ok::<(), rustle::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct CrawlerBuilder {
+    /// The configuration the crawler is built with.
+    config: Config,
+    /// The hooks run on every fetched HTML page, shared by the clones of the builder.
+    handlers: Vec<Arc<dyn PageHandler + Send + Sync>>,
+    /// The storage the crawl is stored in, instead of the database named in the config, shared
+    /// by the clones of the builder.
+    storage: Option<Arc<dyn Storage>>,
+    /// The fetcher the requests are sent with, instead of the network, shared by the clones of
+    /// the builder.
+    fetcher: Option<Arc<dyn Fetcher + Send + Sync>>,
+    /// The timeout set with `timeout`, checked and converted to `timeout_secs` by `build`.
+    timeout: Option<Duration>,
+}
+
+impl CrawlerBuilder {
+    /// Creates a new `CrawlerBuilder` with the default settings and no origin URL.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Sets the URL the crawl starts from, replacing any set before.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the origin URL.
+    pub fn origin(mut self, url: &str) -> Self {
+        self.config.origin_urls = vec![url.to_string()];
+        return self;
+    }
+
+    /// Sets the URLs the crawl starts from, each at depth 0, replacing any set before.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The origin URLs.
+    pub fn origins<I, S>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.origin_urls = urls.into_iter().map(Into::into).collect();
+        return self;
+    }
+
+    /// Sets the depth to which links are followed from the origin URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The recursion depth, between 1 and `MAX_DEPTH`.
+    pub fn depth(mut self, depth: u64) -> Self {
+        self.config.depth = depth;
+        return self;
+    }
+
+    /// Sets the database the crawl is stored in.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name or path of the database file, to which
+    ///   `.db` is appended when it has no extension.
+    pub fn database(mut self, name: &str) -> Self {
+        self.config.database = name.to_string();
+        return self;
+    }
+
+    /// Sets the storage the crawl is stored in, such as a `MemoryStorage`, instead of the
+    /// database named by `database`.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The `Storage` of the crawl.
+    pub fn storage(mut self, storage: Box<dyn Storage>) -> Self {
+        self.storage = Some(Arc::from(storage));
+        return self;
+    }
+
+    /// Sets the fetcher the requests of the crawl are sent with, such as a `MapFetcher` serving
+    /// canned pages, instead of sending them over the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `fetcher` - The `Fetcher` of the crawl.
+    pub fn fetcher(mut self, fetcher: Box<dyn Fetcher + Send + Sync>) -> Self {
+        self.fetcher = Some(Arc::from(fetcher));
+        return self;
+    }
+
+    /// Sets the number of pages fetched at once, which is the number of worker threads, or of
+    /// requests in flight for `Crawler::crawl_async`.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - The number of pages fetched at once, greater than 0.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.config.concurrency = Some(concurrency);
+        return self;
+    }
+
+    /// Sets the `User-Agent` header sent with every request.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_agent` - A string slice that holds the user agent.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.config.user_agent = user_agent.to_string();
+        return self;
+    }
+
+    /// Sets which discovered URLs are crawled, relative to the host of the origin URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The `Scope` of the crawl.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.config.scope = scope;
+        return self;
+    }
+
+    /// Sets the order in which discovered URLs are fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The `Strategy` of the crawl.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.config.strategy = strategy;
+        return self;
+    }
+
+    /// Sets how long a request may take in total, body included, before it fails, in whole
+    /// seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The `Duration` of the timeout, rounded down to the second. A timeout under a
+    ///   second is rejected by `build`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        return self;
+    }
+
+    /// Adds a hook run on every fetched HTML page, after the ones added before.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - The `PageHandler` to run.
+    pub fn handler(mut self, handler: Box<dyn PageHandler + Send + Sync>) -> Self {
+        self.handlers.push(Arc::from(handler));
+        return self;
+    }
+
+    /// Validates the settings and builds the `Crawler`, opening its storage.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Crawler>` holding the new crawler.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error describing every invalid setting, such as an origin
+    /// URL that doesn't parse or has no host, if the timeout is under a second, or if the storage
+    /// cannot be opened.
+    pub fn build(mut self) -> Result<Crawler> {
+        if let Some(timeout) = self.timeout {
+            self.config.timeout_secs = timeout_secs(timeout)?;
+        }
+        self.config.validate()?;
+        let storage = match self.storage {
+            Some(storage) => storage,
+            // A dry run keeps everything in memory, so that the database is left untouched
+            None if self.config.dry_run => Arc::new(MemoryStorage::new()),
+            None => Arc::from(storage::open(&self.config)?),
+        };
+        let mut crawler = Crawler::with_shared_storage(self.config, storage)?;
+        crawler.set_handlers(self.handlers);
+        if let Some(fetcher) = self.fetcher {
+            crawler.set_fetcher(fetcher);
+        }
+        return Ok(crawler);
+    }
+}
+
+impl From<Config> for CrawlerBuilder {
+    fn from(config: Config) -> Self {
+        return CrawlerBuilder {
+            config,
+            handlers: Vec::new(),
+            storage: None,
+            fetcher: None,
+            timeout: None,
+        };
+    }
+}
+
+/// Converts the timeout of a builder into the whole seconds of `Config::timeout_secs`.
+///
+/// # Arguments
+///
+/// * `timeout` - The `Duration` of the timeout.
+///
+/// # Errors
+///
+/// This function will return an error if the timeout is under a second, which would round down
+/// to no timeout at all.
+fn timeout_secs(timeout: Duration) -> Result<u64> {
+    if timeout < Duration::from_secs(1) {
+        bail!(
+            "The timeout must be at least one second, got {} ms",
+            timeout.as_millis()
+        );
+    }
+    return Ok(timeout.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_are_rounded_down_to_the_second() {
+        assert_eq!(timeout_secs(Duration::from_millis(2500)).unwrap(), 2);
+        assert_eq!(timeout_secs(Duration::from_secs(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn sub_second_timeouts_are_rejected() {
+        let built = CrawlerBuilder::new()
+            .origin("https://example.com")
+            .database(crate::database::MEMORY_DATABASE)
+            .timeout(Duration::from_millis(500))
+            .build();
+        assert_eq!(
+            built.err().unwrap().to_string(),
+            "The timeout must be at least one second, got 500 ms"
+        );
+    }
+
+    #[test]
+    fn clones_share_their_storage_and_fetcher() {
+        let builder = CrawlerBuilder::new()
+            .origin("https://example.com/")
+            .depth(1)
+            .storage(Box::new(MemoryStorage::new()))
+            .fetcher(Box::new(
+                crate::fetcher::MapFetcher::new().page("https://example.com/", "Hello"),
+            ));
+        let first = builder.clone().build().unwrap();
+        let second = builder.build().unwrap();
+
+        first.crawl(false).unwrap();
+        assert!(second
+            .storage()
+            .get_site("https://example.com/")
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,65 @@
+use reqwest::header::HeaderMap;
+
+/// The markers that identify the challenge pages of common bot-detection providers, used as the
+/// default value of `Config::challenge_markers`.
+pub const DEFAULT_MARKERS: &[&str] = &[
+    // Cloudflare
+    "cf-chl",
+    "challenge-platform",
+    "Just a moment...",
+    "Attention Required! | Cloudflare",
+    // Imperva / Incapsula
+    "_Incapsula_Resource",
+    "Incapsula incident",
+    // Akamai
+    "errors.edgesuite.net",
+    // PerimeterX
+    "px-captcha",
+    // DataDome
+    "captcha-delivery.com",
+    // Distil / Imperva Advanced Bot Protection
+    "Pardon Our Interruption",
+    // Generic captchas
+    "g-recaptcha",
+    "h-captcha",
+];
+
+/// Checks whether a response is a bot-detection challenge rather than real content.
+///
+/// A response is a challenge when a provider marks it as one in its headers (`cf-mitigated`,
+/// or a `cf_chl` cookie), or when it has a blocking status (403, 429 or 503) and its body
+/// contains one of the `markers`. Bodies of successful responses are not checked against the
+/// markers, as regular pages often embed captchas in forms.
+///
+/// # Arguments
+///
+/// * `status` - The HTTP status code of the response.
+/// * `headers` - A reference to the headers of the response.
+/// * `body` - A string slice that holds the body of the response.
+/// * `markers` - A slice of the strings that identify challenge pages.
+///
+/// # Returns
+///
+/// A boolean indicating whether the response is a challenge.
+pub fn is_challenge(status: u16, headers: &HeaderMap, body: &str, markers: &[String]) -> bool {
+    // Check for challenge headers
+    let mitigated = headers
+        .get("cf-mitigated")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("challenge"));
+    let challenge_cookie = headers
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|cookie| cookie.trim_start().starts_with("cf_chl"));
+    if mitigated || challenge_cookie {
+        return true;
+    }
+
+    // Check the bodies of blocked responses for challenge markers
+    if matches!(status, 403 | 429 | 503) {
+        return markers.iter().any(|marker| body.contains(marker.as_str()));
+    }
+
+    return false;
+}
@@ -0,0 +1,146 @@
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::Encoding;
+
+/// The number of bytes at the start of a page searched for a `<meta charset>` declaration, as
+/// in the prescan of the HTML standard.
+const META_PRESCAN_BYTES: usize = 1024;
+
+/// Decodes the body of an HTML page into a string.
+///
+/// The encoding is taken from the byte order mark, then the `charset` parameter of the
+/// `Content-Type` header, then a `<meta charset>` declaration near the start of the page, and is
+/// otherwise guessed from the bytes themselves. Bytes that are invalid in the chosen encoding
+/// are replaced rather than rejected.
+///
+/// # Arguments
+///
+/// * `body` - The raw bytes of the page.
+/// * `content_type` - The value of the `Content-Type` header, if the response had one.
+///
+/// # Returns
+///
+/// A tuple holding the decoded page and the encoding it was decoded with.
+pub fn decode(body: &[u8], content_type: Option<&str>) -> (String, &'static Encoding) {
+    let encoding = match Encoding::for_bom(body) {
+        Some((encoding, _)) => encoding,
+        None => content_type
+            .and_then(header_charset)
+            .or_else(|| meta_charset(body))
+            .unwrap_or_else(|| detect(body)),
+    };
+
+    // Decoding strips the byte order mark, and switches encodings if it disagrees
+    let (html, encoding, _) = encoding.decode(body);
+    return (html.into_owned(), encoding);
+}
+
+/// Reads the encoding from the `charset` parameter of a `Content-Type` header value.
+///
+/// # Arguments
+///
+/// * `content_type` - The value of the `Content-Type` header.
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    return content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        return Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes());
+    });
+}
+
+/// Reads the encoding from a `<meta charset>` or `<meta http-equiv="Content-Type">` declaration
+/// near the start of a page.
+///
+/// # Arguments
+///
+/// * `body` - The raw bytes of the page.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    // Declarations are ASCII, so the start of the page can be searched regardless of its encoding
+    let head =
+        String::from_utf8_lossy(&body[..body.len().min(META_PRESCAN_BYTES)]).to_ascii_lowercase();
+
+    for tag in head.split("<meta").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let Some((_, rest)) = tag.split_once("charset") else {
+            continue;
+        };
+        let value = rest
+            .trim_start()
+            .strip_prefix('=')
+            .unwrap_or_default()
+            .trim_start()
+            .trim_start_matches(['"', '\'']);
+        let label = value
+            .split(['"', '\'', ';', ' ', '/', '\t', '\n', '\r'])
+            .next()
+            .unwrap_or_default();
+
+        // UTF-16 can't be declared from within the page, as the declaration wouldn't be ASCII
+        match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) if encoding == encoding_rs::UTF_16LE => return Some(encoding_rs::UTF_8),
+            Some(encoding) if encoding == encoding_rs::UTF_16BE => return Some(encoding_rs::UTF_8),
+            Some(encoding) => return Some(encoding),
+            None => continue,
+        }
+    }
+    return None;
+}
+
+/// Guesses the encoding of a page from its bytes.
+///
+/// # Arguments
+///
+/// * `body` - The raw bytes of the page.
+fn detect(body: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(body, true);
+    return detector.guess(None, Utf8Detection::Allow);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_charset_comes_before_meta_charset() {
+        let body = b"<meta charset=\"windows-1251\"><p>caf\xe9</p>";
+        let (html, encoding) = decode(body, Some("text/html; charset=ISO-8859-1"));
+        assert_eq!(encoding, encoding_rs::WINDOWS_1252);
+        assert!(html.contains("café"));
+
+        let (_, encoding) = decode(body, Some("text/html"));
+        assert_eq!(encoding, encoding_rs::WINDOWS_1251);
+    }
+
+    #[test]
+    fn byte_order_mark_overrides_declarations() {
+        let body = b"\xef\xbb\xbf<p>caf\xc3\xa9</p>";
+        let (html, encoding) = decode(body, Some("text/html; charset=iso-8859-1"));
+        assert_eq!(encoding, encoding_rs::UTF_8);
+        assert_eq!(html, "<p>café</p>");
+    }
+
+    #[test]
+    fn meta_charset_is_read_from_http_equiv_declarations() {
+        let body = b"<META HTTP-EQUIV='Content-Type' CONTENT='text/html; charset=Shift_JIS'>";
+        assert_eq!(meta_charset(body), Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(
+            meta_charset(b"<meta charset=\"utf-16le\">"),
+            Some(encoding_rs::UTF_8)
+        );
+        assert_eq!(meta_charset(b"<meta name=\"charset\">"), None);
+    }
+
+    #[test]
+    fn undeclared_encodings_are_detected() {
+        let (html, _) = decode("<p>日本語のページです。</p>".as_bytes(), None);
+        assert_eq!(html, "<p>日本語のページです。</p>");
+
+        let (html, _) = decode(
+            b"<p>Le caf\xe9 est d\xe9j\xe0 servi, tr\xe8s chaud.</p>",
+            None,
+        );
+        assert_eq!(html, "<p>Le café est déjà servi, très chaud.</p>");
+    }
+}
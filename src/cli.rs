@@ -0,0 +1,1589 @@
+use crate::proxy::ProxyConfig;
+use crate::report::{self, CrawlReport};
+#[cfg(feature = "self-test")]
+use crate::selftest;
+use crate::webhook::{Webhook, WebhookEvent, WebhookPayload};
+use crate::{
+    analyze, config, crawl_run, database, explain, export, fetch_failure, output, session, site,
+    spider, wizard,
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Args, Parser, Subcommand};
+use log::info;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Command-line interface of the Rustle webcrawler.
+#[derive(Parser)]
+#[command(
+    name = "rustle",
+    version,
+    about = "A parallel webcrawler that stores sites in SQLite"
+)]
+pub struct Cli {
+    /// The action to perform. Crawls from the configured origin URLs when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// The options of the crawl run when no command is given.
+    #[command(flatten)]
+    pub crawl: CrawlArgs,
+    /// Read the config file from this path instead of the default one.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Config values that take precedence over the config file.
+    #[command(flatten)]
+    pub overrides: config::Overrides,
+}
+
+/// The options of a crawl.
+#[derive(Args)]
+pub struct CrawlArgs {
+    /// Accept the crawl even if it fetched far fewer pages than the previous one.
+    #[arg(long)]
+    pub force_accept: bool,
+    /// Fetch pages with the async crawler, which keeps many requests in flight without a thread
+    /// per request.
+    #[arg(long = "async")]
+    pub async_fetch: bool,
+    /// Fetch only the origin pages and list what the crawl would do with each of their links,
+    /// without storing anything.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// The exit code of a crawl that was marked as suspect.
+const SUSPECT_EXIT_CODE: u8 = 3;
+
+/// The exit code of a crawl whose every fetch failed.
+const FAILED_EXIT_CODE: u8 = 4;
+
+/// The exit code of a command that found no data to print, such as a query without results.
+const NO_DATA_EXIT_CODE: u8 = 5;
+
+/// The number of pages listed by `rustle analyze --js-dependency`.
+const JS_DEPENDENCY_LIMIT: usize = 20;
+
+/// The number of results listed by `rustle search` by default.
+const SEARCH_LIMIT: usize = 20;
+
+/// The width of the longest bar of the histogram of pages by depth printed by `rustle stats`.
+const DEPTH_BAR_WIDTH: u64 = 40;
+
+/// The actions supported by the command-line interface.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Crawl from the configured origin URLs, the default when no command is given.
+    Crawl(CrawlArgs),
+    /// Write a starter config file.
+    Init {
+        /// Walk through the common settings interactively.
+        #[arg(long)]
+        interactive: bool,
+        /// Overwrite the config file if it already exists.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Explain why a URL is or isn't stored, from the stored crawl data.
+    Explain {
+        /// The URL to explain.
+        url: String,
+        /// Print the robots.txt snapshot and rule the page was fetched under, as recorded in
+        /// compliance mode.
+        #[arg(long)]
+        compliance: bool,
+        /// Print the explanation as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Search the text of the pages stored with `store_text`.
+    Search {
+        /// The full-text query, such as `rust AND crawler`.
+        query: String,
+        /// The maximum number of results.
+        #[arg(long, default_value_t = SEARCH_LIMIT)]
+        limit: usize,
+        /// Print the results as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Query the stored crawl data.
+    Query {
+        /// The query to run.
+        #[command(subcommand)]
+        query: QueryCommand,
+    },
+    /// Analyze the stored crawl data.
+    Analyze {
+        /// Propose canonicalization rules from clusters of near-duplicate URLs.
+        #[arg(long)]
+        suggest_rules: bool,
+        /// List the pages that depend the most on JavaScript to render their content.
+        #[arg(long)]
+        js_dependency: bool,
+        /// List security findings, such as pages served as HTML from upload paths.
+        #[arg(long)]
+        security: bool,
+        /// Print the results as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the number of stored sites, domains and links, the sites of each host, the failed
+    /// fetches and the latest crawl runs, without any network traffic.
+    Stats {
+        /// Print the numbers as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a report of the latest crawl, with the totals, the sites of every host, the pages
+    /// by depth, the pages with the most links, the failed fetches and the broken links.
+    Report {
+        /// The format of the report. Picked from the extension of the output file when omitted,
+        /// HTML for `.html` and `.htm` and Markdown otherwise.
+        #[arg(long, value_enum)]
+        format: Option<report::ReportFormat>,
+        /// The file the report is written to. Prints it when omitted.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Report on the latest crawl even if it was marked as suspect, instead of the latest
+        /// accepted one.
+        #[arg(long)]
+        include_suspect: bool,
+    },
+    /// Export the link graph of the stored sites.
+    Export {
+        /// The format of the exported graph.
+        #[arg(long, value_enum, default_value = "dot")]
+        format: export::ExportFormat,
+        /// The file the graph is written to. Prints it when omitted.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Only keep the pages of this domain and its subdomains.
+        #[arg(long)]
+        domain: Option<String>,
+        /// Collapse the pages of each host into a single node.
+        #[arg(long)]
+        hosts: bool,
+        /// Only keep this many nodes, those with the most links.
+        #[arg(long)]
+        top: Option<usize>,
+    },
+    /// Delete the sites and domains that weren't crawled recently, such as pages that
+    /// disappeared from their site, and shrink the database file.
+    Prune {
+        /// Delete what was last crawled before this time, as an RFC 3339 timestamp or a
+        /// duration ago such as `30d` or `12w`.
+        #[arg(long, value_parser = parse_since)]
+        older_than: DateTime<Utc>,
+        /// Print what would be deleted without deleting anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Merge the sites and domains of another crawl database into the configured one.
+    Merge {
+        /// The path of the other database file.
+        path: PathBuf,
+    },
+    /// Crawl a small built-in site to check that Rustle itself works.
+    #[cfg(feature = "self-test")]
+    SelfTest,
+}
+
+/// The queries supported by `rustle query`.
+#[derive(Subcommand)]
+pub enum QueryCommand {
+    /// List the pages crawled since a given time.
+    Recent {
+        /// The earliest crawl time, as an RFC 3339 timestamp or a duration ago such as `24h` or
+        /// `7d`.
+        #[arg(long, value_parser = parse_since)]
+        since: DateTime<Utc>,
+        /// List the pages last stored by crawls marked as suspect too.
+        #[arg(long)]
+        include_suspect: bool,
+        /// Print the pages as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the pages whose content changed since a given time, including new pages.
+    Changed {
+        /// The earliest change time, as an RFC 3339 timestamp or a duration ago such as `24h` or
+        /// `7d`.
+        #[arg(long, value_parser = parse_since)]
+        since: DateTime<Utc>,
+        /// List the pages last stored by crawls marked as suspect too.
+        #[arg(long)]
+        include_suspect: bool,
+        /// Print the pages as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the URLs a stored page links to.
+    Links {
+        /// The URL of the page.
+        url: String,
+        /// Print the links as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the stored pages that link to a given URL, whether over http or https and with or
+    /// without a trailing slash.
+    LinkedBy {
+        /// The URL the pages link to.
+        url: String,
+        /// Print the pages as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the stored URLs that redirect to a given URL, directly or through other redirects.
+    Redirects {
+        /// The URL at the end of the redirect chains.
+        url: String,
+        /// Print the redirects as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the stored links, to pages and to assets, whose target failed to load.
+    Broken {
+        /// Print the links as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the pages fetched and the URLs that failed in a generation of the last crawl to
+    /// reach it, with the outcome of each fetch.
+    Generation {
+        /// The generation, counting the origin as generation 0.
+        generation: u64,
+        /// Print the pages as a versioned JSON document.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Runs a subcommand of the command-line interface.
+///
+/// The commands reading the stored crawl data exit with `NO_DATA_EXIT_CODE` when they find
+/// nothing to print, so that scripts can tell an empty result from a failure.
+///
+/// # Arguments
+///
+/// * `command` - The `Command` parsed from the command-line arguments.
+/// * `config` - The `Config` read from the config file and the command line, or the error
+///   reading it, which only fails the commands that need it.
+/// * `config_path` - The path given with `--config`, or `None` for the default path.
+/// * `database_name` - The database name given with `--database`, with which the commands that
+///   only read the stored crawl data don't need a config file.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process, or an `Err` if the command failed.
+pub fn run(
+    command: Command,
+    config: Result<config::Config>,
+    config_path: Option<&Path>,
+    database_name: Option<&str>,
+) -> Result<ExitCode> {
+    let found = match command {
+        Command::Crawl(args) => return run_crawl(config?, &args),
+        Command::Init {
+            interactive: true,
+            force,
+        } => {
+            let proxy = config.ok().and_then(|config| config.proxy);
+            return init_interactive(config_path, force, proxy);
+        }
+        Command::Init {
+            interactive: false,
+            force,
+        } => init(config_path, force).map(|()| true),
+        Command::Explain {
+            url,
+            compliance,
+            json,
+        } => explain(&config?, &url, compliance, json).map(|()| true),
+        Command::Search { query, limit, json } => {
+            search(&open_database(config, database_name)?, &query, limit, json)
+        }
+        Command::Query { query } => {
+            let database = open_database(config, database_name)?;
+            match query {
+                QueryCommand::Recent {
+                    since,
+                    include_suspect,
+                    json,
+                } => query_recent(&database, since, include_suspect, json),
+                QueryCommand::Changed {
+                    since,
+                    include_suspect,
+                    json,
+                } => query_changed(&database, since, include_suspect, json),
+                QueryCommand::Links { url, json } => query_links(&database, &url, json),
+                QueryCommand::LinkedBy { url, json } => query_linked_by(&database, &url, json),
+                QueryCommand::Redirects { url, json } => query_redirects(&database, &url, json),
+                QueryCommand::Broken { json } => query_broken(&database, json),
+                QueryCommand::Generation { generation, json } => {
+                    query_generation(&database, generation, json)
+                }
+            }
+        }
+        Command::Analyze {
+            suggest_rules,
+            js_dependency,
+            security,
+            json,
+        } => analyze(config, suggest_rules, js_dependency, security, json).map(|()| true),
+        Command::Stats { json } => {
+            let scope = config
+                .as_ref()
+                .map(|config| config.scope)
+                .unwrap_or_default();
+            stats(&open_database(config, database_name)?, scope, json)
+        }
+        Command::Report {
+            format,
+            output,
+            include_suspect,
+        } => write_report(
+            &open_database(config, database_name)?,
+            format,
+            output,
+            include_suspect,
+        ),
+        Command::Export {
+            format,
+            output,
+            domain,
+            hosts,
+            top,
+        } => export(
+            &open_database(config, database_name)?,
+            format,
+            output,
+            &export::ExportOptions {
+                domain,
+                collapse_hosts: hosts,
+                top,
+            },
+        ),
+        Command::Prune {
+            older_than,
+            dry_run,
+        } => prune(&open_database(config, database_name)?, older_than, dry_run).map(|()| true),
+        Command::Merge { path } => {
+            merge(&self::database_name(config, database_name)?, &path).map(|()| true)
+        }
+        #[cfg(feature = "self-test")]
+        Command::SelfTest => self_test().map(|()| true),
+    };
+    return Ok(match found? {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(NO_DATA_EXIT_CODE),
+    });
+}
+
+/// Runs a crawl with the blocking or the async crawler, as chosen by the crawl options.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the crawl.
+/// * `args` - A reference to the `CrawlArgs` of the crawl.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process, or an `Err` if the crawl failed.
+pub fn run_crawl(config: config::Config, args: &CrawlArgs) -> Result<ExitCode> {
+    if args.dry_run || config.dry_run {
+        return dry_run(config);
+    }
+    if args.async_fetch {
+        // Drive the async crawler with a multi-threaded tokio runtime
+        let runtime = tokio::runtime::Runtime::new()?;
+        return runtime.block_on(crawl_async(config, args.force_accept));
+    }
+    return crawl(config, args.force_accept);
+}
+
+/// Crawls the built-in fixture site and prints the outcome of every check.
+///
+/// The config file is not read, so that the result is the same on every installation.
+///
+/// # Errors
+///
+/// This function will return an error if the crawl could not be run, or any check failed.
+#[cfg(feature = "self-test")]
+fn self_test() -> Result<()> {
+    let checks = selftest::run()?;
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        bail!("{} of {} self-test checks failed", failed, checks.len());
+    }
+    println!("All {} self-test checks passed", checks.len());
+    return Ok(());
+}
+
+/// Runs a crawl with the given configuration.
+///
+/// This function creates a new instance of the `Crawler` struct, starts the crawling process,
+/// and prints the report of the finished crawl. The exit code is `SUSPECT_EXIT_CODE` if the
+/// crawl is marked as suspect, or `FAILED_EXIT_CODE` if none of its fetches succeeded. Once the
+/// crawl finishes or fails, `webhook_url` is notified of its outcome.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the crawl.
+/// * `force_accept` - Whether the crawl is accepted even if it looks suspect.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process, or an `Err` if the crawl failed.
+pub fn crawl(config: config::Config, force_accept: bool) -> Result<ExitCode> {
+    info!("Initializing rustle webcrawler");
+    print_storage_location(&config);
+    print_tls_warning(&config);
+
+    let webhook = Webhook::from_config(&config);
+    let origin_urls = config.origin_urls.clone();
+    let start = Instant::now();
+    let outcome = run_crawler(config, force_accept);
+    notify_webhook(webhook.as_ref(), &origin_urls, &outcome, start.elapsed());
+    return outcome.map(|(_, exit_code)| exit_code);
+}
+
+/// Creates the crawler of `crawl`, runs it, writes the crawl report and prints it.
+///
+/// # Returns
+///
+/// A `Result<(CrawlReport, ExitCode)>` holding the report of the crawl and the exit code of the
+/// process, or an `Err` if the crawl failed.
+fn run_crawler(config: config::Config, force_accept: bool) -> Result<(CrawlReport, ExitCode)> {
+    // Declare Crawler
+    let robots_override_diff = config.robots_override_diff;
+    let report_path = config.report_path.clone();
+    let database_name = config.database.clone();
+    let crawler = spider::Crawler::new(config)?;
+
+    // Run Crawler
+    let report = crawler.crawl(force_accept)?;
+    write_crawl_report(report_path.as_deref(), &database_name, &report)?;
+    let exit_code = print_report(&crawler, &report, robots_override_diff)?;
+    return Ok((report, exit_code));
+}
+
+/// Fetches the origin pages of a crawl without storing anything, and prints what the crawl would
+/// do with each of their links. The exit code is 0 however many of them would be skipped.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the crawl.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process, or an `Err` if the dry run failed.
+pub fn dry_run(mut config: config::Config) -> Result<ExitCode> {
+    info!("Starting a dry run of the rustle webcrawler");
+    config.dry_run = true;
+    print_tls_warning(&config);
+
+    let crawler = spider::Crawler::new(config)?;
+    print!("{}", crawler.dry_run()?);
+    return Ok(ExitCode::SUCCESS);
+}
+
+/// Runs a crawl with the given configuration using the async crawler, like `crawl`.
+///
+/// This function must be run on a multi-threaded tokio runtime.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the crawl.
+/// * `force_accept` - Whether the crawl is accepted even if it looks suspect.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process, or an `Err` if the crawl failed.
+pub async fn crawl_async(config: config::Config, force_accept: bool) -> Result<ExitCode> {
+    info!("Initializing rustle webcrawler with the async crawler");
+    print_storage_location(&config);
+    print_tls_warning(&config);
+
+    let webhook = Webhook::from_config(&config);
+    let origin_urls = config.origin_urls.clone();
+    let start = Instant::now();
+    let outcome = run_crawler_async(config, force_accept).await;
+    tokio::task::block_in_place(|| {
+        notify_webhook(webhook.as_ref(), &origin_urls, &outcome, start.elapsed());
+    });
+    return outcome.map(|(_, exit_code)| exit_code);
+}
+
+/// Creates the crawler of `crawl_async`, runs it, writes the crawl report and prints it.
+///
+/// # Returns
+///
+/// A `Result<(CrawlReport, ExitCode)>` holding the report of the crawl and the exit code of the
+/// process, or an `Err` if the crawl failed.
+async fn run_crawler_async(
+    config: config::Config,
+    force_accept: bool,
+) -> Result<(CrawlReport, ExitCode)> {
+    let robots_override_diff = config.robots_override_diff;
+    let report_path = config.report_path.clone();
+    let database_name = config.database.clone();
+    let crawler = tokio::task::block_in_place(|| spider::Crawler::new(config))?;
+    let report = crawler.crawl_async(force_accept).await?;
+    let exit_code = tokio::task::block_in_place(|| {
+        write_crawl_report(report_path.as_deref(), &database_name, &report)?;
+        return print_report(&crawler, &report, robots_override_diff);
+    })?;
+    return Ok((report, exit_code));
+}
+
+/// Notifies the configured webhook of the outcome of a crawl, which failed if it returned an
+/// error or an exit code other than 0. A webhook that can't be notified is only reported, so
+/// that it never changes the exit code of the crawl.
+///
+/// # Arguments
+///
+/// * `webhook` - The `Webhook` to notify, or `None` if `webhook_url` isn't set.
+/// * `origin_urls` - The origin URLs of the crawl.
+/// * `outcome` - A reference to the report and exit code of the crawl, or the error it failed
+///   with.
+/// * `elapsed` - The time the crawl took.
+fn notify_webhook(
+    webhook: Option<&Webhook>,
+    origin_urls: &[String],
+    outcome: &Result<(CrawlReport, ExitCode)>,
+    elapsed: Duration,
+) {
+    let Some(webhook) = webhook else {
+        return;
+    };
+    let payload = match outcome {
+        Ok((report, exit_code)) => {
+            let event = if *exit_code == ExitCode::SUCCESS {
+                WebhookEvent::Success
+            } else {
+                WebhookEvent::Failure
+            };
+            WebhookPayload::from_report(report, event)
+        }
+        Err(e) => WebhookPayload::from_error(origin_urls, e, elapsed),
+    };
+    if let Err(e) = webhook.notify(&payload) {
+        eprintln!("{:#}", e);
+    }
+}
+
+/// Writes the report of a finished crawl to `report_path`, if one is configured, reading the
+/// stored crawl data back from its database.
+///
+/// # Arguments
+///
+/// * `report_path` - The path of the report, or `None` if no report is written.
+/// * `database_name` - A string slice that holds the name of the database of the crawl.
+/// * `report` - A reference to the `CrawlReport` of the crawl.
+///
+/// # Errors
+///
+/// This function will return an error if the database can't be read, or the report can't be
+/// written.
+fn write_crawl_report(
+    report_path: Option<&Path>,
+    database_name: &str,
+    report: &CrawlReport,
+) -> Result<()> {
+    let Some(path) = report_path else {
+        return Ok(());
+    };
+    let database = database::Database::new(database_name)?;
+    report::write_report(
+        &database,
+        Some(report),
+        false,
+        report::ReportFormat::of(path),
+        path,
+    )?;
+    eprintln!("Wrote the crawl report to {}", path.display());
+    return Ok(());
+}
+
+/// Prints the absolute path of the file a crawl is stored in to stderr, so that it is clear which
+/// database is used whatever the current directory.
+fn print_storage_location(config: &config::Config) {
+    match config.storage_location() {
+        Some(path) => {
+            let absolute = std::path::absolute(&path).unwrap_or(path);
+            eprintln!("Storing the crawl in {}", absolute.display());
+        }
+        None => eprintln!("Keeping the crawl in memory"),
+    }
+}
+
+/// Prints a warning to stderr when TLS certificates aren't validated for some hosts, whatever
+/// the log level, as the crawl could be intercepted.
+fn print_tls_warning(config: &config::Config) {
+    if config.danger_accept_invalid_certs {
+        eprintln!("WARNING: TLS certificates are not validated for any host");
+    } else if !config.trusted_hosts.is_empty() {
+        eprintln!(
+            "WARNING: TLS certificates are not validated for {}",
+            config.trusted_hosts.join(", ")
+        );
+    }
+}
+
+/// Prints the report of a finished crawl, along with its robots.txt differences.
+///
+/// # Arguments
+///
+/// * `crawler` - A reference to the `Crawler` that ran the crawl.
+/// * `report` - A reference to the `CrawlReport` of the crawl.
+/// * `robots_override_diff` - Whether the pages admitted differently under the planned
+///   robots.txt rules are printed.
+///
+/// # Returns
+///
+/// A `Result<ExitCode>` holding the exit code of the process.
+fn print_report(
+    crawler: &spider::Crawler,
+    report: &CrawlReport,
+    robots_override_diff: bool,
+) -> Result<ExitCode> {
+    println!("{}", report);
+
+    // Print the pages whose admission would change under the planned robots.txt rules
+    if robots_override_diff {
+        let diff = crawler.robots_diff();
+        println!(
+            "{} pages are admitted differently under the planned robots.txt rules",
+            diff.len()
+        );
+        for change in &diff {
+            println!("{}", change);
+        }
+    }
+
+    // Exit with distinct codes so that scheduled crawls can alert on broken or suspect sessions
+    if report.all_fetches_failed() {
+        eprintln!("Every fetch of the crawl failed");
+        return Ok(ExitCode::from(FAILED_EXIT_CODE));
+    }
+    if report.session.status == session::SessionStatus::Suspect {
+        eprintln!("Crawl marked as suspect, rerun with --force-accept to accept it");
+        return Ok(ExitCode::from(SUSPECT_EXIT_CODE));
+    }
+    return Ok(ExitCode::SUCCESS);
+}
+
+/// Writes a starter config file listing every setting with its default value, refusing to
+/// overwrite an existing one unless `force` is set.
+fn init(config_path: Option<&Path>, force: bool) -> Result<()> {
+    let path = init_path(config_path, force)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    fs::write(&path, config::Config::template()?)
+        .with_context(|| format!("Failed to write config file at {}", path.display()))?;
+
+    println!("Wrote config file to {}", path.display());
+    return Ok(());
+}
+
+/// Gets the path `rustle init` writes the config file to.
+///
+/// # Arguments
+///
+/// * `config_path` - The path given with `--config`, or `None` for the default path.
+/// * `force` - Whether an existing config file may be overwritten.
+///
+/// # Errors
+///
+/// This function will return an error if the default path cannot be determined, or if a config
+/// file already exists there and `force` is not set.
+fn init_path(config_path: Option<&Path>, force: bool) -> Result<PathBuf> {
+    let path = match config_path {
+        Some(path) => path.to_path_buf(),
+        None => config::Config::default_path()?,
+    };
+    if path.exists() && !force {
+        bail!(
+            "Config file already exists at {}; pass --force to overwrite it",
+            path.display()
+        );
+    }
+    return Ok(path);
+}
+
+/// Runs the interactive config wizard, writes its result, and optionally runs a preview crawl.
+/// The seed URLs are checked through `proxy`, the proxy of the config read before the wizard, if
+/// any.
+fn init_interactive(
+    config_path: Option<&Path>,
+    force: bool,
+    proxy: Option<ProxyConfig>,
+) -> Result<ExitCode> {
+    if !std::io::stdin().is_terminal() {
+        bail!("`rustle init --interactive` needs a terminal; use `rustle init` to write a starter config instead");
+    }
+
+    let path = init_path(config_path, force)?;
+
+    // Run the wizard on the terminal
+    let mut wizard = wizard::Wizard::new(
+        std::io::stdin().lock(),
+        std::io::stdout(),
+        Box::new(move |url| wizard::head_check(url, proxy.as_ref())),
+    );
+    let outcome = wizard.run()?;
+    outcome.config.write_to(&path)?;
+    println!("Wrote config file to {}", path.display());
+
+    // Run the preview crawl with the new config
+    if outcome.run_preview {
+        let mut config = outcome.config;
+        config.max_pages = Some(wizard::PREVIEW_PAGES);
+        return crawl(config, false);
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+/// Prints the explanation of why a URL is or isn't stored, or with `compliance` the robots.txt
+/// snapshot and rule it was fetched under, either as text or as a versioned JSON document.
+fn explain(config: &config::Config, url: &str, compliance: bool, json: bool) -> Result<()> {
+    let database = database::Database::new(&config.database)?;
+    database.setup()?;
+
+    if compliance {
+        let record = explain::compliance(url, &database)?;
+        if json {
+            let report = output::ComplianceReport::from(&record);
+            let document = output::Document::new(output::ComplianceReport::NAME, report);
+            println!("{}", document.to_json()?);
+        } else {
+            println!("{}", record);
+        }
+        return Ok(());
+    }
+
+    let explanation = explain::explain(url, config, &database)?;
+    if json {
+        let report = output::ExplainReport::from(&explanation);
+        let document = output::Document::new(output::ExplainReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(());
+    }
+
+    println!("{}", explanation);
+    return Ok(());
+}
+
+/// Prints the stored pages matching a full-text query, either as text or as a versioned JSON
+/// document. Returns whether any page matched.
+fn search(database: &database::Database, query: &str, limit: usize, json: bool) -> Result<bool> {
+    let results = database.search_text(query, limit)?;
+    let found = !results.is_empty();
+    if json {
+        let report = output::SearchReport {
+            query: query.to_string(),
+            results: results
+                .into_iter()
+                .map(|(url, snippet)| output::SearchResultOutput { url, snippet })
+                .collect(),
+        };
+        let document = output::Document::new(output::SearchReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(found);
+    }
+
+    if results.is_empty() {
+        println!("No stored pages match '{}'", query);
+    }
+    for (url, snippet) in &results {
+        println!("{}\n  {}", url, snippet);
+    }
+
+    return Ok(found);
+}
+
+/// Prints the pages crawled since the given time, either as text or as a versioned JSON document.
+/// Returns whether any page was listed.
+fn query_recent(
+    database: &database::Database,
+    since: DateTime<Utc>,
+    include_suspect: bool,
+    json: bool,
+) -> Result<bool> {
+    let sites = database
+        .sites_crawled_since(since, include_suspect)?
+        .collect::<Result<Vec<_>>>()?;
+    if json {
+        let report = output::RecentReport {
+            since: since.to_rfc3339(),
+            pages: sites.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::RecentReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(!sites.is_empty());
+    }
+
+    if sites.is_empty() {
+        println!("No pages crawled since {}", since.to_rfc3339());
+    }
+    for site in &sites {
+        println!("{} {}", site.crawl_time.to_rfc3339(), site);
+    }
+
+    return Ok(!sites.is_empty());
+}
+
+/// Prints the pages whose content changed since the given time, either as text or as a versioned
+/// JSON document. Returns whether any page was listed.
+fn query_changed(
+    database: &database::Database,
+    since: DateTime<Utc>,
+    include_suspect: bool,
+    json: bool,
+) -> Result<bool> {
+    let sites = database
+        .sites_changed_since(since, include_suspect)?
+        .collect::<Result<Vec<_>>>()?;
+    if json {
+        let report = output::ChangedReport {
+            since: since.to_rfc3339(),
+            pages: sites.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::ChangedReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(!sites.is_empty());
+    }
+
+    if sites.is_empty() {
+        println!("No pages changed since {}", since.to_rfc3339());
+    }
+    for site in &sites {
+        let last_changed = site.last_changed.unwrap_or(site.crawl_time);
+        println!("{} {}", last_changed.to_rfc3339(), site);
+    }
+
+    return Ok(!sites.is_empty());
+}
+
+/// Prints the stored URLs that redirect to the given URL, either as text or as a versioned JSON
+/// document. Returns whether any redirect was listed.
+fn query_redirects(database: &database::Database, url: &str, json: bool) -> Result<bool> {
+    let sites = database.redirects_to(url)?.collect::<Result<Vec<_>>>()?;
+    if json {
+        let report = output::RedirectsReport {
+            url: url.to_string(),
+            redirects: sites.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::RedirectsReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(!sites.is_empty());
+    }
+
+    if sites.is_empty() {
+        println!("No stored URLs redirect to {}", url);
+    }
+    for site in &sites {
+        println!(
+            "{} {} -> {} (hops: {})",
+            site.status_code
+                .map(|status_code| status_code.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            site.url,
+            site.links_to
+                .iter()
+                .next()
+                .map(String::as_str)
+                .unwrap_or("?"),
+            site.redirect_hops.unwrap_or_default()
+        );
+    }
+
+    return Ok(!sites.is_empty());
+}
+
+/// Prints the stored links whose target failed to load, along with their kind and the status
+/// code or fetch error of the target, either as text or as a versioned JSON document. Returns
+/// whether any link was listed.
+fn query_broken(database: &database::Database, json: bool) -> Result<bool> {
+    let links = database.broken_links()?;
+    if json {
+        let report = output::BrokenLinksReport {
+            links: links.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::BrokenLinksReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(!links.is_empty());
+    }
+
+    if links.is_empty() {
+        println!("No stored links are broken");
+    }
+    for link in &links {
+        let outcome = match (link.status_code, &link.error_kind) {
+            (_, Some(error_kind)) => error_kind.clone(),
+            (Some(status_code), None) => status_code.to_string(),
+            (None, None) => "-".to_string(),
+        };
+        println!(
+            "{} {} {} <- {}",
+            outcome, link.kind, link.target_url, link.source_url
+        );
+    }
+
+    return Ok(!links.is_empty());
+}
+
+/// Prints the pages fetched and the URLs that could not be fetched in a generation of the last
+/// crawl to reach it, along with the number of error responses and the mean response time, either
+/// as text or as a versioned JSON document. Returns whether any page or failure was listed.
+fn query_generation(database: &database::Database, generation: u64, json: bool) -> Result<bool> {
+    let sites = database
+        .sites_in_generation(generation)?
+        .collect::<Result<Vec<_>>>()?;
+    let failures = fetch_failure::FetchFailure::in_generation(database, generation)?;
+    let listed = !sites.is_empty() || !failures.is_empty();
+    let error_pages = sites
+        .iter()
+        .filter(|site| {
+            site.status_code
+                .is_some_and(|status_code| status_code >= 400)
+        })
+        .count() as u64;
+    let response_times = sites
+        .iter()
+        .filter_map(|site| site.response_ms)
+        .collect::<Vec<u64>>();
+    let mean_response_ms = response_times
+        .iter()
+        .sum::<u64>()
+        .checked_div(response_times.len() as u64);
+    if json {
+        let report = output::GenerationReport {
+            generation,
+            error_pages,
+            mean_response_ms,
+            pages: sites.iter().map(Into::into).collect(),
+            failures: failures.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::GenerationReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(listed);
+    }
+
+    if !listed {
+        println!("No pages stored from generation {}", generation);
+        return Ok(listed);
+    }
+    for site in &sites {
+        println!(
+            "batch {} {} {} ms {}{}",
+            site.batch_id.map(|id| id.to_string()).unwrap_or_default(),
+            site.status_code
+                .map(|status_code| status_code.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            site.response_ms
+                .map(|response_ms| response_ms.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            site,
+            if site.bot_challenged {
+                " [challenged]"
+            } else {
+                ""
+            }
+        );
+    }
+    for failure in &failures {
+        println!(
+            "batch {} failed {}: {}",
+            failure
+                .batch_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            failure.url,
+            failure.message
+        );
+    }
+    println!(
+        "{} pages, {} with an error status, {} failed, {} ms mean response time",
+        sites.len(),
+        error_pages,
+        failures.len(),
+        mean_response_ms
+            .map(|mean| mean.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    );
+
+    return Ok(listed);
+}
+
+/// Prints the URLs a stored page links to, either as text or as a versioned JSON document.
+/// Returns whether the page is stored.
+fn query_links(database: &database::Database, url: &str, json: bool) -> Result<bool> {
+    let Some(site) = database.read_site(url)? else {
+        if !json {
+            println!("{} is not stored", url);
+        }
+        return Ok(false);
+    };
+    let mut links = site.links_to.into_iter().collect::<Vec<String>>();
+    links.sort();
+    print_links(url, links, output::LinksReport::LINKS_NAME, json)?;
+    return Ok(true);
+}
+
+/// Prints the stored pages linking to a URL, or to its variants with the other scheme or
+/// trailing slash, either as text or as a versioned JSON document. Returns whether any page links
+/// to it.
+fn query_linked_by(database: &database::Database, url: &str, json: bool) -> Result<bool> {
+    let referrers = database.inbound_links(url, true)?;
+    let found = !referrers.is_empty();
+    if !found && !json {
+        println!("No stored pages link to {}", url);
+    }
+    print_links(url, referrers, output::LinksReport::LINKED_BY_NAME, json)?;
+    return Ok(found);
+}
+
+/// Prints a list of links of a URL, one per line or as a versioned JSON document.
+fn print_links(url: &str, links: Vec<String>, report_name: &'static str, json: bool) -> Result<()> {
+    if json {
+        let report = output::LinksReport {
+            url: url.to_string(),
+            links,
+        };
+        println!("{}", output::Document::new(report_name, report).to_json()?);
+        return Ok(());
+    }
+
+    for link in &links {
+        println!("{}", link);
+    }
+    return Ok(());
+}
+
+/// Prints the number of stored sites by status class, of stored domains and links, the sites of
+/// each host with their oldest and newest crawl times, a histogram of the sites by depth, the hosts out of scope that stored
+/// pages link to, the recorded fetch failures and the latest crawl runs, either as text or as a versioned JSON document. The sites are counted by
+/// registrable domain instead of by host with the `site` scope. Returns whether any site,
+/// failure or run is stored.
+fn stats(database: &database::Database, scope: config::Scope, json: bool) -> Result<bool> {
+    let status_classes = database
+        .summarize_site_table(None)?
+        .into_iter()
+        .map(|(class, count)| match class {
+            Some(class) => (format!("{}xx", class), count),
+            None => ("unknown".to_string(), count),
+        })
+        .collect::<BTreeMap<String, u64>>();
+    let mut by_domain = database.sites_by_domain(None)?;
+    if scope == config::Scope::Site {
+        by_domain = site::DomainSummary::group_by_site(by_domain);
+    }
+    let external_hosts = database.summarize_external_domains()?;
+    let failures = fetch_failure::FetchFailure::read_all(database)?;
+    let by_kind = fetch_failure::FetchFailure::count_by(&failures, |failure| {
+        Some(failure.error_kind.clone())
+    });
+    let mut failing_hosts =
+        fetch_failure::FetchFailure::count_by(&failures, fetch_failure::FetchFailure::host);
+    failing_hosts.truncate(fetch_failure::WORST_DOMAINS);
+    let runs = crawl_run::CrawlRun::read_latest(crawl_run::RECENT_RUNS, true, database)?;
+    let report = output::StatsReport {
+        sites: status_classes.values().sum(),
+        status_classes,
+        domains: database.count_rows("domains")?,
+        links: database.count_rows("links")?,
+        hosts: by_domain.iter().map(Into::into).collect(),
+        pages_by_depth: database.count_by_depth()?,
+        feeds: database.read_feeds()?,
+        external_hosts: external_hosts
+            .iter()
+            .map(|(host, links)| output::ExternalHostOutput {
+                host: host.clone(),
+                links: *links,
+            })
+            .collect(),
+        fetch_failures: failures.len() as u64,
+        failures_by_kind: by_kind.iter().cloned().collect(),
+        failing_hosts: failing_hosts
+            .iter()
+            .map(|(host, failures)| output::FailingHostOutput {
+                host: host.clone(),
+                failures: *failures,
+            })
+            .collect(),
+        runs: runs.iter().map(Into::into).collect(),
+    };
+    let found = report.sites > 0 || report.fetch_failures > 0 || !report.runs.is_empty();
+    if json {
+        let document = output::Document::new(output::StatsReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(found);
+    }
+
+    if report.sites == 0 {
+        println!("No sites stored");
+    } else {
+        let classes = report
+            .status_classes
+            .iter()
+            .map(|(class, count)| format!("{} {}", count, class))
+            .collect::<Vec<String>>();
+        println!("Sites:   {} ({})", report.sites, classes.join(", "));
+        println!("Domains: {}", report.domains);
+        println!("Links:   {}", report.links);
+        println!();
+        for line in site::DomainSummary::table(&by_domain) {
+            println!("{}", line);
+        }
+    }
+
+    // Draw the number of sites at each depth, scaled to the most populated depth
+    if let Some(most) = report.pages_by_depth.values().max() {
+        println!();
+        println!("Pages by depth:");
+        let width = report
+            .pages_by_depth
+            .values()
+            .map(|count| count.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (depth, count) in &report.pages_by_depth {
+            let bar = "#".repeat((count * DEPTH_BAR_WIDTH).div_ceil(*most) as usize);
+            println!("  {:>3}  {:>width$}  {}", depth, count, bar, width = width);
+        }
+    }
+
+    // List the feeds discovered on each domain
+    if !report.feeds.is_empty() {
+        println!();
+        println!("Feeds:");
+        let width = report.feeds.keys().map(String::len).max().unwrap_or(0);
+        for (domain, feeds) in &report.feeds {
+            for feed in feeds {
+                println!("  {:<width$}  {}", domain, feed, width = width);
+            }
+        }
+    }
+
+    // List the hosts out of scope that the stored pages link to, the most referenced first
+    if !external_hosts.is_empty() {
+        let links: u64 = external_hosts.iter().map(|(_, links)| links).sum();
+        println!();
+        println!(
+            "External links: {} to {} hosts",
+            links,
+            external_hosts.len()
+        );
+        let width = external_hosts
+            .iter()
+            .map(|(host, _)| host.len())
+            .max()
+            .unwrap_or(0);
+        for (host, count) in &external_hosts {
+            println!("  {:<width$}  {}", host, count, width = width);
+        }
+    }
+
+    // List the failed fetches, the most common kinds and worst hosts first
+    if !failures.is_empty() {
+        let kinds = by_kind
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect::<Vec<String>>();
+        println!();
+        println!("Failed fetches: {} ({})", failures.len(), kinds.join(", "));
+        let width = failing_hosts
+            .iter()
+            .map(|(host, _)| host.len())
+            .max()
+            .unwrap_or(0);
+        for (host, count) in &failing_hosts {
+            println!("  {:<width$}  {}", host, count, width = width);
+        }
+    }
+
+    // List the latest crawl runs, the most recent first
+    if !runs.is_empty() {
+        println!();
+        for line in crawl_run::CrawlRun::table(&runs) {
+            println!("{}", line);
+        }
+    }
+    return Ok(found);
+}
+
+/// Gets the name of the database a subcommand reads, `--database` if it is given, so that the
+/// config file is only needed without it.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` read from the config file, or the error reading it.
+/// * `database_name` - The database name given with `--database`, if any.
+fn database_name(config: Result<config::Config>, database_name: Option<&str>) -> Result<String> {
+    return match database_name {
+        Some(database_name) => Ok(database_name.to_string()),
+        None => Ok(config?.database),
+    };
+}
+
+/// Opens the database of an earlier crawl for a subcommand, without creating one.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` read from the config file, or the error reading it.
+/// * `database_name` - The database name given with `--database`, if any.
+///
+/// # Errors
+///
+/// This function will return an error if the config can't be read without `--database`, or the
+/// database doesn't exist or can't be opened.
+fn open_database(
+    config: Result<config::Config>,
+    database_name: Option<&str>,
+) -> Result<database::Database> {
+    let database_name = self::database_name(config, database_name)?;
+    let path = database::Database::path_for(&database_name);
+    if database_name != database::MEMORY_DATABASE && !path.exists() {
+        bail!("No crawl database at {}", path.display());
+    }
+    let database = database::Database::new(&database_name)?;
+    database.setup()?;
+    return Ok(database);
+}
+
+/// Parses the `--since` argument of `rustle query` and the `--older-than` argument of
+/// `rustle prune`, either an RFC 3339 timestamp or a duration ago made of a number and a unit:
+/// `m` for minutes, `h` for hours, `d` for days or `w` for weeks. Negative durations, and
+/// durations reaching before the earliest representable date, are rejected.
+fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(since) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let error = || {
+        format!(
+            "'{}' is neither an RFC 3339 timestamp nor a duration such as 24h or 7d",
+            since
+        )
+    };
+    let (amount, unit) =
+        since.split_at(since.len() - since.chars().last().map_or(0, char::len_utf8));
+    let amount = amount.parse::<i64>().with_context(error)?;
+    if amount < 0 {
+        bail!(
+            "'{}' is a duration in the future, give one ago such as 7d",
+            since
+        );
+    }
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => None,
+    }
+    .with_context(error)?;
+
+    return Utc::now()
+        .checked_sub_signed(duration)
+        .with_context(|| format!("'{}' is too long ago to be a date", since));
+}
+
+/// Prints the requested analyses of the stored crawl data, either as text or as versioned JSON
+/// documents.
+fn analyze(
+    config: Result<config::Config>,
+    suggest_rules: bool,
+    js_dependency: bool,
+    security: bool,
+    json: bool,
+) -> Result<()> {
+    if !suggest_rules && !js_dependency && !security {
+        bail!("Nothing to analyze, pass --suggest-rules, --js-dependency or --security");
+    }
+
+    let config = config?;
+    let database = database::Database::new(&config.database)?;
+    database.setup()?;
+
+    if suggest_rules {
+        print_suggested_rules(&database, json)?;
+    }
+    if js_dependency {
+        print_js_dependency(&database, json)?;
+    }
+    if security {
+        print_security(&config, &database, json)?;
+    }
+
+    return Ok(());
+}
+
+/// Exports the link graph of the stored sites to a file, or prints it.
+///
+/// # Returns
+///
+/// A boolean indicating whether the graph had any node, nothing being exported otherwise.
+///
+/// # Errors
+///
+/// This function will return an error if the database cannot be read, or the output file
+/// cannot be written.
+fn export(
+    database: &database::Database,
+    format: export::ExportFormat,
+    output: Option<PathBuf>,
+    options: &export::ExportOptions,
+) -> Result<bool> {
+    if !export::has_nodes(database, options)? {
+        return Ok(false);
+    }
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match format {
+        export::ExportFormat::Dot => export::export_dot(database, &mut writer, options)?,
+        export::ExportFormat::Json => export::export_json(database, &mut writer, options)?,
+        export::ExportFormat::Csv => export::export_csv(database, &mut writer, options)?,
+    }
+    writer
+        .flush()
+        .context("Failed to write the exported graph")?;
+
+    if let Some(path) = output {
+        info!("Exported the link graph to {}", path.display());
+    }
+    return Ok(true);
+}
+
+/// Writes a report of the latest crawl stored in a database to a file, or prints it.
+///
+/// # Returns
+///
+/// A boolean indicating whether any site or crawl run is stored, nothing being written
+/// otherwise.
+///
+/// # Errors
+///
+/// This function will return an error if the database cannot be read, or the report cannot be
+/// written.
+fn write_report(
+    database: &database::Database,
+    format: Option<report::ReportFormat>,
+    output: Option<PathBuf>,
+    include_suspect: bool,
+) -> Result<bool> {
+    if database.count_rows("sites")? == 0 && database.count_rows("crawl_runs")? == 0 {
+        return Ok(false);
+    }
+
+    let format = match (format, &output) {
+        (Some(format), _) => format,
+        (None, Some(path)) => report::ReportFormat::of(path),
+        (None, None) => report::ReportFormat::Markdown,
+    };
+    match output {
+        Some(path) => {
+            report::write_report(database, None, include_suspect, format, &path)?;
+            info!("Wrote the crawl report to {}", path.display());
+        }
+        None => print!(
+            "{}",
+            report::render_report(database, None, include_suspect, format)?
+        ),
+    }
+    return Ok(true);
+}
+
+/// Merges another crawl database into the configured one and prints how many rows of each
+/// table were inserted, updated and skipped.
+fn merge(database_name: &str, path: &std::path::Path) -> Result<()> {
+    let database = database::Database::new(database_name)?;
+
+    let report = database.merge_from(&path.to_string_lossy())?;
+    println!("sites: {}", report.sites);
+    println!("domains: {}", report.domains);
+    return Ok(());
+}
+
+/// Deletes the sites and domains last crawled before a cutoff time, then returns the space they
+/// took to the file system, and prints how many rows and bytes were reclaimed. With `dry_run`,
+/// only prints what would be deleted.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` to prune.
+/// * `older_than` - The time before which sites and domains are deleted.
+/// * `dry_run` - Whether nothing is deleted.
+fn prune(database: &database::Database, older_than: DateTime<Utc>, dry_run: bool) -> Result<()> {
+    let cutoff = older_than.to_rfc3339_opts(SecondsFormat::Secs, true);
+    if dry_run {
+        let sites = database.sites_older_than(older_than)?;
+        let domains = database.domains_older_than(older_than)?;
+        for url in &sites {
+            println!("site {}", url);
+        }
+        for domain in &domains {
+            println!("domain {}", domain);
+        }
+        println!(
+            "Would delete {} sites and {} domains crawled before {}",
+            sites.len(),
+            domains.len(),
+            cutoff
+        );
+        return Ok(());
+    }
+
+    let bytes_before = database.disk_bytes();
+    let sites = database.delete_sites_older_than(older_than)?;
+    let domains = database.delete_domains_older_than(older_than)?;
+    database.reclaim_space()?;
+    let bytes_after = database.disk_bytes();
+    println!(
+        "Deleted {} sites and {} domains crawled before {}",
+        sites, domains, cutoff
+    );
+    println!(
+        "Reclaimed {} bytes, from {} to {} bytes",
+        bytes_before.saturating_sub(bytes_after),
+        bytes_before,
+        bytes_after
+    );
+    return Ok(());
+}
+
+/// Prints the canonicalization rules suggested by the stored crawl data.
+fn print_suggested_rules(database: &database::Database, json: bool) -> Result<()> {
+    let suggestions = analyze::suggest_rules(database, &analyze::SuggestionThresholds::default())?;
+    if json {
+        let report = output::SuggestRulesReport {
+            suggestions: suggestions.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::SuggestRulesReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(());
+    }
+
+    if suggestions.is_empty() {
+        println!("No canonicalization rules to suggest");
+    }
+    for suggestion in &suggestions {
+        println!("{}", suggestion);
+    }
+
+    return Ok(());
+}
+
+/// Prints the stored pages that depend the most on JavaScript, where the crawl is blind.
+fn print_js_dependency(database: &database::Database, json: bool) -> Result<()> {
+    let pages = database.list_js_dependent(JS_DEPENDENCY_LIMIT)?;
+    if json {
+        let report = output::JsDependencyReport {
+            pages: pages
+                .into_iter()
+                .map(|(url, js_dependency_score)| output::JsDependencyOutput {
+                    url,
+                    js_dependency_score,
+                })
+                .collect(),
+        };
+        let document = output::Document::new(output::JsDependencyReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(());
+    }
+
+    if pages.is_empty() {
+        println!("No pages depend on JavaScript");
+    }
+    for (url, score) in &pages {
+        println!("{:.2} {}", score, url);
+    }
+
+    return Ok(());
+}
+
+/// Prints the security findings of the stored crawl data.
+fn print_security(
+    config: &config::Config,
+    database: &database::Database,
+    json: bool,
+) -> Result<()> {
+    let findings = analyze::find_upload_html(
+        database,
+        &config.upload_path_patterns,
+        &config.html_content_types,
+    )?;
+    if json {
+        let report = output::SecurityReport {
+            upload_html: findings.iter().map(Into::into).collect(),
+        };
+        let document = output::Document::new(output::SecurityReport::NAME, report);
+        println!("{}", document.to_json()?);
+        return Ok(());
+    }
+
+    if findings.is_empty() {
+        println!("No security findings");
+    }
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_since_reads_timestamps_and_durations_ago() {
+        assert_eq!(
+            parse_since("2024-05-01T12:00:00+02:00").unwrap(),
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z").unwrap()
+        );
+        let before = Utc::now();
+        let since = parse_since("7d").unwrap();
+        let after = Utc::now();
+        assert!(since >= before - chrono::Duration::days(7));
+        assert!(since <= after - chrono::Duration::days(7));
+        assert!(parse_since("0m").unwrap() <= Utc::now());
+        let since = parse_since("2w").unwrap();
+        assert!(since <= Utc::now() - chrono::Duration::days(14));
+    }
+
+    #[test]
+    fn parse_since_rejects_malformed_negative_and_overflowing_inputs() {
+        for since in ["", "d", "7", "7y", "seven days", "1.5h", "2024-05-01"] {
+            let error = parse_since(since).unwrap_err().to_string();
+            assert!(error.contains("neither an RFC 3339 timestamp"), "{}", error);
+        }
+        let error = parse_since("-7d").unwrap_err().to_string();
+        assert!(error.contains("in the future"), "{}", error);
+        // Too large for a chrono::Duration
+        assert!(parse_since("99999999999999w").is_err());
+        // A valid duration, but reaching before the earliest date
+        let error = parse_since("1000000000d").unwrap_err().to_string();
+        assert!(error.contains("too long ago"), "{}", error);
+    }
+}
@@ -1,27 +1,758 @@
-use anyhow::{Context, Result};
+use crate::challenge;
+use crate::cookies::SeedCookie;
+use crate::database::{Database, MEMORY_DATABASE};
+use crate::headers;
+use crate::metrics::MetricsConfig;
+use crate::normalize::ascii_host;
+use crate::proxy::ProxyConfig;
+use crate::public_suffix::registrable_domain;
+use crate::render::JsWeights;
+use crate::resource::{FetchPolicies, FetchPolicy};
+use crate::robots::USER_AGENT;
+use crate::webhook::WebhookEvent;
+use anyhow::{bail, Context, Result};
 use directories::BaseDirs;
-use serde::Deserialize;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
 
-#[derive(Deserialize)]
+/// The maximum recursion depth accepted by `Config::validate`.
+pub const MAX_DEPTH: u64 = 100;
+
+/// The prefix of the environment variables that override config values, such as `RUSTLE_DEPTH`
+/// for `depth`.
+pub const ENV_PREFIX: &str = "RUSTLE_";
+
+/// The aliases of `Config` fields kept for older config files, with the fields they stand for.
+const ALIASES: &[(&str, &str)] = &[("origin_url", "origin_urls"), ("database_name", "database")];
+
+#[derive(Deserialize, Serialize, Clone)]
 /// Configuration structure for the application.
 ///
 /// This structure holds the configuration parameters needed for the application to run.
 /// It is derived from the `Deserialize` trait to allow easy loading from configuration files.
 pub struct Config {
-    /// The URL from which the application will start crawling.
-    pub origin_url: String,
+    /// The URLs from which the application will start crawling, each at depth 0. A single URL
+    /// may be given as a bare string, and `origin_url` is accepted as the key of older config
+    /// files.
+    #[serde(alias = "origin_url", deserialize_with = "one_or_many")]
+    pub origin_urls: Vec<String>,
     /// The depth to which the application will crawl.
+    #[serde(default = "default_depth")]
     pub depth: u64,
-    /// The name of the database to be used by the crawler to store sites.
-    pub database_name: String,
+    /// The name or path of the database to be used by the crawler to store sites, or `:memory:`
+    /// to keep them in memory for the duration of the crawl. `.db` is appended to names without
+    /// an extension, and a leading `~` is expanded to the home directory. `database_name` is
+    /// accepted as the key of older config files.
+    #[serde(alias = "database_name", default = "default_database")]
+    pub database: String,
+    /// Where the crawl is stored: the SQLite database named by `database`, or a JSON Lines log.
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// The path of the JSON Lines log when `storage` is `jsonl`, or the database path with a
+    /// `.jsonl` extension when unset.
+    pub storage_path: Option<PathBuf>,
+    /// The file a report of the crawl is written to once it finishes, in HTML if its extension
+    /// is `.html` or `.htm` and in Markdown otherwise. It is read back from the SQLite database.
+    pub report_path: Option<PathBuf>,
+    /// The URL a JSON summary of every crawl is posted to once it finishes or fails, such as a
+    /// Slack incoming webhook. A webhook that can't be notified never changes the exit code.
+    pub webhook_url: Option<String>,
+    /// The outcomes of the crawl `webhook_url` is notified of: `failure`, `success`, or both.
+    #[serde(default = "default_webhook_on")]
+    pub webhook_on: Vec<WebhookEvent>,
+    /// The maximum total size, in bytes, of the headers of a single response. The page of a
+    /// response with larger headers is flagged as a header anomaly, and its body is not read.
+    #[serde(default = "default_max_header_bytes")]
+    pub max_header_bytes: usize,
+    /// The maximum size, in bytes, of the body of a single response. Larger pages are neither
+    /// parsed nor stored.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+    /// The transfer rate, in bytes per second, below which the body of a response is considered
+    /// stalled. `0` never aborts slow responses.
+    #[serde(default = "default_min_transfer_rate")]
+    pub min_transfer_rate: u64,
+    /// The number of seconds the transfer rate may stay below `min_transfer_rate` before the
+    /// fetch is aborted.
+    #[serde(default = "default_slow_transfer_secs")]
+    pub slow_transfer_secs: u64,
+    /// The maximum number of redirects followed for a single request. Longer chains are reported
+    /// as fetch errors.
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    /// Whether redirects to another host out of the `scope` of the crawl are followed. Unfollowed
+    /// redirects are recorded as skipped, and abort the crawl if the origin URL is redirected.
+    #[serde(default)]
+    pub follow_cross_host_redirects: bool,
+    /// Whether the query parameters of followed URLs are sorted by key, consistently
+    /// percent-encoded and stripped of empty parameters, so that URLs differing only in their
+    /// query are crawled once. Off by default, as some servers depend on the order.
+    #[serde(default)]
+    pub normalize_query: bool,
+    /// The maximum length of a followed URL, or unlimited when 0. Longer URLs are rejected as
+    /// crawler traps.
+    #[serde(default = "default_max_url_length")]
+    pub max_url_length: usize,
+    /// The maximum number of path segments of a followed URL, or unlimited when 0. Deeper URLs
+    /// are rejected as crawler traps.
+    #[serde(default = "default_max_path_depth")]
+    pub max_path_depth: usize,
+    /// The number of times a path segment may repeat in a row in a followed URL, or unlimited
+    /// when 0. URLs such as `/a/a/a/a/` that repeat it more are rejected as crawler traps.
+    #[serde(default = "default_max_repeated_segments")]
+    pub max_repeated_segments: usize,
+    /// Whether the documents of the `<frame>` and `<iframe>` elements of a page are crawled as
+    /// links of the page, when they are of the same origin as the page.
+    #[serde(default = "default_follow_frames")]
+    pub follow_frames: bool,
+    /// Whether pages are stored under the canonical URL they declare with
+    /// `<link rel="canonical">`, with the fetched URL stored as its alias, so that the variants
+    /// of a page are stored and crawled once.
+    #[serde(default = "default_respect_canonical")]
+    pub respect_canonical: bool,
+    /// Whether the images, scripts and stylesheets of every page are extracted as asset links,
+    /// and checked with `HEAD` requests even if `fetch_policy.assets` is `skip`.
+    #[serde(default)]
+    pub extract_assets: bool,
+    /// Whether the cookies set by responses are kept for the rest of the crawl and sent back to
+    /// the domains that set them, for sites that need a session or consent cookie.
+    #[serde(default)]
+    pub cookies: bool,
+    /// The cookies sent from the start of the crawl, such as a login cookie obtained out of
+    /// band. Needs `cookies = true`.
+    #[serde(default)]
+    pub seed_cookies: Vec<SeedCookie>,
+    /// The maximum number of cookies a single host may set over the course of a crawl. The
+    /// cookies a host sets are always counted, and the page that goes over this cap is flagged
+    /// as a header anomaly. With `cookies = true`, the cookies beyond it are also skipped by the
+    /// cookie store.
+    #[serde(default = "default_max_cookies_per_host")]
+    pub max_cookies_per_host: u64,
+    /// The number of hours a crawled site stays cached before it is fetched again. `0` always
+    /// refetches, and leaving it unset never refetches sites that are already stored.
+    pub cache_ttl_hours: Option<u64>,
+    /// Which discovered URLs are crawled, relative to the host of the origin URL.
+    #[serde(default)]
+    pub scope: Scope,
+    /// The order in which discovered URLs are fetched.
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// The number of pages fetched at once: the worker threads of a crawl, or the requests in
+    /// flight of an async crawl. Unset uses one thread per CPU core, or 64 requests in flight.
+    pub concurrency: Option<usize>,
+    /// The maximum number of requests in flight to a single host. Fetches of URLs on a host
+    /// that has this many wait for one of them to finish.
+    #[serde(default = "default_max_concurrent_per_host")]
+    pub max_concurrent_per_host: usize,
+    /// The politeness delay, in milliseconds, each worker waits before issuing a request.
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Whether the progress of a crawl is displayed while it runs: as a progress bar when stderr
+    /// is a terminal, or else as a status line logged every `progress_interval_secs`.
+    #[serde(default)]
+    pub progress: bool,
+    /// The number of seconds between the progress lines logged when stderr is not a terminal.
+    #[serde(default = "default_progress_interval_secs")]
+    pub progress_interval_secs: u64,
+    /// The format of the log lines: colored text for terminals, or one JSON object per line for
+    /// log collectors.
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// The file log lines are also written to, if any.
+    pub log_file: Option<PathBuf>,
+    /// The size, in megabytes, past which `log_file` is rotated to `.1`, `.2`, and so on, or
+    /// unset to never rotate it.
+    pub log_max_size_mb: Option<u64>,
+    /// Whether log lines are only written to `log_file`, and not to the console.
+    #[serde(default)]
+    pub quiet: bool,
+    /// The `User-Agent` header sent with every request. robots.txt rules are still matched
+    /// against the `Rustle` user agent.
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    /// The number of seconds a request may take in total, body included, before it fails.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// The maximum number of URLs that failed with a timeout, a connection failure or a server
+    /// error during a crawl and are fetched once more, one at a time, before it ends. 0 never
+    /// retries them.
+    #[serde(default = "default_final_retry_limit")]
+    pub final_retry_limit: usize,
+    /// Whether the crawler obeys the `robots.txt` rules of the sites it visits.
+    #[serde(default = "default_respect_robots")]
+    pub respect_robots: bool,
+    /// The number of hours a fetched `robots.txt` is obeyed before it is fetched again, whether
+    /// it is stored from an earlier crawl or was fetched during a long one.
+    #[serde(default = "default_robots_ttl_hours")]
+    pub robots_ttl_hours: u64,
+    /// Whether the links of the pages at the last depth level are validated with `HEAD`
+    /// requests, recording their status and content type without downloading them. With `depth`
+    /// 0, every link of the origins is only validated.
+    #[serde(default)]
+    pub validate_only: bool,
+    /// Whether only the origin pages are fetched, listing what the crawl would do with each of
+    /// their links, instead of crawling and storing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// The maximum number of pages fetched in a single crawl, or unlimited when unset.
+    pub max_pages: Option<u64>,
+    /// The maximum number of pages fetched from a single host in a single crawl, or from a
+    /// single registrable domain with the `site` scope. Unlimited when unset.
+    pub max_pages_per_domain: Option<u64>,
+    /// The number of URLs the crawl is expected to visit. Past `EXACT_VISITED_LIMIT`, visited
+    /// URLs are only remembered by a 64-bit hash to save memory. The queued URLs and the other
+    /// per-URL state of the crawl are still kept in full.
+    pub expected_crawl_size: Option<u64>,
+    /// The size in megabytes the database may grow to during a crawl, or unlimited when unset.
+    /// The crawl stops admitting new URLs once it is reached.
+    pub max_disk_mb: Option<u64>,
+    /// The number of pages written to the database in a single transaction. Pages are written
+    /// at the end of every depth level, or whenever this many are waiting.
+    #[serde(default = "default_write_batch_size")]
+    pub write_batch_size: usize,
+    /// Whether the visible text of every page is stored for full-text search.
+    #[serde(default)]
+    pub store_text: bool,
+    /// Whether every stored page records the robots.txt snapshot and rule that allowed it, for
+    /// compliance audits.
+    #[serde(default)]
+    pub compliance: bool,
+    /// Whether the sitemaps listed in the origin's robots.txt are used to seed the crawl.
+    #[serde(default)]
+    pub use_sitemaps: bool,
+    /// Whether the RSS and Atom feeds announced by pages are fetched, and the entries they list
+    /// crawled along with the links of the pages.
+    #[serde(default)]
+    pub use_feeds: bool,
+    /// Whether the `<a>` links marked `rel="nofollow"`, `rel="ugc"` or `rel="sponsored"` are
+    /// followed like the other links of a page.
+    #[serde(default)]
+    pub follow_nofollow: bool,
+    /// Whether the links that aren't followed because of their `rel` attribute are still stored
+    /// in the `links_to` of the page, so that the stored link graph is complete.
+    #[serde(default)]
+    pub record_nofollow_links: bool,
+    /// Whether the `<a>` links to hosts out of `scope` are still stored in the `links_to` of the
+    /// page, tagged as external, so that the sites the crawl references are known. They are never
+    /// crawled.
+    #[serde(default)]
+    pub record_external_links: bool,
+    /// Whether the recorded external links are checked with a `HEAD` request at the end of the
+    /// crawl, to flag dead outbound links, without parsing or following them.
+    #[serde(default)]
+    pub verify_external: bool,
+    /// The strings that identify bot-detection challenge pages in blocked responses.
+    #[serde(default = "default_challenge_markers")]
+    pub challenge_markers: Vec<String>,
+    /// The number of consecutive challenge pages from a host after which it is suspended for the
+    /// rest of the crawl.
+    #[serde(default = "default_max_consecutive_challenges")]
+    pub max_consecutive_challenges: u64,
+    /// The fraction of the previous crawl's fetched pages below which a crawl with mostly failed
+    /// fetches is marked as suspect.
+    #[serde(default = "default_suspect_ratio")]
+    pub suspect_ratio: f64,
+    /// The media types of the responses that are parsed for links. Responses of other types are
+    /// recorded without reading their body.
+    #[serde(default = "default_html_content_types")]
+    pub html_content_types: Vec<String>,
+    /// Local `robots.txt` files used instead of the live ones, keyed by domain, to crawl as if
+    /// planned rules were already deployed.
+    #[serde(default)]
+    pub robots_override: BTreeMap<String, PathBuf>,
+    /// Whether the live `robots.txt` of overridden domains is still fetched, to report the pages
+    /// whose admission differs between the live and the planned rules.
+    #[serde(default)]
+    pub robots_override_diff: bool,
+    /// The path fragments of URLs that serve uploaded files, such as `/uploads/`. Pages served as
+    /// HTML under them are flagged by `rustle analyze --security`.
+    #[serde(default = "default_upload_path_patterns")]
+    pub upload_path_patterns: Vec<String>,
+    /// How each kind of resource is fetched, as guessed from the extension of its URL.
+    #[serde(default)]
+    pub fetch_policy: FetchPolicies,
+    /// The weights of the heuristics combined into the `js_dependency_score` of each page.
+    #[serde(default)]
+    pub js_weights: JsWeights,
+    /// The Prometheus metrics endpoint served while a crawl runs.
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Whether invalid and self-signed TLS certificates are accepted from every host. Prefer
+    /// `trusted_hosts` or `extra_root_certs`.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+    /// The hosts whose invalid and self-signed TLS certificates are accepted, such as internal
+    /// staging environments.
+    #[serde(default)]
+    pub trusted_hosts: Vec<String>,
+    /// The PEM files of the extra root certificates trusted for every host, such as the one of an
+    /// internal certificate authority.
+    #[serde(default)]
+    pub extra_root_certs: Vec<PathBuf>,
+    /// The extra headers of every request, by the domain pattern of the hosts they are sent to:
+    /// `*` for every host, `*.example.com` for the subdomains of `example.com`, or a single host.
+    #[serde(default)]
+    pub headers: BTreeMap<String, BTreeMap<String, String>>,
+    /// The proxy every request goes through, or the one of the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `NO_PROXY` environment variables when unset.
+    pub proxy: Option<ProxyConfig>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+/// Determines which discovered URLs are in scope for crawling.
+pub enum Scope {
+    /// Only URLs on exactly the same host as the origin URL.
+    #[default]
+    Host,
+    /// URLs on the host of the origin URL and any of its subdomains.
+    Subdomains,
+    /// URLs on any host under the same registrable domain as the origin URL, such as
+    /// `blog.example.co.uk` and `www.example.co.uk`, according to the Public Suffix List. Hosts
+    /// without a registrable domain, such as IP addresses, are only in scope of themselves.
+    Site,
+    /// Any URL, regardless of host.
+    Any,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+/// Determines the backend a crawl is stored in.
+pub enum StorageKind {
+    /// The SQLite database named by `Config::database`.
+    #[default]
+    Sqlite,
+    /// A JSON Lines log, with one object per page and per domain.
+    Jsonl,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+/// Determines the format of the log lines.
+pub enum LogFormat {
+    /// Colored, human-readable lines from `pretty_env_logger`.
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the fields of per-URL events next to the message.
+    Json,
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+/// Config values given on the command line, which take precedence over the config file.
+pub struct Overrides {
+    /// The URL to start crawling from, instead of `origin_urls`. Repeat it to crawl from
+    /// several URLs.
+    #[arg(long = "origin-url", global = true, value_name = "ORIGIN_URL")]
+    pub origin_urls: Vec<String>,
+    /// The depth to crawl to, instead of `depth`.
+    #[arg(long, global = true)]
+    pub depth: Option<u64>,
+    /// The name or path of the database to store sites in, instead of `database`.
+    #[arg(long = "database", global = true)]
+    pub database: Option<String>,
+    /// The User-Agent header sent with every request, instead of `user_agent`.
+    #[arg(long, global = true)]
+    pub user_agent: Option<String>,
+}
+
+impl Overrides {
+    /// Checks whether no value is overridden.
+    pub fn is_empty(&self) -> bool {
+        return self.origin_urls.is_empty()
+            && self.depth.is_none()
+            && self.database.is_none()
+            && self.user_agent.is_none();
+    }
+
+    /// Sets the overridden values in a parsed config file.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A mutable reference to the TOML table of the config file.
+    fn apply(self, table: &mut toml::Table) {
+        if !self.origin_urls.is_empty() {
+            set_value(table, "origin_urls", self.origin_urls.into());
+        }
+        if let Some(depth) = self.depth {
+            // TOML integers are signed, so a depth past `i64::MAX` is clamped, and then rejected
+            // by `Config::validate`
+            let depth = i64::try_from(depth).unwrap_or(i64::MAX);
+            table.insert("depth".to_string(), depth.into());
+        }
+        if let Some(database) = self.database {
+            set_value(table, "database", database.into());
+        }
+        if let Some(user_agent) = self.user_agent {
+            table.insert("user_agent".to_string(), user_agent.into());
+        }
+    }
+}
+
+impl Scope {
+    /// Checks whether a host is in scope for a crawl that started from `origin_host`.
+    ///
+    /// Hosts are compared in their ASCII form, so that internationalized domain names match
+    /// whether they are written in Unicode or in punycode.
+    ///
+    /// ```
+    /// use rustle::Scope;
+    ///
+    /// assert!(Scope::Host.allows("xn--bcher-kva.example", "bücher.example"));
+    /// assert!(Scope::Subdomains.allows("shop.bücher.example", "xn--bcher-kva.example"));
+    /// assert!(!Scope::Host.allows("buecher.example", "bücher.example"));
+    ///
+    /// // The site scope compares registrable domains
+    /// assert!(Scope::Site.allows("foo.example.co.uk", "bar.example.co.uk"));
+    /// assert!(Scope::Site.allows("www.example.co.uk", "example.co.uk"));
+    /// assert!(!Scope::Site.allows("another.co.uk", "example.co.uk"));
+    /// assert!(!Scope::Site.allows("example.othertld.com", "www.example.com"));
+    /// assert!(Scope::Site.allows("127.0.0.1", "127.0.0.1"));
+    /// assert!(!Scope::Site.allows("intranet", "wiki"));
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string slice that holds the host of the discovered URL.
+    /// * `origin_host` - A string slice that holds the host of the origin URL.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating whether URLs on `host` should be crawled.
+    pub fn allows(&self, host: &str, origin_host: &str) -> bool {
+        let (host, origin_host) = (&ascii_host(host), &ascii_host(origin_host));
+        return match self {
+            Scope::Host => host == origin_host,
+            Scope::Subdomains => {
+                host == origin_host || host.ends_with(&format!(".{}", origin_host))
+            }
+            Scope::Site => match (registrable_domain(host), registrable_domain(origin_host)) {
+                (Some(domain), Some(origin_domain)) => domain == origin_domain,
+                _ => host == origin_host,
+            },
+            Scope::Any => true,
+        };
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        return match s.trim().to_lowercase().as_str() {
+            "host" => Ok(Scope::Host),
+            "subdomains" => Ok(Scope::Subdomains),
+            "site" => Ok(Scope::Site),
+            "any" => Ok(Scope::Any),
+            other => bail!(
+                "Unknown scope '{}', expected one of: host, subdomains, site, any",
+                other
+            ),
+        };
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Scope::Host => "host",
+            Scope::Subdomains => "subdomains",
+            Scope::Site => "site",
+            Scope::Any => "any",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+/// Determines the order in which a crawl fetches the URLs it has discovered.
+pub enum Strategy {
+    /// Breadth-first: every page of a depth level is fetched before any page of the next one.
+    #[default]
+    Bfs,
+    /// Depth-first: the URLs discovered last are fetched first, following each chain of links
+    /// down to `depth` before backtracking.
+    Dfs,
+    /// Shallower URLs first, and among those the URLs of the hosts with the fewest pages
+    /// discovered so far, so that a single large host doesn't hold up the others.
+    Priority,
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        return match s.trim().to_lowercase().as_str() {
+            "bfs" => Ok(Strategy::Bfs),
+            "dfs" => Ok(Strategy::Dfs),
+            "priority" => Ok(Strategy::Priority),
+            other => bail!(
+                "Unknown strategy '{}', expected one of: bfs, dfs, priority",
+                other
+            ),
+        };
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Strategy::Bfs => "bfs",
+            Strategy::Dfs => "dfs",
+            Strategy::Priority => "priority",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// The default value of `Config::challenge_markers`.
+fn default_challenge_markers() -> Vec<String> {
+    return challenge::DEFAULT_MARKERS
+        .iter()
+        .map(|marker| marker.to_string())
+        .collect();
+}
+
+/// The default value of `Config::max_consecutive_challenges`.
+fn default_max_consecutive_challenges() -> u64 {
+    return 5;
+}
+
+/// The default value of `Config::html_content_types`.
+fn default_html_content_types() -> Vec<String> {
+    return vec!["text/html".to_string(), "application/xhtml+xml".to_string()];
+}
+
+/// The default value of `Config::upload_path_patterns`.
+fn default_upload_path_patterns() -> Vec<String> {
+    return vec![
+        "/uploads/".to_string(),
+        "/media/".to_string(),
+        "/files/".to_string(),
+    ];
+}
+
+/// The default value of `Config::suspect_ratio`.
+fn default_suspect_ratio() -> f64 {
+    return 0.5;
+}
+
+/// The default value of `Config::follow_frames`.
+fn default_follow_frames() -> bool {
+    return true;
+}
+
+/// The default value of `Config::respect_canonical`.
+fn default_respect_canonical() -> bool {
+    return true;
+}
+
+/// The default value of `Config::respect_robots`.
+fn default_respect_robots() -> bool {
+    return true;
+}
+
+/// The default value of `Config::max_header_bytes` (64 KiB).
+fn default_max_header_bytes() -> usize {
+    return 64 * 1024;
+}
+
+/// The default value of `Config::max_body_bytes` (5 MiB).
+fn default_max_body_bytes() -> u64 {
+    return 5 * 1024 * 1024;
+}
+
+/// The default value of `Config::min_transfer_rate` (1 KiB per second).
+fn default_min_transfer_rate() -> u64 {
+    return 1024;
+}
+
+/// The default value of `Config::slow_transfer_secs`.
+fn default_slow_transfer_secs() -> u64 {
+    return 10;
+}
+
+/// The default value of `Config::max_redirects`.
+fn default_max_redirects() -> usize {
+    return 10;
+}
+
+/// The default value of `Config::max_url_length`.
+fn default_max_url_length() -> usize {
+    return 2000;
+}
+
+/// The default value of `Config::max_path_depth`.
+fn default_max_path_depth() -> usize {
+    return 30;
+}
+
+/// The default value of `Config::max_repeated_segments`.
+fn default_max_repeated_segments() -> usize {
+    return 3;
+}
+
+/// Deserializes `Config::origin_urls` from either a list of URLs or a single bare URL.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    return match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => Ok(vec![url]),
+        OneOrMany::Many(urls) => Ok(urls),
+    };
+}
+
+/// The default value of `Config::depth`.
+fn default_depth() -> u64 {
+    return 3;
+}
+
+/// The default value of `Config::database`.
+fn default_database() -> String {
+    return "crawler".to_string();
+}
+
+/// The default value of `Config::user_agent`, such as `Rustle/0.0.1`.
+fn default_user_agent() -> String {
+    return format!("{}/{}", USER_AGENT, env!("CARGO_PKG_VERSION"));
+}
+
+/// The default value of `Config::webhook_on`: every outcome.
+fn default_webhook_on() -> Vec<WebhookEvent> {
+    return vec![WebhookEvent::Failure, WebhookEvent::Success];
+}
+
+/// The default value of `Config::timeout_secs`.
+fn default_timeout_secs() -> u64 {
+    return 30;
+}
+
+/// The default value of `Config::final_retry_limit`.
+fn default_final_retry_limit() -> usize {
+    return 20;
+}
+
+/// The default value of `Config::robots_ttl_hours`, the longest a robots.txt is cached for
+/// according to RFC 9309.
+fn default_robots_ttl_hours() -> u64 {
+    return 24;
+}
+
+/// The default value of `Config::write_batch_size`.
+fn default_write_batch_size() -> usize {
+    return 500;
+}
+
+/// The default value of `Config::max_concurrent_per_host`.
+fn default_max_concurrent_per_host() -> usize {
+    return 2;
+}
+
+/// The default value of `Config::progress_interval_secs`.
+fn default_progress_interval_secs() -> u64 {
+    return 10;
+}
+
+/// The default value of `Config::max_cookies_per_host`.
+fn default_max_cookies_per_host() -> u64 {
+    return 50;
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Config {
+            origin_urls: Vec::new(),
+            depth: default_depth(),
+            database: default_database(),
+            storage: StorageKind::default(),
+            storage_path: None,
+            report_path: None,
+            webhook_url: None,
+            webhook_on: default_webhook_on(),
+            max_header_bytes: default_max_header_bytes(),
+            max_body_bytes: default_max_body_bytes(),
+            min_transfer_rate: default_min_transfer_rate(),
+            slow_transfer_secs: default_slow_transfer_secs(),
+            max_redirects: default_max_redirects(),
+            follow_cross_host_redirects: false,
+            normalize_query: false,
+            max_url_length: default_max_url_length(),
+            max_path_depth: default_max_path_depth(),
+            max_repeated_segments: default_max_repeated_segments(),
+            follow_frames: default_follow_frames(),
+            respect_canonical: default_respect_canonical(),
+            extract_assets: false,
+            cookies: false,
+            seed_cookies: Vec::new(),
+            max_cookies_per_host: default_max_cookies_per_host(),
+            cache_ttl_hours: None,
+            scope: Scope::default(),
+            strategy: Strategy::default(),
+            concurrency: None,
+            max_concurrent_per_host: default_max_concurrent_per_host(),
+            delay_ms: 0,
+            progress: false,
+            progress_interval_secs: default_progress_interval_secs(),
+            log_format: LogFormat::default(),
+            log_file: None,
+            log_max_size_mb: None,
+            quiet: false,
+            user_agent: default_user_agent(),
+            timeout_secs: default_timeout_secs(),
+            final_retry_limit: default_final_retry_limit(),
+            respect_robots: default_respect_robots(),
+            robots_ttl_hours: default_robots_ttl_hours(),
+            validate_only: false,
+            dry_run: false,
+            max_pages: None,
+            max_pages_per_domain: None,
+            expected_crawl_size: None,
+            max_disk_mb: None,
+            write_batch_size: default_write_batch_size(),
+            store_text: false,
+            compliance: false,
+            use_sitemaps: false,
+            use_feeds: false,
+            follow_nofollow: false,
+            record_nofollow_links: false,
+            record_external_links: false,
+            verify_external: false,
+            challenge_markers: default_challenge_markers(),
+            max_consecutive_challenges: default_max_consecutive_challenges(),
+            suspect_ratio: default_suspect_ratio(),
+            html_content_types: default_html_content_types(),
+            robots_override: BTreeMap::new(),
+            robots_override_diff: false,
+            upload_path_patterns: default_upload_path_patterns(),
+            fetch_policy: FetchPolicies::default(),
+            js_weights: JsWeights::default(),
+            metrics: MetricsConfig::default(),
+            danger_accept_invalid_certs: false,
+            trusted_hosts: Vec::new(),
+            extra_root_certs: Vec::new(),
+            headers: BTreeMap::new(),
+            proxy: None,
+        };
+    }
 }
 
 impl Config {
     /// Creates a new `Config` instance by reading from the configuration file.
     ///
     /// This function reads the configuration file located at `config.toml` and parses its contents
-    /// into a `Config` struct.
+    /// into a `Config` struct, which is then validated.
     ///
     /// # Returns
     ///
@@ -30,19 +761,837 @@ impl Config {
     ///
     /// # Errors
     ///
-    /// This function will return an error if it fails to read or parse the configuration file.
+    /// This function will return an error if it fails to read or parse the configuration file,
+    /// or if the configuration is invalid.
+    pub fn new() -> Result<Self> {
+        return Self::load(None, Overrides::default());
+    }
+
+    /// Creates a new `Config` instance from a configuration file, environment variables and
+    /// values given on the command line, in increasing order of precedence.
     ///
-    /// # Panics
+    /// Every setting can be set with an environment variable named after it, such as
+    /// `RUSTLE_DEPTH` for `depth` or `RUSTLE_ORIGIN_URL` for `origin_url`. Its value is read as
+    /// a TOML value, such as `3` or `["https://a.example", "https://b.example"]`, or else as a
+    /// string.
     ///
-    /// This function will panic if the base directories cannot be determined.    
-    pub fn new() -> Result<Self> {
-        let base_dirs = BaseDirs::new().context("Failed to get base directories")?;
-        let config_path = format!("{}/Rustle/config.toml", base_dirs.config_dir().display());
-        let config_str = fs::read_to_string(&config_path)
-            .with_context(|| format!("Failed to read config file at {}", config_path))?;
-        let config: Self = toml::from_str(&config_str)
-            .with_context(|| format!("Failed to parse config file at {}", config_path))?;
+    /// The file at `path` must exist:
+    ///
+    /// ```
+    /// use rustle::{Config, Overrides};
+    /// use std::path::Path;
+    ///
+    /// let path = std::env::temp_dir().join("rustle-load-doctest.toml");
+    /// std::fs::write(&path, "origin_url = \"https://example.com\"\ndepth = 3\ndatabase_name = \"sites\"\n").unwrap();
+    ///
+    /// std::env::set_var("RUSTLE_DEPTH", "2");
+    /// std::env::set_var("RUSTLE_USER_AGENT", "Bot/1.0");
+    ///
+    /// let overrides = Overrides {
+    ///     depth: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let config = Config::load(Some(Path::new(&path)), overrides).unwrap();
+    /// assert_eq!(config.origin_urls, ["https://example.com"]);
+    /// assert_eq!(config.depth, 1);
+    /// assert_eq!(config.user_agent, "Bot/1.0");
+    /// ```
+    ///
+    /// The file at `Config::default_path` is optional, so that a crawl can be configured on the
+    /// command line or in the environment alone. Only the origin URLs have no default:
+    ///
+    /// ```
+    /// use rustle::{Config, Overrides};
+    ///
+    /// let dir = std::env::temp_dir().join("rustle-load-default-doctest");
+    /// std::env::set_var("XDG_CONFIG_HOME", &dir);
+    /// std::env::set_var("HOME", &dir);
+    ///
+    /// let Err(error) = Config::load(None, Overrides::default()) else {
+    ///     panic!("a config without origin URLs was loaded");
+    /// };
+    /// assert!(error.to_string().contains("--origin-url"));
+    ///
+    /// let overrides = Overrides {
+    ///     origin_urls: vec!["https://example.com".to_string()],
+    ///     ..Default::default()
+    /// };
+    /// let config = Config::load(None, overrides).unwrap();
+    /// assert_eq!(config.depth, 3);
+    /// assert_eq!(config.database, "crawler");
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file, or `None` for `Config::default_path`.
+    /// * `overrides` - The `Overrides` given on the command line.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to read or parse the configuration file
+    /// or an environment variable, if no origin URL is set anywhere, or if the configuration is
+    /// invalid.
+    pub fn load(path: Option<&Path>, overrides: Overrides) -> Result<Self> {
+        let config_path = match path {
+            Some(path) => path.to_path_buf(),
+            None => Self::default_path()?,
+        };
+
+        // The default config file is optional, as every setting but the origin URLs has a
+        // default, and they can be given in the environment or on the command line
+        let read_file = path.is_some() || config_path.exists();
+        let mut table = if read_file {
+            let config_str = fs::read_to_string(&config_path).with_context(|| {
+                format!("Failed to read config file at {}", config_path.display())
+            })?;
+            toml::from_str::<toml::Table>(&config_str).with_context(|| {
+                format!("Failed to parse config file at {}", config_path.display())
+            })?
+        } else {
+            toml::Table::new()
+        };
+
+        let env_values = env_values(std::env::vars());
+        for (_, field, value) in &env_values {
+            set_value(&mut table, field, value.clone());
+        }
+        let mut sources = Vec::new();
+        if read_file {
+            sources.push(format!("config file at {}", config_path.display()));
+        }
+        if !env_values.is_empty() {
+            let env_vars: Vec<&str> = env_values.iter().map(|(var, _, _)| var.as_str()).collect();
+            sources.push(env_vars.join(", "));
+        }
+        if !overrides.is_empty() {
+            sources.push("the command-line values".to_string());
+        }
+        overrides.apply(&mut table);
+
+        // Name the places the origin URLs can be given in, rather than only the missing field
+        if !table.contains_key("origin_urls") && !table.contains_key("origin_url") {
+            let file_hint = match read_file {
+                true => format!(
+                    "add origin_urls to the config file at {}",
+                    config_path.display()
+                ),
+                false => format!(
+                    "write a config file at {} with `rustle init`",
+                    config_path.display()
+                ),
+            };
+            bail!("No origin URL is set: {file_hint}, set RUSTLE_ORIGIN_URL, or pass --origin-url");
+        }
+
+        let sources = sources.join(" and ");
+        let config: Self = table
+            .try_into()
+            .with_context(|| format!("Failed to parse the configuration from the {sources}"))?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid configuration from the {sources}"))?;
 
         return Ok(config);
     }
+
+    /// Returns the path of the configuration file for the current OS.
+    ///
+    /// On Linux, the file is in `$XDG_CONFIG_HOME` when it is set to an absolute path, and in
+    /// `~/.config` otherwise:
+    ///
+    /// ```
+    /// use rustle::Config;
+    /// use std::path::Path;
+    ///
+    /// let dir = std::env::temp_dir().join("rustle-default-path-doctest");
+    /// std::env::set_var("XDG_CONFIG_HOME", &dir);
+    /// let path = Config::default_path().unwrap();
+    /// if cfg!(target_os = "linux") {
+    ///     assert_eq!(path, dir.join("Rustle").join("config.toml"));
+    /// } else if cfg!(target_os = "macos") {
+    ///     assert!(path.ends_with("Library/Application Support/Rustle/config.toml"));
+    /// }
+    /// assert!(path.ends_with(Path::new("Rustle").join("config.toml")));
+    ///
+    /// // Relative paths are ignored, as the XDG Base Directory Specification requires
+    /// std::env::set_var("XDG_CONFIG_HOME", "relative");
+    /// assert!(Config::default_path().unwrap().is_absolute());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the base directories cannot be determined.
+    pub fn default_path() -> Result<PathBuf> {
+        let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| cfg!(target_os = "linux") && dir.is_absolute());
+        let config_dir = match xdg_config_home {
+            Some(dir) => dir,
+            None => BaseDirs::new()
+                .context("Failed to get base directories")?
+                .config_dir()
+                .to_path_buf(),
+        };
+        return Ok(config_dir.join("Rustle").join("config.toml"));
+    }
+
+    /// Returns the path of the JSON Lines log a crawl is stored in when `storage` is `jsonl`.
+    ///
+    /// # Returns
+    ///
+    /// The `storage_path` if it is set, or else the path of the database with a `.jsonl`
+    /// extension, such as `crawler.jsonl`.
+    pub fn jsonl_path(&self) -> PathBuf {
+        return match &self.storage_path {
+            Some(path) => path.clone(),
+            None => Database::path_for(&self.database).with_extension("jsonl"),
+        };
+    }
+
+    /// Returns the path of the file a crawl is stored in: the SQLite database, or the JSON Lines
+    /// log when `storage` is `jsonl`.
+    ///
+    /// # Returns
+    ///
+    /// The path of the file, or `None` if the crawl is kept in memory.
+    pub fn storage_location(&self) -> Option<PathBuf> {
+        return match self.storage {
+            StorageKind::Sqlite if self.database == MEMORY_DATABASE => None,
+            StorageKind::Sqlite => Some(Database::path_for(&self.database)),
+            StorageKind::Jsonl => Some(self.jsonl_path()),
+        };
+    }
+
+    /// Returns the distinct hosts of the origin URLs, in the order they are listed.
+    ///
+    /// ```
+    /// use rustle::Config;
+    ///
+    /// let config = Config {
+    ///     origin_urls: vec![
+    ///         "https://example.com/docs/".to_string(),
+    ///         "https://example.com/blog/".to_string(),
+    ///         "https://news.example.com/".to_string(),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(config.origin_hosts(), ["example.com", "news.example.com"]);
+    ///
+    /// // Internationalized domain names are written in punycode, whatever their form
+    /// let config = Config {
+    ///     origin_urls: vec![
+    ///         "https://bücher.example/".to_string(),
+    ///         "https://xn--bcher-kva.example/neu/".to_string(),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(config.origin_hosts(), ["xn--bcher-kva.example"]);
+    /// ```
+    pub fn origin_hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = Vec::new();
+        for origin_url in &self.origin_urls {
+            let host = Url::parse(origin_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string));
+            if let Some(host) = host.filter(|host| !hosts.contains(host)) {
+                hosts.push(host);
+            }
+        }
+        return hosts;
+    }
+
+    /// Validates the configuration values.
+    ///
+    /// This checks that every origin URL is an absolute http(s) URL with a host and that the
+    /// depth is within `1..=MAX_DEPTH`, or `0..=MAX_DEPTH` with `validate_only`. Every problem found is reported in the same error.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error describing each invalid value.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        // Check the origin URLs
+        if self.origin_urls.is_empty() {
+            problems.push("origin_urls must hold at least one URL".to_string());
+        }
+        for (i, origin_url) in self.origin_urls.iter().enumerate() {
+            if self.origin_urls[..i].contains(origin_url) {
+                problems.push(format!("origin_url '{}' is listed twice", origin_url));
+            }
+            match Url::parse(origin_url) {
+                Ok(url) => {
+                    if url.scheme() != "http" && url.scheme() != "https" {
+                        problems.push(format!(
+                            "origin_url '{}' must use the http or https scheme",
+                            origin_url
+                        ));
+                    }
+                    if !url.has_host() {
+                        problems.push(format!("origin_url '{}' has no host", origin_url));
+                    }
+                }
+                Err(e) => problems.push(format!(
+                    "origin_url '{}' is not a valid URL: {}",
+                    origin_url, e
+                )),
+            }
+        }
+
+        // Check the depth, which may be 0 when the links of the origins are only validated
+        let min_depth = u64::from(!self.validate_only);
+        if self.depth < min_depth || self.depth > MAX_DEPTH {
+            problems.push(format!(
+                "depth {} must be between {} and {}",
+                self.depth, min_depth, MAX_DEPTH
+            ));
+        }
+
+        // Check the database name, and that the crawl can be stored next to it
+        if self.database.trim().is_empty() {
+            problems.push("database must not be empty".to_string());
+        } else if let Some(path) = self.storage_location() {
+            if let Some(problem) = Self::check_parent_writable(&path) {
+                problems.push(problem);
+            }
+        }
+
+        // Check the suspect ratio
+        if !(0.0..=1.0).contains(&self.suspect_ratio) {
+            problems.push(format!(
+                "suspect_ratio {} must be between 0 and 1",
+                self.suspect_ratio
+            ));
+        }
+
+        // Check that slow responses are given some time before they are aborted
+        if self.min_transfer_rate > 0 && self.slow_transfer_secs == 0 {
+            problems.push("slow_transfer_secs must be greater than 0".to_string());
+        }
+
+        // Check the user agent, which must fit in a header
+        if self.user_agent.trim().is_empty() || HeaderValue::from_str(&self.user_agent).is_err() {
+            problems.push(format!(
+                "user_agent '{}' must be a non-empty header value",
+                self.user_agent
+            ));
+        }
+
+        // Check that pages can be fetched
+        if self.concurrency == Some(0) {
+            problems.push("concurrency must be greater than 0".to_string());
+        }
+        if self.max_concurrent_per_host == 0 {
+            problems.push("max_concurrent_per_host must be greater than 0".to_string());
+        }
+
+        // Check that progress lines aren't logged in a tight loop
+        if self.progress && self.progress_interval_secs == 0 {
+            problems.push("progress_interval_secs must be greater than 0".to_string());
+        }
+
+        // Check that log lines are written somewhere, and that the log file can grow
+        if self.quiet && self.log_file.is_none() {
+            problems.push("quiet needs a log_file to write log lines to".to_string());
+        }
+        if self.log_max_size_mb == Some(0) {
+            problems.push("log_max_size_mb must be greater than 0".to_string());
+        }
+
+        // Check that requests are given some time to complete
+        if self.timeout_secs == 0 {
+            problems.push("timeout_secs must be greater than 0".to_string());
+        }
+
+        // Check that robots.txt isn't fetched again for every URL
+        if self.robots_ttl_hours == 0 {
+            problems.push("robots_ttl_hours must be greater than 0".to_string());
+        }
+
+        // Check that there are external links to verify
+        if self.verify_external && !self.record_external_links {
+            problems.push("verify_external needs record_external_links = true".to_string());
+        }
+
+        // Check that the crawl report can be read back from the database once the crawl ends
+        if self.report_path.is_some()
+            && (self.storage != StorageKind::Sqlite || self.database == MEMORY_DATABASE)
+        {
+            problems
+                .push("report_path needs storage = \"sqlite\" with a database file".to_string());
+        }
+
+        // Check that the webhook can be notified
+        if let Some(webhook_url) = &self.webhook_url {
+            match Url::parse(webhook_url) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                Ok(_) => problems.push(format!(
+                    "webhook_url '{}' must use the http or https scheme",
+                    webhook_url
+                )),
+                Err(e) => problems.push(format!(
+                    "webhook_url '{}' is not a valid URL: {}",
+                    webhook_url, e
+                )),
+            }
+            if self.webhook_on.is_empty() {
+                problems.push("webhook_on must list \"failure\", \"success\" or both".to_string());
+            }
+        }
+
+        // Check that pages can be written
+        if self.write_batch_size == 0 {
+            problems.push("write_batch_size must be greater than 0".to_string());
+        }
+
+        // Check the upload path patterns, which are matched against URL paths
+        for pattern in &self.upload_path_patterns {
+            if !pattern.starts_with('/') {
+                problems.push(format!(
+                    "upload_path_patterns entry '{}' must start with '/'",
+                    pattern
+                ));
+            }
+        }
+
+        // Check that pages are fetched in full, as their links could not be followed otherwise
+        if self.fetch_policy.pages != FetchPolicy::Full {
+            problems.push("fetch_policy.pages must be \"full\"".to_string());
+        }
+
+        // Check the seeded cookies, which are only sent with a cookie store
+        if !self.seed_cookies.is_empty() && !self.cookies {
+            problems.push("seed_cookies needs cookies = true".to_string());
+        }
+        for cookie in &self.seed_cookies {
+            problems.extend(cookie.problems());
+        }
+
+        // Check the trusted hosts, which are matched against the hosts of URLs
+        for host in &self.trusted_hosts {
+            let parsed = Url::parse(&format!("https://{}/", host));
+            if host.is_empty()
+                || parsed.map_or(true, |url| url.path() != "/" || url.port().is_some())
+            {
+                problems.push(format!("trusted_hosts entry '{}' must be a host", host));
+            }
+        }
+
+        // Check the extra headers, leaving their values out of the problems as they may be secret
+        for (pattern, headers) in &self.headers {
+            if !headers::is_valid_pattern(pattern) {
+                problems.push(format!(
+                    "headers pattern '{}' must be '*', '*.domain' or a host",
+                    pattern
+                ));
+            }
+            for (name, value) in headers {
+                if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    problems.push(format!(
+                        "headers.\"{}\" name '{}' is not a valid header name",
+                        pattern, name
+                    ));
+                } else if HeaderValue::from_str(value).is_err() {
+                    problems.push(format!(
+                        "headers.\"{}\".{} is not a valid header value",
+                        pattern, name
+                    ));
+                }
+            }
+        }
+
+        // Check the proxy, so that a typo doesn't surface as every page failing
+        if let Some(proxy) = &self.proxy {
+            problems.extend(proxy.problems());
+        }
+
+        if !problems.is_empty() {
+            bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
+        return Ok(());
+    }
+
+    /// Checks that the directory a crawl is stored in can be written to, or else created in the
+    /// closest of its ancestors that exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path of the database or JSON Lines log.
+    ///
+    /// # Returns
+    ///
+    /// A description of the problem, or `None` if the directory can be written to or created.
+    fn check_parent_writable(path: &Path) -> Option<String> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let Some((existing, metadata)) = parent
+            .ancestors()
+            .map(|dir| match dir.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => dir,
+            })
+            .find_map(|dir| fs::metadata(dir).ok().map(|metadata| (dir, metadata)))
+        else {
+            return Some(format!(
+                "{} is stored in {}, which cannot be created",
+                path.display(),
+                parent.display()
+            ));
+        };
+
+        return if !metadata.is_dir() {
+            Some(format!(
+                "{} is stored in {}, but {} is not a directory",
+                path.display(),
+                parent.display(),
+                existing.display()
+            ))
+        } else if metadata.permissions().readonly() {
+            Some(format!(
+                "{} is stored in {}, but {} is not writable",
+                path.display(),
+                parent.display(),
+                existing.display()
+            ))
+        } else {
+            None
+        };
+    }
+
+    /// Writes the configuration as TOML to the given path, creating its parent directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A `PathBuf` reference that holds the path of the file to write.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the configuration cannot be serialized or written.
+    pub fn write_to(&self, path: &PathBuf) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let config_str = toml::to_string(self).context("Failed to serialize config")?;
+        fs::write(path, config_str)
+            .with_context(|| format!("Failed to write config file at {}", path.display()))?;
+
+        return Ok(());
+    }
+
+    /// Builds the starter config file written by `rustle init`.
+    ///
+    /// The settings are taken from the fields of `Config` itself, so that new settings appear in
+    /// the template without changing it. The required settings are written with placeholder
+    /// values, and every other one is commented out with its default value:
+    ///
+    /// ```
+    /// use rustle::Config;
+    ///
+    /// let template = Config::template().unwrap();
+    /// assert!(template.contains("\norigin_urls = [\"https://example.com\"]\n"));
+    /// assert!(template.contains("\n# timeout_secs = 30\n"));
+    /// assert!(template.contains("\n# max_pages is unset by default\n"));
+    /// assert!(template.contains("\n# [metrics]\n"));
+    ///
+    /// let config: Config = toml::from_str(&template).unwrap();
+    /// config.validate().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the default configuration cannot be serialized.
+    pub fn template() -> Result<String> {
+        let starter = Config {
+            origin_urls: vec!["https://example.com".to_string()],
+            ..Default::default()
+        };
+        let table = toml::Table::try_from(&starter).context("Failed to serialize config")?;
+
+        let mut required = String::new();
+        let mut optional = String::new();
+        let mut sections = String::new();
+        for &name in field_names() {
+            let Some(value) = table.get(name) else {
+                // Unset options are not serialized, and neither are the aliases listed with them
+                if !is_alias(name) {
+                    optional.push_str(&format!("# {name} is unset by default\n"));
+                }
+                continue;
+            };
+
+            let mut entry = toml::Table::new();
+            entry.insert(name.to_string(), value.clone());
+            let entry_str = toml::to_string(&entry).context("Failed to serialize config")?;
+            if starter_requires(&table, name) {
+                required.push_str(&entry_str);
+            } else if value.is_table() {
+                // Tables go last, as the keys after a table header belong to the table
+                sections.push('\n');
+                sections.push_str(&commented(&entry_str));
+            } else {
+                optional.push_str(&commented(&entry_str));
+            }
+        }
+
+        return Ok(format!(
+            "# Rustle configuration. Uncomment a setting to change it from its default value.\n\n{required}\n{optional}{sections}"
+        ));
+    }
+}
+
+/// Gets the names of the fields of `Config`, in the order they are declared, along with the
+/// aliases serde accepts for them.
+fn field_names() -> &'static [&'static str] {
+    let mut recorder = FieldNames(&[]);
+    // The recorder always fails once it has seen the fields
+    let _ = Config::deserialize(&mut recorder);
+    return recorder.0;
+}
+
+/// Checks whether a config field name is one of the aliases of `Config` kept for older config
+/// files, such as `origin_url`.
+fn is_alias(name: &str) -> bool {
+    return ALIASES.iter().any(|&(alias, _)| alias == name);
+}
+
+/// Sets a config value in a parsed config file, replacing the value of any of its aliases so
+/// that they don't clash.
+///
+/// # Arguments
+///
+/// * `table` - A mutable reference to the TOML table of the config file.
+/// * `field` - The name of the field, or of one of its aliases.
+/// * `value` - The value to set.
+fn set_value(table: &mut toml::Table, field: &str, value: toml::Value) {
+    let field = ALIASES
+        .iter()
+        .find(|&&(alias, _)| alias == field)
+        .map_or(field, |&(_, field)| field);
+    for &(alias, _) in ALIASES.iter().filter(|&&(_, target)| target == field) {
+        table.remove(alias);
+    }
+    table.insert(field.to_string(), value);
+}
+
+/// Gets the config values set with environment variables, named after the fields of `Config`
+/// with the `ENV_PREFIX`.
+///
+/// The value of a string setting is taken as is, and any other value is read as a TOML value,
+/// or else as a string, which leaves type errors to be reported when the config is parsed.
+///
+/// # Arguments
+///
+/// * `vars` - The environment variables, as name and value pairs.
+///
+/// # Returns
+///
+/// The name of each variable of a known field, with the name of the field and its value.
+fn env_values(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Vec<(String, &'static str, toml::Value)> {
+    let defaults = toml::Table::try_from(Config::default()).unwrap_or_default();
+    let fields = field_names();
+
+    let mut values = Vec::new();
+    for (var, raw) in vars {
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let Some(&field) = fields.iter().find(|field| field.to_uppercase() == name) else {
+            continue;
+        };
+
+        let value = match defaults.get(field) {
+            Some(toml::Value::String(_)) => toml::Value::String(raw),
+            _ => toml::from_str::<toml::Table>(&format!("value = {raw}"))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(toml::Value::String(raw)),
+        };
+        values.push((var, field, value));
+    }
+
+    // Sort the variables, so that their order doesn't depend on the environment
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    return values;
+}
+
+/// Checks whether a config field is required, by parsing the starter config without it.
+fn starter_requires(table: &toml::Table, name: &str) -> bool {
+    let mut without = table.clone();
+    without.remove(name);
+    return toml::Value::Table(without).try_into::<Config>().is_err();
+}
+
+/// Comments out every line of a snippet of TOML.
+fn commented(toml_str: &str) -> String {
+    return toml_str
+        .lines()
+        .map(|line| match line.is_empty() {
+            true => "\n".to_string(),
+            false => format!("# {line}\n"),
+        })
+        .collect();
+}
+
+/// A deserializer that records the field names of the struct deserialized from it, without
+/// deserializing anything.
+struct FieldNames(&'static [&'static str]);
+
+impl<'de> serde::Deserializer<'de> for &mut FieldNames {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        return Err(serde::de::Error::custom(
+            "only the field names of structs are recorded",
+        ));
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0 = fields;
+        return Err(serde::de::Error::custom(
+            "only the field names of structs are recorded",
+        ));
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes the tests that set `XDG_CONFIG_HOME`, as the environment is shared by threads.
+    static XDG_CONFIG_HOME_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Creates an empty directory in the temporary directory, unique to the test and process.
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rustle-config-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    #[test]
+    fn default_path_honors_an_absolute_xdg_config_home() {
+        let _lock = XDG_CONFIG_HOME_LOCK.lock().unwrap();
+        let dir = temp_dir("xdg");
+
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+        let path = Config::default_path().unwrap();
+        if cfg!(target_os = "linux") {
+            assert_eq!(path, dir.join("Rustle").join("config.toml"));
+        } else {
+            assert!(!path.starts_with(&dir));
+        }
+        assert!(path.ends_with(Path::new("Rustle").join("config.toml")));
+
+        std::env::set_var("XDG_CONFIG_HOME", "relative");
+        let path = Config::default_path().unwrap();
+        assert!(path.is_absolute());
+        assert!(path.ends_with(Path::new("Rustle").join("config.toml")));
+        if cfg!(target_os = "macos") {
+            assert!(path.ends_with("Library/Application Support/Rustle/config.toml"));
+        }
+
+        std::env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_default_file_falls_back_to_the_defaults() {
+        let _lock = XDG_CONFIG_HOME_LOCK.lock().unwrap();
+        let dir = temp_dir("missing");
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+
+        let overrides = Overrides {
+            origin_urls: vec!["https://example.com".to_string()],
+            ..Default::default()
+        };
+        let config = Config::load(None, overrides).unwrap();
+        assert_eq!(config.origin_urls, vec!["https://example.com".to_string()]);
+        assert_eq!(config.depth, 3);
+        assert_eq!(config.database, "crawler");
+        assert!(!Config::default_path().unwrap().exists());
+
+        std::env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_origin_url_names_where_to_set_it() {
+        let _lock = XDG_CONFIG_HOME_LOCK.lock().unwrap();
+        let dir = temp_dir("origin");
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+
+        // Without a config file, the error points to `rustle init`
+        let default_path = Config::default_path().unwrap();
+        let error = Config::load(None, Overrides::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains(&format!(
+            "write a config file at {} with `rustle init`",
+            default_path.display()
+        )));
+        assert!(error.contains("RUSTLE_ORIGIN_URL"));
+        assert!(error.contains("--origin-url"));
+
+        // With one, it points to the file instead
+        let path = dir.join("config.toml");
+        fs::write(&path, "depth = 2\n").unwrap();
+        let error = Config::load(Some(&path), Overrides::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains(&format!(
+            "add origin_urls to the config file at {}",
+            path.display()
+        )));
+        assert!(error.contains("--origin-url"));
+
+        // A config file that is named but missing is an error, unlike the default one
+        let missing = dir.join("missing.toml");
+        let error = Config::load(Some(&missing), Overrides::default())
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains(&missing.display().to_string()));
+
+        std::env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn internationalized_hosts_are_compared_in_punycode() {
+        assert!(Scope::Host.allows("xn--bcher-kva.example", "bücher.example"));
+        assert!(Scope::Host.allows("BÜCHER.example", "xn--bcher-kva.example"));
+        assert!(Scope::Subdomains.allows("shop.bücher.example", "xn--bcher-kva.example"));
+        assert!(!Scope::Host.allows("buecher.example", "bücher.example"));
+
+        let config = Config {
+            origin_urls: vec![
+                "https://bücher.example/".to_string(),
+                "https://xn--bcher-kva.example/neu/".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(config.origin_hosts(), ["xn--bcher-kva.example"]);
+    }
 }
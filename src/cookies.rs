@@ -0,0 +1,129 @@
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use url::Url;
+
+/// A cookie set before a crawl starts, such as a login cookie obtained out of band.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SeedCookie {
+    /// The name of the cookie.
+    pub name: String,
+    /// The value of the cookie.
+    pub value: String,
+    /// The domain the cookie is sent to, along with its subdomains.
+    pub domain: String,
+    /// The path under which the cookie is sent.
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+impl SeedCookie {
+    /// Checks the cookie, leaving its value out of the problems as it may be secret.
+    ///
+    /// # Returns
+    ///
+    /// The problems found, each as a sentence to list in the validation error of the config.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.name.is_empty() || self.name.contains(['=', ';', ' ', ',']) {
+            problems.push(format!(
+                "seed_cookies name '{}' must be a non-empty cookie name",
+                self.name
+            ));
+        }
+        if self.value.contains([';', ',']) {
+            problems.push(format!(
+                "seed_cookies value of '{}' must not contain ';' or ','",
+                self.name
+            ));
+        }
+        if self.url().is_none() {
+            problems.push(format!(
+                "seed_cookies domain '{}' of '{}' must be a host",
+                self.domain, self.name
+            ));
+        }
+        if !self.path.starts_with('/') {
+            problems.push(format!(
+                "seed_cookies path '{}' of '{}' must start with '/'",
+                self.path, self.name
+            ));
+        }
+        return problems;
+    }
+
+    /// Gets the URL the cookie is set from, on its domain and path.
+    fn url(&self) -> Option<Url> {
+        return Url::parse(&format!("https://{}{}", self.domain, self.path))
+            .ok()
+            .filter(|url| url.host_str() == Some(self.domain.to_lowercase().as_str()));
+    }
+}
+
+/// The default value of `SeedCookie::path`.
+fn default_path() -> String {
+    return "/".to_string();
+}
+
+/// The cookie store of a crawl, which sends every host the cookies it or its parent domains set,
+/// and skips the cookies a host sets past `max_cookies_per_host`.
+///
+/// Cookies are kept apart by domain, path and name as a browser would, and their names and values
+/// are never logged.
+pub(crate) struct CookieJar {
+    /// The cookies accepted so far.
+    jar: Jar,
+    /// The maximum number of cookies accepted from a single host.
+    max_per_host: u64,
+    /// The number of cookies accepted from each host so far.
+    accepted: Mutex<HashMap<String, u64>>,
+}
+
+impl CookieJar {
+    /// Creates a `CookieJar` holding the seeded cookies.
+    ///
+    /// # Arguments
+    ///
+    /// * `seeds` - A slice of the cookies set before the crawl starts.
+    /// * `max_per_host` - The maximum number of cookies accepted from a single host.
+    pub(crate) fn new(seeds: &[SeedCookie], max_per_host: u64) -> Self {
+        let jar = Jar::default();
+        for seed in seeds {
+            if let Some(url) = seed.url() {
+                jar.add_cookie_str(
+                    &format!(
+                        "{}={}; Domain={}; Path={}",
+                        seed.name, seed.value, seed.domain, seed.path
+                    ),
+                    &url,
+                );
+            }
+        }
+        return CookieJar {
+            jar,
+            max_per_host,
+            accepted: Mutex::new(HashMap::new()),
+        };
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let host = url.host_str().unwrap_or_default().to_string();
+        let mut accepted = self.accepted.lock().unwrap();
+        let count = accepted.entry(host).or_insert(0);
+        let remaining = self.max_per_host.saturating_sub(*count);
+        let cookie_headers: Vec<&HeaderValue> = cookie_headers.take(remaining as usize).collect();
+        if cookie_headers.is_empty() {
+            return;
+        }
+        *count += cookie_headers.len() as u64;
+        self.jar.set_cookies(&mut cookie_headers.into_iter(), url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        return self.jar.cookies(url);
+    }
+}
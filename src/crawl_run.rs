@@ -0,0 +1,218 @@
+use crate::database::Database;
+use crate::session::SessionStatus;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// The number of crawl runs listed by `rustle stats`.
+pub const RECENT_RUNS: usize = 10;
+
+/// Represents an invocation of the crawler as recorded in the `crawl_runs` table, from the
+/// moment it starts, so that every stored site can be traced back to the run that last touched
+/// it.
+pub struct CrawlRun {
+    /// An `i64` that holds the id of the run, which increases with every run.
+    pub id: i64,
+    /// A `DateTime<Utc>` that represents the time the run started.
+    pub started_at: DateTime<Utc>,
+    /// The time the run finished, or `None` if it stopped early or is still running.
+    pub finished_at: Option<DateTime<Utc>>,
+    /// A string that holds the origin URL of the run, or its origin URLs separated by spaces.
+    pub origin_url: String,
+    /// A `u64` that holds the depth the run was configured with.
+    pub depth: u64,
+    /// A `u64` that holds the number of pages successfully fetched, once the run finished.
+    pub pages_fetched: u64,
+    /// A `u64` that holds the number of fetches that failed, once the run finished.
+    pub errors: u64,
+    /// The verdict given to the crawl session of the run, once it finished.
+    pub status: Option<SessionStatus>,
+}
+
+impl CrawlRun {
+    /// Records the start of a run.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_url` - A string slice that holds the origin URLs of the run, separated by
+    ///   spaces.
+    /// * `depth` - The depth the run is configured with.
+    /// * `started_at` - The time the run started.
+    /// * `database` - A reference to the `Database` where the run is recorded.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<i64>` holding the id of the new run.
+    pub fn start(
+        origin_url: &str,
+        depth: u64,
+        started_at: DateTime<Utc>,
+        database: &Database,
+    ) -> Result<i64> {
+        let mut statement = database.prepare(&format!(
+            "INSERT INTO crawl_runs (started_at, origin_url, depth, pages_fetched, errors) VALUES ('{}', '{}', {}, 0, 0) RETURNING id",
+            started_at.to_rfc3339(),
+            origin_url.replace("'", "''"),
+            depth
+        ))?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        return statement
+            .read::<i64, usize>(0)
+            .context("Failed to read id from the database");
+    }
+
+    /// Records the end of a run, along with the outcome of its fetches.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the run.
+    /// * `finished_at` - The time the run finished.
+    /// * `pages_fetched` - The number of pages successfully fetched.
+    /// * `errors` - The number of fetches that failed.
+    /// * `status` - The verdict given to the crawl session of the run.
+    /// * `database` - A reference to the `Database` where the run is recorded.
+    pub fn finish(
+        id: i64,
+        finished_at: DateTime<Utc>,
+        pages_fetched: u64,
+        errors: u64,
+        status: SessionStatus,
+        database: &Database,
+    ) -> Result<()> {
+        database.execute(&format!(
+            "UPDATE crawl_runs SET finished_at = '{}', pages_fetched = {}, errors = {}, status = '{}' WHERE id = {}",
+            finished_at.to_rfc3339(),
+            pages_fetched,
+            errors,
+            status,
+            id
+        ))?;
+        return Ok(());
+    }
+
+    /// Reads the latest runs, the most recent first.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of runs to read.
+    /// * `include_suspect` - Whether the runs whose crawl was marked as suspect are read too.
+    /// * `database` - A reference to the `Database` the runs are recorded in.
+    pub fn read_latest(
+        limit: usize,
+        include_suspect: bool,
+        database: &Database,
+    ) -> Result<Vec<Self>> {
+        let mut statement = database.prepare(&format!(
+            "SELECT id, started_at, finished_at, origin_url, depth, pages_fetched, errors, status FROM crawl_runs {} ORDER BY id DESC LIMIT {}",
+            match include_suspect {
+                true => String::new(),
+                false => format!(
+                    "WHERE status IS NULL OR status != '{}'",
+                    SessionStatus::Suspect
+                ),
+            },
+            limit
+        ))?;
+
+        let mut runs = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let started_at = statement
+                .read::<String, usize>(1)
+                .context("Failed to read started_at from the database")?;
+            let finished_at = statement
+                .read::<Option<String>, usize>(2)
+                .context("Failed to read finished_at from the database")?
+                .map(|finished_at| {
+                    DateTime::parse_from_rfc3339(&finished_at)
+                        .context("Failed to parse finished_at as RFC 3339")
+                })
+                .transpose()?
+                .map(|finished_at| finished_at.with_timezone(&Utc));
+            runs.push(Self {
+                id: statement
+                    .read::<i64, usize>(0)
+                    .context("Failed to read id from the database")?,
+                started_at: DateTime::parse_from_rfc3339(&started_at)
+                    .context("Failed to parse started_at as RFC 3339")?
+                    .with_timezone(&Utc),
+                finished_at,
+                origin_url: statement
+                    .read::<String, usize>(3)
+                    .context("Failed to read origin_url from the database")?,
+                depth: statement
+                    .read::<i64, usize>(4)
+                    .context("Failed to read depth from the database")?
+                    as u64,
+                pages_fetched: statement
+                    .read::<i64, usize>(5)
+                    .context("Failed to read pages_fetched from the database")?
+                    as u64,
+                errors: statement
+                    .read::<i64, usize>(6)
+                    .context("Failed to read errors from the database")?
+                    as u64,
+                status: statement
+                    .read::<Option<String>, usize>(7)
+                    .context("Failed to read status from the database")?
+                    .map(|status| status.parse())
+                    .transpose()?,
+            });
+        }
+
+        return Ok(runs);
+    }
+
+    /// Builds the query selecting the id of the last run that reached a generation, going by the
+    /// sites and the failed fetches it recorded in that generation. The query selects `NULL` if
+    /// only sites and failures stored without a run reached it.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation` - The generation, counting the origin as generation 0.
+    pub fn last_to_reach_query(generation: u64) -> String {
+        return format!(
+            "SELECT MAX(run_id) FROM (SELECT run_id FROM sites WHERE generation = {0} \
+             UNION ALL SELECT run_id FROM fetch_errors WHERE generation = {0})",
+            generation
+        );
+    }
+
+    /// Formats runs as the lines of a table, with a header and aligned columns. Runs that
+    /// didn't finish are listed as `unfinished`.
+    ///
+    /// # Arguments
+    ///
+    /// * `runs` - The runs, one per line in the given order.
+    pub fn table(runs: &[CrawlRun]) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:>5}  {:<20}  {:<20}  {:>5}  {:>8}  {:>6}  {:<8}  {}",
+            "Run", "Started", "Finished", "Depth", "Pages", "Errors", "Status", "Origin"
+        )];
+        for run in runs {
+            let finished_at = match run.finished_at {
+                Some(finished_at) => finished_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                None => "unfinished".to_string(),
+            };
+            let status = match run.status {
+                Some(status) => status.to_string(),
+                None => "-".to_string(),
+            };
+            lines.push(format!(
+                "{:>5}  {:<20}  {:<20}  {:>5}  {:>8}  {:>6}  {:<8}  {}",
+                run.id,
+                run.started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                finished_at,
+                run.depth,
+                run.pages_fetched,
+                run.errors,
+                status,
+                run.origin_url
+            ));
+        }
+        return lines;
+    }
+}
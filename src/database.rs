@@ -1,6 +1,52 @@
-use anyhow::{Context, Result};
-use log::{info, trace};
+use anyhow::{bail, Context, Result};
+use chrono::DateTime;
+use directories::BaseDirs;
+use log::{info, trace, warn};
 use sqlite::ConnectionThreadSafe;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long SQLite waits for another connection to release its lock before giving up.
+const BUSY_TIMEOUT_MS: usize = 5000;
+
+/// The number of times a statement that still found the database busy is retried.
+const BUSY_RETRIES: u32 = 5;
+
+/// The delay before the first retry of a busy statement, which grows with every retry.
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The primary result code of SQLite when another connection holds a conflicting lock.
+const SQLITE_BUSY: isize = 5;
+
+/// The primary result code of SQLite when a table is locked by the same shared cache.
+const SQLITE_LOCKED: isize = 6;
+
+/// The database name that opens a private in-memory database instead of a file.
+pub const MEMORY_DATABASE: &str = ":memory:";
+
+/// The first SQLite version that can drop columns with `ALTER TABLE … DROP COLUMN`.
+const DROP_COLUMN_VERSION: (u32, u32, u32) = (3, 35, 0);
+
+/// The version of the schema created by this version of Rustle, which `setup` stores in the
+/// `user_version` pragma of the database.
+pub const SCHEMA_VERSION: u32 = 9;
+
+/// The schema migrations, in order: the migration at index `i` brings a database from version
+/// `i` to version `i + 1`. Changes to the schema go in a new migration at the end, so that
+/// existing databases get them too.
+const MIGRATIONS: [fn(&Database) -> Result<()>; SCHEMA_VERSION as usize] = [
+    Database::migrate_initial_schema,
+    Database::migrate_robots_url,
+    Database::migrate_anchor_text,
+    Database::migrate_canonical_url,
+    Database::migrate_feeds,
+    Database::migrate_discovery,
+    Database::migrate_run_status,
+    Database::migrate_skip_decisions,
+    Database::migrate_failure_generations,
+];
 
 /// Represents a database connection.
 ///
@@ -9,17 +55,54 @@ use sqlite::ConnectionThreadSafe;
 pub struct Database {
     /// A thread-safe connection to the database.
     conn: ConnectionThreadSafe,
+    /// Held while a statement runs through `execute`, so that a failed transaction is rolled back
+    /// before the statements of other threads reach the connection.
+    execution: Mutex<()>,
+    /// The path of the database file.
+    path: PathBuf,
+}
+
+/// Counts the rows of a table merged from another database.
+#[derive(Default)]
+pub struct MergeCounts {
+    /// The number of rows that were only in the other database.
+    pub inserted: u64,
+    /// The number of rows that were in both databases and changed in this one.
+    pub updated: u64,
+    /// The number of rows that were in both databases and left as they were.
+    pub skipped: u64,
+}
+
+/// Implements the `Display` trait for the `MergeCounts` struct.
+impl std::fmt::Display for MergeCounts {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} inserted, {} updated, {} skipped",
+            self.inserted, self.updated, self.skipped
+        )
+    }
+}
+
+/// Represents the outcome of merging another database into this one.
+pub struct MergeReport {
+    /// The rows of the `sites` table.
+    pub sites: MergeCounts,
+    /// The rows of the `domains` table.
+    pub domains: MergeCounts,
 }
 
 impl Database {
     /// Creates a new `Database` instance with a thread-safe connection.
     ///
-    /// This function opens a thread-safe connection to the specified database file.
-    /// If the database file does not exist, it will be created.
+    /// This function opens a thread-safe connection to the specified database file, in
+    /// write-ahead logging mode with a busy timeout. If the database file does not exist, it will
+    /// be created, along with any missing parent directories.
     ///
     /// # Arguments
     ///
-    /// * `db_name` - A string slice that holds the name of the database file (without the `.db` extension).
+    /// * `db_name` - A string slice that holds the name or path of the database file, as resolved
+    ///   by `path_for`, or `:memory:` for a private in-memory database, as opened by `in_memory`.
     ///
     /// # Returns
     ///
@@ -28,31 +111,331 @@ impl Database {
     ///
     /// # Errors
     ///
-    /// This function will return an error if it fails to open a thread-safe connection to the database.
+    /// This function will return an error if it fails to create the parent directories of the
+    /// database, or to open a thread-safe connection to it.
     pub fn new(db_name: &str) -> Result<Self> {
-        let conn =
-            sqlite::Connection::open_thread_safe(format!("{}.db", db_name)).context(format!(
-                "Failed to open thread-safe connection to the database: {}.db",
-                db_name
-            ))?;
-        info!("Opened database connection to '{}'.db'", db_name);
-        return Ok(Database { conn });
+        if db_name == MEMORY_DATABASE {
+            return Self::in_memory();
+        }
+
+        let path = Self::path_for(db_name);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let mut conn = sqlite::Connection::open_thread_safe(&path).context(format!(
+            "Failed to open thread-safe connection to the database: {}",
+            path.display()
+        ))?;
+        let absolute = std::path::absolute(&path).unwrap_or_else(|_| path.clone());
+        info!("Opened database connection to '{}'", absolute.display());
+
+        // Let readers, such as `rustle query` during a crawl, work alongside the writer, and wait
+        // for locks held by other connections instead of failing at once
+        conn.set_busy_timeout(BUSY_TIMEOUT_MS)
+            .context("Failed to set the busy timeout of the database")?;
+        let database = Database {
+            conn,
+            execution: Mutex::new(()),
+            path,
+        };
+        database.execute("PRAGMA journal_mode = WAL")?;
+        database.execute("PRAGMA synchronous = NORMAL")?;
+        return Ok(database);
+    }
+
+    /// Resolves the name or path of a database, as given in `Config::database`, to the path of
+    /// its file.
+    ///
+    /// A leading `~` is expanded to the home directory, and `.db` is appended when the name has
+    /// no extension. Relative paths stay relative to the current directory.
+    ///
+    /// ```
+    /// use rustle::Database;
+    /// use std::path::PathBuf;
+    ///
+    /// assert_eq!(Database::path_for("crawler"), PathBuf::from("crawler.db"));
+    /// assert_eq!(Database::path_for("/var/data/crawl.db"), PathBuf::from("/var/data/crawl.db"));
+    /// assert_eq!(Database::path_for("data/crawl.sqlite"), PathBuf::from("data/crawl.sqlite"));
+    /// assert!(!Database::path_for("~/crawls/site").starts_with("~"));
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `db_name` - A string slice that holds the name or path of the database.
+    pub fn path_for(db_name: &str) -> PathBuf {
+        let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        let mut path = match (db_name.strip_prefix("~/"), home) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(db_name),
+        };
+        if path.extension().is_none() {
+            path.set_extension("db");
+        }
+        return path;
+    }
+
+    /// Creates a new `Database` instance backed by a private in-memory database.
+    ///
+    /// The database lives as long as the instance, and takes up no space on disk.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new `Database` instance, or an error if the connection fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustle::{Database, Site, Storage};
+    ///
+    /// let db = Database::in_memory().unwrap();
+    /// db.setup().unwrap();
+    /// let site = Site {
+    ///     url: "https://example.com/".to_string(),
+    ///     links_to: ["https://example.com/about".to_string()].into(),
+    ///     status_code: Some(200),
+    ///     title: Some("Example".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// db.put_site(&site).unwrap();
+    ///
+    /// let stored = db.get_site("https://example.com/").unwrap().unwrap();
+    /// assert_eq!(stored.links_to, site.links_to);
+    /// assert_eq!(stored.status_code, Some(200));
+    /// assert_eq!(stored.title.as_deref(), Some("Example"));
+    /// assert_eq!(db.disk_bytes(), 0);
+    /// ```
+    pub fn in_memory() -> Result<Self> {
+        let path = PathBuf::from(MEMORY_DATABASE);
+        let conn = sqlite::Connection::open_thread_safe(&path)
+            .context("Failed to open thread-safe connection to an in-memory database")?;
+        info!("Opened in-memory database connection");
+        return Ok(Database {
+            conn,
+            execution: Mutex::new(()),
+            path,
+        });
     }
 
-    /// Initializes the SQLite tables for storing site and domain data.
+    /// Initializes the SQLite tables for storing site, domain and crawl session data.
+    ///
+    /// The schema version of the database is read from its `user_version` pragma, and the
+    /// migrations it is missing are run in order, in a single transaction, so that a database
+    /// created by an older version of Rustle is brought up to date or left as it was. Databases
+    /// from before schema versions were recorded are at version 0. The first migration creates
+    /// the schema, and adds the tables and columns such databases may be missing.
     ///
-    /// This function creates two tables in the database if they do not already exist:
+    /// The schema has these tables:
     /// - `sites`: Stores site data with columns:
     ///   - `url`: The primary key, a text field that stores the URL of the site.
     ///   - `crawl_time`: A text field that stores the crawl time of the site.
-    ///   - `links_to`: A text field that stores the URLs that the site links to, as a comma-separated string.
+    ///   - `header_anomaly`: An integer flag set when the response headers exceeded the configured limits.
+    ///   - `cookies_set`: An integer that stores the number of cookies the response tried to set.
+    ///   - `bot_challenged`: An integer flag set when the response was a bot-detection challenge.
+    ///   - `js_dependency_score`: A real that estimates how much the site depends on JavaScript.
+    ///   - `status_code`: An integer that stores the HTTP status code of the response.
+    ///   - `final_url`: A text field that stores the URL of the response, after redirects.
+    ///   - `is_redirect`: An integer flag set when the site only redirects to the URL it links to.
+    ///   - `redirect_hops`: An integer that stores the number of redirects to its `final_url`.
+    ///   - `canonical_url`: A text field that stores the canonical URL the page declared, when
+    ///     it differs from the URL of the site.
+    ///   - `content_type`: A text field that stores the media type of the response.
+    ///   - `body_bytes`: An integer that stores the size of the response body, if it is known.
+    ///   - `sniffed_type`: A text field that stores the media type sniffed from the body.
+    ///   - `etag` and `last_modified`: Text fields that store the `ETag` and `Last-Modified`
+    ///     headers of the response, which make the next fetch of the site conditional.
+    ///   - `generation` and `batch_id`: Integer fields that store the generation and worker batch
+    ///     of the crawl that fetched the site.
+    ///   - `response_ms`: An integer field that stores the time taken to fetch the site.
+    ///   - `robots_snapshot`: An integer that references the `robots_history` snapshot the site
+    ///     was fetched under, in compliance mode.
+    ///   - `robots_rule`: A text field that stores the robots.txt rule that allowed the site, or
+    ///     `no rules`, in compliance mode.
+    ///   - `title`: A text field that stores the whitespace-normalized `<title>` of the page.
+    ///   - `description`: A text field that stores the `<meta name="description">` of the page.
+    ///   - `content_hash`: A text field that stores the SHA-256 of the normalized HTML of the page.
+    ///   - `last_changed`: A text field that stores when the page was first seen with its
+    ///     current content hash.
+    ///   - `run_id`: An integer that references the `crawl_runs` row of the run that last
+    ///     stored the site.
+    ///   - `depth`: An integer that stores the smallest depth the site was discovered at over
+    ///     every crawl, counting the origin as depth 0.
+    ///   - `discovered_from`: A text field that stores the URL of the page the site was first
+    ///     discovered on at its `depth`.
+    /// - `links`: Stores a row per link between sites with columns:
+    ///   - `source_url`: A text field that stores the URL of the linking site.
+    ///   - `target_url`: A text field that stores the URL it links to.
+    ///   - `anchor_text`: A text field that stores the whitespace-collapsed text of the link,
+    ///     empty for links without any text, or `NULL` if it wasn't recorded.
+    ///   - `kind`: A text field that stores `asset` for the images, scripts and stylesheets a
+    ///     page embeds, `nofollow` for the links not followed because of their `rel` attribute,
+    ///     `external` for the links to hosts out of scope, and `page` for the other links.
     /// - `domains`: Stores domain data with columns:
     ///   - `domain`: The primary key, a text field that stores the domain name.
     ///   - `crawl_time`: A text field that stores the crawl time of the domain.
     ///   - `robots`: A text field that stores the robots.txt content of the domain.
+    ///   - `sitemaps`: A text field that stores the sitemap URLs of the domain, one per line.
+    ///   - `feeds`: A text field that stores the URLs of the RSS and Atom feeds discovered on the
+    ///     pages of the domain, one per line.
+    ///   - `robots_override`: A text field that stores the path of the local robots.txt used
+    ///     instead of the live one, if any.
+    ///   - `robots_url`: A text field that stores the URL the robots.txt was fetched from.
+    ///   - `broken_tls_redirect`: An integer flag set when the domain redirects back and forth
+    ///     between `http` and `https`.
+    /// - `crawls`: Stores a row per crawl session with columns:
+    ///   - `id`: The primary key, an integer that increases with every session.
+    ///   - `origin_url`: A text field that stores the origin URL the crawl started from.
+    ///   - `start_time` and `end_time`: Text fields that store when the crawl started and finished.
+    ///   - `pages_fetched` and `fetch_failures`: Integers that store the outcome of the fetches.
+    ///   - `status`: A text field that stores whether the session is `accepted` or `suspect`.
+    ///   - `disk_bytes`: An integer that stores the size of the database when the crawl finished.
+    ///   - `truncated_for_disk`: An integer flag set when the crawl stopped admitting URLs
+    ///     because it reached `max_disk_mb`.
+    /// - `crawl_runs`: Stores a row per invocation of the crawler, from the moment it starts,
+    ///   with columns:
+    ///   - `id`: The primary key, an integer that increases with every run.
+    ///   - `started_at` and `finished_at`: Text fields that store when the run started and
+    ///     finished. `finished_at` is `NULL` for runs that stopped early or are still running.
+    ///   - `origin_url`: A text field that stores the origin URLs of the run.
+    ///   - `depth`: An integer that stores the depth the run was configured with.
+    ///   - `pages_fetched` and `errors`: Integers that store the outcome of the fetches.
+    /// - `robots_history`: Stores every distinct robots.txt applied to a domain with columns:
+    ///   - `id`: The primary key, an integer that increases with every snapshot.
+    ///   - `domain`: A text field that stores the domain name.
+    ///   - `fetch_time`: A text field that stores when the robots.txt was fetched or read.
+    ///   - `robots`: A text field that stores the raw contents of the robots.txt.
+    ///   - `robots_override`: A text field that stores the path of the local robots.txt used
+    ///     instead of the live one, if any.
+    /// - `fetch_errors`: Stores a row per URL whose last fetch failed with columns:
+    ///   - `url`: The primary key, a text field that stores the URL.
+    ///   - `error_kind`: A text field that stores the category of the failure, such as `timeout`.
+    ///   - `message`: A text field that stores the reason of the failure.
+    ///   - `attempt_count`: An integer that stores the number of fetches that failed in a row.
+    ///   - `last_attempt`: A text field that stores when the URL was last fetched.
+    ///   - `generation`, `batch_id` and `run_id`: Integers that store the generation, worker
+    ///     batch and crawl run of the last failed fetch, if it had them.
+    /// - `skip_decisions`: Stores a row per discovered URL last skipped instead of fetched, with
+    ///   columns:
+    ///   - `url`: The primary key, a text field that stores the URL.
+    ///   - `reason`: A text field that stores why the URL was skipped, such as `robots`.
+    ///   - `decided_at`: A text field that stores when the URL was skipped.
+    ///   - `run_id`: An integer that stores the id of the crawl run that skipped the URL.
+    ///
+    /// The `sites` table is indexed by `crawl_time`, `last_changed`, `generation` and `final_url`,
+    /// the `domains` table by `crawl_time`, and the `links` table by `target_url`. It also creates the FTS5
+    /// virtual table `page_text`, holding the `url` and visible `content` of every page stored
+    /// with `store_text`, if the SQLite library supports FTS5.
+    ///
+    /// This function logs trace messages indicating the progress of the table setup.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database was created by a newer version of
+    /// Rustle, whose schema this version does not understand, or if a migration fails, in which
+    /// case the database is left as it was.
+    ///
+    /// # Examples
+    ///
+    /// A database from before the `links` table, whose sites still hold their links in a
+    /// `links_to` column, is migrated:
+    ///
+    /// ```
+    /// use rustle::{Database, SCHEMA_VERSION, Storage};
+    ///
+    /// let db = Database::in_memory()?;
+    /// db.execute("CREATE TABLE sites (url TEXT PRIMARY KEY, crawl_time TEXT NOT NULL, links_to TEXT)")?;
+    /// db.execute(
+    ///     "INSERT INTO sites VALUES ('https://example.com/', '2024-01-01T00:00:00+00:00', 'https://example.com/about')",
+    /// )?;
+    /// assert_eq!(db.schema_version()?, 0);
     ///
-    /// This function logs trace messages indicating the progress of the table setup.    
+    /// db.setup()?;
+    /// assert_eq!(db.schema_version()?, SCHEMA_VERSION);
+    /// let site = db.get_site("https://example.com/")?.unwrap();
+    /// assert!(site.links_to.contains("https://example.com/about"));
+    ///
+    /// // A database from a newer version of Rustle is refused
+    /// db.execute(&format!("PRAGMA user_version = {}", SCHEMA_VERSION + 1))?;
+    /// assert!(db.setup().is_err());
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
     pub fn setup(&self) -> Result<()> {
+        // Leave up-to-date databases alone, so that readers such as `rustle query` don't wait
+        // for the write lock of a running crawl
+        if self.schema_version()? == SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        // Take the write lock before reading the version, so that two processes opening the same
+        // database don't both migrate it
+        self.execute("BEGIN IMMEDIATE")?;
+        let migrated = self.migrate();
+        if migrated.is_err() {
+            let _ = self.conn.execute("ROLLBACK");
+            return migrated;
+        }
+        return self.execute("COMMIT");
+    }
+
+    /// Reads the schema version of the database from its `user_version` pragma, which is 0 for
+    /// new databases and databases from before schema versions were recorded.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u32>` holding the schema version.
+    pub fn schema_version(&self) -> Result<u32> {
+        let mut statement = self.prepare("PRAGMA user_version")?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        let version = statement
+            .read::<i64, usize>(0)
+            .context("Failed to read user_version from the database")?;
+        return Ok(version as u32);
+    }
+
+    /// Runs the migrations the database is missing, recording the schema version reached after
+    /// each of them. Runs within the transaction of `setup`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the schema version of the database is newer than
+    /// `SCHEMA_VERSION`, or if a migration fails.
+    fn migrate(&self) -> Result<()> {
+        let version = self.schema_version()?;
+        if version > SCHEMA_VERSION {
+            bail!(
+                "The database {} has schema version {}, but this version of Rustle only understands versions up to {}. \
+                 Upgrade Rustle to use it, or set `database` to another file",
+                self.path.display(),
+                version,
+                SCHEMA_VERSION
+            );
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let target = index + 1;
+            info!("Migrating the database schema to version {}", target);
+            migration(self).with_context(|| {
+                format!(
+                    "Failed to migrate the database schema to version {}",
+                    target
+                )
+            })?;
+            self.execute(&format!("PRAGMA user_version = {}", target))?;
+        }
+        return Ok(());
+    }
+
+    /// Creates the tables of schema version 1, as documented on `setup`.
+    ///
+    /// Databases from before schema versions were recorded may already have some of them, with
+    /// fewer columns, so the missing columns are added and the links of their sites are moved
+    /// into the `links` table.
+    fn migrate_initial_schema(&self) -> Result<()> {
         trace!("Setting up SQLite table 'sites'");
         self.conn
             .execute(
@@ -60,10 +443,78 @@ impl Database {
                 CREATE TABLE IF NOT EXISTS sites (
                     url TEXT PRIMARY KEY,
                     crawl_time TEXT NOT NULL,
-                    links_to TEXT
+                    header_anomaly INTEGER NOT NULL DEFAULT 0,
+                    cookies_set INTEGER NOT NULL DEFAULT 0,
+                    bot_challenged INTEGER NOT NULL DEFAULT 0,
+                    js_dependency_score REAL NOT NULL DEFAULT 0,
+                    status_code INTEGER,
+                    final_url TEXT,
+                    content_type TEXT,
+                    body_bytes INTEGER,
+                    title TEXT,
+                    description TEXT,
+                    content_hash TEXT,
+                    last_changed TEXT,
+                    sniffed_type TEXT,
+                    etag TEXT,
+                    last_modified TEXT,
+                    generation INTEGER,
+                    batch_id INTEGER,
+                    response_ms INTEGER,
+                    robots_snapshot INTEGER REFERENCES robots_history (id),
+                    robots_rule TEXT,
+                    is_redirect INTEGER NOT NULL DEFAULT 0,
+                    redirect_hops INTEGER,
+                    run_id INTEGER REFERENCES crawl_runs (id)
                 );"#,
             )
             .context("Failed to setup SQLite table 'sites'")?;
+        self.ensure_column("sites", "header_anomaly", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("sites", "cookies_set", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("sites", "bot_challenged", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("sites", "js_dependency_score", "REAL NOT NULL DEFAULT 0")?;
+        self.ensure_column("sites", "status_code", "INTEGER")?;
+        self.ensure_column("sites", "final_url", "TEXT")?;
+        self.ensure_column("sites", "content_type", "TEXT")?;
+        self.ensure_column("sites", "body_bytes", "INTEGER")?;
+        self.ensure_column("sites", "title", "TEXT")?;
+        self.ensure_column("sites", "description", "TEXT")?;
+        self.ensure_column("sites", "content_hash", "TEXT")?;
+        self.ensure_column("sites", "last_changed", "TEXT")?;
+        self.ensure_column("sites", "sniffed_type", "TEXT")?;
+        self.ensure_column("sites", "etag", "TEXT")?;
+        self.ensure_column("sites", "last_modified", "TEXT")?;
+        self.ensure_column("sites", "generation", "INTEGER")?;
+        self.ensure_column("sites", "batch_id", "INTEGER")?;
+        self.ensure_column("sites", "response_ms", "INTEGER")?;
+        self.ensure_column(
+            "sites",
+            "robots_snapshot",
+            "INTEGER REFERENCES robots_history (id)",
+        )?;
+        self.ensure_column("sites", "robots_rule", "TEXT")?;
+        self.ensure_column("sites", "is_redirect", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("sites", "redirect_hops", "INTEGER")?;
+        self.ensure_column("sites", "run_id", "INTEGER REFERENCES crawl_runs (id)")?;
+        self.execute("CREATE INDEX IF NOT EXISTS sites_crawl_time ON sites (crawl_time)")?;
+        self.execute("CREATE INDEX IF NOT EXISTS sites_last_changed ON sites (last_changed)")?;
+        self.execute("CREATE INDEX IF NOT EXISTS sites_generation ON sites (generation)")?;
+        self.execute("CREATE INDEX IF NOT EXISTS sites_final_url ON sites (final_url)")?;
+
+        trace!("Setting up SQLite table 'links'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS links (
+                    source_url TEXT NOT NULL,
+                    target_url TEXT NOT NULL,
+                    kind TEXT NOT NULL DEFAULT 'page',
+                    PRIMARY KEY (source_url, target_url)
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'links'")?;
+        self.execute("CREATE INDEX IF NOT EXISTS links_target_url ON links (target_url)")?;
+        self.migrate_links_to()?;
 
         trace!("Setting up SQLite table 'domains'");
         self.conn
@@ -72,14 +523,121 @@ impl Database {
                 CREATE TABLE IF NOT EXISTS domains (
                     domain TEXT PRIMARY KEY,
                     crawl_time TEXT NOT NULL,
-                    robots TEXT
+                    robots TEXT,
+                    sitemaps TEXT,
+                    robots_override TEXT,
+                    broken_tls_redirect INTEGER NOT NULL DEFAULT 0
                 );"#,
             )
             .context("Failed to setup SQLite table 'domains'")?;
+        self.ensure_column("domains", "sitemaps", "TEXT")?;
+        self.ensure_column("domains", "robots_override", "TEXT")?;
+        self.ensure_column(
+            "domains",
+            "broken_tls_redirect",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        self.execute("CREATE INDEX IF NOT EXISTS domains_crawl_time ON domains (crawl_time)")?;
+
+        trace!("Setting up SQLite table 'crawls'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS crawls (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    origin_url TEXT NOT NULL,
+                    start_time TEXT NOT NULL,
+                    end_time TEXT NOT NULL,
+                    pages_fetched INTEGER NOT NULL,
+                    fetch_failures INTEGER NOT NULL,
+                    status TEXT NOT NULL,
+                    disk_bytes INTEGER NOT NULL DEFAULT 0,
+                    truncated_for_disk INTEGER NOT NULL DEFAULT 0
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'crawls'")?;
+        self.ensure_column("crawls", "disk_bytes", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("crawls", "truncated_for_disk", "INTEGER NOT NULL DEFAULT 0")?;
+
+        trace!("Setting up SQLite table 'crawl_runs'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS crawl_runs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    started_at TEXT NOT NULL,
+                    finished_at TEXT,
+                    origin_url TEXT NOT NULL,
+                    depth INTEGER NOT NULL,
+                    pages_fetched INTEGER NOT NULL DEFAULT 0,
+                    errors INTEGER NOT NULL DEFAULT 0
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'crawl_runs'")?;
+
+        trace!("Setting up SQLite table 'robots_history'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS robots_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    domain TEXT NOT NULL,
+                    fetch_time TEXT NOT NULL,
+                    robots TEXT NOT NULL,
+                    robots_override TEXT
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'robots_history'")?;
+        self.execute(
+            "CREATE INDEX IF NOT EXISTS robots_history_domain ON robots_history (domain, id)",
+        )?;
+
+        trace!("Setting up SQLite table 'fetch_errors'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS fetch_errors (
+                    url TEXT PRIMARY KEY,
+                    error_kind TEXT NOT NULL,
+                    message TEXT NOT NULL,
+                    attempt_count INTEGER NOT NULL,
+                    last_attempt TEXT NOT NULL
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'fetch_errors'")?;
+
+        // Full-text search is optional, as SQLite can be built without FTS5
+        trace!("Setting up SQLite table 'page_text'");
+        if let Err(e) = self.conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS page_text USING fts5(url UNINDEXED, content);",
+        ) {
+            trace!("Not setting up SQLite table 'page_text': {}", e);
+        }
 
         return Ok(());
     }
 
+    /// Checks whether the database supports full-text search, that is whether its `page_text`
+    /// table could be created.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<bool>` indicating whether the `page_text` table exists.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the schema of the database cannot be read.
+    pub fn has_text_search(&self) -> Result<bool> {
+        let mut statement =
+            self.prepare("SELECT name FROM sqlite_master WHERE name = 'page_text'")?;
+        return Ok(matches!(
+            statement
+                .next()
+                .context("Failed to execute the SQL query")?,
+            sqlite::State::Row
+        ));
+    }
+
     /// Prepares an SQLite statement for execution.
     ///
     /// This function takes a raw SQL statement as input and prepares it for execution
@@ -100,9 +658,12 @@ impl Database {
     /// # Examples
     ///
     /// ```
-    /// let db = Database::new("example").unwrap();
+    /// use rustle::Database;
+    ///
+    /// let db = Database::in_memory().unwrap();
+    /// db.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)").unwrap();
     /// let stmt = db.prepare("SELECT * FROM test").unwrap();
-    /// ```   
+    /// ```
     pub fn prepare(&self, statement: &str) -> Result<sqlite::Statement<'_>> {
         trace!("Preparing SQLite Statement: '{}'", statement);
 
@@ -128,20 +689,888 @@ impl Database {
     ///
     /// # Errors
     ///
-    /// This function will return an error if the SQL statement fails to execute.
+    /// This function will return an error if the SQL statement fails to execute. Statements that
+    /// find the database busy or locked are retried a few times first. A list of statements that
+    /// opens a transaction and fails is rolled back, so that the connection is never left inside
+    /// it.
     ///
     /// # Examples
     ///
     /// ```
-    /// let db = Database::new("example").unwrap();
+    /// use rustle::Database;
+    ///
+    /// let db = Database::in_memory().unwrap();
     /// db.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)").unwrap();
     /// ```
     pub fn execute(&self, statement: &str) -> Result<()> {
         trace!("Executing SQLite Statement: '{}'", statement);
 
+        let opens_transaction = statement.trim_start().starts_with("BEGIN");
+        let mut retries = 0;
+        loop {
+            let executed = {
+                let _execution = self
+                    .execution
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let executed = self.conn.execute(statement);
+                // A list of statements may have stopped inside the transaction it opened
+                if executed.is_err() && opens_transaction {
+                    let _ = self.conn.execute("ROLLBACK");
+                }
+                executed
+            };
+            match executed {
+                Ok(()) => return Ok(()),
+                Err(e) if is_busy(&e) && retries < BUSY_RETRIES => {
+                    retries += 1;
+                    warn!(
+                        "Database is busy, retrying ({} of {})",
+                        retries, BUSY_RETRIES
+                    );
+                    std::thread::sleep(BUSY_RETRY_DELAY * retries);
+                }
+                Err(e) => return Err(e).context("Failed to execute SQLite statement"),
+            }
+        }
+    }
+
+    /// Executes a list of SQL statements in a single transaction.
+    ///
+    /// The statements are sent to SQLite at once, so that the statements of other threads
+    /// sharing the connection cannot end up inside the transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - A slice of the raw SQL statements to be executed, in order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any statement fails, in which case the transaction
+    /// is rolled back and none of the statements take effect.
+    pub fn execute_transaction(&self, statements: &[String]) -> Result<()> {
         return self
-            .conn
-            .execute(statement)
-            .context("Failed to execute SQLite statement");
+            .execute(&format!("BEGIN; {}; COMMIT;", statements.join("; ")))
+            .context("Rolled back the transaction");
+    }
+
+    /// Adds the `robots_url` column to the `domains` table, in schema version 2.
+    fn migrate_robots_url(&self) -> Result<()> {
+        return self.ensure_column("domains", "robots_url", "TEXT");
+    }
+
+    /// Adds the `anchor_text` column to the `links` table, in schema version 3.
+    fn migrate_anchor_text(&self) -> Result<()> {
+        return self.ensure_column("links", "anchor_text", "TEXT");
+    }
+
+    /// Adds the `canonical_url` column to the `sites` table, in schema version 4.
+    fn migrate_canonical_url(&self) -> Result<()> {
+        return self.ensure_column("sites", "canonical_url", "TEXT");
+    }
+
+    /// Adds the `feeds` column to the `domains` table, in schema version 5.
+    fn migrate_feeds(&self) -> Result<()> {
+        return self.ensure_column("domains", "feeds", "TEXT");
+    }
+
+    /// Adds the `depth` and `discovered_from` columns to the `sites` table, in schema version 6.
+    fn migrate_discovery(&self) -> Result<()> {
+        self.ensure_column("sites", "depth", "INTEGER")?;
+        return self.ensure_column("sites", "discovered_from", "TEXT");
+    }
+
+    /// Adds the `status` column to the `crawl_runs` table, in schema version 7.
+    fn migrate_run_status(&self) -> Result<()> {
+        return self.ensure_column("crawl_runs", "status", "TEXT");
+    }
+
+    /// Creates the `skip_decisions` table, in schema version 8.
+    fn migrate_skip_decisions(&self) -> Result<()> {
+        trace!("Setting up SQLite table 'skip_decisions'");
+        self.conn
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS skip_decisions (
+                    url TEXT PRIMARY KEY,
+                    reason TEXT NOT NULL,
+                    decided_at TEXT NOT NULL,
+                    run_id INTEGER
+                );"#,
+            )
+            .context("Failed to setup SQLite table 'skip_decisions'")?;
+        return Ok(());
+    }
+
+    /// Adds the `generation`, `batch_id` and `run_id` columns to the `fetch_errors` table, in
+    /// schema version 9.
+    fn migrate_failure_generations(&self) -> Result<()> {
+        self.ensure_column("fetch_errors", "generation", "INTEGER")?;
+        self.ensure_column("fetch_errors", "batch_id", "INTEGER")?;
+        return self.ensure_column(
+            "fetch_errors",
+            "run_id",
+            "INTEGER REFERENCES crawl_runs (id)",
+        );
+    }
+
+    /// Moves the links of sites stored by older versions of Rustle, as comma-separated
+    /// `links_to` and `nofollow_links` columns, into the `links` table, then drops the columns.
+    ///
+    /// Runs within the transaction of the initial migration, and only does anything for
+    /// databases that still have the column. SQLite versions before 3.35 can't drop columns, so
+    /// they are emptied instead, and left unused.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the links cannot be read or written.
+    fn migrate_links_to(&self) -> Result<()> {
+        if !self
+            .columns("main", "sites")?
+            .contains(&"links_to".to_string())
+        {
+            return Ok(());
+        }
+
+        info!("Migrating the links of stored sites into the links table");
+        let has_nofollow = self
+            .columns("main", "sites")?
+            .contains(&"nofollow_links".to_string());
+        let mut statement = self.prepare(&format!(
+            "SELECT url, links_to, {} FROM sites WHERE links_to IS NOT NULL AND links_to != ''",
+            if has_nofollow {
+                "nofollow_links"
+            } else {
+                "NULL"
+            }
+        ))?;
+        let mut links = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let links_to = statement
+                .read::<String, usize>(1)
+                .context("Failed to read links_to from the database")?;
+            let nofollow_links = statement
+                .read::<Option<String>, usize>(2)
+                .context("Failed to read nofollow_links from the database")?
+                .unwrap_or_default();
+            let nofollow_links = split_links(&nofollow_links);
+            for link in split_links(&links_to) {
+                let kind = if nofollow_links.contains(&link) {
+                    "nofollow"
+                } else {
+                    "page"
+                };
+                links.push(format!(
+                    "INSERT OR IGNORE INTO links (source_url, target_url, kind) VALUES ('{}', '{}', '{}')",
+                    url.replace("'", "''"),
+                    link.replace("'", "''"),
+                    kind
+                ));
+            }
+        }
+        drop(statement);
+
+        let mut columns = vec!["links_to"];
+        if has_nofollow {
+            columns.push("nofollow_links");
+        }
+        if self.sqlite_version()? >= DROP_COLUMN_VERSION {
+            for column in columns {
+                links.push(format!("ALTER TABLE sites DROP COLUMN {}", column));
+            }
+        } else {
+            let cleared: Vec<String> = columns
+                .iter()
+                .map(|column| format!("{} = NULL", column))
+                .collect();
+            links.push(format!("UPDATE sites SET {}", cleared.join(", ")));
+        }
+        return self
+            .execute(&links.join("; "))
+            .context("Failed to migrate the links_to column into the links table");
+    }
+
+    /// Adds a column to an existing table if it is not already present.
+    ///
+    /// Tables created by older versions of Rustle are missing columns that were added later, and
+    /// `CREATE TABLE IF NOT EXISTS` will not add them. This function inspects the table with
+    /// `PRAGMA table_info` and issues an `ALTER TABLE` only when the column is missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A string slice that holds the name of the table.
+    /// * `column` - A string slice that holds the name of the column.
+    /// * `definition` - A string slice that holds the column type and constraints.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the table cannot be inspected or altered.
+    pub fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut statement = self.prepare(&format!("PRAGMA table_info({})", table))?;
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let name = statement
+                .read::<String, &str>("name")
+                .context("Failed to read column name from the database")?;
+            if name == column {
+                return Ok(());
+            }
+        }
+
+        trace!(
+            "Migrating SQLite table '{}': adding column '{}'",
+            table,
+            column
+        );
+        return self.execute(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ));
+    }
+
+    /// Merges the sites and domains of another database into this one.
+    ///
+    /// Rows only found in the other database are copied. When a URL or domain is in both, the
+    /// row with the newer `crawl_time` is kept, and the links of both rows are kept for sites.
+    /// Links are read from the `links_to` column of databases that predate the `links` table.
+    /// The robots.txt snapshots of copied sites are not carried over, as they reference the
+    /// other database. Either everything is merged, or nothing is.
+    ///
+    /// # Arguments
+    ///
+    /// * `other_path` - A string slice that holds the path of the other database file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<MergeReport>` counting the rows inserted, updated and skipped in every table.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the other database does not exist or cannot be
+    /// read, or the rows cannot be written.
+    pub fn merge_from(&self, other_path: &str) -> Result<MergeReport> {
+        // Attaching a missing file would silently create an empty database
+        if !Path::new(other_path).is_file() {
+            bail!("There is no database at {}", other_path);
+        }
+        self.setup()?;
+        self.execute(&format!(
+            "ATTACH DATABASE '{}' AS other",
+            other_path.replace("'", "''")
+        ))
+        .with_context(|| format!("Failed to attach the database at {}", other_path))?;
+
+        self.execute("BEGIN")?;
+        let merged = self.merge_links().and_then(|linked| {
+            let sites = self.merge_table("sites", "url", &linked)?;
+            let domains = self.merge_table("domains", "domain", &HashSet::new())?;
+            return Ok(MergeReport { sites, domains });
+        });
+        let merged = match merged {
+            Ok(report) => self.execute("COMMIT").map(|_| report),
+            Err(e) => {
+                let _ = self.execute("ROLLBACK");
+                Err(e)
+            }
+        };
+
+        self.execute("DETACH DATABASE other")?;
+        return merged;
+    }
+
+    /// Adds the links of the attached `other` database to the links table of this one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<HashSet<String>>` containing the URLs of the sites that gained links.
+    fn merge_links(&self) -> Result<HashSet<String>> {
+        // Databases written before the links table existed keep the links in a links_to column
+        // and those not followed in a nofollow_links column, and those written before anchor
+        // texts were recorded have none
+        let link_columns = self.columns("other", "links")?;
+        let legacy = link_columns.is_empty();
+        let site_columns = self.columns("other", "sites")?;
+        let query = if link_columns.contains(&"anchor_text".to_string()) {
+            "SELECT source_url, target_url, anchor_text, kind FROM other.links"
+        } else if !legacy {
+            "SELECT source_url, target_url, NULL, kind FROM other.links"
+        } else if !site_columns.contains(&"links_to".to_string()) {
+            return Ok(HashSet::new());
+        } else if site_columns.contains(&"nofollow_links".to_string()) {
+            "SELECT url, links_to, NULL, nofollow_links FROM other.sites WHERE links_to IS NOT NULL"
+        } else {
+            "SELECT url, links_to, NULL, NULL FROM other.sites WHERE links_to IS NOT NULL"
+        };
+
+        let mut statement = self.prepare(query)?;
+        let mut links = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let source = statement
+                .read::<String, usize>(0)
+                .context("Failed to read the linking URL from the database")?;
+            let targets = statement
+                .read::<String, usize>(1)
+                .context("Failed to read the linked URLs from the database")?;
+            let anchor_text = statement
+                .read::<Option<String>, usize>(2)
+                .context("Failed to read anchor_text from the database")?;
+            let kind = statement
+                .read::<Option<String>, usize>(3)
+                .context("Failed to read kind from the database")?
+                .unwrap_or_default();
+            if legacy {
+                let nofollow_links = split_links(&kind);
+                for target in split_links(&targets) {
+                    let kind = if nofollow_links.contains(&target) {
+                        "nofollow"
+                    } else {
+                        "page"
+                    };
+                    links.push((source.clone(), target, None, kind.to_string()));
+                }
+            } else {
+                links.push((source, targets, anchor_text, kind));
+            }
+        }
+        drop(statement);
+
+        let mut linked = HashSet::new();
+        for (source, target, anchor_text, kind) in links {
+            let anchor_text_str = match anchor_text {
+                Some(anchor_text) => format!("'{}'", anchor_text.replace("'", "''")),
+                None => "NULL".to_string(),
+            };
+            self.execute(&format!(
+                "INSERT OR IGNORE INTO main.links (source_url, target_url, anchor_text, kind) VALUES ('{}', '{}', {}, '{}')",
+                source.replace("'", "''"),
+                target.replace("'", "''"),
+                anchor_text_str,
+                kind.replace("'", "''")
+            ))?;
+            if self.conn.change_count() > 0 {
+                linked.insert(source);
+            }
+        }
+
+        return Ok(linked);
+    }
+
+    /// Merges a table of the attached `other` database into the same table of this one.
+    ///
+    /// Only the columns found in both tables are copied, so that databases written by older
+    /// versions of Rustle can be merged as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A string slice that holds the name of the table, which has a `crawl_time`.
+    /// * `key` - A string slice that holds the name of the primary key of the table.
+    /// * `linked` - The keys of the rows that gained links, which count as updated.
+    fn merge_table(&self, table: &str, key: &str, linked: &HashSet<String>) -> Result<MergeCounts> {
+        let other_columns = self.columns("other", table)?;
+        let columns = self
+            .columns("main", table)?
+            .into_iter()
+            .filter(|column| {
+                other_columns.contains(column) && column != "robots_snapshot" && column != "run_id"
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut counts = MergeCounts::default();
+        if other_columns.is_empty() {
+            return Ok(counts);
+        }
+
+        // Find the rows in both tables, with their crawl times, before copying the others
+        let query = format!(
+            "SELECT o.{key}, o.crawl_time, m.crawl_time FROM other.{table} o JOIN main.{table} m ON o.{key} = m.{key}",
+            key = key,
+            table = table
+        );
+        let mut statement = self.prepare(&query)?;
+        let mut conflicts = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let value = statement
+                .read::<String, usize>(0)
+                .with_context(|| format!("Failed to read {} from the database", key))?;
+            let other_time = statement
+                .read::<String, usize>(1)
+                .context("Failed to read crawl_time from the database")?;
+            let time = statement
+                .read::<String, usize>(2)
+                .context("Failed to read crawl_time from the database")?;
+            conflicts.push((value, other_time, time));
+        }
+        drop(statement);
+
+        let missing = format!(
+            "FROM other.{table} WHERE {key} NOT IN (SELECT {key} FROM main.{table})",
+            table = table,
+            key = key
+        );
+        let mut statement = self.prepare(&format!("SELECT COUNT(*) {}", missing))?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        counts.inserted = statement
+            .read::<i64, usize>(0)
+            .context("Failed to read the count from the database")?
+            as u64;
+        drop(statement);
+        self.execute(&format!(
+            "INSERT INTO main.{} ({}) SELECT {} {}",
+            table, columns, columns, missing
+        ))?;
+
+        for (value, other_time, time) in conflicts {
+            let newer = is_newer(&other_time, &time);
+            if newer {
+                self.execute(&format!(
+                    "INSERT OR REPLACE INTO main.{} ({}) SELECT {} FROM other.{} WHERE {} = '{}'",
+                    table,
+                    columns,
+                    columns,
+                    table,
+                    key,
+                    value.replace("'", "''")
+                ))?;
+            }
+
+            if newer || linked.contains(&value) {
+                counts.updated += 1;
+            } else {
+                counts.skipped += 1;
+            }
+        }
+
+        return Ok(counts);
+    }
+
+    /// Reads the version of the SQLite library the database is opened with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` holding the major, minor and patch numbers of the version.
+    fn sqlite_version(&self) -> Result<(u32, u32, u32)> {
+        let mut statement = self.prepare("SELECT sqlite_version()")?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        let version = statement
+            .read::<String, usize>(0)
+            .context("Failed to read the SQLite version")?;
+        return parse_sqlite_version(&version);
+    }
+
+    /// Lists the columns of a table, in their order in the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - A string slice that holds the name of the database, such as `main`.
+    /// * `table` - A string slice that holds the name of the table.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the column names, which is empty if there is no table.
+    fn columns(&self, schema: &str, table: &str) -> Result<Vec<String>> {
+        let mut statement = self.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+        let mut columns = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            columns.push(
+                statement
+                    .read::<String, &str>("name")
+                    .context("Failed to read column name from the database")?,
+            );
+        }
+
+        return Ok(columns);
+    }
+
+    /// Counts the rows of a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A string slice that holds the name of the table.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u64>` holding the number of rows.
+    pub fn count_rows(&self, table: &str) -> Result<u64> {
+        return self.count_where(table, "1");
+    }
+
+    /// Counts the rows of a table that match a condition.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A string slice that holds the name of the table.
+    /// * `condition` - A string slice that holds the SQL condition of the `WHERE` clause.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u64>` holding the number of matching rows.
+    pub fn count_where(&self, table: &str, condition: &str) -> Result<u64> {
+        let mut statement = self.prepare(&format!(
+            "SELECT COUNT(*) FROM {} WHERE {}",
+            table, condition
+        ))?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        let count = statement
+            .read::<i64, usize>(0)
+            .context("Failed to read the count from the database")?;
+        return Ok(count as u64);
+    }
+
+    /// Returns the space left free by deleted rows to the file system.
+    ///
+    /// Databases created with `auto_vacuum = INCREMENTAL` are shrunk with an incremental vacuum,
+    /// and others are rebuilt with `VACUUM`. The write-ahead log is then checkpointed and
+    /// truncated, so that the files on disk shrink right away.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database cannot be vacuumed, such as when
+    /// another connection is writing to it.
+    pub fn reclaim_space(&self) -> Result<()> {
+        let mut statement = self.prepare("PRAGMA auto_vacuum")?;
+        statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+        let auto_vacuum = statement
+            .read::<i64, usize>(0)
+            .context("Failed to read auto_vacuum from the database")?;
+        drop(statement);
+
+        // 2 is INCREMENTAL, the only mode in which free pages are kept until asked for
+        if auto_vacuum == 2 {
+            self.execute("PRAGMA incremental_vacuum")?;
+        } else {
+            self.execute("VACUUM")?;
+        }
+        self.execute("PRAGMA wal_checkpoint(TRUNCATE)")?;
+        return Ok(());
+    }
+
+    /// Gets the size the database takes up on disk, including its journal files.
+    ///
+    /// # Returns
+    ///
+    /// The total size in bytes of the database file and its rollback journal or write-ahead log,
+    /// counting files that don't exist as empty.
+    pub fn disk_bytes(&self) -> u64 {
+        let path = self.path.to_string_lossy();
+        return [
+            path.to_string(),
+            format!("{}-journal", path),
+            format!("{}-wal", path),
+        ]
+        .iter()
+        .filter_map(|file| std::fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    }
+}
+
+/// Checks whether a crawl time is newer than another, comparing RFC 3339 timestamps by the time
+/// they represent and anything else as text.
+///
+/// # Arguments
+///
+/// * `time` - A string slice that holds the crawl time that may be newer.
+/// * `other` - A string slice that holds the crawl time it is compared to.
+fn is_newer(time: &str, other: &str) -> bool {
+    return match (
+        DateTime::parse_from_rfc3339(time),
+        DateTime::parse_from_rfc3339(other),
+    ) {
+        (Ok(time), Ok(other)) => time > other,
+        _ => time > other,
+    };
+}
+
+/// Parses a SQLite version such as `3.35.5` into its major, minor and patch numbers.
+///
+/// # Arguments
+///
+/// * `version` - A string slice that holds the version.
+fn parse_sqlite_version(version: &str) -> Result<(u32, u32, u32)> {
+    let mut numbers = version.trim().split('.').map(str::parse::<u32>);
+    let mut next = || -> Result<u32> {
+        return numbers
+            .next()
+            .unwrap_or(Ok(0))
+            .with_context(|| format!("Failed to parse SQLite version '{}'", version));
+    };
+    return Ok((next()?, next()?, next()?));
+}
+
+/// Splits a comma-separated `links_to` value into a sorted set of URLs.
+///
+/// # Arguments
+///
+/// * `links` - A string slice that holds the comma-separated URLs.
+fn split_links(links: &str) -> BTreeSet<String> {
+    return links
+        .split(',')
+        .map(|link| link.trim().to_string())
+        .filter(|link| !link.is_empty())
+        .collect();
+}
+
+/// Checks whether an SQLite error means that the database was busy or locked by another
+/// connection, so that the statement may succeed if retried.
+///
+/// # Arguments
+///
+/// * `error` - A reference to the `sqlite::Error`.
+fn is_busy(error: &sqlite::Error) -> bool {
+    // The extended codes keep the primary code in their low byte
+    return matches!(
+        error.code.map(|code| code & 0xff),
+        Some(SQLITE_BUSY) | Some(SQLITE_LOCKED)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the rows returned by a query.
+    fn count(database: &Database, query: &str) -> i64 {
+        let mut statement = database.prepare(query).unwrap();
+        return std::iter::from_fn(|| match statement.next().unwrap() {
+            sqlite::State::Row => Some(()),
+            sqlite::State::Done => None,
+        })
+        .count() as i64;
+    }
+
+    #[test]
+    fn memory_databases_are_private_and_never_touch_disk() {
+        let first = Database::new(MEMORY_DATABASE).unwrap();
+        let second = Database::new(MEMORY_DATABASE).unwrap();
+        first.setup().unwrap();
+        second.setup().unwrap();
+        first
+            .execute("INSERT INTO domains (domain, crawl_time, robots) VALUES ('example.com', '2024-05-01T12:00:00+00:00', '')")
+            .unwrap();
+
+        assert_eq!(count(&first, "SELECT domain FROM domains"), 1);
+        assert_eq!(count(&second, "SELECT domain FROM domains"), 0);
+        assert_eq!(first.disk_bytes(), 0);
+        assert!(!Database::path_for(MEMORY_DATABASE).exists());
+        assert!(!std::path::Path::new(MEMORY_DATABASE).exists());
+    }
+
+    #[test]
+    fn sqlite_versions_are_parsed() {
+        assert_eq!(parse_sqlite_version("3.35.0").unwrap(), (3, 35, 0));
+        assert_eq!(parse_sqlite_version("3.51.2\n").unwrap(), (3, 51, 2));
+        assert_eq!(parse_sqlite_version("3.8").unwrap(), (3, 8, 0));
+        assert!(parse_sqlite_version("3.x.1").is_err());
+        assert!(parse_sqlite_version("3.34.1").unwrap() < DROP_COLUMN_VERSION);
+    }
+
+    #[test]
+    fn legacy_link_columns_are_dropped_or_emptied() {
+        let database = Database::in_memory().unwrap();
+        database
+            .execute(
+                "CREATE TABLE sites (url TEXT PRIMARY KEY, crawl_time TEXT NOT NULL, links_to TEXT, nofollow_links TEXT); \
+                 INSERT INTO sites VALUES ('https://example.com/', '2024-01-01T00:00:00+00:00', 'https://example.com/a,https://example.com/b', 'https://example.com/b')",
+            )
+            .unwrap();
+        database.setup().unwrap();
+
+        let columns = database.columns("main", "sites").unwrap();
+        if database.sqlite_version().unwrap() >= DROP_COLUMN_VERSION {
+            assert!(!columns.contains(&"links_to".to_string()));
+            assert!(!columns.contains(&"nofollow_links".to_string()));
+        } else {
+            assert_eq!(
+                count(
+                    &database,
+                    "SELECT url FROM sites WHERE links_to IS NOT NULL OR nofollow_links IS NOT NULL"
+                ),
+                0
+            );
+        }
+        assert_eq!(
+            count(
+                &database,
+                "SELECT target_url FROM links WHERE source_url = 'https://example.com/' AND kind = 'nofollow'"
+            ),
+            1
+        );
+        assert_eq!(database.count_rows("links").unwrap(), 2);
+    }
+
+    #[test]
+    fn pre_versioning_databases_keep_every_column_and_link() {
+        let database = Database::in_memory().unwrap();
+        database
+            .execute(
+                "CREATE TABLE sites (url TEXT PRIMARY KEY, crawl_time TEXT NOT NULL, links_to TEXT, nofollow_links TEXT, status_code INTEGER, title TEXT); \
+                 CREATE TABLE domains (domain TEXT PRIMARY KEY, crawl_time TEXT NOT NULL, robots TEXT, sitemaps TEXT); \
+                 CREATE TABLE crawls (id INTEGER PRIMARY KEY AUTOINCREMENT, origin_url TEXT NOT NULL, start_time TEXT NOT NULL, end_time TEXT NOT NULL, pages_fetched INTEGER NOT NULL, fetch_failures INTEGER NOT NULL, status TEXT NOT NULL); \
+                 CREATE TABLE fetch_errors (url TEXT PRIMARY KEY, error_kind TEXT NOT NULL, message TEXT NOT NULL, attempt_count INTEGER NOT NULL, last_attempt TEXT NOT NULL); \
+                 INSERT INTO sites VALUES ('https://example.com/', '2024-01-01T00:00:00+00:00', 'https://example.com/a,https://example.com/b,https://example.com/it''s', 'https://example.com/b', 200, 'Home'); \
+                 INSERT INTO sites VALUES ('https://example.com/a', '2024-01-02T00:00:00+00:00', '', NULL, 404, NULL); \
+                 INSERT INTO domains VALUES ('example.com', '2024-01-01T00:00:00+00:00', 'User-agent: *', 'https://example.com/sitemap.xml'); \
+                 INSERT INTO crawls (origin_url, start_time, end_time, pages_fetched, fetch_failures, status) VALUES ('https://example.com/', '2024-01-01T00:00:00+00:00', '2024-01-01T00:01:00+00:00', 2, 1, 'accepted'); \
+                 INSERT INTO fetch_errors VALUES ('https://example.com/c', 'timeout', 'timed out', 2, '2024-01-01T00:00:30+00:00')",
+            )
+            .unwrap();
+        assert_eq!(database.schema_version().unwrap(), 0);
+
+        database.setup().unwrap();
+        assert_eq!(database.schema_version().unwrap(), SCHEMA_VERSION);
+
+        // Every table has the columns of a new database, apart from the legacy link columns that
+        // SQLite versions before 3.35 can't drop
+        let fresh = Database::in_memory().unwrap();
+        fresh.setup().unwrap();
+        for table in [
+            "sites",
+            "links",
+            "domains",
+            "crawls",
+            "crawl_runs",
+            "robots_history",
+            "fetch_errors",
+            "skip_decisions",
+        ] {
+            let mut columns = database.columns("main", table).unwrap();
+            columns.retain(|column| column != "links_to" && column != "nofollow_links");
+            columns.sort();
+            let mut expected = fresh.columns("main", table).unwrap();
+            expected.sort();
+            assert_eq!(columns, expected, "columns of {}", table);
+        }
+
+        // The rows and their values survive
+        assert_eq!(
+            count(
+                &database,
+                "SELECT url FROM sites WHERE url = 'https://example.com/' AND crawl_time = '2024-01-01T00:00:00+00:00' AND status_code = 200 AND title = 'Home'"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT url FROM sites WHERE url = 'https://example.com/a' AND status_code = 404 AND title IS NULL"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT domain FROM domains WHERE robots = 'User-agent: *' AND sitemaps = 'https://example.com/sitemap.xml' AND broken_tls_redirect = 0"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT id FROM crawls WHERE pages_fetched = 2 AND fetch_failures = 1 AND status = 'accepted' AND disk_bytes = 0"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &database,
+                "SELECT url FROM fetch_errors WHERE error_kind = 'timeout' AND attempt_count = 2 AND generation IS NULL"
+            ),
+            1
+        );
+
+        // Every link moved into the links table, with the nofollow one marked as such
+        assert_eq!(database.count_rows("links").unwrap(), 3);
+        for (target, kind) in [
+            ("https://example.com/a", "page"),
+            ("https://example.com/b", "nofollow"),
+            ("https://example.com/it''s", "page"),
+        ] {
+            assert_eq!(
+                count(
+                    &database,
+                    &format!(
+                        "SELECT target_url FROM links WHERE source_url = 'https://example.com/' AND target_url = '{}' AND kind = '{}'",
+                        target, kind
+                    )
+                ),
+                1,
+                "link to {}",
+                target
+            );
+        }
+        let site = database.read_site("https://example.com/").unwrap().unwrap();
+        assert_eq!(site.links_to.len(), 3);
+        assert!(site.links_to.contains("https://example.com/it's"));
+    }
+
+    #[test]
+    fn concurrent_transactions_never_leave_a_connection_inside_a_transaction() {
+        let path = std::env::temp_dir().join(format!("rustle-stress-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let shared = Database::new(path.to_str().unwrap()).unwrap();
+        shared
+            .execute("CREATE TABLE entries (id INTEGER PRIMARY KEY)")
+            .unwrap();
+
+        // Half of the threads share a connection, the others contend for the file with their own
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let shared = &shared;
+                let path = &path;
+                scope.spawn(move || {
+                    let own = Database::new(path.to_str().unwrap()).unwrap();
+                    let database = if thread % 2 == 0 { shared } else { &own };
+                    for batch in 0..25 {
+                        let id = thread * 1000 + batch;
+                        database
+                            .execute_transaction(&[format!(
+                                "INSERT INTO entries (id) VALUES ({})",
+                                id
+                            )])
+                            .unwrap();
+                        // Fails on its second statement, after the first one took effect
+                        let failed = database.execute_transaction(&[
+                            format!("INSERT INTO entries (id) VALUES ({})", id + 500),
+                            format!("INSERT INTO entries (id) VALUES ({})", id),
+                        ]);
+                        assert!(failed.is_err());
+                    }
+                });
+            }
+        });
+
+        assert_eq!(count(&shared, "SELECT id FROM entries"), 8 * 25);
+        assert_eq!(
+            count(&shared, "SELECT id FROM entries WHERE id % 1000 >= 500"),
+            0
+        );
+        shared.execute("BEGIN; COMMIT;").unwrap();
+        drop(shared);
+        let _ = std::fs::remove_file(&path);
     }
 }
@@ -0,0 +1,181 @@
+use crate::database::Database;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+
+/// The reasons a discovered URL can be skipped instead of being fetched.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SkipReason {
+    /// The URL is stored, and its stored copy is still fresh under `cache_ttl_hours`.
+    Cached,
+    /// The URL leads to a page already stored under its canonical URL.
+    CanonicalAlias,
+    /// The host of the URL was suspended after too many failures.
+    HostSuspended,
+    /// The robots.txt of the domain of the URL disallows it.
+    Robots,
+    /// The URL could not be checked against the cache or robots.txt.
+    CheckFailed,
+    /// The database reached `max_disk_mb`.
+    DiskLimit,
+    /// The domain of the URL reached `max_pages_per_domain`.
+    DomainPageLimit,
+    /// The crawl reached `max_pages`.
+    PageLimit,
+}
+
+impl SkipReason {
+    /// Describes the reason in a sentence, as printed by `rustle explain`.
+    pub fn describe(&self) -> &'static str {
+        return match self {
+            SkipReason::Cached => "its stored copy was still cached",
+            SkipReason::CanonicalAlias => "its page was already stored under its canonical URL",
+            SkipReason::HostSuspended => "its host was suspended after repeated failures",
+            SkipReason::Robots => "robots.txt disallowed it",
+            SkipReason::CheckFailed => "it could not be checked against the cache or robots.txt",
+            SkipReason::DiskLimit => "the database had reached max_disk_mb",
+            SkipReason::DomainPageLimit => "its domain had reached max_pages_per_domain",
+            SkipReason::PageLimit => "the crawl had reached max_pages",
+        };
+    }
+}
+
+impl std::str::FromStr for SkipReason {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        return match s {
+            "cached" => Ok(SkipReason::Cached),
+            "canonical_alias" => Ok(SkipReason::CanonicalAlias),
+            "host_suspended" => Ok(SkipReason::HostSuspended),
+            "robots" => Ok(SkipReason::Robots),
+            "check_failed" => Ok(SkipReason::CheckFailed),
+            "disk_limit" => Ok(SkipReason::DiskLimit),
+            "domain_page_limit" => Ok(SkipReason::DomainPageLimit),
+            "page_limit" => Ok(SkipReason::PageLimit),
+            other => bail!("Unknown skip reason '{}'", other),
+        };
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SkipReason::Cached => "cached",
+            SkipReason::CanonicalAlias => "canonical_alias",
+            SkipReason::HostSuspended => "host_suspended",
+            SkipReason::Robots => "robots",
+            SkipReason::CheckFailed => "check_failed",
+            SkipReason::DiskLimit => "disk_limit",
+            SkipReason::DomainPageLimit => "domain_page_limit",
+            SkipReason::PageLimit => "page_limit",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Represents the last time a discovered URL was skipped instead of being fetched, as recorded
+/// in the `skip_decisions` table until a later crawl stores it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkipDecision {
+    /// A `String` that holds the URL that was skipped.
+    pub url: String,
+    /// The reason the URL was skipped.
+    pub reason: SkipReason,
+    /// A `DateTime<Utc>` that represents the time the URL was skipped.
+    pub decided_at: DateTime<Utc>,
+    /// The id of the crawl run that skipped the URL, if it was recorded.
+    pub run_id: Option<i64>,
+}
+
+impl SkipDecision {
+    /// Records skip decisions in a single transaction, replacing the earlier decisions about the
+    /// same URLs.
+    ///
+    /// # Arguments
+    ///
+    /// * `decisions` - A slice of the decisions to record.
+    /// * `database` - A reference to the `Database` where the decisions are recorded.
+    pub fn write_many(decisions: &[SkipDecision], database: &Database) -> Result<()> {
+        if decisions.is_empty() {
+            return Ok(());
+        }
+        let statements: Vec<String> = decisions
+            .iter()
+            .map(|decision| {
+                return format!(
+                    "INSERT OR REPLACE INTO skip_decisions (url, reason, decided_at, run_id) VALUES ('{}', '{}', '{}', {})",
+                    decision.url.replace("'", "''"),
+                    decision.reason,
+                    decision.decided_at.to_rfc3339(),
+                    decision
+                        .run_id
+                        .map_or("NULL".to_string(), |run_id| run_id.to_string())
+                );
+            })
+            .collect();
+        return database.execute_transaction(&statements);
+    }
+
+    /// Forgets the decisions about URLs that were stored since.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs that were stored.
+    /// * `database` - A reference to the `Database` the decisions are recorded in.
+    pub fn clear(urls: &[&str], database: &Database) -> Result<()> {
+        if urls.is_empty() {
+            return Ok(());
+        }
+        let quoted: Vec<String> = urls
+            .iter()
+            .map(|url| format!("'{}'", url.replace("'", "''")))
+            .collect();
+        database.execute(&format!(
+            "DELETE FROM skip_decisions WHERE url IN ({})",
+            quoted.join(", ")
+        ))?;
+        return Ok(());
+    }
+
+    /// Reads the last decision to skip a URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    /// * `database` - A reference to the `Database` the decisions are recorded in.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Self>>` which is `Ok(None)` if the URL wasn't skipped since it was last
+    /// stored.
+    pub fn read_into(url: &str, database: &Database) -> Result<Option<Self>> {
+        let mut statement = database.prepare(&format!(
+            "SELECT reason, decided_at, run_id FROM skip_decisions WHERE url = '{}'",
+            url.replace("'", "''")
+        ))?;
+
+        if let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let reason = statement
+                .read::<String, usize>(0)
+                .context("Failed to read reason from the database")?;
+            let decided_at = statement
+                .read::<String, usize>(1)
+                .context("Failed to read decided_at from the database")?;
+            return Ok(Some(Self {
+                url: url.to_string(),
+                reason: reason.parse()?,
+                decided_at: DateTime::parse_from_rfc3339(&decided_at)
+                    .context("Failed to parse decided_at as RFC 3339")?
+                    .with_timezone(&Utc),
+                run_id: statement
+                    .read::<Option<i64>, usize>(2)
+                    .context("Failed to read run_id from the database")?,
+            }));
+        }
+
+        return Ok(None);
+    }
+}
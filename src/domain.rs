@@ -1,124 +1,30 @@
-use crate::database::Database;
-use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use log::info;
 
 /// Represents a domain that has been crawled.
 ///
 /// This struct holds information about a domain, including the domain name,
 /// the time it was crawled, and the contents of its robots.txt file.
+#[derive(Clone)]
 pub struct Domain {
-    ///  A `String` that holds the domain name.
+    ///  A `String` that holds the domain name. It is stored in its ASCII form, with
+    /// internationalized domain names in punycode.
     pub domain: String,
     /// A `DateTime<Utc>` that represents the time the domain was crawled.
     pub crawl_time: DateTime<Utc>,
-    /// A `String` that contains the contents of the domain's robots.txt file.
+    /// A `String` that contains the contents of the domain's robots.txt file, empty if the
+    /// domain has none.
     pub robots: String,
-}
-
-impl Domain {
-    /// Reads a `Domain` from the database based on the given domain.
-    ///
-    /// This function queries the database for a domain with the specified domain name.
-    /// If a matching domain is found, it constructs a `Domain` instance with the retrieved data.
-    ///
-    /// # Arguments
-    ///
-    /// * `domain` - A string slice that holds the domain of the site to be read.
-    /// * `database` - A reference to the `Database` from which the domain will be read.
-    ///
-    /// # Returns
-    ///
-    /// A `Result<Option<Self>>` which is `Ok(Some(Domain))` if a matching domain is found,
-    /// or `Ok(None)` if no match is found. If an error occurs during the query or data retrieval,
-    /// it returns an `Err`.
-    pub fn read_into(domain: &str, database: &Database) -> Result<Option<Self>> {
-        // Declare SQLite Query to get all entries where the domain value is equal to the given domain
-        let query = format!(
-            "SELECT crawl_time, robots FROM domains WHERE domain = '{}'",
-            domain
-        );
-
-        // Prepare Query
-        let mut statement = database.prepare(&query)?;
-
-        // Iterate over the rows returned by the query (should only be one, but need to return none
-        // if no rows are returned)
-        while let sqlite::State::Row = statement
-            .next()
-            .context("Failed to execute the SQL query")?
-        {
-            // Read the crawl time from the first column of the current row
-            let crawl_time_str: String = statement
-                .read::<String, usize>(0)
-                .context("Failed to read crawl_time from the database")?;
-
-            // Read the domain's robots.txt from the second column of the current row
-            let robots: String = statement
-                .read::<String, usize>(1)
-                .context("Failed to read robots from the database")?
-                .replace("''", "'");
-
-            // Parse the crawl time string into a DateTime<Utc> object
-            let crawl_time = DateTime::parse_from_rfc3339(&crawl_time_str)
-                .context("Failed to parse crawl_time as RFC 3339")?
-                .with_timezone(&Utc);
-
-            // Return a `Domain` instance with the retrieved data
-            return Ok(Some(Self {
-                domain: domain.to_string(),
-                crawl_time,
-                robots,
-            }));
-        }
-
-        return Ok(None);
-    }
-
-    /// Writes the `Domain` instance into the database.
-    ///
-    /// This function formats the `crawl_time` field into an RFC 3339 string, and then inserts or
-    /// replaces the domain record in the database with the current `Domain` instance's data.
-    ///
-    /// # Arguments
-    ///
-    /// * `database` - A reference to the `Database` where the domain will be written.
-    pub fn write_into(&self, database: &Database) {
-        let crawl_time_str = self.crawl_time.to_rfc3339();
-
-        let query =
-            format!(
-            "INSERT OR REPLACE INTO domains (domain, crawl_time, robots) VALUES ('{}', '{}', '{}')",
-            self.domain, crawl_time_str, self.robots.replace("'", "''")
-        );
-
-        database.execute(&query).unwrap();
-    }
-
-    /// Summarizes the database by counting the number of entries in the `domains` table.
-    ///
-    /// This function prepares and executes a SQL query to count the number of entries
-    /// in the `domains` table and logs the result using the `info` log level.
-    ///
-    /// # Arguments
-    ///
-    /// * `database` - A reference to the `Database` where the domain will be summarized.
-    ///
-    /// # Returns
-    ///
-    /// A `Result<()>` which is `Ok(())` if the operation is successful, or an `Err` if an error occurs.
-    pub fn summarize_domain_table(database: &Database) -> Result<()> {
-        let query = "SELECT COUNT(*) FROM domains";
-        let mut statement = database.prepare(query)?;
-        let _ = statement
-            .next()
-            .context("Failed to execute the SQL query")?;
-
-        let count = statement
-            .read::<i64, usize>(0)
-            .context("Failed to read the count from the database")?;
-
-        info!("{} Entries in domain table", count);
-        return Ok(());
-    }
+    /// The URL the robots.txt was fetched from, such as `http://example.com/robots.txt` for a
+    /// plain-HTTP site, or `None` if it was read from an override or stored by an older version.
+    pub robots_url: Option<String>,
+    /// A `Vec<String>` containing the URLs of the sitemaps discovered for the domain.
+    pub sitemaps: Vec<String>,
+    /// A `Vec<String>` containing the URLs of the RSS and Atom feeds discovered on the pages of
+    /// the domain.
+    pub feeds: Vec<String>,
+    /// An `Option<String>` that holds the path of the local robots.txt that was used instead of
+    /// the live one, if any. The `robots` field then holds the contents of that file.
+    pub robots_override: Option<String>,
+    /// A `bool` that is set when the domain redirects back and forth between `http` and `https`.
+    pub broken_tls_redirect: bool,
 }
@@ -0,0 +1,492 @@
+use crate::database::Database;
+use crate::domain::Domain;
+use crate::normalize::ascii_host;
+use crate::site_table::written_since;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use url::Url;
+
+impl Database {
+    /// Reads a `Domain` from the database based on the given domain.
+    ///
+    /// This function queries the database for a domain with the specified domain name.
+    /// If a matching domain is found, it constructs a `Domain` instance with the retrieved data.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain of the site to be read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Domain>>` which is `Ok(Some(Domain))` if a matching domain is found,
+    /// or `Ok(None)` if no match is found. If an error occurs during the query or data retrieval,
+    /// it returns an `Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustle::{Database, Domain};
+    ///
+    /// let db = Database::in_memory().unwrap();
+    /// db.setup().unwrap();
+    /// let domain = Domain {
+    ///     domain: "example.com".to_string(),
+    ///     crawl_time: chrono::Utc::now(),
+    ///     robots: "User-agent: *\nDisallow: /private/\n".to_string(),
+    ///     robots_url: Some("https://example.com/robots.txt".to_string()),
+    ///     sitemaps: vec!["https://example.com/sitemap.xml".to_string()],
+    ///     feeds: vec!["https://example.com/feed.xml".to_string()],
+    ///     robots_override: None,
+    ///     broken_tls_redirect: false,
+    /// };
+    /// db.write_domain(&domain).unwrap();
+    ///
+    /// let stored = db.read_domain("example.com").unwrap().unwrap();
+    /// assert_eq!(stored.robots, domain.robots);
+    /// assert_eq!(stored.robots_url, domain.robots_url);
+    /// assert_eq!(stored.sitemaps, domain.sitemaps);
+    /// assert_eq!(stored.feeds, domain.feeds);
+    ///
+    /// // Internationalized domain names are stored in punycode, and read in either form
+    /// let idn = Domain {
+    ///     domain: "bücher.example".to_string(),
+    ///     ..domain.clone()
+    /// };
+    /// db.write_domain(&idn).unwrap();
+    /// assert!(db.read_domain("xn--bcher-kva.example").unwrap().is_some());
+    /// assert!(db.read_domain("Bücher.example").unwrap().is_some());
+    /// ```
+    pub fn read_domain(&self, domain: &str) -> Result<Option<Domain>> {
+        // Declare SQLite Query to get all entries where the domain value is equal to the given domain
+        let query = format!(
+            "SELECT crawl_time, robots, sitemaps, robots_override, broken_tls_redirect, robots_url, feeds FROM domains WHERE domain = '{}'",
+            ascii_host(domain)
+        );
+
+        // Prepare Query
+        let mut statement = self.prepare(&query)?;
+
+        // Read the first row returned by the query (there should only be one, but need to return
+        // none if no rows are returned)
+        if let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            // Read the crawl time from the first column of the current row
+            let crawl_time_str: String = statement
+                .read::<String, usize>(0)
+                .context("Failed to read crawl_time from the database")?;
+
+            // Read the domain's robots.txt from the second column of the current row
+            let robots: String = statement
+                .read::<String, usize>(1)
+                .context("Failed to read robots from the database")?
+                .replace("''", "'");
+
+            // Read the domain's sitemaps from the third column of the current row, one per line
+            let sitemaps = statement
+                .read::<Option<String>, usize>(2)
+                .context("Failed to read sitemaps from the database")?
+                .map(|sitemaps| sitemaps.lines().map(str::to_string).collect())
+                .unwrap_or_default();
+
+            // Read the path of the robots.txt override from the fourth column of the current row
+            let robots_override = statement
+                .read::<Option<String>, usize>(3)
+                .context("Failed to read robots_override from the database")?;
+
+            // Read the broken TLS redirect flag from the fifth column of the current row
+            let broken_tls_redirect = statement
+                .read::<i64, usize>(4)
+                .context("Failed to read broken_tls_redirect from the database")?
+                != 0;
+
+            // Read the URL the robots.txt was fetched from from the sixth column of the current row
+            let robots_url = statement
+                .read::<Option<String>, usize>(5)
+                .context("Failed to read robots_url from the database")?;
+
+            // Read the domain's feeds from the seventh column of the current row, one per line
+            let feeds = statement
+                .read::<Option<String>, usize>(6)
+                .context("Failed to read feeds from the database")?
+                .map(|feeds| feeds.lines().map(str::to_string).collect())
+                .unwrap_or_default();
+
+            // Parse the crawl time string into a DateTime<Utc> object
+            let crawl_time = DateTime::parse_from_rfc3339(&crawl_time_str)
+                .context("Failed to parse crawl_time as RFC 3339")?
+                .with_timezone(&Utc);
+
+            // Return a `Domain` instance with the retrieved data
+            return Ok(Some(Domain {
+                domain: domain.to_string(),
+                crawl_time,
+                robots,
+                robots_url,
+                sitemaps,
+                feeds,
+                robots_override,
+                broken_tls_redirect,
+            }));
+        }
+
+        return Ok(None);
+    }
+
+    /// Writes the `Domain` instance into the database.
+    ///
+    /// This function formats the `crawl_time` field into an RFC 3339 string, joins the `sitemaps`
+    /// and `feeds` fields into newline-separated strings, and then inserts or replaces the domain record in
+    /// the database with the current `Domain` instance's data, keyed by the ASCII form of its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A reference to the `Domain` to write.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the domain cannot be written, even after retrying.
+    pub fn write_domain(&self, domain: &Domain) -> Result<()> {
+        let crawl_time_str = domain.crawl_time.to_rfc3339();
+
+        let sitemaps_str = domain.sitemaps.join("\n");
+        let feeds_str = domain.feeds.join("\n");
+
+        let robots_override_str = match &domain.robots_override {
+            Some(path) => format!("'{}'", path.replace("'", "''")),
+            None => "NULL".to_string(),
+        };
+
+        let robots_url_str = match &domain.robots_url {
+            Some(url) => format!("'{}'", url.replace("'", "''")),
+            None => "NULL".to_string(),
+        };
+
+        let query =
+            format!(
+            "INSERT OR REPLACE INTO domains (domain, crawl_time, robots, sitemaps, robots_override, broken_tls_redirect, robots_url, feeds) VALUES ('{}', '{}', '{}', '{}', {}, {}, {}, '{}')",
+            ascii_host(&domain.domain), crawl_time_str, domain.robots.replace("'", "''"), sitemaps_str.replace("'", "''"),
+            robots_override_str, domain.broken_tls_redirect as i64, robots_url_str,
+            feeds_str.replace("'", "''")
+        );
+
+        return self.execute(&query);
+    }
+
+    /// Lists the names of the domains crawled before the given time, which `delete_older_than`
+    /// deletes.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - The time before which the domains were crawled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the domain names, oldest crawl first.
+    pub fn domains_older_than(&self, cutoff: DateTime<Utc>) -> Result<Vec<String>> {
+        let query = format!(
+            "SELECT domain FROM domains WHERE crawl_time < '{}' ORDER BY crawl_time, domain",
+            cutoff.to_rfc3339()
+        );
+        let mut statement = self.prepare(&query)?;
+
+        let mut domains = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            domains.push(
+                statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read domain from the database")?,
+            );
+        }
+        return Ok(domains);
+    }
+
+    /// Deletes the domains crawled before the given time. Their robots.txt snapshots are kept,
+    /// as the compliance records of the pages fetched under them reference them.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - The time before which the domains were crawled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u64>` holding the number of deleted domains.
+    pub fn delete_domains_older_than(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        // RFC 3339 timestamps in UTC sort in chronological order as strings
+        let condition = format!("crawl_time < '{}'", cutoff.to_rfc3339());
+        let count = self.count_where("domains", &condition)?;
+        if count == 0 {
+            return Ok(0);
+        }
+        self.execute_transaction(&[format!("DELETE FROM domains WHERE {}", condition)])?;
+
+        info!("Deleted {} domains crawled before {}", count, cutoff);
+        return Ok(count);
+    }
+
+    /// Flags a domain as redirecting back and forth between `http` and `https`.
+    ///
+    /// The flag is set on the stored row of the domain, or on a new row without a robots.txt if
+    /// the domain isn't stored yet. A new row is dated at the Unix epoch, like those of
+    /// `add_feeds`, so that it is never taken for a fresh robots.txt allowing everything.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    pub fn mark_broken_tls_redirect(&self, domain: &str) -> Result<()> {
+        let query = format!(
+            "INSERT INTO domains (domain, crawl_time, robots, broken_tls_redirect) VALUES ('{}', '{}', '', 1) ON CONFLICT(domain) DO UPDATE SET broken_tls_redirect = 1",
+            ascii_host(domain),
+            DateTime::UNIX_EPOCH.to_rfc3339()
+        );
+        return self.execute(&query);
+    }
+
+    /// Adds feeds discovered on the pages of a domain to the feeds stored for it.
+    ///
+    /// The feeds are added to the stored row of the domain, or to a new row without a robots.txt
+    /// if the domain isn't stored yet. A new row is dated at the Unix epoch, so that a later crawl
+    /// that obeys robots.txt fetches it rather than taking the missing one as allowing everything.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `feeds` - The URLs of the discovered feeds.
+    pub fn add_feeds(&self, domain: &str, feeds: &[String]) -> Result<()> {
+        let mut stored = match self.read_domain(domain)? {
+            Some(stored) => stored.feeds,
+            None => Vec::new(),
+        };
+        for feed in feeds {
+            if !stored.contains(feed) {
+                stored.push(feed.clone());
+            }
+        }
+
+        let query = format!(
+            "INSERT INTO domains (domain, crawl_time, robots, feeds) VALUES ('{}', '{}', '', '{}') ON CONFLICT(domain) DO UPDATE SET feeds = excluded.feeds",
+            ascii_host(domain),
+            DateTime::UNIX_EPOCH.to_rfc3339(),
+            stored.join("\n").replace("'", "''")
+        );
+        return self.execute(&query);
+    }
+
+    /// Reads the feeds stored for every domain that has any.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<BTreeMap<String, Vec<String>>>` holding the feeds of each domain, by name.
+    pub fn read_feeds(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut statement = self
+            .prepare("SELECT domain, feeds FROM domains WHERE feeds IS NOT NULL AND feeds != ''")?;
+
+        let mut feeds = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let domain = statement
+                .read::<String, usize>(0)
+                .context("Failed to read domain from the database")?;
+            let domain_feeds = statement
+                .read::<String, usize>(1)
+                .context("Failed to read feeds from the database")?
+                .lines()
+                .map(str::to_string)
+                .collect();
+            feeds.insert(domain, domain_feeds);
+        }
+        return Ok(feeds);
+    }
+
+    /// Summarizes the database by counting the number of entries in the `domains` table.
+    ///
+    /// This function prepares and executes a SQL query to count the number of entries
+    /// in the `domains` table. It then reports
+    /// the cookies set per host, and warns about every host that served responses with
+    /// oversized headers or too many cookies, or bot-detection challenges, every domain with
+    /// broken TLS redirects, and every domain crawled with a robots.txt override.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Only count the domains written at or after this time, such as the start of a
+    ///   crawl, or `None` to count them all.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u64>` holding the number of domains counted, or an `Err` if an error occurs.
+    pub fn summarize_domain_table(&self, since: Option<DateTime<Utc>>) -> Result<u64> {
+        let query = format!("SELECT COUNT(*) FROM domains{}", written_since(since));
+        let mut statement = self.prepare(&query)?;
+        let _ = statement
+            .next()
+            .context("Failed to execute the SQL query")?;
+
+        let count = statement
+            .read::<i64, usize>(0)
+            .context("Failed to read the count from the database")?;
+
+        // Group the pages that set cookies or were flagged as header anomalies by host
+        let query = "SELECT url, header_anomaly, cookies_set FROM sites WHERE header_anomaly = 1 OR cookies_set > 0";
+        let mut statement = self.prepare(query)?;
+        let mut hosts: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let header_anomaly = statement
+                .read::<i64, usize>(1)
+                .context("Failed to read header_anomaly from the database")?;
+            let cookies_set = statement
+                .read::<i64, usize>(2)
+                .context("Failed to read cookies_set from the database")?;
+
+            let host = match Url::parse(&url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string))
+            {
+                Some(host) => host,
+                None => continue,
+            };
+            let entry = hosts.entry(host).or_insert((0, 0));
+            entry.0 += header_anomaly as u64;
+            entry.1 += cookies_set as u64;
+        }
+
+        // Report cookie store growth per host, and warn about hosts with header anomalies
+        for (host, (anomalies, cookies)) in &hosts {
+            info!("{}: {} cookies set", host, cookies);
+            if *anomalies > 0 {
+                warn!(
+                    "{}: {} responses with oversized headers or too many cookies",
+                    host, anomalies
+                );
+            }
+        }
+
+        // Warn about every host that served bot-detection challenges, since its data is incomplete
+        let query = "SELECT url FROM sites WHERE bot_challenged = 1";
+        let mut statement = self.prepare(query)?;
+        let mut challenged: BTreeMap<String, u64> = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            if let Some(host) = Url::parse(&url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string))
+            {
+                *challenged.entry(host).or_insert(0) += 1;
+            }
+        }
+        for (host, pages) in &challenged {
+            warn!(
+                "{}: {} pages were bot-detection challenges, crawl data for this host is incomplete",
+                host, pages
+            );
+        }
+
+        // Report every domain whose redirects flip between http and https, a bug for its owner to fix
+        let query = "SELECT domain FROM domains WHERE broken_tls_redirect = 1";
+        let mut statement = self.prepare(query)?;
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let domain = statement
+                .read::<String, usize>(0)
+                .context("Failed to read domain from the database")?;
+            error!(
+                "{}: redirects back and forth between http and https, its TLS redirect configuration is broken",
+                domain
+            );
+        }
+
+        // Warn about every domain crawled under planned robots.txt rules rather than live ones
+        let query = "SELECT domain, robots_override FROM domains WHERE robots_override IS NOT NULL";
+        let mut statement = self.prepare(query)?;
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let domain = statement
+                .read::<String, usize>(0)
+                .context("Failed to read domain from the database")?;
+            let path = statement
+                .read::<String, usize>(1)
+                .context("Failed to read robots_override from the database")?;
+            warn!(
+                "{}: crawled with the robots.txt override {} instead of the live robots.txt",
+                domain, path
+            );
+        }
+
+        return Ok(count as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn domains_round_trip_through_an_in_memory_database() {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let crawl_time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        database
+            .write_domain(&Domain {
+                domain: "bücher.example".to_string(),
+                crawl_time,
+                robots: "User-agent: *\nDisallow: /it's-private".to_string(),
+                robots_url: Some("https://xn--bcher-kva.example/robots.txt".to_string()),
+                sitemaps: vec![
+                    "https://xn--bcher-kva.example/sitemap.xml".to_string(),
+                    "https://xn--bcher-kva.example/news.xml".to_string(),
+                ],
+                feeds: vec!["https://xn--bcher-kva.example/feed".to_string()],
+                robots_override: Some("robots/it's.txt".to_string()),
+                broken_tls_redirect: true,
+            })
+            .unwrap();
+
+        // Domains are stored in their ASCII form, and read back under either form
+        let stored = database
+            .read_domain("xn--bcher-kva.example")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.domain, "xn--bcher-kva.example");
+        assert_eq!(stored.crawl_time, crawl_time);
+        assert_eq!(stored.robots, "User-agent: *\nDisallow: /it's-private");
+        assert_eq!(
+            stored.robots_url.as_deref(),
+            Some("https://xn--bcher-kva.example/robots.txt")
+        );
+        assert_eq!(
+            stored.sitemaps,
+            [
+                "https://xn--bcher-kva.example/sitemap.xml",
+                "https://xn--bcher-kva.example/news.xml"
+            ]
+        );
+        assert_eq!(stored.feeds, ["https://xn--bcher-kva.example/feed"]);
+        assert_eq!(stored.robots_override.as_deref(), Some("robots/it's.txt"));
+        assert!(stored.broken_tls_redirect);
+        assert!(database.read_domain("bücher.example").unwrap().is_some());
+        assert!(database.read_domain("example.org").unwrap().is_none());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What a crawl would do with a link of an origin page, as listed by a dry run.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LinkOutcome {
+    /// The link is in scope, allowed by robots.txt and passes every filter, so it would be
+    /// fetched.
+    WouldCrawl,
+    /// The host of the link is out of the `scope` of the origins.
+    OutOfScope,
+    /// The link is skipped by `fetch_policy`, a crawler trap rule or its `rel="nofollow"`.
+    Filtered,
+    /// The robots.txt of the host of the link disallows it.
+    BlockedByRobots,
+    /// The link isn't an `http` or `https` URL with a host, such as a `mailto:` link.
+    UnsupportedScheme,
+}
+
+impl LinkOutcome {
+    /// Every outcome, in the order they are listed.
+    pub const ALL: [LinkOutcome; 5] = [
+        LinkOutcome::WouldCrawl,
+        LinkOutcome::OutOfScope,
+        LinkOutcome::Filtered,
+        LinkOutcome::BlockedByRobots,
+        LinkOutcome::UnsupportedScheme,
+    ];
+}
+
+impl fmt::Display for LinkOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LinkOutcome::WouldCrawl => "would crawl",
+            LinkOutcome::OutOfScope => "out of scope",
+            LinkOutcome::Filtered => "filtered",
+            LinkOutcome::BlockedByRobots => "blocked by robots",
+            LinkOutcome::UnsupportedScheme => "unsupported scheme",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// Represents the outcome of a dry run: the origin pages fetched, and the links found on them
+/// sorted by what the crawl would do with them. Nothing is stored by a dry run.
+///
+/// Its listing is sorted by outcome and then by URL, so that two dry runs can be diffed:
+///
+/// ```
+/// use rustle::{Config, CrawlerBuilder, LinkOutcome, MapFetcher};
+/// use std::sync::Arc;
+///
+/// let fetcher = Arc::new(
+///     MapFetcher::new()
+///         .response(
+///             "https://example.com/robots.txt",
+///             200,
+///             &[("content-type", "text/plain")],
+///             b"User-agent: *\nDisallow: /admin\n",
+///         )
+///         .page(
+///             "https://example.com/",
+///             r#"<a href="/about">About</a> <a href="/admin">Admin</a>
+///             <a href="https://other.org/">Other</a> <a href="/report.pdf">Report</a>
+///             <a href="mailto:team@example.com">Mail</a>"#,
+///         ),
+/// );
+/// let config = Config {
+///     origin_urls: vec!["https://example.com/".to_string()],
+///     dry_run: true,
+///     ..Default::default()
+/// };
+/// let crawler = CrawlerBuilder::from(config)
+///     .fetcher(Box::new(fetcher.clone()))
+///     .build()?;
+/// let dry_run = crawler.dry_run()?;
+///
+/// assert_eq!(dry_run.urls(LinkOutcome::WouldCrawl), ["https://example.com/about"]);
+/// assert_eq!(dry_run.urls(LinkOutcome::OutOfScope), ["https://other.org/"]);
+/// assert_eq!(dry_run.urls(LinkOutcome::Filtered), ["https://example.com/report.pdf"]);
+/// assert_eq!(dry_run.urls(LinkOutcome::BlockedByRobots), ["https://example.com/admin"]);
+/// assert_eq!(dry_run.urls(LinkOutcome::UnsupportedScheme), ["mailto:team@example.com"]);
+///
+/// // The links are not followed
+/// assert!(!fetcher.requested("https://example.com/about"));
+/// # Ok::<(), rustle::Error>(())
+/// ```
+#[derive(Default, Debug)]
+pub struct DryRun {
+    /// The origin URLs, along with the status code of their page or the reason it wasn't
+    /// fetched.
+    pub seeds: BTreeMap<String, String>,
+    /// The links of the origin pages by outcome, along with the reason of the filtered ones.
+    pub links: BTreeMap<LinkOutcome, BTreeMap<String, Option<String>>>,
+}
+
+impl DryRun {
+    /// Records the outcome of a link, keeping the first one found for a URL linked several
+    /// times.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The `LinkOutcome` of the link.
+    /// * `url` - The URL of the link, normalized if it would be crawled.
+    /// * `reason` - Why the link is filtered, if it is.
+    pub(crate) fn add(&mut self, outcome: LinkOutcome, url: String, reason: Option<String>) {
+        if self.links.values().any(|urls| urls.contains_key(&url)) {
+            return;
+        }
+        self.links.entry(outcome).or_default().insert(url, reason);
+    }
+
+    /// Gets the URLs of the links with an outcome, sorted.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The `LinkOutcome` of the links.
+    pub fn urls(&self, outcome: LinkOutcome) -> Vec<&str> {
+        return self
+            .links
+            .get(&outcome)
+            .map(|urls| urls.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+    }
+}
+
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Dry run, nothing was stored")?;
+        for (url, outcome) in &self.seeds {
+            writeln!(f, "  Origin {}: {}", url, outcome)?;
+        }
+        for outcome in LinkOutcome::ALL {
+            let urls = self.links.get(&outcome);
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{} ({}):",
+                capitalize(&outcome.to_string()),
+                urls.map_or(0, BTreeMap::len)
+            )?;
+            for (url, reason) in urls.into_iter().flatten() {
+                match reason {
+                    Some(reason) => writeln!(f, "  {} ({})", url, reason)?,
+                    None => writeln!(f, "  {}", url)?,
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// Writes the first letter of a name in uppercase.
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    return match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    };
+}
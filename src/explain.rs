@@ -0,0 +1,380 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::decision::SkipDecision;
+use crate::robots::CompiledRobots;
+use crate::robots_history::RobotsSnapshot;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use url::Url;
+
+/// The number of referring pages named in an explanation.
+const MAX_REFERRERS: usize = 5;
+
+/// Represents everything the stored crawl data tells about a single URL.
+pub struct Explanation {
+    /// The URL being explained.
+    pub url: String,
+    /// Whether the URL is stored in the `sites` table.
+    pub stored: bool,
+    /// The findings about the URL, in the order the crawler goes through them.
+    pub steps: Vec<ExplanationStep>,
+}
+
+/// Represents a single finding of an `Explanation`.
+pub struct ExplanationStep {
+    /// The stage of the crawl the finding is about, such as `robots` or `fetch`.
+    pub stage: &'static str,
+    /// A human-readable description of the finding.
+    pub detail: String,
+}
+
+/// Implements the `Display` trait for the `Explanation` struct.
+///
+/// The formatted string shows the URL, followed by one line per step.
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{}", self.url)?;
+        for step in &self.steps {
+            write!(fmt, "\n  {}: {}", step.stage, step.detail)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Represents the chain from a page fetched in compliance mode to the robots.txt it was
+/// fetched under.
+pub struct ComplianceRecord {
+    /// The URL of the page.
+    pub url: String,
+    /// The time the page was fetched.
+    pub crawl_time: DateTime<Utc>,
+    /// The robots.txt rule that allowed the page, or `no rules`.
+    pub robots_rule: String,
+    /// The snapshot of the robots.txt applied to the domain of the page.
+    pub snapshot: RobotsSnapshot,
+}
+
+/// Implements the `Display` trait for the `ComplianceRecord` struct.
+///
+/// The formatted string shows the page, the rule and snapshot that allowed it, and the raw
+/// contents of the snapshot, indented.
+impl std::fmt::Display for ComplianceRecord {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{}", self.url)?;
+        write!(fmt, "\n  fetched at {}", self.crawl_time.to_rfc3339())?;
+        write!(fmt, "\n  allowed by rule: {}", self.robots_rule)?;
+        write!(
+            fmt,
+            "\n  under robots.txt snapshot {} of {}, fetched at {}",
+            self.snapshot.id,
+            self.snapshot.domain,
+            self.snapshot.fetch_time.to_rfc3339()
+        )?;
+        if let Some(path) = &self.snapshot.robots_override {
+            write!(fmt, " from the override {}", path)?;
+        }
+        if self.snapshot.robots.trim().is_empty() {
+            write!(fmt, "\n  (empty robots.txt)")?;
+        }
+        for line in self.snapshot.robots.lines() {
+            write!(fmt, "\n    | {}", line)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Reconstructs why a URL is or isn't stored, from the stored crawl data and the configuration.
+///
+/// The explanation follows the URL through the crawl: how it was discovered, whether it is in
+/// scope, what the stored robots.txt of its domain says about it, why it was last skipped if it
+/// was, how it was fetched, and how long the stored copy is cached.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL to explain.
+/// * `config` - A reference to the `Config` the crawls run with.
+/// * `database` - A reference to the `Database` holding the crawl data.
+///
+/// # Returns
+///
+/// A `Result<Explanation>` holding the findings about the URL.
+///
+/// # Errors
+///
+/// This function will return an error if the URL cannot be parsed, or the database cannot be read.
+pub fn explain(url: &str, config: &Config, database: &Database) -> Result<Explanation> {
+    let parsed_url = Url::parse(url).with_context(|| format!("'{}' is not a valid URL", url))?;
+    let host = parsed_url.host_str().unwrap_or_default();
+    let mut steps = Vec::new();
+
+    // Discovery
+    let referrers = database.inbound_links(url, false)?;
+    let detail = if config
+        .origin_urls
+        .iter()
+        .any(|origin_url| origin_url == url)
+    {
+        "is an origin URL of the crawl".to_string()
+    } else if referrers.is_empty() {
+        "is not linked from any stored page, so it was never discovered by following links"
+            .to_string()
+    } else {
+        let mut detail = format!(
+            "linked from {} stored pages: {}",
+            referrers.len(),
+            referrers
+                .iter()
+                .take(MAX_REFERRERS)
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        if referrers.len() > MAX_REFERRERS {
+            detail.push_str(", ...");
+        }
+        detail
+    };
+    steps.push(ExplanationStep {
+        stage: "discovery",
+        detail,
+    });
+
+    // Scope
+    let detail = if config
+        .origin_hosts()
+        .iter()
+        .any(|origin_host| config.scope.allows(host, origin_host))
+    {
+        format!("host {} is in scope (scope = {})", host, config.scope)
+    } else {
+        format!(
+            "host {} is out of scope (scope = {}), so the URL is never fetched",
+            host, config.scope
+        )
+    };
+    steps.push(ExplanationStep {
+        stage: "scope",
+        detail,
+    });
+
+    // Robots
+    let detail = match database.read_domain(host)? {
+        _ if !config.respect_robots => "robots.txt is ignored (respect_robots = false)".to_string(),
+        None => format!("the robots.txt of {} was never fetched", host),
+        Some(domain) => {
+            let source = match &domain.robots_override {
+                Some(path) => format!("the robots.txt override {}", path),
+                None => match &domain.robots_url {
+                    Some(robots_url) if domain.robots.is_empty() => {
+                        format!("the missing robots.txt at {}", robots_url)
+                    }
+                    Some(robots_url) => format!("the robots.txt fetched from {}", robots_url),
+                    None => format!("the stored robots.txt of {}", host),
+                },
+            };
+            if CompiledRobots::parse(&domain.robots).allows(parsed_url.path()) {
+                format!("allowed by {}", source)
+            } else {
+                format!("disallowed by {}, so the URL is never fetched", source)
+            }
+        }
+    };
+    steps.push(ExplanationStep {
+        stage: "robots",
+        detail,
+    });
+
+    // Admission, from the log of the URLs skipped since they were last stored
+    if let Some(decision) = SkipDecision::read_into(url, database)? {
+        let mut detail = format!("skipped at {}", decision.decided_at.to_rfc3339());
+        if let Some(run_id) = decision.run_id {
+            detail.push_str(&format!(" by crawl run {}", run_id));
+        }
+        detail.push_str(&format!(" because {}", decision.reason.describe()));
+        steps.push(ExplanationStep {
+            stage: "admission",
+            detail,
+        });
+    }
+
+    // Fetch and cache
+    let site = database.read_site(url)?;
+    match &site {
+        Some(site) => {
+            let mut detail = format!("fetched at {}", site.crawl_time.to_rfc3339());
+            if let Some(status_code) = site.status_code {
+                detail.push_str(&format!(" with status {}", status_code));
+            }
+            if let Some(final_url) = site.final_url.as_ref().filter(|f| f.as_str() != url) {
+                detail.push_str(&format!(", redirected to {}", final_url));
+            }
+            if site.bot_challenged {
+                detail.push_str(", but got a bot-detection challenge instead of the content");
+            }
+            if site.header_anomaly {
+                detail.push_str(", with oversized headers or too many cookies");
+            }
+            detail.push_str(&format!(", {} links found", site.links_to.len()));
+            steps.push(ExplanationStep {
+                stage: "fetch",
+                detail,
+            });
+
+            let detail = match config.cache_ttl_hours {
+                Some(0) => "refetched by every crawl (cache_ttl_hours = 0)".to_string(),
+                Some(hours) => format!(
+                    "cached until {}, later crawls skip it until then",
+                    (site.crawl_time + chrono::Duration::hours(hours as i64)).to_rfc3339()
+                ),
+                None => "cached forever, later crawls never refetch it".to_string(),
+            };
+            steps.push(ExplanationStep {
+                stage: "cache",
+                detail,
+            });
+        }
+        None => steps.push(ExplanationStep {
+            stage: "fetch",
+            detail: "not stored: it was never fetched, its fetch failed, the crawl ran out of \
+                     depth or pages, or the page asked not to be indexed"
+                .to_string(),
+        }),
+    }
+
+    return Ok(Explanation {
+        url: url.to_string(),
+        stored: site.is_some(),
+        steps,
+    });
+}
+
+/// Reads the compliance record of a stored page, linking it to the robots.txt snapshot and rule
+/// that allowed it.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL of the page.
+/// * `database` - A reference to the `Database` holding the crawl data.
+///
+/// # Returns
+///
+/// A `Result<ComplianceRecord>` holding the chain from the page to the raw robots.txt.
+///
+/// # Errors
+///
+/// This function will return an error if the page is not stored, was not fetched in compliance
+/// mode, or its snapshot is missing from the database.
+pub fn compliance(url: &str, database: &Database) -> Result<ComplianceRecord> {
+    let site = match database.read_site(url)? {
+        Some(site) => site,
+        None => bail!("{} is not stored", url),
+    };
+    let (snapshot_id, robots_rule) = match (site.robots_snapshot, site.robots_rule) {
+        (Some(snapshot_id), Some(robots_rule)) => (snapshot_id, robots_rule),
+        _ => bail!(
+            "{} was not fetched in compliance mode, or without respecting robots.txt",
+            url
+        ),
+    };
+    let snapshot = RobotsSnapshot::read_into(snapshot_id, database)?
+        .with_context(|| format!("Robots snapshot {} of {} is missing", snapshot_id, url))?;
+
+    return Ok(ComplianceRecord {
+        url: site.url,
+        crawl_time: site.crawl_time,
+        robots_rule,
+        snapshot,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::CrawlerBuilder;
+    use crate::fetcher::MapFetcher;
+
+    /// Crawls a small site, whose robots.txt disallows `/private`, into a temporary database,
+    /// then explains each of the given URLs.
+    fn explain_after_crawl(urls: &[&str]) -> Vec<Explanation> {
+        let path = std::env::temp_dir().join(format!("rustle-explain-{}.db", std::process::id()));
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            database: path.display().to_string(),
+            depth: 1,
+            ..Config::default()
+        };
+        let fetcher = MapFetcher::new()
+            .response(
+                "https://example.com/robots.txt",
+                200,
+                &[("content-type", "text/plain")],
+                b"User-agent: *\nDisallow: /private\n",
+            )
+            .page(
+                "https://example.com/",
+                r#"<a href="/a">A</a> <a href="/private">Private</a>"#,
+            )
+            .page("https://example.com/a", "<p>A</p>");
+        CrawlerBuilder::from(config.clone())
+            .fetcher(Box::new(fetcher))
+            .build()
+            .unwrap()
+            .crawl(false)
+            .unwrap();
+
+        let database = Database::new(&config.database).unwrap();
+        let explanations = urls
+            .iter()
+            .map(|url| explain(url, &config, &database).unwrap())
+            .collect();
+        drop(database);
+        let _ = std::fs::remove_file(&path);
+        return explanations;
+    }
+
+    /// Gets the detail of the step of an explanation at a stage, if it has one.
+    fn step<'a>(explanation: &'a Explanation, stage: &str) -> Option<&'a str> {
+        return explanation
+            .steps
+            .iter()
+            .find(|step| step.stage == stage)
+            .map(|step| step.detail.as_str());
+    }
+
+    #[test]
+    fn fetched_blocked_and_undiscovered_urls_are_explained() {
+        let explanations = explain_after_crawl(&[
+            "https://example.com/a",
+            "https://example.com/private",
+            "https://example.com/never",
+        ]);
+
+        // A fetched page is traced back to the page linking to it
+        let fetched = &explanations[0];
+        assert!(fetched.stored);
+        assert_eq!(
+            step(fetched, "discovery"),
+            Some("linked from 1 stored pages: https://example.com/")
+        );
+        assert!(step(fetched, "fetch").unwrap().contains("with status 200"));
+        assert_eq!(step(fetched, "admission"), None);
+
+        // A page disallowed by robots.txt was discovered, but skipped
+        let blocked = &explanations[1];
+        assert!(!blocked.stored);
+        assert!(step(blocked, "robots")
+            .unwrap()
+            .starts_with("disallowed by"));
+        assert!(step(blocked, "admission")
+            .unwrap()
+            .ends_with("because robots.txt disallowed it"));
+
+        // A page no stored page links to was never discovered, let alone skipped
+        let undiscovered = &explanations[2];
+        assert!(!undiscovered.stored);
+        assert!(step(undiscovered, "discovery")
+            .unwrap()
+            .contains("never discovered"));
+        assert_eq!(step(undiscovered, "admission"), None);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::config::Scope;
+use crate::database::Database;
+use crate::output::{Document, EdgeOutput, GraphReport};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use url::Url;
+
+/// The formats the link graph can be exported to.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A Graphviz DOT digraph.
+    Dot,
+    /// A JSON document listing the nodes and edges.
+    Json,
+    /// A CSV edge list, one `source,target,links` row per edge.
+    Csv,
+}
+
+/// Options narrowing down the link graph before it is exported.
+#[derive(Default)]
+pub struct ExportOptions {
+    /// Only keep the links between pages of this domain and its subdomains.
+    pub domain: Option<String>,
+    /// Collapse the pages of each host into a single node.
+    pub collapse_hosts: bool,
+    /// Only keep the given number of nodes with the most links, and the links between them.
+    pub top: Option<usize>,
+}
+
+/// Writes the link graph stored in the links table as a DOT digraph.
+///
+/// Nodes and edges are written in sorted order, so that the same database always produces the
+/// same file. When hosts are collapsed, every edge is labeled with the number of links it stands
+/// for, and links within a host are left out.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the sites.
+/// * `writer` - The writer the DOT digraph is written to.
+/// * `options` - The `ExportOptions` narrowing down the graph.
+///
+/// # Returns
+///
+/// A `Result<()>` which is `Ok(())` if the graph was written, or an `Err` if an error occurs.
+pub fn export_dot(
+    database: &Database,
+    writer: &mut impl Write,
+    options: &ExportOptions,
+) -> Result<()> {
+    let graph = LinkGraph::build(database, options)?;
+
+    writeln!(writer, "digraph rustle {{")?;
+    for node in &graph.nodes {
+        writeln!(writer, "    {};", quote(node))?;
+    }
+    for ((from, to), links) in &graph.edges {
+        if options.collapse_hosts {
+            writeln!(
+                writer,
+                "    {} -> {} [label=\"{}\"];",
+                quote(from),
+                quote(to),
+                links
+            )?;
+        } else {
+            writeln!(writer, "    {} -> {};", quote(from), quote(to))?;
+        }
+    }
+    writeln!(writer, "}}")?;
+
+    return Ok(());
+}
+
+/// Writes the link graph stored in the links table as a versioned JSON document, holding the
+/// sorted `nodes` and the `edges` between them along with the number of links each stands for.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the sites.
+/// * `writer` - The writer the JSON document is written to.
+/// * `options` - The `ExportOptions` narrowing down the graph.
+///
+/// # Returns
+///
+/// A `Result<()>` which is `Ok(())` if the graph was written, or an `Err` if an error occurs.
+pub fn export_json(
+    database: &Database,
+    writer: &mut impl Write,
+    options: &ExportOptions,
+) -> Result<()> {
+    let graph = LinkGraph::build(database, options)?;
+
+    let report = GraphReport {
+        nodes: graph.nodes.into_iter().collect(),
+        edges: graph
+            .edges
+            .into_iter()
+            .map(|((from, to), links)| EdgeOutput { from, to, links })
+            .collect(),
+    };
+    let document = Document::new(GraphReport::NAME, report);
+    writeln!(writer, "{}", document.to_json()?)?;
+
+    return Ok(());
+}
+
+/// Writes the link graph stored in the links table as a CSV edge list, with a
+/// `source,target,links` header row.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the sites.
+/// * `writer` - The writer the CSV rows are written to.
+/// * `options` - The `ExportOptions` narrowing down the graph.
+///
+/// # Returns
+///
+/// A `Result<()>` which is `Ok(())` if the graph was written, or an `Err` if an error occurs.
+pub fn export_csv(
+    database: &Database,
+    writer: &mut impl Write,
+    options: &ExportOptions,
+) -> Result<()> {
+    let graph = LinkGraph::build(database, options)?;
+
+    writeln!(writer, "source,target,links")?;
+    for ((from, to), links) in &graph.edges {
+        writeln!(writer, "{},{},{}", csv_field(from), csv_field(to), links)?;
+    }
+
+    return Ok(());
+}
+
+/// Checks whether the stored link graph has any node, once narrowed down by the export options.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` holding the sites.
+/// * `options` - The `ExportOptions` narrowing down the graph.
+pub fn has_nodes(database: &Database, options: &ExportOptions) -> Result<bool> {
+    return Ok(!LinkGraph::build(database, options)?.nodes.is_empty());
+}
+
+/// Represents the link graph of the stored sites, narrowed down by the export options.
+struct LinkGraph {
+    /// The nodes of the graph, in sorted order.
+    nodes: BTreeSet<String>,
+    /// The edges of the graph, along with the number of links each edge stands for.
+    edges: BTreeMap<(String, String), usize>,
+}
+
+impl LinkGraph {
+    /// Builds the link graph of the sites stored in the database.
+    ///
+    /// Every stored site is a node, as well as every URL it links to, even if it wasn't fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - A reference to the `Database` holding the sites.
+    /// * `options` - The `ExportOptions` narrowing down the graph.
+    fn build(database: &Database, options: &ExportOptions) -> Result<Self> {
+        let mut nodes = BTreeSet::new();
+        let mut edges = BTreeMap::new();
+        for (url, links) in database.list_links()? {
+            let Some(from) = node_of(&url, options) else {
+                continue;
+            };
+            nodes.insert(from.clone());
+            for link in links {
+                let Some(to) = node_of(&link, options) else {
+                    continue;
+                };
+                nodes.insert(to.clone());
+                if options.collapse_hosts && from == to {
+                    continue;
+                }
+                *edges.entry((from.clone(), to)).or_insert(0) += 1;
+            }
+        }
+
+        // Keep the nodes with the highest degree, breaking ties by name
+        if let Some(top) = options.top {
+            let mut degrees: BTreeMap<&String, usize> =
+                nodes.iter().map(|node| (node, 0)).collect();
+            for (from, to) in edges.keys() {
+                *degrees.entry(from).or_insert(0) += 1;
+                *degrees.entry(to).or_insert(0) += 1;
+            }
+            let mut ranked: Vec<(&String, usize)> = degrees.into_iter().collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let kept: BTreeSet<String> = ranked
+                .into_iter()
+                .take(top)
+                .map(|(node, _)| node.clone())
+                .collect();
+            edges.retain(|(from, to), _| kept.contains(from) && kept.contains(to));
+            nodes = kept;
+        }
+
+        return Ok(LinkGraph { nodes, edges });
+    }
+}
+
+/// Gets the node a URL belongs to, or `None` if the URL is left out of the graph.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL.
+/// * `options` - The `ExportOptions` narrowing down the graph.
+fn node_of(url: &str, options: &ExportOptions) -> Option<String> {
+    if options.domain.is_none() && !options.collapse_hosts {
+        return Some(url.to_string());
+    }
+
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    if let Some(domain) = &options.domain {
+        if !Scope::Subdomains.allows(host, domain) {
+            return None;
+        }
+    }
+    if options.collapse_hosts {
+        return Some(host.to_string());
+    }
+    return Some(url.to_string());
+}
+
+/// Quotes a CSV field if it holds a comma, quote or line break, doubling the quotes it contains.
+///
+/// # Arguments
+///
+/// * `field` - A string slice that holds the field.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    return field.to_string();
+}
+
+/// Quotes a node name as a DOT identifier, escaping the backslashes and quotes it contains.
+///
+/// # Arguments
+///
+/// * `name` - A string slice that holds the node name.
+fn quote(name: &str) -> String {
+    return format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Crawls a tiny fixture site into a new database at a path, and opens it.
+    fn crawl_fixture(path: &std::path::Path) -> Database {
+        let _ = std::fs::remove_file(path);
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .page(
+                "https://example.com/",
+                r#"<a href="/a">A</a><a href="https://blog.example.com/">Blog</a><a href="https://other.org/">Other</a>"#,
+            )
+            .page("https://example.com/a", r#"<a href="/">Home</a><a href="/b">B</a>"#)
+            .page("https://blog.example.com/", r#"<a href="https://example.com/a">A</a>"#);
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            database: path.display().to_string(),
+            depth: 1,
+            respect_robots: false,
+            scope: Scope::Subdomains,
+            ..Config::default()
+        };
+        crate::builder::CrawlerBuilder::from(config)
+            .fetcher(Box::new(fetcher))
+            .build()
+            .unwrap()
+            .crawl(false)
+            .unwrap();
+        return Database::new(&path.display().to_string()).unwrap();
+    }
+
+    /// Exports the link graph of a database as DOT.
+    fn dot(database: &Database, options: &ExportOptions) -> String {
+        let mut output = Vec::new();
+        export_dot(database, &mut output, options).unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn crawls_export_to_a_deterministic_dot_file() {
+        let path = std::env::temp_dir().join(format!("rustle-export-{}.db", std::process::id()));
+        let database = crawl_fixture(&path);
+        let graph = dot(&database, &ExportOptions::default());
+        assert_eq!(
+            graph,
+            r#"digraph rustle {
+    "https://blog.example.com/";
+    "https://example.com/";
+    "https://example.com/a";
+    "https://example.com/b";
+    "https://blog.example.com/" -> "https://example.com/a";
+    "https://example.com/" -> "https://blog.example.com/";
+    "https://example.com/" -> "https://example.com/a";
+    "https://example.com/a" -> "https://example.com/";
+    "https://example.com/a" -> "https://example.com/b";
+}
+"#
+        );
+
+        // Hosts collapse into nodes whose edges count links, and the top nodes keep their edges
+        assert_eq!(
+            dot(
+                &database,
+                &ExportOptions {
+                    collapse_hosts: true,
+                    ..Default::default()
+                }
+            ),
+            r#"digraph rustle {
+    "blog.example.com";
+    "example.com";
+    "blog.example.com" -> "example.com" [label="1"];
+    "example.com" -> "blog.example.com" [label="1"];
+}
+"#
+        );
+        assert_eq!(
+            dot(
+                &database,
+                &ExportOptions {
+                    top: Some(2),
+                    ..Default::default()
+                }
+            ),
+            r#"digraph rustle {
+    "https://example.com/";
+    "https://example.com/a";
+    "https://example.com/" -> "https://example.com/a";
+    "https://example.com/a" -> "https://example.com/";
+}
+"#
+        );
+        assert_eq!(
+            dot(
+                &database,
+                &ExportOptions {
+                    domain: Some("blog.example.com".to_string()),
+                    ..Default::default()
+                }
+            ),
+            "digraph rustle {\n    \"https://blog.example.com/\";\n}\n"
+        );
+        drop(database);
+
+        // Crawling the same site again produces the same file
+        let database = crawl_fixture(&path);
+        assert_eq!(dot(&database, &ExportOptions::default()), graph);
+        drop(database);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn node_names_are_escaped() {
+        assert_eq!(quote("https://example.com/"), r#""https://example.com/""#);
+        assert_eq!(quote(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+        assert_eq!(csv_field("a,b"), r#""a,b""#);
+        assert_eq!(csv_field(r#"a"b"#), r#""a""b""#);
+        assert_eq!(csv_field("https://example.com/"), "https://example.com/");
+    }
+}
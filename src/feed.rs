@@ -0,0 +1,164 @@
+use crate::sitemap::unescape;
+
+/// The media types of the feeds announced with `<link rel="alternate">`.
+pub const FEED_TYPES: &[&str] = &["application/rss+xml", "application/atom+xml"];
+
+/// Parses an RSS or Atom feed into the URLs of its entries.
+///
+/// This is a minimal parser that only looks at the `<item>` elements of RSS feeds and the
+/// `<entry>` elements of Atom feeds. The URL of an entry is the text of its `<link>` element, or
+/// the `href` of its first `<link>` element that is not of another relation than `alternate`, or
+/// else its `<id>` if that is a URL. Malformed documents yield whatever URLs could be found.
+///
+/// # Arguments
+///
+/// * `xml` - A string slice that holds the XML content of the feed.
+///
+/// # Returns
+///
+/// A `Vec<String>` containing the entry URLs as they appear in the feed, which may be relative.
+pub fn parse_feed(xml: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for (open, close) in [("<item", "</item>"), ("<entry", "</entry>")] {
+        let mut rest = xml;
+        while let Some(start) = find_element(rest, open) {
+            rest = &rest[start + open.len()..];
+            let end = rest.find(close).unwrap_or(rest.len());
+            if let Some(url) = entry_url(&rest[..end]) {
+                urls.push(url);
+            }
+            rest = &rest[end..];
+        }
+    }
+    return urls;
+}
+
+/// Finds the next start tag of an element, skipping elements whose name only starts with it.
+///
+/// # Arguments
+///
+/// * `xml` - A string slice that holds the XML to search.
+/// * `open` - A string slice that holds the start of the tag, such as `<item`.
+///
+/// # Returns
+///
+/// The byte offset of the tag, or `None` if there is none.
+fn find_element(xml: &str, open: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = xml[offset..].find(open) {
+        let start = offset + start;
+        let next = xml[start + open.len()..].chars().next();
+        if next.is_none_or(|next| next == '>' || next == '/' || next.is_whitespace()) {
+            return Some(start);
+        }
+        offset = start + open.len();
+    }
+    return None;
+}
+
+/// Reads the URL of a feed entry.
+///
+/// # Arguments
+///
+/// * `entry` - A string slice that holds the content of the `<item>` or `<entry>` element.
+fn entry_url(entry: &str) -> Option<String> {
+    // RSS: the text of the `<link>` element
+    if let Some(link) = element_text(entry, "link") {
+        return Some(link);
+    }
+
+    // Atom: the `href` of the first alternate `<link>` element
+    let mut rest = entry;
+    while let Some(start) = find_element(rest, "<link") {
+        rest = &rest[start..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        let relation = attribute(tag, "rel");
+        if relation.is_none_or(|relation| relation == "alternate") {
+            if let Some(href) = attribute(tag, "href").filter(|href| !href.is_empty()) {
+                return Some(href);
+            }
+        }
+        rest = &rest[end..];
+    }
+
+    // Atom: the `<id>` of the entry, if it is a URL
+    return element_text(entry, "id").filter(|id| id.starts_with("http"));
+}
+
+/// Reads the text of the first element of a name, without its `CDATA` markers.
+///
+/// # Arguments
+///
+/// * `xml` - A string slice that holds the XML to search.
+/// * `name` - A string slice that holds the name of the element.
+///
+/// # Returns
+///
+/// The unescaped, trimmed text of the element, or `None` if it is missing or empty.
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    let text = unescape(
+        xml[start..end]
+            .trim()
+            .trim_start_matches("<![CDATA[")
+            .trim_end_matches("]]>")
+            .trim(),
+    );
+    return if text.is_empty() { None } else { Some(text) };
+}
+
+/// Reads the value of an attribute of a start tag.
+///
+/// # Arguments
+///
+/// * `tag` - A string slice that holds the start tag, without its closing `>`.
+/// * `name` - A string slice that holds the name of the attribute.
+///
+/// # Returns
+///
+/// The unescaped value of the attribute, or `None` if the tag doesn't have it.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals]
+            .split_whitespace()
+            .next_back()
+            .unwrap_or_default();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let end = value.find(quote)?;
+        if key.eq_ignore_ascii_case(name) {
+            return Some(unescape(value[..end].trim()));
+        }
+        rest = &value[end + 1..];
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rss_and_atom_entries_are_parsed() {
+        let rss = "<rss><channel><link>https://example.com/</link>
+            <item><title>First</title><link>https://example.com/first?a=1&amp;b=2</link></item>
+            <item><link><![CDATA[/second]]></link></item></channel></rss>";
+        assert_eq!(
+            parse_feed(rss),
+            ["https://example.com/first?a=1&b=2", "/second"]
+        );
+
+        let atom = r#"<feed><entry><link rel="edit" href="/edit/1"/><link href="/posts/1"/></entry>
+            <entry><id>https://example.com/posts/2</id></entry><entry><id>tag:x</id>"#;
+        assert_eq!(
+            parse_feed(atom),
+            ["/posts/1", "https://example.com/posts/2"]
+        );
+    }
+}
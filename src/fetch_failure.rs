@@ -0,0 +1,247 @@
+use crate::crawl_run::CrawlRun;
+use crate::database::Database;
+use crate::report::ErrorCategory;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use url::Url;
+
+/// The number of failing domains listed by `rustle stats`.
+pub const WORST_DOMAINS: usize = 10;
+
+/// Represents a URL whose last fetch failed, as recorded in the `fetch_errors` table until a
+/// later fetch of it succeeds.
+pub struct FetchFailure {
+    /// A `String` that holds the URL that could not be fetched.
+    pub url: String,
+    /// A `String` that holds the `ErrorCategory` of the last failure, such as `timeout`.
+    pub error_kind: String,
+    /// A `String` that holds the reason of the last failure.
+    pub message: String,
+    /// A `u64` that holds the number of fetches of the URL that failed in a row.
+    pub attempt_count: u64,
+    /// A `DateTime<Utc>` that represents the time of the last failed fetch.
+    pub last_attempt: DateTime<Utc>,
+    /// The generation the URL was fetched in by the last failed fetch, if it was fetched by a
+    /// worker batch.
+    pub generation: Option<u64>,
+    /// The worker batch of the last failed fetch, if it was fetched by one.
+    pub batch_id: Option<u64>,
+    /// The id of the crawl run of the last failed fetch, if it was recorded.
+    pub run_id: Option<i64>,
+}
+
+impl FetchFailure {
+    /// Creates a new instance of the `FetchFailure` struct for a single failed fetch, outside of
+    /// any worker batch or crawl run.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL that could not be fetched.
+    /// * `category` - The `ErrorCategory` of the failure.
+    /// * `message` - A string slice that holds the reason of the failure.
+    /// * `attempt_time` - The time of the failed fetch.
+    pub fn new(
+        url: &str,
+        category: ErrorCategory,
+        message: &str,
+        attempt_time: DateTime<Utc>,
+    ) -> Self {
+        return FetchFailure {
+            url: url.to_string(),
+            error_kind: category.to_string(),
+            message: message.to_string(),
+            attempt_count: 1,
+            last_attempt: attempt_time,
+            generation: None,
+            batch_id: None,
+            run_id: None,
+        };
+    }
+
+    /// Records a failed fetch of a URL, counting it as one more attempt if the URL already
+    /// failed before. The `attempt_count` of the failure is not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure` - A reference to the failed fetch.
+    /// * `database` - A reference to the `Database` where the failure is recorded.
+    pub fn record(failure: &FetchFailure, database: &Database) -> Result<()> {
+        let or_null = |value: Option<String>| value.unwrap_or_else(|| "NULL".to_string());
+        database.execute(&format!(
+            "INSERT INTO fetch_errors (url, error_kind, message, attempt_count, last_attempt, generation, batch_id, run_id) \
+             VALUES ('{}', '{}', '{}', 1, '{}', {}, {}, {}) \
+             ON CONFLICT (url) DO UPDATE SET error_kind = excluded.error_kind, message = excluded.message, \
+             attempt_count = attempt_count + 1, last_attempt = excluded.last_attempt, \
+             generation = excluded.generation, batch_id = excluded.batch_id, run_id = excluded.run_id",
+            failure.url.replace("'", "''"),
+            failure.error_kind.replace("'", "''"),
+            failure.message.replace("'", "''"),
+            failure.last_attempt.to_rfc3339(),
+            or_null(failure.generation.map(|generation| generation.to_string())),
+            or_null(failure.batch_id.map(|batch_id| batch_id.to_string())),
+            or_null(failure.run_id.map(|run_id| run_id.to_string()))
+        ))?;
+        return Ok(());
+    }
+
+    /// Forgets the failures of URLs that were fetched since.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs that were fetched.
+    /// * `database` - A reference to the `Database` the failures are recorded in.
+    pub fn clear(urls: &[&str], database: &Database) -> Result<()> {
+        if urls.is_empty() {
+            return Ok(());
+        }
+        let quoted: Vec<String> = urls
+            .iter()
+            .map(|url| format!("'{}'", url.replace("'", "''")))
+            .collect();
+        database.execute(&format!(
+            "DELETE FROM fetch_errors WHERE url IN ({})",
+            quoted.join(", ")
+        ))?;
+        return Ok(());
+    }
+
+    /// Reads every recorded failure, the most recent first.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - A reference to the `Database` the failures are recorded in.
+    pub fn read_all(database: &Database) -> Result<Vec<Self>> {
+        return Self::read_matching(database, "ORDER BY last_attempt DESC, url");
+    }
+
+    /// Reads the failures of a generation of the last crawl run that reached it, the same run
+    /// `Database::sites_in_generation` reads the sites of.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - A reference to the `Database` the failures are recorded in.
+    /// * `generation` - The generation of the crawl, counting the origin as generation 0.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<Self>>` holding the failures of the generation, in the order of their
+    /// batches.
+    pub fn in_generation(database: &Database, generation: u64) -> Result<Vec<Self>> {
+        return Self::read_matching(
+            database,
+            &format!(
+                "WHERE generation = {} AND run_id IS ({}) ORDER BY batch_id, url",
+                generation,
+                CrawlRun::last_to_reach_query(generation)
+            ),
+        );
+    }
+
+    /// Reads the failures selected by the end of a query, after its `FROM` clause.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - A reference to the `Database` the failures are recorded in.
+    /// * `filter` - A string slice that holds the `WHERE` and `ORDER BY` clauses of the query.
+    fn read_matching(database: &Database, filter: &str) -> Result<Vec<Self>> {
+        let mut statement = database.prepare(&format!(
+            "SELECT url, error_kind, message, attempt_count, last_attempt, generation, batch_id, run_id FROM fetch_errors {}",
+            filter
+        ))?;
+
+        let mut failures = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let last_attempt = statement
+                .read::<String, usize>(4)
+                .context("Failed to read last_attempt from the database")?;
+            failures.push(Self {
+                url: statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read url from the database")?,
+                error_kind: statement
+                    .read::<String, usize>(1)
+                    .context("Failed to read error_kind from the database")?,
+                message: statement
+                    .read::<String, usize>(2)
+                    .context("Failed to read message from the database")?,
+                attempt_count: statement
+                    .read::<i64, usize>(3)
+                    .context("Failed to read attempt_count from the database")?
+                    as u64,
+                last_attempt: DateTime::parse_from_rfc3339(&last_attempt)
+                    .context("Failed to parse last_attempt as RFC 3339")?
+                    .with_timezone(&Utc),
+                generation: statement
+                    .read::<Option<i64>, usize>(5)
+                    .context("Failed to read generation from the database")?
+                    .map(|generation| generation as u64),
+                batch_id: statement
+                    .read::<Option<i64>, usize>(6)
+                    .context("Failed to read batch_id from the database")?
+                    .map(|batch_id| batch_id as u64),
+                run_id: statement
+                    .read::<Option<i64>, usize>(7)
+                    .context("Failed to read run_id from the database")?,
+            });
+        }
+
+        return Ok(failures);
+    }
+
+    /// Counts failures by a key, the key with the most failures first.
+    ///
+    /// ```
+    /// use chrono::Utc;
+    /// use rustle::{ErrorCategory, FetchFailure};
+    ///
+    /// let failure = |url: &str, category: ErrorCategory| {
+    ///     FetchFailure::new(url, category, "", Utc::now())
+    /// };
+    /// let failures = [
+    ///     failure("https://a.example/1", ErrorCategory::Timeout),
+    ///     failure("https://b.example/", ErrorCategory::Connection),
+    ///     failure("https://a.example/2", ErrorCategory::Connection),
+    /// ];
+    ///
+    /// let by_kind = FetchFailure::count_by(&failures, |f| Some(f.error_kind.clone()));
+    /// assert_eq!(by_kind, [("connection".to_string(), 2), ("timeout".to_string(), 1)]);
+    ///
+    /// let by_host = FetchFailure::count_by(&failures, FetchFailure::host);
+    /// assert_eq!(by_host[0], ("a.example".to_string(), 2));
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `failures` - The failures to count.
+    /// * `key` - The function that gets the key of a failure, or `None` to leave it out.
+    ///
+    /// # Returns
+    ///
+    /// The number of failures of each key, sorted by count and then by key.
+    pub fn count_by(
+        failures: &[Self],
+        key: impl Fn(&Self) -> Option<String>,
+    ) -> Vec<(String, u64)> {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for failure in failures {
+            if let Some(key) = key(failure) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        return counts;
+    }
+
+    /// Gets the host of the URL that could not be fetched, or `None` if it doesn't parse.
+    pub fn host(&self) -> Option<String> {
+        return Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::charset;
+use crate::cookies::CookieJar;
+use anyhow::{bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use url::Url;
+
+/// Sends the HTTP requests of a crawl, so that they can be served by something else than the
+/// network, such as the canned pages of a `MapFetcher` in tests.
+///
+/// A fetcher sends a single request and never follows redirects itself: the crawler follows
+/// them, adds the configured headers and records the response times. Every request of a blocking
+/// crawl goes through the fetcher of the `Crawler`, as do the robots.txt files, sitemaps and
+/// feeds of an async crawl, whose pages are fetched with its async client.
+///
+/// ```
+/// use rustle::{Config, CrawlerBuilder, MapFetcher, MemoryStorage};
+/// use std::sync::Arc;
+///
+/// let fetcher = Arc::new(
+///     MapFetcher::new()
+///         .page("https://example.com/", r#"<a href="/about">About</a>"#)
+///         .page("https://example.com/about", "<p>About us</p>"),
+/// );
+/// let crawler = CrawlerBuilder::new()
+///     .origin("https://example.com/")
+///     .depth(1)
+///     .storage(Box::new(MemoryStorage::new()))
+///     .fetcher(Box::new(fetcher.clone()))
+///     .build()?;
+/// let report = crawler.crawl(false)?;
+/// assert_eq!(report.pages_fetched, 2);
+/// assert!(fetcher.requested("https://example.com/about"));
+/// # Ok::<(), rustle::Error>(())
+/// ```
+pub trait Fetcher {
+    /// Sends a request, without following redirects.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A reference to the `FetchRequest` to send.
+    ///
+    /// # Returns
+    ///
+    /// The `FetchResponse` to the request, whatever its status.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no response could be received.
+    fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse>;
+}
+
+impl<F: Fetcher + ?Sized> Fetcher for Arc<F> {
+    fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        return self.as_ref().fetch(request);
+    }
+}
+
+/// Represents a request sent by a `Fetcher`.
+pub struct FetchRequest {
+    /// The method of the request.
+    pub method: Method,
+    /// The URL requested.
+    pub url: Url,
+    /// The headers of the request, besides the `User-Agent` of the crawl.
+    pub headers: HeaderMap,
+}
+
+/// Represents the response to a `FetchRequest`.
+pub struct FetchResponse {
+    /// The URL the response came from.
+    pub url: Url,
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response, read as it arrives so that it can be cut short.
+    pub body: Box<dyn Read + Send>,
+}
+
+impl FetchResponse {
+    /// Creates a `FetchResponse` whose body is already known.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL the response came from.
+    /// * `status` - The status code of the response.
+    /// * `headers` - The headers of the response.
+    /// * `body` - The bytes of the body.
+    pub fn new(url: Url, status: StatusCode, headers: HeaderMap, body: Vec<u8>) -> Self {
+        return FetchResponse {
+            url,
+            status,
+            headers,
+            body: Box::new(Cursor::new(body)),
+        };
+    }
+
+    /// Turns a response with an error status into an error.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the status is a client or server error.
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.status.is_client_error() || self.status.is_server_error() {
+            bail!("HTTP status {} for {}", self.status, self.url);
+        }
+        return Ok(self);
+    }
+
+    /// Reads the whole body.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the body can't be read.
+    pub fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.body
+            .read_to_end(&mut body)
+            .context("Failed to read the response")?;
+        return Ok(body);
+    }
+
+    /// Reads the whole body as text, decoded from the charset of its `Content-Type`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the body can't be read.
+    pub fn text(self) -> Result<String> {
+        let content_type = self
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = self.bytes()?;
+        return Ok(charset::decode(&body, content_type.as_deref()).0);
+    }
+}
+
+/// The `Fetcher` of a crawl unless another is given, which sends requests over the network with
+/// reqwest.
+///
+/// Its clients are built on the first request rather than along with it, as the blocking client
+/// of reqwest can't be built from an async context.
+pub struct ReqwestFetcher {
+    /// The settings both clients are built with.
+    settings: ClientSettings,
+    /// The hosts whose invalid TLS certificates are accepted.
+    trusted_hosts: Vec<String>,
+    /// The client of every request, or the error it failed to build with.
+    client: OnceLock<Result<reqwest::blocking::Client, String>>,
+    /// The client that accepts invalid TLS certificates, used for `trusted_hosts`.
+    relaxed_client: OnceLock<Result<reqwest::blocking::Client, String>>,
+}
+
+/// The settings of the reqwest clients of a crawl.
+#[derive(Clone)]
+pub(crate) struct ClientSettings {
+    /// The `User-Agent` header of every request.
+    pub(crate) user_agent: String,
+    /// The time a request is given to complete, body included.
+    pub(crate) timeout: Duration,
+    /// Whether invalid TLS certificates are accepted from every host.
+    pub(crate) accept_invalid_certs: bool,
+    /// The extra root certificates trusted for every host.
+    pub(crate) root_certs: Vec<reqwest::Certificate>,
+    /// The proxy every request goes through, or `None` for the proxy environment variables.
+    pub(crate) proxy: Option<reqwest::Proxy>,
+    /// The cookie store shared by the clients of the crawl, if cookies are kept.
+    pub(crate) cookie_jar: Option<Arc<CookieJar>>,
+}
+
+impl ClientSettings {
+    /// Builds a blocking client, leaving redirects to the crawler so that loops are caught.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_invalid_certs` - Whether invalid TLS certificates are accepted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client can't be built.
+    pub(crate) fn blocking_client(
+        &self,
+        accept_invalid_certs: bool,
+    ) -> Result<reqwest::blocking::Client> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(accept_invalid_certs);
+        for certificate in &self.root_certs {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(cookie_jar) = &self.cookie_jar {
+            builder = builder.cookie_provider(cookie_jar.clone());
+        }
+        return builder.build().context("Failed to build the HTTP client");
+    }
+
+    /// Builds an async client, like `blocking_client`.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_invalid_certs` - Whether invalid TLS certificates are accepted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client can't be built.
+    pub(crate) fn async_client(&self, accept_invalid_certs: bool) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(accept_invalid_certs);
+        for certificate in &self.root_certs {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(cookie_jar) = &self.cookie_jar {
+            builder = builder.cookie_provider(cookie_jar.clone());
+        }
+        return builder.build().context("Failed to build the HTTP client");
+    }
+}
+
+impl ReqwestFetcher {
+    /// Creates a `ReqwestFetcher`, without building its clients yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The settings its clients are built with.
+    /// * `trusted_hosts` - The hosts whose invalid TLS certificates are accepted, in ASCII.
+    pub(crate) fn new(settings: ClientSettings, trusted_hosts: Vec<String>) -> Self {
+        return ReqwestFetcher {
+            settings,
+            trusted_hosts,
+            client: OnceLock::new(),
+            relaxed_client: OnceLock::new(),
+        };
+    }
+
+    /// Gets the client that sends a request to a URL, building it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A reference to the URL requested.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client can't be built.
+    fn client(&self, url: &Url) -> Result<&reqwest::blocking::Client> {
+        let trusted = url.scheme() == "https"
+            && self
+                .trusted_hosts
+                .iter()
+                .any(|host| Some(host.as_str()) == url.host_str());
+        let client = if trusted {
+            self.relaxed_client.get_or_init(|| {
+                return self
+                    .settings
+                    .blocking_client(true)
+                    .map_err(|e| format!("{:#}", e));
+            })
+        } else {
+            self.client.get_or_init(|| {
+                return self
+                    .settings
+                    .blocking_client(self.settings.accept_invalid_certs)
+                    .map_err(|e| format!("{:#}", e));
+            })
+        };
+        return client.as_ref().map_err(|e| anyhow::anyhow!("{}", e));
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        let response = self
+            .client(&request.url)?
+            .request(request.method.clone(), request.url.clone())
+            .headers(request.headers.clone())
+            .send()?;
+        return Ok(FetchResponse {
+            url: response.url().clone(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: Box::new(response),
+        });
+    }
+}
+
+/// A `Fetcher` that serves canned responses from memory, and remembers every request, to test
+/// crawls without a network.
+///
+/// URLs without a canned response are answered with an empty `404`, and `HEAD` requests with the
+/// headers of the canned response but no body.
+#[derive(Default)]
+pub struct MapFetcher {
+    /// The canned responses, by URL.
+    responses: HashMap<String, (StatusCode, HeaderMap, Vec<u8>)>,
+    /// The messages of the canned failures, by URL.
+    failures: HashMap<String, String>,
+    /// The method and URL of every request received, in order.
+    requests: Mutex<Vec<(Method, String)>>,
+}
+
+impl MapFetcher {
+    /// Creates a `MapFetcher` without any canned response.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Serves an HTML page at a URL with a `200` status.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the page.
+    /// * `html` - A string slice that holds the HTML of the page.
+    pub fn page(self, url: &str, html: &str) -> Self {
+        return self.response(
+            url,
+            200,
+            &[("content-type", "text/html; charset=utf-8")],
+            html.as_bytes(),
+        );
+    }
+
+    /// Serves a response at a URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the response.
+    /// * `status` - The status code of the response.
+    /// * `headers` - The names and values of the headers of the response.
+    /// * `body` - The bytes of the body of the response.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the URL doesn't parse, or the status code or a header is
+    /// invalid.
+    pub fn response(
+        mut self,
+        url: &str,
+        status: u16,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Self {
+        let url = Url::parse(url).expect("Invalid URL of a canned response");
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .expect("Invalid header name of a canned response"),
+                HeaderValue::from_str(value).expect("Invalid header value of a canned response"),
+            );
+        }
+        let status = StatusCode::from_u16(status).expect("Invalid status of a canned response");
+        self.responses
+            .insert(url.to_string(), (status, header_map, body.to_vec()));
+        return self;
+    }
+
+    /// Fails every request for a URL, as if the server couldn't be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL whose requests fail.
+    /// * `message` - A string slice that holds the message of the error.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the URL doesn't parse.
+    pub fn failure(mut self, url: &str, message: &str) -> Self {
+        let url = Url::parse(url).expect("Invalid URL of a canned failure");
+        self.failures.insert(url.to_string(), message.to_string());
+        return self;
+    }
+
+    /// Gets the method and URL of every request received so far, in order.
+    pub fn requests(&self) -> Vec<(Method, String)> {
+        return self.requests.lock().unwrap().clone();
+    }
+
+    /// Checks whether a URL was requested.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    pub fn requested(&self, url: &str) -> bool {
+        return self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(_, requested)| requested == url);
+    }
+}
+
+impl Fetcher for MapFetcher {
+    fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.method.clone(), request.url.to_string()));
+        if let Some(message) = self.failures.get(request.url.as_str()) {
+            bail!("{}", message);
+        }
+        let (status, headers, body) = match self.responses.get(request.url.as_str()) {
+            Some((status, headers, body)) => (*status, headers.clone(), body.clone()),
+            None => (StatusCode::NOT_FOUND, HeaderMap::new(), Vec::new()),
+        };
+        let body = if request.method == Method::HEAD {
+            Vec::new()
+        } else {
+            body
+        };
+        return Ok(FetchResponse::new(
+            request.url.clone(),
+            status,
+            headers,
+            body,
+        ));
+    }
+}
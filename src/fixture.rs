@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// The largest body of a request a `FixtureServer` reads. Requests announcing a longer one are
+/// dropped unanswered, without allocating their body.
+const MAX_REQUEST_BODY_BYTES: usize = 1024 * 1024;
+
+/// Represents a request received by a `FixtureServer`.
+#[derive(Clone, Debug)]
+pub struct FixtureRequest {
+    /// The method of the request, such as `GET`.
+    pub method: String,
+    /// The path of the request, along with its query.
+    pub path: String,
+    /// The headers of the request, with their names lowercased.
+    pub headers: Vec<(String, String)>,
+    /// The body of the request, empty if it had none.
+    pub body: Vec<u8>,
+}
+
+impl FixtureRequest {
+    /// Gets the value of a header of the request, if it was sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name of the header, in lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self
+            .headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str());
+    }
+}
+
+/// Represents the answer of a `FixtureServer` to a request.
+#[derive(Clone, Debug)]
+pub struct FixtureResponse {
+    /// The status code of the response.
+    pub status: u16,
+    /// The headers of the response, besides `Content-Length` and `Connection`.
+    pub headers: Vec<(String, String)>,
+    /// The body of the response, left out of the answers to `HEAD` requests.
+    pub body: Vec<u8>,
+}
+
+impl FixtureResponse {
+    /// Creates a response without any header.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The status code of the response.
+    /// * `body` - The body of the response.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        return FixtureResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        };
+    }
+
+    /// Creates a response holding an HTML page encoded in UTF-8.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The status code of the response.
+    /// * `html` - The HTML of the page.
+    pub fn html(status: u16, html: impl Into<Vec<u8>>) -> Self {
+        return Self::new(status, html).header("Content-Type", "text/html; charset=utf-8");
+    }
+
+    /// Adds a header to the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name of the header.
+    /// * `value` - A string slice that holds the value of the header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        return self;
+    }
+}
+
+/// Records the requests a `FixtureServer` received, and how many it handled at once.
+#[derive(Default)]
+struct FixtureLoad {
+    /// The requests received so far, in the order they were read.
+    requests: Mutex<Vec<FixtureRequest>>,
+    /// The number of requests read but not answered yet.
+    current: AtomicUsize,
+    /// The largest number of requests handled at once so far.
+    peak: AtomicUsize,
+}
+
+/// An HTTP server of fixture pages, running in the background of the process on an ephemeral
+/// port of the loopback interface.
+///
+/// Every connection is answered on a thread of its own, with a single response built by the
+/// function the server was started with, so that a slow page holds up no other request. The
+/// server serves the `rustle self-test` command, the tests and the benchmarks alike. It stops
+/// accepting connections when it is dropped, letting the requests in flight finish.
+pub struct FixtureServer {
+    /// The scheme and address of the server, such as `http://127.0.0.1:1234`.
+    base: String,
+    /// The requests received so far, and the number handled at once.
+    load: Arc<FixtureLoad>,
+    /// Whether the server was asked to stop accepting connections.
+    stopping: Arc<AtomicBool>,
+    /// The thread accepting the connections, until the server is dropped.
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl FixtureServer {
+    /// Starts serving the responses built by a function, on a thread of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `respond` - The function building the response to every request. A request is counted
+    ///   as handled until the function returns, so it may wait to simulate a slow server.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no port can be bound.
+    pub fn start(
+        respond: impl Fn(&FixtureRequest) -> FixtureResponse + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").context("Failed to start the fixture server")?;
+        let address = listener
+            .local_addr()
+            .context("Failed to read the address of the fixture server")?;
+        let load = Arc::new(FixtureLoad::default());
+        let server_load = Arc::clone(&load);
+        let respond = Arc::new(respond);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let server_stopping = Arc::clone(&stopping);
+        let acceptor = std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if server_stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+                let (load, respond) = (Arc::clone(&server_load), Arc::clone(&respond));
+                std::thread::spawn(move || {
+                    let _ = answer(stream, &load, respond.as_ref());
+                });
+            }
+        });
+        return Ok(FixtureServer {
+            base: format!("http://{}", address),
+            load,
+            stopping,
+            acceptor: Some(acceptor),
+        });
+    }
+
+    /// Gets the scheme and address of the server, such as `http://127.0.0.1:1234`.
+    pub fn base(&self) -> &str {
+        return &self.base;
+    }
+
+    /// Gets the full URL of a path of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice that holds the path, starting with `/`.
+    pub fn url(&self, path: &str) -> String {
+        return format!("{}{}", self.base, path);
+    }
+
+    /// Gets the requests received so far, in the order they were read.
+    pub fn requests(&self) -> Vec<FixtureRequest> {
+        return self.load.requests.lock().unwrap().clone();
+    }
+
+    /// Counts the requests received so far for a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice that holds the path, along with its query.
+    pub fn requests_for(&self, path: &str) -> usize {
+        return self
+            .load
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.path == path)
+            .count();
+    }
+
+    /// Gets the largest number of requests the server handled at once so far.
+    pub fn peak(&self) -> usize {
+        return self.load.peak.load(Ordering::SeqCst);
+    }
+}
+
+/// Implements the `Drop` trait for the `FixtureServer` struct.
+///
+/// The accepting thread is woken up by a last connection of its own, after which it closes the
+/// listener and ends. The connections already accepted are answered on their own threads.
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let address = self.base.trim_start_matches("http://");
+        if TcpStream::connect(address).is_ok() {
+            if let Some(acceptor) = self.acceptor.take() {
+                let _ = acceptor.join();
+            }
+        }
+    }
+}
+
+/// Reads a request, and writes the response built for it.
+///
+/// # Arguments
+///
+/// * `stream` - The `TcpStream` of the connection.
+/// * `load` - A reference to the `FixtureLoad` the request is recorded in.
+/// * `respond` - The function building the response.
+fn answer(
+    mut stream: TcpStream,
+    load: &FixtureLoad,
+    respond: &(impl Fn(&FixtureRequest) -> FixtureResponse + ?Sized),
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let mut request = FixtureRequest {
+        method: parts.next().unwrap_or_default().to_string(),
+        path: parts.next().unwrap_or("/").to_string(),
+        headers,
+        body: Vec::new(),
+    };
+    let length = request
+        .header("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or_default();
+    if length > MAX_REQUEST_BODY_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Body of {} bytes exceeds the limit of {} bytes",
+                length, MAX_REQUEST_BODY_BYTES
+            ),
+        ));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    load.requests.lock().unwrap().push(request.clone());
+
+    // A request is counted until it is answered, as the client may send the next one as soon
+    // as it reads the answer
+    let current = load.current.fetch_add(1, Ordering::SeqCst) + 1;
+    load.peak.fetch_max(current, Ordering::SeqCst);
+    let response = respond(&request);
+    load.current.fetch_sub(1, Ordering::SeqCst);
+
+    let reason = reqwest::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Unknown");
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    stream.write_all(head.as_bytes())?;
+    if request.method != "HEAD" {
+        stream.write_all(&response.body)?;
+    }
+    return stream.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_servers_stop_accepting_connections() {
+        let server = FixtureServer::start(|_| FixtureResponse::html(200, "<p>Page</p>")).unwrap();
+        let address = server.base().trim_start_matches("http://").to_string();
+        let response = reqwest::blocking::get(server.url("/")).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(server.requests_for("/"), 1);
+
+        drop(server);
+        assert!(TcpStream::connect(&address).is_err());
+    }
+
+    #[test]
+    fn requests_announcing_huge_bodies_are_dropped_unanswered() {
+        let server = FixtureServer::start(|_| FixtureResponse::html(200, "<p>Page</p>")).unwrap();
+        let mut stream = TcpStream::connect(server.base().trim_start_matches("http://")).unwrap();
+        stream
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n")
+            .unwrap();
+
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer).unwrap();
+        assert!(answer.is_empty());
+        assert!(server.requests().is_empty());
+    }
+}
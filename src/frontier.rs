@@ -0,0 +1,231 @@
+use crate::config::Strategy;
+use crate::visited::VisitedSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use url::Url;
+
+/// The work queue of a crawl: the URLs it has discovered but not fetched yet, handed out in the
+/// order of its `Strategy`.
+///
+/// Every URL is queued at most once over the life of the frontier, however many pages link to it,
+/// and the depth it was first queued at is kept along with it. A URL that was taken stays pending
+/// until it is `finish`ed, so that the frontier can tell when a depth level is done, and when the
+/// whole crawl is.
+pub struct Frontier {
+    /// The order in which queued URLs are handed out.
+    strategy: Strategy,
+    /// The queued URLs, with the smallest key on top.
+    queue: BinaryHeap<Entry>,
+    /// Every URL that was ever queued, or marked as seen.
+    seen: VisitedSet,
+    /// The number of URLs queued so far for each host, for the `Priority` strategy.
+    host_counts: HashMap<String, u64>,
+    /// The number of URLs queued so far, which orders URLs that are otherwise equal.
+    pushed: u64,
+    /// The number of URLs of each depth that are queued, or were taken but aren't finished yet.
+    pending: BTreeMap<u64, usize>,
+}
+
+/// A queued URL.
+struct Entry {
+    /// The sort key of the URL, the smallest of which is handed out first.
+    key: (u64, u64, u64),
+    /// The URL.
+    url: String,
+    /// The depth the URL is fetched at.
+    depth: u64,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        return self.key == other.key;
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap, so the smallest key is made the greatest entry
+        return other.key.cmp(&self.key);
+    }
+}
+
+impl Frontier {
+    /// Creates an empty `Frontier`.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The `Strategy` the URLs are handed out in.
+    /// * `expected_size` - The number of URLs the crawl is expected to visit, if it is known,
+    ///   which decides how the URLs already queued are remembered.
+    pub fn new(strategy: Strategy, expected_size: Option<u64>) -> Self {
+        return Frontier {
+            strategy,
+            queue: BinaryHeap::new(),
+            seen: VisitedSet::with_expected_size(expected_size),
+            host_counts: HashMap::new(),
+            pushed: 0,
+            pending: BTreeMap::new(),
+        };
+    }
+
+    /// Marks a URL as seen without queuing it, so that it is never queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    pub fn mark_seen(&mut self, url: &str) {
+        self.seen.insert(url);
+    }
+
+    /// Queues a URL, unless it was already queued or marked as seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    /// * `depth` - The depth the URL is fetched at.
+    ///
+    /// # Returns
+    ///
+    /// Whether the URL was queued.
+    pub fn push(&mut self, url: &str, depth: u64) -> bool {
+        if !self.seen.insert(url) {
+            return false;
+        }
+
+        let order = self.pushed;
+        self.pushed += 1;
+        let key = match self.strategy {
+            Strategy::Bfs => (depth, 0, order),
+            Strategy::Dfs => (0, 0, u64::MAX - order),
+            Strategy::Priority => {
+                let host = Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_default();
+                let count = self.host_counts.entry(host).or_insert(0);
+                *count += 1;
+                (depth, *count - 1, order)
+            }
+        };
+        self.queue.push(Entry {
+            key,
+            url: url.to_string(),
+            depth,
+        });
+        *self.pending.entry(depth).or_insert(0) += 1;
+        return true;
+    }
+
+    /// Takes the next URL to fetch.
+    ///
+    /// # Returns
+    ///
+    /// The URL along with the depth it is fetched at, or `None` if no URL is queued.
+    pub fn pop(&mut self) -> Option<(String, u64)> {
+        return self.queue.pop().map(|entry| (entry.url, entry.depth));
+    }
+
+    /// Marks a URL that was taken as done, once its links are queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The depth the URL was taken at.
+    ///
+    /// # Returns
+    ///
+    /// The depth levels this finished, shallowest first: those with no URL pending left, and no
+    /// shallower URL pending either.
+    pub fn finish(&mut self, depth: u64) -> Vec<u64> {
+        if let Some(count) = self.pending.get_mut(&depth) {
+            *count = count.saturating_sub(1);
+        }
+
+        let mut finished = Vec::new();
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.get() > 0 {
+                break;
+            }
+            finished.push(entry.remove_entry().0);
+        }
+        return finished;
+    }
+
+    /// Checks whether every URL that was queued is finished.
+    pub fn is_done(&self) -> bool {
+        return self.pending.is_empty();
+    }
+
+    /// Gets the number of queued URLs.
+    pub fn len(&self) -> usize {
+        return self.queue.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_handed_out_in_strategy_order() {
+        let mut frontier = Frontier::new(Strategy::Priority, None);
+        frontier.push("https://a.example/1", 1);
+        frontier.push("https://a.example/2", 1);
+        frontier.push("https://b.example/1", 1);
+        frontier.push("https://a.example/", 0);
+        assert!(!frontier.push("https://a.example/1", 1));
+
+        // Shallower URLs first, then the hosts with the fewest URLs queued before them
+        assert_eq!(frontier.pop(), Some(("https://a.example/".to_string(), 0)));
+        assert_eq!(frontier.pop(), Some(("https://a.example/1".to_string(), 1)));
+        assert_eq!(frontier.pop(), Some(("https://b.example/1".to_string(), 1)));
+        assert_eq!(frontier.pop(), Some(("https://a.example/2".to_string(), 1)));
+        assert_eq!(frontier.pop(), None);
+    }
+
+    #[test]
+    fn depths_finish_with_their_last_url() {
+        let mut frontier = Frontier::new(Strategy::Bfs, None);
+        frontier.push("https://example.com/a", 1);
+        frontier.push("https://example.com/b", 1);
+
+        let (_, depth) = frontier.pop().unwrap();
+        frontier.push("https://example.com/a/1", 2);
+        assert!(frontier.finish(depth).is_empty());
+
+        // Depth 1 is done once its last URL is, and the crawl once every URL is
+        let (_, depth) = frontier.pop().unwrap();
+        assert_eq!(frontier.finish(depth), vec![1]);
+        let (_, depth) = frontier.pop().unwrap();
+        assert!(!frontier.is_done());
+        assert_eq!(frontier.finish(depth), vec![2]);
+        assert!(frontier.is_done());
+    }
+
+    #[test]
+    fn colliding_urls_are_skipped_without_stalling_the_crawl() {
+        let mut frontier = Frontier::new(Strategy::Bfs, Some(50_000_000));
+        // As if another URL with the same hash as the second page had been visited
+        frontier.seen = VisitedSet::Hashed(
+            [VisitedSet::hash("https://example.com/second")]
+                .into_iter()
+                .collect(),
+        );
+        assert!(frontier.push("https://example.com/first", 1));
+        assert!(!frontier.push("https://example.com/second", 1));
+
+        // The skipped URL is never waited for, so its depth finishes with the URL that was queued
+        let (url, depth) = frontier.pop().unwrap();
+        assert_eq!(url, "https://example.com/first");
+        assert_eq!(frontier.pop(), None);
+        assert_eq!(frontier.finish(depth), vec![1]);
+        assert!(frontier.is_done());
+    }
+}
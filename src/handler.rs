@@ -0,0 +1,66 @@
+use select::document::Document;
+use select::predicate::Name;
+use std::collections::HashSet;
+use url::Url;
+
+/// A hook run on every HTML page the crawler fetches, for custom processing such as collecting
+/// product prices.
+///
+/// Handlers are registered with `CrawlerBuilder::handler`, and run from the worker threads once
+/// the links of a page have been extracted, so they must be `Sync`. A handler that panics is
+/// logged and skipped for that page, without aborting the crawl.
+///
+/// ```no_run
+/// use rustle::{CrawlerBuilder, PageHandler};
+/// use std::collections::HashSet;
+/// use url::Url;
+///
+/// struct PriceCollector;
+///
+/// impl PageHandler for PriceCollector {
+///     fn on_page(&self, url: &Url, _status: u16, html: &str, _links: &HashSet<String>) {
+///         if html.contains("class=\"price\"") {
+///             println!("{} lists a price", url);
+///         }
+///     }
+/// }
+///
+/// let crawler = CrawlerBuilder::new()
+///     .origin("https://example.com")
+///     .handler(Box::new(PriceCollector))
+///     .build()?;
+/// crawler.crawl(false)?;
+/// # Ok::<(), rustle::Error>(())
+/// ```
+pub trait PageHandler {
+    /// Processes a fetched HTML page.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL the page was requested from.
+    /// * `status` - The status code of the response.
+    /// * `html` - A string slice that holds the decoded HTML of the page.
+    /// * `links` - The links extracted from the page, including those of `nofollow` pages.
+    fn on_page(&self, url: &Url, status: u16, html: &str, links: &HashSet<String>);
+}
+
+/// A `PageHandler` that prints the URL and title of every page to stdout, one per line and
+/// separated by a tab.
+pub struct TitlePrinter;
+
+impl PageHandler for TitlePrinter {
+    fn on_page(&self, url: &Url, _status: u16, html: &str, _links: &HashSet<String>) {
+        let document = Document::from(html);
+        let title = document
+            .find(Name("title"))
+            .next()
+            .map(|node| {
+                node.text()
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+        println!("{}\t{}", url, title);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::normalize::ascii_host;
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::BTreeMap;
+
+/// The domain pattern of the headers sent to every host.
+pub const ALL_HOSTS: &str = "*";
+
+/// The extra headers of the requests of a crawl, by the domain patterns of the hosts they are
+/// sent to, so that credentials meant for one site never reach the sites it links to.
+///
+/// A pattern is `*` for every host, `*.example.com` for the subdomains of `example.com`, or a
+/// host such as `staging.example.com` for that host alone. When several patterns match a host,
+/// the headers of the longest one win.
+#[derive(Default)]
+pub struct HeaderRules {
+    /// The domain patterns along with their headers, the least specific first.
+    rules: Vec<(String, HeaderMap)>,
+}
+
+impl HeaderRules {
+    /// Parses the `headers` table of a config.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - A reference to the header names and values, by domain pattern.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a header name or value is invalid.
+    pub fn new(table: &BTreeMap<String, BTreeMap<String, String>>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (pattern, headers) in table {
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name '{}' for {}", name, pattern))?;
+                let mut value = HeaderValue::from_str(value)
+                    .with_context(|| format!("Invalid value of header {} for {}", name, pattern))?;
+                value.set_sensitive(true);
+                header_map.insert(name, value);
+            }
+            rules.push((normalize_pattern(pattern), header_map));
+        }
+        rules.sort_by_key(|(pattern, _)| pattern.len());
+        return Ok(HeaderRules { rules });
+    }
+
+    /// Gets the extra headers of the requests sent to a host.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string slice that holds the host, in ASCII.
+    pub fn for_host(&self, host: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (pattern, pattern_headers) in &self.rules {
+            if matches(pattern, host) {
+                for (name, value) in pattern_headers {
+                    headers.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        return headers;
+    }
+}
+
+/// Checks whether a domain pattern of the `headers` table is well-formed.
+///
+/// # Arguments
+///
+/// * `pattern` - A string slice that holds the domain pattern.
+pub(crate) fn is_valid_pattern(pattern: &str) -> bool {
+    let domain = pattern.strip_prefix("*.").unwrap_or(pattern);
+    return pattern == ALL_HOSTS
+        || (!domain.is_empty()
+            && !domain.contains(['*', '/', ':', ' '])
+            && !domain.starts_with('.'));
+}
+
+/// Lists the names of some headers with their values redacted, to log the headers of a request
+/// without leaking the credentials they may hold.
+///
+/// # Arguments
+///
+/// * `headers` - A reference to the headers.
+pub fn redacted(headers: &HeaderMap) -> String {
+    return headers
+        .keys()
+        .map(|name| format!("{}: [redacted]", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+}
+
+/// Writes the domain of a pattern in ASCII and lowercase, as the hosts of parsed URLs are.
+///
+/// # Arguments
+///
+/// * `pattern` - A string slice that holds the domain pattern.
+fn normalize_pattern(pattern: &str) -> String {
+    if pattern == ALL_HOSTS {
+        return pattern.to_string();
+    }
+    return match pattern.strip_prefix("*.") {
+        Some(domain) => format!("*.{}", ascii_host(domain)),
+        None => ascii_host(pattern),
+    };
+}
+
+/// Checks whether a host matches a normalized domain pattern.
+///
+/// # Arguments
+///
+/// * `pattern` - A string slice that holds the domain pattern.
+/// * `host` - A string slice that holds the host.
+fn matches(pattern: &str, host: &str) -> bool {
+    if pattern == ALL_HOSTS {
+        return true;
+    }
+    return match pattern.strip_prefix('*') {
+        Some(suffix) => host.ends_with(suffix),
+        None => host == pattern,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_are_picked_by_the_most_specific_pattern() {
+        use std::collections::BTreeMap;
+
+        let table = BTreeMap::from([
+            (
+                "*".to_string(),
+                BTreeMap::from([("Accept-Language".to_string(), "en".to_string())]),
+            ),
+            (
+                "staging.example.com".to_string(),
+                BTreeMap::from([("X-Staging-Key".to_string(), "secret".to_string())]),
+            ),
+        ]);
+        let rules = HeaderRules::new(&table).unwrap();
+
+        let headers = rules.for_host("staging.example.com");
+        assert_eq!(headers["x-staging-key"], "secret");
+        assert_eq!(headers["accept-language"], "en");
+
+        let headers = rules.for_host("example.com");
+        assert!(!headers.contains_key("x-staging-key"));
+        assert_eq!(headers["accept-language"], "en");
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        assert_eq!(redacted(&headers), "authorization: [redacted]");
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use tokio::sync::Notify;
+
+/// Limits the number of requests in flight to each host, so that a depth level made mostly of
+/// the URLs of one host doesn't open dozens of connections to that server at once.
+///
+/// Requests over the limit wait for a permit of their host to be released, from a worker thread
+/// with `acquire` or from an async crawl with `acquire_async`.
+pub struct HostLimiter {
+    /// The maximum number of requests in flight to a single host.
+    max_per_host: usize,
+    /// The number of requests in flight to every host with at least one.
+    in_flight: Mutex<HashMap<String, usize>>,
+    /// Wakes the worker threads waiting for a permit once one is released.
+    released: Condvar,
+    /// Wakes the async fetches waiting for a permit once one is released.
+    released_async: Notify,
+}
+
+/// A permit to send requests to a host, released when it is dropped.
+pub struct HostPermit<'a> {
+    /// The limiter the permit was acquired from.
+    limiter: &'a HostLimiter,
+    /// The host the permit is for.
+    host: String,
+}
+
+impl HostLimiter {
+    /// Creates a new `HostLimiter` with no requests in flight.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_per_host` - The maximum number of requests in flight to a single host.
+    pub fn new(max_per_host: usize) -> Self {
+        return HostLimiter {
+            max_per_host,
+            in_flight: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+            released_async: Notify::new(),
+        };
+    }
+
+    /// Acquires a permit for a host, blocking the thread until one is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string slice that holds the host about to be requested.
+    pub fn acquire(&self, host: &str) -> HostPermit<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        loop {
+            let count = in_flight.entry(host.to_string()).or_insert(0);
+            if *count < self.max_per_host {
+                *count += 1;
+                return self.permit(host);
+            }
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+    }
+
+    /// Acquires a permit for a host, waiting without blocking the thread until one is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string slice that holds the host about to be requested.
+    pub async fn acquire_async(&self, host: &str) -> HostPermit<'_> {
+        loop {
+            // Register for the next release before checking, so that it can't be missed
+            let released = self.released_async.notified();
+            if let Some(permit) = self.try_acquire(host) {
+                return permit;
+            }
+            released.await;
+        }
+    }
+
+    /// Acquires a permit for a host if one is available right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string slice that holds the host about to be requested.
+    fn try_acquire(&self, host: &str) -> Option<HostPermit<'_>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(host.to_string()).or_insert(0);
+        if *count >= self.max_per_host {
+            return None;
+        }
+        *count += 1;
+        return Some(self.permit(host));
+    }
+
+    /// Wraps a permit that was just counted for a host.
+    fn permit(&self, host: &str) -> HostPermit<'_> {
+        return HostPermit {
+            limiter: self,
+            host: host.to_string(),
+        };
+    }
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.host) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.host);
+            }
+        }
+        drop(in_flight);
+        self.limiter.released.notify_all();
+        self.limiter.released_async.notify_waiters();
+    }
+}
@@ -0,0 +1,112 @@
+//! Rustle is a parallel webcrawler that stores the sites it crawls in SQLite.
+//!
+//! A crawl is configured with a [`Config`], either read from the config file or built in code,
+//! and run by a [`Crawler`], which stores every fetched page as a [`Site`] and the robots.txt of
+//! every domain as a [`Domain`] in its [`Database`]. Once finished, it returns a
+//! [`CrawlReport`] of what was fetched and what failed.
+//!
+//! Errors are reported as [`anyhow::Error`], re-exported as [`Error`] along with [`Result`].
+//!
+//! ```no_run
+//! use rustle::{Config, Crawler};
+//!
+//! let config = Config {
+//!     origin_urls: vec!["https://example.com".to_string()],
+//!     depth: 2,
+//!     database: "example".to_string(),
+//!     ..Default::default()
+//! };
+//! let report = Crawler::new(config)?.crawl(false)?;
+//! println!("{}", report);
+//! # Ok::<(), rustle::Error>(())
+//! ```
+
+mod analyze;
+mod builder;
+mod challenge;
+mod charset;
+mod cli;
+mod config;
+mod cookies;
+mod crawl_run;
+mod database;
+mod decision;
+mod domain;
+mod domain_table;
+mod dry_run;
+mod explain;
+mod export;
+mod feed;
+mod fetch_failure;
+mod fetcher;
+#[cfg(any(test, feature = "fixture"))]
+mod fixture;
+mod frontier;
+mod handler;
+mod headers;
+mod host_limit;
+mod logging;
+mod metrics;
+mod normalize;
+mod output;
+mod progress;
+mod proxy;
+mod public_suffix;
+mod redirect;
+mod render;
+mod report;
+mod resource;
+mod robots;
+mod robots_history;
+#[cfg(feature = "self-test")]
+mod selftest;
+mod session;
+mod site;
+mod site_table;
+mod sitemap;
+mod spider;
+mod storage;
+mod trap;
+mod visited;
+mod webhook;
+mod wizard;
+
+pub use anyhow::{Error, Result};
+pub use builder::CrawlerBuilder;
+pub use config::{Config, LogFormat, Overrides, Scope, StorageKind, Strategy};
+pub use cookies::SeedCookie;
+pub use database::{Database, MergeCounts, MergeReport, MEMORY_DATABASE, SCHEMA_VERSION};
+pub use decision::{SkipDecision, SkipReason};
+pub use domain::Domain;
+pub use dry_run::{DryRun, LinkOutcome};
+pub use export::ExportFormat;
+pub use fetch_failure::FetchFailure;
+pub use fetcher::{FetchRequest, FetchResponse, Fetcher, MapFetcher};
+pub use handler::{PageHandler, TitlePrinter};
+pub use metrics::MetricsConfig;
+pub use proxy::ProxyConfig;
+pub use render::JsWeights;
+pub use report::{
+    render_report, CrawlReport, ErrorCategory, FetchError, LatencyStats, ReportFormat,
+};
+pub use resource::{FetchPolicies, FetchPolicy, ResourceKind};
+pub use robots::RobotsDiff;
+pub use session::{CrawlSession, SessionStatus};
+pub use site::{BrokenLink, DomainSummary, Site};
+pub use spider::Crawler;
+pub use storage::{JsonlStorage, MemoryStorage, Storage};
+pub use trap::TrapRule;
+pub use webhook::{WebhookEvent, WebhookPayload};
+
+// The entry points of the `rustle` binary and of the integration tests running it, which aren't
+// part of the library API
+#[doc(hidden)]
+pub use cli::{crawl as cli_crawl, run as run_command, run_crawl, Cli};
+#[doc(hidden)]
+pub use logging::init as init_logging;
+
+// The HTTP server of fixture pages shared by the self-test, the integration tests and the
+// benchmarks
+#[cfg(feature = "fixture")]
+#[doc(hidden)]
+pub use fixture::{FixtureRequest, FixtureResponse, FixtureServer};
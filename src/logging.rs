@@ -0,0 +1,270 @@
+use crate::config::{Config, LogFormat};
+use crate::progress::ProgressLogger;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use env_logger::filter::{Builder, Filter};
+use env_logger::Target;
+use log::kv::{Error, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Number};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The number of rotated log files kept next to `log_file`, as `.1` (the newest) to `.9`.
+pub const MAX_ROTATED_LOGS: u32 = 9;
+
+/// A logger that prints every record as a single line of JSON, with the key-value pairs of the
+/// record as fields next to its message, such as
+/// `{"timestamp":"...","level":"DEBUG","target":"rustle::spider","message":"...","url":"..."}`.
+struct JsonLogger {
+    /// The `RUST_LOG` filter of the records printed.
+    filter: Filter,
+    /// Where the lines are printed.
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        return self.filter.enabled(metadata);
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+
+        let mut line = Map::new();
+        line.insert("timestamp".to_string(), Utc::now().to_rfc3339().into());
+        line.insert("level".to_string(), record.level().as_str().into());
+        line.insert("target".to_string(), record.target().into());
+        line.insert("message".to_string(), record.args().to_string().into());
+        let _ = record.key_values().visit(&mut JsonFields(&mut line));
+
+        // Write the line at once, so that a log file isn't rotated in the middle of it
+        let line = format!("{}\n", serde_json::Value::Object(line));
+        let _ = self.output.lock().unwrap().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = self.output.lock().unwrap().flush();
+    }
+}
+
+/// Adds the key-value pairs of a record to its JSON line.
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        // Keep numbers and booleans as such, and write anything else as a string
+        let value = if let Some(number) = value.to_u64() {
+            number.into()
+        } else if let Some(number) = value.to_i64() {
+            number.into()
+        } else if let Some(number) = value.to_f64().and_then(Number::from_f64) {
+            number.into()
+        } else if let Some(boolean) = value.to_bool() {
+            boolean.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        return Ok(());
+    }
+}
+
+/// A logger that prints every record with each of several loggers, such as on the console and
+/// in the log file.
+struct TeeLogger {
+    /// The loggers every record is printed with.
+    loggers: Vec<Box<dyn Log>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        return self.loggers.iter().any(|logger| logger.enabled(metadata));
+    }
+
+    fn log(&self, record: &Record) {
+        for logger in &self.loggers {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        for logger in &self.loggers {
+            logger.flush();
+        }
+    }
+}
+
+/// A log file that is rotated once it grows past a size cap: the file is renamed to `.1`, the
+/// previous `.1` to `.2`, and so on up to `MAX_ROTATED_LOGS`, and a new file is started.
+struct RotatingFile {
+    /// The path of the log file.
+    path: PathBuf,
+    /// The size past which the file is rotated, or `None` to never rotate it.
+    max_bytes: Option<u64>,
+    /// The open log file.
+    file: File,
+    /// The size of the open log file.
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens a log file for appending, creating it and its parent directories if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path of the log file.
+    /// * `max_bytes` - The size past which the file is rotated, or `None` to never rotate it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file or its parent directories cannot be
+    /// created.
+    fn open(path: &Path, max_bytes: Option<u64>) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        return Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_bytes,
+            file,
+            size,
+        });
+    }
+
+    /// Gets the path of a rotated log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The number of the rotated file, 1 being the newest.
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        return PathBuf::from(path);
+    }
+
+    /// Moves the log file to `.1`, shifting the older rotated files up by one and dropping the
+    /// oldest, and starts a new log file.
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..MAX_ROTATED_LOGS).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        return Ok(());
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self
+            .max_bytes
+            .is_some_and(|max_bytes| self.size > 0 && self.size + buf.len() as u64 > max_bytes)
+        {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        return Ok(written);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.file.flush();
+    }
+}
+
+/// Builds a logger printing lines of the given format to an output.
+///
+/// # Arguments
+///
+/// * `format` - The `LogFormat` of the log lines.
+/// * `filters` - A string slice that holds the `RUST_LOG` filters.
+/// * `output` - Where the lines are printed, or `None` for stderr, in color when it is a
+///   terminal.
+///
+/// # Returns
+///
+/// The logger along with the most verbose level it prints.
+fn build_logger(
+    format: LogFormat,
+    filters: &str,
+    output: Option<Box<dyn Write + Send>>,
+) -> (Box<dyn Log>, LevelFilter) {
+    match format {
+        LogFormat::Pretty => {
+            let mut builder = pretty_env_logger::formatted_builder();
+            builder.parse_filters(filters);
+            if let Some(output) = output {
+                builder.target(Target::Pipe(output));
+            }
+            let logger = builder.build();
+            let max_level = logger.filter();
+            return (Box::new(logger), max_level);
+        }
+        LogFormat::Json => {
+            let filter = Builder::new().parse(filters).build();
+            let max_level = filter.filter();
+            let output = output.unwrap_or_else(|| Box::new(io::stderr()));
+            return (
+                Box::new(JsonLogger {
+                    filter,
+                    output: Mutex::new(output),
+                }),
+                max_level,
+            );
+        }
+    }
+}
+
+/// Sets up the logger of the application, filtered by `RUST_LOG` in either format.
+///
+/// Lines are printed on stderr, above the progress bar of a crawl rather than through it, unless
+/// `quiet` is set, and to `log_file` if it is set.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` holding the log settings.
+///
+/// # Errors
+///
+/// This function will return an error if the log file cannot be opened.
+pub fn init(config: &Config) -> Result<()> {
+    let filters = std::env::var("RUST_LOG").unwrap_or_default();
+    let mut loggers = Vec::new();
+    let mut max_level = LevelFilter::Off;
+    if !config.quiet {
+        let (logger, level) = build_logger(config.log_format, &filters, None);
+        loggers.push(Box::new(ProgressLogger::new(logger)) as Box<dyn Log>);
+        max_level = max_level.max(level);
+    }
+    if let Some(path) = &config.log_file {
+        let max_bytes = config.log_max_size_mb.map(|mb| mb * 1024 * 1024);
+        let file = RotatingFile::open(path, max_bytes).with_context(|| {
+            format!(
+                "Invalid configuration: log_file {} cannot be opened",
+                path.display()
+            )
+        })?;
+        let (logger, level) = build_logger(config.log_format, &filters, Some(Box::new(file)));
+        loggers.push(logger);
+        max_level = max_level.max(level);
+    }
+
+    if log::set_boxed_logger(Box::new(TeeLogger { loggers })).is_ok() {
+        log::set_max_level(max_level);
+    }
+    return Ok(());
+}
@@ -1,34 +1,35 @@
+use anyhow::Result;
+use clap::Parser;
 use log::info;
-use std::time::Instant;
-extern crate pretty_env_logger;
-
-mod config;
-mod database;
-mod domain;
-mod site;
-mod spider;
+use rustle::{init_logging, run_command, run_crawl, Cli, Config};
+use std::process::ExitCode;
 
 /// The main entry point of the Rustle application.
 ///
-/// This function initializes the runtime timer, sets up the logger,
-/// creates a new instance of the `Crawler` struct, and starts the crawling process.
-fn main() {
-    // Get Config Values
-    info!("Getting config values");
-    let config = config::Config::new().unwrap();
-
-    // Start Runtime & Init Logger
-    info!("Initializing rustle webcrawler");
-    let runtime = Instant::now();
-    pretty_env_logger::init();
-
-    // Declare Crawler
-    let crawler =
-        spider::Crawler::new(config.origin_url, config.depth, &config.database_name).unwrap();
+/// This function parses the command-line arguments, sets up the logger, and either runs a
+/// subcommand or runs a crawl with the config values.
+fn main() -> Result<ExitCode> {
+    let args = Cli::parse();
 
-    // Run Crawler
-    crawler.crawl();
+    // Read the config before setting up the logger, as it picks the format and file of the log
+    // lines.
+    // Subcommands such as `init` may run before the config file exists, so failing to read it
+    // is only an error for the commands that need it
+    let config = Config::load(args.config.as_deref(), args.overrides.clone());
+    let default_config = Config::default();
+    init_logging(config.as_ref().unwrap_or(&default_config))?;
 
-    // Print Runtime
-    info!("Runtime: {}s", runtime.elapsed().as_secs());
+    return match args.command {
+        Some(command) => run_command(
+            command,
+            config,
+            args.config.as_deref(),
+            args.overrides.database.as_deref(),
+        ),
+        None => {
+            // Get Config Values
+            info!("Getting config values");
+            run_crawl(config?, &args.crawl)
+        }
+    };
 }
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The interval at which the metrics listener checks for new connections.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The time a client of the metrics listener is given to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The settings of the Prometheus metrics endpoint served during a crawl.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Whether `/metrics` is served while a crawl runs.
+    pub enabled: bool,
+    /// The address the metrics listener binds to, such as `127.0.0.1:9898`.
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        return MetricsConfig {
+            enabled: false,
+            listen: "127.0.0.1:9898".to_string(),
+        };
+    }
+}
+
+/// Builds a page of metrics in the Prometheus text exposition format.
+#[derive(Default)]
+pub(crate) struct MetricsPage {
+    /// The text of the page so far.
+    text: String,
+}
+
+impl MetricsPage {
+    /// Adds a metric with a single value.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name of the metric.
+    /// * `kind` - A string slice that holds the type of the metric, `counter` or `gauge`.
+    /// * `help` - A string slice that holds the description of the metric.
+    /// * `value` - The value of the metric.
+    pub(crate) fn metric(&mut self, name: &str, kind: &str, help: &str, value: u64) {
+        self.header(name, kind, help);
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    /// Adds a metric with one value per value of a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name of the metric.
+    /// * `kind` - A string slice that holds the type of the metric, `counter` or `gauge`.
+    /// * `help` - A string slice that holds the description of the metric.
+    /// * `label` - A string slice that holds the name of the label.
+    /// * `values` - The value of the metric for every value of the label.
+    pub(crate) fn labeled_metric<'a>(
+        &mut self,
+        name: &str,
+        kind: &str,
+        help: &str,
+        label: &str,
+        values: impl IntoIterator<Item = (&'a str, u64)>,
+    ) {
+        self.header(name, kind, help);
+        for (label_value, value) in values {
+            let label_value = label_value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            let _ = writeln!(
+                self.text,
+                "{}{{{}=\"{}\"}} {}",
+                name, label, label_value, value
+            );
+        }
+    }
+
+    /// Adds the `HELP` and `TYPE` lines of a metric.
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    /// Gets the text of the page.
+    pub(crate) fn into_text(self) -> String {
+        return self.text;
+    }
+}
+
+/// Binds the metrics listener, if metrics are enabled.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `MetricsConfig` of the crawl.
+///
+/// # Returns
+///
+/// The non-blocking `TcpListener` of the endpoint, or `None` if metrics are disabled.
+///
+/// # Errors
+///
+/// This function will return an error if the listen address cannot be bound.
+pub(crate) fn bind(config: &MetricsConfig) -> Result<Option<TcpListener>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let listener = TcpListener::bind(&config.listen)
+        .with_context(|| format!("Failed to bind the metrics listener to {}", config.listen))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to set up the metrics listener")?;
+    info!("Serving metrics at http://{}/metrics", config.listen);
+    return Ok(Some(listener));
+}
+
+/// Serves the metrics endpoint from the current thread until `stop` is set.
+///
+/// # Arguments
+///
+/// * `listener` - A reference to the listener returned by `bind`.
+/// * `stop` - A reference to the flag set once the crawl is finished.
+/// * `render` - Renders the current metrics in the Prometheus text format.
+pub(crate) fn serve(listener: &TcpListener, stop: &AtomicBool, render: impl Fn() -> String) {
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => respond(stream, &render),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(ACCEPT_INTERVAL),
+            Err(e) => warn!("Failed to accept a metrics connection: {}", e),
+        }
+    }
+}
+
+/// Serves the metrics endpoint from an async task, until the future is dropped.
+///
+/// # Arguments
+///
+/// * `listener` - A reference to the listener returned by `bind`.
+/// * `render` - Renders the current metrics in the Prometheus text format.
+pub(crate) async fn serve_async(listener: &TcpListener, render: impl Fn() -> String) {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => tokio::task::block_in_place(|| respond(stream, &render)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                tokio::time::sleep(ACCEPT_INTERVAL).await
+            }
+            Err(e) => warn!("Failed to accept a metrics connection: {}", e),
+        }
+    }
+}
+
+/// Answers a single request to the metrics listener, logging any failure.
+///
+/// # Arguments
+///
+/// * `stream` - The connection of the client.
+/// * `render` - Renders the current metrics in the Prometheus text format.
+fn respond(stream: TcpStream, render: &impl Fn() -> String) {
+    if let Err(e) = write_response(&stream, render) {
+        warn!("Failed to answer a metrics request: {:#}", e);
+    }
+}
+
+/// Reads the request of a client of the metrics listener and writes the response, serving the
+/// metrics at `/metrics` and a 404 everywhere else.
+///
+/// # Arguments
+///
+/// * `stream` - A reference to the connection of the client.
+/// * `render` - Renders the current metrics in the Prometheus text format.
+///
+/// # Errors
+///
+/// This function will return an error if the request cannot be read or the response written.
+fn write_response(stream: &TcpStream, render: &impl Fn() -> String) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line)?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, body) = match path {
+        "/metrics" => ("200 OK", render()),
+        _ => ("404 Not Found", "Not Found\n".to_string()),
+    };
+    write!(
+        &*stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    return Ok(());
+}
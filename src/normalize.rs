@@ -0,0 +1,178 @@
+use url::Url;
+
+/// The characters that percent-encoded bytes of a query are decoded to, as they mean the same
+/// encoded or not: the unreserved characters, and the delimiters that don't separate parameters.
+const DECODED_CHARACTERS: &[u8] = b"-._~!$()*,;:@/";
+
+/// Normalizes the query of a URL, so that URLs of the same resource share the same query.
+///
+/// The parameters are sorted by key, keeping the order of parameters of the same key, and empty
+/// parameters, such as those left by `&&` or a key without a value after `=`, are dropped. A key
+/// without `=` is kept as it is. Percent-encoded bytes are decoded when they mean the same as
+/// their character, and written with uppercase digits otherwise, so that `&`, `=`, `+` and `#`
+/// keep their meaning. A query that ends up empty is removed.
+///
+/// # Arguments
+///
+/// * `url` - A mutable reference to the URL whose query is normalized.
+pub fn normalize_query(url: &mut Url) {
+    let Some(query) = url.query() else {
+        return;
+    };
+
+    let mut parameters: Vec<(String, Option<String>)> = query
+        .split('&')
+        .filter_map(|parameter| match parameter.split_once('=') {
+            Some((_, "")) => None,
+            Some((key, value)) => Some((normalize_encoding(key), Some(normalize_encoding(value)))),
+            None if parameter.is_empty() => None,
+            None => Some((normalize_encoding(parameter), None)),
+        })
+        .collect();
+    parameters.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let query = parameters
+        .iter()
+        .map(|(key, value)| match value {
+            Some(value) => format!("{}={}", key, value),
+            None => key.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join("&");
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.set_query(Some(&query));
+    }
+}
+
+/// Decodes the percent-encoded bytes of a query component that mean the same as their character,
+/// and writes the others with uppercase hexadecimal digits.
+///
+/// # Arguments
+///
+/// * `component` - A string slice that holds the key or value of a query parameter.
+fn normalize_encoding(component: &str) -> String {
+    let mut normalized = String::with_capacity(component.len());
+    let mut rest = component;
+    while let Some(start) = rest.find('%') {
+        normalized.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let byte = rest
+            .get(1..3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) if byte.is_ascii_alphanumeric() || DECODED_CHARACTERS.contains(&byte) => {
+                normalized.push(byte as char);
+            }
+            Some(byte) => normalized.push_str(&format!("%{:02X}", byte)),
+            None => {
+                // A lone `%` is left as it is
+                normalized.push('%');
+                rest = &rest[1..];
+                continue;
+            }
+        }
+        rest = &rest[3..];
+    }
+    normalized.push_str(rest);
+    return normalized;
+}
+
+/// Writes a host in its ASCII form, lowercased and with the labels of internationalized domain
+/// names in punycode, so that a host written in Unicode and in punycode is the same domain.
+///
+/// # Arguments
+///
+/// * `host` - A string slice that holds the host, in either form.
+///
+/// # Returns
+///
+/// The ASCII form of the host, or the host lowercased if it isn't a valid domain name.
+pub fn ascii_host(host: &str) -> String {
+    return idna::domain_to_ascii(host).unwrap_or_else(|_| host.to_lowercase());
+}
+
+/// Writes a host in its human-readable Unicode form, for logs.
+///
+/// # Arguments
+///
+/// * `host` - A string slice that holds the host, in either form.
+pub fn display_host(host: &str) -> String {
+    return idna::domain_to_unicode(host).0;
+}
+
+/// Writes a URL with its host in ASCII form, as `Url` does when it parses a URL. URLs that are
+/// already ASCII, or that don't parse, are left as they are.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL.
+pub fn ascii_url(url: &str) -> String {
+    if url.is_ascii() {
+        return url.to_string();
+    }
+    return Url::parse(url)
+        .map(String::from)
+        .unwrap_or_else(|_| url.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_normalized() {
+        use url::Url;
+
+        let normalize = |url: &str| {
+            let mut url = Url::parse(url).unwrap();
+            normalize_query(&mut url);
+            url.to_string()
+        };
+        assert_eq!(
+            normalize("https://example.com/?b=2&a=1"),
+            "https://example.com/?a=1&b=2"
+        );
+
+        // Parameters of the same key keep their order
+        assert_eq!(
+            normalize("https://example.com/?tag=z&id=7&tag=a"),
+            "https://example.com/?id=7&tag=z&tag=a"
+        );
+
+        // Encoded reserved characters stay encoded, and the others are decoded
+        assert_eq!(
+            normalize("https://example.com/?q=a%26b%3dc&path=%2fdocs%7e&sum=1%2b1"),
+            "https://example.com/?path=/docs~&q=a%26b%3Dc&sum=1%2B1"
+        );
+
+        // Parameters without a value are dropped, and keys without `=` are kept
+        assert_eq!(
+            normalize("https://example.com/?page=&print&&a=1"),
+            "https://example.com/?a=1&print"
+        );
+        assert_eq!(
+            normalize("https://example.com/?utm="),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn hosts_are_converted_between_ascii_and_unicode() {
+        assert_eq!(ascii_host("Bücher.example"), "xn--bcher-kva.example");
+        assert_eq!(ascii_host("xn--bcher-kva.example"), "xn--bcher-kva.example");
+        assert_eq!(ascii_host("127.0.0.1"), "127.0.0.1");
+        assert_eq!(display_host("xn--bcher-kva.example"), "bücher.example");
+    }
+
+    #[test]
+    fn urls_are_converted_to_ascii() {
+        assert_eq!(
+            ascii_url("https://bücher.example/katalog?q=größe"),
+            "https://xn--bcher-kva.example/katalog?q=gr%C3%B6%C3%9Fe"
+        );
+        assert_eq!(ascii_url("https://example.com"), "https://example.com");
+    }
+}
@@ -0,0 +1,1169 @@
+use crate::analyze::{RuleSuggestion, UploadHtmlFinding};
+use crate::crawl_run::CrawlRun;
+use crate::explain::{ComplianceRecord, Explanation};
+use crate::fetch_failure::FetchFailure;
+use crate::site::{BrokenLink, DomainSummary, Site};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The version of the JSON documents emitted by Rustle.
+///
+/// Bump this whenever the serialized shape of any document changes. Fields that are removed or
+/// renamed are kept, marked as deprecated, for one version before they are dropped.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The name and version of the program that generated a document.
+pub const GENERATOR: &str = concat!("rustle ", env!("CARGO_PKG_VERSION"));
+
+/// Represents a versioned JSON document emitted by Rustle.
+///
+/// Every document carries the `format_version` and `generator` of its producer, and the name
+/// of the `report` it holds, alongside the fields of the report itself.
+#[derive(Serialize)]
+pub struct Document<T: Serialize> {
+    /// The version of the document format, see `FORMAT_VERSION`.
+    pub format_version: u32,
+    /// The name and version of the program that generated the document.
+    pub generator: &'static str,
+    /// The name of the report held by the document.
+    pub report: &'static str,
+    /// The fields of the report.
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T: Serialize> Document<T> {
+    /// Creates a new instance of the `Document` struct for the current format version.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The name of the report held by the document.
+    /// * `body` - The fields of the report.
+    pub fn new(report: &'static str, body: T) -> Self {
+        return Document {
+            format_version: FORMAT_VERSION,
+            generator: GENERATOR,
+            report,
+            body,
+        };
+    }
+
+    /// Serializes the document to pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        return Ok(serde_json::to_string_pretty(self)?);
+    }
+}
+
+/// The report emitted by `rustle analyze --suggest-rules --json`.
+#[derive(Serialize)]
+pub struct SuggestRulesReport {
+    /// The suggested rules, strongest first.
+    pub suggestions: Vec<SuggestionOutput>,
+}
+
+impl SuggestRulesReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "suggest-rules";
+}
+
+/// Represents a suggested canonicalization rule in the `SuggestRulesReport`.
+#[derive(Serialize)]
+pub struct SuggestionOutput {
+    /// A human-readable description of the rule.
+    pub rule: String,
+    /// The number of URL pairs that the rule would merge.
+    pub evidence: usize,
+    /// The number of stored URLs showing the pattern.
+    pub occurrences: usize,
+    /// A few of the URL pairs that the rule would merge.
+    pub examples: Vec<ExampleOutput>,
+}
+
+/// Represents a URL pair that a suggested rule would merge.
+#[derive(Serialize)]
+pub struct ExampleOutput {
+    /// The URL showing the pattern.
+    pub from: String,
+    /// The stored variant of the URL without the pattern.
+    pub to: String,
+}
+
+impl From<&RuleSuggestion> for SuggestionOutput {
+    fn from(suggestion: &RuleSuggestion) -> Self {
+        return SuggestionOutput {
+            rule: suggestion.rule.clone(),
+            evidence: suggestion.evidence,
+            occurrences: suggestion.occurrences,
+            examples: suggestion
+                .examples
+                .iter()
+                .map(|(from, to)| ExampleOutput {
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+                .collect(),
+        };
+    }
+}
+
+/// The report emitted by `rustle analyze --security --json`.
+#[derive(Serialize)]
+pub struct SecurityReport {
+    /// The pages served as HTML from paths where uploaded files are stored.
+    pub upload_html: Vec<UploadHtmlOutput>,
+}
+
+impl SecurityReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "security";
+}
+
+/// Represents a page served as HTML from an upload path in the `SecurityReport`.
+#[derive(Serialize)]
+pub struct UploadHtmlOutput {
+    /// The URL of the page.
+    pub url: String,
+    /// The upload path pattern the URL matched.
+    pub pattern: String,
+    /// The media type declared by the response.
+    pub declared_type: String,
+    /// The media type sniffed from the body, if it matched a known signature.
+    pub sniffed_type: Option<String>,
+}
+
+impl From<&UploadHtmlFinding> for UploadHtmlOutput {
+    fn from(finding: &UploadHtmlFinding) -> Self {
+        return UploadHtmlOutput {
+            url: finding.url.clone(),
+            pattern: finding.pattern.clone(),
+            declared_type: finding.declared_type.clone(),
+            sniffed_type: finding.sniffed_type.clone(),
+        };
+    }
+}
+
+/// The report emitted by `rustle analyze --js-dependency --json`.
+#[derive(Serialize)]
+pub struct JsDependencyReport {
+    /// The pages most dependent on JavaScript, highest score first.
+    pub pages: Vec<JsDependencyOutput>,
+}
+
+impl JsDependencyReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "js-dependency";
+}
+
+/// Represents a page in the `JsDependencyReport`.
+#[derive(Serialize)]
+pub struct JsDependencyOutput {
+    /// The URL of the page.
+    pub url: String,
+    /// The estimate of how much the page depends on JavaScript to render its content.
+    pub js_dependency_score: f64,
+}
+
+/// The report emitted by `rustle search <query> --json`.
+#[derive(Serialize)]
+pub struct SearchReport {
+    /// The full-text query.
+    pub query: String,
+    /// The matching pages, best matches first.
+    pub results: Vec<SearchResultOutput>,
+}
+
+impl SearchReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "search";
+}
+
+/// Represents a matching page in the `SearchReport`.
+#[derive(Serialize)]
+pub struct SearchResultOutput {
+    /// The URL of the page.
+    pub url: String,
+    /// The text of the page around the match, with matched terms in square brackets.
+    pub snippet: String,
+}
+
+/// The report emitted by `rustle export --format json`.
+#[derive(Serialize)]
+pub struct GraphReport {
+    /// The nodes of the link graph, in sorted order.
+    pub nodes: Vec<String>,
+    /// The edges of the link graph, in sorted order.
+    pub edges: Vec<EdgeOutput>,
+}
+
+impl GraphReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "graph";
+}
+
+/// Represents an edge of the link graph in the `GraphReport`.
+#[derive(Serialize)]
+pub struct EdgeOutput {
+    /// The node the links start from.
+    pub from: String,
+    /// The node the links lead to.
+    pub to: String,
+    /// The number of links the edge stands for, more than one only when hosts are collapsed.
+    pub links: usize,
+}
+
+/// The report emitted by `rustle stats --json`.
+#[derive(Serialize)]
+pub struct StatsReport {
+    /// The number of stored sites.
+    pub sites: u64,
+    /// The number of stored sites of each status class, such as `2xx`, or `unknown`.
+    pub status_classes: BTreeMap<String, u64>,
+    /// The number of stored domains.
+    pub domains: u64,
+    /// The number of stored links.
+    pub links: u64,
+    /// The stored sites of each host, the host with the most sites first.
+    pub hosts: Vec<DomainSummaryOutput>,
+    /// The number of stored sites at each depth they were discovered at, by depth.
+    pub pages_by_depth: BTreeMap<u64, u64>,
+    /// The URLs of the RSS and Atom feeds discovered on each domain, by domain.
+    pub feeds: BTreeMap<String, Vec<String>>,
+    /// The hosts out of scope that stored pages link to, the most referenced host first.
+    pub external_hosts: Vec<ExternalHostOutput>,
+    /// The number of URLs whose last fetch failed.
+    pub fetch_failures: u64,
+    /// The number of URLs whose last fetch failed, by the category of the failure.
+    pub failures_by_kind: BTreeMap<String, u64>,
+    /// The hosts with the most URLs whose last fetch failed, the worst first.
+    pub failing_hosts: Vec<FailingHostOutput>,
+    /// The latest crawl runs, the most recent first.
+    pub runs: Vec<CrawlRunOutput>,
+}
+
+/// Represents a crawl run in the `StatsReport`.
+#[derive(Serialize)]
+pub struct CrawlRunOutput {
+    /// The id of the run, which the sites it stored reference.
+    pub id: i64,
+    /// The time the run started, as an RFC 3339 timestamp.
+    pub started_at: String,
+    /// The time the run finished, as an RFC 3339 timestamp, or null if it didn't finish.
+    pub finished_at: Option<String>,
+    /// The origin URLs of the run, separated by spaces.
+    pub origin_url: String,
+    /// The depth the run was configured with.
+    pub depth: u64,
+    /// The number of pages the run fetched.
+    pub pages_fetched: u64,
+    /// The number of fetches of the run that failed.
+    pub errors: u64,
+}
+
+impl From<&CrawlRun> for CrawlRunOutput {
+    fn from(run: &CrawlRun) -> Self {
+        return CrawlRunOutput {
+            id: run.id,
+            started_at: run.started_at.to_rfc3339(),
+            finished_at: run.finished_at.map(|finished_at| finished_at.to_rfc3339()),
+            origin_url: run.origin_url.clone(),
+            depth: run.depth,
+            pages_fetched: run.pages_fetched,
+            errors: run.errors,
+        };
+    }
+}
+
+/// Represents a host with failed fetches in the `StatsReport`.
+#[derive(Serialize)]
+pub struct FailingHostOutput {
+    /// The host of the URLs.
+    pub host: String,
+    /// The number of URLs of the host whose last fetch failed.
+    pub failures: u64,
+}
+
+/// Represents a host out of scope in the `StatsReport`.
+#[derive(Serialize)]
+pub struct ExternalHostOutput {
+    /// The host the links point at.
+    pub host: String,
+    /// The number of stored links to the host.
+    pub links: u64,
+}
+
+impl StatsReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "stats";
+}
+
+/// Represents the stored sites of a host in the `StatsReport`.
+#[derive(Serialize)]
+pub struct DomainSummaryOutput {
+    /// The host of the sites.
+    pub host: String,
+    /// The number of stored sites of the host.
+    pub pages: u64,
+    /// The crawl time of the least recently crawled site, as an RFC 3339 timestamp.
+    pub oldest_crawl: String,
+    /// The crawl time of the most recently crawled site, as an RFC 3339 timestamp.
+    pub newest_crawl: String,
+}
+
+impl From<&DomainSummary> for DomainSummaryOutput {
+    fn from(summary: &DomainSummary) -> Self {
+        return DomainSummaryOutput {
+            host: summary.host.clone(),
+            pages: summary.pages,
+            oldest_crawl: summary.oldest_crawl.to_rfc3339(),
+            newest_crawl: summary.newest_crawl.to_rfc3339(),
+        };
+    }
+}
+
+/// The report emitted by `rustle query links <url> --json` and `rustle query linked-by <url>
+/// --json`.
+#[derive(Serialize)]
+pub struct LinksReport {
+    /// The URL whose links are listed.
+    pub url: String,
+    /// The URLs the page links to, or the pages linking to it, in sorted order.
+    pub links: Vec<String>,
+}
+
+impl LinksReport {
+    /// The name of the report listing the URLs a page links to.
+    pub const LINKS_NAME: &'static str = "links";
+    /// The name of the report listing the pages linking to a URL.
+    pub const LINKED_BY_NAME: &'static str = "linked-by";
+}
+
+/// The report emitted by `rustle query recent --json`.
+#[derive(Serialize)]
+pub struct RecentReport {
+    /// The earliest crawl time of the listed pages, as an RFC 3339 timestamp.
+    pub since: String,
+    /// The pages crawled since then, oldest crawl first.
+    pub pages: Vec<PageOutput>,
+}
+
+impl RecentReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "recent";
+}
+
+/// The report emitted by `rustle query changed --json`.
+#[derive(Serialize)]
+pub struct ChangedReport {
+    /// The earliest change time of the listed pages, as an RFC 3339 timestamp.
+    pub since: String,
+    /// The pages whose content changed since then, oldest change first.
+    pub pages: Vec<PageOutput>,
+}
+
+impl ChangedReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "changed";
+}
+
+/// The report emitted by `rustle query generation <n> --json`.
+#[derive(Serialize)]
+pub struct GenerationReport {
+    /// The generation of the listed pages, counting the origin as generation 0.
+    pub generation: u64,
+    /// The number of listed pages whose response had an error status code.
+    pub error_pages: u64,
+    /// The mean response time of the listed pages, in milliseconds, if any was recorded.
+    pub mean_response_ms: Option<u64>,
+    /// The pages fetched in the generation, in the order of their batches.
+    pub pages: Vec<GenerationPageOutput>,
+    /// The URLs of the generation that could not be fetched, in the order of their batches.
+    pub failures: Vec<GenerationFailureOutput>,
+}
+
+impl GenerationReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "generation";
+}
+
+/// Represents a page and the outcome of its fetch in the `GenerationReport`.
+#[derive(Serialize)]
+pub struct GenerationPageOutput {
+    /// The URL of the page.
+    pub url: String,
+    /// The worker batch that fetched the page, if it was recorded.
+    pub batch_id: Option<u64>,
+    /// The HTTP status code of the response, if it was recorded.
+    pub status_code: Option<u16>,
+    /// The time taken to fetch the page, in milliseconds, if it was recorded.
+    pub response_ms: Option<u64>,
+    /// Whether the response was a bot-detection challenge.
+    pub bot_challenged: bool,
+}
+
+impl From<&Site> for GenerationPageOutput {
+    fn from(site: &Site) -> Self {
+        return GenerationPageOutput {
+            url: site.url.clone(),
+            batch_id: site.batch_id,
+            status_code: site.status_code,
+            response_ms: site.response_ms,
+            bot_challenged: site.bot_challenged,
+        };
+    }
+}
+
+/// Represents a URL that could not be fetched in the `GenerationReport`.
+#[derive(Serialize)]
+pub struct GenerationFailureOutput {
+    /// The URL that could not be fetched.
+    pub url: String,
+    /// The worker batch that tried to fetch the URL, if it was recorded.
+    pub batch_id: Option<u64>,
+    /// The category of the failure, such as `timeout`.
+    pub error_kind: String,
+    /// The reason of the failure.
+    pub message: String,
+}
+
+impl From<&FetchFailure> for GenerationFailureOutput {
+    fn from(failure: &FetchFailure) -> Self {
+        return GenerationFailureOutput {
+            url: failure.url.clone(),
+            batch_id: failure.batch_id,
+            error_kind: failure.error_kind.clone(),
+            message: failure.message.clone(),
+        };
+    }
+}
+
+/// The report emitted by `rustle query redirects <url> --json`.
+#[derive(Serialize)]
+pub struct RedirectsReport {
+    /// The URL the listed redirects lead to.
+    pub url: String,
+    /// The redirects whose chain ends at the URL, closest first.
+    pub redirects: Vec<RedirectOutput>,
+}
+
+impl RedirectsReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "redirects";
+}
+
+/// Represents a stored redirect in the `RedirectsReport`.
+#[derive(Serialize)]
+pub struct RedirectOutput {
+    /// The URL that redirects.
+    pub url: String,
+    /// The next URL of the redirect chain.
+    pub next: Option<String>,
+    /// The HTTP status code of the redirect, if it was recorded.
+    pub status_code: Option<u16>,
+    /// The number of redirects between the URL and the end of the chain, if it was recorded.
+    pub hops: Option<u64>,
+}
+
+impl From<&Site> for RedirectOutput {
+    fn from(site: &Site) -> Self {
+        return RedirectOutput {
+            url: site.url.clone(),
+            next: site.links_to.iter().next().cloned(),
+            status_code: site.status_code,
+            hops: site.redirect_hops,
+        };
+    }
+}
+
+/// The report emitted by `rustle query broken --json`.
+#[derive(Serialize)]
+pub struct BrokenLinksReport {
+    /// The links whose target failed to load, sorted by target and then by source.
+    pub links: Vec<BrokenLinkOutput>,
+}
+
+impl BrokenLinksReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "broken";
+}
+
+/// Represents a link whose target failed to load in the `BrokenLinksReport`.
+#[derive(Serialize)]
+pub struct BrokenLinkOutput {
+    /// The URL of the page the link is on.
+    pub source: String,
+    /// The URL the link points at.
+    pub target: String,
+    /// The kind of the link, `page` or `asset`.
+    pub kind: String,
+    /// The HTTP status code of the target, if it was fetched.
+    pub status_code: Option<u16>,
+    /// The category of the last failed fetch of the target, if it failed.
+    pub error_kind: Option<String>,
+}
+
+impl From<&BrokenLink> for BrokenLinkOutput {
+    fn from(link: &BrokenLink) -> Self {
+        return BrokenLinkOutput {
+            source: link.source_url.clone(),
+            target: link.target_url.clone(),
+            kind: link.kind.clone(),
+            status_code: link.status_code,
+            error_kind: link.error_kind.clone(),
+        };
+    }
+}
+
+/// Represents a stored page in a report.
+#[derive(Serialize)]
+pub struct PageOutput {
+    /// The URL of the page.
+    pub url: String,
+    /// The time the page was crawled, as an RFC 3339 timestamp.
+    pub crawl_time: String,
+    /// The time the content of the page last changed, as an RFC 3339 timestamp, if it is known.
+    pub last_changed: Option<String>,
+    /// The HTTP status code of the response, if it was recorded.
+    pub status_code: Option<u16>,
+    /// The title of the page, if it has one.
+    pub title: Option<String>,
+}
+
+impl From<&Site> for PageOutput {
+    fn from(site: &Site) -> Self {
+        return PageOutput {
+            url: site.url.clone(),
+            crawl_time: site.crawl_time.to_rfc3339(),
+            last_changed: site
+                .last_changed
+                .map(|last_changed| last_changed.to_rfc3339()),
+            status_code: site.status_code,
+            title: site.title.clone(),
+        };
+    }
+}
+
+/// The report emitted by `rustle explain <url> --json`.
+#[derive(Serialize)]
+pub struct ExplainReport {
+    /// The URL being explained.
+    pub url: String,
+    /// Whether the URL is stored in the database.
+    pub stored: bool,
+    /// The findings about the URL, in the order the crawler goes through them.
+    pub steps: Vec<ExplainStepOutput>,
+}
+
+impl ExplainReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "explain";
+}
+
+/// The report emitted by `rustle explain <url> --compliance --json`.
+#[derive(Serialize)]
+pub struct ComplianceReport {
+    /// The URL of the page.
+    pub url: String,
+    /// The time the page was fetched, as an RFC 3339 timestamp.
+    pub crawl_time: String,
+    /// The robots.txt rule that allowed the page, or `no rules`.
+    pub robots_rule: String,
+    /// The id of the robots.txt snapshot the page was fetched under.
+    pub snapshot_id: i64,
+    /// The domain of the snapshot.
+    pub domain: String,
+    /// The time the robots.txt of the snapshot was fetched, as an RFC 3339 timestamp.
+    pub robots_fetch_time: String,
+    /// The path of the local robots.txt used instead of the live one, if any.
+    pub robots_override: Option<String>,
+    /// The raw contents of the robots.txt of the snapshot.
+    pub robots: String,
+}
+
+impl ComplianceReport {
+    /// The name of the report.
+    pub const NAME: &'static str = "compliance";
+}
+
+impl From<&ComplianceRecord> for ComplianceReport {
+    fn from(record: &ComplianceRecord) -> Self {
+        return ComplianceReport {
+            url: record.url.clone(),
+            crawl_time: record.crawl_time.to_rfc3339(),
+            robots_rule: record.robots_rule.clone(),
+            snapshot_id: record.snapshot.id,
+            domain: record.snapshot.domain.clone(),
+            robots_fetch_time: record.snapshot.fetch_time.to_rfc3339(),
+            robots_override: record.snapshot.robots_override.clone(),
+            robots: record.snapshot.robots.clone(),
+        };
+    }
+}
+
+/// Represents a finding in the `ExplainReport`.
+#[derive(Serialize)]
+pub struct ExplainStepOutput {
+    /// The stage of the crawl the finding is about, such as `robots` or `fetch`.
+    pub stage: String,
+    /// A human-readable description of the finding.
+    pub detail: String,
+}
+
+impl From<&Explanation> for ExplainReport {
+    fn from(explanation: &Explanation) -> Self {
+        return ExplainReport {
+            url: explanation.url.clone(),
+            stored: explanation.stored,
+            steps: explanation
+                .steps
+                .iter()
+                .map(|step| ExplainStepOutput {
+                    stage: step.stage.to_string(),
+                    detail: step.detail.clone(),
+                })
+                .collect(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The expected JSON of every document, in which `{version}` stands for the version of the
+    // crate. A change to any of them is a change of the document format, see `FORMAT_VERSION`.
+    const SUGGEST_RULES: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "suggest-rules",
+  "suggestions": [
+    {
+      "rule": "drop the utm_source query parameter",
+      "evidence": 3,
+      "occurrences": 5,
+      "examples": [
+        {
+          "from": "https://example.com/?utm_source=feed",
+          "to": "https://example.com/"
+        }
+      ]
+    }
+  ]
+}
+"#;
+
+    const SECURITY: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "security",
+  "upload_html": [
+    {
+      "url": "https://example.com/uploads/page.png",
+      "pattern": "/uploads/",
+      "declared_type": "image/png",
+      "sniffed_type": "text/html"
+    }
+  ]
+}
+"#;
+
+    const JS_DEPENDENCY: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "js-dependency",
+  "pages": [
+    {
+      "url": "https://example.com/app",
+      "js_dependency_score": 0.75
+    }
+  ]
+}
+"#;
+
+    const SEARCH: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "search",
+  "query": "crawler",
+  "results": [
+    {
+      "url": "https://example.com/",
+      "snippet": "a [crawler] in Rust"
+    }
+  ]
+}
+"#;
+
+    const GRAPH: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "graph",
+  "nodes": [
+    "https://example.com/",
+    "https://example.com/about"
+  ],
+  "edges": [
+    {
+      "from": "https://example.com/",
+      "to": "https://example.com/about",
+      "links": 1
+    }
+  ]
+}
+"#;
+
+    const STATS: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "stats",
+  "sites": 2,
+  "status_classes": {
+    "2xx": 2
+  },
+  "domains": 1,
+  "links": 3,
+  "hosts": [
+    {
+      "host": "example.com",
+      "pages": 2,
+      "oldest_crawl": "2024-05-01T12:00:00+00:00",
+      "newest_crawl": "2024-05-01T12:05:00+00:00"
+    }
+  ],
+  "pages_by_depth": {
+    "0": 1,
+    "1": 1
+  },
+  "feeds": {
+    "example.com": [
+      "https://example.com/feed.xml"
+    ]
+  },
+  "external_hosts": [
+    {
+      "host": "example.org",
+      "links": 1
+    }
+  ],
+  "fetch_failures": 1,
+  "failures_by_kind": {
+    "timeout": 1
+  },
+  "failing_hosts": [
+    {
+      "host": "example.com",
+      "failures": 1
+    }
+  ],
+  "runs": [
+    {
+      "id": 1,
+      "started_at": "2024-05-01T12:00:00+00:00",
+      "finished_at": null,
+      "origin_url": "https://example.com/",
+      "depth": 1,
+      "pages_fetched": 2,
+      "errors": 1
+    }
+  ]
+}
+"#;
+
+    const LINKS: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "links",
+  "url": "https://example.com/",
+  "links": [
+    "https://example.com/about"
+  ]
+}
+"#;
+
+    const LINKED_BY: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "linked-by",
+  "url": "https://example.com/",
+  "links": [
+    "https://example.com/about"
+  ]
+}
+"#;
+
+    const RECENT: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "recent",
+  "since": "2024-05-01T00:00:00+00:00",
+  "pages": [
+    {
+      "url": "https://example.com/",
+      "crawl_time": "2024-05-01T12:00:00+00:00",
+      "last_changed": null,
+      "status_code": 200,
+      "title": "Example"
+    }
+  ]
+}
+"#;
+
+    const CHANGED: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "changed",
+  "since": "2024-05-01T00:00:00+00:00",
+  "pages": [
+    {
+      "url": "https://example.com/",
+      "crawl_time": "2024-05-01T12:00:00+00:00",
+      "last_changed": null,
+      "status_code": 200,
+      "title": "Example"
+    }
+  ]
+}
+"#;
+
+    const GENERATION: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "generation",
+  "generation": 1,
+  "error_pages": 0,
+  "mean_response_ms": 42,
+  "pages": [
+    {
+      "url": "https://example.com/about",
+      "batch_id": 3,
+      "status_code": 200,
+      "response_ms": 42,
+      "bot_challenged": false
+    }
+  ],
+  "failures": [
+    {
+      "url": "https://example.com/slow",
+      "batch_id": 3,
+      "error_kind": "timeout",
+      "message": "operation timed out"
+    }
+  ]
+}
+"#;
+
+    const REDIRECTS: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "redirects",
+  "url": "https://example.com/new",
+  "redirects": [
+    {
+      "url": "https://example.com/old",
+      "next": "https://example.com/new",
+      "status_code": 301,
+      "hops": 1
+    }
+  ]
+}
+"#;
+
+    const BROKEN_LINKS: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "broken",
+  "links": [
+    {
+      "source": "https://example.com/",
+      "target": "https://example.com/missing",
+      "kind": "page",
+      "status_code": 404,
+      "error_kind": null
+    }
+  ]
+}
+"#;
+
+    const EXPLAIN: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "explain",
+  "url": "https://example.com/private/",
+  "stored": false,
+  "steps": [
+    {
+      "stage": "robots",
+      "detail": "Disallow: /private/"
+    }
+  ]
+}
+"#;
+
+    const COMPLIANCE: &str = r#"
+{
+  "format_version": 1,
+  "generator": "rustle {version}",
+  "report": "compliance",
+  "url": "https://example.com/",
+  "crawl_time": "2024-05-01T12:00:00+00:00",
+  "robots_rule": "Allow: /",
+  "snapshot_id": 1,
+  "domain": "example.com",
+  "robots_fetch_time": "2024-05-01T11:59:00+00:00",
+  "robots_override": null,
+  "robots": "User-agent: *\nAllow: /\n"
+}
+"#;
+
+    /// Serializes a report into its document, and compares it with the expected JSON.
+    fn assert_snapshot<T: Serialize>(report: &'static str, body: T, expected: &str) {
+        let json = Document::new(report, body).to_json().unwrap();
+        assert_eq!(
+            json,
+            expected
+                .trim()
+                .replace("{version}", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn suggest_rules_snapshot() {
+        let report = SuggestRulesReport {
+            suggestions: vec![SuggestionOutput {
+                rule: "drop the utm_source query parameter".to_string(),
+                evidence: 3,
+                occurrences: 5,
+                examples: vec![ExampleOutput {
+                    from: "https://example.com/?utm_source=feed".to_string(),
+                    to: "https://example.com/".to_string(),
+                }],
+            }],
+        };
+        assert_snapshot(SuggestRulesReport::NAME, report, SUGGEST_RULES);
+    }
+
+    #[test]
+    fn security_snapshot() {
+        let report = SecurityReport {
+            upload_html: vec![UploadHtmlOutput {
+                url: "https://example.com/uploads/page.png".to_string(),
+                pattern: "/uploads/".to_string(),
+                declared_type: "image/png".to_string(),
+                sniffed_type: Some("text/html".to_string()),
+            }],
+        };
+        assert_snapshot(SecurityReport::NAME, report, SECURITY);
+    }
+
+    #[test]
+    fn js_dependency_snapshot() {
+        let report = JsDependencyReport {
+            pages: vec![JsDependencyOutput {
+                url: "https://example.com/app".to_string(),
+                js_dependency_score: 0.75,
+            }],
+        };
+        assert_snapshot(JsDependencyReport::NAME, report, JS_DEPENDENCY);
+    }
+
+    #[test]
+    fn search_snapshot() {
+        let report = SearchReport {
+            query: "crawler".to_string(),
+            results: vec![SearchResultOutput {
+                url: "https://example.com/".to_string(),
+                snippet: "a [crawler] in Rust".to_string(),
+            }],
+        };
+        assert_snapshot(SearchReport::NAME, report, SEARCH);
+    }
+
+    #[test]
+    fn graph_snapshot() {
+        let report = GraphReport {
+            nodes: vec![
+                "https://example.com/".to_string(),
+                "https://example.com/about".to_string(),
+            ],
+            edges: vec![EdgeOutput {
+                from: "https://example.com/".to_string(),
+                to: "https://example.com/about".to_string(),
+                links: 1,
+            }],
+        };
+        assert_snapshot(GraphReport::NAME, report, GRAPH);
+    }
+
+    #[test]
+    fn stats_snapshot() {
+        let report = StatsReport {
+            sites: 2,
+            status_classes: BTreeMap::from([("2xx".to_string(), 2)]),
+            domains: 1,
+            links: 3,
+            hosts: vec![DomainSummaryOutput {
+                host: "example.com".to_string(),
+                pages: 2,
+                oldest_crawl: "2024-05-01T12:00:00+00:00".to_string(),
+                newest_crawl: "2024-05-01T12:05:00+00:00".to_string(),
+            }],
+            pages_by_depth: BTreeMap::from([(0, 1), (1, 1)]),
+            feeds: BTreeMap::from([(
+                "example.com".to_string(),
+                vec!["https://example.com/feed.xml".to_string()],
+            )]),
+            external_hosts: vec![ExternalHostOutput {
+                host: "example.org".to_string(),
+                links: 1,
+            }],
+            fetch_failures: 1,
+            failures_by_kind: BTreeMap::from([("timeout".to_string(), 1)]),
+            failing_hosts: vec![FailingHostOutput {
+                host: "example.com".to_string(),
+                failures: 1,
+            }],
+            runs: vec![CrawlRunOutput {
+                id: 1,
+                started_at: "2024-05-01T12:00:00+00:00".to_string(),
+                finished_at: None,
+                origin_url: "https://example.com/".to_string(),
+                depth: 1,
+                pages_fetched: 2,
+                errors: 1,
+            }],
+        };
+        assert_snapshot(StatsReport::NAME, report, STATS);
+    }
+
+    #[test]
+    fn links_snapshot() {
+        let report = || LinksReport {
+            url: "https://example.com/".to_string(),
+            links: vec!["https://example.com/about".to_string()],
+        };
+        assert_snapshot(LinksReport::LINKS_NAME, report(), LINKS);
+        assert_snapshot(LinksReport::LINKED_BY_NAME, report(), LINKED_BY);
+    }
+
+    /// A stored page, as listed by the `recent` and `changed` reports.
+    fn page() -> PageOutput {
+        return PageOutput {
+            url: "https://example.com/".to_string(),
+            crawl_time: "2024-05-01T12:00:00+00:00".to_string(),
+            last_changed: None,
+            status_code: Some(200),
+            title: Some("Example".to_string()),
+        };
+    }
+
+    #[test]
+    fn recent_snapshot() {
+        let report = RecentReport {
+            since: "2024-05-01T00:00:00+00:00".to_string(),
+            pages: vec![page()],
+        };
+        assert_snapshot(RecentReport::NAME, report, RECENT);
+    }
+
+    #[test]
+    fn changed_snapshot() {
+        let report = ChangedReport {
+            since: "2024-05-01T00:00:00+00:00".to_string(),
+            pages: vec![page()],
+        };
+        assert_snapshot(ChangedReport::NAME, report, CHANGED);
+    }
+
+    #[test]
+    fn generation_snapshot() {
+        let report = GenerationReport {
+            generation: 1,
+            error_pages: 0,
+            mean_response_ms: Some(42),
+            pages: vec![GenerationPageOutput {
+                url: "https://example.com/about".to_string(),
+                batch_id: Some(3),
+                status_code: Some(200),
+                response_ms: Some(42),
+                bot_challenged: false,
+            }],
+            failures: vec![GenerationFailureOutput {
+                url: "https://example.com/slow".to_string(),
+                batch_id: Some(3),
+                error_kind: "timeout".to_string(),
+                message: "operation timed out".to_string(),
+            }],
+        };
+        assert_snapshot(GenerationReport::NAME, report, GENERATION);
+    }
+
+    #[test]
+    fn redirects_snapshot() {
+        let report = RedirectsReport {
+            url: "https://example.com/new".to_string(),
+            redirects: vec![RedirectOutput {
+                url: "https://example.com/old".to_string(),
+                next: Some("https://example.com/new".to_string()),
+                status_code: Some(301),
+                hops: Some(1),
+            }],
+        };
+        assert_snapshot(RedirectsReport::NAME, report, REDIRECTS);
+    }
+
+    #[test]
+    fn broken_links_snapshot() {
+        let report = BrokenLinksReport {
+            links: vec![BrokenLinkOutput {
+                source: "https://example.com/".to_string(),
+                target: "https://example.com/missing".to_string(),
+                kind: "page".to_string(),
+                status_code: Some(404),
+                error_kind: None,
+            }],
+        };
+        assert_snapshot(BrokenLinksReport::NAME, report, BROKEN_LINKS);
+    }
+
+    #[test]
+    fn explain_snapshot() {
+        let report = ExplainReport {
+            url: "https://example.com/private/".to_string(),
+            stored: false,
+            steps: vec![ExplainStepOutput {
+                stage: "robots".to_string(),
+                detail: "Disallow: /private/".to_string(),
+            }],
+        };
+        assert_snapshot(ExplainReport::NAME, report, EXPLAIN);
+    }
+
+    #[test]
+    fn compliance_snapshot() {
+        let report = ComplianceReport {
+            url: "https://example.com/".to_string(),
+            crawl_time: "2024-05-01T12:00:00+00:00".to_string(),
+            robots_rule: "Allow: /".to_string(),
+            snapshot_id: 1,
+            domain: "example.com".to_string(),
+            robots_fetch_time: "2024-05-01T11:59:00+00:00".to_string(),
+            robots_override: None,
+            robots: "User-agent: *\nAllow: /\n".to_string(),
+        };
+        assert_snapshot(ComplianceReport::NAME, report, COMPLIANCE);
+    }
+}
@@ -0,0 +1,194 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{info, Log, Metadata, Record};
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The interval at which the progress bar is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The interval at which the progress display checks whether the crawl is finished.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The progress bars drawn on the terminal, shared with the logger so that log lines are printed
+/// above them instead of through them.
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Gets the progress bars drawn on the terminal.
+fn bars() -> &'static MultiProgress {
+    return BARS.get_or_init(MultiProgress::new);
+}
+
+/// Represents the progress of a running crawl.
+pub struct ProgressStatus {
+    /// The deepest depth level being fetched, the origin being 0.
+    pub depth: u64,
+    /// The number of pages fetched so far at the current depth level.
+    pub level_pages: u64,
+    /// The number of queued URLs whose fetch hasn't started yet.
+    pub frontier_size: u64,
+    /// The number of fetches that failed so far.
+    pub errors: u64,
+    /// The number of pages fetched so far in total.
+    pub pages_fetched: u64,
+}
+
+impl ProgressStatus {
+    /// Formats the status along with the rate pages were fetched at.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - The time since the crawl started.
+    fn describe(&self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64();
+        let pages_per_sec = if secs > 0.0 {
+            self.pages_fetched as f64 / secs
+        } else {
+            0.0
+        };
+        return format!("{}, {:.1} pages/s", self, pages_per_sec);
+    }
+}
+
+impl fmt::Display for ProgressStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "depth {}: {} pages fetched, {} URLs left, {} errors",
+            self.depth, self.level_pages, self.frontier_size, self.errors
+        );
+    }
+}
+
+/// A logger that prints through the progress bars, so that log lines don't interleave with
+/// them.
+pub(crate) struct ProgressLogger {
+    /// The logger that formats and prints the lines.
+    inner: Box<dyn Log>,
+}
+
+impl ProgressLogger {
+    /// Wraps a logger so that it prints through the progress bars.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The logger that formats and prints the lines.
+    pub(crate) fn new(inner: Box<dyn Log>) -> Self {
+        return ProgressLogger { inner };
+    }
+}
+
+impl Log for ProgressLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        return self.inner.enabled(metadata);
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.enabled(record.metadata()) {
+            bars().suspend(|| self.inner.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Displays the progress of a crawl, as a progress bar when stderr is a terminal, or else as a
+/// status line logged at a regular interval. The progress bar is cleared once it is dropped.
+struct ProgressDisplay {
+    /// The progress bar, if stderr is a terminal.
+    bar: Option<ProgressBar>,
+    /// The interval between updates.
+    interval: Duration,
+    /// The instant the crawl started.
+    start: Instant,
+}
+
+impl ProgressDisplay {
+    /// Starts displaying the progress of a crawl.
+    ///
+    /// # Arguments
+    ///
+    /// * `status_interval` - The interval between status lines when stderr is not a terminal.
+    fn new(status_interval: Duration) -> Self {
+        if !std::io::stderr().is_terminal() {
+            return ProgressDisplay {
+                bar: None,
+                interval: status_interval,
+                start: Instant::now(),
+            };
+        }
+        let bar = bars().add(ProgressBar::new_spinner());
+        bar.set_style(ProgressStyle::with_template("{spinner} {elapsed} {msg}").unwrap());
+        return ProgressDisplay {
+            bar: Some(bar),
+            interval: REDRAW_INTERVAL,
+            start: Instant::now(),
+        };
+    }
+
+    /// Shows the current progress of the crawl.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The current `ProgressStatus` of the crawl.
+    fn update(&self, status: ProgressStatus) {
+        let line = status.describe(self.start.elapsed());
+        match &self.bar {
+            Some(bar) => {
+                bar.set_message(line);
+                bar.tick();
+            }
+            None => info!("Progress: {}", line),
+        }
+    }
+}
+
+impl Drop for ProgressDisplay {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+            bars().remove(bar);
+        }
+    }
+}
+
+/// Displays the progress of a crawl from the current thread until `stop` is set.
+///
+/// # Arguments
+///
+/// * `stop` - A reference to the flag set once the crawl is finished.
+/// * `status_interval` - The interval between status lines when stderr is not a terminal.
+/// * `status` - Reads the current progress of the crawl.
+pub(crate) fn run(
+    stop: &AtomicBool,
+    status_interval: Duration,
+    status: impl Fn() -> ProgressStatus,
+) {
+    let display = ProgressDisplay::new(status_interval);
+    let mut last_update = Instant::now();
+    while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(STOP_CHECK_INTERVAL);
+        if last_update.elapsed() >= display.interval {
+            last_update = Instant::now();
+            display.update(status());
+        }
+    }
+}
+
+/// Displays the progress of a crawl from an async task, until the future is dropped.
+///
+/// # Arguments
+///
+/// * `status_interval` - The interval between status lines when stderr is not a terminal.
+/// * `status` - Reads the current progress of the crawl.
+pub(crate) async fn run_async(status_interval: Duration, status: impl Fn() -> ProgressStatus) {
+    let display = ProgressDisplay::new(status_interval);
+    loop {
+        tokio::time::sleep(display.interval).await;
+        display.update(status());
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The schemes of the proxies a crawl can go through.
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// The messages of the errors the HTTP client raises when a proxy rejects its credentials: an
+/// HTTP proxy answering the `CONNECT` of an `https` URL with a 407, or a SOCKS5 proxy refusing
+/// the username and password, or asking for some.
+const AUTH_FAILURE_MESSAGES: &[&str] = &[
+    "proxy authentication required",
+    "Password auth failure",
+    "No acceptable auth methods",
+    "Authorization required",
+];
+
+/// The proxy every request of a crawl goes through.
+///
+/// When no proxy is configured, the `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`
+/// environment variables are used instead, and they are ignored once one is.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ProxyConfig {
+    /// The URL of the proxy, such as `http://proxy.corp:3128` or `socks5h://127.0.0.1:1080`.
+    /// `socks5h` resolves host names through the proxy, and `socks5` resolves them locally.
+    pub url: String,
+    /// The username sent to the proxy.
+    pub username: Option<String>,
+    /// The password sent to the proxy along with `username`.
+    pub password: Option<String>,
+    /// The hosts reached directly rather than through the proxy, such as `localhost`,
+    /// `.corp.example` for a domain and its subdomains, or `10.0.0.0/8`.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Checks the proxy URL.
+    ///
+    /// # Returns
+    ///
+    /// The problems found, each as a sentence to list in the validation error of the config.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match Url::parse(&self.url) {
+            Ok(url) => {
+                if !PROXY_SCHEMES.contains(&url.scheme()) {
+                    problems.push(format!(
+                        "proxy.url '{}' must use the {} scheme",
+                        self.url,
+                        PROXY_SCHEMES.join(", ")
+                    ));
+                }
+                if !url.has_host() {
+                    problems.push(format!("proxy.url '{}' has no host", self.url));
+                }
+            }
+            Err(e) => problems.push(format!(
+                "proxy.url '{}' is not a valid URL: {}",
+                self.url, e
+            )),
+        }
+        if self.password.is_some() && self.username.is_none() {
+            problems.push("proxy.password needs a proxy.username".to_string());
+        }
+        return problems;
+    }
+
+    /// Builds the proxy that the HTTP clients of a crawl are given.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the proxy URL is invalid.
+    pub(crate) fn build(&self) -> Result<reqwest::Proxy> {
+        let mut proxy = reqwest::Proxy::all(&self.url)
+            .with_context(|| format!("Invalid proxy URL '{}'", self.url))?;
+        if let Some(username) = &self.username {
+            proxy = proxy.basic_auth(username, self.password.as_deref().unwrap_or_default());
+        }
+        return Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(","))));
+    }
+}
+
+/// Checks whether the message of an error is that of a proxy rejecting the credentials of the
+/// crawler, rather than of the target site failing.
+///
+/// # Arguments
+///
+/// * `message` - A string slice that holds the message of the error.
+pub fn is_auth_failure(message: &str) -> bool {
+    return AUTH_FAILURE_MESSAGES
+        .iter()
+        .any(|failure| message.contains(failure));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_auth_failures_are_recognized() {
+        assert!(is_auth_failure("proxy authentication required"));
+        assert!(is_auth_failure("Password auth failure, code: 1"));
+        assert!(!is_auth_failure("Connection refused (os error 111)"));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::normalize::ascii_host;
+use publicsuffix::{List, Psl};
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+/// A snapshot of the Public Suffix List, from https://publicsuffix.org/list/public_suffix_list.dat.
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+/// Gets the Public Suffix List, parsing it the first time it is needed.
+fn list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    return LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("The embedded Public Suffix List is valid")
+    });
+}
+
+/// Gets the registrable domain of a host: its public suffix, such as `com` or `co.uk`, along
+/// with the label before it. Hosts under the same registrable domain belong to the same site.
+///
+/// # Arguments
+///
+/// * `host` - A string slice that holds the host, in either its Unicode or its ASCII form.
+///
+/// # Returns
+///
+/// The registrable domain in ASCII form, or `None` if the host is an IP address, a single label
+/// such as an intranet host, or a public suffix itself.
+pub fn registrable_domain(host: &str) -> Option<String> {
+    let host = host.trim_end_matches('.');
+    let unbracketed = host.trim_start_matches('[').trim_end_matches(']');
+    if unbracketed.parse::<IpAddr>().is_ok() || !host.contains('.') {
+        return None;
+    }
+
+    let host = ascii_host(host);
+    let domain = list().domain(host.as_bytes())?;
+    return Some(String::from_utf8_lossy(domain.as_bytes()).into_owned());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registrable_domains_are_found() {
+        let domain = |host| registrable_domain(host);
+        assert_eq!(domain("blog.example.com").as_deref(), Some("example.com"));
+        assert_eq!(
+            domain("foo.example.co.uk").as_deref(),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            domain("Shop.Bücher.example").as_deref(),
+            Some("xn--bcher-kva.example")
+        );
+        assert_eq!(domain("co.uk"), None);
+        assert_eq!(domain("127.0.0.1"), None);
+        assert_eq!(domain("[::1]"), None);
+        assert_eq!(domain("intranet"), None);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use url::Url;
+
+/// The kinds of loops a redirect chain can run into.
+#[derive(PartialEq, Eq, Debug)]
+pub enum RedirectLoop {
+    /// The chain alternates between the `http` and `https` variants of the same host and path,
+    /// which points at a broken TLS redirect configuration on the site.
+    SchemeFlip,
+    /// The chain comes back to a URL it already visited.
+    Cycle,
+}
+
+/// Tracks the URLs visited by a chain of redirects, to detect loops.
+///
+/// URLs are compared by their scheme, host and path, so that redirects that only shuffle
+/// query parameters around are still caught.
+#[derive(Default)]
+pub struct RedirectChain {
+    /// The (scheme, host, path) of every URL visited so far, in order.
+    visited: Vec<(String, String, String)>,
+}
+
+impl RedirectChain {
+    /// Creates a new `RedirectChain` starting at the given URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A reference to the URL the chain starts at.
+    pub fn new(url: &Url) -> Self {
+        let mut chain = RedirectChain::default();
+        chain.visited.push(Self::key(url));
+        return chain;
+    }
+
+    /// Records the next URL of the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A reference to the URL being redirected to.
+    ///
+    /// # Returns
+    ///
+    /// The kind of loop the chain ran into, or `None` if `url` wasn't visited before.
+    pub fn visit(&mut self, url: &Url) -> Option<RedirectLoop> {
+        let key = Self::key(url);
+        let start = self.visited.iter().position(|visited| *visited == key);
+        self.visited.push(key);
+
+        // Look for the same host and path under different schemes within the loop
+        let start = start?;
+        let mut schemes: HashMap<(&str, &str), &str> = HashMap::new();
+        for (scheme, host, path) in &self.visited[start..] {
+            match schemes.insert((host.as_str(), path.as_str()), scheme.as_str()) {
+                Some(previous) if previous != scheme => return Some(RedirectLoop::SchemeFlip),
+                _ => {}
+            }
+        }
+        return Some(RedirectLoop::Cycle);
+    }
+
+    /// Gets the number of redirects followed so far.
+    pub fn redirects(&self) -> usize {
+        return self.visited.len() - 1;
+    }
+
+    /// Gets the (scheme, host, path) that URLs are compared by.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A reference to the URL.
+    fn key(url: &Url) -> (String, String, String) {
+        return (
+            url.scheme().to_string(),
+            url.host_str().unwrap_or_default().to_string(),
+            url.path().to_string(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follows a chain of redirects through the given URLs, returning what each hop ran into.
+    fn follow(urls: &[&str]) -> Vec<Option<RedirectLoop>> {
+        let mut chain = RedirectChain::new(&Url::parse(urls[0]).unwrap());
+        return urls[1..]
+            .iter()
+            .map(|url| chain.visit(&Url::parse(url).unwrap()))
+            .collect();
+    }
+
+    #[test]
+    fn scheme_flip_flops_are_detected() {
+        assert_eq!(
+            follow(&[
+                "http://example.com/x",
+                "https://example.com/x",
+                "http://example.com/x",
+            ]),
+            [None, Some(RedirectLoop::SchemeFlip)]
+        );
+    }
+
+    #[test]
+    fn cycles_without_a_scheme_change_are_plain_cycles() {
+        assert_eq!(
+            follow(&[
+                "https://example.com/a",
+                "https://example.com/b?from=a",
+                "https://example.com/a?from=b",
+            ]),
+            [None, Some(RedirectLoop::Cycle)]
+        );
+    }
+
+    #[test]
+    fn upgrades_to_https_are_not_loops() {
+        assert_eq!(
+            follow(&[
+                "http://example.com/x",
+                "https://example.com/x",
+                "https://www.example.com/x",
+            ]),
+            [None, None]
+        );
+        assert_eq!(
+            follow(&["http://example.com/", "https://example.com/"]).len(),
+            1
+        );
+    }
+}
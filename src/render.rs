@@ -0,0 +1,144 @@
+use select::document::Document;
+use select::predicate::Any;
+use serde::{Deserialize, Serialize};
+
+/// The `id`s commonly given to the empty container that a client-side framework renders into.
+const ROOT_IDS: &[&str] = &["app", "root", "__next", "__nuxt", "svelte"];
+
+/// The attributes that client-side frameworks add to the elements they render.
+const FRAMEWORK_MARKERS: &[&str] = &[
+    "data-reactroot",
+    "ng-version",
+    "ng-app",
+    "data-v-app",
+    "data-server-rendered",
+];
+
+/// The weights of the heuristics combined into a page's `js_dependency_score`.
+///
+/// Every heuristic yields a value between 0 and 1 that is multiplied by its weight, and the
+/// products are summed. With the default weights, which sum to 1, a static page scores 0 and a
+/// bare client-side rendered shell scores close to 1.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct JsWeights {
+    /// The weight of the share of script bytes in the page's script and body text bytes.
+    pub script_ratio: f64,
+    /// The weight of an empty framework root container, such as `<div id="app"></div>`.
+    pub empty_root: f64,
+    /// The weight of a `<noscript>` element, usually a warning that JavaScript is required.
+    pub noscript: f64,
+    /// The weight of framework markers such as `data-reactroot` or `ng-version`.
+    pub framework_marker: f64,
+    /// The number of bytes each external `<script src>` is assumed to weigh, as its size is not
+    /// known without fetching it.
+    pub external_script_bytes: usize,
+}
+
+impl Default for JsWeights {
+    fn default() -> Self {
+        return JsWeights {
+            script_ratio: 0.4,
+            empty_root: 0.3,
+            noscript: 0.1,
+            framework_marker: 0.2,
+            external_script_bytes: 10_000,
+        };
+    }
+}
+
+/// Estimates how much a page depends on JavaScript to render its content.
+///
+/// The document is walked once, measuring the visible text and the script bytes of the page, and
+/// looking for empty framework root containers, `<noscript>` elements and framework markers.
+/// These are combined using the `weights`; see `JsWeights` for the meaning of each.
+///
+/// # Arguments
+///
+/// * `document` - A reference to the parsed HTML `Document` of the page.
+/// * `weights` - A reference to the `JsWeights` used to combine the heuristics.
+///
+/// # Returns
+///
+/// The `js_dependency_score` of the page, where higher values mean the crawl sees less of it.
+pub fn js_dependency_score(document: &Document, weights: &JsWeights) -> f64 {
+    let mut text_bytes = 0;
+    let mut script_bytes = 0;
+    let mut empty_root = false;
+    let mut noscript = false;
+    let mut framework_marker = false;
+
+    for node in document.find(Any) {
+        // Text is either script source or visible text, depending on its parent
+        if let Some(text) = node.as_text() {
+            match node.parent().and_then(|parent| parent.name()) {
+                Some("script") => script_bytes += text.len(),
+                Some("style" | "noscript" | "title" | "template") => {}
+                _ => text_bytes += text.trim().len(),
+            }
+            continue;
+        }
+
+        match node.name() {
+            Some("script") if node.attr("src").is_some() => {
+                script_bytes += weights.external_script_bytes
+            }
+            Some("noscript") => noscript = true,
+            Some("div") => {
+                let is_root = node.attr("id").is_some_and(|id| ROOT_IDS.contains(&id));
+                if is_root
+                    && node
+                        .children()
+                        .all(|child| child.as_text().is_some_and(|t| t.trim().is_empty()))
+                {
+                    empty_root = true;
+                }
+            }
+            _ => {}
+        }
+        if node
+            .attrs()
+            .any(|(name, _)| FRAMEWORK_MARKERS.contains(&name))
+        {
+            framework_marker = true;
+        }
+    }
+
+    let script_ratio = if script_bytes + text_bytes == 0 {
+        0.0
+    } else {
+        script_bytes as f64 / (script_bytes + text_bytes) as f64
+    };
+
+    return weights.script_ratio * script_ratio
+        + weights.empty_root * empty_root as u8 as f64
+        + weights.noscript * noscript as u8 as f64
+        + weights.framework_marker * framework_marker as u8 as f64;
+}
+
+/// Extracts the visible text of a page.
+///
+/// The text of `<script>`, `<style>`, `<noscript>`, `<title>` and `<template>` elements is left
+/// out, and the remaining text is joined by single spaces.
+///
+/// # Arguments
+///
+/// * `document` - A reference to the parsed HTML `Document` of the page.
+///
+/// # Returns
+///
+/// The visible text of the page.
+pub fn visible_text(document: &Document) -> String {
+    return document
+        .find(Any)
+        .filter(|node| {
+            !matches!(
+                node.parent().and_then(|parent| parent.name()),
+                Some("script" | "style" | "noscript" | "title" | "template")
+            )
+        })
+        .filter_map(|node| node.as_text())
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<&str>>()
+        .join(" ");
+}
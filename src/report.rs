@@ -0,0 +1,816 @@
+use crate::crawl_run::CrawlRun;
+use crate::database::Database;
+use crate::fetch_failure::FetchFailure;
+use crate::session::CrawlSession;
+use crate::site::DomainSummary;
+use crate::trap::TrapRule;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// The page an HTML crawl report is written into, in place of its `{{title}}`, `{{generated}}`
+/// and `{{body}}`. It is embedded in the binary, and so is its stylesheet, so that the report
+/// can be opened anywhere.
+const HTML_TEMPLATE: &str = include_str!("report_template.html");
+
+/// The title of a crawl report.
+const REPORT_TITLE: &str = "Rustle crawl report";
+
+/// The number of pages listed in the outbound links table of a crawl report.
+const TOP_PAGES: usize = 10;
+
+/// The width of the longest bar of the depth histogram of a Markdown crawl report.
+const DEPTH_BAR_WIDTH: u64 = 40;
+
+/// Represents a URL whose fetch failed, along with why.
+pub struct FetchError {
+    /// A string that holds the URL that could not be fetched.
+    pub url: String,
+    /// A string that holds the reason the fetch failed.
+    pub reason: String,
+    /// The category of the reason the fetch failed.
+    pub category: ErrorCategory,
+}
+
+/// The categories that failed fetches are counted by.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ErrorCategory {
+    /// The request or the response body took longer than `timeout_secs`.
+    Timeout,
+    /// The connection to the server could not be established.
+    Connection,
+    /// The proxy rejected the credentials of the crawler, or asked for some.
+    ProxyAuth,
+    /// The redirects of the URL loop or are longer than `max_redirects`.
+    Redirect,
+    /// The response body is over `max_body_bytes`.
+    TooLarge,
+    /// The response body arrived slower than `min_transfer_rate`.
+    TooSlow,
+    /// The URL doesn't parse or has an unsupported scheme.
+    InvalidUrl,
+    /// Any other failure of the request or of reading the response.
+    Other,
+}
+
+impl ErrorCategory {
+    /// Whether a failure of this category may go away by itself, so that the fetch is worth
+    /// retrying: a timeout, a failed connection or a body that arrived too slowly.
+    pub fn is_transient(&self) -> bool {
+        return matches!(
+            self,
+            ErrorCategory::Timeout | ErrorCategory::Connection | ErrorCategory::TooSlow
+        );
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::ProxyAuth => "proxy auth",
+            ErrorCategory::Redirect => "redirect",
+            ErrorCategory::TooLarge => "too large",
+            ErrorCategory::TooSlow => "too slow",
+            ErrorCategory::InvalidUrl => "invalid URL",
+            ErrorCategory::Other => "other",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// Represents the response times of the requests of a crawl, from sending a request to
+/// receiving the headers of its response.
+pub struct LatencyStats {
+    /// The mean response time.
+    pub mean: Duration,
+    /// The median response time.
+    pub p50: Duration,
+    /// The response time that 90% of the requests were faster than or as fast as.
+    pub p90: Duration,
+    /// The response time that 99% of the requests were faster than or as fast as.
+    pub p99: Duration,
+    /// The slowest response time.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Computes the response time statistics of a set of requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - A mutable slice of the response time of every request, which is sorted.
+    ///
+    /// # Returns
+    ///
+    /// The `LatencyStats` of the requests, or `None` if there are none.
+    ///
+    /// ```
+    /// use rustle::LatencyStats;
+    /// use std::time::Duration;
+    ///
+    /// let mut samples: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+    /// let latency = LatencyStats::from_samples(&mut samples).unwrap();
+    /// assert_eq!(latency.p50, Duration::from_millis(50));
+    /// assert_eq!(latency.p99, Duration::from_millis(99));
+    /// assert_eq!(latency.max, Duration::from_millis(100));
+    /// assert!(LatencyStats::from_samples(&mut []).is_none());
+    /// ```
+    pub fn from_samples(samples: &mut [Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let total: Duration = samples.iter().sum();
+
+        // Take the nearest rank, the smallest sample that at least the given percent of samples
+        // are smaller than or equal to
+        let percentile = |percent: usize| {
+            let rank = (samples.len() * percent).div_ceil(100);
+            return samples[rank.max(1) - 1];
+        };
+        return Some(LatencyStats {
+            mean: total / samples.len() as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        });
+    }
+}
+
+/// Represents the outcome of a finished crawl, as printed once it ends.
+pub struct CrawlReport {
+    /// The crawl session recorded in the database.
+    pub session: CrawlSession,
+    /// A `u64` that holds the number of pages successfully fetched.
+    pub pages_fetched: u64,
+    /// A `u64` that holds the number of URLs skipped because their stored copy was still fresh.
+    pub pages_cached: u64,
+    /// A `u64` that holds the number of URLs skipped because robots.txt disallows them.
+    pub pages_blocked_by_robots: u64,
+    /// The number of distinct URLs rejected as crawler traps, by the rule that rejected them.
+    pub trap_rejections: BTreeMap<TrapRule, u64>,
+    /// The hosts, or registrable domains with the `site` scope, whose pages stopped being fetched
+    /// once they reached `max_pages_per_domain`.
+    pub truncated_domains: Vec<String>,
+    /// The URLs that could not be fetched, in the order they failed.
+    pub fetch_errors: Vec<FetchError>,
+    /// A `u64` that holds the number of URLs that failed transiently and were fetched by the
+    /// retry pass at the end of the crawl.
+    pub recovered_by_retry: u64,
+    /// A `u64` that holds the number of body bytes downloaded.
+    pub bytes_downloaded: u64,
+    /// A `u64` that holds the number of HTTP requests sent to fetch pages, redirects included.
+    pub requests_sent: u64,
+    /// A `u64` that holds the number of those requests whose TLS certificate wasn't validated,
+    /// because of `danger_accept_invalid_certs` or `trusted_hosts`.
+    pub relaxed_tls_requests: u64,
+    /// The response times of the requests sent to fetch pages, if any were sent.
+    pub latency: Option<LatencyStats>,
+    /// A `u64` that holds the deepest generation of links the crawl reached, the origin being 0.
+    pub max_depth: u64,
+    /// A `usize` that holds the number of pages fetched at once.
+    pub concurrency: usize,
+    /// The time the crawl took.
+    pub elapsed: Duration,
+    /// The number of sites the crawl stored by the class of their status code, where `None`
+    /// counts the sites stored without a status code.
+    pub stored_sites: BTreeMap<Option<i64>, u64>,
+    /// A `u64` that holds the number of domains the crawl stored.
+    pub stored_domains: u64,
+    /// The sites the crawl stored by host, the host with the most sites first.
+    pub stored_by_domain: Vec<DomainSummary>,
+}
+
+impl CrawlReport {
+    /// Whether no page could be fetched at all, although some fetches were attempted.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating whether every fetch of the crawl failed.
+    pub fn all_fetches_failed(&self) -> bool {
+        return self.pages_fetched == 0 && !self.fetch_errors.is_empty();
+    }
+
+    /// Gets the number of pages successfully fetched per second of the crawl.
+    pub fn pages_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        return self.pages_fetched as f64 / secs;
+    }
+
+    /// Counts the failed fetches by the category of their reason.
+    ///
+    /// # Returns
+    ///
+    /// A `BTreeMap<ErrorCategory, u64>` holding the number of failed fetches of every category
+    /// with at least one.
+    pub fn errors_by_category(&self) -> BTreeMap<ErrorCategory, u64> {
+        let mut categories = BTreeMap::new();
+        for error in &self.fetch_errors {
+            *categories.entry(error.category).or_default() += 1;
+        }
+        return categories;
+    }
+}
+
+impl fmt::Display for CrawlReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stored_sites: u64 = self.stored_sites.values().sum();
+        let classes: Vec<String> = self
+            .stored_sites
+            .iter()
+            .map(|(class, count)| match class {
+                Some(class) => format!("{} {}xx", count, class),
+                None => format!("{} unknown", count),
+            })
+            .collect();
+
+        let categories: Vec<String> = self
+            .errors_by_category()
+            .iter()
+            .map(|(category, count)| format!("{} {}", count, category))
+            .collect();
+
+        writeln!(f, "Crawl finished in {:.1}s", self.elapsed.as_secs_f64())?;
+        writeln!(f, "  Pages fetched:           {}", self.pages_fetched)?;
+        writeln!(
+            f,
+            "  Pages per second:        {:.1}",
+            self.pages_per_second()
+        )?;
+        writeln!(f, "  Pages skipped as cached: {}", self.pages_cached)?;
+        writeln!(
+            f,
+            "  Pages blocked by robots: {}",
+            self.pages_blocked_by_robots
+        )?;
+        let trap_rules: Vec<String> = self
+            .trap_rejections
+            .iter()
+            .map(|(rule, count)| format!("{} {}", count, rule))
+            .collect();
+        if trap_rules.is_empty() {
+            writeln!(f, "  Trap URLs rejected:      0")?;
+        } else {
+            writeln!(
+                f,
+                "  Trap URLs rejected:      {} ({})",
+                self.trap_rejections.values().sum::<u64>(),
+                trap_rules.join(", ")
+            )?;
+        }
+        if !self.truncated_domains.is_empty() {
+            writeln!(
+                f,
+                "  Truncated by budget:     {} ({})",
+                self.truncated_domains.len(),
+                self.truncated_domains.join(", ")
+            )?;
+        }
+        if categories.is_empty() {
+            writeln!(f, "  Fetch errors:            0")?;
+        } else {
+            writeln!(
+                f,
+                "  Fetch errors:            {} ({})",
+                self.fetch_errors.len(),
+                categories.join(", ")
+            )?;
+        }
+        writeln!(f, "  Recovered on retry:      {}", self.recovered_by_retry)?;
+        writeln!(f, "  Requests sent:           {}", self.requests_sent)?;
+        if self.relaxed_tls_requests > 0 {
+            writeln!(
+                f,
+                "  Unvalidated TLS:         {} requests",
+                self.relaxed_tls_requests
+            )?;
+        }
+        if let Some(latency) = &self.latency {
+            writeln!(
+                f,
+                "  Response time:           mean {} ms, p50 {} ms, p90 {} ms, p99 {} ms, max {} ms",
+                latency.mean.as_millis(),
+                latency.p50.as_millis(),
+                latency.p90.as_millis(),
+                latency.p99.as_millis(),
+                latency.max.as_millis()
+            )?;
+        }
+        writeln!(f, "  Bytes downloaded:        {}", self.bytes_downloaded)?;
+        writeln!(f, "  Max depth reached:       {}", self.max_depth)?;
+        writeln!(f, "  Concurrency:             {}", self.concurrency)?;
+        if classes.is_empty() {
+            writeln!(f, "  Sites stored:            {}", stored_sites)?;
+        } else {
+            writeln!(
+                f,
+                "  Sites stored:            {} ({})",
+                stored_sites,
+                classes.join(", ")
+            )?;
+        }
+        writeln!(f, "  Domains stored:          {}", self.stored_domains)?;
+        write!(f, "  Session:                 {}", self.session.status)?;
+        if !self.stored_by_domain.is_empty() {
+            write!(f, "\n  Sites by domain:")?;
+            for line in DomainSummary::table(&self.stored_by_domain) {
+                write!(f, "\n    {}", line)?;
+            }
+        }
+
+        for error in &self.fetch_errors {
+            write!(f, "\n  Failed {}: {}", error.url, error.reason)?;
+        }
+        return Ok(());
+    }
+}
+
+/// The formats a crawl report can be written in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A Markdown document, readable as text and rendered by code hosts.
+    Markdown,
+    /// A self-contained HTML page.
+    Html,
+}
+
+impl ReportFormat {
+    /// Picks the format of a report from the extension of the file it is written to: HTML for
+    /// `.html` and `.htm`, and Markdown otherwise.
+    ///
+    /// ```
+    /// use rustle::ReportFormat;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(ReportFormat::of(Path::new("crawl.HTML")), ReportFormat::Html);
+    /// assert_eq!(ReportFormat::of(Path::new("crawl.md")), ReportFormat::Markdown);
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path of the report.
+    pub fn of(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        return match extension.as_deref() {
+            Some("html" | "htm") => ReportFormat::Html,
+            _ => ReportFormat::Markdown,
+        };
+    }
+}
+
+/// A section of a crawl report, with a heading.
+struct Section {
+    /// The heading of the section.
+    title: &'static str,
+    /// The contents of the section, in order.
+    blocks: Vec<Block>,
+}
+
+/// A part of a section of a crawl report.
+enum Block {
+    /// A paragraph of text.
+    Text(String),
+    /// A list of names along with their values.
+    Facts(Vec<(&'static str, String)>),
+    /// A table with a header row.
+    Table {
+        /// The names of the columns.
+        header: Vec<&'static str>,
+        /// The cells of every row.
+        rows: Vec<Vec<String>>,
+    },
+    /// A bar chart of counts, by label.
+    Histogram(Vec<(String, u64)>),
+}
+
+/// Writes a crawl report to a file: the run, the totals, the sites of every host, the pages by
+/// depth, the pages with the most outbound links, the failed fetches and the broken links, if
+/// any were found.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` the crawl is stored in.
+/// * `report` - The `CrawlReport` of the crawl that just finished, or `None` to report on the
+///   latest run recorded in the database.
+/// * `include_suspect` - Whether the latest run may be one whose crawl was marked as suspect,
+///   which is otherwise skipped for the run before it.
+/// * `format` - The `ReportFormat` of the report.
+/// * `path` - A reference to the path of the file the report is written to.
+///
+/// # Errors
+///
+/// This function will return an error if the database can't be read, or the file can't be
+/// written.
+pub fn write_report(
+    database: &Database,
+    report: Option<&CrawlReport>,
+    include_suspect: bool,
+    format: ReportFormat,
+    path: &Path,
+) -> Result<()> {
+    let contents = render_report(database, report, include_suspect, format)?;
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write the crawl report to {}", path.display()))?;
+    return Ok(());
+}
+
+/// Renders a crawl report, like `write_report`.
+///
+/// ```
+/// use rustle::{render_report, Database, ReportFormat, Site, Storage};
+///
+/// let db = Database::in_memory()?;
+/// db.setup()?;
+/// db.put_site(&Site {
+///     url: "https://example.com/".to_string(),
+///     links_to: ["https://example.com/about".to_string()].into(),
+///     status_code: Some(200),
+///     depth: Some(0),
+///     ..Default::default()
+/// })?;
+///
+/// let markdown = render_report(&db, None, false, ReportFormat::Markdown)?;
+/// assert!(markdown.starts_with("# Rustle crawl report\n"));
+/// assert!(markdown.contains("| example.com | 1 |"));
+///
+/// let html = render_report(&db, None, false, ReportFormat::Html)?;
+/// assert!(html.contains("<h2>Pages by depth</h2>"));
+/// # Ok::<(), rustle::Error>(())
+/// ```
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` the crawl is stored in.
+/// * `report` - The `CrawlReport` of the crawl that just finished, or `None` to report on the
+///   latest run recorded in the database.
+/// * `include_suspect` - Whether the latest run may be one whose crawl was marked as suspect,
+///   which is otherwise skipped for the run before it.
+/// * `format` - The `ReportFormat` of the report.
+///
+/// # Errors
+///
+/// This function will return an error if the database can't be read.
+pub fn render_report(
+    database: &Database,
+    report: Option<&CrawlReport>,
+    include_suspect: bool,
+    format: ReportFormat,
+) -> Result<String> {
+    let sections = report_sections(database, report, include_suspect)?;
+    let generated = timestamp(Utc::now());
+    return Ok(match format {
+        ReportFormat::Markdown => render_markdown(&sections, &generated),
+        ReportFormat::Html => render_html(&sections, &generated),
+    });
+}
+
+/// Gathers the sections of a crawl report from the database and the report of the crawl.
+///
+/// # Arguments
+///
+/// * `database` - A reference to the `Database` the crawl is stored in.
+/// * `report` - The `CrawlReport` of the crawl, if it just finished.
+/// * `include_suspect` - Whether the latest run recorded may be one marked as suspect.
+fn report_sections(
+    database: &Database,
+    report: Option<&CrawlReport>,
+    include_suspect: bool,
+) -> Result<Vec<Section>> {
+    let mut sections = Vec::new();
+
+    // The run, from the report of the crawl or else from the latest run recorded, skipping
+    // suspect runs unless asked not to
+    let latest_run = CrawlRun::read_latest(1, include_suspect, database)?;
+    let run = match report {
+        Some(report) => Block::Facts(vec![
+            ("Origin", report.session.origin_url.clone()),
+            ("Started", timestamp(report.session.start_time)),
+            ("Finished", timestamp(report.session.end_time)),
+            ("Duration", format!("{:.1}s", report.elapsed.as_secs_f64())),
+            ("Status", report.session.status.to_string()),
+            ("Concurrency", report.concurrency.to_string()),
+        ]),
+        None => match latest_run.first() {
+            Some(run) => Block::Facts(vec![
+                ("Origin", run.origin_url.clone()),
+                ("Started", timestamp(run.started_at)),
+                (
+                    "Finished",
+                    run.finished_at.map_or("unfinished".to_string(), timestamp),
+                ),
+                ("Depth", run.depth.to_string()),
+            ]),
+            None => Block::Text("No crawl run is recorded in the database.".to_string()),
+        },
+    };
+    sections.push(Section {
+        title: "Run",
+        blocks: vec![run],
+    });
+
+    // The totals, along with those only known right after the crawl
+    let stored_sites = database.summarize_site_table(None)?;
+    let classes: Vec<String> = stored_sites
+        .iter()
+        .map(|(class, count)| match class {
+            Some(class) => format!("{} {}xx", count, class),
+            None => format!("{} unknown", count),
+        })
+        .collect();
+    let mut totals = vec![
+        (
+            "Sites stored",
+            match classes.is_empty() {
+                true => "0".to_string(),
+                false => format!(
+                    "{} ({})",
+                    stored_sites.values().sum::<u64>(),
+                    classes.join(", ")
+                ),
+            },
+        ),
+        (
+            "Domains stored",
+            database.count_rows("domains")?.to_string(),
+        ),
+        ("Links stored", database.count_rows("links")?.to_string()),
+    ];
+    match report {
+        Some(report) => totals.extend([
+            ("Pages fetched", report.pages_fetched.to_string()),
+            ("Pages skipped as cached", report.pages_cached.to_string()),
+            (
+                "Pages blocked by robots",
+                report.pages_blocked_by_robots.to_string(),
+            ),
+            ("Fetch errors", report.fetch_errors.len().to_string()),
+            ("Requests sent", report.requests_sent.to_string()),
+            ("Bytes downloaded", report.bytes_downloaded.to_string()),
+            ("Max depth reached", report.max_depth.to_string()),
+        ]),
+        None => {
+            if let Some(run) = latest_run.first() {
+                totals.extend([
+                    ("Pages fetched", run.pages_fetched.to_string()),
+                    ("Fetch errors", run.errors.to_string()),
+                ]);
+            }
+        }
+    }
+    sections.push(Section {
+        title: "Totals",
+        blocks: vec![Block::Facts(totals)],
+    });
+
+    sections.push(Section {
+        title: "Domains",
+        blocks: vec![Block::Table {
+            header: vec!["Host", "Pages", "Oldest crawl", "Newest crawl"],
+            rows: database
+                .sites_by_domain(None)?
+                .into_iter()
+                .map(|summary| {
+                    return vec![
+                        summary.host,
+                        summary.pages.to_string(),
+                        timestamp(summary.oldest_crawl),
+                        timestamp(summary.newest_crawl),
+                    ];
+                })
+                .collect(),
+        }],
+    });
+
+    sections.push(Section {
+        title: "Pages by depth",
+        blocks: vec![Block::Histogram(
+            database
+                .count_by_depth()?
+                .into_iter()
+                .map(|(depth, count)| (depth.to_string(), count))
+                .collect(),
+        )],
+    });
+
+    // The pages with the most links, ties broken by URL
+    let mut links = database.list_links()?;
+    links.sort_by(|(a_url, a_links), (b_url, b_links)| {
+        return b_links.len().cmp(&a_links.len()).then(a_url.cmp(b_url));
+    });
+    sections.push(Section {
+        title: "Top pages by outbound links",
+        blocks: vec![Block::Table {
+            header: vec!["Page", "Links"],
+            rows: links
+                .into_iter()
+                .filter(|(_, links)| !links.is_empty())
+                .take(TOP_PAGES)
+                .map(|(url, links)| vec![url, links.len().to_string()])
+                .collect(),
+        }],
+    });
+
+    // The fetches that failed in the crawl, or else those recorded in the database
+    let errors: Vec<Vec<String>> = match report {
+        Some(report) => report
+            .fetch_errors
+            .iter()
+            .map(|error| {
+                return vec![
+                    error.url.clone(),
+                    error.category.to_string(),
+                    error.reason.clone(),
+                ];
+            })
+            .collect(),
+        None => FetchFailure::read_all(database)?
+            .into_iter()
+            .map(|failure| vec![failure.url, failure.error_kind, failure.message])
+            .collect(),
+    };
+    sections.push(Section {
+        title: "Errors",
+        blocks: vec![Block::Table {
+            header: vec!["URL", "Category", "Reason"],
+            rows: errors,
+        }],
+    });
+
+    // The broken links, which are only known once the status of their targets is stored
+    let broken = database.broken_links()?;
+    if !broken.is_empty() {
+        sections.push(Section {
+            title: "Broken links",
+            blocks: vec![Block::Table {
+                header: vec!["Page", "Link", "Kind", "Status"],
+                rows: broken
+                    .into_iter()
+                    .map(|link| {
+                        let status = match (link.status_code, link.error_kind) {
+                            (Some(status_code), _) => status_code.to_string(),
+                            (None, Some(error_kind)) => error_kind,
+                            (None, None) => "unknown".to_string(),
+                        };
+                        return vec![link.source_url, link.target_url, link.kind, status];
+                    })
+                    .collect(),
+            }],
+        });
+    }
+    return Ok(sections);
+}
+
+/// Renders the sections of a crawl report as a Markdown document.
+///
+/// # Arguments
+///
+/// * `sections` - A slice of the sections of the report.
+/// * `generated` - A string slice that holds the time the report was generated.
+fn render_markdown(sections: &[Section], generated: &str) -> String {
+    let mut document = format!("# {}\n\nGenerated {}\n", REPORT_TITLE, generated);
+    for section in sections {
+        document.push_str(&format!("\n## {}\n", section.title));
+        for block in &section.blocks {
+            document.push('\n');
+            match block {
+                Block::Text(text) => document.push_str(&format!("{}\n", escape_markdown(text))),
+                Block::Facts(facts) => {
+                    for (name, value) in facts {
+                        document.push_str(&format!("- **{}:** {}\n", name, escape_markdown(value)));
+                    }
+                }
+                Block::Table { rows, .. } if rows.is_empty() => document.push_str("None\n"),
+                Block::Table { header, rows } => {
+                    document.push_str(&format!("| {} |\n", header.join(" | ")));
+                    document.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+                    for row in rows {
+                        let cells: Vec<String> =
+                            row.iter().map(|cell| escape_markdown(cell)).collect();
+                        document.push_str(&format!("| {} |\n", cells.join(" | ")));
+                    }
+                }
+                Block::Histogram(bars) => {
+                    let most = bars.iter().map(|(_, count)| *count).max().unwrap_or(0);
+                    if most == 0 {
+                        document.push_str("None\n");
+                        continue;
+                    }
+                    document.push_str("| Depth | Pages | |\n| --- | --- | --- |\n");
+                    for (label, count) in bars {
+                        let bar = "#".repeat((count * DEPTH_BAR_WIDTH).div_ceil(most) as usize);
+                        document.push_str(&format!("| {} | {} | `{}` |\n", label, count, bar));
+                    }
+                }
+            }
+        }
+    }
+    return document;
+}
+
+/// Renders the sections of a crawl report as an HTML page, in `HTML_TEMPLATE`.
+///
+/// # Arguments
+///
+/// * `sections` - A slice of the sections of the report.
+/// * `generated` - A string slice that holds the time the report was generated.
+fn render_html(sections: &[Section], generated: &str) -> String {
+    let mut body = String::new();
+    for section in sections {
+        body.push_str(&format!("<h2>{}</h2>\n", escape_html(section.title)));
+        for block in &section.blocks {
+            match block {
+                Block::Text(text) => body.push_str(&format!("<p>{}</p>\n", escape_html(text))),
+                Block::Facts(facts) => {
+                    body.push_str("<dl>\n");
+                    for (name, value) in facts {
+                        body.push_str(&format!(
+                            "<dt>{}</dt><dd>{}</dd>\n",
+                            escape_html(name),
+                            escape_html(value)
+                        ));
+                    }
+                    body.push_str("</dl>\n");
+                }
+                Block::Table { rows, .. } if rows.is_empty() => body.push_str("<p>None</p>\n"),
+                Block::Table { header, rows } => {
+                    body.push_str("<table>\n<tr>");
+                    for name in header {
+                        body.push_str(&format!("<th>{}</th>", escape_html(name)));
+                    }
+                    body.push_str("</tr>\n");
+                    for row in rows {
+                        body.push_str("<tr>");
+                        for cell in row {
+                            body.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                        }
+                        body.push_str("</tr>\n");
+                    }
+                    body.push_str("</table>\n");
+                }
+                Block::Histogram(bars) => {
+                    let most = bars.iter().map(|(_, count)| *count).max().unwrap_or(0);
+                    if most == 0 {
+                        body.push_str("<p>None</p>\n");
+                        continue;
+                    }
+                    body.push_str("<table>\n<tr><th>Depth</th><th>Pages</th><th></th></tr>\n");
+                    for (label, count) in bars {
+                        body.push_str(&format!(
+                            "<tr><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width: {:.1}%\"></div></td></tr>\n",
+                            escape_html(label),
+                            count,
+                            *count as f64 * 100.0 / most as f64
+                        ));
+                    }
+                    body.push_str("</table>\n");
+                }
+            }
+        }
+    }
+    return HTML_TEMPLATE
+        .replace("{{title}}", REPORT_TITLE)
+        .replace("{{generated}}", &escape_html(generated))
+        .replace("{{body}}", &body);
+}
+
+/// Formats a time of a crawl report, to the second in UTC.
+fn timestamp(time: DateTime<Utc>) -> String {
+    return time.to_rfc3339_opts(SecondsFormat::Secs, true);
+}
+
+/// Escapes the characters of a text that Markdown would read as markup, or that would break a
+/// table cell.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// Escapes the characters of a text that HTML would read as markup.
+fn escape_html(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
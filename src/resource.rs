@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The extensions of URLs that are obviously not HTML, and are treated as assets.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "7z", "avi", "bin", "bmp", "bz2", "css", "csv", "dmg", "doc", "docx", "exe", "flac", "gif",
+    "gz", "ico", "iso", "jpeg", "jpg", "js", "mkv", "mov", "mp3", "mp4", "msi", "ogg", "pdf",
+    "png", "ppt", "pptx", "rar", "svg", "tar", "tgz", "tif", "tiff", "wav", "webm", "webp", "woff",
+    "woff2", "xls", "xlsx", "zip",
+];
+
+/// The extensions of URLs that are treated as feeds.
+const FEED_EXTENSIONS: &[&str] = &["atom", "rss"];
+
+/// The kinds of resources a URL is expected to point at.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum ResourceKind {
+    /// An HTML page.
+    Page,
+    /// A binary file such as an image, a stylesheet or an archive.
+    Asset,
+    /// An RSS or Atom feed.
+    Feed,
+}
+
+impl ResourceKind {
+    /// Guesses the kind of resource a URL points at from the extension of its path.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    ///
+    /// # Returns
+    ///
+    /// The expected `ResourceKind`, which is `Page` unless the extension says otherwise.
+    pub fn of(url: &str) -> Self {
+        // Only look at the path, so that hosts such as `example.zip` are not mistaken for files
+        let path = match Url::parse(url) {
+            Ok(parsed_url) => parsed_url.path().to_string(),
+            Err(_) => url.split(['?', '#']).next().unwrap_or_default().to_string(),
+        };
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        let extension = match file_name.rsplit_once('.') {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => return ResourceKind::Page,
+        };
+
+        if ASSET_EXTENSIONS.contains(&extension.as_str()) {
+            return ResourceKind::Asset;
+        }
+        if FEED_EXTENSIONS.contains(&extension.as_str()) {
+            return ResourceKind::Feed;
+        }
+        return ResourceKind::Page;
+    }
+}
+
+impl std::fmt::Display for ResourceKind {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResourceKind::Page => "pages",
+            ResourceKind::Asset => "assets",
+            ResourceKind::Feed => "feeds",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Determines how a kind of resource is fetched.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchPolicy {
+    /// The resource is never requested.
+    Skip,
+    /// Only the headers are requested, recording the status, size and type of the resource. A
+    /// resource that turns out to be HTML is then fetched in full.
+    Head,
+    /// The resource is fetched in full.
+    Full,
+}
+
+/// The `FetchPolicy` of each `ResourceKind`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct FetchPolicies {
+    /// How HTML pages are fetched.
+    pub pages: FetchPolicy,
+    /// How assets are fetched.
+    pub assets: FetchPolicy,
+    /// How feeds are fetched.
+    pub feeds: FetchPolicy,
+}
+
+impl Default for FetchPolicies {
+    fn default() -> Self {
+        return FetchPolicies {
+            pages: FetchPolicy::Full,
+            assets: FetchPolicy::Skip,
+            feeds: FetchPolicy::Full,
+        };
+    }
+}
+
+impl FetchPolicies {
+    /// Gets the `FetchPolicy` of a kind of resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of resource.
+    pub fn of(&self, kind: ResourceKind) -> FetchPolicy {
+        return match kind {
+            ResourceKind::Page => self.pages,
+            ResourceKind::Asset => self.assets,
+            ResourceKind::Feed => self.feeds,
+        };
+    }
+}
+
+/// The leading bytes of common binary formats, along with their media types.
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// The tags that an HTML document commonly starts with, in lowercase.
+const HTML_TAGS: &[&str] = &[
+    "<!doctype html",
+    "<html",
+    "<head",
+    "<body",
+    "<script",
+    "<iframe",
+    "<div",
+    "<!--",
+];
+
+/// Guesses the media type of a response from its first bytes, regardless of what it declares.
+///
+/// This is a small subset of the MIME sniffing done by browsers: the magic numbers of common
+/// binary formats, and the tags HTML documents usually start with.
+///
+/// # Arguments
+///
+/// * `body` - The raw bytes of the response.
+///
+/// # Returns
+///
+/// The sniffed media type, or `None` if the body matches no known signature.
+pub fn sniff_media_type(body: &[u8]) -> Option<&'static str> {
+    if let Some((_, media_type)) = MAGIC_NUMBERS
+        .iter()
+        .find(|(magic, _)| body.starts_with(magic))
+    {
+        return Some(media_type);
+    }
+
+    // Browsers skip leading whitespace and a byte order mark before looking for markup
+    let body = body.strip_prefix(b"\xef\xbb\xbf").unwrap_or(body);
+    let start = body
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map_or(&[][..], |position| &body[position..]);
+    let head = String::from_utf8_lossy(&start[..start.len().min(64)]).to_ascii_lowercase();
+    if HTML_TAGS.iter().any(|tag| head.starts_with(tag)) {
+        return Some("text/html");
+    }
+    if head.starts_with("<?xml") {
+        return Some("text/xml");
+    }
+    return None;
+}
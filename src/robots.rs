@@ -0,0 +1,274 @@
+use chrono::{DateTime, Utc};
+use robots_txt::matcher::SimpleMatcher;
+use robots_txt::parts::Rule;
+use robots_txt::Robots;
+
+/// The user agent whose `robots.txt` section the crawler obeys.
+pub const USER_AGENT: &str = "Rustle";
+
+/// The names of the page-level robots directives, some of which take a value after a colon,
+/// such as `max-snippet: 20`. A prefix of an `X-Robots-Tag` value that is one of them is a
+/// directive rather than a user agent.
+const DIRECTIVE_NAMES: &[&str] = &[
+    "all",
+    "noindex",
+    "nofollow",
+    "none",
+    "noarchive",
+    "nocache",
+    "nosnippet",
+    "noimageindex",
+    "notranslate",
+    "indexifembedded",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+    "unavailable_after",
+];
+
+/// Represents a parsed `robots.txt` file, reduced to the rules that apply to Rustle.
+///
+/// Parsing with `Robots::from_str_lossy` borrows from the raw text, so the rules of the chosen
+/// section are copied into owned values that can be cached for the duration of a crawl.
+pub struct CompiledRobots {
+    /// The rules of the section that applies to `USER_AGENT`.
+    rules: Vec<Rule<'static>>,
+    /// The id of the `robots_history` snapshot the rules were parsed from, if it was recorded.
+    pub snapshot: Option<i64>,
+    /// The time the `robots.txt` was fetched, or a fetch of it last failed, from which its
+    /// freshness is counted. The time it was parsed, unless set otherwise.
+    pub checked_at: DateTime<Utc>,
+}
+
+impl CompiledRobots {
+    /// Parses the contents of a `robots.txt` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `robots_txt` - A string slice that holds the contents of the `robots.txt` file.
+    ///
+    /// # Returns
+    ///
+    /// A new `CompiledRobots` holding the rules that apply to `USER_AGENT`.
+    pub fn parse(robots_txt: &str) -> Self {
+        let robots = Robots::from_str_lossy(robots_txt);
+        let rules = robots
+            .choose_section(USER_AGENT)
+            .rules
+            .iter()
+            .map(|rule| Rule::new(rule.allow, rule.path.to_string()))
+            .collect();
+
+        return CompiledRobots {
+            rules,
+            snapshot: None,
+            checked_at: Utc::now(),
+        };
+    }
+
+    /// Checks whether a path may be crawled.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice that holds the path of the URL to check.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating whether the path is allowed.
+    pub fn allows(&self, path: &str) -> bool {
+        return SimpleMatcher::new(&self.rules).check_path(path);
+    }
+
+    /// Finds the rule that decides whether a path may be crawled.
+    ///
+    /// Rules are checked in order, as in `allows`, and the first one whose path is a
+    /// case-insensitive prefix of the path decides.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice that holds the path of the URL to check.
+    ///
+    /// # Returns
+    ///
+    /// The deciding rule formatted as in the `robots.txt`, such as `Disallow: /private`, or
+    /// `None` if no rule applies to the path.
+    pub fn matching_rule(&self, path: &str) -> Option<String> {
+        let allowed = self.allows(path);
+        return self
+            .rules
+            .iter()
+            .find(|rule| {
+                path.get(..rule.path.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&rule.path))
+            })
+            .filter(|rule| rule.allow == allowed)
+            .map(|rule| {
+                let directive = if rule.allow { "Allow" } else { "Disallow" };
+                format!("{}: {}", directive, rule.path)
+            });
+    }
+}
+
+/// Represents a URL whose admission differs between the live `robots.txt` of its domain and the
+/// planned override.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct RobotsDiff {
+    /// The URL that was checked.
+    pub url: String,
+    /// Whether the live rules allow the URL. The planned rules decide the opposite.
+    pub allowed_live: bool,
+}
+
+/// Implements the `Display` trait for the `RobotsDiff` struct.
+///
+/// The formatted string describes the change in admission, followed by the URL.
+impl std::fmt::Display for RobotsDiff {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.allowed_live {
+            write!(
+                fmt,
+                "allowed now, disallowed under planned rules: {}",
+                self.url
+            )
+        } else {
+            write!(
+                fmt,
+                "disallowed now, allowed under planned rules: {}",
+                self.url
+            )
+        }
+    }
+}
+
+/// Represents the page-level robots directives of a page, from its `<meta name="robots">` tags
+/// and `X-Robots-Tag` headers.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RobotsDirectives {
+    /// Whether the page must not be stored.
+    pub noindex: bool,
+    /// Whether the links of the page must not be followed.
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Parses a comma-separated list of directives, such as `noindex, nofollow`.
+    ///
+    /// `none` is treated as both `noindex` and `nofollow`, and unknown directives are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - A string slice that holds the directives.
+    pub fn parse(content: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for directive in content.split(',').map(|d| d.trim().to_lowercase()) {
+            match directive.as_str() {
+                "noindex" => directives.noindex = true,
+                "nofollow" => directives.nofollow = true,
+                "none" => {
+                    directives.noindex = true;
+                    directives.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+        return directives;
+    }
+
+    /// Parses the value of an `X-Robots-Tag` header.
+    ///
+    /// Values may be scoped to a user agent (`googlebot: noindex`), in which case they only
+    /// apply if the user agent is `USER_AGENT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - A string slice that holds the header value.
+    pub fn parse_header(value: &str) -> Self {
+        if let Some((agent, directives)) = value.split_once(':') {
+            // Directives like `max-image-preview: large` also contain a colon, so only treat the
+            // prefix as a user agent if it isn't a directive itself
+            let agent = agent.trim();
+            let is_directive = DIRECTIVE_NAMES
+                .iter()
+                .any(|name| agent.eq_ignore_ascii_case(name));
+            if !agent.contains(',') && !is_directive {
+                if agent.eq_ignore_ascii_case(USER_AGENT) {
+                    return Self::parse(directives);
+                }
+                return RobotsDirectives::default();
+            }
+        }
+        return Self::parse(value);
+    }
+
+    /// Combines two sets of directives, keeping every restriction from both.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The directives to combine with.
+    pub fn merge(self, other: Self) -> Self {
+        return RobotsDirectives {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_directive_is_parsed_on_its_own() {
+        let noindex = RobotsDirectives::parse("noindex");
+        assert!(noindex.noindex && !noindex.nofollow);
+        let nofollow = RobotsDirectives::parse("NoFollow");
+        assert!(!nofollow.noindex && nofollow.nofollow);
+        let none = RobotsDirectives::parse("none");
+        assert!(none.noindex && none.nofollow);
+        assert_eq!(RobotsDirectives::parse("all"), RobotsDirectives::default());
+        assert_eq!(
+            RobotsDirectives::parse("noarchive, max-snippet: 20"),
+            RobotsDirectives::default()
+        );
+        assert_eq!(RobotsDirectives::parse(""), RobotsDirectives::default());
+    }
+
+    #[test]
+    fn combined_directives_keep_every_restriction() {
+        let both = RobotsDirectives {
+            noindex: true,
+            nofollow: true,
+        };
+        assert_eq!(RobotsDirectives::parse("noindex, nofollow"), both);
+        assert_eq!(RobotsDirectives::parse(" nofollow ,noindex "), both);
+        assert_eq!(RobotsDirectives::parse("noarchive, none"), both);
+        assert_eq!(
+            RobotsDirectives::parse("noindex").merge(RobotsDirectives::parse("nofollow")),
+            both
+        );
+    }
+
+    #[test]
+    fn header_values_scoped_to_another_agent_are_ignored() {
+        assert!(RobotsDirectives::parse_header("rustle: noindex").noindex);
+        assert!(RobotsDirectives::parse_header("Rustle: none").nofollow);
+        assert_eq!(
+            RobotsDirectives::parse_header("googlebot: noindex, nofollow"),
+            RobotsDirectives::default()
+        );
+        assert!(RobotsDirectives::parse_header("noindex, nofollow").nofollow);
+    }
+
+    #[test]
+    fn header_directives_with_a_value_are_not_read_as_agents() {
+        let directives = RobotsDirectives::parse_header("max-image-preview: large, noindex");
+        assert!(directives.noindex && !directives.nofollow);
+        let directives = RobotsDirectives::parse_header("Max-Snippet: 20, nofollow");
+        assert!(!directives.noindex && directives.nofollow);
+        let directives =
+            RobotsDirectives::parse_header("unavailable_after: 2030-01-01T00:00:00Z, noindex");
+        assert!(directives.noindex);
+        let directives = RobotsDirectives::parse_header("noindex, max-video-preview: -1");
+        assert!(directives.noindex);
+    }
+}
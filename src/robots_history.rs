@@ -0,0 +1,144 @@
+use crate::database::Database;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+/// Represents a robots.txt as it was applied to a domain during a crawl.
+///
+/// Snapshots are only ever added, so that the rules a page was fetched under can be shown long
+/// after the robots.txt of its domain changed.
+pub struct RobotsSnapshot {
+    /// An `i64` that identifies the snapshot, referenced by the `robots_snapshot` of sites.
+    pub id: i64,
+    /// A `String` that holds the domain name.
+    pub domain: String,
+    /// A `DateTime<Utc>` that represents the time the robots.txt was fetched or read.
+    pub fetch_time: DateTime<Utc>,
+    /// A `String` that contains the raw contents of the robots.txt.
+    pub robots: String,
+    /// An `Option<String>` that holds the path of the local robots.txt that was used instead of
+    /// the live one, if any.
+    pub robots_override: Option<String>,
+}
+
+impl RobotsSnapshot {
+    /// Records the robots.txt applied to a domain.
+    ///
+    /// The latest snapshot of the domain is reused if its contents and override are the same, so
+    /// that a new snapshot is only added when the robots.txt changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `robots` - A string slice that holds the raw contents of the robots.txt.
+    /// * `fetch_time` - The time the robots.txt was fetched or read.
+    /// * `robots_override` - The path of the local robots.txt used instead of the live one, if any.
+    /// * `database` - A reference to the `Database` where the snapshot is recorded.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<i64>` holding the id of the snapshot.
+    pub fn record(
+        domain: &str,
+        robots: &str,
+        fetch_time: DateTime<Utc>,
+        robots_override: Option<&str>,
+        database: &Database,
+    ) -> Result<i64> {
+        let domain_str = domain.replace("'", "''");
+        let robots_str = robots.replace("'", "''");
+        let robots_override_str = match robots_override {
+            Some(path) => format!("'{}'", path.replace("'", "''")),
+            None => "NULL".to_string(),
+        };
+
+        // Check the latest snapshot and add the new one in a single statement, so that workers
+        // recording the same robots.txt at once don't both add it
+        database.execute(&format!(
+            "INSERT INTO robots_history (domain, fetch_time, robots, robots_override) \
+             SELECT '{0}', '{1}', '{2}', {3} WHERE NOT EXISTS (\
+             SELECT 1 FROM robots_history WHERE id = (SELECT MAX(id) FROM robots_history WHERE domain = '{0}') \
+             AND robots = '{2}' AND robots_override IS {3})",
+            domain_str,
+            fetch_time.to_rfc3339(),
+            robots_str,
+            robots_override_str
+        ))?;
+
+        // The latest snapshot with these contents is either the one just added or the one that
+        // made it unnecessary, whatever other workers added since
+        return Self::read_one(
+            &format!(
+                "SELECT id, domain, fetch_time, robots, robots_override FROM robots_history \
+                 WHERE domain = '{}' AND robots = '{}' AND robots_override IS {} ORDER BY id DESC LIMIT 1",
+                domain_str, robots_str, robots_override_str
+            ),
+            database,
+        )?
+        .map(|snapshot| snapshot.id)
+        .with_context(|| format!("Robots snapshot of {} disappeared", domain));
+    }
+
+    /// Reads a `RobotsSnapshot` from the database by its id.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the snapshot.
+    /// * `database` - A reference to the `Database` from which the snapshot will be read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Self>>` which is `Ok(None)` if no snapshot has the id.
+    pub fn read_into(id: i64, database: &Database) -> Result<Option<Self>> {
+        return Self::read_one(
+            &format!(
+                "SELECT id, domain, fetch_time, robots, robots_override FROM robots_history WHERE id = {}",
+                id
+            ),
+            database,
+        );
+    }
+
+    /// Reads the first snapshot returned by a query selecting all of its columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A string slice that holds the query.
+    /// * `database` - A reference to the `Database` from which the snapshot will be read.
+    fn read_one(query: &str, database: &Database) -> Result<Option<Self>> {
+        let mut statement = database.prepare(query)?;
+        if let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let id = statement
+                .read::<i64, usize>(0)
+                .context("Failed to read id from the database")?;
+            let domain = statement
+                .read::<String, usize>(1)
+                .context("Failed to read domain from the database")?;
+            let fetch_time = statement
+                .read::<String, usize>(2)
+                .context("Failed to read fetch_time from the database")?;
+            let robots = statement
+                .read::<String, usize>(3)
+                .context("Failed to read robots from the database")?;
+            let robots_override = statement
+                .read::<Option<String>, usize>(4)
+                .context("Failed to read robots_override from the database")?;
+
+            let fetch_time = DateTime::parse_from_rfc3339(&fetch_time)
+                .context("Failed to parse fetch_time as RFC 3339")?
+                .with_timezone(&Utc);
+
+            return Ok(Some(Self {
+                id,
+                domain,
+                fetch_time,
+                robots,
+                robots_override,
+            }));
+        }
+
+        return Ok(None);
+    }
+}
@@ -0,0 +1,687 @@
+use crate::config::Config;
+use crate::database::{Database, MEMORY_DATABASE};
+use crate::domain::Domain;
+use crate::fixture::{FixtureRequest, FixtureResponse, FixtureServer};
+use crate::report::ErrorCategory;
+use crate::spider::Crawler;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The depth of the self-test crawl.
+const SELF_TEST_DEPTH: u64 = 2;
+
+/// The number of pages the self-test crawl fetches at once, more than `max_concurrent_per_host`
+/// so that the cap on the requests in flight to the fixture server is exercised.
+const SELF_TEST_CONCURRENCY: usize = 8;
+
+/// The time the fixture server takes to answer a request, so that concurrent requests overlap.
+const FIXTURE_LATENCY: Duration = Duration::from_millis(50);
+
+/// The malformed link of the fixture origin, which is reported without failing the crawl.
+const FIXTURE_MALFORMED_LINK: &str = "http://[malformed/";
+
+/// The page of the fixture site that fails with a server error the first time it is requested,
+/// and is fetched by the retry pass at the end of the crawl.
+const FIXTURE_FLAKY_PAGE: &str = "/flaky";
+
+/// The robots.txt of the fixture site.
+const FIXTURE_ROBOTS: &str = "User-agent: *\nDisallow: /private/\n";
+
+/// A robots.txt that disallows the whole fixture site, stored as stale before it is checked
+/// against, so that the fixture's own robots.txt is fetched again and replaces it.
+const STALE_ROBOTS: &str = "User-agent: *\nDisallow: /\n";
+
+/// The pages of the fixture site, as their path, status code, `Location` header and body.
+///
+/// The origin links to every kind of page, including a malformed link, links without a host, a
+/// page that redirects to itself and one that fails the first time, links to `/about` with an
+/// image and then with nested markup, an iframe of its own origin and one of another port, and
+/// `/variant`, which declares `/article` as its canonical URL and links to it. The iframe declares
+/// a canonical URL out of scope, and the origin announces an RSS feed. `/about` starts a chain one generation deeper than the crawl, so that the depth limit is
+/// exercised as well.
+const FIXTURE_PAGES: &[(&str, u16, Option<&str>, &str)] = &[
+    (
+        "/",
+        200,
+        None,
+        r#"<html><head><title>Rustle self-test</title>
+<link rel="alternate" type="application/rss+xml" href="/feed.xml"></head><body>
+<a href="/about"><img src="/logo.png" alt="Rustle"></a>
+<a href="/about"><span>About</span>
+  <em>us</em></a>
+<a href="/private/secret">Secret</a>
+<a href="/old">Old</a>
+<a href="/missing">Missing</a>
+<a href="http://[malformed/">Malformed</a>
+<a href="/loop">Loop</a>
+<a href="/flaky">Flaky</a>
+<a href="mailto:team@example.com">Mail</a>
+<a href="tel:+15550100">Call</a>
+<a href="javascript:void(0)">Menu</a>
+<a href="/variant">Variant</a>
+<iframe src="/frame"></iframe>
+<iframe src="http://127.0.0.1:1/embed"></iframe>
+</body></html>"#,
+    ),
+    (
+        "/about",
+        200,
+        None,
+        r#"<html><head><title>About</title></head><body><a href="team">Team</a></body></html>"#,
+    ),
+    (
+        "/team",
+        200,
+        None,
+        r#"<html><head><title>Team</title></head><body><a href="/deep">Deep</a></body></html>"#,
+    ),
+    (
+        "/deep",
+        200,
+        None,
+        r#"<html><head><title>Deep</title></head><body></body></html>"#,
+    ),
+    (
+        "/frame",
+        200,
+        None,
+        r#"<html><head><title>Frame</title>
+<link rel="canonical" href="https://example.com/frame"></head><body></body></html>"#,
+    ),
+    (
+        "/variant",
+        200,
+        None,
+        r#"<html><head><title>Article</title><link rel="canonical" href="/article#top"></head>
+<body><a href="/article">Article</a></body></html>"#,
+    ),
+    (
+        "/private/secret",
+        200,
+        None,
+        r#"<html><head><title>Secret</title></head><body></body></html>"#,
+    ),
+    ("/old", 301, Some("/new"), ""),
+    ("/loop", 301, Some("/loop"), ""),
+    (
+        "/new",
+        200,
+        None,
+        r#"<html><head><title>New</title></head><body></body></html>"#,
+    ),
+    (
+        FIXTURE_FLAKY_PAGE,
+        200,
+        None,
+        r#"<html><head><title>Flaky</title></head><body></body></html>"#,
+    ),
+    ("/robots.txt", 200, None, FIXTURE_ROBOTS),
+];
+
+/// Represents the outcome of one self-test check.
+pub struct Check {
+    /// The name of the check.
+    pub name: &'static str,
+    /// Whether the crawl behaved as expected.
+    pub passed: bool,
+    /// What was found, explaining the failure when the check failed.
+    pub detail: String,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = if self.passed { "PASS" } else { "FAIL" };
+        return write!(f, "{} {}: {}", verdict, self.name, self.detail);
+    }
+}
+
+/// Crawls the built-in fixture site and checks the stored rows against the expected ones.
+///
+/// The fixture site is served by an HTTP server running in the background of the process, and
+/// crawled with the default settings into an in-memory database, so that the result only depends
+/// on the installation and not on the config or the network.
+///
+/// # Returns
+///
+/// A `Result<Vec<Check>>` holding the outcome of every check, or an error if the crawl could not
+/// be run at all.
+pub fn run() -> Result<Vec<Check>> {
+    let flaky_requests = AtomicUsize::new(0);
+    let server = FixtureServer::start(move |request| respond(request, &flaky_requests))?;
+
+    let config = Config {
+        origin_urls: vec![server.url("/")],
+        depth: SELF_TEST_DEPTH,
+        database: MEMORY_DATABASE.to_string(),
+        concurrency: Some(SELF_TEST_CONCURRENCY),
+        ..Default::default()
+    };
+    let mut checks = crawl_fixture(config.clone(), &server)?;
+    checks.push(check_robots_refetch(config, &server)?);
+    return Ok(checks);
+}
+
+/// Crawls the fixture site and runs the checks.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the self-test crawl.
+/// * `server` - A reference to the `FixtureServer` of the fixture site.
+fn crawl_fixture(config: Config, server: &FixtureServer) -> Result<Vec<Check>> {
+    let base = server.base();
+    let max_concurrent_per_host = config.max_concurrent_per_host;
+    let crawler = Crawler::with_storage(config, Box::new(Database::in_memory()?))?;
+    let report = crawler.crawl(false);
+    let storage = crawler.storage();
+    let url = |path: &str| format!("{}{}", base, path);
+    let read = |path: &str| storage.get_site(&url(path));
+
+    let mut checks = Vec::new();
+
+    // Fetching
+    let origin = read("/")?;
+    checks.push(match &origin {
+        Some(site) if site.status_code == Some(200) && site.title.as_deref() == Some("Rustle self-test") => Check {
+            name: "fetching",
+            passed: true,
+            detail: format!("{} was stored with status 200 and its title", url("/")),
+        },
+        Some(site) => Check {
+            name: "fetching",
+            passed: false,
+            detail: format!(
+                "{} was stored with status {:?} and title {:?}, expected 200 and \"Rustle self-test\"",
+                url("/"),
+                site.status_code,
+                site.title
+            ),
+        },
+        None => Check {
+            name: "fetching",
+            passed: false,
+            detail: format!("{} was not stored", url("/")),
+        },
+    });
+
+    // Link extraction
+    let expected_links: HashSet<String> = ["/about", "/private/secret", "/old", "/missing"]
+        .iter()
+        .map(|path| url(path))
+        .collect();
+    let about = read("/about")?;
+    let team = read("/team")?;
+    let missing_links: Vec<&String> = match &origin {
+        Some(site) => expected_links.difference(&site.links_to).collect(),
+        None => expected_links.iter().collect(),
+    };
+    let relative_resolved = about
+        .as_ref()
+        .is_some_and(|site| site.links_to.contains(&url("/team")));
+    let hostless_links: Vec<&String> = match &origin {
+        Some(site) => site
+            .links_to
+            .iter()
+            .filter(|link| !link.starts_with(base))
+            .collect(),
+        None => Vec::new(),
+    };
+    checks.push(Check {
+        name: "link extraction",
+        passed: missing_links.is_empty() && relative_resolved && hostless_links.is_empty(),
+        detail: if missing_links.is_empty() && relative_resolved && hostless_links.is_empty() {
+            "every link of the fixture pages was found, and the links without a host were skipped"
+                .to_string()
+        } else if !missing_links.is_empty() {
+            format!("links missing from {}: {:?}", url("/"), missing_links)
+        } else if !hostless_links.is_empty() {
+            format!(
+                "links without a host were followed from {}: {:?}",
+                url("/"),
+                hostless_links
+            )
+        } else {
+            format!("the relative link of {} was not resolved", url("/about"))
+        },
+    });
+
+    // Anchor text
+    let about_text = origin
+        .as_ref()
+        .and_then(|site| site.anchor_texts.get(&url("/about")));
+    checks.push(Check {
+        name: "anchor text",
+        passed: about_text.is_some_and(|text| text == "About us"),
+        detail: match about_text {
+            Some(text) if text == "About us" => format!(
+                "the link to {} was stored with the text of its nested markup, \"About us\"",
+                url("/about")
+            ),
+            Some(text) => format!(
+                "the link to {} was stored with the text {:?}, expected \"About us\"",
+                url("/about"),
+                text
+            ),
+            None => format!("the link to {} was stored without its text", url("/about")),
+        },
+    });
+
+    // Frames
+    let frame = read("/frame")?;
+    let frame_linked = origin
+        .as_ref()
+        .is_some_and(|site| site.links_to.contains(&url("/frame")));
+    let embed_linked = origin
+        .as_ref()
+        .is_some_and(|site| site.links_to.contains("http://127.0.0.1:1/embed"));
+    let frame_stored = frame
+        .as_ref()
+        .is_some_and(|site| site.status_code == Some(200));
+    checks.push(Check {
+        name: "frames",
+        passed: frame_linked && frame_stored && !embed_linked,
+        detail: if embed_linked {
+            format!(
+                "the iframe of another origin was followed from {}",
+                url("/")
+            )
+        } else if !frame_linked {
+            format!("the iframe of {} was not recorded as a link", url("/"))
+        } else if !frame_stored {
+            format!("the framed page {} was not stored", url("/frame"))
+        } else {
+            format!(
+                "the iframe {} was crawled, and the iframe of another origin was skipped",
+                url("/frame")
+            )
+        },
+    });
+
+    // Canonical URLs
+    let variant = read("/variant")?;
+    let article = read("/article")?;
+    let aliased = variant.as_ref().is_some_and(|site| {
+        site.canonical_url.as_deref() == Some(url("/article").as_str())
+            && site.links_to == HashSet::from([url("/article")])
+    });
+    let article_stored = article
+        .as_ref()
+        .is_some_and(|site| site.title.as_deref() == Some("Article"));
+    let article_generation = article.as_ref().and_then(|site| site.generation);
+    let off_scope_recorded = frame
+        .as_ref()
+        .and_then(|site| site.canonical_url.as_deref())
+        == Some("https://example.com/frame");
+    checks.push(Check {
+        name: "canonical URLs",
+        passed: aliased && article_stored && article_generation == Some(1) && off_scope_recorded,
+        detail: if !aliased {
+            format!(
+                "{} was not stored as an alias of {}",
+                url("/variant"),
+                url("/article")
+            )
+        } else if !article_stored {
+            format!(
+                "the page of {} was not stored under {}",
+                url("/variant"),
+                url("/article")
+            )
+        } else if article_generation != Some(1) {
+            format!(
+                "{} was fetched again in generation {:?}, although its page was stored",
+                url("/article"),
+                article_generation
+            )
+        } else if !off_scope_recorded {
+            format!(
+                "the canonical URL out of scope of {} was not recorded",
+                url("/frame")
+            )
+        } else {
+            format!(
+                "{} was stored under its canonical URL {} and not fetched again, and the canonical URL out of scope of {} was recorded",
+                url("/variant"),
+                url("/article"),
+                url("/frame")
+            )
+        },
+    });
+
+    // Feed discovery
+    let host = url::Url::parse(base)?
+        .host_str()
+        .context("The fixture server has no host")?
+        .to_string();
+    let feeds = storage
+        .get_domain(&host)?
+        .map(|domain| domain.feeds)
+        .unwrap_or_default();
+    checks.push(Check {
+        name: "feed discovery",
+        passed: feeds == [url("/feed.xml")],
+        detail: if feeds == [url("/feed.xml")] {
+            format!(
+                "the feed {} announced by {} was stored with {}",
+                url("/feed.xml"),
+                url("/"),
+                host
+            )
+        } else {
+            format!(
+                "{} was stored with the feeds {:?}, expected {}",
+                host,
+                feeds,
+                url("/feed.xml")
+            )
+        },
+    });
+
+    // Robots enforcement
+    let secret = read("/private/secret")?;
+    checks.push(Check {
+        name: "robots enforcement",
+        passed: secret.is_none(),
+        detail: match secret {
+            None => format!("{} was disallowed and not fetched", url("/private/secret")),
+            Some(_) => format!(
+                "{} was stored, although robots.txt disallows /private/",
+                url("/private/secret")
+            ),
+        },
+    });
+
+    // Redirect handling
+    let old = read("/old")?;
+    let new = read("/new")?;
+    checks.push(match (&old, &new) {
+        (Some(old), Some(new))
+            if old.is_redirect
+                && old.final_url.as_deref() == Some(url("/new").as_str())
+                && new.status_code == Some(200)
+                && new.redirect_hops == Some(1) =>
+        {
+            Check {
+                name: "redirect handling",
+                passed: true,
+                detail: format!(
+                    "{} was stored as a redirect to {}",
+                    url("/old"),
+                    url("/new")
+                ),
+            }
+        }
+        (None, _) => Check {
+            name: "redirect handling",
+            passed: false,
+            detail: format!("{} was not stored as a redirect", url("/old")),
+        },
+        (Some(old), None) => Check {
+            name: "redirect handling",
+            passed: false,
+            detail: format!(
+                "{} redirects to {:?}, but {} was not stored",
+                url("/old"),
+                old.final_url,
+                url("/new")
+            ),
+        },
+        (Some(old), Some(new)) => Check {
+            name: "redirect handling",
+            passed: false,
+            detail: format!(
+                "{} was stored with is_redirect {} and final URL {:?}, {} with status {:?} after {:?} hops",
+                url("/old"),
+                old.is_redirect,
+                old.final_url,
+                url("/new"),
+                new.status_code,
+                new.redirect_hops
+            ),
+        },
+    });
+
+    // Error pages
+    let missing = read("/missing")?;
+    checks.push(Check {
+        name: "error pages",
+        passed: missing.as_ref().and_then(|site| site.status_code) == Some(404),
+        detail: match missing {
+            Some(site) => match site.status_code {
+                Some(status) => format!("{} was stored with status {}", url("/missing"), status),
+                None => format!("{} was stored without a status", url("/missing")),
+            },
+            None => format!("{} was not stored", url("/missing")),
+        },
+    });
+
+    // Depth limit
+    let deep = read("/deep")?;
+    checks.push(Check {
+        name: "depth limit",
+        passed: team.is_some() && deep.is_none(),
+        detail: match (&team, &deep) {
+            (Some(_), None) => format!(
+                "generation {} was fetched and generation {} was not",
+                SELF_TEST_DEPTH,
+                SELF_TEST_DEPTH + 1
+            ),
+            (None, _) => format!(
+                "{} was not stored, although it is within depth {}",
+                url("/team"),
+                SELF_TEST_DEPTH
+            ),
+            (Some(_), Some(_)) => format!(
+                "{} was stored, although it is beyond depth {}",
+                url("/deep"),
+                SELF_TEST_DEPTH
+            ),
+        },
+    });
+
+    // Per-URL failures
+    let reported = |url: &str, category: ErrorCategory| match &report {
+        Ok(report) => report
+            .fetch_errors
+            .iter()
+            .any(|error| error.url == url && error.category == category),
+        Err(_) => false,
+    };
+    let malformed_reported = reported(FIXTURE_MALFORMED_LINK, ErrorCategory::InvalidUrl);
+    let loop_reported = reported(&url("/loop"), ErrorCategory::Redirect);
+    checks.push(Check {
+        name: "per-URL failures",
+        passed: malformed_reported && loop_reported,
+        detail: match (&report, malformed_reported, loop_reported) {
+            (Err(e), _, _) => format!("the crawl failed: {:#}", e),
+            (Ok(_), true, true) => format!(
+                "the malformed link {} and the redirect loop at {} were reported, and the crawl went on",
+                FIXTURE_MALFORMED_LINK,
+                url("/loop")
+            ),
+            (Ok(_), false, _) => format!(
+                "the malformed link {} was not reported as an invalid URL",
+                FIXTURE_MALFORMED_LINK
+            ),
+            (Ok(_), true, false) => format!(
+                "the redirect loop at {} was not reported as a redirect error",
+                url("/loop")
+            ),
+        },
+    });
+
+    // Retry pass
+    let flaky = read(FIXTURE_FLAKY_PAGE)?;
+    let flaky_requests = server.requests_for(FIXTURE_FLAKY_PAGE);
+    checks.push(Check {
+        name: "retry pass",
+        passed: report
+            .as_ref()
+            .is_ok_and(|report| report.recovered_by_retry == 1)
+            && flaky.as_ref().and_then(|site| site.status_code) == Some(200),
+        detail: match (&report, flaky.and_then(|site| site.status_code)) {
+            (Err(e), _) => format!("the crawl failed: {:#}", e),
+            (Ok(report), Some(200)) if report.recovered_by_retry == 1 => format!(
+                "{} failed with a server error, and was stored with status 200 after {} requests",
+                url(FIXTURE_FLAKY_PAGE),
+                flaky_requests
+            ),
+            (Ok(report), status) => format!(
+                "{} URLs were recovered by the retry pass and {} was stored with status {:?} after {} requests, expected 1 and 200",
+                report.recovered_by_retry,
+                url(FIXTURE_FLAKY_PAGE),
+                status,
+                flaky_requests
+            ),
+        },
+    });
+
+    // Database writes
+    let stored = storage.site_count()?;
+    let expected_rows = 10;
+    checks.push(match report {
+        Ok(report) if stored == expected_rows => Check {
+            name: "database writes",
+            passed: true,
+            detail: format!(
+                "{} sites and a crawl session with {} pages were stored",
+                stored, report.session.pages_fetched
+            ),
+        },
+        Ok(_) => Check {
+            name: "database writes",
+            passed: false,
+            detail: format!("{} sites were stored, expected {}", stored, expected_rows),
+        },
+        Err(e) => Check {
+            name: "database writes",
+            passed: false,
+            detail: format!("the crawl failed before a session was recorded: {:#}", e),
+        },
+    });
+
+    // Per-host cap
+    let peak = server.peak();
+    checks.push(Check {
+        name: "per-host cap",
+        passed: peak <= max_concurrent_per_host,
+        detail: if peak <= max_concurrent_per_host {
+            format!(
+                "at most {} requests were in flight to the fixture server, within max_concurrent_per_host = {}",
+                peak, max_concurrent_per_host
+            )
+        } else {
+            format!(
+                "{} requests were in flight to the fixture server at once, over max_concurrent_per_host = {}",
+                peak, max_concurrent_per_host
+            )
+        },
+    });
+
+    return Ok(checks);
+}
+
+/// Stores a robots.txt for the fixture host that is older than `robots_ttl_hours`, and checks
+/// that checking URLs against it fetches the fixture's robots.txt again, exactly once.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` of the self-test crawl.
+/// * `server` - A reference to the `FixtureServer` of the fixture site.
+fn check_robots_refetch(config: Config, server: &FixtureServer) -> Result<Check> {
+    let base = server.base();
+    let name = "robots.txt refetch";
+    let host = url::Url::parse(base)?
+        .host_str()
+        .context("The fixture server has no host")?
+        .to_string();
+    let stale_time = Utc::now() - chrono::Duration::hours(config.robots_ttl_hours as i64 + 1);
+
+    let crawler = Crawler::with_storage(config, Box::new(Database::in_memory()?))?;
+    let storage = crawler.storage();
+    storage.setup()?;
+    storage.put_domain(&Domain {
+        domain: host.clone(),
+        crawl_time: stale_time,
+        robots: STALE_ROBOTS.to_string(),
+        robots_url: Some(format!("{}/robots.txt", base)),
+        sitemaps: Vec::new(),
+        feeds: Vec::new(),
+        robots_override: None,
+        broken_tls_redirect: false,
+    })?;
+
+    // Check two URLs, of which only the first should load robots.txt
+    let requests_before = server.requests_for("/robots.txt");
+    let about_allowed = crawler.is_allowed_to_scrape(&format!("{}/about", base))?;
+    let secret_allowed = crawler.is_allowed_to_scrape(&format!("{}/private/secret", base))?;
+    let requests = server.requests_for("/robots.txt") - requests_before;
+    let refreshed = storage
+        .get_domain(&host)?
+        .is_some_and(|domain| domain.robots == FIXTURE_ROBOTS && domain.crawl_time > stale_time);
+
+    let passed = requests == 1 && refreshed && about_allowed && !secret_allowed;
+    return Ok(Check {
+        name,
+        passed,
+        detail: if passed {
+            format!(
+                "the stale robots.txt of {} was fetched again once, stored, and obeyed",
+                host
+            )
+        } else if requests != 1 {
+            format!(
+                "the stale robots.txt of {} was fetched {} times, expected once",
+                host, requests
+            )
+        } else if !refreshed {
+            format!(
+                "the robots.txt of {} was fetched again, but the stored copy wasn't replaced",
+                host
+            )
+        } else {
+            format!(
+                "the robots.txt of {} was fetched again, but {}/about allowed: {} and {}/private/secret allowed: {}",
+                host, base, about_allowed, base, secret_allowed
+            )
+        },
+    });
+}
+
+/// Answers a request with the matching fixture page, or a 404, once `FIXTURE_LATENCY` has
+/// passed. The first request for `FIXTURE_FLAKY_PAGE` is answered with a 503 instead.
+///
+/// # Arguments
+///
+/// * `request` - A reference to the `FixtureRequest` to answer.
+/// * `flaky_requests` - A reference to the number of requests for the flaky page so far.
+fn respond(request: &FixtureRequest, flaky_requests: &AtomicUsize) -> FixtureResponse {
+    std::thread::sleep(FIXTURE_LATENCY);
+
+    let path = request.path.as_str();
+    let (mut status, location, body) = FIXTURE_PAGES
+        .iter()
+        .find(|(page, ..)| *page == path)
+        .map(|(_, status, location, body)| (*status, *location, *body))
+        .unwrap_or((
+            404,
+            None,
+            "<html><head><title>Not Found</title></head></html>",
+        ));
+    if path == FIXTURE_FLAKY_PAGE && flaky_requests.fetch_add(1, Ordering::SeqCst) == 0 {
+        status = 503;
+    }
+
+    let mut response = if path == "/robots.txt" {
+        FixtureResponse::new(status, body).header("Content-Type", "text/plain")
+    } else {
+        FixtureResponse::html(status, body)
+    };
+    if let Some(location) = location {
+        response = response.header("Location", location);
+    }
+    return response;
+}
@@ -0,0 +1,180 @@
+use crate::database::Database;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+
+/// The verdicts a crawl session can be given once it is finished.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SessionStatus {
+    /// The crawl looks healthy, and its data can be trusted by reports.
+    Accepted,
+    /// The crawl fetched far fewer pages than the previous one while most fetches failed. Its
+    /// data is kept, but reports on the latest crawl skip it.
+    Suspect,
+}
+
+impl std::str::FromStr for SessionStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        return match s {
+            "accepted" => Ok(SessionStatus::Accepted),
+            "suspect" => Ok(SessionStatus::Suspect),
+            other => bail!("Unknown crawl session status '{}'", other),
+        };
+    }
+}
+
+impl std::fmt::Display for SessionStatus {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SessionStatus::Accepted => "accepted",
+            SessionStatus::Suspect => "suspect",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Represents a single run of the crawler from an origin URL.
+#[derive(Clone)]
+pub struct CrawlSession {
+    /// A string that holds the origin URL the crawl started from, or its origin URLs separated
+    /// by spaces if it started from several.
+    pub origin_url: String,
+    /// A `DateTime<Utc>` that represents the time the crawl started.
+    pub start_time: DateTime<Utc>,
+    /// A `DateTime<Utc>` that represents the time the crawl finished.
+    pub end_time: DateTime<Utc>,
+    /// A `u64` that holds the number of pages successfully fetched.
+    pub pages_fetched: u64,
+    /// A `u64` that holds the number of fetches that failed.
+    pub fetch_failures: u64,
+    /// The verdict given to the crawl.
+    pub status: SessionStatus,
+    /// A `u64` that holds the size of the database when the crawl finished.
+    pub disk_bytes: u64,
+    /// Whether the crawl stopped admitting new URLs because it reached `max_disk_mb`.
+    pub truncated_for_disk: bool,
+}
+
+impl CrawlSession {
+    /// Judges whether a finished crawl is suspect compared to the previous accepted crawl.
+    ///
+    /// A crawl is suspect when it fetched fewer than `min_ratio` times the pages of the previous
+    /// crawl, and more of its fetches failed than succeeded. Requiring both keeps crawls that are
+    /// small because their pages were cached, or because the site shrank, from being flagged.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The previous accepted crawl from the same origin URL, if any.
+    /// * `min_ratio` - The fraction of the previous crawl's pages below which a crawl is small.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating whether the crawl is suspect.
+    pub fn is_suspect(&self, previous: Option<&CrawlSession>, min_ratio: f64) -> bool {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return false,
+        };
+
+        let shrunk = (self.pages_fetched as f64) < previous.pages_fetched as f64 * min_ratio;
+        return shrunk && self.fetch_failures > self.pages_fetched;
+    }
+
+    /// Reads the latest accepted `CrawlSession` from the given origin URL.
+    ///
+    /// Suspect sessions are skipped, so that a failed crawl is never used as the baseline of the
+    /// next one, nor as the latest data of a site.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_url` - A string slice that holds the origin URL of the crawl.
+    /// * `database` - A reference to the `Database` from which the session will be read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Self>>` which is `Ok(None)` if no accepted crawl is stored.
+    pub fn read_latest_accepted(origin_url: &str, database: &Database) -> Result<Option<Self>> {
+        let query = format!(
+            "SELECT start_time, end_time, pages_fetched, fetch_failures, status, disk_bytes, truncated_for_disk FROM crawls WHERE origin_url = '{}' AND status = '{}' ORDER BY id DESC LIMIT 1",
+            origin_url.replace("'", "''"),
+            SessionStatus::Accepted
+        );
+        let mut statement = database.prepare(&query)?;
+
+        if let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let start_time = statement
+                .read::<String, usize>(0)
+                .context("Failed to read start_time from the database")?;
+            let end_time = statement
+                .read::<String, usize>(1)
+                .context("Failed to read end_time from the database")?;
+            let pages_fetched = statement
+                .read::<i64, usize>(2)
+                .context("Failed to read pages_fetched from the database")?
+                as u64;
+            let fetch_failures = statement
+                .read::<i64, usize>(3)
+                .context("Failed to read fetch_failures from the database")?
+                as u64;
+            let status = statement
+                .read::<String, usize>(4)
+                .context("Failed to read status from the database")?;
+            let disk_bytes = statement
+                .read::<i64, usize>(5)
+                .context("Failed to read disk_bytes from the database")?
+                as u64;
+            let truncated_for_disk = statement
+                .read::<i64, usize>(6)
+                .context("Failed to read truncated_for_disk from the database")?
+                != 0;
+
+            return Ok(Some(Self {
+                origin_url: origin_url.to_string(),
+                start_time: DateTime::parse_from_rfc3339(&start_time)
+                    .context("Failed to parse start_time as RFC 3339")?
+                    .with_timezone(&Utc),
+                end_time: DateTime::parse_from_rfc3339(&end_time)
+                    .context("Failed to parse end_time as RFC 3339")?
+                    .with_timezone(&Utc),
+                pages_fetched,
+                fetch_failures,
+                status: status.parse()?,
+                disk_bytes,
+                truncated_for_disk,
+            }));
+        }
+
+        return Ok(None);
+    }
+
+    /// Writes the `CrawlSession` instance into the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - A reference to the `Database` where the session will be written.
+    pub fn write_into(&self, database: &Database) -> Result<()> {
+        let query = format!(
+            "INSERT INTO crawls (origin_url, start_time, end_time, pages_fetched, fetch_failures, status, disk_bytes, truncated_for_disk) VALUES ('{}', '{}', '{}', {}, {}, '{}', {}, {})",
+            self.origin_url.replace("'", "''"),
+            self.start_time.to_rfc3339(),
+            self.end_time.to_rfc3339(),
+            self.pages_fetched,
+            self.fetch_failures,
+            self.status,
+            self.disk_bytes,
+            self.truncated_for_disk as i64
+        );
+        database.execute(&query)?;
+
+        info!(
+            "Crawl session {}: {} pages fetched, {} failures",
+            self.status, self.pages_fetched, self.fetch_failures
+        );
+        return Ok(());
+    }
+}
@@ -1,13 +1,181 @@
-use crate::database::Database;
-use anyhow::{Context, Result};
+use crate::public_suffix::registrable_domain;
 use chrono::prelude::*;
-use log::info;
-use std::collections::HashSet;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use url::Url;
+
+/// Represents the stored sites of one host: how many there are, and when the oldest and newest
+/// of them were crawled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DomainSummary {
+    /// The host of the sites.
+    pub host: String,
+    /// The number of stored sites of the host.
+    pub pages: u64,
+    /// The crawl time of the least recently crawled site of the host.
+    pub oldest_crawl: DateTime<Utc>,
+    /// The crawl time of the most recently crawled site of the host.
+    pub newest_crawl: DateTime<Utc>,
+}
+
+impl DomainSummary {
+    /// Groups sites by host, sorted by page count descending and then by host.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use rustle::DomainSummary;
+    ///
+    /// let old = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    /// let new = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+    /// let summaries = DomainSummary::from_sites([
+    ///     ("https://example.com/", old),
+    ///     ("https://example.com/about", new),
+    ///     ("https://blog.example.com/", new),
+    /// ]);
+    /// assert_eq!(summaries[0].host, "example.com");
+    /// assert_eq!(summaries[0].pages, 2);
+    /// assert_eq!((summaries[0].oldest_crawl, summaries[0].newest_crawl), (old, new));
+    /// assert_eq!(summaries[1].host, "blog.example.com");
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `sites` - The URL and crawl time of every site. URLs without a host are counted under
+    ///   an empty host.
+    pub fn from_sites<'a>(sites: impl IntoIterator<Item = (&'a str, DateTime<Utc>)>) -> Vec<Self> {
+        let mut hosts: BTreeMap<String, DomainSummary> = BTreeMap::new();
+        for (url, crawl_time) in sites {
+            let host = Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default();
+            let summary = hosts.entry(host.clone()).or_insert_with(|| DomainSummary {
+                host,
+                pages: 0,
+                oldest_crawl: crawl_time,
+                newest_crawl: crawl_time,
+            });
+            summary.pages += 1;
+            summary.oldest_crawl = summary.oldest_crawl.min(crawl_time);
+            summary.newest_crawl = summary.newest_crawl.max(crawl_time);
+        }
+
+        let mut summaries = hosts.into_values().collect::<Vec<Self>>();
+        summaries.sort_by(|a, b| b.pages.cmp(&a.pages).then_with(|| a.host.cmp(&b.host)));
+        return summaries;
+    }
+
+    /// Groups the summaries of hosts by their registrable domain, for crawls with the `site`
+    /// scope, sorted like `from_sites`. Hosts without a registrable domain, such as IP addresses,
+    /// stay on their own.
+    ///
+    /// ```
+    /// use chrono::Utc;
+    /// use rustle::DomainSummary;
+    ///
+    /// let now = Utc::now();
+    /// let summaries = DomainSummary::from_sites([
+    ///     ("https://www.example.co.uk/", now),
+    ///     ("https://blog.example.co.uk/", now),
+    ///     ("https://another.co.uk/", now),
+    ///     ("http://127.0.0.1/", now),
+    /// ]);
+    /// let sites = DomainSummary::group_by_site(summaries);
+    /// assert_eq!(sites[0].host, "example.co.uk");
+    /// assert_eq!(sites[0].pages, 2);
+    /// assert_eq!(sites[1].host, "127.0.0.1");
+    /// assert_eq!(sites[2].host, "another.co.uk");
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `summaries` - The summaries of every host.
+    pub fn group_by_site(summaries: Vec<Self>) -> Vec<Self> {
+        let mut sites: BTreeMap<String, DomainSummary> = BTreeMap::new();
+        for summary in summaries {
+            let site = registrable_domain(&summary.host).unwrap_or_else(|| summary.host.clone());
+            match sites.get_mut(&site) {
+                Some(grouped) => {
+                    grouped.pages += summary.pages;
+                    grouped.oldest_crawl = grouped.oldest_crawl.min(summary.oldest_crawl);
+                    grouped.newest_crawl = grouped.newest_crawl.max(summary.newest_crawl);
+                }
+                None => {
+                    sites.insert(
+                        site.clone(),
+                        DomainSummary {
+                            host: site,
+                            ..summary
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut summaries = sites.into_values().collect::<Vec<Self>>();
+        summaries.sort_by(|a, b| b.pages.cmp(&a.pages).then_with(|| a.host.cmp(&b.host)));
+        return summaries;
+    }
+
+    /// Formats summaries as the lines of a table, with a header and aligned columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `summaries` - The summaries, one per line in the given order.
+    pub fn table(summaries: &[DomainSummary]) -> Vec<String> {
+        let width = summaries
+            .iter()
+            .map(|summary| summary.host.len())
+            .chain(["Domain".len()])
+            .max()
+            .unwrap_or_default();
+        let mut lines = vec![format!(
+            "{:<width$}  {:>8}  {:<20}  {}",
+            "Domain", "Pages", "Oldest crawl", "Newest crawl"
+        )];
+        for summary in summaries {
+            lines.push(format!(
+                "{:<width$}  {:>8}  {:<20}  {}",
+                summary.host,
+                summary.pages,
+                summary
+                    .oldest_crawl
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                summary
+                    .newest_crawl
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+        return lines;
+    }
+}
+
+/// Represents a stored link whose target failed to load, either with an error status code or
+/// with a fetch error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrokenLink {
+    /// The URL of the page the link is on.
+    pub source_url: String,
+    /// The URL the link points at.
+    pub target_url: String,
+    /// The kind of the link: `asset` for the images, scripts and stylesheets of the page,
+    /// `external` for the links to hosts out of scope, and `page` for the other links.
+    pub kind: String,
+    /// The HTTP status code of the target, if it was fetched.
+    pub status_code: Option<u16>,
+    /// The `ErrorCategory` of the last failed fetch of the target, such as `timeout`, if it
+    /// failed.
+    pub error_kind: Option<String>,
+}
+
+/// The URL, content hash and title of a stored site, as listed by `Database::list_fingerprints`.
+pub type Fingerprint = (String, Option<String>, Option<String>);
 
 /// Represents a website with its URL, crawl time, and links to other sites.
 ///
 /// This struct is used to store information about a website, including its URL,
 /// the time it was crawled, and the URLs it links to.
+#[derive(Clone, Default)]
 pub struct Site {
     /// A string that holds the URL of a given site.
     pub url: String,
@@ -15,144 +183,212 @@ pub struct Site {
     pub crawl_time: DateTime<Utc>,
     /// A `HashSet<String>` containing the urls that the site links to.
     pub links_to: HashSet<String>,
+    /// A `HashMap<String, String>` that holds the anchor text of the links of `links_to` whose
+    /// `<a>` element was read, by URL. It is empty for links without any text, such as
+    /// image-only links. Use `links_detailed` to list the links along with their anchor text.
+    pub anchor_texts: HashMap<String, String>,
+    /// A `HashSet<String>` that holds the URLs of `links_to` that are assets the page embeds,
+    /// such as images, scripts and stylesheets, rather than links to other pages. They are only
+    /// extracted with `extract_assets`.
+    pub assets: HashSet<String>,
+    /// A `bool` that is set when the response headers exceeded the configured size or cookie
+    /// limits.
+    pub header_anomaly: bool,
+    /// A `u64` that holds the number of cookies the response tried to set.
+    pub cookies_set: u64,
+    /// A `bool` that is set when the response was a bot-detection challenge instead of the
+    /// site's content.
+    pub bot_challenged: bool,
+    /// A `HashSet<String>` that holds the URLs of `links_to` that aren't followed because their
+    /// `<a>` elements are marked `rel="nofollow"`, `rel="ugc"` or `rel="sponsored"`. They are
+    /// only recorded with `record_nofollow_links`.
+    pub nofollow_links: HashSet<String>,
+    /// A `HashSet<String>` that holds the URLs of `links_to` on hosts out of the scope of the
+    /// crawl, which are never followed. They are only recorded with `record_external_links`.
+    pub external_links: HashSet<String>,
+    /// A `f64` that estimates how much the site depends on JavaScript to render its content.
+    pub js_dependency_score: f64,
+    /// An `Option<u16>` that holds the HTTP status code of the response, if it was recorded.
+    pub status_code: Option<u16>,
+    /// An `Option<String>` that holds the URL of the response after redirects, if it was
+    /// recorded.
+    pub final_url: Option<String>,
+    /// A `bool` that is set when the site only redirects to another URL. Its `links_to` then
+    /// holds the next URL of the redirect chain, and its `final_url` the last URL of the chain
+    /// that was fetched, which is the site itself if its redirect wasn't followed.
+    pub is_redirect: bool,
+    /// An `Option<u64>` that holds the number of redirects between the site and its `final_url`,
+    /// if it was recorded.
+    pub redirect_hops: Option<u64>,
+    /// An `Option<String>` that holds the canonical URL the page declared with
+    /// `<link rel="canonical">`, if it differs from the URL of the site. A site whose page was
+    /// stored under its canonical URL instead only links to it, as an alias.
+    pub canonical_url: Option<String>,
+    /// An `Option<String>` that holds the media type of the response, if it was recorded.
+    pub content_type: Option<String>,
+    /// An `Option<u64>` that holds the size of the response body in bytes, if it is known.
+    pub body_bytes: Option<u64>,
+    /// An `Option<String>` that holds the media type sniffed from the first bytes of the body, if
+    /// it was read and matched a known signature.
+    pub sniffed_type: Option<String>,
+    /// An `Option<String>` that holds the `ETag` header of the response, if it had one.
+    pub etag: Option<String>,
+    /// An `Option<String>` that holds the `Last-Modified` header of the response, if it had one.
+    pub last_modified: Option<String>,
+    /// An `Option<String>` that holds the whitespace-normalized `<title>` of the page, if it has
+    /// one.
+    pub title: Option<String>,
+    /// An `Option<String>` that holds the content of the `<meta name="description">` tag of the
+    /// page, if it has one.
+    pub description: Option<String>,
+    /// An `Option<String>` that holds the SHA-256 of the whitespace-normalized HTML of the page,
+    /// if it was read.
+    pub content_hash: Option<String>,
+    /// An `Option<DateTime<Utc>>` that holds the time the content of the page was first seen
+    /// with its current hash, if it is known.
+    pub last_changed: Option<DateTime<Utc>>,
+    /// An `Option<u64>` that holds the generation of the crawl that fetched the site, counting the
+    /// origin as generation 0, if it was recorded.
+    pub generation: Option<u64>,
+    /// An `Option<u64>` that holds the worker batch of the crawl that fetched the site, numbered
+    /// in increasing order from the start of the crawl, if it was recorded.
+    pub batch_id: Option<u64>,
+    /// An `Option<u64>` that holds the smallest number of links between an origin URL and the
+    /// site over every crawl that stored it, counting the origin as depth 0, if it was recorded.
+    pub depth: Option<u64>,
+    /// An `Option<String>` that holds the URL of the page the site was first discovered on at its
+    /// `depth`, or `None` for origin URLs, the URLs seeded from sitemaps and feeds, and when it
+    /// wasn't recorded.
+    pub discovered_from: Option<String>,
+    /// An `Option<u64>` that holds the time from sending the request to reading the response, in
+    /// milliseconds, if it was recorded.
+    pub response_ms: Option<u64>,
+    /// An `Option<i64>` that references the `robots_history` snapshot the site was fetched under,
+    /// if it was recorded in compliance mode.
+    pub robots_snapshot: Option<i64>,
+    /// An `Option<String>` that holds the robots.txt rule that allowed the site, or `no rules`,
+    /// if it was recorded in compliance mode.
+    pub robots_rule: Option<String>,
+    /// An `Option<i64>` that references the `crawl_runs` row of the run that last stored the
+    /// site, if it was recorded.
+    pub run_id: Option<i64>,
+    /// An `Option<String>` that holds the visible text of the page, written to the `page_text`
+    /// table when set. It is not read back by `Database::read_site`; use `Database::search_text` to query it.
+    pub text: Option<String>,
 }
 
 /// Implements the `Display` trait for the `Site` struct.
 ///
 /// This allows a `Site` instance to be formatted as a string using the `{}` marker.
-/// The formatted string will display the URL of the site and the number of links it contains.
+/// The formatted string will display the URL of the site, its title if it has one, and the
+/// number of links it contains.
 impl std::fmt::Display for Site {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(fmt, "{} ({})", self.url, self.links_to.len())
+        match &self.title {
+            Some(title) => write!(fmt, "{} \"{}\" ({})", self.url, title, self.links_to.len()),
+            None => write!(fmt, "{} ({})", self.url, self.links_to.len()),
+        }
     }
 }
 
 impl Site {
-    /// Reads a `Site` from the database based on the given domain.
+    /// Hashes the content of a page, so that changes between crawls can be detected.
     ///
-    /// This function queries the database for a site with the specified URL.
-    /// If a matching domain is found, it constructs a `Site` instance with the retrieved data.
+    /// Runs of whitespace are collapsed before hashing, so that reformatting alone doesn't count
+    /// as a change.
     ///
     /// # Arguments
     ///
-    /// * `url` - A string slice that holds the URL of the site to be read.
-    /// * `database` - A reference to the `Database` from which the site will be read.
+    /// * `html` - A string slice that holds the HTML of the page.
     ///
     /// # Returns
     ///
-    /// A `Result<Option<Self>>` which is `Ok(Some(Site))` if a matching site is found,
-    /// or `Ok(None)` if no match is found. If an error occurs during the query or data retrieval,
-    /// it returns an `Err`.
-    pub fn read_into(url: &str, database: &Database) -> Result<Option<Self>> {
-        // Declare SQLite Query to get all entries where the URL value is equal to the given URL
-        let query = format!(
-            "SELECT crawl_time, links_to FROM sites WHERE url = '{}'",
-            url.replace("'", "''")
-        );
-
-        // Prepare Query
-        let mut statement = database.prepare(&query)?;
-
-        // Iterate over the rows returned by the query (should only be one, but need to return none
-        // if no rows are returned)
-        while let sqlite::State::Row = statement
-            .next()
-            .context("Failed to execute the SQL query")?
-        {
-            // Read the crawl time from the first column of the current row
-            let crawl_time_str: String = statement
-                .read::<String, usize>(0)
-                .context("Failed to read crawl_time from the database")?;
-
-            // Read the links to other sites from the second column of the current row
-            let links_to_str: String = statement
-                .read::<String, usize>(1)
-                .context("Failed to read links_to from the database")?;
-
-            // Parse the crawl time string into a DateTime<Utc> object
-            let crawl_time = DateTime::parse_from_rfc3339(&crawl_time_str)
-                .context("Failed to parse crawl_time as RFC 3339")?
-                .with_timezone(&Utc);
-
-            // Split the links_to string by commas and collect them into a HashSet
-            let links_to = if links_to_str.is_empty() {
-                HashSet::new()
-            } else {
-                links_to_str
-                    .replace("''", "'")
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect()
-            };
-
-            // Return a `Site` instance with the retrieved data
-            return Ok(Some(Self {
-                url: url.to_string().replace("''", "'"),
-                crawl_time,
-                links_to,
-            }));
+    /// The SHA-256 of the normalized HTML, as a lowercase hex string.
+    pub fn hash_content(html: &str) -> String {
+        let mut hasher = Sha256::new();
+        for (i, word) in html.split_whitespace().enumerate() {
+            if i > 0 {
+                hasher.update(b" ");
+            }
+            hasher.update(word.as_bytes());
         }
-
-        // If no rows are retrieved by the query, return None
-        return Ok(None);
+        return hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
     }
 
-    /// Writes the `Site` instance into the database.
+    /// Lists the links of the site along with their anchor text, which is empty for links
+    /// without any text or whose text wasn't recorded.
     ///
-    /// This function converts the `links_to` field into a comma-separated string,
-    /// formats the `crawl_time` field into an RFC 3339 string, and then inserts or
-    /// replaces the site record in the database with the current `Site` instance's data.
+    /// ```
+    /// use rustle::{Database, Site, Storage};
     ///
-    /// # Arguments
+    /// let db = Database::in_memory()?;
+    /// db.setup()?;
+    /// let site = Site {
+    ///     url: "https://example.com/".to_string(),
+    ///     links_to: ["https://example.com/about", "https://example.com/logo"]
+    ///         .map(str::to_string)
+    ///         .into(),
+    ///     anchor_texts: [("https://example.com/about".to_string(), "About us".to_string())].into(),
+    ///     ..Default::default()
+    /// };
+    /// db.put_site(&site)?;
+    ///
+    /// let stored = db.get_site("https://example.com/")?.unwrap();
+    /// assert_eq!(
+    ///     stored.links_detailed(),
+    ///     [
+    ///         ("https://example.com/about", "About us"),
+    ///         ("https://example.com/logo", ""),
+    ///     ]
+    /// );
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    ///
+    /// # Returns
     ///
-    /// * `database` - A reference to the `Database` where the site will be written.
-    pub fn write_into(&self, database: &Database) {
-        // Convert links_to HashSet into comma-separated string
-        let links_to_str = self
+    /// A `Vec<(&str, &str)>` holding the URL and anchor text of every link, sorted by URL.
+    pub fn links_detailed(&self) -> Vec<(&str, &str)> {
+        let mut links: Vec<(&str, &str)> = self
             .links_to
             .iter()
-            .cloned()
-            .collect::<Vec<String>>()
-            .join(",");
-
-        // Convert crawl_time to RFC 3339 string
-        let crawl_time_str = self.crawl_time.to_rfc3339();
-
-        // Declare SQLite query
-        let query =
-            format!(
-            "INSERT OR REPLACE INTO sites (url, crawl_time, links_to) VALUES ('{}', '{}', '{}')",
-            self.url.replace("'", "''"), crawl_time_str, links_to_str.replace("'", "''")
-        );
-
-        // Execute query
-        database.execute(&query).unwrap();
+            .map(|link| {
+                let anchor_text = self.anchor_texts.get(link).map_or("", String::as_str);
+                (link.as_str(), anchor_text)
+            })
+            .collect();
+        links.sort();
+        return links;
     }
 
-    /// Summarizes the database by counting the number of entries in the `sites` table.
-    ///
-    /// This function prepares and executes a SQL query to count the number of entries
-    /// in the `sites` table and logs the result using the `info` log level.
-    ///
-    /// # Arguments
+    /// Gets the links of the site that are crawled: those that are neither external nor marked
+    /// not to be followed.
     ///
-    /// * `database` - A reference to the `Database` where the domain will be summarized.
+    /// ```
+    /// use rustle::Site;
     ///
-    /// # Returns
-    ///
-    /// A `Result<()>` which is `Ok(())` if the operation is successful, or an `Err` if an error occurs.
-    pub fn summarize_site_table(database: &Database) -> Result<()> {
-        let query = "SELECT COUNT(*) FROM sites";
-        let mut statement = database.prepare(query).unwrap();
-        let _ = statement
-            .next()
-            .context("Failed to execute the SQL query")?;
-
-        let count = statement
-            .read::<i64, usize>(0)
-            .context("Failed to read the count from the database")?;
-
-        info!("{} Entries in site table", count);
-        return Ok(());
+    /// let site = Site {
+    ///     url: "https://example.com/".to_string(),
+    ///     links_to: ["https://example.com/about", "https://example.com/ad", "https://other.org/"]
+    ///         .map(str::to_string)
+    ///         .into(),
+    ///     nofollow_links: ["https://example.com/ad".to_string()].into(),
+    ///     external_links: ["https://other.org/".to_string()].into(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(site.followed_links(), ["https://example.com/about".to_string()].into());
+    /// ```
+    pub fn followed_links(&self) -> HashSet<String> {
+        return self
+            .links_to
+            .iter()
+            .filter(|link| {
+                !self.nofollow_links.contains(*link) && !self.external_links.contains(*link)
+            })
+            .cloned()
+            .collect();
     }
 }
@@ -0,0 +1,1594 @@
+use crate::crawl_run::CrawlRun;
+use crate::database::Database;
+use crate::session::SessionStatus;
+use crate::site::{BrokenLink, DomainSummary, Fingerprint, Site};
+use anyhow::{bail, Context, Result};
+use chrono::prelude::*;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use url::Url;
+
+/// The links of a site as read from the links table: their URLs, their anchor text by URL, and
+/// the URLs of the assets, of the links not followed because of their `rel` attribute and of the
+/// external links among them.
+type StoredLinks = (
+    HashSet<String>,
+    HashMap<String, String>,
+    HashSet<String>,
+    HashSet<String>,
+    HashSet<String>,
+);
+
+impl Database {
+    /// Reads a `Site` from the database based on the given domain.
+    ///
+    /// This function queries the database for a site with the specified URL.
+    /// If a matching domain is found, it constructs a `Site` instance with the retrieved data.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the site to be read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Site>>` which is `Ok(Some(Site))` if a matching site is found,
+    /// or `Ok(None)` if no match is found. If an error occurs during the query or data retrieval,
+    /// it returns an `Err`.
+    pub fn read_site(&self, url: &str) -> Result<Option<Site>> {
+        // Declare SQLite Query to get all entries where the URL value is equal to the given URL
+        let query = format!(
+            "SELECT crawl_time, header_anomaly, cookies_set, bot_challenged, js_dependency_score, status_code, final_url, content_type, body_bytes, title, description, content_hash, last_changed, sniffed_type, etag, last_modified, generation, batch_id, response_ms, robots_snapshot, robots_rule, is_redirect, redirect_hops, run_id, canonical_url, depth, discovered_from FROM sites WHERE url = '{}'",
+            url.replace("'", "''")
+        );
+
+        // Prepare Query
+        let mut statement = self.prepare(&query)?;
+
+        // Read the first row returned by the query (there should only be one, but need to return
+        // none if no rows are returned)
+        if let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            // Read the crawl time from the first column of the current row
+            let crawl_time_str: String = statement
+                .read::<String, usize>(0)
+                .context("Failed to read crawl_time from the database")?;
+
+            // Read the header anomaly flag and cookie count from the second and third columns
+            let header_anomaly = statement
+                .read::<i64, usize>(1)
+                .context("Failed to read header_anomaly from the database")?
+                != 0;
+            let cookies_set = statement
+                .read::<i64, usize>(2)
+                .context("Failed to read cookies_set from the database")?
+                as u64;
+
+            // Read the bot challenge flag from the fourth column
+            let bot_challenged = statement
+                .read::<i64, usize>(3)
+                .context("Failed to read bot_challenged from the database")?
+                != 0;
+
+            // Read the JavaScript dependency score from the fifth column
+            let js_dependency_score = statement
+                .read::<f64, usize>(4)
+                .context("Failed to read js_dependency_score from the database")?;
+
+            // Read the status code and final URL from the sixth and seventh columns
+            let status_code = statement
+                .read::<Option<i64>, usize>(5)
+                .context("Failed to read status_code from the database")?
+                .map(|status_code| status_code as u16);
+            let final_url = statement
+                .read::<Option<String>, usize>(6)
+                .context("Failed to read final_url from the database")?;
+
+            // Read the content type from the eighth column
+            let content_type = statement
+                .read::<Option<String>, usize>(7)
+                .context("Failed to read content_type from the database")?;
+
+            // Read the body size from the ninth column
+            let body_bytes = statement
+                .read::<Option<i64>, usize>(8)
+                .context("Failed to read body_bytes from the database")?
+                .map(|body_bytes| body_bytes as u64);
+
+            // Read the title and description from the tenth and eleventh columns
+            let title = statement
+                .read::<Option<String>, usize>(9)
+                .context("Failed to read title from the database")?;
+            let description = statement
+                .read::<Option<String>, usize>(10)
+                .context("Failed to read description from the database")?;
+
+            // Read the content hash and the time it last changed from the last two columns
+            let content_hash = statement
+                .read::<Option<String>, usize>(11)
+                .context("Failed to read content_hash from the database")?;
+            let last_changed = statement
+                .read::<Option<String>, usize>(12)
+                .context("Failed to read last_changed from the database")?
+                .map(|last_changed| {
+                    DateTime::parse_from_rfc3339(&last_changed)
+                        .context("Failed to parse last_changed as RFC 3339")
+                })
+                .transpose()?
+                .map(|last_changed| last_changed.with_timezone(&Utc));
+
+            // Read the sniffed media type from the fourteenth column
+            let sniffed_type = statement
+                .read::<Option<String>, usize>(13)
+                .context("Failed to read sniffed_type from the database")?;
+
+            // Read the validators of the response from the last two columns
+            let etag = statement
+                .read::<Option<String>, usize>(14)
+                .context("Failed to read etag from the database")?;
+            let last_modified = statement
+                .read::<Option<String>, usize>(15)
+                .context("Failed to read last_modified from the database")?;
+
+            // Read the generation, batch and response time of the fetch from the last columns
+            let generation = statement
+                .read::<Option<i64>, usize>(16)
+                .context("Failed to read generation from the database")?
+                .map(|generation| generation as u64);
+            let batch_id = statement
+                .read::<Option<i64>, usize>(17)
+                .context("Failed to read batch_id from the database")?
+                .map(|batch_id| batch_id as u64);
+            let response_ms = statement
+                .read::<Option<i64>, usize>(18)
+                .context("Failed to read response_ms from the database")?
+                .map(|response_ms| response_ms as u64);
+
+            // Read the compliance record of the fetch from the last two columns
+            let robots_snapshot = statement
+                .read::<Option<i64>, usize>(19)
+                .context("Failed to read robots_snapshot from the database")?;
+            let robots_rule = statement
+                .read::<Option<String>, usize>(20)
+                .context("Failed to read robots_rule from the database")?;
+
+            // Read the redirect flag and hop count from the last two columns
+            let is_redirect = statement
+                .read::<i64, usize>(21)
+                .context("Failed to read is_redirect from the database")?
+                != 0;
+            let redirect_hops = statement
+                .read::<Option<i64>, usize>(22)
+                .context("Failed to read redirect_hops from the database")?
+                .map(|redirect_hops| redirect_hops as u64);
+
+            // Read the run that last stored the site, and the canonical URL it declared, from the
+            // last two columns
+            let run_id = statement
+                .read::<Option<i64>, usize>(23)
+                .context("Failed to read run_id from the database")?;
+            let canonical_url = statement
+                .read::<Option<String>, usize>(24)
+                .context("Failed to read canonical_url from the database")?;
+
+            // Read the depth the site was discovered at, and the page it was discovered on, from
+            // the last two columns
+            let depth = statement
+                .read::<Option<i64>, usize>(25)
+                .context("Failed to read depth from the database")?
+                .map(|depth| depth as u64);
+            let discovered_from = statement
+                .read::<Option<String>, usize>(26)
+                .context("Failed to read discovered_from from the database")?;
+
+            // Parse the crawl time string into a DateTime<Utc> object
+            let crawl_time = DateTime::parse_from_rfc3339(&crawl_time_str)
+                .context("Failed to parse crawl_time as RFC 3339")?
+                .with_timezone(&Utc);
+
+            // Collect the links to other sites and their anchor text from the links table
+            let (links_to, anchor_texts, assets, nofollow_links, external_links) =
+                self.read_links(url)?;
+
+            // Return a `Site` instance with the retrieved data
+            return Ok(Some(Site {
+                url: url.to_string().replace("''", "'"),
+                crawl_time,
+                links_to,
+                anchor_texts,
+                assets,
+                header_anomaly,
+                cookies_set,
+                bot_challenged,
+                nofollow_links,
+                external_links,
+                js_dependency_score,
+                status_code,
+                final_url,
+                is_redirect,
+                redirect_hops,
+                canonical_url,
+                content_type,
+                body_bytes,
+                sniffed_type,
+                etag,
+                last_modified,
+                title,
+                description,
+                content_hash,
+                last_changed,
+                generation,
+                batch_id,
+                depth,
+                discovered_from,
+                response_ms,
+                robots_snapshot,
+                robots_rule,
+                run_id,
+                text: None,
+            }));
+        }
+
+        // If no rows are retrieved by the query, return None
+        return Ok(None);
+    }
+
+    /// Writes several sites into the database in a single transaction, which is much faster
+    /// than writing them one by one.
+    ///
+    /// # Arguments
+    ///
+    /// * `sites` - A slice of the sites to be written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any site cannot be written, in which case none of
+    /// them are.
+    pub fn write_sites(&self, sites: &[Site]) -> Result<()> {
+        let text_search = self.has_text_search()?;
+        let statements = sites
+            .iter()
+            .flat_map(|site| write_statements(site, text_search))
+            .collect::<Vec<String>>();
+        return self.execute_transaction(&statements);
+    }
+
+    /// Updates the crawl time of a stored site, and the details of the fetch that found it
+    /// unmodified, keeping the rest of its data, links included.
+    ///
+    /// The crawl time, generation, batch, depth, compliance record and crawl run of the site
+    /// replace the stored ones, as does the page it was discovered from if it is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `site` - A reference to the `Site` that was found unmodified.
+    pub fn touch_site(&self, site: &Site) -> Result<()> {
+        let or_null = |value: Option<String>| value.unwrap_or_else(|| "NULL".to_string());
+        let quoted = |value: &Option<String>| {
+            return or_null(
+                value
+                    .as_ref()
+                    .map(|value| format!("'{}'", value.replace("'", "''"))),
+            );
+        };
+        return self.execute(&format!(
+            "UPDATE sites SET crawl_time = '{}', generation = {}, batch_id = {}, depth = {}, \
+             discovered_from = COALESCE({}, discovered_from), robots_snapshot = {}, robots_rule = {}, run_id = {} \
+             WHERE url = '{}'",
+            site.crawl_time.to_rfc3339(),
+            or_null(site.generation.map(|generation| generation.to_string())),
+            or_null(site.batch_id.map(|batch_id| batch_id.to_string())),
+            or_null(site.depth.map(|depth| depth.to_string())),
+            quoted(&site.discovered_from),
+            or_null(site.robots_snapshot.map(|snapshot| snapshot.to_string())),
+            quoted(&site.robots_rule),
+            or_null(site.run_id.map(|run_id| run_id.to_string())),
+            site.url.replace("'", "''")
+        ));
+    }
+
+    /// Deletes the stored text of sites, such as pages that asked not to be indexed since.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs of the sites.
+    pub fn delete_text(&self, urls: &[&str]) -> Result<()> {
+        if urls.is_empty() || !self.has_text_search()? {
+            return Ok(());
+        }
+        let quoted: Vec<String> = urls
+            .iter()
+            .map(|url| format!("'{}'", url.replace("'", "''")))
+            .collect();
+        self.execute(&format!(
+            "DELETE FROM page_text WHERE url IN ({})",
+            quoted.join(", ")
+        ))?;
+        return Ok(());
+    }
+
+    /// Searches the stored text of the sites with a full-text query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A string slice that holds the FTS5 query, such as `rust AND crawler`.
+    /// * `limit` - The maximum number of results.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<(String, String)>>` holding the URL of every matching site along with a
+    /// snippet of its text around the match, best matches first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the SQLite library lacks FTS5, or the query is
+    /// invalid.
+    pub fn search_text(&self, query: &str, limit: usize) -> Result<Vec<(String, String)>> {
+        if !self.has_text_search()? {
+            bail!("Full-text search is unavailable, as SQLite was built without FTS5");
+        }
+
+        let query = format!(
+            "SELECT url, snippet(page_text, 1, '[', ']', '...', 12) FROM page_text WHERE page_text MATCH '{}' ORDER BY rank LIMIT {}",
+            query.replace("'", "''"),
+            limit
+        );
+        let mut statement = self.prepare(&query)?;
+
+        let mut results = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the full-text query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let snippet = statement
+                .read::<String, usize>(1)
+                .context("Failed to read snippet from the database")?;
+            results.push((url, snippet));
+        }
+
+        return Ok(results);
+    }
+
+    /// Reads the sites crawled at or after the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The earliest crawl time of the sites to read.
+    /// * `include_suspect` - Whether the sites last stored by a crawl marked as suspect are read
+    ///   too.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` holding an iterator over the sites, oldest crawl first, which reads each site
+    /// from the database as it is advanced.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the query cannot be prepared. The iterator yields
+    /// an error for every site that cannot be read.
+    pub fn sites_crawled_since(
+        &self,
+        since: DateTime<Utc>,
+        include_suspect: bool,
+    ) -> Result<impl Iterator<Item = Result<Site>> + '_> {
+        return self.read_sites_since("crawl_time", since, include_suspect);
+    }
+
+    /// Reads the sites whose content changed at or after the given time, including sites first
+    /// seen since then.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The earliest change time of the sites to read.
+    /// * `include_suspect` - Whether the sites last stored by a crawl marked as suspect are read
+    ///   too.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` holding an iterator over the sites, oldest change first, which reads each site
+    /// from the database as it is advanced.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the query cannot be prepared. The iterator yields
+    /// an error for every site that cannot be read.
+    pub fn sites_changed_since(
+        &self,
+        since: DateTime<Utc>,
+        include_suspect: bool,
+    ) -> Result<impl Iterator<Item = Result<Site>> + '_> {
+        return self.read_sites_since("last_changed", since, include_suspect);
+    }
+
+    /// Lists the URLs of the sites crawled before the given time, which `delete_older_than`
+    /// deletes.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - The time before which the sites were crawled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the URLs, oldest crawl first.
+    pub fn sites_older_than(&self, cutoff: DateTime<Utc>) -> Result<Vec<String>> {
+        let query = format!(
+            "SELECT url FROM sites WHERE crawl_time < '{}' ORDER BY crawl_time, url",
+            cutoff.to_rfc3339()
+        );
+        let mut statement = self.prepare(&query)?;
+
+        let mut urls = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            urls.push(
+                statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read url from the database")?,
+            );
+        }
+        return Ok(urls);
+    }
+
+    /// Deletes the sites crawled before the given time, such as pages that disappeared from
+    /// their site since, along with their links and stored text, in a single transaction.
+    ///
+    /// Links to the deleted sites from the sites that are kept are left as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - The time before which the sites were crawled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<u64>` holding the number of deleted sites.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sites cannot be deleted, in which case none of
+    /// them are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chrono::{Duration, Utc};
+    /// use rustle::{Database, Site};
+    ///
+    /// let db = Database::in_memory()?;
+    /// db.setup()?;
+    /// let site = |url: &str, crawl_time| Site {
+    ///     url: url.to_string(),
+    ///     crawl_time,
+    ///     ..Default::default()
+    /// };
+    /// let now = Utc::now();
+    /// let sites = [
+    ///     site("https://example.com/gone", now - Duration::days(40)),
+    ///     site("https://example.com/", now),
+    /// ];
+    /// db.write_sites(&sites)?;
+    ///
+    /// let cutoff = now - Duration::days(30);
+    /// assert_eq!(db.sites_older_than(cutoff)?, ["https://example.com/gone"]);
+    /// assert_eq!(db.delete_sites_older_than(cutoff)?, 1);
+    /// assert!(db.read_site("https://example.com/gone")?.is_none());
+    /// assert!(db.read_site("https://example.com/")?.is_some());
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    pub fn delete_sites_older_than(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        // RFC 3339 timestamps in UTC sort in chronological order as strings
+        let condition = format!("crawl_time < '{}'", cutoff.to_rfc3339());
+        let count = self.count_where("sites", &condition)?;
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let stale = format!("SELECT url FROM sites WHERE {}", condition);
+        let mut statements = vec![format!("DELETE FROM links WHERE source_url IN ({})", stale)];
+        if self.has_text_search()? {
+            statements.push(format!("DELETE FROM page_text WHERE url IN ({})", stale));
+        }
+        statements.push(format!("DELETE FROM sites WHERE {}", condition));
+        self.execute_transaction(&statements)?;
+
+        info!("Deleted {} sites crawled before {}", count, cutoff);
+        return Ok(count);
+    }
+
+    /// Reads the sites whose timestamp in the given column is at or after the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - A string slice that holds the name of the timestamp column.
+    /// * `since` - The earliest time of the sites to read.
+    /// * `include_suspect` - Whether the sites last stored by a crawl marked as suspect are read
+    ///   too.
+    fn read_sites_since<'a>(
+        &'a self,
+        column: &str,
+        since: DateTime<Utc>,
+        include_suspect: bool,
+    ) -> Result<impl Iterator<Item = Result<Site>> + 'a> {
+        // RFC 3339 timestamps in UTC sort in chronological order as strings
+        let query = format!(
+            "SELECT url FROM sites WHERE {} >= '{}'{} ORDER BY {}, url",
+            column,
+            since.to_rfc3339(),
+            match include_suspect {
+                true => String::new(),
+                false => format!(
+                    " AND (run_id IS NULL OR run_id NOT IN (SELECT id FROM crawl_runs WHERE status = '{}'))",
+                    SessionStatus::Suspect
+                ),
+            },
+            column
+        );
+        return self.read_sites_matching(&query);
+    }
+
+    /// Reads the sites fetched in a generation of the last crawl that reached it.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation` - The generation of the crawl, counting the origin as generation 0.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` holding an iterator over the sites of the generation, in the order of their
+    /// batches.
+    pub fn sites_in_generation(
+        &self,
+        generation: u64,
+    ) -> Result<impl Iterator<Item = Result<Site>> + '_> {
+        // Sites last stored by an earlier crawl keep the generation they had in it, so they are
+        // left out along with the rest of that crawl
+        let query = format!(
+            "SELECT url FROM sites WHERE generation = {} AND run_id IS ({}) ORDER BY batch_id, url",
+            generation,
+            CrawlRun::last_to_reach_query(generation)
+        );
+        return self.read_sites_matching(&query);
+    }
+
+    /// Reads the redirects that lead to a URL, directly or through other redirects.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL the redirects lead to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` holding an iterator over the redirect sites whose chain ends at `url`, closest
+    /// first.
+    pub fn redirects_to<'a>(
+        &'a self,
+        url: &str,
+    ) -> Result<impl Iterator<Item = Result<Site>> + 'a> {
+        // Walk the links of redirects back from the URL, one hop at a time. A walk is no longer
+        // than the number of redirects, so that redirect loops end
+        let query = format!(
+            "WITH RECURSIVE chain (url, hops) AS (\
+             SELECT source_url, 1 FROM links JOIN sites ON sites.url = links.source_url \
+             WHERE sites.is_redirect = 1 AND links.target_url = '{}' \
+             UNION SELECT links.source_url, chain.hops + 1 FROM links \
+             JOIN chain ON links.target_url = chain.url JOIN sites ON sites.url = links.source_url \
+             WHERE sites.is_redirect = 1 AND chain.hops < (SELECT COUNT(*) FROM sites WHERE is_redirect = 1)) \
+             SELECT url FROM chain GROUP BY url ORDER BY MIN(hops), url",
+            url.replace("'", "''")
+        );
+        return self.read_sites_matching(&query);
+    }
+
+    /// Reads the sites whose URLs are returned by a query, one at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A string slice that holds the query, which selects the `url` column only.
+    fn read_sites_matching<'a>(
+        &'a self,
+        query: &str,
+    ) -> Result<impl Iterator<Item = Result<Site>> + 'a> {
+        let mut statement = self.prepare(query)?;
+
+        return Ok(std::iter::from_fn(move || {
+            match statement.next().context("Failed to execute the SQL query") {
+                Ok(sqlite::State::Row) => {}
+                Ok(sqlite::State::Done) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+            let site = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")
+                .and_then(|url| {
+                    self.read_site(&url)?
+                        .with_context(|| format!("Site {} disappeared from the database", url))
+                });
+            return Some(site);
+        }));
+    }
+
+    /// Summarizes the database by counting the number of entries in the `sites` table.
+    ///
+    /// This function prepares and executes a SQL query to count the number of entries
+    /// in the `sites` table, broken down by the class of their status code (`2xx`, `3xx`, ...).
+    /// Sites stored before status codes were recorded are counted as `unknown`.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Only count the sites written at or after this time, such as the start of a
+    ///   crawl, or `None` to count them all.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<BTreeMap<Option<i64>, u64>>` holding the number of sites of each status class,
+    /// with `None` for the unknown ones, or an `Err` if an error occurs.
+    pub fn summarize_site_table(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<BTreeMap<Option<i64>, u64>> {
+        let query = format!(
+            "SELECT status_code / 100, COUNT(*) FROM sites{} GROUP BY status_code / 100 ORDER BY status_code / 100",
+            written_since(since)
+        );
+        let mut statement = self.prepare(&query)?;
+
+        let mut counts = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let class = statement
+                .read::<Option<i64>, usize>(0)
+                .context("Failed to read the status class from the database")?;
+            let count = statement
+                .read::<i64, usize>(1)
+                .context("Failed to read the count from the database")?;
+
+            counts.insert(class, count as u64);
+        }
+        return Ok(counts);
+    }
+
+    /// Counts the sites stored in the database by the depth they were discovered at. Sites
+    /// whose depth wasn't recorded aren't counted.
+    ///
+    /// A site discovered again at a smaller depth keeps the smaller depth, along with the page
+    /// it was discovered on there:
+    ///
+    /// ```
+    /// use rustle::{Database, Site, Storage};
+    ///
+    /// let db = Database::in_memory()?;
+    /// db.setup()?;
+    /// let site = |depth, discovered_from: &str| Site {
+    ///     url: "https://example.com/deep".to_string(),
+    ///     depth: Some(depth),
+    ///     discovered_from: Some(discovered_from.to_string()),
+    ///     ..Default::default()
+    /// };
+    /// db.put_site(&site(3, "https://example.com/c"))?;
+    /// db.put_site(&site(1, "https://example.com/a"))?;
+    /// db.put_site(&site(2, "https://example.com/b"))?;
+    ///
+    /// let stored = db.get_site("https://example.com/deep")?.unwrap();
+    /// assert_eq!(stored.depth, Some(1));
+    /// assert_eq!(stored.discovered_from.as_deref(), Some("https://example.com/a"));
+    /// assert_eq!(db.count_by_depth()?, [(1, 1)].into());
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// A `Result<BTreeMap<u64, u64>>` holding the number of sites at each depth, or an `Err` if
+    /// an error occurs.
+    pub fn count_by_depth(&self) -> Result<BTreeMap<u64, u64>> {
+        let mut statement = self
+            .prepare("SELECT depth, COUNT(*) FROM sites WHERE depth IS NOT NULL GROUP BY depth")?;
+        let mut counts = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let depth = statement
+                .read::<i64, usize>(0)
+                .context("Failed to read depth from the database")?;
+            let count = statement
+                .read::<i64, usize>(1)
+                .context("Failed to read the count from the database")?;
+            counts.insert(depth as u64, count as u64);
+        }
+        return Ok(counts);
+    }
+
+    /// Lists the URLs of every site stored in the database, in sorted order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the URLs, or an `Err` if an error occurs.
+    pub fn list_urls(&self) -> Result<Vec<String>> {
+        let mut statement = self.prepare("SELECT url FROM sites ORDER BY url")?;
+        let mut urls = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            urls.push(
+                statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read url from the database")?,
+            );
+        }
+
+        return Ok(urls);
+    }
+
+    /// Lists the URL, content hash and title of every site stored in the database, in sorted
+    /// order of URL.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<Fingerprint>>` containing the URL, content hash and title of every site,
+    /// or an `Err` if an error occurs.
+    pub fn list_fingerprints(&self) -> Result<Vec<Fingerprint>> {
+        let mut statement =
+            self.prepare("SELECT url, content_hash, title FROM sites ORDER BY url")?;
+        let mut fingerprints = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let content_hash = statement
+                .read::<Option<String>, usize>(1)
+                .context("Failed to read content_hash from the database")?;
+            let title = statement
+                .read::<Option<String>, usize>(2)
+                .context("Failed to read title from the database")?;
+            fingerprints.push((url, content_hash, title));
+        }
+
+        return Ok(fingerprints);
+    }
+
+    /// Summarizes the sites stored in the database by host, with the number of pages of each
+    /// host and the times its oldest and newest pages were crawled.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Only summarize the sites written at or after this time, or `None` to
+    ///   summarize them all.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<DomainSummary>>` holding the summary of every host, the host with the most
+    /// pages first, or an `Err` if an error occurs.
+    pub fn sites_by_domain(&self, since: Option<DateTime<Utc>>) -> Result<Vec<DomainSummary>> {
+        let mut statement = self.prepare(&format!(
+            "SELECT url, crawl_time FROM sites{}",
+            written_since(since)
+        ))?;
+        let mut sites = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let crawl_time = statement
+                .read::<String, usize>(1)
+                .context("Failed to read crawl_time from the database")?;
+            let crawl_time = DateTime::parse_from_rfc3339(&crawl_time)
+                .context("Failed to parse crawl_time as RFC 3339")?
+                .with_timezone(&Utc);
+            sites.push((url, crawl_time));
+        }
+
+        return Ok(DomainSummary::from_sites(
+            sites
+                .iter()
+                .map(|(url, crawl_time)| (url.as_str(), *crawl_time)),
+        ));
+    }
+
+    /// Counts the stored external links by the host they point at, so that the sites a crawl
+    /// references are known even though they aren't crawled.
+    ///
+    /// ```
+    /// use rustle::{Database, Site, Storage};
+    ///
+    /// let db = Database::in_memory()?;
+    /// db.setup()?;
+    /// for page in ["https://example.com/", "https://example.com/about"] {
+    ///     db.put_site(&Site {
+    ///         url: page.to_string(),
+    ///         links_to: ["https://github.com/rustle", "https://docs.rs/url"]
+    ///             .map(str::to_string)
+    ///             .into(),
+    ///         external_links: ["https://github.com/rustle".to_string()].into(),
+    ///         ..Default::default()
+    ///     })?;
+    /// }
+    ///
+    /// let external = db.summarize_external_domains()?;
+    /// assert_eq!(external, [("github.com".to_string(), 2)]);
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<(String, u64)>>` holding every external host along with the number of links
+    /// to it, the most referenced host first, or an `Err` if an error occurs.
+    pub fn summarize_external_domains(&self) -> Result<Vec<(String, u64)>> {
+        let mut statement = self.prepare("SELECT target_url FROM links WHERE kind = 'external'")?;
+        let mut by_host: BTreeMap<String, u64> = BTreeMap::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let target_url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read target_url from the database")?;
+            let Some(host) = Url::parse(&target_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+            else {
+                continue;
+            };
+            *by_host.entry(host).or_insert(0) += 1;
+        }
+
+        let mut summary: Vec<(String, u64)> = by_host.into_iter().collect();
+        summary.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        return Ok(summary);
+    }
+
+    /// Lists the links of every site stored in the database, in sorted order of the sites.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<(String, Vec<String>)>>` containing the URL of every site along with the
+    /// URLs it links to, or an `Err` if an error occurs.
+    pub fn list_links(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let mut statement = self.prepare(
+            "SELECT sites.url, links.target_url FROM sites LEFT JOIN links ON links.source_url = sites.url ORDER BY sites.url, links.target_url",
+        )?;
+        let mut sites: Vec<(String, Vec<String>)> = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let target = statement
+                .read::<Option<String>, usize>(1)
+                .context("Failed to read target_url from the database")?;
+            if sites.last().map(|(last, _)| last) != Some(&url) {
+                sites.push((url, Vec::new()));
+            }
+            if let (Some(target), Some((_, links))) = (target, sites.last_mut()) {
+                links.push(target);
+            }
+        }
+
+        return Ok(sites);
+    }
+
+    /// Lists the URLs of the sites whose response declared one of the given media types.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_types` - A slice of the media types to list the sites of.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the URLs in sorted order, or an `Err` if an error
+    /// occurs.
+    pub fn list_by_content_type(&self, content_types: &[String]) -> Result<Vec<String>> {
+        let content_types = content_types
+            .iter()
+            .map(|content_type| format!("'{}'", content_type.to_lowercase().replace("'", "''")))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let query = format!(
+            "SELECT url FROM sites WHERE content_type IN ({}) ORDER BY url",
+            content_types
+        );
+        let mut statement = self.prepare(&query)?;
+        let mut urls = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            urls.push(
+                statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read url from the database")?,
+            );
+        }
+
+        return Ok(urls);
+    }
+
+    /// Lists the sites that depend the most on JavaScript to render their content, which are the
+    /// pages an HTML-only crawl is most likely blind to.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of sites to list.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<(String, f64)>>` containing the URLs and scores of the sites, highest score
+    /// first, or an `Err` if an error occurs.
+    pub fn list_js_dependent(&self, limit: usize) -> Result<Vec<(String, f64)>> {
+        let query = format!(
+            "SELECT url, js_dependency_score FROM sites WHERE js_dependency_score > 0 ORDER BY js_dependency_score DESC, url LIMIT {}",
+            limit
+        );
+        let mut statement = self.prepare(&query)?;
+        let mut sites = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read url from the database")?;
+            let score = statement
+                .read::<f64, usize>(1)
+                .context("Failed to read js_dependency_score from the database")?;
+            sites.push((url, score));
+        }
+
+        return Ok(sites);
+    }
+
+    /// Lists the URLs of every stored site that links to the given URL, in sorted order. With
+    /// `variants`, links to the URL with the other of the `http` and `https` schemes or with or
+    /// without a trailing slash count as well.
+    ///
+    /// The stored links are matched in full, so a URL only matches itself and its variants, and
+    /// never a longer URL it is a prefix of:
+    ///
+    /// ```
+    /// use rustle::{Database, Site, Storage};
+    ///
+    /// let db = Database::in_memory().unwrap();
+    /// db.setup().unwrap();
+    /// for (url, link) in [
+    ///     ("https://example.com/a", "https://example.com/old"),
+    ///     ("https://example.com/b", "http://example.com/old/"),
+    ///     ("https://example.com/c", "https://example.com/old-page"),
+    ///     ("https://example.com/d", "https://example.com/old/new"),
+    ///     ("https://example.com/e", "https://example.com/bold"),
+    /// ] {
+    ///     db.put_site(&Site {
+    ///         url: url.to_string(),
+    ///         links_to: [link.to_string()].into(),
+    ///         ..Default::default()
+    ///     })
+    ///     .unwrap();
+    /// }
+    ///
+    /// let referrers = db.inbound_links("https://example.com/old", true).unwrap();
+    /// assert_eq!(referrers, ["https://example.com/a", "https://example.com/b"]);
+    /// let referrers = db.inbound_links("https://example.com/old", false).unwrap();
+    /// assert_eq!(referrers, ["https://example.com/a"]);
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL that is linked to.
+    /// * `variants` - Whether links to the variants of the URL count as links to it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>>` containing the distinct URLs of the referring sites, or an `Err`
+    /// if an error occurs.
+    pub fn inbound_links(&self, url: &str, variants: bool) -> Result<Vec<String>> {
+        let targets = if variants {
+            url_variants(url)
+        } else {
+            BTreeSet::from([url.to_string()])
+        };
+        let targets = targets
+            .iter()
+            .map(|target| format!("'{}'", target.replace("'", "''")))
+            .collect::<Vec<String>>();
+        let query = format!(
+            "SELECT DISTINCT source_url FROM links WHERE target_url IN ({}) ORDER BY source_url",
+            targets.join(", ")
+        );
+        let mut statement = self.prepare(&query)?;
+        let mut referrers = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            referrers.push(
+                statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read source_url from the database")?,
+            );
+        }
+
+        return Ok(referrers);
+    }
+
+    /// Lists the stored links whose target failed to load, either with an error status code or
+    /// with a fetch error, sorted by target and then by source.
+    ///
+    /// ```
+    /// use rustle::{Database, Site, Storage};
+    ///
+    /// let db = Database::in_memory()?;
+    /// db.setup()?;
+    /// db.put_site(&Site {
+    ///     url: "https://example.com/".to_string(),
+    ///     links_to: ["https://example.com/gone", "https://example.com/logo.png"]
+    ///         .map(str::to_string)
+    ///         .into(),
+    ///     assets: ["https://example.com/logo.png".to_string()].into(),
+    ///     ..Default::default()
+    /// })?;
+    /// for url in ["https://example.com/gone", "https://example.com/logo.png"] {
+    ///     db.put_site(&Site {
+    ///         url: url.to_string(),
+    ///         status_code: Some(404),
+    ///         ..Default::default()
+    ///     })?;
+    /// }
+    ///
+    /// let broken = db.broken_links()?;
+    /// assert_eq!(broken.len(), 2);
+    /// assert_eq!(broken[1].target_url, "https://example.com/logo.png");
+    /// assert_eq!((broken[1].kind.as_str(), broken[1].status_code), ("asset", Some(404)));
+    /// assert_eq!(broken[0].kind, "page");
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<BrokenLink>>` containing the broken links, or an `Err` if an error occurs.
+    pub fn broken_links(&self) -> Result<Vec<BrokenLink>> {
+        let mut statement = self.prepare(
+            "SELECT links.source_url, links.target_url, links.kind, sites.status_code, fetch_errors.error_kind \
+             FROM links LEFT JOIN sites ON sites.url = links.target_url \
+             LEFT JOIN fetch_errors ON fetch_errors.url = links.target_url \
+             WHERE sites.status_code >= 400 OR fetch_errors.url IS NOT NULL \
+             ORDER BY links.target_url, links.source_url",
+        )?;
+
+        let mut broken = Vec::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            broken.push(BrokenLink {
+                source_url: statement
+                    .read::<String, usize>(0)
+                    .context("Failed to read source_url from the database")?,
+                target_url: statement
+                    .read::<String, usize>(1)
+                    .context("Failed to read target_url from the database")?,
+                kind: statement
+                    .read::<String, usize>(2)
+                    .context("Failed to read kind from the database")?,
+                status_code: statement
+                    .read::<Option<i64>, usize>(3)
+                    .context("Failed to read status_code from the database")?
+                    .map(|status_code| status_code as u16),
+                error_kind: statement
+                    .read::<Option<String>, usize>(4)
+                    .context("Failed to read error_kind from the database")?,
+            });
+        }
+
+        return Ok(broken);
+    }
+
+    /// Reads the URLs a site links to from the links table, along with their anchor text, the
+    /// URLs of the assets among them, those of the links that aren't followed and those of the
+    /// external links.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the site.
+    fn read_links(&self, url: &str) -> Result<StoredLinks> {
+        let query = format!(
+            "SELECT target_url, anchor_text, kind FROM links WHERE source_url = '{}'",
+            url.replace("'", "''")
+        );
+        let mut statement = self.prepare(&query)?;
+        let mut links = HashSet::new();
+        let mut anchor_texts = HashMap::new();
+        let mut assets = HashSet::new();
+        let mut nofollow_links = HashSet::new();
+        let mut external_links = HashSet::new();
+        while let sqlite::State::Row = statement
+            .next()
+            .context("Failed to execute the SQL query")?
+        {
+            let target_url = statement
+                .read::<String, usize>(0)
+                .context("Failed to read target_url from the database")?;
+            let anchor_text = statement
+                .read::<Option<String>, usize>(1)
+                .context("Failed to read anchor_text from the database")?;
+            let kind = statement
+                .read::<String, usize>(2)
+                .context("Failed to read kind from the database")?;
+            if let Some(anchor_text) = anchor_text {
+                anchor_texts.insert(target_url.clone(), anchor_text);
+            }
+            if kind == "asset" {
+                assets.insert(target_url.clone());
+            } else if kind == "nofollow" {
+                nofollow_links.insert(target_url.clone());
+            } else if kind == "external" {
+                external_links.insert(target_url.clone());
+            }
+            links.insert(target_url);
+        }
+
+        return Ok((links, anchor_texts, assets, nofollow_links, external_links));
+    }
+}
+
+/// Builds the SQL statements that write the `Site` instance into the database.
+///
+/// This function formats the `crawl_time` field into an RFC 3339 string, inserts or
+/// replaces the site record with the current `Site` instance's data, replaces the rows of the
+/// `links` table with the `links_to` field, their `anchor_texts` and their kind, and replaces the stored text of the site if it
+/// has any. Without text, the stored text is only kept if the content hash of the site is
+/// unchanged. The stored `depth` and `discovered_from` of the site are kept unless the site
+/// was discovered at a smaller depth this time.
+///
+/// # Arguments
+///
+/// * `site` - A reference to the `Site` to write.
+/// * `text_search` - A boolean indicating whether the database has a `page_text` table.
+fn write_statements(site: &Site, text_search: bool) -> Vec<String> {
+    // Convert crawl_time to RFC 3339 string
+    let crawl_time_str = site.crawl_time.to_rfc3339();
+
+    // Convert the optional status code, final URL, content type, body size, sniffed type,
+    // validators, title, description, content hash, change time, fetch details and compliance
+    // record to SQL values
+    let status_code_str = match site.status_code {
+        Some(status_code) => status_code.to_string(),
+        None => "NULL".to_string(),
+    };
+    let final_url_str = match &site.final_url {
+        Some(final_url) => format!("'{}'", final_url.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let content_type_str = match &site.content_type {
+        Some(content_type) => format!("'{}'", content_type.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let body_bytes_str = match site.body_bytes {
+        Some(body_bytes) => body_bytes.to_string(),
+        None => "NULL".to_string(),
+    };
+    let sniffed_type_str = match &site.sniffed_type {
+        Some(sniffed_type) => format!("'{}'", sniffed_type.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let etag_str = match &site.etag {
+        Some(etag) => format!("'{}'", etag.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let last_modified_str = match &site.last_modified {
+        Some(last_modified) => format!("'{}'", last_modified.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let title_str = match &site.title {
+        Some(title) => format!("'{}'", title.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let description_str = match &site.description {
+        Some(description) => format!("'{}'", description.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+    let content_hash_str = match &site.content_hash {
+        Some(content_hash) => format!("'{}'", content_hash),
+        None => "NULL".to_string(),
+    };
+    let last_changed_str = match &site.last_changed {
+        Some(last_changed) => format!("'{}'", last_changed.to_rfc3339()),
+        None => "NULL".to_string(),
+    };
+
+    let generation_str = match site.generation {
+        Some(generation) => generation.to_string(),
+        None => "NULL".to_string(),
+    };
+    let batch_id_str = match site.batch_id {
+        Some(batch_id) => batch_id.to_string(),
+        None => "NULL".to_string(),
+    };
+    let response_ms_str = match site.response_ms {
+        Some(response_ms) => response_ms.to_string(),
+        None => "NULL".to_string(),
+    };
+
+    let robots_snapshot_str = match site.robots_snapshot {
+        Some(robots_snapshot) => robots_snapshot.to_string(),
+        None => "NULL".to_string(),
+    };
+    let robots_rule_str = match &site.robots_rule {
+        Some(robots_rule) => format!("'{}'", robots_rule.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+
+    let redirect_hops_str = match site.redirect_hops {
+        Some(redirect_hops) => redirect_hops.to_string(),
+        None => "NULL".to_string(),
+    };
+    let run_id_str = match site.run_id {
+        Some(run_id) => run_id.to_string(),
+        None => "NULL".to_string(),
+    };
+    let canonical_url_str = match &site.canonical_url {
+        Some(canonical_url) => format!("'{}'", canonical_url.replace("'", "''")),
+        None => "NULL".to_string(),
+    };
+
+    // Keep the depth and referrer the site was first discovered at, unless it is shallower now
+    let url_str = site.url.replace("'", "''");
+    let stored_depth = format!("(SELECT depth FROM sites WHERE url = '{}')", url_str);
+    let stored_discovered_from = format!(
+        "(SELECT discovered_from FROM sites WHERE url = '{}')",
+        url_str
+    );
+    let (depth_str, discovered_from_str) = match site.depth {
+        Some(depth) => {
+            let discovered_from = match &site.discovered_from {
+                Some(discovered_from) => format!("'{}'", discovered_from.replace("'", "''")),
+                None => "NULL".to_string(),
+            };
+            (
+                format!("MIN({}, COALESCE({}, {}))", depth, stored_depth, depth),
+                format!(
+                    "CASE WHEN {} <= {} THEN {} ELSE {} END",
+                    stored_depth, depth, stored_discovered_from, discovered_from
+                ),
+            )
+        }
+        None => (stored_depth, stored_discovered_from),
+    };
+
+    // Declare SQLite query
+    let query =
+        format!(
+        "INSERT OR REPLACE INTO sites (url, crawl_time, header_anomaly, cookies_set, bot_challenged, js_dependency_score, status_code, final_url, content_type, body_bytes, title, description, content_hash, last_changed, sniffed_type, etag, last_modified, generation, batch_id, response_ms, robots_snapshot, robots_rule, is_redirect, redirect_hops, run_id, canonical_url, depth, discovered_from) VALUES ('{}', '{}', {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+        url_str, crawl_time_str,
+        site.header_anomaly as i64, site.cookies_set, site.bot_challenged as i64,
+        site.js_dependency_score, status_code_str, final_url_str, content_type_str,
+        body_bytes_str, title_str, description_str, content_hash_str, last_changed_str,
+        sniffed_type_str, etag_str, last_modified_str, generation_str, batch_id_str,
+        response_ms_str, robots_snapshot_str, robots_rule_str, site.is_redirect as i64,
+        redirect_hops_str, run_id_str, canonical_url_str, depth_str, discovered_from_str
+    );
+
+    // Forget the stored text of the site unless its content is unchanged, which is checked
+    // against the row about to be replaced
+    let mut statements = Vec::new();
+    if text_search && site.text.is_none() {
+        statements.push(format!(
+            "DELETE FROM page_text WHERE url = '{}' AND NOT EXISTS (SELECT 1 FROM sites WHERE url = '{}' AND content_hash = {})",
+            url_str, url_str, content_hash_str
+        ));
+    }
+
+    // Replace the links of the site along with its row
+    statements.push(query);
+    statements.push(format!(
+        "DELETE FROM links WHERE source_url = '{}'",
+        url_str
+    ));
+    if !site.links_to.is_empty() {
+        let links = site
+            .links_to
+            .iter()
+            .map(|link| {
+                let anchor_text_str = match site.anchor_texts.get(link) {
+                    Some(anchor_text) => format!("'{}'", anchor_text.replace("'", "''")),
+                    None => "NULL".to_string(),
+                };
+                let kind = if site.assets.contains(link) {
+                    "asset"
+                } else if site.external_links.contains(link) {
+                    "external"
+                } else if site.nofollow_links.contains(link) {
+                    "nofollow"
+                } else {
+                    "page"
+                };
+                format!(
+                    "('{}', '{}', {}, '{}')",
+                    url_str,
+                    link.replace("'", "''"),
+                    anchor_text_str,
+                    kind
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        statements.push(format!(
+            "INSERT INTO links (source_url, target_url, anchor_text, kind) VALUES {}",
+            links
+        ));
+    }
+
+    // Replace the stored text of the site, if it has any
+    if let Some(text) = &site.text {
+        statements.push(format!("DELETE FROM page_text WHERE url = '{}'", url_str));
+        statements.push(format!(
+            "INSERT INTO page_text (url, content) VALUES ('{}', '{}')",
+            url_str,
+            text.replace("'", "''")
+        ));
+    }
+
+    return statements;
+}
+
+/// Gets the variants of a URL that are taken for the same page when searching links: the URL
+/// with either of the `http` and `https` schemes, each with and without a trailing slash.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL.
+fn url_variants(url: &str) -> BTreeSet<String> {
+    // Parse the URL so that its host and path are written the same way as stored links
+    let url = Url::parse(url)
+        .map(String::from)
+        .unwrap_or_else(|_| url.to_string());
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    let Some(rest) = rest else {
+        return BTreeSet::from([url]);
+    };
+
+    let rest = rest.strip_suffix('/').unwrap_or(rest);
+    let mut variants = BTreeSet::new();
+    for scheme in ["http", "https"] {
+        variants.insert(format!("{}://{}", scheme, rest));
+        variants.insert(format!("{}://{}/", scheme, rest));
+    }
+    return variants;
+}
+
+/// Builds the `WHERE` clause that keeps the rows of a table written at or after a time, going
+/// by their `crawl_time` column, or an empty clause to keep them all.
+///
+/// # Arguments
+///
+/// * `since` - The time the rows must have been written at or after, if any.
+pub(crate) fn written_since(since: Option<DateTime<Utc>>) -> String {
+    return since.map_or(String::new(), |since| {
+        return format!(
+            " WHERE julianday(crawl_time) >= julianday('{}')",
+            since.to_rfc3339()
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Searches the stored text for a word, returning the URLs that contain it.
+    fn search(database: &Database, word: &str) -> Vec<String> {
+        return database
+            .search_text(word, 10)
+            .unwrap()
+            .into_iter()
+            .map(|(url, _)| url)
+            .collect();
+    }
+
+    #[test]
+    fn sites_round_trip_through_an_in_memory_database() {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let crawl_time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let site = Site {
+            url: "https://example.com/it's".to_string(),
+            crawl_time,
+            links_to: HashSet::from([
+                "https://example.com/about".to_string(),
+                "https://example.com/logo.png".to_string(),
+                "https://example.com/ad".to_string(),
+                "https://example.org/".to_string(),
+            ]),
+            anchor_texts: HashMap::from([(
+                "https://example.com/about".to_string(),
+                "About us".to_string(),
+            )]),
+            assets: HashSet::from(["https://example.com/logo.png".to_string()]),
+            nofollow_links: HashSet::from(["https://example.com/ad".to_string()]),
+            external_links: HashSet::from(["https://example.org/".to_string()]),
+            cookies_set: 2,
+            js_dependency_score: 0.5,
+            status_code: Some(200),
+            content_type: Some("text/html".to_string()),
+            body_bytes: Some(1024),
+            etag: Some("\"v1\"".to_string()),
+            title: Some("It's a title".to_string()),
+            content_hash: Some("abc".to_string()),
+            last_changed: Some(crawl_time),
+            depth: Some(1),
+            discovered_from: Some("https://example.com/".to_string()),
+            response_ms: Some(42),
+            ..Default::default()
+        };
+        database.write_sites(std::slice::from_ref(&site)).unwrap();
+
+        let stored = database.read_site(&site.url).unwrap().unwrap();
+        assert_eq!(stored.url, site.url);
+        assert_eq!(stored.crawl_time, crawl_time);
+        assert_eq!(stored.links_to, site.links_to);
+        assert_eq!(stored.anchor_texts, site.anchor_texts);
+        assert_eq!(stored.assets, site.assets);
+        assert_eq!(stored.nofollow_links, site.nofollow_links);
+        assert_eq!(stored.external_links, site.external_links);
+        assert_eq!(stored.cookies_set, 2);
+        assert_eq!(stored.js_dependency_score, 0.5);
+        assert_eq!(stored.status_code, Some(200));
+        assert_eq!(stored.content_type.as_deref(), Some("text/html"));
+        assert_eq!(stored.body_bytes, Some(1024));
+        assert_eq!(stored.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(stored.title.as_deref(), Some("It's a title"));
+        assert_eq!(stored.content_hash.as_deref(), Some("abc"));
+        assert_eq!(stored.last_changed, Some(crawl_time));
+        assert_eq!(stored.depth, Some(1));
+        assert_eq!(
+            stored.discovered_from.as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(stored.response_ms, Some(42));
+        assert!(!stored.is_redirect);
+        assert!(database
+            .read_site("https://example.com/missing")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn stored_text_is_only_kept_while_the_content_is_unchanged() {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let site = |hash: &str, text: Option<&str>| Site {
+            url: "https://example.com/".to_string(),
+            crawl_time: Utc::now(),
+            content_hash: Some(hash.to_string()),
+            text: text.map(str::to_string),
+            ..Default::default()
+        };
+        database.write_sites(&[site("a", Some("crawler"))]).unwrap();
+        assert_eq!(search(&database, "crawler"), ["https://example.com/"]);
+
+        // A revalidated page keeps its hash and its text, a changed one loses the old text
+        database.write_sites(&[site("a", None)]).unwrap();
+        assert_eq!(search(&database, "crawler"), ["https://example.com/"]);
+        database.write_sites(&[site("b", None)]).unwrap();
+        assert!(search(&database, "crawler").is_empty());
+
+        database.write_sites(&[site("c", Some("spider"))]).unwrap();
+        database.delete_text(&["https://example.com/"]).unwrap();
+        assert!(search(&database, "spider").is_empty());
+    }
+
+    #[test]
+    fn redirects_are_found_through_the_whole_chain() {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let redirect = |url: &str, next: &str| Site {
+            url: url.to_string(),
+            crawl_time: Utc::now(),
+            links_to: HashSet::from([next.to_string()]),
+            is_redirect: true,
+            ..Default::default()
+        };
+        // /old -> /older-mid -> /mid -> /new, plus a loop between /a and /b that leads nowhere
+        database
+            .write_sites(&[
+                redirect("https://example.com/old", "https://example.com/older-mid"),
+                redirect("https://example.com/older-mid", "https://example.com/mid"),
+                redirect("https://example.com/mid", "https://example.com/new"),
+                redirect("https://example.com/a", "https://example.com/b"),
+                redirect("https://example.com/b", "https://example.com/a"),
+            ])
+            .unwrap();
+        let redirects_to = |url: &str| {
+            return database
+                .redirects_to(url)
+                .unwrap()
+                .map(|site| site.unwrap().url)
+                .collect::<Vec<_>>();
+        };
+
+        assert_eq!(
+            redirects_to("https://example.com/new"),
+            [
+                "https://example.com/mid",
+                "https://example.com/older-mid",
+                "https://example.com/old"
+            ]
+        );
+        assert_eq!(
+            redirects_to("https://example.com/mid"),
+            ["https://example.com/older-mid", "https://example.com/old"]
+        );
+        assert_eq!(
+            redirects_to("https://example.com/a"),
+            ["https://example.com/b", "https://example.com/a"]
+        );
+        assert!(redirects_to("https://example.com/old").is_empty());
+    }
+
+    #[test]
+    fn sites_since_a_cutoff_include_the_cutoff_itself() {
+        let database = Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let cutoff = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let crawled_since = |since| {
+            return database
+                .sites_crawled_since(since, true)
+                .unwrap()
+                .map(|site| site.unwrap().url)
+                .collect::<Vec<_>>();
+        };
+        let changed_since = |since| {
+            return database
+                .sites_changed_since(since, true)
+                .unwrap()
+                .map(|site| site.unwrap().url)
+                .collect::<Vec<_>>();
+        };
+        assert!(crawled_since(cutoff).is_empty());
+        assert!(changed_since(cutoff).is_empty());
+
+        let site = |path: &str, crawl_time: DateTime<Utc>| Site {
+            url: format!("https://example.com/{}", path),
+            crawl_time,
+            last_changed: Some(crawl_time),
+            ..Default::default()
+        };
+        database
+            .write_sites(&[
+                site("after", cutoff + chrono::Duration::seconds(1)),
+                site("at", cutoff),
+                site("before", cutoff - chrono::Duration::seconds(1)),
+                site("just-after", cutoff + chrono::Duration::milliseconds(1)),
+                site("just-before", cutoff - chrono::Duration::milliseconds(1)),
+            ])
+            .unwrap();
+
+        let expected = [
+            "https://example.com/at",
+            "https://example.com/just-after",
+            "https://example.com/after",
+        ];
+        assert_eq!(crawled_since(cutoff), expected);
+        assert_eq!(changed_since(cutoff), expected);
+        assert!(crawled_since(cutoff + chrono::Duration::seconds(2)).is_empty());
+        assert_eq!(
+            crawled_since(cutoff - chrono::Duration::seconds(2)).len(),
+            5
+        );
+    }
+}
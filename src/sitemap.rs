@@ -0,0 +1,99 @@
+/// The kinds of sitemap documents.
+#[derive(PartialEq, Eq, Debug)]
+pub enum SitemapKind {
+    /// A `<sitemapindex>` whose `<loc>` entries point at further sitemaps.
+    Index,
+    /// A `<urlset>` whose `<loc>` entries point at pages.
+    UrlSet,
+}
+
+/// Represents a parsed sitemap document.
+pub struct Sitemap {
+    /// Whether the `locations` are sitemaps or pages.
+    pub kind: SitemapKind,
+    /// The contents of every `<loc>` element, in document order.
+    pub locations: Vec<String>,
+}
+
+/// Extracts the URLs of every `Sitemap:` directive in a `robots.txt` file.
+///
+/// Sitemap directives are independent of user-agent sections, so every line is considered.
+///
+/// # Arguments
+///
+/// * `robots_txt` - A string slice that holds the contents of the `robots.txt` file.
+///
+/// # Returns
+///
+/// A `Vec<String>` containing the sitemap URLs, in the order they appear.
+pub fn sitemap_directives(robots_txt: &str) -> Vec<String> {
+    return robots_txt
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case("sitemap") && !value.trim().is_empty() {
+                return Some(value.trim().to_string());
+            }
+            return None;
+        })
+        .collect();
+}
+
+/// Parses a sitemap or sitemap index document.
+///
+/// This is a minimal parser that only looks for the root element and the `<loc>` elements, which
+/// is all the crawler needs. Malformed documents yield whatever locations could be found.
+///
+/// # Arguments
+///
+/// * `xml` - A string slice that holds the XML content of the sitemap.
+///
+/// # Returns
+///
+/// A `Sitemap` holding the kind of the document and its locations.
+pub fn parse_sitemap(xml: &str) -> Sitemap {
+    let kind = if xml.contains("<sitemapindex") {
+        SitemapKind::Index
+    } else {
+        SitemapKind::UrlSet
+    };
+
+    let mut locations = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<loc>") {
+        rest = &rest[start + "<loc>".len()..];
+        let end = match rest.find("</loc>") {
+            Some(end) => end,
+            None => break,
+        };
+
+        let location = unescape(
+            rest[..end]
+                .trim()
+                .trim_start_matches("<![CDATA[")
+                .trim_end_matches("]]>")
+                .trim(),
+        );
+        if !location.is_empty() {
+            locations.push(location);
+        }
+        rest = &rest[end..];
+    }
+
+    return Sitemap { kind, locations };
+}
+
+/// Replaces the predefined XML entities in a string with the characters they stand for.
+///
+/// # Arguments
+///
+/// * `text` - A string slice that holds the escaped text.
+pub(crate) fn unescape(text: &str) -> String {
+    return text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+}
@@ -1,455 +1,7462 @@
-use crate::database::Database;
+use crate::builder::CrawlerBuilder;
+use crate::challenge;
+use crate::charset;
+use crate::config::{Config, Scope};
+use crate::cookies::CookieJar;
+use crate::decision::{SkipDecision, SkipReason};
 use crate::domain::Domain;
-use crate::site::Site;
-use anyhow::Result;
-use chrono::Utc;
-use log::{info, trace, warn};
+use crate::dry_run::{DryRun, LinkOutcome};
+use crate::feed::{self, parse_feed};
+use crate::fetch_failure::FetchFailure;
+use crate::fetcher::{ClientSettings, FetchRequest, FetchResponse, Fetcher, ReqwestFetcher};
+use crate::frontier::Frontier;
+use crate::handler::PageHandler;
+use crate::headers::{self, HeaderRules};
+use crate::host_limit::HostLimiter;
+use crate::metrics::{self, MetricsPage};
+use crate::normalize::{ascii_host, ascii_url, display_host, normalize_query};
+use crate::progress::{self, ProgressStatus};
+use crate::proxy::{self, ProxyConfig};
+use crate::public_suffix::registrable_domain;
+use crate::redirect::{RedirectChain, RedirectLoop};
+use crate::render;
+use crate::report::{CrawlReport, ErrorCategory, FetchError, LatencyStats};
+use crate::resource::{self, FetchPolicy, ResourceKind};
+use crate::robots::{CompiledRobots, RobotsDiff, RobotsDirectives, USER_AGENT};
+use crate::session::{CrawlSession, SessionStatus};
+use crate::site::{DomainSummary, Site};
+use crate::sitemap::{parse_sitemap, sitemap_directives, SitemapKind};
+use crate::storage::Storage;
+use crate::trap::TrapRule;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use futures_util::future::{self, Either, OptionFuture};
+use futures_util::stream::{self, FuturesUnordered, StreamExt};
+use log::{debug, error, info, trace, warn};
 use rayon::prelude::*;
-use robots_txt::matcher::SimpleMatcher;
-use robots_txt::Robots;
+use reqwest::header::{HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE};
 use select::document::Document;
-use select::predicate::Name;
-use std::collections::HashSet;
+use select::node::Node;
+use select::predicate::{Name, Predicate};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::io::Read;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use url::Url;
 extern crate pretty_env_logger;
 
+/// The maximum number of sitemap indexes followed below a sitemap listed in robots.txt.
+const MAX_SITEMAP_DEPTH: u64 = 3;
+
+/// The maximum number of sitemaps fetched when seeding a crawl.
+const MAX_SITEMAPS: usize = 100;
+
+/// The interval at which the size of the database is checked against `max_disk_mb`.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The fraction of `max_disk_mb` past which the crawl warns that the limit is approaching.
+const DISK_WARNING_RATIO: f64 = 0.9;
+
+/// The interval over which the transfer rate of a response body is measured.
+const TRANSFER_RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of threads reading response bodies that may be left blocked in a read, after the
+/// crawler gave up on their body as stalled, on top of one per worker. Past it, bodies are read
+/// on the workers themselves.
+const MAX_STALLED_BODY_READERS: usize = 16;
+
+/// The number of URLs fetched at once by an async crawl, unless `concurrency` is set.
+const DEFAULT_ASYNC_CONCURRENCY: usize = 64;
+
+/// The pause before each fetch of the retry pass that ends a crawl, on top of `delay_ms`, giving
+/// the servers that failed time to recover.
+const FINAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Each distinct host of the origin URLs of a crawl, along with its robots.txt if it has one.
+type OriginRobots = Vec<(String, Option<RobotsFile>)>;
+
+/// The robots.txt of a domain, as read from its override file or fetched from the domain.
+pub(crate) struct RobotsFile {
+    /// The contents of the robots.txt, empty if the domain answered that it has none.
+    pub(crate) contents: String,
+    /// The URL the robots.txt was fetched from, or `None` if it was read from an override file.
+    pub(crate) url: Option<String>,
+}
+
+/// The values of the `rel` attribute of an `<a>` element that ask not to follow its link.
+const NOFOLLOW_RELS: [&str; 3] = ["nofollow", "ugc", "sponsored"];
+
 /// Represents a web crawler with a specified origin URL and recursion depth.
 pub struct Crawler {
-    /// The starting URL for the crawler.
-    origin_url: String,
-    /// The maximum depth to which the crawler will run.
-    recursion_depth: u64,
-    /// The database that the crawler will store sites in.
-    database: Database,
+    /// The configuration the crawler runs with (origin URL, recursion depth, limits, ...).
+    config: Config,
+    /// The storage that the crawler will store sites in.
+    storage: Arc<dyn Storage>,
+    /// The worker threads fetching pages, `concurrency` of them or one per CPU core.
+    pool: rayon::ThreadPool,
+    /// The permits limiting the requests in flight to each host.
+    host_limiter: HostLimiter,
+    /// The distinct hosts of the origin URLs, used to decide which discovered URLs are in scope.
+    origin_hosts: Vec<String>,
+    /// The extra headers of the requests sent to each host.
+    header_rules: HeaderRules,
+    /// The settings of the HTTP clients of the crawl.
+    client_settings: ClientSettings,
+    /// The fetcher every request of a blocking crawl is sent with.
+    fetcher: Arc<dyn Fetcher + Send + Sync>,
+    /// The async client that accepts invalid TLS certificates, used for `trusted_hosts`.
+    relaxed_async_client: Mutex<Option<reqwest::Client>>,
+    /// The number of requests sent so far without validating TLS certificates.
+    relaxed_tls_requests: AtomicU64,
+    /// The hooks run on every fetched HTML page, in the order they were registered.
+    handlers: Vec<Arc<dyn PageHandler + Send + Sync>>,
+    /// The number of cookies set by each host so far during this crawl, counted up to
+    /// `max_cookies_per_host`.
+    cookie_counts: Mutex<HashMap<String, u64>>,
+    /// The number of pages fetched so far during this crawl.
+    pages_fetched: AtomicU64,
+    /// The number of pages fetched so far during this crawl from each host, or each registrable
+    /// domain with the `site` scope, when `max_pages_per_domain` is set.
+    domain_pages: Mutex<HashMap<String, u64>>,
+    /// The hosts, or registrable domains, that reached `max_pages_per_domain`.
+    truncated_domains: Mutex<BTreeSet<String>>,
+    /// The number of pages successfully fetched so far during this crawl.
+    pages_succeeded: AtomicU64,
+    /// The fetches that failed so far during this crawl, along with why.
+    fetch_errors: Mutex<Vec<FetchError>>,
+    /// The id of the crawl run recorded when the crawl started, which every stored site
+    /// references, if the storage records runs.
+    run_id: Mutex<Option<i64>>,
+    /// The URLs whose fetch failed so far with a timeout, a failed connection or a server error,
+    /// along with the batch that fetched them, which are retried once the crawl is done.
+    transient_failures: Mutex<Vec<(String, Batch)>>,
+    /// The response time of every request sent so far to fetch a page.
+    latencies: Mutex<Vec<Duration>>,
+    /// The number of requests sent so far to fetch pages, per host.
+    host_requests: Mutex<BTreeMap<String, u64>>,
+    /// The deepest depth level being fetched, the origin being 0.
+    current_depth: AtomicU64,
+    /// The number of queued URLs whose fetch hasn't started yet.
+    frontier_size: AtomicU64,
+    /// The number of pages successfully fetched before the current depth level started.
+    level_start_pages: AtomicU64,
+    /// The number of URLs skipped so far because their stored copy was still fresh.
+    pages_cached: AtomicU64,
+    /// The number of URLs skipped so far because robots.txt disallows them.
+    pages_blocked_by_robots: AtomicU64,
+    /// The number of responses skipped so far for not being HTML.
+    skipped_by_type: AtomicU64,
+    /// The number of links skipped so far for having the extension of a binary file, counting a
+    /// URL once for every link to it.
+    skipped_by_extension: AtomicU64,
+    /// The URLs rejected as crawler traps, along with the rule that rejected them.
+    trap_rejections: Mutex<HashMap<String, TrapRule>>,
+    /// The number of pages stored so far that weren't stored before.
+    pages_new: AtomicU64,
+    /// The number of pages stored so far whose content changed since they were last stored.
+    pages_changed: AtomicU64,
+    /// The number of pages stored so far whose content is the same as when they were last stored.
+    pages_unchanged: AtomicU64,
+    /// The number of pages so far that the server reported as not modified since they were
+    /// stored.
+    pages_not_modified: AtomicU64,
+    /// The URLs whose redirect wasn't followed, along with the redirect target, because it leads
+    /// out of the scope of the crawl or to a URL robots.txt disallows.
+    blocked_redirects: Mutex<BTreeMap<String, String>>,
+    /// The sites fetched since the last write, which are written together in a transaction.
+    pending_sites: Mutex<Vec<Site>>,
+    /// The decisions to skip discovered URLs since the last flush, written along with the sites.
+    pending_skips: Mutex<Vec<SkipDecision>>,
+    /// The number of fetches aborted so far because their body arrived slower than
+    /// `min_transfer_rate`.
+    too_slow: AtomicU64,
+    /// The number of threads reading a response body at the moment, including those still
+    /// blocked in the read of a body given up on as stalled.
+    body_readers: Arc<AtomicUsize>,
+    /// The number of worker batches started so far, which numbers the next one.
+    batch_ids: AtomicU64,
+    /// The pages fetched, failures and total response time of every generation so far.
+    generation_stats: Mutex<BTreeMap<u64, GenerationStats>>,
+    /// The number of requests sent and body bytes downloaded so far, per expected kind of
+    /// resource.
+    kind_stats: Mutex<BTreeMap<ResourceKind, (u64, u64)>>,
+    /// The number of resources fetched with a HEAD request that turned out to be HTML and were
+    /// fetched again in full.
+    kind_corrections: AtomicU64,
+    /// The parsed robots.txt of every domain checked so far during this crawl, keyed by host.
+    robots_cache: Mutex<HashMap<String, Arc<OnceLock<CompiledRobots>>>>,
+    /// The contents of the configured robots.txt override files, keyed by domain.
+    robots_overrides: HashMap<String, String>,
+    /// The parsed live robots.txt of every overridden domain checked so far, when diffing.
+    live_robots_cache: Mutex<HashMap<String, Arc<OnceLock<CompiledRobots>>>>,
+    /// The URLs whose admission differs between the live and the planned robots.txt rules.
+    robots_diff: Mutex<Vec<RobotsDiff>>,
+    /// The number of bot-detection challenges each host has served in a row.
+    consecutive_challenges: Mutex<HashMap<String, u64>>,
+    /// The hosts suspended for the rest of the crawl for serving too many challenges.
+    suspended_hosts: Mutex<HashSet<String>>,
+    /// The canonical URLs stored so far in place of the alias their page was fetched from.
+    canonical_urls: Mutex<HashSet<String>>,
+    /// The URLs extracted as the assets of a page so far, when `extract_assets` is set.
+    asset_urls: Mutex<HashSet<String>>,
+    /// The external links recorded so far, checked at the end of the crawl when
+    /// `verify_external` is set.
+    external_urls: Mutex<BTreeSet<String>>,
+    /// The URL of the page each URL to crawl was first discovered on, until it is stored.
+    referrers: Mutex<HashMap<String, String>>,
+    /// The robots.txt snapshot and rule each URL was allowed by when it was checked, in
+    /// compliance mode, until it is stored.
+    robots_decisions: Mutex<HashMap<String, (Option<i64>, String)>>,
+    /// The feeds discovered on the pages fetched since they were last stored, by host of the
+    /// pages.
+    discovered_feeds: Mutex<BTreeMap<String, BTreeSet<String>>>,
+    /// The feeds fetched so far, when `use_feeds` is set.
+    fetched_feeds: Mutex<HashSet<String>>,
+    /// The hosts suspended for the rest of the crawl for redirecting back and forth between
+    /// `http` and `https`.
+    broken_tls_hosts: Mutex<HashSet<String>>,
+    /// The size of the database as of the last disk check.
+    disk_bytes: AtomicU64,
+    /// The time of the last disk check, if any.
+    last_disk_check: Mutex<Option<Instant>>,
+    /// Whether the crawl already warned that `max_disk_mb` is approaching.
+    disk_warned: AtomicBool,
+    /// Whether the visible text of pages is stored, which is turned off as `max_disk_mb`
+    /// approaches.
+    store_text: AtomicBool,
+    /// Whether the crawl stopped admitting new URLs because it reached `max_disk_mb`.
+    truncated_for_disk: AtomicBool,
 }
 
-impl Crawler {
-    /// Creates a new instance of the `Crawler` struct.
+/// Represents the result of successfully fetching a page.
+struct FetchedPage {
+    /// The HTML content of the page.
+    html: String,
+    /// Whether the response headers exceeded the configured size or cookie limits.
+    header_anomaly: bool,
+    /// Whether the response headers exceeded `max_header_bytes`. The body of such responses is
+    /// not read.
+    oversized_headers: bool,
+    /// The number of cookies the response tried to set.
+    cookies_set: u64,
+    /// Whether the response was a bot-detection challenge rather than the site's content.
+    bot_challenged: bool,
+    /// The robots directives of the response's `X-Robots-Tag` headers.
+    robots_directives: RobotsDirectives,
+    /// The HTTP status code of the response.
+    status_code: u16,
+    /// The URL of the response, after redirects.
+    final_url: String,
+    /// The URLs redirected through on the way to `final_url`, starting with the requested URL,
+    /// along with the status code of their redirect.
+    redirects: Vec<(String, u16)>,
+    /// The URL that `final_url` redirects to, if the redirect wasn't followed because it leads
+    /// out of the scope of the crawl or to a URL robots.txt disallows. The body of such responses is not read.
+    blocked_redirect: Option<String>,
+    /// The media type of the response, without its parameters.
+    content_type: Option<String>,
+    /// Whether the response is HTML. The body of other responses is not read, and `html` is
+    /// left empty, unless they are feeds.
+    is_html: bool,
+    /// Whether the response is an RSS or Atom feed, whose body is read into `html` to crawl its
+    /// entries.
+    is_feed: bool,
+    /// The size of the body, as read or as announced by the `Content-Length` header.
+    body_bytes: Option<u64>,
+    /// The number of bytes of the body that were received, before decoding. It is 0 if the body
+    /// wasn't read.
+    received_bytes: u64,
+    /// The media type sniffed from the first bytes of the body, if it was read.
+    sniffed_type: Option<&'static str>,
+    /// The `ETag` header of the response, if it had one.
+    etag: Option<String>,
+    /// The `Last-Modified` header of the response, if it had one.
+    last_modified: Option<String>,
+    /// The time from sending the request to reading the response, in milliseconds.
+    response_ms: u64,
+}
+
+/// Identifies the worker batch that fetched a page.
+///
+/// Every generation of a crawl, made of the pages one link further from the origin, is split
+/// between the workers in batches. Batches are numbered in the order they start.
+#[derive(Clone, Copy)]
+struct Batch {
+    /// The generation of the batch, counting the origin as generation 0.
+    generation: u64,
+    /// The number of the batch, unique within the crawl.
+    id: u64,
+}
+
+/// The worker threads of a crawl, lent to an async crawl for its blocking work: storage access,
+/// robots.txt, sitemap and feed fetches, and HTML parsing. Running that work on them rather than
+/// on the task of the crawl keeps it from holding up the other pages in flight.
+struct BlockingPool<'a, 'scope> {
+    /// The scope the work is spawned in, which ends once the crawl is done.
+    scope: &'a rayon::Scope<'scope>,
+}
+
+impl<'scope> BlockingPool<'_, 'scope> {
+    /// Runs blocking work on a worker thread of the crawl, waiting for it without blocking the
+    /// task. A panic of the work is resumed in the task, as if the work had been run in place.
     ///
     /// ## Arguments
-    /// * `origin_url` - A `String` representing the starting URL of the crawler.
-    /// * `recursion_depth` - A `u64` representing the maximum depth to which the crawler will run.
-    /// ## Returns
     ///
-    /// A new instance of the `Crawler` struct.
-    pub fn new(origin_url: String, recursion_depth: u64, database_name: &str) -> Result<Self> {
-        Ok(Crawler {
-            origin_url,
-            recursion_depth,
-            database: Database::new(database_name)?,
-        })
+    /// * `work` - The blocking work.
+    async fn run<T: Send + 'scope>(&self, work: impl FnOnce() -> T + Send + 'scope) -> T {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.scope.spawn(move |_| {
+            let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(work)));
+        });
+        return match receiver
+            .await
+            .expect("The blocking work of the crawl was dropped")
+        {
+            Ok(value) => value,
+            Err(panic) => std::panic::resume_unwind(panic),
+        };
     }
+}
 
-    /// Starts the crawling process from the origin URL.
-    ///
-    /// This function initializes a reqwest blocking client, fetches the HTMl content of the origin
-    /// URl, extracts all links from it, and iterates over these links to discover new links.
-    pub fn crawl(&self) {
-        info!(
-            "Starting crawl process from origin URL: {}",
-            self.origin_url
-        );
+/// Represents the outcomes of the fetches of a generation.
+#[derive(Default)]
+struct GenerationStats {
+    /// The number of pages fetched.
+    pages: u64,
+    /// The number of fetches that failed.
+    failures: u64,
+    /// The total response time of the pages fetched, in milliseconds.
+    response_ms: u64,
+}
 
-        // Declare reqwest blocking client
-        let reqwest_client = reqwest::blocking::Client::new();
+/// Represents the links extracted from a page.
+struct PageLinks {
+    /// The normalized links of the page that are followed, along with their anchor text.
+    links: HashMap<String, String>,
+    /// The normalized links of the page that aren't followed because of their `rel` attribute,
+    /// along with their anchor text, when they are recorded.
+    nofollow: HashMap<String, String>,
+    /// The normalized URLs of the images, scripts and stylesheets of the page, when they are
+    /// extracted.
+    assets: HashSet<String>,
+    /// The links of the page to hosts out of scope, along with their anchor text, when they are
+    /// recorded.
+    external: HashMap<String, String>,
+}
 
-        // Setup Database
-        let _ = self.database.setup();
+/// Tracks the transfer rate of a response body while it is read.
+struct TransferRate {
+    /// The instant the current measuring interval started.
+    interval_start: Instant,
+    /// The number of bytes read during the current interval.
+    interval_bytes: u64,
+    /// The instant the transfer rate fell below `min_transfer_rate`, if it is below it.
+    slow_since: Option<Instant>,
+}
 
-        // Get HTML of origin url
-        let html = match Self::get_html(&reqwest_client, &self.origin_url) {
-            Some(content) => content,
-            None => {
-                warn!("Skipping URL with unsupported scheme: {}", self.origin_url);
-                return;
-            }
+impl TransferRate {
+    /// Starts measuring the transfer rate of a body.
+    fn new() -> Self {
+        return TransferRate {
+            interval_start: Instant::now(),
+            interval_bytes: 0,
+            slow_since: None,
         };
+    }
+}
 
-        // Get all links from the origin url
-        let urls = Self::get_links(&self, &html);
+/// Represents a fetch failure raised by the crawler itself rather than by the HTTP client, such
+/// as a body over the size limit, along with its category.
+#[derive(Debug)]
+struct CategorizedError {
+    /// The category of the failure.
+    category: ErrorCategory,
+    /// A string that holds the reason of the failure.
+    reason: String,
+}
 
-        // Save origin URL to database
-        Self::write_site(&self, &self.origin_url, &urls);
+impl CategorizedError {
+    /// Wraps the reason of a fetch failure into an error of the given category.
+    ///
+    /// ## Arguments
+    ///
+    /// * `category` - The category of the failure.
+    /// * `reason` - The reason of the failure.
+    fn error(category: ErrorCategory, reason: String) -> anyhow::Error {
+        return anyhow::Error::new(CategorizedError { category, reason });
+    }
+}
 
-        // Fetch and store robots.txt
-        let domain = Url::parse(&self.origin_url)
-            .unwrap()
-            .host_str()
-            .unwrap()
-            .to_string();
-        if let Some(robots) = self.get_robots(&domain, &reqwest_client) {
-            Self::write_domain(&self, &domain, &robots);
-        }
+impl fmt::Display for CategorizedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.reason);
+    }
+}
 
-        // Iterate over all links until none are left
-        Self::iterate_links(&self, &urls, &reqwest_client, 0);
+impl std::error::Error for CategorizedError {}
 
-        // Print Database Summary
-        let _ = Site::summarize_site_table(&self.database);
-        let _ = Domain::summarize_domain_table(&self.database);
-    }
+/// Represents the descriptive metadata parsed from a page.
+#[derive(Default)]
+struct PageMetadata {
+    /// The whitespace-normalized text of the `<title>` element, if the page has one.
+    title: Option<String>,
+    /// The content of the `<meta name="description">` tag, if the page has one.
+    description: Option<String>,
+    /// The visible text of the page, if it is stored.
+    text: Option<String>,
+    /// The anchor text of the links of the page, by URL.
+    anchor_texts: HashMap<String, String>,
+    /// The canonical URL the page declares with `<link rel="canonical">`, if any.
+    canonical_url: Option<String>,
+    /// The URLs the page links to that aren't followed because of their `rel` attribute, when
+    /// they are recorded.
+    nofollow_links: HashSet<String>,
+    /// The URLs of the images, scripts and stylesheets of the page, when they are extracted.
+    assets: HashSet<String>,
+    /// The URLs the page links to on hosts out of scope, when they are recorded.
+    external_links: HashSet<String>,
+    /// The estimate of how much of the page is rendered by JavaScript, from 0 to 1.
+    js_dependency_score: f64,
+}
 
-    /// Fetches the HTML content of the given URL using the provided reqwest blocking client.
+impl Crawler {
+    /// Creates a new instance of the `Crawler` struct.
     ///
-    /// This function sends a GET request to the specified URL and reads the response body into a string.
+    /// This is a shorthand for building the crawler from the config with a `CrawlerBuilder`.
     ///
     /// ## Arguments
+    /// * `config` - The `Config` holding the origin URL, recursion depth, database name and
+    ///   crawl limits.
+    /// ## Returns
     ///
-    /// * `reqwest_client` - A reference to the reqwest blocking client used to make the HTTP request.
-    /// * `url` - A string slice that holds the URL to be fetched.
+    /// A new instance of the `Crawler` struct.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the config is invalid, the database cannot be
+    /// opened, or a robots.txt override file cannot be read.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use rustle::{Config, Crawler};
     ///
+    /// let config = Config {
+    ///     origin_urls: vec!["https://example.com".to_string()],
+    ///     depth: 2,
+    ///     database: "example".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let crawler = Crawler::new(config)?;
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    pub fn new(config: Config) -> Result<Self> {
+        return CrawlerBuilder::from(config).build();
+    }
+
+    /// Creates a new instance of the `Crawler` struct storing into the given storage, instead of
+    /// the database named in the config.
+    ///
+    /// ## Arguments
+    /// * `config` - The `Config` holding the origin URL, recursion depth and crawl limits.
+    /// * `storage` - The `Storage` the crawl is stored in, such as an opened `Database`.
     /// ## Returns
     ///
-    /// A `String` containing the HTML content of the given URL.
-    fn get_html(reqwest_client: &reqwest::blocking::Client, url: &str) -> Option<String> {
-        trace!("Fetching HTML content for URL: {}", url);
+    /// A new instance of the `Crawler` struct.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if a robots.txt override or root certificate file
+    /// cannot be read, a configured header is invalid, or the worker threads cannot be started.
+    pub fn with_storage(config: Config, storage: Box<dyn Storage>) -> Result<Self> {
+        return Self::with_shared_storage(config, Arc::from(storage));
+    }
 
-        // Parse the URL to check its scheme
-        let parsed_url = Url::parse(url).unwrap();
-        if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
-            warn!("Unsupported URL scheme {}", parsed_url.scheme());
-            return None;
-        }
+    /// Creates a new instance of the `Crawler` struct storing into a storage that may be shared
+    /// with other crawlers, such as those built from clones of a `CrawlerBuilder`.
+    ///
+    /// ## Arguments
+    /// * `config` - The `Config` holding the origin URL, recursion depth and crawl limits.
+    /// * `storage` - The shared `Storage` the crawl is stored in.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if a robots.txt override or root certificate file
+    /// cannot be read, a configured header is invalid, or the worker threads cannot be started.
+    pub(crate) fn with_shared_storage(
+        mut config: Config,
+        storage: Arc<dyn Storage>,
+    ) -> Result<Self> {
+        // Write the hosts of the origins, overrides and trusted hosts in ASCII, as those of every
+        // parsed link
+        config.origin_urls = config
+            .origin_urls
+            .iter()
+            .map(|url| ascii_url(url))
+            .collect();
+        config.robots_override = std::mem::take(&mut config.robots_override)
+            .into_iter()
+            .map(|(domain, path)| (ascii_host(&domain), path))
+            .collect();
+        config.trusted_hosts = config
+            .trusted_hosts
+            .iter()
+            .map(|host| ascii_host(host))
+            .collect();
 
-        // Fetch the site and make sure it accepts connection
-        let response = reqwest_client.get(url).send();
-        let mut site = match response {
-            Ok(resp) => resp,
-            Err(e) => {
-                warn!("Failed to fetch URL: {}: {}", url, e);
-                return None;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.concurrency.unwrap_or_default())
+            .build()
+            .context("Failed to build the worker threads")?;
+        if config.store_text {
+            storage.setup()?;
+            if !storage.has_text_search()? {
+                bail!("store_text needs full-text search, which the storage doesn't provide");
             }
-        };
-
-        // Fetch HTML content
-        let mut html = String::new();
-        if let Err(e) = site.read_to_string(&mut html) {
-            warn!(
-                "Failed to read response as valid UTF-8 for URL: {}: {}",
-                url, e
+        }
+        let mut robots_overrides = HashMap::new();
+        for (domain, path) in &config.robots_override {
+            let robots = std::fs::read_to_string(path).with_context(|| {
+                format!(
+                    "Failed to read the robots.txt override of {} at {}",
+                    domain,
+                    path.display()
+                )
+            })?;
+            info!(
+                "Using robots.txt override {} for {}",
+                path.display(),
+                display_host(domain)
             );
-            return None;
+            robots_overrides.insert(domain.clone(), robots);
         }
-
-        return Some(html);
+        let header_rules = HeaderRules::new(&config.headers)?;
+        let mut root_certs = Vec::new();
+        for path in &config.extra_root_certs {
+            let pem = std::fs::read(path).with_context(|| {
+                format!("Failed to read the root certificates at {}", path.display())
+            })?;
+            root_certs.extend(
+                reqwest::Certificate::from_pem_bundle(&pem).with_context(|| {
+                    format!("Invalid PEM root certificates at {}", path.display())
+                })?,
+            );
+        }
+        let client_settings = ClientSettings {
+            user_agent: config.user_agent.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            accept_invalid_certs: config.danger_accept_invalid_certs,
+            root_certs,
+            proxy: config.proxy.as_ref().map(ProxyConfig::build).transpose()?,
+            cookie_jar: config.cookies.then(|| {
+                Arc::new(CookieJar::new(
+                    &config.seed_cookies,
+                    config.max_cookies_per_host,
+                ))
+            }),
+        };
+        let fetcher: Arc<dyn Fetcher + Send + Sync> = Arc::new(ReqwestFetcher::new(
+            client_settings.clone(),
+            config.trusted_hosts.clone(),
+        ));
+        let origin_hosts = config.origin_hosts();
+        let store_text = config.store_text;
+        let max_concurrent_per_host = config.max_concurrent_per_host;
+        Ok(Crawler {
+            config,
+            storage,
+            pool,
+            host_limiter: HostLimiter::new(max_concurrent_per_host),
+            origin_hosts,
+            header_rules,
+            client_settings,
+            fetcher,
+            relaxed_async_client: Mutex::new(None),
+            relaxed_tls_requests: AtomicU64::new(0),
+            handlers: Vec::new(),
+            cookie_counts: Mutex::new(HashMap::new()),
+            pages_fetched: AtomicU64::new(0),
+            domain_pages: Mutex::new(HashMap::new()),
+            truncated_domains: Mutex::new(BTreeSet::new()),
+            pages_succeeded: AtomicU64::new(0),
+            fetch_errors: Mutex::new(Vec::new()),
+            run_id: Mutex::new(None),
+            transient_failures: Mutex::new(Vec::new()),
+            latencies: Mutex::new(Vec::new()),
+            host_requests: Mutex::new(BTreeMap::new()),
+            current_depth: AtomicU64::new(0),
+            frontier_size: AtomicU64::new(0),
+            level_start_pages: AtomicU64::new(0),
+            pages_cached: AtomicU64::new(0),
+            pages_blocked_by_robots: AtomicU64::new(0),
+            skipped_by_type: AtomicU64::new(0),
+            skipped_by_extension: AtomicU64::new(0),
+            trap_rejections: Mutex::new(HashMap::new()),
+            pages_new: AtomicU64::new(0),
+            pages_changed: AtomicU64::new(0),
+            pages_unchanged: AtomicU64::new(0),
+            pages_not_modified: AtomicU64::new(0),
+            too_slow: AtomicU64::new(0),
+            body_readers: Arc::new(AtomicUsize::new(0)),
+            blocked_redirects: Mutex::new(BTreeMap::new()),
+            pending_sites: Mutex::new(Vec::new()),
+            pending_skips: Mutex::new(Vec::new()),
+            batch_ids: AtomicU64::new(0),
+            generation_stats: Mutex::new(BTreeMap::new()),
+            kind_stats: Mutex::new(BTreeMap::new()),
+            kind_corrections: AtomicU64::new(0),
+            robots_cache: Mutex::new(HashMap::new()),
+            robots_overrides,
+            live_robots_cache: Mutex::new(HashMap::new()),
+            robots_diff: Mutex::new(Vec::new()),
+            consecutive_challenges: Mutex::new(HashMap::new()),
+            suspended_hosts: Mutex::new(HashSet::new()),
+            canonical_urls: Mutex::new(HashSet::new()),
+            asset_urls: Mutex::new(HashSet::new()),
+            external_urls: Mutex::new(BTreeSet::new()),
+            referrers: Mutex::new(HashMap::new()),
+            robots_decisions: Mutex::new(HashMap::new()),
+            discovered_feeds: Mutex::new(BTreeMap::new()),
+            fetched_feeds: Mutex::new(HashSet::new()),
+            broken_tls_hosts: Mutex::new(HashSet::new()),
+            disk_bytes: AtomicU64::new(0),
+            last_disk_check: Mutex::new(None),
+            disk_warned: AtomicBool::new(false),
+            store_text: AtomicBool::new(store_text),
+            truncated_for_disk: AtomicBool::new(false),
+        })
     }
 
-    /// Extracts and normalizes all the links from the given HTML content.
-    ///
-    /// This function parses the HTML content, finds all anchor (`<a>`) tags, and extracts their `href` attributes.
-    /// It then normalizes these URLs using the `normalize_url` function and collects them into a `HashSet`.
+    /// Sets the hooks run on every fetched HTML page, replacing those set before.
     ///
     /// ## Arguments
     ///
-    /// * `html` - A string slice that holds the HTML content to be processed.
+    /// * `handlers` - The `PageHandler`s, run in order.
+    pub(crate) fn set_handlers(&mut self, handlers: Vec<Arc<dyn PageHandler + Send + Sync>>) {
+        self.handlers = handlers;
+    }
+
+    /// Sets the fetcher every request of a blocking crawl is sent with, replacing the one that
+    /// sends them over the network.
     ///
-    /// ## Returns
+    /// ## Arguments
     ///
-    /// A `HashSet<String>` containing all the normalized links found in the HTML content.
-    fn get_links(&self, html: &str) -> HashSet<String> {
-        trace!("Extracting links from HTML content");
-        return Document::from(html)
-            .find(Name("a"))
-            .filter_map(|n| n.attr("href"))
-            .filter_map(|url| self.normalize_url(url))
-            .collect::<HashSet<String>>();
+    /// * `fetcher` - The `Fetcher` of the crawl.
+    pub(crate) fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher + Send + Sync>) {
+        self.fetcher = fetcher;
     }
 
-    /// Normalizes a given URL to ensure it is a valid and complete URL.
+    /// Gets the storage the crawl is stored in.
+    pub fn storage(&self) -> &dyn Storage {
+        return self.storage.as_ref();
+    }
+
+    /// Starts the crawling process from the origin URL.
     ///
-    /// This function attempts to parse the given URL and checks if it has a host that matches the `ORIGIN_URL`.
-    /// If the URL is relative (starts with `//` or `/`), it will be converted to an absolute URL using `ORIGIN_URL`.
+    /// This function initializes a reqwest blocking client, fetches the robots.txt of the origin
+    /// domain, fetches the HTMl content of the origin URl (unless it is disallowed or cached),
+    /// extracts all links from it, and iterates over these links to discover new links.
+    ///
+    /// Once the crawl is finished, it is recorded as a crawl session, which is marked as suspect
+    /// when it fetched far fewer pages than the previous crawl while most of its fetches failed.
     ///
     /// ## Arguments
     ///
-    /// * `url` - A string slice that holds the URL to be normalized.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
     ///
     /// ## Returns
     ///
-    /// An `Option<String>` containing the normalized URL if it is valid and belongs to the same host as `ORIGIN_URL`,
-    /// otherwise `None`.
-    fn normalize_url(&self, url: &str) -> Option<String> {
-        trace!("Normalizing URL: {}", url);
+    /// A `CrawlReport` of the finished crawl, holding the recorded `CrawlSession`.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the database can't be set up or summarized, or if
+    /// the origin URL may not be crawled: it is invalid, disallowed by robots.txt, or redirects
+    /// out of the scope of the crawl or to a URL robots.txt disallows.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use rustle::{Config, Crawler};
+    ///
+    /// let crawler = Crawler::new(Config::new()?)?;
+    /// let report = crawler.crawl(false)?;
+    /// for error in &report.fetch_errors {
+    ///     eprintln!("{}: {}", error.url, error.reason);
+    /// }
+    /// println!("{} pages fetched", report.pages_fetched);
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    pub fn crawl(&self, force_accept: bool) -> Result<CrawlReport> {
+        let listener = metrics::bind(&self.config.metrics)?;
 
-        // Parse the Url with the `Url` crate
-        let parsed_url = Url::parse(url);
-        match parsed_url {
-            // If the parsed Url is a valid Url
-            Ok(parsed_url) => {
-                // If its host matched the origin url, return it, else, skip it
-                if parsed_url.has_host() {
-                    return Some(url.to_string());
-                } else {
-                    return None;
-                }
+        // Serve the metrics and display the progress from threads of their own until the crawl
+        // is finished
+        let stop = AtomicBool::new(false);
+        return std::thread::scope(|scope| {
+            if let Some(listener) = &listener {
+                scope.spawn(|| metrics::serve(listener, &stop, || self.render_metrics()));
             }
-            // If the parsed Url is not a valid Url
-            Err(_e) => {
-                // If the Url starts with "//" (relative top level Url), normalize it with https
-                // If the Url starts with "/" (relative path Url), normalize it with the origin url
-                // Else, skip the Url
-                if url.starts_with("//") {
-                    return Some(format!("https:{}", url));
-                } else if url.starts_with('/') {
-                    return Some(format!("{}{}", self.origin_url, url));
-                } else {
-                    return None;
+            if self.config.progress {
+                scope.spawn(|| {
+                    progress::run(&stop, self.progress_interval(), || self.progress_status())
+                });
+            }
+            let report = self.crawl_blocking(force_accept);
+            stop.store(true, Ordering::SeqCst);
+            return report;
+        });
+    }
+
+    /// Fetches the origin pages and sorts the links found on them by what the crawl would do
+    /// with them, under its normalization, scope, filter and robots.txt rules, without following
+    /// any of them. The command line runs it instead of `crawl` with `dry_run`.
+    ///
+    /// Nothing is written to the database, as the `CrawlerBuilder` keeps the crawl in memory
+    /// with `dry_run` unless it is given a storage.
+    ///
+    /// ## Returns
+    ///
+    /// The `DryRun` listing the origins and the outcome of their links.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if an origin URL or a link can't be checked against
+    /// robots.txt.
+    pub fn dry_run(&self) -> Result<DryRun> {
+        let mut dry_run = DryRun::default();
+        for origin_url in &self.config.origin_urls {
+            if self.config.respect_robots && !self.is_allowed_to_scrape(origin_url)? {
+                dry_run
+                    .seeds
+                    .insert(origin_url.clone(), "blocked by robots".to_string());
+                continue;
+            }
+            let page = match self.get_html(origin_url, reqwest::Method::GET, None) {
+                Ok(page) => page,
+                Err(e) => {
+                    dry_run
+                        .seeds
+                        .insert(origin_url.clone(), format!("failed, {:#}", e));
+                    continue;
                 }
+            };
+            let outcome = match &page.blocked_redirect {
+                Some(target) => format!(
+                    "status {}, redirects to {}, which isn't followed",
+                    page.status_code, target
+                ),
+                None if page.final_url != *origin_url => {
+                    format!("status {} at {}", page.status_code, page.final_url)
+                }
+                None => format!("status {}", page.status_code),
+            };
+            dry_run.seeds.insert(origin_url.clone(), outcome);
+
+            let Ok(page_url) = Url::parse(&page.final_url) else {
+                continue;
+            };
+            let document = Document::from(page.html.as_str());
+            let base_url = Self::base_url(&document, page_url);
+            for anchor in document.find(Name("a")) {
+                let Some(mut url) = anchor
+                    .attr("href")
+                    .and_then(|href| base_url.join(href.trim()).ok())
+                else {
+                    continue;
+                };
+                url.set_fragment(None);
+                let nofollow = !self.config.follow_nofollow && Self::is_nofollow(&anchor);
+                let (outcome, url, reason) = self.dry_run_outcome(url, nofollow)?;
+                dry_run.add(outcome, url, reason);
             }
         }
+        return Ok(dry_run);
     }
 
-    /// Fetches the HTML content of the given URL and extracts all the links from it.
+    /// Finds what the crawl would do with a link of an origin page, applying the same rules as
+    /// `get_links`, `normalize_url` and `admit_url` in the same order.
     ///
     /// ## Arguments
     ///
-    /// * `url` - A string slice that holds the URL to be fetched.
-    /// * `reqwest_client` - A reference to the reqwest blocking client used to make the HTTP request.
+    /// * `url` - The resolved URL of the link.
+    /// * `nofollow` - Whether the link asks not to be followed, and isn't.
     ///
     /// ## Returns
     ///
-    /// A `HashSet<String>` containing all the links extracted from the HTML content of the given URL.
-    fn fetch_and_process_links(
+    /// The `LinkOutcome` of the link, its URL, normalized if it would be crawled, and the reason
+    /// it is filtered, if it is.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the link can't be checked against robots.txt.
+    fn dry_run_outcome(
         &self,
-        url: &String,
-        reqwest_client: &reqwest::blocking::Client,
-    ) -> HashSet<String> {
-        trace!("Fetching and processing links for URL: {}", url);
+        url: Url,
+        nofollow: bool,
+    ) -> Result<(LinkOutcome, String, Option<String>)> {
+        let host = match url.host_str() {
+            Some(host) if matches!(url.scheme(), "http" | "https") => host,
+            _ => return Ok((LinkOutcome::UnsupportedScheme, url.to_string(), None)),
+        };
+        if !self.in_scope(host) {
+            return Ok((LinkOutcome::OutOfScope, url.to_string(), None));
+        }
+        if nofollow {
+            let reason = Some("rel=nofollow".to_string());
+            return Ok((LinkOutcome::Filtered, url.to_string(), reason));
+        }
+        let (kind, policy) = self.fetch_policy(url.as_str());
+        if policy == FetchPolicy::Skip {
+            let reason = Some(format!("fetch_policy skips {}", kind));
+            return Ok((LinkOutcome::Filtered, url.to_string(), reason));
+        }
 
-        // Get HTML from given URL
-        let html = match Self::get_html(&reqwest_client, url) {
-            Some(content) => content,
-            None => {
-                warn!("Skipping URL with unsupported scheme: {}", url);
-                return HashSet::new();
+        // Only a trap rule is left to reject the link once it is in scope and fetched
+        let Some(normalized) = self.normalize_url(url.as_str()) else {
+            let mut parsed_url = url.clone();
+            if self.config.normalize_query {
+                normalize_query(&mut parsed_url);
             }
+            let reason = TrapRule::check(&parsed_url, &self.config)
+                .map(|rule| format!("{} trap rule", rule));
+            return Ok((LinkOutcome::Filtered, url.to_string(), reason));
         };
+        if self.config.respect_robots && !self.is_allowed_to_scrape(&normalized)? {
+            return Ok((LinkOutcome::BlockedByRobots, normalized, None));
+        }
+        return Ok((LinkOutcome::WouldCrawl, normalized, None));
+    }
 
-        // Extract links from the HTML
-        let links = Self::get_links(&self, &html);
+    /// Runs the crawl started by `crawl`, fetching pages on the worker threads.
+    ///
+    /// ## Arguments
+    ///
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
+    ///
+    /// ## Returns
+    ///
+    /// A `CrawlReport` of the finished crawl, holding the recorded `CrawlSession`.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `crawl`.
+    fn crawl_blocking(&self, force_accept: bool) -> Result<CrawlReport> {
+        let start_time = Utc::now();
+        let runtime = Instant::now();
 
-        // Write Url to Database
-        Self::write_site(&self, url, &links);
+        // Get the links of the origin urls, from the database if they are cached, or else by
+        // fetching their HTML and saving it to the database
+        let (robots, cached_links) = self.start_crawl()?;
+        let origin_urls = self.uncached_origin_urls(&cached_links);
+        self.start_level(0, origin_urls.len());
+        let mut urls = self.pool.install(|| {
+            origin_urls
+                .par_iter()
+                .map_init(
+                    || self.next_batch(0),
+                    |batch, origin_url| {
+                        // Fetch the origin url and save it to the database
+                        self.frontier_size.fetch_sub(1, Ordering::SeqCst);
+                        self.reserve_domain_page(origin_url);
+                        self.reserve_page();
+                        return self.fetch_and_process_links(origin_url, *batch);
+                    },
+                )
+                .reduce(HashSet::new, |mut links1, links2| {
+                    links1.extend(links2);
+                    return links1;
+                })
+        });
+        self.flush_sites();
+        urls.extend(cached_links.into_values().flatten());
+        self.seed_crawl(&mut urls, &robots)?;
 
-        trace!("Scraped {} - {} Links", url, links.len());
+        // Iterate over all links until none are left
+        let max_depth = Self::iterate_links(self, &urls, 0);
+        let recovered = self.retry_transient_failures();
+        self.verify_external_links();
 
-        return links;
+        let concurrency = self.pool.current_num_threads();
+        return self.finish_crawl(
+            start_time,
+            runtime,
+            max_depth,
+            recovered,
+            concurrency,
+            force_accept,
+        );
     }
 
-    /// Checks if a URL exists in the database and if its crawl_time is less than a day old, skips
-    /// it.
+    /// Starts the crawling process from the origin URL, fetching pages concurrently with the
+    /// async reqwest client.
+    ///
+    /// This does the same as `crawl`, but keeps up to `concurrency` requests in flight at once
+    /// rather than tying up one thread per request. The storage, robots.txt, sitemaps and feeds
+    /// are still accessed by blocking code, and pages are still parsed on the worker threads of
+    /// the crawl, which the pages in flight hand that work to.
+    ///
+    /// The crawl must be run on a multi-threaded tokio runtime, as it holds the thread it is
+    /// polled on until it is done: once started, it can't be cancelled by dropping it.
     ///
     /// ## Arguments
     ///
-    /// * `url` - A string slice that holds the URL of the site.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
     ///
     /// ## Returns
     ///
-    /// A boolean indicating whether the URL should be skipped.
-    pub fn should_skip_cached_url(&self, url: &str) -> Result<bool> {
-        if let Some(site) = Site::read_into(url, &self.database)? {
-            let one_day_ago = Utc::now() - chrono::Duration::days(1);
-            if site.crawl_time > one_day_ago {
-                trace!("Skipping cached URL: {}", url);
-                return Ok(true);
-            }
+    /// A `CrawlReport` of the finished crawl, holding the recorded `CrawlSession`.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `crawl`, or an error if it is run on a
+    /// current-thread runtime.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use rustle::{Config, Crawler};
+    ///
+    /// let crawler = Crawler::new(Config::new()?)?;
+    /// let runtime = tokio::runtime::Runtime::new()?;
+    /// let report = runtime.block_on(crawler.crawl_async(false))?;
+    /// println!("{} pages fetched", report.pages_fetched);
+    /// # Ok::<(), rustle::Error>(())
+    /// ```
+    pub async fn crawl_async(&self, force_accept: bool) -> Result<CrawlReport> {
+        let handle = tokio::runtime::Handle::current();
+        if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::CurrentThread {
+            bail!("An async crawl needs a multi-threaded tokio runtime");
         }
 
-        return Ok(false);
+        // The blocking work borrows the crawler, so the crawl runs in a scope of its worker
+        // threads that can't outlive it, driven from this thread
+        return tokio::task::block_in_place(|| {
+            return self.pool.in_place_scope(|scope| {
+                return handle.block_on(self.crawl_in_pool(&BlockingPool { scope }, force_accept));
+            });
+        });
     }
 
-    /// Checks if a URL is allowed to be scraped based on the robots.txt rules.
+    /// Runs the async crawl started by `crawl_async`, along with its metrics endpoint and
+    /// progress display.
     ///
     /// ## Arguments
     ///
-    /// * `url` - A string slice that holds the URL to be checked.
+    /// * `blocking` - A reference to the `BlockingPool` the blocking work of the crawl runs on.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
     ///
     /// ## Returns
     ///
-    /// A boolean indicating whether the URL is allowed to be scraped.    
-    fn is_allowed_to_scrape(&self, url: &str) -> Result<bool> {
-        let parsed_url = Url::parse(url).unwrap();
-        let path = parsed_url.path().to_string();
-        let domain = parsed_url.host_str().unwrap().to_string();
+    /// A `CrawlReport` of the finished crawl.
+    async fn crawl_in_pool<'s>(
+        &'s self,
+        blocking: &BlockingPool<'_, 's>,
+        force_accept: bool,
+    ) -> Result<CrawlReport> {
+        let start_time = Utc::now();
+        let runtime = Instant::now();
+        let client = self.async_client()?;
+        let listener = metrics::bind(&self.config.metrics)?;
 
-        // Check if robots.txt is already in the database
-        let robots_txt = if let Some(domain_data) = Domain::read_into(&domain, &self.database)? {
-            domain_data.robots
-        } else {
-            // Fetch robots.txt from the domain
-            let robots = self.get_robots(&domain, &reqwest::blocking::Client::new());
-            if let Some(robots_content) = robots {
-                // Save robots.txt to the database
-                self.write_domain(&domain, &robots_content);
-                robots_content
-            } else {
-                String::new()
-            }
+        // robots.txt and sitemaps are still fetched with the fetcher of the crawl, on its worker
+        // threads
+        let crawl = self.crawl_with_client(&client, blocking, start_time, runtime, force_accept);
+
+        // Serve the metrics and display the progress alongside the crawl, until the crawl is
+        // finished
+        let serve = OptionFuture::from(
+            listener
+                .as_ref()
+                .map(|listener| metrics::serve_async(listener, || self.render_metrics())),
+        );
+        let display = OptionFuture::from(
+            self.config
+                .progress
+                .then(|| progress::run_async(self.progress_interval(), || self.progress_status())),
+        );
+        let background = std::pin::pin!(future::join(serve, display));
+        let report = match future::select(std::pin::pin!(crawl), background).await {
+            Either::Left((report, _)) => report,
+            Either::Right((_, crawl)) => crawl.await,
         };
+        return report;
+    }
 
-        // Parse robots.txt and check if the URL is allowed
-        let robots = Robots::from_str_lossy(&robots_txt);
-        let matcher = SimpleMatcher::new(&robots.choose_section("Rustle").rules);
-        let allowed = matcher.check_path(&path);
+    /// Runs the async crawl started by `crawl_async`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - A reference to the async reqwest client used to fetch pages.
+    /// * `blocking` - A reference to the `BlockingPool` the blocking work of the crawl runs on.
+    /// * `start_time` - The time the crawl started.
+    /// * `runtime` - The instant the crawl started, to measure how long it took.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
+    ///
+    /// ## Returns
+    ///
+    /// A `CrawlReport` of the finished crawl.
+    async fn crawl_with_client<'s>(
+        &'s self,
+        client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        start_time: DateTime<Utc>,
+        runtime: Instant,
+        force_accept: bool,
+    ) -> Result<CrawlReport> {
+        let (robots, cached_links) = blocking.run(move || self.start_crawl()).await?;
+        let origin_urls = self.uncached_origin_urls(&cached_links);
+        self.start_level(0, origin_urls.len());
+        let batch = self.next_batch(0);
+        let mut urls = stream::iter(origin_urls)
+            .map(|origin_url| async move {
+                self.frontier_size.fetch_sub(1, Ordering::SeqCst);
+                self.reserve_domain_page(origin_url);
+                self.reserve_page();
+                return self
+                    .fetch_and_process_links_async(origin_url, client, blocking, batch)
+                    .await;
+            })
+            .buffer_unordered(self.async_concurrency())
+            .concat()
+            .await;
+        urls.extend(cached_links.into_values().flatten());
+        let urls = blocking
+            .run(move || {
+                self.flush_sites();
+                return self.seed_crawl(&mut urls, &robots).map(|()| urls);
+            })
+            .await?;
 
-        trace!("URL: {} - Allowed? {}", url, allowed);
+        let max_depth = self.iterate_links_async(&urls, client, blocking, 0).await;
 
-        return Ok(allowed);
+        // The retry pass fetches one URL at a time, which the fetcher does just as well,
+        // and so does the check of the external links
+        return blocking
+            .run(move || {
+                let recovered = self.retry_transient_failures();
+                self.verify_external_links();
+                return self.finish_crawl(
+                    start_time,
+                    runtime,
+                    max_depth,
+                    recovered,
+                    self.async_concurrency(),
+                    force_accept,
+                );
+            })
+            .await;
     }
 
-    /// Iterates through the given set of origin links, fetching and processing each link to discover new links.
+    /// Gets the number of URLs an async crawl fetches at once.
+    fn async_concurrency(&self) -> usize {
+        return self.config.concurrency.unwrap_or(DEFAULT_ASYNC_CONCURRENCY);
+    }
+
+    /// Builds the async reqwest client of a crawl, along with the client it uses instead for
+    /// `trusted_hosts`. Requests go through the configured proxy, or else through the one of
+    /// the proxy environment variables, and share the cookie store of the crawl.
+    fn async_client(&self) -> Result<reqwest::Client> {
+        if !self.config.trusted_hosts.is_empty() {
+            *self.relaxed_async_client.lock().unwrap() =
+                Some(self.client_settings.async_client(true)?);
+        }
+        return self
+            .client_settings
+            .async_client(self.config.danger_accept_invalid_certs);
+    }
+
+    /// Sets up the database and checks that the origin URLs may be crawled, before they are
+    /// fetched.
     ///
-    /// This function maintains a set of visited URLs to avoid processing the same URL multiple times.
-    /// It uses a reqwest blocking client to fetch the HTML content of each URL and extracts links from it.
-    /// The process continues until there are no new URLs to visit.
+    /// ## Returns
     ///
-    /// ## Arguments
+    /// Each distinct host of the origin URLs along with its robots.txt if it has one, and the
+    /// links of the stored copy of every origin URL that is cached and doesn't need to be
+    /// fetched.
     ///
-    /// * `origin_links` - A reference to a `HashSet<String>` containing the initial set of URLs to start the iteration.
-    /// * `reqwest_client` - A reference to the reqwest blocking client used to make the HTTP requests.    
-    fn iterate_links(
-        &self,
-        origin_links: &HashSet<String>,
-        reqwest_client: &reqwest::blocking::Client,
-        mut depth: u64,
-    ) {
+    /// ## Errors
+    ///
+    /// This function will return an error if the database can't be set up, or if an origin URL
+    /// is disallowed by robots.txt.
+    fn start_crawl(&self) -> Result<(OriginRobots, HashMap<String, HashSet<String>>)> {
         info!(
-            "Starting link iteration with target depth: {}",
-            self.recursion_depth
+            "Starting crawl process from origin URLs: {}",
+            self.config.origin_urls.join(", ")
         );
+        match self.config.cache_ttl_hours {
+            Some(hours) => info!("Refetching cached URLs older than {} hours", hours),
+            None => info!("Never refetching cached URLs"),
+        }
+        if self.config.danger_accept_invalid_certs {
+            warn!("TLS certificates are NOT validated: invalid and self-signed certificates are accepted from every host");
+        } else if !self.config.trusted_hosts.is_empty() {
+            warn!(
+                "TLS certificates are NOT validated for trusted hosts: {}",
+                self.config
+                    .trusted_hosts
+                    .iter()
+                    .map(|host| display_host(host))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        for path in &self.config.extra_root_certs {
+            info!("Trusting the extra root certificates at {}", path.display());
+        }
 
-        // Initialize a set to keep track of visited URLs
-        let mut visited_urls = HashSet::new();
-        visited_urls.insert(self.origin_url.to_string());
-
-        // Fetch new set of URLs to visit, exlcuding visited URLs
-        let mut new_urls = origin_links
-            .difference(&visited_urls)
-            .map(|x| x.to_string())
-            .collect::<HashSet<String>>();
-
-        // Loop until the maximum recursion depth is reached, or there are no new URLs to visit
-        while !(depth >= self.recursion_depth) && !new_urls.is_empty() {
-            // Use parallel iteration w/ `rayon` crate to process URLs
-            let (next_visited_urls, next_new_urls): (HashSet<String>, HashSet<String>) = new_urls
-                .par_iter()
-                .map(|url| {
-                    // Check if site is cached and can be skipped
-                    if self.should_skip_cached_url(url).unwrap()
-                        && !self.is_allowed_to_scrape(url).unwrap()
-                    {
-                        return None;
-                    }
+        // Setup Database, and record the start of the run that the stored sites reference
+        self.storage.setup()?;
+        *self.run_id.lock().unwrap() = self.storage.start_run(
+            &self.config.origin_urls.join(" "),
+            self.config.depth,
+            Utc::now(),
+        )?;
 
-                    // Fetch all links from the current URL
-                    let links = Self::fetch_and_process_links(&self, &url, &reqwest_client);
+        // Fetch and store the robots.txt of every origin host before any origin is requested
+        let mut robots_by_host: OriginRobots = Vec::new();
+        for origin_url in &self.config.origin_urls {
+            let Ok(origin_url) = Url::parse(origin_url) else {
+                continue;
+            };
+            let Some(domain) = origin_url.host_str() else {
+                continue;
+            };
+            if robots_by_host.iter().any(|(host, _)| host == domain) {
+                continue;
+            }
+            let robots = self.get_robots(domain, &origin_url);
+            if let Some(robots) = &robots {
+                Self::write_domain(self, domain, robots, sitemap_directives(&robots.contents));
+            }
+            robots_by_host.push((domain.to_string(), robots));
+        }
 
-                    return Some((url.clone(), links));
-                })
-                .fold(
-                    // Inititalize empty sets for visited and new URLs
-                    || (HashSet::new(), HashSet::new()),
-                    |(mut visited, mut new), opt| {
-                        if let Some((url, links)) = opt {
-                            // Add the current URl to the visited set
-                            visited.insert(url);
-
-                            // Add all newly found links to the new set, exclduing already visited URLs
-                            new.extend(links.difference(&visited).cloned());
-                        }
-                        return (visited, new);
-                    },
-                )
-                .reduce(
-                    // Combine results from different threads
-                    || (HashSet::new(), HashSet::new()),
-                    |(mut visited1, mut new1), (visited2, new2)| {
-                        visited1.extend(visited2);
-                        new1.extend(new2);
-                        return (visited1, new1);
-                    },
+        let mut cached_links = HashMap::new();
+        for origin_url in &self.config.origin_urls {
+            // Make sure the origin itself may be crawled
+            if self.config.respect_robots && !self.is_allowed_to_scrape(origin_url)? {
+                bail!(
+                    "Origin URL {} is disallowed by the robots.txt of its host, aborting crawl",
+                    origin_url
                 );
+            }
 
-            // Update loop variables
-            visited_urls.extend(next_visited_urls);
-            new_urls = next_new_urls;
-            depth += 1;
-            trace!("------ DEPTH: {} ------", depth);
+            // Use the links of the stored origin if it is cached
+            let cached_origin = if self.should_skip_cached_url(origin_url)? {
+                self.storage.get_site(origin_url)?
+            } else {
+                None
+            };
+            if let Some(site) = cached_origin {
+                self.pages_cached.fetch_add(1, Ordering::SeqCst);
+                let links = site.followed_links();
+                self.record_referrers(origin_url, &links, 0);
+                cached_links.insert(origin_url.clone(), links);
+            }
         }
+        return Ok((robots_by_host, cached_links));
     }
 
-    /// Fetches the `robots.txt` file for a given domain.
+    /// Gets the origin URLs that are fetched at depth 0, those whose stored copy isn't cached.
     ///
     /// ## Arguments
     ///
-    /// * `domain` - A string slice that holds the domain name.
-    /// * `reqwest_client` - A reference to a `reqwest::blocking::Client` used to make the HTTP request.
-    ///
-    /// ## Returns
-    ///
-    /// An `Option<String>` which contains the content of the `robots.txt` file if the request is successful,
-    /// or `None` if the request fails or the file does not exist.
-    pub fn get_robots(
-        &self,
-        domain: &str,
-        reqwest_client: &reqwest::blocking::Client,
-    ) -> Option<String> {
-        let robots_url = format!("https://{}/robots.txt", domain);
-        match reqwest_client.get(&robots_url).send() {
-            Ok(response) => {
-                if response.status().is_success() {
-                    return response.text().ok();
-                }
-            }
-            Err(e) => {
-                trace!("Failed to fetch robots.txt for {}: {}", domain, e);
-            }
-        }
-        return None;
+    /// * `cached_links` - A reference to the links of the cached origin URLs, by origin URL.
+    fn uncached_origin_urls(&self, cached_links: &HashMap<String, HashSet<String>>) -> Vec<&str> {
+        return self
+            .config
+            .origin_urls
+            .iter()
+            .filter(|origin_url| !cached_links.contains_key(*origin_url))
+            .map(String::as_str)
+            .collect();
     }
 
-    /// Writes a `Site` to the database.
-    ///
-    /// This function creates a `Site` instance with the given URL and links,
-    /// sets the current time as the crawl time, and writes the `Site` to the database.
+    /// Checks whether a host is in scope of any of the origin hosts.
     ///
     /// ## Arguments
     ///
-    /// * `url` - A string slice that holds the URL of the site.
-    /// * `links_to` - A reference to a `HashSet` containing the URLs that the site links to.
-    fn write_site(&self, url: &str, links_to: &HashSet<String>) {
-        trace!("Writing site to database for URL: {}", url);
+    /// * `host` - A string slice that holds the host of a discovered URL.
+    fn in_scope(&self, host: &str) -> bool {
+        return self
+            .origin_hosts
+            .iter()
+            .any(|origin_host| self.config.scope.allows(host, origin_host));
+    }
 
-        // Declare a `Site` struct to hold information
-        let site = Site {
-            url: url.to_string(),
-            crawl_time: Utc::now(),
-            links_to: links_to.clone(),
+    /// Checks that no origin URL has a redirect that wasn't followed, and adds the pages listed in
+    /// the sitemaps of the robots.txt of every origin host to the URLs to crawl if
+    /// `use_sitemaps` is set.
+    ///
+    /// ## Arguments
+    ///
+    /// * `urls` - A mutable reference to the links of the origin URLs.
+    /// * `robots_by_host` - Each host of the origin URLs along with its robots.txt, if it has
+    ///   one.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if an origin URL redirects out of the scope of the
+    /// crawl or to a URL robots.txt disallows.
+    fn seed_crawl(&self, urls: &mut HashSet<String>, robots_by_host: &OriginRobots) -> Result<()> {
+        // Make sure no origin redirects where it can't be followed
+        let blocked_redirects = self.blocked_redirects.lock().unwrap();
+        for origin_url in &self.config.origin_urls {
+            if let Some(target) = blocked_redirects.get(origin_url) {
+                bail!(
+                    "Origin URL {} redirects to {}, which is out of scope or disallowed by robots.txt, aborting crawl. Set follow_cross_host_redirects = true to follow redirects out of scope",
+                    origin_url,
+                    target
+                );
+            }
+        }
+        drop(blocked_redirects);
+
+        // Seed the crawl with the pages listed in the sitemaps of the origins' robots.txt
+        if !self.config.use_sitemaps {
+            return Ok(());
+        }
+        for (domain, robots) in robots_by_host {
+            let Some(robots) = robots else {
+                continue;
+            };
+            let (pages, sitemaps) = self.get_sitemap_urls(sitemap_directives(&robots.contents));
+            info!(
+                "Seeding crawl with {} URLs from {} sitemaps of {}",
+                pages.len(),
+                sitemaps.len(),
+                display_host(domain)
+            );
+            urls.extend(pages);
+
+            // Store every sitemap found, including those nested in sitemap indexes
+            Self::write_domain(self, domain, robots, sitemaps);
+        }
+        return Ok(());
+    }
+
+    /// Summarizes the stored crawl data, logs the statistics of the crawl, and records it as a
+    /// crawl session.
+    ///
+    /// ## Arguments
+    ///
+    /// * `start_time` - The time the crawl started.
+    /// * `runtime` - The instant the crawl started, to measure how long it took.
+    /// * `max_depth` - The deepest depth level the crawl reached.
+    /// * `recovered` - The number of URLs fetched by the retry pass after failing transiently.
+    /// * `concurrency` - The number of pages the crawl fetched at once.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
+    ///
+    /// ## Returns
+    ///
+    /// The `CrawlReport` of the crawl.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the database can't be summarized.
+    fn finish_crawl(
+        &self,
+        start_time: DateTime<Utc>,
+        runtime: Instant,
+        max_depth: u64,
+        recovered: u64,
+        concurrency: usize,
+        force_accept: bool,
+    ) -> Result<CrawlReport> {
+        // Store the feeds discovered since the last depth level
+        self.store_feeds();
+
+        // Count what this crawl stored, the report printing it
+        let stored_sites = self.storage.summarize_sites(start_time)?;
+        let stored_domains = self.storage.summarize_domains(start_time)?;
+        let mut stored_by_domain = self.storage.summarize_by_domain(start_time)?;
+        if self.config.scope == Scope::Site {
+            stored_by_domain = DomainSummary::group_by_site(stored_by_domain);
+        }
+        info!(
+            "{} pages new, {} changed and {} unchanged since they were last stored",
+            self.pages_new.load(Ordering::SeqCst),
+            self.pages_changed.load(Ordering::SeqCst),
+            self.pages_unchanged.load(Ordering::SeqCst)
+        );
+        info!(
+            "{} pages were not modified, and their stored copy was kept",
+            self.pages_not_modified.load(Ordering::SeqCst)
+        );
+        info!(
+            "Skipped {} non-HTML responses and {} links with binary extensions",
+            self.skipped_by_type.load(Ordering::SeqCst),
+            self.skipped_by_extension.load(Ordering::SeqCst)
+        );
+        let mut bytes_downloaded = 0;
+        for (kind, (requests, bytes)) in self.kind_stats.lock().unwrap().iter() {
+            info!(
+                "{}: {} requests, {} bytes downloaded",
+                kind, requests, bytes
+            );
+            bytes_downloaded += bytes;
+        }
+        info!(
+            "{} resources fetched with HEAD turned out to be HTML and were fetched again",
+            self.kind_corrections.load(Ordering::SeqCst)
+        );
+        for (generation, stats) in self.generation_stats.lock().unwrap().iter() {
+            info!(
+                "Generation {}: {} pages fetched, {} failures, {} ms mean response time",
+                generation,
+                stats.pages,
+                stats.failures,
+                stats
+                    .response_ms
+                    .checked_div(stats.pages)
+                    .unwrap_or_default()
+            );
+        }
+        let blocked_redirects = self.blocked_redirects.lock().unwrap().len();
+        if blocked_redirects > 0 {
+            info!(
+                "Skipped {} URLs that redirect out of scope or to a URL robots.txt disallows",
+                blocked_redirects
+            );
+        }
+        let too_slow = self.too_slow.load(Ordering::SeqCst);
+        if too_slow > 0 {
+            warn!(
+                "{} fetches were aborted for transferring slower than {} bytes per second",
+                too_slow, self.config.min_transfer_rate
+            );
+        }
+        if self.truncated_for_disk.load(Ordering::SeqCst) {
+            error!("Crawl was truncated after reaching max_disk_mb, its crawl data is incomplete");
+        }
+        for host in self.suspended_hosts.lock().unwrap().iter() {
+            error!(
+                "Host {} was suspended for serving bot-detection challenges, its crawl data is incomplete",
+                display_host(host)
+            );
+        }
+
+        let mut trap_rejections = BTreeMap::new();
+        for rule in self.trap_rejections.lock().unwrap().values() {
+            *trap_rejections.entry(*rule).or_insert(0) += 1;
+        }
+
+        let mut latencies = std::mem::take(&mut *self.latencies.lock().unwrap());
+        let requests_sent = latencies.len() as u64;
+        let latency = LatencyStats::from_samples(&mut latencies);
+
+        let session = self.record_session(start_time, force_accept);
+        return Ok(CrawlReport {
+            pages_fetched: session.pages_fetched,
+            pages_cached: self.pages_cached.load(Ordering::SeqCst),
+            pages_blocked_by_robots: self.pages_blocked_by_robots.load(Ordering::SeqCst),
+            trap_rejections,
+            truncated_domains: self
+                .truncated_domains
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|domain| display_host(domain))
+                .collect(),
+            fetch_errors: std::mem::take(&mut *self.fetch_errors.lock().unwrap()),
+            recovered_by_retry: recovered,
+            bytes_downloaded,
+            requests_sent,
+            relaxed_tls_requests: self.relaxed_tls_requests.load(Ordering::SeqCst),
+            latency,
+            max_depth,
+            concurrency,
+            elapsed: runtime.elapsed(),
+            stored_sites,
+            stored_domains,
+            stored_by_domain,
+            session,
+        });
+    }
+
+    /// Records the finished crawl as a crawl session, judging it against the previous accepted
+    /// crawl from the same origin URL, and records the end of its crawl run.
+    ///
+    /// ## Arguments
+    ///
+    /// * `start_time` - The time the crawl started.
+    /// * `force_accept` - Whether the session is accepted even if it looks suspect.
+    ///
+    /// ## Returns
+    ///
+    /// The recorded `CrawlSession`.
+    fn record_session(&self, start_time: DateTime<Utc>, force_accept: bool) -> CrawlSession {
+        // Judge a crawl from several origins against the previous one from the same origins
+        let origin_url = self.config.origin_urls.join(" ");
+        let previous = self
+            .storage
+            .latest_accepted_session(&origin_url)
+            .unwrap_or_else(|e| {
+                warn!("Failed to read the previous crawl session: {:#}", e);
+                None
+            });
+
+        let mut session = CrawlSession {
+            origin_url,
+            start_time,
+            end_time: Utc::now(),
+            pages_fetched: self.pages_succeeded.load(Ordering::SeqCst),
+            fetch_failures: self.fetch_errors.lock().unwrap().len() as u64,
+            status: SessionStatus::Accepted,
+            disk_bytes: self.storage.disk_bytes(),
+            truncated_for_disk: self.truncated_for_disk.load(Ordering::SeqCst),
         };
+        if session.is_suspect(previous.as_ref(), self.config.suspect_ratio) {
+            let previous_pages = previous.map(|p| p.pages_fetched).unwrap_or_default();
+            if force_accept {
+                warn!(
+                    "Accepting crawl that fetched {} pages ({} failures) against {} previously",
+                    session.pages_fetched, session.fetch_failures, previous_pages
+                );
+            } else {
+                error!(
+                    "Crawl fetched only {} pages ({} failures) against {} previously, marking it as suspect",
+                    session.pages_fetched, session.fetch_failures, previous_pages
+                );
+                session.status = SessionStatus::Suspect;
+            }
+        }
 
-        // Call method to write Site struct to database
-        site.write_into(&self.database);
+        if let Err(e) = self.storage.put_session(&session) {
+            error!("Failed to record the crawl session: {:#}", e);
+        }
+        if let Some(run_id) = *self.run_id.lock().unwrap() {
+            if let Err(e) = self.storage.finish_run(
+                run_id,
+                session.end_time,
+                session.pages_fetched,
+                session.fetch_failures,
+                session.status,
+            ) {
+                error!("Failed to record the end of crawl run {}: {:#}", run_id, e);
+            }
+        }
+        return session;
     }
 
-    /// Writes a `Domain` to the database.
+    /// Fetches the HTML content of the given URL using the provided reqwest blocking client.
     ///
-    /// This function creates a `Domain` instance with the given domain and robots.txt string,
-    /// sets the current time as the crawl time, and writes the `Domain` to the database.
+    /// This function sends a request to the specified URL, checks the response headers
+    /// against the configured size and cookie limits, and reads the response body into a string.
+    /// Oversized headers and excess cookies do not abort the fetch; the page is only flagged.
+    /// The body of HEAD requests and of responses that aren't HTML is never read.
     ///
     /// ## Arguments
     ///
-    /// * `domain` - A string slice that holds the domain of the site. (Fomratted as "example.com")
-    /// * `robots` - A string slice that holds the contents of the domain's robots.txt
-    fn write_domain(&self, domain: &str, robots: &str) {
-        trace!("Writing domain to database for domain: {}", domain);
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `method` - The method of the request, either GET or HEAD.
+    /// * `stored` - The stored copy of the page, if any, whose `ETag` and `Last-Modified` make
+    ///   the request conditional. The body of a `304 Not Modified` response is never read.
+    ///
+    /// ## Returns
+    ///
+    /// A `FetchedPage` containing the HTML content of the given URL and its header statistics.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the URL has an unsupported scheme, the request
+    /// fails, the response is a `304 Not Modified` without a stored copy to keep, or the body is
+    /// over the size limit or can't be read.
+    fn get_html(
+        &self,
+        url: &str,
+        method: reqwest::Method,
+        stored: Option<&Site>,
+    ) -> Result<FetchedPage> {
+        trace!(
+            event = "fetch_start", url = url, method = method.as_str();
+            "Fetching HTML content for URL: {}", url
+        );
+        let (mut site, mut page) =
+            self.fetch(url, method.clone(), &Self::conditional_headers(stored))?;
+        Self::check_not_modified(&page, stored)?;
+        if self.should_read_body(&page, &method, url)? {
+            // Fetch HTML content, reading at most one byte past the size limit to detect bodies
+            // that exceed it without announcing their length
+            let body_start = Instant::now();
+            let headers = site.headers.clone();
+            let body = self.read_body(&mut site, self.config.max_body_bytes + 1)?;
+            self.read_html(&mut page, &body, url, &Self::host_of(url), &headers)?;
+            page.response_ms += body_start.elapsed().as_millis() as u64;
+        }
+        return Ok(page);
+    }
 
-        let domain = Domain {
-            domain: domain.to_string(),
-            crawl_time: Utc::now(),
-            robots: robots.to_string(),
+    /// Sends a request for a URL and reads the status and headers of its response, leaving its
+    /// body unread.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `method` - The method of the request.
+    /// * `headers` - A reference to the extra headers of the request.
+    ///
+    /// ## Returns
+    ///
+    /// The response, whose body can still be read, along with the `FetchedPage` of its headers.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the URL has an unsupported scheme or the request
+    /// fails.
+    fn fetch(
+        &self,
+        url: &str,
+        method: reqwest::Method,
+        headers: &HeaderMap,
+    ) -> Result<(FetchResponse, FetchedPage)> {
+        let parsed_url = Self::parse_fetch_url(url)?;
+        let host = parsed_url.host_str().unwrap_or_default();
+
+        // Wait for the politeness delay before issuing the request
+        if self.config.delay_ms > 0 {
+            std::thread::sleep(Duration::from_millis(self.config.delay_ms));
+        }
+
+        // Fetch the site and make sure it accepts connection
+        let request_start = Instant::now();
+        let (site, redirects) = self.send(method, url, headers)?;
+        let mut page = self.read_head(url, host, site.status, &site.url, &site.headers, redirects);
+        page.response_ms = request_start.elapsed().as_millis() as u64;
+        return Ok((site, page));
+    }
+
+    /// Checks that a URL resolves without downloading it: requests its headers with `HEAD`, or
+    /// with a `GET` of its first byte when the server rejects `HEAD`, and never reads the body.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be checked.
+    ///
+    /// ## Returns
+    ///
+    /// A `FetchedPage` holding the status and headers of the response, whose `html` is empty.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `fetch`.
+    fn validate_link(&self, url: &str) -> Result<FetchedPage> {
+        trace!(
+            event = "fetch_start", url = url, method = "HEAD";
+            "Validating URL: {}", url
+        );
+        let (_, page) = self.fetch(url, reqwest::Method::HEAD, &HeaderMap::new())?;
+        if !Self::rejects_head(&page) {
+            return Ok(page);
+        }
+        let (_, page) = self.fetch(url, reqwest::Method::GET, &Self::first_byte_range())?;
+        return Ok(page);
+    }
+
+    /// Fetches the HTML content of a given URL with the async reqwest client.
+    ///
+    /// This does the same as `get_html`, without blocking the thread while waiting for the
+    /// response.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reqwest_client` - A reference to the async reqwest client used to make the HTTP request.
+    /// * `blocking` - A reference to the `BlockingPool` the page is decoded on.
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `method` - The method of the request, either GET or HEAD.
+    /// * `stored` - The stored copy of the page, if any, whose `ETag` and `Last-Modified` make
+    ///   the request conditional.
+    ///
+    /// ## Returns
+    ///
+    /// A `FetchedPage` containing the HTML content of the given URL and its header statistics.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `get_html`.
+    async fn get_html_async<'s>(
+        &'s self,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        url: &str,
+        method: reqwest::Method,
+        stored: Option<&Site>,
+    ) -> Result<FetchedPage> {
+        trace!(
+            event = "fetch_start", url = url, method = method.as_str();
+            "Fetching HTML content for URL: {}", url
+        );
+        let (mut site, mut page) = self
+            .fetch_async(
+                reqwest_client,
+                blocking,
+                url,
+                method.clone(),
+                &Self::conditional_headers(stored),
+            )
+            .await?;
+        Self::check_not_modified(&page, stored)?;
+        if self.should_read_body(&page, &method, url)? {
+            let body_start = Instant::now();
+            let headers = site.headers().clone();
+            let body = self
+                .read_body_async(&mut site, self.config.max_body_bytes + 1)
+                .await?;
+            let url = url.to_string();
+            page = blocking
+                .run(move || {
+                    self.read_html(&mut page, &body, &url, &Self::host_of(&url), &headers)?;
+                    return Ok::<_, anyhow::Error>(page);
+                })
+                .await?;
+            page.response_ms += body_start.elapsed().as_millis() as u64;
+        }
+        return Ok(page);
+    }
+
+    /// Sends a request for a URL with the async reqwest client and reads the status and headers
+    /// of its response, like `fetch`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reqwest_client` - A reference to the async reqwest client used to make the HTTP request.
+    /// * `blocking` - A reference to the `BlockingPool` redirects are checked against robots.txt
+    ///   on.
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `method` - The method of the request.
+    /// * `headers` - A reference to the extra headers of the request.
+    ///
+    /// ## Returns
+    ///
+    /// The response, whose body can still be read, along with the `FetchedPage` of its headers.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `fetch`.
+    async fn fetch_async<'s>(
+        &'s self,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        url: &str,
+        method: reqwest::Method,
+        headers: &HeaderMap,
+    ) -> Result<(reqwest::Response, FetchedPage)> {
+        let parsed_url = Self::parse_fetch_url(url)?;
+        let host = parsed_url.host_str().unwrap_or_default();
+
+        // Wait for the politeness delay before issuing the request
+        if self.config.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.delay_ms)).await;
+        }
+
+        let request_start = Instant::now();
+        let (site, redirects) = self
+            .send_async(reqwest_client, blocking, method, url, headers)
+            .await?;
+        let mut page = self.read_head(
+            url,
+            host,
+            site.status(),
+            site.url(),
+            site.headers(),
+            redirects,
+        );
+        page.response_ms = request_start.elapsed().as_millis() as u64;
+        return Ok((site, page));
+    }
+
+    /// Checks that a URL resolves with the async reqwest client, like `validate_link`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reqwest_client` - A reference to the async reqwest client used to make the HTTP request.
+    /// * `blocking` - A reference to the `BlockingPool` redirects are checked against robots.txt
+    ///   on.
+    /// * `url` - A string slice that holds the URL to be checked.
+    ///
+    /// ## Returns
+    ///
+    /// A `FetchedPage` holding the status and headers of the response, whose `html` is empty.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `fetch`.
+    async fn validate_link_async<'s>(
+        &'s self,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        url: &str,
+    ) -> Result<FetchedPage> {
+        trace!(
+            event = "fetch_start", url = url, method = "HEAD";
+            "Validating URL: {}", url
+        );
+        let (_, page) = self
+            .fetch_async(
+                reqwest_client,
+                blocking,
+                url,
+                reqwest::Method::HEAD,
+                &HeaderMap::new(),
+            )
+            .await?;
+        if !Self::rejects_head(&page) {
+            return Ok(page);
+        }
+        let (_, page) = self
+            .fetch_async(
+                reqwest_client,
+                blocking,
+                url,
+                reqwest::Method::GET,
+                &Self::first_byte_range(),
+            )
+            .await?;
+        return Ok(page);
+    }
+
+    /// Checks whether the response to a `HEAD` request says the server doesn't support `HEAD`,
+    /// with a `405 Method Not Allowed` or a `501 Not Implemented`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A reference to the `FetchedPage` of the response.
+    fn rejects_head(page: &FetchedPage) -> bool {
+        return page.status_code == reqwest::StatusCode::METHOD_NOT_ALLOWED.as_u16()
+            || page.status_code == reqwest::StatusCode::NOT_IMPLEMENTED.as_u16();
+    }
+
+    /// Builds the headers that ask for the first byte of a resource only.
+    fn first_byte_range() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, HeaderValue::from_static("bytes=0-0"));
+        return headers;
+    }
+
+    /// Parses a URL about to be fetched, checking that its scheme is supported.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be fetched.
+    ///
+    /// ## Returns
+    ///
+    /// The parsed `Url`.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the URL doesn't parse, or has a scheme other than
+    /// `http` or `https`.
+    fn parse_fetch_url(url: &str) -> Result<Url> {
+        let parsed_url = Url::parse(url).map_err(|e| {
+            CategorizedError::error(
+                ErrorCategory::InvalidUrl,
+                format!("Invalid URL {}: {}", url, e),
+            )
+        })?;
+        if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
+            return Err(CategorizedError::error(
+                ErrorCategory::InvalidUrl,
+                format!("Unsupported URL scheme {}", parsed_url.scheme()),
+            ));
+        }
+        return Ok(parsed_url);
+    }
+
+    /// Builds the headers that ask for a page only if it changed since its stored copy.
+    ///
+    /// ## Arguments
+    ///
+    /// * `stored` - The stored copy of the page, if any.
+    ///
+    /// ## Returns
+    ///
+    /// A `HeaderMap` holding the `If-None-Match` and `If-Modified-Since` headers of the stored
+    /// copy, which is empty if there is none.
+    fn conditional_headers(stored: Option<&Site>) -> HeaderMap {
+        let mut conditional_headers = HeaderMap::new();
+        if let Some(stored) = stored {
+            let validators = [
+                (IF_NONE_MATCH, &stored.etag),
+                (IF_MODIFIED_SINCE, &stored.last_modified),
+            ];
+            for (name, value) in validators {
+                if let Some(value) = value.as_ref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                    conditional_headers.insert(name, value);
+                }
+            }
+        }
+        return conditional_headers;
+    }
+
+    /// Checks that a `304 Not Modified` response has a stored copy to keep. Without one, the
+    /// request had no validators and the response has nothing to store, so it is treated as a
+    /// failed fetch to be retried by a later crawl rather than stored as an empty page.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A reference to the `FetchedPage` built from the headers of the response.
+    /// * `stored` - The stored copy of the page, if any.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the response is a `304 Not Modified` and there is
+    /// no stored copy.
+    fn check_not_modified(page: &FetchedPage, stored: Option<&Site>) -> Result<()> {
+        if page.status_code == reqwest::StatusCode::NOT_MODIFIED.as_u16() && stored.is_none() {
+            return Err(CategorizedError::error(
+                ErrorCategory::Other,
+                "Not Modified response to a request without a stored copy".to_string(),
+            ));
+        }
+        return Ok(());
+    }
+
+    /// Builds the `FetchedPage` of a response from its status and headers, before its body is
+    /// read.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the requested URL.
+    /// * `host` - A string slice that holds the host of the requested URL.
+    /// * `status` - The status code of the response.
+    /// * `response_url` - The URL of the response, after redirects.
+    /// * `headers` - A reference to the headers of the response.
+    /// * `redirects` - The URLs redirected through on the way to `response_url`.
+    ///
+    /// ## Returns
+    ///
+    /// A `FetchedPage` whose `html` is still empty.
+    fn read_head(
+        &self,
+        url: &str,
+        host: &str,
+        status: reqwest::StatusCode,
+        response_url: &Url,
+        headers: &HeaderMap,
+        redirects: Vec<(String, u16)>,
+    ) -> FetchedPage {
+        // Check the response headers against the configured limits
+        let oversized_headers = self.check_header_size(headers, url);
+        let (too_many_cookies, cookies_set) = self.count_cookies(host, headers, url);
+        let header_anomaly = oversized_headers || too_many_cookies;
+
+        let blocked_redirect = headers
+            .get(reqwest::header::LOCATION)
+            .filter(|_| status.is_redirection())
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| response_url.join(location).ok())
+            .map(|location| location.to_string());
+        let etag = headers
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let last_modified = headers
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        // Read the robots directives of the X-Robots-Tag headers
+        let robots_directives = headers
+            .get_all("x-robots-tag")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(RobotsDirectives::parse_header)
+            .fold(RobotsDirectives::default(), RobotsDirectives::merge);
+
+        // Read the media type of the response. Responses without a media type are assumed to be
+        // HTML.
+        let content_type = headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase()
+            });
+        let is_html = content_type.as_ref().is_none_or(|content_type| {
+            self.config
+                .html_content_types
+                .iter()
+                .any(|html_type| html_type.eq_ignore_ascii_case(content_type))
+        });
+        let is_feed = content_type.as_ref().is_some_and(|content_type| {
+            feed::FEED_TYPES.contains(&content_type.as_str())
+                || matches!(content_type.as_str(), "application/xml" | "text/xml")
+        });
+        let announced_bytes = headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        return FetchedPage {
+            html: String::new(),
+            header_anomaly,
+            oversized_headers,
+            cookies_set,
+            bot_challenged: false,
+            robots_directives,
+            status_code: status.as_u16(),
+            final_url: response_url.to_string(),
+            redirects,
+            blocked_redirect,
+            content_type,
+            is_html,
+            is_feed,
+            body_bytes: announced_bytes,
+            received_bytes: 0,
+            sniffed_type: None,
+            etag,
+            last_modified,
+            response_ms: 0,
         };
+    }
+
+    /// Checks whether the body of a response is read, which is only the case for HTML and feed
+    /// responses to GET requests that are neither `304 Not Modified` nor unfollowed redirects,
+    /// and whose headers are within `max_header_bytes`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A reference to the `FetchedPage` built from the headers of the response.
+    /// * `method` - A reference to the method of the request.
+    /// * `url` - A string slice that holds the requested URL, used for logging.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether the body is read.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the body announces it is over the size limit.
+    fn should_read_body(
+        &self,
+        page: &FetchedPage,
+        method: &reqwest::Method,
+        url: &str,
+    ) -> Result<bool> {
+        let not_modified = page.status_code == reqwest::StatusCode::NOT_MODIFIED.as_u16();
+        if !(page.is_html || page.is_feed)
+            || method == reqwest::Method::HEAD
+            || not_modified
+            || page.blocked_redirect.is_some()
+            || page.oversized_headers
+        {
+            trace!(
+                "Not reading {} response for URL: {}",
+                page.content_type.as_deref().unwrap_or_default(),
+                url
+            );
+            return Ok(false);
+        }
+
+        // Skip bodies that announce they are over the size limit
+        let max_body_bytes = self.config.max_body_bytes;
+        if let Some(length) = page.body_bytes.filter(|length| *length > max_body_bytes) {
+            return Err(CategorizedError::error(
+                ErrorCategory::TooLarge,
+                format!(
+                    "Body of {} bytes exceeds the limit of {} bytes",
+                    length, max_body_bytes
+                ),
+            ));
+        }
+        return Ok(true);
+    }
+
+    /// Decodes the body of an HTML response into its `FetchedPage`, and checks whether it is a
+    /// bot-detection challenge.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A mutable reference to the `FetchedPage` of the response.
+    /// * `body` - The bytes of the body, read up to one byte past the size limit.
+    /// * `url` - A string slice that holds the requested URL.
+    /// * `host` - A string slice that holds the host of the requested URL.
+    /// * `headers` - A reference to the headers of the response.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the body is over the size limit.
+    fn read_html(
+        &self,
+        page: &mut FetchedPage,
+        body: &[u8],
+        url: &str,
+        host: &str,
+        headers: &HeaderMap,
+    ) -> Result<()> {
+        let max_body_bytes = self.config.max_body_bytes;
+        if body.len() as u64 > max_body_bytes {
+            return Err(CategorizedError::error(
+                ErrorCategory::TooLarge,
+                format!(
+                    "Aborted reading after {} bytes, over the limit of {} bytes",
+                    body.len(),
+                    max_body_bytes
+                ),
+            ));
+        }
+        page.body_bytes = Some(body.len() as u64);
+        page.received_bytes = body.len() as u64;
+        page.sniffed_type = resource::sniff_media_type(body);
+
+        // Decode the body, replacing the bytes that are invalid in its encoding
+        let (html, encoding) = charset::decode(
+            body,
+            headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+        );
+        trace!("Decoded {} as {}", url, encoding.name());
+
+        // Check whether the page is a bot-detection challenge rather than the site's content
+        page.bot_challenged = challenge::is_challenge(
+            page.status_code,
+            headers,
+            &html,
+            &self.config.challenge_markers,
+        );
+        self.record_challenge(host, page.bot_challenged, url);
+        page.html = html;
+        return Ok(());
+    }
+
+    /// Reads the body of a response in chunks, aborting it if it arrives too slowly.
+    ///
+    /// The transfer rate is measured over intervals of `TRANSFER_RATE_INTERVAL`. Once it stays
+    /// below `min_transfer_rate` for `slow_transfer_secs`, the fetch is aborted and counted as
+    /// too slow, so that a server trickling bytes can't hold a worker for hours. The body is read
+    /// on a thread of its own, so that a server that stops sending altogether is given up on
+    /// after `stall_timeout` too, rather than only once its next bytes arrive.
+    ///
+    /// A thread given up on stays blocked until its read returns, which the network fetcher
+    /// bounds with `timeout_secs`. At most `MAX_STALLED_BODY_READERS` such threads are left
+    /// behind: past them, the body is read on the worker itself, which a stalled server then
+    /// holds until the read of the fetcher times out.
+    ///
+    /// ## Arguments
+    ///
+    /// * `response` - A mutable reference to the response whose body is read.
+    /// * `limit` - The maximum number of bytes read.
+    ///
+    /// ## Returns
+    ///
+    /// The bytes read.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if reading fails or is aborted.
+    fn read_body(&self, response: &mut FetchResponse, limit: u64) -> Result<Vec<u8>> {
+        let mut reader =
+            std::mem::replace(&mut response.body, Box::new(std::io::empty())).take(limit);
+        let mut chunk = [0; 8192];
 
-        domain.write_into(&self.database);
+        // Read on the worker itself once too many readers are left blocked on stalled bodies
+        let max_readers = self.pool.current_num_threads() + MAX_STALLED_BODY_READERS;
+        if self.body_readers.load(Ordering::SeqCst) >= max_readers {
+            let mut body = Vec::new();
+            let mut rate = TransferRate::new();
+            loop {
+                let read = match reader.read(&mut chunk) {
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    read => read.context("Failed to read the response")?,
+                };
+                if read == 0 {
+                    return Ok(body);
+                }
+                body.extend_from_slice(&chunk[..read]);
+                self.check_transfer_rate(&mut rate, read, body.len())?;
+            }
+        }
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        let body_readers = Arc::clone(&self.body_readers);
+        body_readers.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            loop {
+                let read = match reader.read(&mut chunk) {
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    read => read.map(|read| chunk[..read].to_vec()),
+                };
+                let done = !matches!(&read, Ok(chunk) if !chunk.is_empty());
+
+                // Stop once the body is read, or once the crawler gave up on it
+                if sender.send(read).is_err() || done {
+                    break;
+                }
+            }
+            body_readers.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        let mut body = Vec::new();
+        let mut rate = TransferRate::new();
+        loop {
+            let chunk = match receiver.recv_timeout(self.stall_timeout()) {
+                Ok(chunk) => chunk.context("Failed to read the response")?,
+                Err(RecvTimeoutError::Timeout) => return Err(self.too_slow_error(body.len(), 0.0)),
+                Err(RecvTimeoutError::Disconnected) => bail!("Failed to read the response"),
+            };
+            if chunk.is_empty() {
+                return Ok(body);
+            }
+            body.extend_from_slice(&chunk);
+            self.check_transfer_rate(&mut rate, chunk.len(), body.len())?;
+        }
+    }
+
+    /// Reads the body of an async response in chunks, aborting it if it arrives too slowly,
+    /// like `read_body`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `response` - A mutable reference to the response whose body is read.
+    /// * `limit` - The maximum number of bytes read.
+    ///
+    /// ## Returns
+    ///
+    /// The bytes read.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if reading fails or is aborted.
+    async fn read_body_async(
+        &self,
+        response: &mut reqwest::Response,
+        limit: u64,
+    ) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        let mut rate = TransferRate::new();
+        loop {
+            let chunk = match tokio::time::timeout(self.stall_timeout(), response.chunk()).await {
+                Ok(chunk) => chunk.context("Failed to read the response")?,
+                Err(_) => return Err(self.too_slow_error(body.len(), 0.0)),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            let read = chunk.len().min((limit - body.len() as u64) as usize);
+            body.extend_from_slice(&chunk[..read]);
+            if body.len() as u64 >= limit {
+                break;
+            }
+            self.check_transfer_rate(&mut rate, read, body.len())?;
+        }
+        return Ok(body);
+    }
+
+    /// Measures the transfer rate of a response body as it is read, aborting the fetch once it
+    /// has stayed below `min_transfer_rate` for `slow_transfer_secs`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `rate` - A mutable reference to the `TransferRate` of the body.
+    /// * `read` - The number of bytes just read.
+    /// * `body_len` - The number of bytes read so far.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the transfer is too slow.
+    fn check_transfer_rate(
+        &self,
+        rate: &mut TransferRate,
+        read: usize,
+        body_len: usize,
+    ) -> Result<()> {
+        rate.interval_bytes += read as u64;
+
+        // Check the transfer rate of every elapsed interval
+        let elapsed = rate.interval_start.elapsed();
+        if self.config.min_transfer_rate == 0 || elapsed < TRANSFER_RATE_INTERVAL {
+            return Ok(());
+        }
+        let bytes_per_sec = rate.interval_bytes as f64 / elapsed.as_secs_f64();
+        if bytes_per_sec >= self.config.min_transfer_rate as f64 {
+            rate.slow_since = None;
+        } else if rate
+            .slow_since
+            .get_or_insert(rate.interval_start)
+            .elapsed()
+            .as_secs()
+            >= self.config.slow_transfer_secs
+        {
+            return Err(self.too_slow_error(body_len, bytes_per_sec));
+        }
+        rate.interval_start = Instant::now();
+        rate.interval_bytes = 0;
+        return Ok(());
+    }
+
+    /// Gets the longest time a response body may send nothing before it is aborted as too
+    /// slow: `slow_transfer_secs`, and at least one `TRANSFER_RATE_INTERVAL`, or forever when
+    /// `min_transfer_rate` is 0.
+    fn stall_timeout(&self) -> Duration {
+        if self.config.min_transfer_rate == 0 {
+            return Duration::MAX;
+        }
+        return Duration::from_secs(self.config.slow_transfer_secs).max(TRANSFER_RATE_INTERVAL);
+    }
+
+    /// Counts a fetch aborted for a body that arrived too slowly, and builds its error.
+    ///
+    /// ## Arguments
+    ///
+    /// * `body_len` - The number of bytes read before the fetch was aborted.
+    /// * `bytes_per_sec` - The last measured transfer rate of the body.
+    fn too_slow_error(&self, body_len: usize, bytes_per_sec: f64) -> anyhow::Error {
+        self.too_slow.fetch_add(1, Ordering::SeqCst);
+        return CategorizedError::error(
+            ErrorCategory::TooSlow,
+            format!(
+                "Aborted reading after {} bytes, at {:.0} bytes per second",
+                body_len, bytes_per_sec
+            ),
+        );
+    }
+
+    /// Sends a request with the fetcher of the crawl, following redirects until a final
+    /// response is reached.
+    ///
+    /// Redirects are followed manually, so that loops are caught as soon as they come back to a
+    /// URL of the chain. A loop between the `http` and `https` variants of a URL is a broken TLS
+    /// redirect configuration: its host is recorded in the database and suspended for the rest
+    /// of the crawl.
+    ///
+    /// ## Arguments
+    ///
+    /// * `method` - The method of the request.
+    /// * `url` - A string slice that holds the URL to request.
+    /// * `headers` - A reference to the extra headers sent with every request of the chain.
+    ///
+    /// ## Returns
+    ///
+    /// The final `FetchResponse` of the redirect chain, and the URLs redirected through on the
+    /// way, starting with `url`, along with the status code of their redirect. Unless
+    /// `follow_cross_host_redirects` is set, a redirect to another host is not followed, and is
+    /// itself the final response.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if a request fails, a redirect has an invalid
+    /// `Location`, or the chain loops or is longer than `max_redirects`.
+    fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Result<(FetchResponse, Vec<(String, u16)>)> {
+        let mut current = Url::parse(url)?;
+        let mut chain = RedirectChain::new(&current);
+        let mut redirects = Vec::new();
+        loop {
+            // Count the requests whose TLS certificate the fetcher doesn't validate
+            self.relaxes_tls(&current);
+            let request = FetchRequest {
+                method: method.clone(),
+                url: current.clone(),
+                headers: self.request_headers(&current, headers),
+            };
+            let request_start = Instant::now();
+            let response = self.fetcher.fetch(&request);
+            self.record_latency(&current, request_start.elapsed());
+            let response = response?;
+            Self::check_proxy_auth(response.status, &current)?;
+            let Some(next) = self.redirect_target(&current, response.status, &response.headers)?
+            else {
+                return Ok((response, redirects));
+            };
+            let allowed = self.redirect_allowed(&next)?;
+            match self.next_redirect(
+                &mut chain,
+                &current,
+                response.status,
+                next,
+                allowed,
+                &mut redirects,
+            )? {
+                Some(next) => current = next,
+                None => return Ok((response, redirects)),
+            }
+        }
+    }
+
+    /// Sends a request with the async reqwest client, following redirects until a final
+    /// response is reached, like `send`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reqwest_client` - A reference to the async reqwest client, which must not follow
+    ///   redirects itself.
+    /// * `blocking` - A reference to the `BlockingPool` redirects are checked against robots.txt
+    ///   on.
+    /// * `method` - The method of the request.
+    /// * `url` - A string slice that holds the URL to request.
+    /// * `headers` - A reference to the extra headers sent with every request of the chain.
+    ///
+    /// ## Returns
+    ///
+    /// The final `Response` of the redirect chain, and the URLs redirected through on the way.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return the same errors as `send`.
+    async fn send_async<'s>(
+        &'s self,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        method: reqwest::Method,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Result<(reqwest::Response, Vec<(String, u16)>)> {
+        let mut current = Url::parse(url)?;
+        let mut chain = RedirectChain::new(&current);
+        let mut redirects = Vec::new();
+        loop {
+            let relaxed_client = self.relaxed_async_client(&current);
+            let request_start = Instant::now();
+            let response = relaxed_client
+                .as_ref()
+                .unwrap_or(reqwest_client)
+                .request(method.clone(), current.clone())
+                .headers(self.request_headers(&current, headers))
+                .send()
+                .await;
+            self.record_latency(&current, request_start.elapsed());
+            let response = response?;
+            Self::check_proxy_auth(response.status(), &current)?;
+            let Some(next) =
+                self.redirect_target(&current, response.status(), response.headers())?
+            else {
+                return Ok((response, redirects));
+            };
+            // Only a check against robots.txt may block, and has to wait for a worker thread
+            let allowed = if self.config.respect_robots {
+                let checked = next.clone();
+                blocking
+                    .run(move || self.redirect_allowed(&checked))
+                    .await?
+            } else {
+                true
+            };
+            match self.next_redirect(
+                &mut chain,
+                &current,
+                response.status(),
+                next,
+                allowed,
+                &mut redirects,
+            )? {
+                Some(next) => current = next,
+                None => return Ok((response, redirects)),
+            }
+        }
+    }
+
+    /// Checks whether the TLS certificate of a URL goes unvalidated, because of
+    /// `danger_accept_invalid_certs` or `trusted_hosts`, and counts the request if it does.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A reference to the URL requested.
+    fn relaxes_tls(&self, url: &Url) -> bool {
+        let relaxed = url.scheme() == "https"
+            && (self.config.danger_accept_invalid_certs
+                || self
+                    .config
+                    .trusted_hosts
+                    .iter()
+                    .any(|host| Some(host.as_str()) == url.host_str()));
+        if relaxed {
+            self.relaxed_tls_requests.fetch_add(1, Ordering::SeqCst);
+        }
+        return relaxed;
+    }
+
+    /// Gets the async client that sends a request instead of the one of the crawl, which is
+    /// the client accepting invalid TLS certificates when the host of the request is trusted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A reference to the URL requested.
+    fn relaxed_async_client(&self, url: &Url) -> Option<reqwest::Client> {
+        if !self.relaxes_tls(url) {
+            return None;
+        }
+        return self.relaxed_async_client.lock().unwrap().clone();
+    }
+
+    /// Gets the headers of a request: the configured headers of its host, which are looked up
+    /// again at every redirect so that they never follow it to another host, and then those of
+    /// the request itself.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A reference to the URL requested.
+    /// * `headers` - A reference to the headers of the request itself.
+    fn request_headers(&self, url: &Url, headers: &HeaderMap) -> HeaderMap {
+        let mut request_headers = self
+            .header_rules
+            .for_host(url.host_str().unwrap_or_default());
+        if !request_headers.is_empty() {
+            trace!(
+                "Sending configured headers to {}: {}",
+                url,
+                headers::redacted(&request_headers)
+            );
+        }
+        for (name, value) in headers {
+            request_headers.insert(name.clone(), value.clone());
+        }
+        return request_headers;
+    }
+
+    /// Checks that a response doesn't come from a proxy asking for credentials, as a proxy
+    /// answers plain `http` requests with a 407 rather than failing them.
+    ///
+    /// ## Arguments
+    ///
+    /// * `status` - The status code of the response.
+    /// * `url` - A reference to the URL that was requested.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an `ErrorCategory::ProxyAuth` error if the status is 407.
+    fn check_proxy_auth(status: reqwest::StatusCode, url: &Url) -> Result<()> {
+        if status == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            return Err(CategorizedError::error(
+                ErrorCategory::ProxyAuth,
+                format!(
+                    "The proxy rejected the credentials of the crawler for {}",
+                    url
+                ),
+            ));
+        }
+        return Ok(());
+    }
+
+    /// Checks whether a redirect may be followed to a URL under the robots.txt of its domain.
+    ///
+    /// ## Arguments
+    ///
+    /// * `next` - A reference to the URL the redirect leads to.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the URL may be fetched, which is always the case unless `respect_robots` is set.
+    fn redirect_allowed(&self, next: &Url) -> Result<bool> {
+        return Ok(!self.config.respect_robots || self.is_allowed_to_scrape(next.as_str())?);
+    }
+
+    /// Resolves the URL a response redirects to, unless it isn't a redirect, or the redirect
+    /// leaves the scope of the crawl to another host and `follow_cross_host_redirects` isn't set.
+    ///
+    /// ## Arguments
+    ///
+    /// * `current` - A reference to the URL the response came from.
+    /// * `status` - The status code of the response.
+    /// * `headers` - A reference to the headers of the response.
+    ///
+    /// ## Returns
+    ///
+    /// The URL the redirect leads to, or `None` if the response is the final response of the
+    /// chain.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the redirect has an invalid `Location`.
+    fn redirect_target(
+        &self,
+        current: &Url,
+        status: reqwest::StatusCode,
+        headers: &HeaderMap,
+    ) -> Result<Option<Url>> {
+        let location = match headers.get(reqwest::header::LOCATION) {
+            Some(location) if status.is_redirection() => location,
+            _ => return Ok(None),
+        };
+
+        // Leave redirects to other hosts out of the scope of the crawl unfollowed unless the
+        // configuration allows them
+        let next = current.join(location.to_str()?)?;
+        let host = next.host_str().unwrap_or_default();
+        if !self.config.follow_cross_host_redirects
+            && next.host_str() != current.host_str()
+            && !self.in_scope(host)
+        {
+            return Ok(None);
+        }
+        return Ok(Some(next));
+    }
+
+    /// Follows a redirect to the URL found by `redirect_target`, unless robots.txt disallows it,
+    /// checking the chain for loops.
+    ///
+    /// ## Arguments
+    ///
+    /// * `chain` - A mutable reference to the `RedirectChain` followed so far.
+    /// * `current` - A reference to the URL the response came from.
+    /// * `status` - The status code of the response.
+    /// * `next` - The URL the redirect leads to.
+    /// * `allowed` - Whether robots.txt allows the next URL, see `redirect_allowed`.
+    /// * `redirects` - A mutable reference to the URLs redirected through so far, which the
+    ///   current URL is added to if the redirect is followed.
+    ///
+    /// ## Returns
+    ///
+    /// The next URL to request, or `None` if the response is the final response of the chain.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the chain loops or is longer than `max_redirects`.
+    fn next_redirect(
+        &self,
+        chain: &mut RedirectChain,
+        current: &Url,
+        status: reqwest::StatusCode,
+        next: Url,
+        allowed: bool,
+        redirects: &mut Vec<(String, u16)>,
+    ) -> Result<Option<Url>> {
+        if !allowed {
+            trace!(
+                event = "blocked_by_robots", url = next.as_str();
+                "Not following redirect disallowed by robots.txt: {} -> {}", current, next
+            );
+            return Ok(None);
+        }
+        redirects.push((current.to_string(), status.as_u16()));
+
+        // Check the next URL for loops
+        trace!("Following redirect: {} -> {}", current, next);
+        match chain.visit(&next) {
+            Some(RedirectLoop::SchemeFlip) => {
+                let host = next.host_str().unwrap_or_default().to_string();
+                if self.broken_tls_hosts.lock().unwrap().insert(host.clone()) {
+                    error!(
+                        "Host {} redirects back and forth between http and https, suspending it",
+                        display_host(&host)
+                    );
+                    if let Err(e) = self.storage.mark_broken_tls_redirect(&host) {
+                        warn!("Failed to record the broken redirects of {}: {:#}", host, e);
+                    }
+                }
+                return Err(CategorizedError::error(
+                    ErrorCategory::Redirect,
+                    format!("Redirects flip between http and https at {}", next),
+                ));
+            }
+            Some(RedirectLoop::Cycle) => {
+                return Err(CategorizedError::error(
+                    ErrorCategory::Redirect,
+                    format!("Redirect loop at {}", next),
+                ));
+            }
+            None if chain.redirects() > self.config.max_redirects => {
+                return Err(CategorizedError::error(
+                    ErrorCategory::Redirect,
+                    format!("More than {} redirects", self.config.max_redirects),
+                ));
+            }
+            None => return Ok(Some(next)),
+        }
+    }
+
+    /// Records whether a response from a host was a bot-detection challenge, suspending the host
+    /// once it has served `max_consecutive_challenges` challenges in a row.
+    ///
+    /// ## Arguments
+    ///
+    /// * `host` - A string slice that holds the host the response came from.
+    /// * `bot_challenged` - Whether the response was a challenge.
+    /// * `url` - A string slice that holds the URL of the response, used for logging.
+    fn record_challenge(&self, host: &str, bot_challenged: bool, url: &str) {
+        let mut challenges = self.consecutive_challenges.lock().unwrap();
+        if !bot_challenged {
+            challenges.remove(host);
+            return;
+        }
+
+        warn!("Bot-detection challenge served for URL: {}", url);
+        let count = challenges.entry(host.to_string()).or_insert(0);
+        *count += 1;
+        if *count >= self.config.max_consecutive_challenges
+            && self
+                .suspended_hosts
+                .lock()
+                .unwrap()
+                .insert(host.to_string())
+        {
+            error!(
+                "Suspending host {} after {} consecutive bot-detection challenges",
+                display_host(host),
+                count
+            );
+        }
+    }
+
+    /// Checks whether the host of a URL has been suspended, for serving bot-detection challenges
+    /// or for redirecting back and forth between `http` and `https`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to check.
+    fn is_suspended(&self, url: &str) -> bool {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        return self.suspended_hosts.lock().unwrap().contains(&host)
+            || self.broken_tls_hosts.lock().unwrap().contains(&host);
+    }
+
+    /// Checks the total size of the headers of a response, the sum of every header name and
+    /// value, against `max_header_bytes`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `headers` - A reference to the headers of the response.
+    /// * `url` - A string slice that holds the URL of the response, used for logging.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether the headers are over the limit, in which case the body of
+    /// the response is left unread.
+    fn check_header_size(&self, headers: &HeaderMap, url: &str) -> bool {
+        let header_bytes = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum::<usize>();
+        if header_bytes <= self.config.max_header_bytes {
+            return false;
+        }
+        warn!(
+            "Oversized headers for URL: {}: {} bytes (limit {}), not reading its body",
+            url, header_bytes, self.config.max_header_bytes
+        );
+        return true;
+    }
+
+    /// Counts the cookies a response sets towards the total of its host over the whole crawl.
+    ///
+    /// This is only a counter, kept whether or not the cookie store is enabled, which flags the
+    /// responses of hosts that set more than `max_cookies_per_host` cookies. The cookies
+    /// themselves are skipped past that cap by the `CookieJar` of the crawl with `cookies = true`,
+    /// and never kept without it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `host` - A string slice that holds the host the response came from.
+    /// * `headers` - A reference to the headers of the response.
+    /// * `url` - A string slice that holds the URL of the response, used for logging.
+    ///
+    /// ## Returns
+    ///
+    /// A tuple of whether the host went over `max_cookies_per_host` with this response, and the
+    /// number of cookies it set.
+    fn count_cookies(&self, host: &str, headers: &HeaderMap, url: &str) -> (bool, u64) {
+        let cookies_set = headers.get_all(reqwest::header::SET_COOKIE).iter().count() as u64;
+        if cookies_set == 0 {
+            return (false, 0);
+        }
+
+        let mut cookie_counts = self.cookie_counts.lock().unwrap();
+        let counted = cookie_counts.entry(host.to_string()).or_insert(0);
+        let remaining = self.config.max_cookies_per_host.saturating_sub(*counted);
+        *counted += cookies_set.min(remaining);
+        if cookies_set <= remaining {
+            return (false, cookies_set);
+        }
+        warn!(
+            "Too many cookies from host: {}: {} of {} cookies set by {} are over the limit",
+            host,
+            cookies_set - remaining,
+            cookies_set,
+            url
+        );
+        return (true, cookies_set);
+    }
+
+    /// Extracts and normalizes all the links from the given HTML document, along with their
+    /// anchor text.
+    ///
+    /// This function finds all anchor (`<a>`) tags in the parsed HTML, and extracts their `href` attributes.
+    /// Each `href` is resolved against the base URL of the page, which is the `href` of its first
+    /// `<base>` element (itself resolved against the page URL) or else the page URL, and stripped
+    /// of its fragment. The URLs are then normalized using the `normalize_url` function and
+    /// collected into a `HashMap`, and the `href`s that don't parse are reported as fetch errors.
+    /// The anchor text of a URL is the whitespace-collapsed text of the first of its `<a>` tags
+    /// that has any, nested markup included.
+    ///
+    /// With `follow_frames`, the `src` of `<frame>` and `<iframe>` elements is extracted the same
+    /// way, without anchor text, but only for frames of the same origin as the page, so that
+    /// embedded ads and widgets are never followed.
+    ///
+    /// Unless `follow_nofollow` is set, the links marked `rel="nofollow"`, `rel="ugc"` or
+    /// `rel="sponsored"` are skipped, or with `record_nofollow_links` kept apart so that they are
+    /// recorded without being crawled. A URL the page also links to without such a `rel`
+    /// attribute is followed.
+    ///
+    /// With `extract_assets`, the `src` of `<img>` and `<script>` elements, every candidate of
+    /// the `srcset` of `<img>` elements and the `href` of `<link rel="stylesheet">` elements are
+    /// extracted as well, as assets, unless the page also links to them.
+    ///
+    /// With `record_external_links`, the `<a>` links to hosts out of scope are kept apart along
+    /// with their anchor text, so that they are recorded without being crawled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document` to be processed.
+    /// * `page_url` - A string slice that holds the URL the document was fetched from.
+    ///
+    /// ## Returns
+    ///
+    /// The `PageLinks` containing all the normalized links found in the HTML content, along with
+    /// their anchor text, which is empty for links without any text such as image-only links,
+    /// and the normalized URLs of the assets.
+    fn get_links(&self, document: &Document, page_url: &str) -> PageLinks {
+        trace!("Extracting links from HTML content");
+        let mut links: HashMap<String, String> = HashMap::new();
+        let mut nofollow: HashMap<String, String> = HashMap::new();
+
+        // Find the base URL of the page, honoring only the first `<base href>` element
+        let parsed_page_url = match Url::parse(page_url) {
+            Ok(parsed_page_url) => parsed_page_url,
+            Err(_) => {
+                return PageLinks {
+                    links,
+                    nofollow,
+                    assets: HashSet::new(),
+                    external: HashMap::new(),
+                }
+            }
+        };
+        let page_origin = parsed_page_url.origin();
+        let base_url = Self::base_url(document, parsed_page_url);
+
+        // Report the links that don't parse, rather than dropping them silently
+        let resolve = |href: &str| match base_url.join(href.trim()) {
+            Ok(mut url) => {
+                url.set_fragment(None);
+                Some(url)
+            }
+            Err(e) => {
+                self.record_malformed_link(page_url, href.trim(), e);
+                None
+            }
+        };
+
+        let mut external: HashMap<String, String> = HashMap::new();
+        for anchor in document.find(Name("a")) {
+            // Skip the links the page asks not to follow, such as those posted by its users
+            let skipped = !self.config.follow_nofollow && Self::is_nofollow(&anchor);
+            if skipped && !self.config.record_nofollow_links {
+                continue;
+            }
+            let Some(resolved) = anchor.attr("href").and_then(resolve) else {
+                continue;
+            };
+
+            // Record the links to other sites without following them
+            let (url, targets) = match self.normalize_url(resolved.as_str()) {
+                Some(url) if skipped => (url, &mut nofollow),
+                Some(url) => (url, &mut links),
+                None => match self.external_url(&resolved) {
+                    Some(url) => (url, &mut external),
+                    None => continue,
+                },
+            };
+
+            // Keep the first text found for the URL, as later links to it are often icons
+            let anchor_text = targets.entry(url).or_default();
+            if anchor_text.is_empty() {
+                *anchor_text = anchor
+                    .text()
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+            }
+        }
+
+        // Follow the frames of the page, whose documents are part of it
+        if self.config.follow_frames {
+            for frame in document.find(Name("frame").or(Name("iframe"))) {
+                let Some(url) = frame
+                    .attr("src")
+                    .and_then(resolve)
+                    .filter(|url| url.origin() == page_origin)
+                    .and_then(|url| self.normalize_url(url.as_str()))
+                else {
+                    continue;
+                };
+                links.entry(url).or_default();
+            }
+        }
+
+        // Extract the images, scripts and stylesheets of the page, to check that they load
+        let mut assets = HashSet::new();
+        if self.config.extract_assets {
+            let mut hrefs: Vec<&str> = Vec::new();
+            for image in document.find(Name("img")) {
+                hrefs.extend(image.attr("src"));
+                if let Some(srcset) = image.attr("srcset") {
+                    // Every candidate is a URL followed by an optional width or density
+                    hrefs.extend(
+                        srcset
+                            .split(',')
+                            .filter_map(|candidate| candidate.split_whitespace().next()),
+                    );
+                }
+            }
+            hrefs.extend(document.find(Name("script")).filter_map(|n| n.attr("src")));
+            hrefs.extend(
+                document
+                    .find(Name("link"))
+                    .filter(|n| {
+                        n.attr("rel").is_some_and(|rel| {
+                            rel.split_whitespace()
+                                .any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+                        })
+                    })
+                    .filter_map(|n| n.attr("href")),
+            );
+
+            for href in hrefs {
+                if let Some(url) = resolve(href).and_then(|url| self.normalize_url(url.as_str())) {
+                    if !links.contains_key(&url) {
+                        assets.insert(url);
+                    }
+                }
+            }
+        }
+        nofollow.retain(|url, _| !links.contains_key(url));
+        return PageLinks {
+            links,
+            nofollow,
+            assets,
+            external,
+        };
+    }
+
+    /// Finds the URL the links of a page are relative to, honoring only the first `<base href>`
+    /// element.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document`.
+    /// * `page_url` - The URL the document was fetched from.
+    fn base_url(document: &Document, page_url: Url) -> Url {
+        return document
+            .find(Name("base"))
+            .find_map(|n| n.attr("href"))
+            .and_then(|href| page_url.join(href.trim()).ok())
+            .unwrap_or(page_url);
+    }
+
+    /// Gets the URL of an external link, one to an `http(s)` host out of scope, if external
+    /// links are recorded. Its host is in its ASCII form, as in the normalized URLs of the crawl.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A reference to the resolved URL of the link, which `normalize_url` rejected.
+    ///
+    /// ## Returns
+    ///
+    /// An `Option<String>` containing the URL if it is an external link that is recorded,
+    /// otherwise `None`.
+    fn external_url(&self, url: &Url) -> Option<String> {
+        if !self.config.record_external_links || !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        return url
+            .host_str()
+            .filter(|host| !self.in_scope(host))
+            .map(|_| url.to_string());
+    }
+
+    /// Checks whether an `<a>` element asks not to be followed, with a `rel` attribute holding
+    /// `nofollow`, `ugc` or `sponsored` among its space-separated values.
+    ///
+    /// ## Arguments
+    ///
+    /// * `anchor` - The `<a>` element.
+    fn is_nofollow(anchor: &Node) -> bool {
+        return anchor.attr("rel").is_some_and(|rel| {
+            rel.split_whitespace().any(|rel| {
+                NOFOLLOW_RELS
+                    .iter()
+                    .any(|value| rel.eq_ignore_ascii_case(value))
+            })
+        });
+    }
+
+    /// Reads the robots directives of the `<meta name="robots">` tags of a document, along with
+    /// any `<meta>` tags addressed to Rustle specifically.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document`.
+    ///
+    /// ## Returns
+    ///
+    /// The combined `RobotsDirectives` of every matching `<meta>` tag.
+    fn get_meta_robots(document: &Document) -> RobotsDirectives {
+        return document
+            .find(Name("meta"))
+            .filter(|n| {
+                n.attr("name").is_some_and(|name| {
+                    name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(USER_AGENT)
+                })
+            })
+            .filter_map(|n| n.attr("content"))
+            .map(RobotsDirectives::parse)
+            .fold(RobotsDirectives::default(), RobotsDirectives::merge);
+    }
+
+    /// Reads the title and description of a document.
+    ///
+    /// Both are whitespace-normalized, and empty ones are treated as missing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document`.
+    ///
+    /// ## Returns
+    ///
+    /// The `PageMetadata` of the document.
+    fn get_metadata(document: &Document) -> PageMetadata {
+        let normalize = |text: &str| {
+            let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+            return if text.is_empty() { None } else { Some(text) };
+        };
+
+        let title = document
+            .find(Name("title"))
+            .next()
+            .and_then(|n| normalize(&n.text()));
+        let description = document
+            .find(Name("meta"))
+            .find(|n| {
+                n.attr("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("description"))
+            })
+            .and_then(|n| n.attr("content"))
+            .and_then(normalize);
+
+        return PageMetadata {
+            title,
+            description,
+            text: None,
+            anchor_texts: HashMap::new(),
+            canonical_url: None,
+            nofollow_links: HashSet::new(),
+            assets: HashSet::new(),
+            external_links: HashSet::new(),
+            js_dependency_score: 0.0,
+        };
+    }
+
+    /// Reads the canonical URL a document declares with `<link rel="canonical">`, resolved
+    /// against the URL of the page and without its fragment.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document`.
+    /// * `page_url` - A string slice that holds the URL of the page.
+    ///
+    /// ## Returns
+    ///
+    /// The canonical URL of the document, or `None` if it declares none that parses.
+    fn get_canonical(document: &Document, page_url: &str) -> Option<String> {
+        let href = document
+            .find(Name("link"))
+            .find(|n| {
+                n.attr("rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("canonical"))
+                })
+            })
+            .and_then(|n| n.attr("href"))?;
+        let mut url = Url::parse(page_url).ok()?.join(href.trim()).ok()?;
+        url.set_fragment(None);
+        return Some(url.to_string());
+    }
+
+    /// Reads the URLs of the RSS and Atom feeds a document announces with
+    /// `<link rel="alternate">`, resolved against the URL of the page.
+    ///
+    /// ## Arguments
+    ///
+    /// * `document` - A reference to the parsed HTML `Document`.
+    /// * `page_url` - A string slice that holds the URL of the page.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` containing the URLs of the feeds, in document order.
+    fn get_feeds(document: &Document, page_url: &str) -> Vec<String> {
+        let Ok(page_url) = Url::parse(page_url) else {
+            return Vec::new();
+        };
+        return document
+            .find(Name("link"))
+            .filter(|n| {
+                let alternate = n.attr("rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+                });
+                let feed_type = n.attr("type").is_some_and(|media_type| {
+                    feed::FEED_TYPES
+                        .iter()
+                        .any(|feed_type| media_type.trim().eq_ignore_ascii_case(feed_type))
+                });
+                return alternate && feed_type;
+            })
+            .filter_map(|n| n.attr("href"))
+            .filter_map(|href| page_url.join(href.trim()).ok())
+            .map(|mut url| {
+                url.set_fragment(None);
+                return url.to_string();
+            })
+            .collect();
+    }
+
+    /// Normalizes a given URL to ensure it is a valid and complete URL.
+    ///
+    /// This function attempts to parse the given URL and checks that its host is in the configured
+    /// `Scope` relative to the origin URL. URLs whose expected `ResourceKind` has the `Skip`
+    /// fetch policy are skipped, and so are URLs without a host or of another scheme than `http`
+    /// and `https`, such as `mailto:`, `tel:` and `javascript:` links. With `normalize_query`, the
+    /// query of the URL is sorted and normalized, and the URLs of crawler traps are rejected last.
+    /// The host of the normalized URL is in its ASCII form, with internationalized domain names
+    /// in punycode.
+    /// If the URL is relative (starts with `//` or `/`), it will be converted to an absolute URL using `ORIGIN_URL`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be normalized.
+    ///
+    /// ## Returns
+    ///
+    /// An `Option<String>` containing the normalized URL if it is valid and in scope, otherwise `None`.
+    fn normalize_url(&self, url: &str) -> Option<String> {
+        trace!("Normalizing URL: {}", url);
+
+        // Skip URLs of obviously binary files without requesting them, unless assets are fetched
+        if self.fetch_policy(url).1 == FetchPolicy::Skip {
+            trace!(
+                "Skipping URL whose kind of resource is not fetched: {}",
+                url
+            );
+            self.skipped_by_extension.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+
+        // Parse the Url with the `Url` crate
+        let parsed_url = Url::parse(url);
+        match parsed_url {
+            // If the parsed Url is a valid Url
+            Ok(parsed_url) => {
+                // Skip URLs that can't be fetched, as they have no host or aren't web pages
+                let host = match parsed_url.host_str() {
+                    Some(host) if matches!(parsed_url.scheme(), "http" | "https") => host,
+                    _ => {
+                        trace!("Skipping URL without an http(s) host: {}", url);
+                        return None;
+                    }
+                };
+
+                // If its host is in scope of the origin url, return it, else, skip it
+                if !self.in_scope(host) {
+                    return None;
+                }
+
+                // Normalize the query, so that URLs differing only in their query are crawled once,
+                // and write URLs with Unicode hosts as parsed, with their hosts in punycode
+                let mut parsed_url = parsed_url;
+                if self.config.normalize_query {
+                    normalize_query(&mut parsed_url);
+                }
+                let url = if self.config.normalize_query || !url.is_ascii() {
+                    parsed_url.as_str()
+                } else {
+                    url
+                };
+
+                // Skip the URLs of crawler traps, whose links lead into endless URL spaces
+                if let Some(rule) = TrapRule::check(&parsed_url, &self.config) {
+                    trace!("Skipping URL rejected by the {} trap rule: {}", rule, url);
+                    self.trap_rejections
+                        .lock()
+                        .unwrap()
+                        .insert(url.to_string(), rule);
+                    return None;
+                }
+                return Some(url.to_string());
+            }
+            // If the parsed Url is not a valid Url
+            Err(_e) => {
+                // If the Url starts with "//" (relative top level Url), normalize it with https
+                // If the Url starts with "/" (relative path Url), normalize it with the origin url
+                // Else, skip the Url
+                if url.starts_with("//") {
+                    return self.normalize_url(&format!("https:{}", url));
+                } else if let (true, Some(origin_url)) =
+                    (url.starts_with('/'), self.config.origin_urls.first())
+                {
+                    let joined = Url::parse(origin_url).and_then(|origin| origin.join(url));
+                    return joined
+                        .ok()
+                        .and_then(|joined| self.normalize_url(joined.as_str()));
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Fetches the HTML content of the given URL and extracts all the links from it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `batch` - The worker batch fetching the URL.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing all the links extracted from the HTML content of the given URL.
+    fn fetch_and_process_links(&self, url: &str, batch: Batch) -> HashSet<String> {
+        trace!("Fetching and processing links for URL: {}", url);
+
+        let (kind, method, stored) = self.plan_fetch(url, batch.generation);
+
+        // Wait until fewer than `max_concurrent_per_host` requests are in flight to the host
+        let permit = self.host_limiter.acquire(&Self::host_of(url));
+        if self.validates(batch.generation) {
+            let page = self.validate_link(url);
+            drop(permit);
+            return self.process_page(url, kind, &method, stored, page, batch);
+        }
+        let mut page = self.get_html(url, method.clone(), stored.as_ref());
+
+        // Fetch resources again in full if they turn out to be HTML after all
+        if method == reqwest::Method::HEAD && page.as_ref().is_ok_and(|page| page.is_html) {
+            self.record_kind_correction(url, kind, page.as_ref().ok());
+            page = self.get_html(url, reqwest::Method::GET, stored.as_ref());
+        }
+        drop(permit);
+
+        return self.store_page(url, kind, &method, stored, page, batch);
+    }
+
+    /// Stores a fetched page with `process_page`, and records it as the referrer of the links it
+    /// is the first to be found linking to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the fetched URL.
+    /// * `kind` - The expected kind of the fetched resource.
+    /// * `method` - A reference to the method of the last request.
+    /// * `stored` - The stored copy of the URL from before the fetch, if there is one.
+    /// * `page` - The fetched page, or the reason the fetch failed.
+    /// * `batch` - The worker batch that fetched the URL.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing the links of the page that are followed.
+    fn store_page(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+        method: &reqwest::Method,
+        stored: Option<Site>,
+        page: Result<FetchedPage>,
+        batch: Batch,
+    ) -> HashSet<String> {
+        let links = self.process_page(url, kind, method, stored, page, batch);
+        self.record_referrers(url, &links, batch.generation);
+        return links;
+    }
+
+    /// Fetches the HTML content of the given URL with the async reqwest client and extracts all
+    /// the links from it, like `fetch_and_process_links`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `reqwest_client` - A reference to the async reqwest client used to make the HTTP request.
+    /// * `blocking` - A reference to the `BlockingPool` the page is planned and stored on.
+    /// * `batch` - The worker batch fetching the URL.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing all the links extracted from the HTML content of the given URL.
+    async fn fetch_and_process_links_async<'s>(
+        &'s self,
+        url: &str,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        batch: Batch,
+    ) -> HashSet<String> {
+        trace!("Fetching and processing links for URL: {}", url);
+
+        let owned_url = url.to_string();
+        let (kind, method, stored) = blocking
+            .run(move || self.plan_fetch(&owned_url, batch.generation))
+            .await;
+
+        // Wait until fewer than `max_concurrent_per_host` requests are in flight to the host
+        let permit = self.host_limiter.acquire_async(&Self::host_of(url)).await;
+        let owned_url = url.to_string();
+        if self.validates(batch.generation) {
+            let page = self
+                .validate_link_async(reqwest_client, blocking, url)
+                .await;
+            drop(permit);
+            return blocking
+                .run(move || self.process_page(&owned_url, kind, &method, stored, page, batch))
+                .await;
+        }
+        let mut page = self
+            .get_html_async(
+                reqwest_client,
+                blocking,
+                url,
+                method.clone(),
+                stored.as_ref(),
+            )
+            .await;
+
+        // Fetch resources again in full if they turn out to be HTML after all
+        if method == reqwest::Method::HEAD && page.as_ref().is_ok_and(|page| page.is_html) {
+            self.record_kind_correction(url, kind, page.as_ref().ok());
+            page = self
+                .get_html_async(
+                    reqwest_client,
+                    blocking,
+                    url,
+                    reqwest::Method::GET,
+                    stored.as_ref(),
+                )
+                .await;
+        }
+        drop(permit);
+
+        return blocking
+            .run(move || self.store_page(&owned_url, kind, &method, stored, page, batch))
+            .await;
+    }
+
+    /// Records a page as the referrer of the links it is the first to be found linking to, so
+    /// that they are stored along with the page they were discovered on. Links found at the last
+    /// depth level aren't crawled, and aren't recorded.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the page.
+    /// * `links` - A reference to the links of the page that are followed.
+    /// * `generation` - The generation of the page, counting the origin as generation 0.
+    fn record_referrers(&self, url: &str, links: &HashSet<String>, generation: u64) {
+        if generation >= self.deepest_depth() {
+            return;
+        }
+        let mut referrers = self.referrers.lock().unwrap();
+        for link in links {
+            referrers
+                .entry(link.clone())
+                .or_insert_with(|| url.to_string());
+        }
+    }
+
+    /// Gets the host of a URL, which is empty if it doesn't parse or has none.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    fn host_of(url: &str) -> String {
+        return Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+    }
+
+    /// Decides how a URL is fetched, only requesting the headers of the kinds of resources whose
+    /// fetch policy says so, or of the URLs that are only validated, and reads its stored copy.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be fetched.
+    /// * `generation` - The generation of the URL, counting the origin as generation 0.
+    ///
+    /// ## Returns
+    ///
+    /// The expected kind of the resource, the method of the request, and the stored copy of the
+    /// URL if there is one.
+    fn plan_fetch(
+        &self,
+        url: &str,
+        generation: u64,
+    ) -> (ResourceKind, reqwest::Method, Option<Site>) {
+        let (kind, policy) = self.fetch_policy(url);
+        let method = match policy {
+            _ if self.validates(generation) => reqwest::Method::HEAD,
+            FetchPolicy::Head => reqwest::Method::HEAD,
+            _ => reqwest::Method::GET,
+        };
+        let stored = self.storage.get_site(url).unwrap_or_else(|e| {
+            warn!("Failed to read the stored copy of {}: {:#}", url, e);
+            None
+        });
+        return (kind, method, stored);
+    }
+
+    /// Gets the expected kind of resource of a URL along with its fetch policy.
+    ///
+    /// URLs extracted as assets are assets whatever their extension, and with `extract_assets`,
+    /// assets that would be skipped are requested with `HEAD` instead.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    ///
+    /// ## Returns
+    ///
+    /// The expected `ResourceKind` of the URL and the `FetchPolicy` it is fetched with.
+    fn fetch_policy(&self, url: &str) -> (ResourceKind, FetchPolicy) {
+        let kind = if self.asset_urls.lock().unwrap().contains(url) {
+            ResourceKind::Asset
+        } else {
+            ResourceKind::of(url)
+        };
+        let policy = match self.config.fetch_policy.of(kind) {
+            FetchPolicy::Skip if kind == ResourceKind::Asset && self.config.extract_assets => {
+                FetchPolicy::Head
+            }
+            policy => policy,
+        };
+        return (kind, policy);
+    }
+
+    /// Counts a HEAD request whose response turned out to be HTML, before it is fetched again in
+    /// full.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the requested URL.
+    /// * `kind` - The expected kind of the requested resource.
+    /// * `page` - The page fetched with the HEAD request.
+    fn record_kind_correction(&self, url: &str, kind: ResourceKind, page: Option<&FetchedPage>) {
+        trace!("Fetching {} again in full, as it is HTML", url);
+        self.record_request(kind, page);
+        self.kind_corrections.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Records the outcome of a fetch, stores the fetched page and extracts its links.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the fetched URL.
+    /// * `kind` - The expected kind of the fetched resource.
+    /// * `method` - A reference to the method of the last request.
+    /// * `stored` - The stored copy of the URL from before the fetch, if there is one.
+    /// * `page` - The fetched page, or the reason the fetch failed.
+    /// * `batch` - The worker batch that fetched the URL.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing the links of the page that are followed.
+    fn process_page(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+        method: &reqwest::Method,
+        stored: Option<Site>,
+        page: Result<FetchedPage>,
+        batch: Batch,
+    ) -> HashSet<String> {
+        self.record_request(kind, page.as_ref().ok());
+        self.record_generation(batch.generation, page.as_ref().ok());
+
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                if self.record_fetch_error(url, &e, Some(batch)).is_transient() {
+                    self.transient_failures
+                        .lock()
+                        .unwrap()
+                        .push((url.to_string(), batch));
+                }
+                return HashSet::new();
+            }
+        };
+        debug!(
+            event = "fetch_done", url = url, status = page.status_code,
+            latency_ms = page.response_ms;
+            "Fetched {} with status {} in {} ms", url, page.status_code, page.response_ms
+        );
+        self.pages_succeeded.fetch_add(1, Ordering::SeqCst);
+        if page.status_code >= 500 {
+            self.transient_failures
+                .lock()
+                .unwrap()
+                .push((url.to_string(), batch));
+        }
+        self.write_redirects(&page, batch);
+
+        // Record redirects out of scope or to disallowed URLs without following them
+        if let Some(target) = &page.blocked_redirect {
+            info!(
+                "Skipping URL whose redirect isn't followed: {} -> {}",
+                url, target
+            );
+            self.blocked_redirects
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), target.clone());
+            let site = Site {
+                url: self
+                    .normalize_url(&page.final_url)
+                    .unwrap_or_else(|| page.final_url.clone()),
+                crawl_time: Utc::now(),
+                links_to: HashSet::from([target.clone()]),
+                status_code: Some(page.status_code),
+                final_url: Some(page.final_url.clone()),
+                is_redirect: true,
+                redirect_hops: Some(0),
+                generation: Some(batch.generation),
+                batch_id: Some(batch.id),
+                ..Default::default()
+            };
+            self.store_site(site);
+            return HashSet::new();
+        }
+
+        // Keep the stored copy of pages that weren't modified, only bumping their crawl time
+        let not_modified = page.status_code == reqwest::StatusCode::NOT_MODIFIED.as_u16();
+        if let (true, Some(stored)) = (not_modified, &stored) {
+            trace!("Keeping stored copy of unmodified URL: {}", url);
+            self.pages_not_modified.fetch_add(1, Ordering::SeqCst);
+            self.pages_unchanged.fetch_add(1, Ordering::SeqCst);
+            let (robots_snapshot, robots_rule) = match self.robots_decision(url) {
+                Ok(decision) => decision,
+                Err(e) => {
+                    self.record_fetch_error(url, &e, Some(batch));
+                    return HashSet::new();
+                }
+            };
+            let touched = Site {
+                url: url.to_string(),
+                crawl_time: Utc::now(),
+                generation: Some(batch.generation),
+                batch_id: Some(batch.id),
+                depth: Some(batch.generation),
+                discovered_from: self.referrers.lock().unwrap().remove(url),
+                robots_snapshot,
+                robots_rule,
+                run_id: *self.run_id.lock().unwrap(),
+                ..Default::default()
+            };
+            if let Err(e) = self.storage.touch_site(&touched) {
+                warn!("Failed to update the crawl time of {}: {:#}", url, e);
+            } else {
+                self.forget_failures(&[url]);
+            }
+            let links = stored.followed_links();
+            self.asset_urls
+                .lock()
+                .unwrap()
+                .extend(stored.assets.iter().cloned());
+            self.record_external_urls(&stored.external_links);
+            return links;
+        }
+
+        // Follow the entries of feeds fetched in full as the links of the feed
+        if page.is_feed && page.received_bytes > 0 {
+            let entries = self.get_feed_entries(&page);
+            trace!("Found {} entries in feed {}", entries.len(), url);
+            Self::write_site(
+                self,
+                url,
+                &entries,
+                &page,
+                stored.as_ref(),
+                &PageMetadata::default(),
+                batch,
+            );
+            return entries;
+        }
+
+        // Record responses that aren't HTML, that were only validated, or whose body was left
+        // unread for their oversized headers, without parsing them for links
+        if !page.is_html || self.validates(batch.generation) || page.oversized_headers {
+            if method == reqwest::Method::GET && !page.is_html {
+                self.skipped_by_type.fetch_add(1, Ordering::SeqCst);
+            }
+            Self::write_site(
+                self,
+                url,
+                &HashSet::new(),
+                &page,
+                stored.as_ref(),
+                &PageMetadata::default(),
+                batch,
+            );
+            return HashSet::new();
+        }
+
+        // Parse the HTML, and combine the robots directives of its meta tags and headers
+        let document = Document::from(page.html.as_str());
+        let directives = page
+            .robots_directives
+            .merge(Self::get_meta_robots(&document));
+
+        // Extract links from the HTML along with their anchor text, and its assets, unless it is
+        // a bot-detection challenge whose links are junk
+        let page_links = if page.bot_challenged {
+            PageLinks {
+                links: HashMap::new(),
+                nofollow: HashMap::new(),
+                assets: HashSet::new(),
+                external: HashMap::new(),
+            }
+        } else {
+            Self::get_links(self, &document, &page.final_url)
+        };
+        let assets = page_links.assets;
+        let mut links: HashSet<String> = page_links.links.keys().cloned().collect();
+
+        // Run the custom page handlers, then check the assets along with the links
+        self.run_handlers(url, &page, &links);
+        links.extend(assets.iter().cloned());
+        self.asset_urls
+            .lock()
+            .unwrap()
+            .extend(assets.iter().cloned());
+
+        // Read the title and description of the page, and its visible text if it is stored
+        let mut metadata = Self::get_metadata(&document);
+        if self.store_text.load(Ordering::SeqCst) {
+            metadata.text = Some(render::visible_text(&document));
+        }
+        metadata.nofollow_links = page_links.nofollow.keys().cloned().collect();
+        metadata.external_links = page_links.external.keys().cloned().collect();
+        metadata.anchor_texts = page_links.links;
+        metadata.anchor_texts.extend(page_links.nofollow);
+        metadata.anchor_texts.extend(page_links.external);
+        metadata.assets = assets;
+        self.record_external_urls(&metadata.external_links);
+        if !page.bot_challenged {
+            metadata.canonical_url = Self::get_canonical(&document, &page.final_url);
+            self.record_feeds(&page.final_url, Self::get_feeds(&document, &page.final_url));
+        }
+
+        // Estimate how much of the page is rendered by JavaScript, out of sight of the crawl
+        metadata.js_dependency_score =
+            render::js_dependency_score(&document, &self.config.js_weights);
+
+        // Write Url to Database, unless the page asks not to be indexed
+        if directives.noindex {
+            trace!("Not storing noindex URL: {}", url);
+            if let Err(e) = self.storage.delete_text(&[url]) {
+                error!(
+                    "Failed to delete the stored text of noindex URL {}: {:#}",
+                    url, e
+                );
+            }
+        } else {
+            Self::write_site(self, url, &links, &page, stored.as_ref(), &metadata, batch);
+        }
+
+        trace!("Scraped {} - {} Links", url, links.len());
+
+        // Don't follow the links of the page if it asks not to be followed
+        if directives.nofollow {
+            trace!("Not following links of nofollow URL: {}", url);
+            return HashSet::new();
+        }
+
+        return links;
+    }
+
+    /// Reads the entries of a feed fetched in full, resolved against its URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A reference to the fetched feed.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing the normalized URLs of the entries of the feed.
+    fn get_feed_entries(&self, page: &FetchedPage) -> HashSet<String> {
+        let Ok(base_url) = Url::parse(&page.final_url) else {
+            return HashSet::new();
+        };
+        return parse_feed(&page.html)
+            .into_iter()
+            .filter_map(|entry| base_url.join(&entry).ok())
+            .filter_map(|entry| self.normalize_url(entry.as_str()))
+            .collect();
+    }
+
+    /// Runs every page handler on a fetched HTML page, logging and skipping those that panic.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL the page was requested from.
+    /// * `page` - A reference to the fetched page.
+    /// * `links` - A reference to the links extracted from the page.
+    fn run_handlers(&self, url: &str, page: &FetchedPage, links: &HashSet<String>) {
+        if self.handlers.is_empty() {
+            return;
+        }
+        let Ok(parsed_url) = Url::parse(url) else {
+            return;
+        };
+
+        for (index, handler) in self.handlers.iter().enumerate() {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                handler.on_page(&parsed_url, page.status_code, &page.html, links)
+            }));
+            if result.is_err() {
+                error!("Page handler {} panicked on {}, skipping it", index, url);
+            }
+        }
+    }
+
+    /// Counts a request in the statistics of its expected kind of resource.
+    ///
+    /// ## Arguments
+    ///
+    /// * `kind` - The expected kind of the requested resource.
+    /// * `page` - The fetched page, or `None` if the request failed.
+    fn record_request(&self, kind: ResourceKind, page: Option<&FetchedPage>) {
+        let mut kind_stats = self.kind_stats.lock().unwrap();
+        let entry = kind_stats.entry(kind).or_default();
+        entry.0 += 1;
+        entry.1 += page.map(|page| page.received_bytes).unwrap_or_default();
+    }
+
+    /// Records the response time of a request sent to fetch a page, and counts it for its host.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A reference to the requested URL.
+    /// * `latency` - The time from sending the request to receiving its response headers, or
+    ///   to its failure.
+    fn record_latency(&self, url: &Url, latency: Duration) {
+        self.latencies.lock().unwrap().push(latency);
+        *self
+            .host_requests
+            .lock()
+            .unwrap()
+            .entry(url.host_str().unwrap_or_default().to_string())
+            .or_default() += 1;
+    }
+
+    /// Finds the category of the reason a fetch failed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `error` - A reference to the error the fetch failed with.
+    ///
+    /// ## Returns
+    ///
+    /// The `ErrorCategory` of the first cause of the error that has one, or
+    /// `ErrorCategory::Other`.
+    fn categorize(error: &anyhow::Error) -> ErrorCategory {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<CategorizedError>() {
+                return e.category;
+            }
+            if proxy::is_auth_failure(&cause.to_string()) {
+                return ErrorCategory::ProxyAuth;
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return ErrorCategory::Timeout;
+                }
+                if e.is_connect() {
+                    return ErrorCategory::Connection;
+                }
+                if e.is_builder() {
+                    return ErrorCategory::InvalidUrl;
+                }
+            }
+            if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+                match e.kind() {
+                    std::io::ErrorKind::TimedOut => return ErrorCategory::Timeout,
+                    std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionAborted => {
+                        return ErrorCategory::Connection
+                    }
+                    _ => {}
+                }
+            }
+            if cause.downcast_ref::<url::ParseError>().is_some() {
+                return ErrorCategory::InvalidUrl;
+            }
+        }
+        return ErrorCategory::Other;
+    }
+
+    /// Records a URL that could not be fetched in the fetch errors of the crawl report, and logs
+    /// it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    /// * `error` - A reference to the reason the URL could not be fetched.
+    /// * `batch` - The worker batch that fetched the URL, or `None` if it wasn't fetched by one.
+    ///
+    /// ## Returns
+    ///
+    /// The `ErrorCategory` of the failure.
+    fn record_fetch_error(
+        &self,
+        url: &str,
+        error: &anyhow::Error,
+        batch: Option<Batch>,
+    ) -> ErrorCategory {
+        let reason = format!("{:#}", error);
+        let category = Self::categorize(error);
+        warn!(
+            event = "fetch_failed", url = url, category:% = category, error = reason;
+            "Skipping URL that could not be fetched: {}: {}", url, reason
+        );
+        let failure = FetchFailure {
+            generation: batch.map(|batch| batch.generation),
+            batch_id: batch.map(|batch| batch.id),
+            run_id: *self.run_id.lock().unwrap(),
+            ..FetchFailure::new(url, category, &reason, Utc::now())
+        };
+        if let Err(e) = self.storage.record_fetch_failure(&failure) {
+            error!("Failed to record the failed fetch of {}: {:#}", url, e);
+        }
+        self.fetch_errors.lock().unwrap().push(FetchError {
+            url: url.to_string(),
+            reason,
+            category,
+        });
+        return category;
+    }
+
+    /// Records a link that doesn't parse as a URL in the fetch errors of the crawl report, once
+    /// per link.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page_url` - A string slice that holds the URL of the page the link is on.
+    /// * `href` - A string slice that holds the `href` of the link.
+    /// * `error` - The reason the link doesn't parse.
+    fn record_malformed_link(&self, page_url: &str, href: &str, error: url::ParseError) {
+        let recorded = self
+            .fetch_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|fetch_error| fetch_error.url == href);
+        if !recorded {
+            let error =
+                anyhow::Error::new(error).context(format!("Malformed link on {}", page_url));
+            self.record_fetch_error(href, &error, None);
+        }
+    }
+
+    /// Counts a fetch in the statistics of its generation.
+    ///
+    /// ## Arguments
+    ///
+    /// * `generation` - The generation of the fetched URL.
+    /// * `page` - The fetched page, or `None` if the fetch failed.
+    fn record_generation(&self, generation: u64, page: Option<&FetchedPage>) {
+        let mut generation_stats = self.generation_stats.lock().unwrap();
+        let stats = generation_stats.entry(generation).or_default();
+        match page {
+            Some(page) => {
+                stats.pages += 1;
+                stats.response_ms += page.response_ms;
+            }
+            None => stats.failures += 1,
+        }
+    }
+
+    /// Starts the next worker batch of a generation.
+    ///
+    /// ## Arguments
+    ///
+    /// * `generation` - The generation of the batch.
+    fn next_batch(&self, generation: u64) -> Batch {
+        return Batch {
+            generation,
+            id: self.batch_ids.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+
+    /// Reserves one page of the configured `max_pages` budget.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether another page may be fetched.
+    fn reserve_page(&self) -> bool {
+        let fetched = self.pages_fetched.fetch_add(1, Ordering::SeqCst);
+        return self
+            .config
+            .max_pages
+            .is_none_or(|max_pages| fetched < max_pages);
+    }
+
+    /// Reserves one page of the configured `max_pages_per_domain` budget of the host of a URL,
+    /// or of its registrable domain with the `site` scope, so that the pages of other hosts are
+    /// still fetched once the host has spent its budget.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL about to be fetched.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether another page of the host may be fetched.
+    fn reserve_domain_page(&self, url: &str) -> bool {
+        let Some(max_pages) = self.config.max_pages_per_domain else {
+            return true;
+        };
+        let host = Self::host_of(url);
+        let domain = match self.config.scope {
+            Scope::Site => registrable_domain(&host).unwrap_or(host),
+            _ => host,
+        };
+
+        let mut domain_pages = self.domain_pages.lock().unwrap();
+        let fetched = domain_pages.entry(domain.clone()).or_insert(0);
+        if *fetched >= max_pages {
+            drop(domain_pages);
+            if self
+                .truncated_domains
+                .lock()
+                .unwrap()
+                .insert(domain.clone())
+            {
+                info!(
+                    "Domain {} reached max_pages_per_domain, its other pages are skipped",
+                    display_host(&domain)
+                );
+            }
+            return false;
+        }
+        *fetched += 1;
+        return true;
+    }
+
+    /// Checks the size of the database against the configured `max_disk_mb`.
+    ///
+    /// The database is only measured once every `DISK_CHECK_INTERVAL`, and the last measurement
+    /// is used in between. Past `DISK_WARNING_RATIO` of the limit the crawl stops storing the
+    /// text of pages, and at the limit it stops admitting new URLs for the rest of the crawl.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether another URL may be admitted.
+    fn check_disk(&self) -> bool {
+        if self.truncated_for_disk.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        // Only one worker measures the database at a time, the others use the last measurement
+        if let Ok(mut last_disk_check) = self.last_disk_check.try_lock() {
+            if last_disk_check.is_none_or(|last| last.elapsed() >= DISK_CHECK_INTERVAL) {
+                *last_disk_check = Some(Instant::now());
+                let disk_bytes = self.storage.disk_bytes();
+                trace!("Database takes up {} bytes on disk", disk_bytes);
+                self.disk_bytes.store(disk_bytes, Ordering::SeqCst);
+            }
+        }
+
+        let max_disk_mb = match self.config.max_disk_mb {
+            Some(max_disk_mb) => max_disk_mb,
+            None => return true,
+        };
+        let max_disk_bytes = max_disk_mb * 1024 * 1024;
+        let disk_bytes = self.disk_bytes.load(Ordering::SeqCst);
+        if disk_bytes >= max_disk_bytes {
+            if !self.truncated_for_disk.swap(true, Ordering::SeqCst) {
+                error!(
+                    "Database takes up {} bytes, reaching max_disk_mb = {}, no longer admitting new URLs",
+                    disk_bytes, max_disk_mb
+                );
+            }
+            return false;
+        }
+        if disk_bytes as f64 >= max_disk_bytes as f64 * DISK_WARNING_RATIO
+            && !self.disk_warned.swap(true, Ordering::SeqCst)
+        {
+            warn!(
+                "Database takes up {} bytes, approaching max_disk_mb = {}",
+                disk_bytes, max_disk_mb
+            );
+            if self.store_text.swap(false, Ordering::SeqCst) {
+                warn!("No longer storing the text of pages to save disk space");
+            }
+        }
+        return true;
+    }
+
+    /// Checks if a URL exists in the database and if its crawl_time is newer than the configured
+    /// cache TTL, skips it.
+    ///
+    /// A TTL of zero never skips, and an absent TTL always skips URLs that are already stored.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the site.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether the URL should be skipped.
+    pub(crate) fn should_skip_cached_url(&self, url: &str) -> Result<bool> {
+        if let Some(site) = self.storage.get_site(url)? {
+            // Find the oldest crawl time that is still considered fresh, where `None` means that
+            // every stored site is fresh
+            let fresh_after = match self.config.cache_ttl_hours {
+                Some(0) => return Ok(false),
+                Some(hours) => chrono::Duration::try_hours(hours as i64)
+                    .and_then(|ttl| Utc::now().checked_sub_signed(ttl)),
+                None => None,
+            };
+
+            if fresh_after.is_none_or(|fresh_after| site.crawl_time > fresh_after) {
+                trace!(event = "skipped_cached", url = url; "Skipping cached URL: {}", url);
+                return Ok(true);
+            }
+        }
+
+        return Ok(false);
+    }
+
+    /// Checks if a URL is allowed to be scraped based on the robots.txt rules.
+    ///
+    /// The robots.txt of each domain is loaded and parsed once, and then kept in memory for all
+    /// later checks against that domain, until it is older than `robots_ttl_hours` and loaded
+    /// again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL to be checked.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether the URL is allowed to be scraped.    
+    pub(crate) fn is_allowed_to_scrape(&self, url: &str) -> Result<bool> {
+        let parsed_url = Url::parse(url).with_context(|| format!("Failed to parse URL {}", url))?;
+        let path = parsed_url.path().to_string();
+        let domain = parsed_url
+            .host_str()
+            .with_context(|| format!("URL {} has no host to read robots.txt from", url))?
+            .to_string();
+
+        // Get the cache slot of the domain, so that only one worker loads its robots.txt, and
+        // replace it once the robots.txt it holds is stale
+        let slot = {
+            let mut robots_cache = self.robots_cache.lock().unwrap();
+            let slot = robots_cache.entry(domain.clone()).or_default();
+            if slot
+                .get()
+                .is_some_and(|robots| self.is_robots_stale(robots.checked_at))
+            {
+                *slot = Arc::default();
+            }
+            slot.clone()
+        };
+        let robots = slot.get_or_init(|| self.load_robots(&domain, &parsed_url));
+
+        // Check if the URL is allowed, and remember what allowed it in compliance mode, as the
+        // robots.txt may be refreshed before the page is stored
+        let allowed = robots.allows(&path);
+        if allowed && self.config.compliance {
+            let rule = robots
+                .matching_rule(&path)
+                .unwrap_or_else(|| "no rules".to_string());
+            self.robots_decisions
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), (robots.snapshot, rule));
+        }
+
+        trace!("URL: {} - Allowed? {}", url, allowed);
+
+        // Compare the planned rules of overridden domains with the live ones
+        if self.config.robots_override_diff && self.robots_overrides.contains_key(&domain) {
+            let slot = self
+                .live_robots_cache
+                .lock()
+                .unwrap()
+                .entry(domain.clone())
+                .or_default()
+                .clone();
+            let live = slot.get_or_init(|| {
+                CompiledRobots::parse(
+                    &self
+                        .fetch_robots(&domain, &parsed_url)
+                        .map(|robots| robots.contents)
+                        .unwrap_or_default(),
+                )
+            });
+            let allowed_live = live.allows(&path);
+            if allowed_live != allowed {
+                self.robots_diff.lock().unwrap().push(RobotsDiff {
+                    url: url.to_string(),
+                    allowed_live,
+                });
+            }
+        }
+
+        return Ok(allowed);
+    }
+
+    /// Loads and parses the robots.txt of a domain, from the database if it is stored there and
+    /// fresh, or otherwise by fetching it and saving it to the database. A stale stored copy
+    /// stays in force if it can't be fetched again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `page_url` - A reference to the URL being checked, over whose scheme robots.txt is
+    ///   fetched first.
+    ///
+    /// ## Returns
+    ///
+    /// The `CompiledRobots` of the domain, which allows everything if it has no robots.txt. In
+    /// compliance mode, it refers to the `robots_history` snapshot of its contents.
+    fn load_robots(&self, domain: &str, page_url: &Url) -> CompiledRobots {
+        // Use the robots.txt override of the domain, if there is one
+        if let Some(robots_content) = self.robots_overrides.get(domain) {
+            let robots_file = RobotsFile {
+                contents: robots_content.clone(),
+                url: None,
+            };
+            self.write_domain(domain, &robots_file, sitemap_directives(robots_content));
+            let mut robots = CompiledRobots::parse(robots_content);
+            robots.snapshot = self.record_robots_snapshot(domain, robots_content, Utc::now());
+            return robots;
+        }
+
+        // Check if robots.txt is already in the database, ignoring overrides of earlier crawls
+        let stored = match self.storage.get_domain(domain) {
+            Ok(stored) => stored.filter(|domain_data| domain_data.robots_override.is_none()),
+            Err(e) => {
+                warn!("Failed to read domain {} from the database: {}", domain, e);
+                None
+            }
+        };
+
+        let now = Utc::now();
+        let (robots_txt, fetch_time, checked_at) = match stored {
+            Some(domain_data) if !self.is_robots_stale(domain_data.crawl_time) => (
+                domain_data.robots,
+                domain_data.crawl_time,
+                domain_data.crawl_time,
+            ),
+            stored => {
+                // Fetch robots.txt from the domain
+                if let Some(domain_data) = &stored {
+                    info!(
+                        "Fetching the robots.txt of {} again, stored at {}",
+                        domain, domain_data.crawl_time
+                    );
+                }
+                match (self.get_robots(domain, page_url), stored) {
+                    (Some(robots_file), _) => {
+                        // Save robots.txt to the database, even if the domain has none, so that
+                        // it isn't fetched again
+                        let sitemaps = sitemap_directives(&robots_file.contents);
+                        self.write_domain(domain, &robots_file, sitemaps);
+                        (robots_file.contents, now, now)
+                    }
+                    (None, Some(domain_data)) => {
+                        // Keep obeying the stale copy rather than allowing everything
+                        warn!(
+                            "Failed to fetch the robots.txt of {} again, obeying the copy stored at {}",
+                            domain, domain_data.crawl_time
+                        );
+                        (domain_data.robots, domain_data.crawl_time, now)
+                    }
+                    (None, None) => (String::new(), now, now),
+                }
+            }
+        };
+
+        trace!("Parsed robots.txt for domain: {}", domain);
+        let mut robots = CompiledRobots::parse(&robots_txt);
+        robots.snapshot = self.record_robots_snapshot(domain, &robots_txt, fetch_time);
+        robots.checked_at = checked_at;
+        return robots;
+    }
+
+    /// Checks whether a robots.txt is older than `robots_ttl_hours`, and should be fetched again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `checked_at` - The time the robots.txt was fetched, or a fetch of it last failed.
+    fn is_robots_stale(&self, checked_at: DateTime<Utc>) -> bool {
+        return chrono::Duration::try_hours(self.config.robots_ttl_hours as i64)
+            .and_then(|ttl| Utc::now().checked_sub_signed(ttl))
+            .is_some_and(|fresh_after| checked_at <= fresh_after);
+    }
+
+    /// Records the robots.txt applied to a domain in the `robots_history`, in compliance mode.
+    ///
+    /// ## Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `robots_txt` - A string slice that holds the contents of the applied robots.txt.
+    /// * `fetch_time` - The time the robots.txt was fetched or read.
+    ///
+    /// ## Returns
+    ///
+    /// The id of the snapshot, or `None` outside of compliance mode or if it can't be recorded.
+    fn record_robots_snapshot(
+        &self,
+        domain: &str,
+        robots_txt: &str,
+        fetch_time: DateTime<Utc>,
+    ) -> Option<i64> {
+        if !self.config.compliance {
+            return None;
+        }
+
+        let robots_override = self
+            .config
+            .robots_override
+            .get(domain)
+            .map(|path| path.display().to_string());
+        return self
+            .storage
+            .record_robots_snapshot(domain, robots_txt, fetch_time, robots_override.as_deref())
+            .inspect_err(|e| error!("Failed to record the robots.txt of {}: {:#}", domain, e))
+            .ok();
+    }
+
+    /// Finds the robots.txt snapshot and rule that allowed a URL, in compliance mode.
+    ///
+    /// The decision recorded when the URL was checked is used, so that a robots.txt refreshed
+    /// since doesn't take credit for it. URLs that weren't checked fall back to the robots.txt of
+    /// their domain if it was already loaded.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL that was fetched.
+    ///
+    /// ## Returns
+    ///
+    /// A tuple holding the id of the snapshot, and the deciding rule or `no rules`, which are
+    /// both `None` outside of compliance mode.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if the URL wasn't checked and doesn't parse.
+    fn robots_decision(&self, url: &str) -> Result<(Option<i64>, Option<String>)> {
+        if !self.config.compliance {
+            return Ok((None, None));
+        }
+        if let Some((snapshot, rule)) = self.robots_decisions.lock().unwrap().remove(url) {
+            return Ok((snapshot, Some(rule)));
+        }
+
+        let parsed_url = Url::parse(url).with_context(|| format!("Failed to parse URL {}", url))?;
+        let slot = self
+            .robots_cache
+            .lock()
+            .unwrap()
+            .get(parsed_url.host_str().unwrap_or_default())
+            .cloned();
+        return Ok(match slot.as_ref().and_then(|slot| slot.get()) {
+            Some(robots) => (
+                robots.snapshot,
+                Some(
+                    robots
+                        .matching_rule(parsed_url.path())
+                        .unwrap_or_else(|| "no rules".to_string()),
+                ),
+            ),
+            None => (None, None),
+        });
+    }
+
+    /// Iterates through the given set of origin links, fetching and processing each link to discover new links.
+    ///
+    /// The discovered URLs are queued in a `Frontier`, which hands them out in the order of the
+    /// `strategy` of the crawl, and is shared by every worker thread. Workers take the next URL as
+    /// soon as they are done with the last one, rather than waiting for the rest of its depth level,
+    /// and queue the links of every page they fetch unless they are deeper than `depth`. The process
+    /// continues until there are no new URLs to visit.
+    ///
+    /// ## Arguments
+    ///
+    /// * `origin_links` - A reference to a `HashSet<String>` containing the initial set of URLs to start the iteration.
+    /// * `depth` - The depth of the page the origin links were found on.
+    ///
+    /// ## Returns
+    ///
+    /// The deepest depth level whose links were iterated.
+    fn iterate_links(&self, origin_links: &HashSet<String>, depth: u64) -> u64 {
+        info!(
+            "Starting link iteration with target depth: {} ({} strategy)",
+            self.config.depth, self.config.strategy
+        );
+
+        let mut frontier = self.start_frontier(origin_links, depth);
+        let feed_urls = self.fetch_feed_urls(depth + 1);
+        self.queue_links(&mut frontier, &feed_urls, depth + 1);
+        let frontier = Mutex::new(frontier);
+        let ready = Condvar::new();
+        let max_depth = AtomicU64::new(depth);
+
+        // Every worker thread of the crawl takes URLs until the frontier is done
+        self.pool.broadcast(|_| {
+            self.run_worker(&frontier, &ready, &max_depth);
+        });
+        self.flush_sites();
+
+        return max_depth.into_inner();
+    }
+
+    /// Takes URLs from the frontier of a crawl and fetches them until every URL of the frontier is
+    /// done. A worker with nothing to take waits for the pages being fetched by the others, which
+    /// may queue more URLs, rather than stopping.
+    ///
+    /// ## Arguments
+    ///
+    /// * `frontier` - A reference to the frontier shared by the workers.
+    /// * `ready` - A reference to the `Condvar` notified whenever the frontier changes.
+    /// * `max_depth` - A reference to the deepest depth level taken so far.
+    fn run_worker(&self, frontier: &Mutex<Frontier>, ready: &Condvar, max_depth: &AtomicU64) {
+        let mut batch: Option<Batch> = None;
+        loop {
+            let mut queue = frontier.lock().unwrap();
+            let (url, depth) = loop {
+                if let Some(next) = self.take_url(&mut queue, max_depth) {
+                    break next;
+                }
+                if queue.is_done() {
+                    ready.notify_all();
+                    return;
+                }
+                queue = ready.wait(queue).unwrap();
+            };
+            drop(queue);
+
+            // Each worker fetches the pages of a depth level in a batch of its own
+            let page_batch = match batch {
+                Some(batch) if batch.generation == depth => batch,
+                _ => self.next_batch(depth),
+            };
+            batch = Some(page_batch);
+            let fetched = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                self.visit_url(&url, depth, page_batch)
+            }));
+
+            // A page whose worker panicked is done, without any links
+            let mut queue = frontier.lock().unwrap();
+            let finished = match &fetched {
+                Ok((links, feed_urls)) => {
+                    self.complete_url(&mut queue, depth, links.iter().chain(feed_urls))
+                }
+                Err(_) => self.complete_url(&mut queue, depth, []),
+            };
+            drop(queue);
+            ready.notify_all();
+
+            // A worker that panicked leaves the others to finish the crawl, which then fails
+            if let Err(panic) = fetched {
+                std::panic::resume_unwind(panic);
+            }
+            for depth in finished {
+                self.finish_level(depth);
+            }
+        }
+    }
+
+    /// Iterates over the links of the origin like `iterate_links`, fetching up to `concurrency`
+    /// pages at once with the async reqwest client.
+    ///
+    /// ## Arguments
+    ///
+    /// * `origin_links` - A reference to a `HashSet<String>` containing the initial set of URLs to start the iteration.
+    /// * `reqwest_client` - A reference to the async reqwest client used to fetch pages.
+    /// * `blocking` - A reference to the `BlockingPool` the blocking work of the crawl runs on.
+    /// * `depth` - The depth of the page the origin links were found on.
+    ///
+    /// ## Returns
+    ///
+    /// The deepest depth level whose links were iterated.
+    async fn iterate_links_async<'s>(
+        &'s self,
+        origin_links: &HashSet<String>,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        depth: u64,
+    ) -> u64 {
+        info!(
+            "Starting link iteration with target depth: {} ({} strategy)",
+            self.config.depth, self.config.strategy
+        );
+
+        let mut frontier = self.start_frontier(origin_links, depth);
+        let feed_urls = blocking.run(move || self.fetch_feed_urls(depth + 1)).await;
+        self.queue_links(&mut frontier, &feed_urls, depth + 1);
+        let max_depth = AtomicU64::new(depth);
+        let mut batches = HashMap::new();
+        let mut in_flight = FuturesUnordered::new();
+        let concurrency = self.async_concurrency();
+        loop {
+            // Keep up to `concurrency` URLs in flight, taking the next one as soon as a fetch is
+            // done
+            while in_flight.len() < concurrency {
+                let Some((url, url_depth)) = self.take_url(&mut frontier, &max_depth) else {
+                    break;
+                };
+                let batch = *batches
+                    .entry(url_depth)
+                    .or_insert_with(|| self.next_batch(url_depth));
+                in_flight.push(async move {
+                    let (links, feed_urls) = self
+                        .visit_url_async(url, url_depth, reqwest_client, blocking, batch)
+                        .await;
+                    return (url_depth, links, feed_urls);
+                });
+            }
+
+            let Some((url_depth, links, feed_urls)) = in_flight.next().await else {
+                break;
+            };
+            let finished =
+                self.complete_url(&mut frontier, url_depth, links.iter().chain(&feed_urls));
+            if !finished.is_empty() {
+                blocking
+                    .run(move || {
+                        for depth in finished {
+                            self.finish_level(depth);
+                        }
+                    })
+                    .await;
+            }
+        }
+        blocking.run(move || self.flush_sites()).await;
+
+        return max_depth.into_inner();
+    }
+
+    /// Takes the next URL of the frontier, starting its depth level if it is the first URL taken
+    /// at that depth.
+    ///
+    /// ## Arguments
+    ///
+    /// * `frontier` - A mutable reference to the frontier of the crawl.
+    /// * `max_depth` - A reference to the deepest depth level taken so far.
+    ///
+    /// ## Returns
+    ///
+    /// The URL and its depth, or `None` if no URL is queued.
+    fn take_url(&self, frontier: &mut Frontier, max_depth: &AtomicU64) -> Option<(String, u64)> {
+        let (url, depth) = frontier.pop()?;
+        if depth > max_depth.fetch_max(depth, Ordering::SeqCst) {
+            self.start_level(depth, frontier.len());
+        }
+        self.frontier_size
+            .store(frontier.len() as u64, Ordering::SeqCst);
+        return Some((url, depth));
+    }
+
+    /// Fetches a URL taken from the frontier unless `admit_url` skips it, and gets the entries of
+    /// the feeds discovered so far that are due at the next depth level.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    /// * `depth` - The depth of the URL.
+    /// * `batch` - The worker batch fetching the URL.
+    ///
+    /// ## Returns
+    ///
+    /// The links of the page that are followed, and the entries of the feeds.
+    fn visit_url(&self, url: &str, depth: u64, batch: Batch) -> (HashSet<String>, HashSet<String>) {
+        let links = if self.admit_url(url) {
+            self.fetch_and_process_links(url, batch)
+        } else {
+            HashSet::new()
+        };
+        return (links, self.fetch_feed_urls(depth + 1));
+    }
+
+    /// Visits a URL taken from the frontier like `visit_url`, with the async reqwest client.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The URL.
+    /// * `depth` - The depth of the URL.
+    /// * `reqwest_client` - A reference to the async reqwest client used to fetch the URL.
+    /// * `blocking` - A reference to the `BlockingPool` the URL is admitted on.
+    /// * `batch` - The worker batch fetching the URL.
+    ///
+    /// ## Returns
+    ///
+    /// The links of the page that are followed, and the entries of the feeds.
+    async fn visit_url_async<'s>(
+        &'s self,
+        url: String,
+        depth: u64,
+        reqwest_client: &reqwest::Client,
+        blocking: &BlockingPool<'_, 's>,
+        batch: Batch,
+    ) -> (HashSet<String>, HashSet<String>) {
+        let (url, admitted) = blocking
+            .run(move || {
+                let admitted = self.admit_url(&url);
+                return (url, admitted);
+            })
+            .await;
+        let links = if admitted {
+            self.fetch_and_process_links_async(&url, reqwest_client, blocking, batch)
+                .await
+        } else {
+            HashSet::new()
+        };
+        let feed_urls = blocking.run(move || self.fetch_feed_urls(depth + 1)).await;
+        return (links, feed_urls);
+    }
+
+    /// Marks a URL taken from the frontier as done, queueing the links found on its page.
+    ///
+    /// ## Arguments
+    ///
+    /// * `frontier` - A mutable reference to the frontier of the crawl.
+    /// * `depth` - The depth of the URL.
+    /// * `links` - The links to queue one level deeper.
+    ///
+    /// ## Returns
+    ///
+    /// The depth levels the URL was the last of, which `finish_level` is called for once the
+    /// frontier is released.
+    fn complete_url<'a>(
+        &self,
+        frontier: &mut Frontier,
+        depth: u64,
+        links: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<u64> {
+        self.queue_links(frontier, links, depth + 1);
+        let finished = frontier.finish(depth);
+        self.frontier_size
+            .store(frontier.len() as u64, Ordering::SeqCst);
+        return finished;
+    }
+
+    /// Creates the frontier of a crawl, with the links of its origins queued and the origins
+    /// themselves marked as seen so that they aren't fetched again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `origin_links` - A reference to a `HashSet<String>` containing the links of the origins.
+    /// * `depth` - The depth of the origins.
+    fn start_frontier(&self, origin_links: &HashSet<String>, depth: u64) -> Frontier {
+        let mut frontier = Frontier::new(self.config.strategy, self.config.expected_crawl_size);
+        for origin_url in &self.config.origin_urls {
+            frontier.mark_seen(origin_url);
+        }
+        self.queue_links(&mut frontier, origin_links, depth + 1);
+        return frontier;
+    }
+
+    /// Queues the URLs found on a page, unless they are deeper than `depth`, or than the level of
+    /// validated links past it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `frontier` - A mutable reference to the frontier of the crawl.
+    /// * `urls` - The URLs to queue.
+    /// * `depth` - The depth the URLs would be fetched at.
+    fn queue_links<'a>(
+        &self,
+        frontier: &mut Frontier,
+        urls: impl IntoIterator<Item = &'a String>,
+        depth: u64,
+    ) {
+        if depth > self.deepest_depth() {
+            return;
+        }
+        for url in urls {
+            frontier.push(url, depth);
+        }
+    }
+
+    /// Stores the feeds found since the last call, and fetches them to crawl their entries along
+    /// with the links of the page they were found on, unless those are deeper than `depth`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `depth` - The depth the entries of the feeds would be fetched at.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing the normalized URLs of the entries of the feeds.
+    fn fetch_feed_urls(&self, depth: u64) -> HashSet<String> {
+        if depth > self.deepest_depth() {
+            return HashSet::new();
+        }
+        return self.get_feed_urls(self.store_feeds());
+    }
+
+    /// Gets the depth of the deepest URLs that are queued: `depth`, or one more with
+    /// `validate_only`, whose links one level deeper are validated.
+    fn deepest_depth(&self) -> u64 {
+        return self.config.depth + u64::from(self.config.validate_only);
+    }
+
+    /// Checks whether the URLs of a generation are only validated, rather than fetched in full:
+    /// those one level deeper than `depth` with `validate_only`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `generation` - The generation of the URLs, counting the origin as generation 0.
+    fn validates(&self, generation: u64) -> bool {
+        return self.config.validate_only && generation > self.config.depth;
+    }
+
+    /// Writes the sites fetched so far and logs the progress of the crawl once every URL of a
+    /// depth level, and of the levels above it, is done.
+    ///
+    /// ## Arguments
+    ///
+    /// * `depth` - The depth level that was just finished.
+    fn finish_level(&self, depth: u64) {
+        self.flush_sites();
+        self.log_depth(depth);
+    }
+
+    /// Fetches the URLs that failed transiently during the crawl once more, one at a time and
+    /// `FINAL_RETRY_DELAY` apart, up to `final_retry_limit` of them. A URL that is fetched this
+    /// time is stored and taken out of the fetch errors, but its links aren't followed.
+    ///
+    /// ## Returns
+    ///
+    /// The number of URLs that were fetched this time.
+    fn retry_transient_failures(&self) -> u64 {
+        let mut retries = std::mem::take(&mut *self.transient_failures.lock().unwrap());
+        if self.config.final_retry_limit == 0 || retries.is_empty() {
+            return 0;
+        }
+        if retries.len() > self.config.final_retry_limit {
+            warn!(
+                "{} URLs failed transiently, only retrying the first {}",
+                retries.len(),
+                self.config.final_retry_limit
+            );
+            retries.truncate(self.config.final_retry_limit);
+        }
+        info!("Retrying {} URLs that failed transiently", retries.len());
+
+        let mut recovered = 0;
+        for (url, batch) in retries {
+            if self.is_suspended(&url) {
+                continue;
+            }
+            if !self.check_disk() {
+                break;
+            }
+            std::thread::sleep(FINAL_RETRY_DELAY);
+
+            // Forget the failure, which is only reported again if the retry fails too. A server
+            // error response was counted as fetched, and is counted again if the retry succeeds
+            let mut fetch_errors = self.fetch_errors.lock().unwrap();
+            let failures = fetch_errors.len();
+            fetch_errors.retain(|fetch_error| fetch_error.url != url);
+            if fetch_errors.len() == failures {
+                self.pages_succeeded.fetch_sub(1, Ordering::SeqCst);
+            }
+            drop(fetch_errors);
+
+            self.fetch_and_process_links(&url, batch);
+            let failed_again = self
+                .fetch_errors
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|fetch_error| fetch_error.url == url)
+                || self
+                    .transient_failures
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|(failed_url, _)| *failed_url == url);
+            if !failed_again {
+                info!("Fetched {} on retry", url);
+                recovered += 1;
+            }
+        }
+        self.flush_sites();
+        return recovered;
+    }
+
+    /// Remembers the external links of a page, to check them at the end of the crawl when
+    /// `verify_external` is set.
+    ///
+    /// ## Arguments
+    ///
+    /// * `external_links` - A reference to the external links of the page.
+    fn record_external_urls(&self, external_links: &HashSet<String>) {
+        if self.config.verify_external {
+            self.external_urls
+                .lock()
+                .unwrap()
+                .extend(external_links.iter().cloned());
+        }
+    }
+
+    /// Checks every external link recorded during the crawl with a `HEAD` request, when
+    /// `verify_external` is set, so that dead outbound links are flagged.
+    ///
+    /// The response of each link is stored as a site without links, under the link's URL, and a
+    /// request that fails is recorded in the failed fetches, so that `rustle query broken` lists
+    /// the links whose target is dead. External links are never parsed nor followed, and their
+    /// failures aren't counted against the crawl.
+    fn verify_external_links(&self) {
+        let urls = std::mem::take(&mut *self.external_urls.lock().unwrap());
+        if urls.is_empty() {
+            return;
+        }
+        info!("Checking {} external links", urls.len());
+
+        let dead = AtomicU64::new(0);
+        self.pool.install(|| {
+            urls.par_iter().for_each(|url| {
+                let _permit = self.host_limiter.acquire(&Self::host_of(url));
+                let request_start = Instant::now();
+                let response = self.send(reqwest::Method::HEAD, url, &HeaderMap::new());
+                let (response, redirects) = match response {
+                    Ok(response) => response,
+                    Err(e) => {
+                        let reason = format!("{:#}", e);
+                        warn!("External link {} is dead: {}", url, reason);
+                        dead.fetch_add(1, Ordering::SeqCst);
+                        let failure = FetchFailure {
+                            run_id: *self.run_id.lock().unwrap(),
+                            ..FetchFailure::new(url, Self::categorize(&e), &reason, Utc::now())
+                        };
+                        if let Err(e) = self.storage.record_fetch_failure(&failure) {
+                            error!("Failed to record the failed check of {}: {:#}", url, e);
+                        }
+                        return;
+                    }
+                };
+
+                let status_code = response.status.as_u16();
+                if status_code >= 400 {
+                    warn!("External link {} is dead with status {}", url, status_code);
+                    dead.fetch_add(1, Ordering::SeqCst);
+                }
+                self.store_site(Site {
+                    url: url.clone(),
+                    crawl_time: Utc::now(),
+                    status_code: Some(status_code),
+                    final_url: Some(response.url.to_string()),
+                    redirect_hops: Some(redirects.len() as u64),
+                    response_ms: Some(request_start.elapsed().as_millis() as u64),
+                    ..Default::default()
+                });
+            });
+        });
+        self.flush_sites();
+
+        info!(
+            "{} of {} external links are dead",
+            dead.into_inner(),
+            urls.len()
+        );
+    }
+
+    /// Checks whether a discovered URL is fetched, skipping it if it is cached, on a suspended
+    /// host or disallowed by robots.txt, or if the page or disk limit is reached.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the discovered URL.
+    ///
+    /// ## Returns
+    ///
+    /// A boolean indicating whether the URL is fetched, in which case one page of `max_pages`
+    /// and of `max_pages_per_domain` was reserved for it.
+    fn admit_url(&self, url: &str) -> bool {
+        // Check if site is cached, on a suspended host, or disallowed by robots.txt, and can be
+        // skipped. A URL that can't be checked is reported and skipped, without failing the crawl
+        match self.should_skip_cached_url(url) {
+            Ok(false) => {}
+            Ok(true) => {
+                self.pages_cached.fetch_add(1, Ordering::SeqCst);
+                return self.skip_url(url, SkipReason::Cached);
+            }
+            Err(e) => {
+                self.record_fetch_error(url, &e, None);
+                return self.skip_url(url, SkipReason::CheckFailed);
+            }
+        }
+        if self.is_canonical_alias(url) {
+            trace!(
+                event = "skipped_alias", url = url;
+                "Skipping URL whose canonical URL is already stored: {}", url
+            );
+            return self.skip_url(url, SkipReason::CanonicalAlias);
+        }
+        if self.is_suspended(url) {
+            return self.skip_url(url, SkipReason::HostSuspended);
+        }
+        if self.config.respect_robots {
+            match self.is_allowed_to_scrape(url) {
+                Ok(true) => {}
+                Ok(false) => {
+                    trace!(
+                        event = "blocked_by_robots", url = url;
+                        "Skipping URL disallowed by robots.txt: {}", url
+                    );
+                    self.pages_blocked_by_robots.fetch_add(1, Ordering::SeqCst);
+                    return self.skip_url(url, SkipReason::Robots);
+                }
+                Err(e) => {
+                    self.record_fetch_error(url, &e, None);
+                    return self.skip_url(url, SkipReason::CheckFailed);
+                }
+            }
+        }
+
+        // Stop fetching once the page or disk limit is reached, and the pages of a host once its
+        // own page limit is
+        if !self.check_disk() {
+            return self.skip_url(url, SkipReason::DiskLimit);
+        }
+        if !self.reserve_domain_page(url) {
+            return self.skip_url(url, SkipReason::DomainPageLimit);
+        }
+        if !self.reserve_page() {
+            return self.skip_url(url, SkipReason::PageLimit);
+        }
+        return true;
+    }
+
+    /// Queues the decision to skip a discovered URL, to be recorded for `rustle explain` along
+    /// with the next sites written.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the skipped URL.
+    /// * `reason` - The reason the URL is skipped.
+    ///
+    /// ## Returns
+    ///
+    /// Always `false`, so that `admit_url` can return it as is.
+    fn skip_url(&self, url: &str, reason: SkipReason) -> bool {
+        self.pending_skips.lock().unwrap().push(SkipDecision {
+            url: url.to_string(),
+            reason,
+            decided_at: Utc::now(),
+            run_id: *self.run_id.lock().unwrap(),
+        });
+        return false;
+    }
+
+    /// Checks whether a URL leads to a page already stored under its canonical URL in this crawl,
+    /// because it is that canonical URL, or was stored as an alias of it by an earlier crawl.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the discovered URL.
+    fn is_canonical_alias(&self, url: &str) -> bool {
+        if !self.config.respect_canonical {
+            return false;
+        }
+        {
+            let canonical_urls = self.canonical_urls.lock().unwrap();
+            if canonical_urls.is_empty() {
+                return false;
+            }
+            if canonical_urls.contains(url) {
+                return true;
+            }
+        }
+
+        let canonical_url = match self.storage.get_site(url) {
+            Ok(Some(site)) => site.canonical_url,
+            _ => None,
+        };
+        return canonical_url.is_some_and(|canonical_url| {
+            self.canonical_urls.lock().unwrap().contains(&canonical_url)
+        });
+    }
+
+    /// Records the start of a deeper depth level, for the metrics of the crawl.
+    ///
+    /// ## Arguments
+    ///
+    /// * `depth` - The depth level about to be fetched.
+    /// * `frontier_size` - The number of queued URLs.
+    fn start_level(&self, depth: u64, frontier_size: usize) {
+        self.current_depth.store(depth, Ordering::SeqCst);
+        self.frontier_size
+            .store(frontier_size as u64, Ordering::SeqCst);
+        self.level_start_pages.store(
+            self.pages_succeeded.load(Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+    }
+
+    /// Reads the current progress of the crawl, from the same counters as its metrics.
+    fn progress_status(&self) -> ProgressStatus {
+        let pages_fetched = self.pages_succeeded.load(Ordering::SeqCst);
+        return ProgressStatus {
+            depth: self.current_depth.load(Ordering::SeqCst),
+            level_pages: pages_fetched
+                .saturating_sub(self.level_start_pages.load(Ordering::SeqCst)),
+            frontier_size: self.frontier_size.load(Ordering::SeqCst),
+            errors: self.fetch_errors.lock().unwrap().len() as u64,
+            pages_fetched,
+        };
+    }
+
+    /// Gets the interval between the progress lines logged when stderr is not a terminal.
+    fn progress_interval(&self) -> Duration {
+        return Duration::from_secs(self.config.progress_interval_secs);
+    }
+
+    /// Renders the current metrics of the crawl in the Prometheus text format, from the same
+    /// counters as the crawl report.
+    ///
+    /// ## Returns
+    ///
+    /// A `String` holding the metrics page served at `/metrics`.
+    fn render_metrics(&self) -> String {
+        let mut page = MetricsPage::default();
+        page.metric(
+            "rustle_pages_fetched_total",
+            "counter",
+            "Pages successfully fetched.",
+            self.pages_succeeded.load(Ordering::SeqCst),
+        );
+        page.metric(
+            "rustle_pages_cached_total",
+            "counter",
+            "URLs skipped because their stored copy was still fresh.",
+            self.pages_cached.load(Ordering::SeqCst),
+        );
+        page.metric(
+            "rustle_pages_blocked_by_robots_total",
+            "counter",
+            "URLs skipped because robots.txt disallows them.",
+            self.pages_blocked_by_robots.load(Ordering::SeqCst),
+        );
+        let mut errors = BTreeMap::new();
+        for error in self.fetch_errors.lock().unwrap().iter() {
+            *errors.entry(error.category.to_string()).or_insert(0) += 1;
+        }
+        page.labeled_metric(
+            "rustle_fetch_errors_total",
+            "counter",
+            "Fetches that failed, by category.",
+            "category",
+            errors
+                .iter()
+                .map(|(category, count)| (category.as_str(), *count)),
+        );
+        let bytes_downloaded = self
+            .kind_stats
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(_, bytes)| bytes)
+            .sum();
+        page.metric(
+            "rustle_bytes_downloaded_total",
+            "counter",
+            "Body bytes downloaded.",
+            bytes_downloaded,
+        );
+        page.labeled_metric(
+            "rustle_requests_total",
+            "counter",
+            "Requests sent to fetch pages, by host.",
+            "host",
+            self.host_requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(host, count)| (host.as_str(), *count)),
+        );
+        page.metric(
+            "rustle_frontier_size",
+            "gauge",
+            "Queued URLs whose fetch has not started yet.",
+            self.frontier_size.load(Ordering::SeqCst),
+        );
+        page.metric(
+            "rustle_current_depth",
+            "gauge",
+            "The deepest depth level being fetched, the origin being 0.",
+            self.current_depth.load(Ordering::SeqCst),
+        );
+        return page.into_text();
+    }
+
+    /// Logs the progress of the crawl once a depth level is finished.
+    ///
+    /// ## Arguments
+    ///
+    /// * `depth` - The depth level that was just finished.
+    fn log_depth(&self, depth: u64) {
+        trace!("------ DEPTH: {} ------", depth);
+        info!(
+            "Depth {}: {} pages fetched, {:.1} MiB on disk",
+            depth,
+            self.pages_succeeded.load(Ordering::SeqCst),
+            self.disk_bytes.load(Ordering::SeqCst) as f64 / (1024.0 * 1024.0)
+        );
+    }
+
+    /// Gets the `robots.txt` file for a given domain, from its configured override file if it has
+    /// one, or else by fetching the live file.
+    ///
+    /// ## Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `page_url` - A reference to the URL of a page of the domain, over whose scheme and port
+    ///   robots.txt is fetched first.
+    ///
+    /// ## Returns
+    ///
+    /// An `Option<RobotsFile>` which holds the `robots.txt` file, empty if the domain has none,
+    /// or `None` if it could not be fetched.
+    pub(crate) fn get_robots(&self, domain: &str, page_url: &Url) -> Option<RobotsFile> {
+        if let Some(robots) = self.robots_overrides.get(domain) {
+            trace!("Using robots.txt override for {}", domain);
+            return Some(RobotsFile {
+                contents: robots.clone(),
+                url: None,
+            });
+        }
+        return self.fetch_robots(domain, page_url);
+    }
+
+    /// Fetches the live `robots.txt` file for a given domain.
+    ///
+    /// The file is requested over the scheme of the page first, and over the other one of `http` and
+    /// `https` if the domain can't be reached that way, as plain-HTTP sites have no robots.txt
+    /// over `https`. A `4xx` answer means the domain has no robots.txt, which allows everything.
+    ///
+    /// ## Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `page_url` - A reference to the URL of a page of the domain, over whose scheme and port
+    ///   robots.txt is fetched first.
+    ///
+    /// ## Returns
+    ///
+    /// An `Option<RobotsFile>` which holds the `robots.txt` file along with the URL it was
+    /// fetched from, empty if the domain has none, or `None` if the domain couldn't be reached
+    /// over either scheme or answered with a server error.
+    fn fetch_robots(&self, domain: &str, page_url: &Url) -> Option<RobotsFile> {
+        let robots_url = page_url.join("/robots.txt").ok()?;
+        let mut fallback_url = robots_url.clone();
+        let fallback = if robots_url.scheme() == "http" {
+            "https"
+        } else {
+            "http"
+        };
+        let _ = fallback_url.set_scheme(fallback);
+
+        for robots_url in [robots_url, fallback_url] {
+            match self.send(reqwest::Method::GET, robots_url.as_str(), &HeaderMap::new()) {
+                Ok((response, _)) => {
+                    let url = Some(response.url.to_string());
+                    let status = response.status;
+                    if status.is_success() {
+                        return response
+                            .text()
+                            .ok()
+                            .map(|contents| RobotsFile { contents, url });
+                    }
+                    if status.is_client_error() {
+                        trace!("{} has no robots.txt ({}): {}", domain, status, robots_url);
+                        return Some(RobotsFile {
+                            contents: String::new(),
+                            url,
+                        });
+                    }
+                    trace!("Failed to fetch {}: {}", robots_url, status);
+                    return None;
+                }
+                Err(e) => {
+                    trace!("Failed to fetch {}: {}", robots_url, e);
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Records the feeds discovered on a page, to be stored with the domain of the page, which
+    /// may be another than that of the feeds.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page_url` - A string slice that holds the URL of the page.
+    /// * `feeds` - The URLs of the feeds announced by the page.
+    fn record_feeds(&self, page_url: &str, feeds: Vec<String>) {
+        if feeds.is_empty() {
+            return;
+        }
+        self.discovered_feeds
+            .lock()
+            .unwrap()
+            .entry(Self::host_of(page_url))
+            .or_default()
+            .extend(feeds);
+    }
+
+    /// Stores the feeds discovered since they were last stored with their domain.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` containing the URLs of the feeds that were stored.
+    fn store_feeds(&self) -> Vec<String> {
+        let discovered = std::mem::take(&mut *self.discovered_feeds.lock().unwrap());
+        let mut stored = Vec::new();
+        for (domain, feeds) in discovered {
+            let feeds: Vec<String> = feeds.into_iter().collect();
+            if let Err(e) = self.storage.add_feeds(&domain, &feeds) {
+                error!("Failed to store the feeds of domain {}: {:#}", domain, e);
+            }
+            stored.extend(feeds);
+        }
+        return stored;
+    }
+
+    /// Fetches feeds and collects the in-scope URLs of their entries, if `use_feeds` is set.
+    ///
+    /// Each feed is fetched at most once per crawl, only if its host is in scope and robots.txt
+    /// allows it. Feeds that can't be fetched or parsed are logged and skipped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `feeds` - The URLs of the feeds to fetch.
+    ///
+    /// ## Returns
+    ///
+    /// A `HashSet<String>` containing the normalized URLs of the entries of the feeds.
+    fn get_feed_urls(&self, feeds: Vec<String>) -> HashSet<String> {
+        let mut urls = HashSet::new();
+        if !self.config.use_feeds {
+            return urls;
+        }
+
+        for feed_url in feeds {
+            if !self.in_scope(&Self::host_of(&feed_url))
+                || !self.fetched_feeds.lock().unwrap().insert(feed_url.clone())
+            {
+                continue;
+            }
+            if self.config.respect_robots {
+                match self.is_allowed_to_scrape(&feed_url) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        trace!("Skipping feed disallowed by robots.txt: {}", feed_url);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to check feed against robots.txt: {}: {:#}",
+                            feed_url, e
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            trace!("Fetching feed: {}", feed_url);
+            let xml = match self
+                .send(reqwest::Method::GET, &feed_url, &HeaderMap::new())
+                .and_then(|(response, _)| response.error_for_status()?.text())
+            {
+                Ok(xml) => xml,
+                Err(e) => {
+                    warn!("Failed to fetch feed: {}: {:#}", feed_url, e);
+                    continue;
+                }
+            };
+
+            // Resolve the entries against the feed, as they may be relative
+            let Ok(base_url) = Url::parse(&feed_url) else {
+                continue;
+            };
+            let entries: HashSet<String> = parse_feed(&xml)
+                .into_iter()
+                .filter_map(|entry| base_url.join(&entry).ok())
+                .filter_map(|entry| self.normalize_url(entry.as_str()))
+                .collect();
+            info!("Found {} URLs in feed {}", entries.len(), feed_url);
+            urls.extend(entries);
+        }
+        return urls;
+    }
+
+    /// Fetches sitemaps and collects the in-scope page URLs they list.
+    ///
+    /// Sitemap indexes are followed up to `MAX_SITEMAP_DEPTH` levels deep, each sitemap is
+    /// fetched at most once, and at most `MAX_SITEMAPS` are fetched in total, so cyclic or
+    /// runaway indexes cannot recurse forever. Gzipped sitemaps are decompressed transparently.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sitemaps` - A `Vec<String>` containing the URLs of the sitemaps to start from.
+    ///
+    /// ## Returns
+    ///
+    /// A tuple of the normalized page URLs, and the URLs of every sitemap that was fetched.
+    fn get_sitemap_urls(&self, sitemaps: Vec<String>) -> (HashSet<String>, Vec<String>) {
+        let mut pages = HashSet::new();
+        let mut fetched = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = sitemaps
+            .into_iter()
+            .map(|url| (url, 0))
+            .collect::<VecDeque<(String, u64)>>();
+
+        while let Some((url, depth)) = queue.pop_front() {
+            if fetched.len() >= MAX_SITEMAPS {
+                warn!("Stopping after {} sitemaps", MAX_SITEMAPS);
+                break;
+            }
+            if !seen.insert(url.clone()) {
+                continue;
+            }
+
+            // Fetch the sitemap, decompressing it if it is gzipped
+            trace!("Fetching sitemap: {}", url);
+            let body = match self
+                .send(reqwest::Method::GET, &url, &HeaderMap::new())
+                .and_then(|(response, _)| response.error_for_status()?.bytes())
+            {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("Failed to fetch sitemap: {}: {:#}", url, e);
+                    continue;
+                }
+            };
+            let mut xml = String::new();
+            let read = if body.starts_with(&[0x1f, 0x8b]) {
+                GzDecoder::new(&body[..]).read_to_string(&mut xml)
+            } else {
+                (&body[..]).read_to_string(&mut xml)
+            };
+            if let Err(e) = read {
+                warn!("Failed to read sitemap: {}: {}", url, e);
+                continue;
+            }
+            fetched.push(url.clone());
+
+            // Queue nested sitemaps, or collect the pages in scope
+            let sitemap = parse_sitemap(&xml);
+            match sitemap.kind {
+                SitemapKind::Index if depth < MAX_SITEMAP_DEPTH => {
+                    queue.extend(sitemap.locations.into_iter().map(|loc| (loc, depth + 1)));
+                }
+                SitemapKind::Index => {
+                    warn!("Not following sitemap index nested too deeply: {}", url);
+                }
+                SitemapKind::UrlSet => {
+                    pages.extend(
+                        sitemap
+                            .locations
+                            .iter()
+                            .filter_map(|loc| self.normalize_url(loc)),
+                    );
+                }
+            }
+        }
+
+        return (pages, fetched);
+    }
+
+    /// Writes a `Site` to the database.
+    ///
+    /// This function creates a `Site` instance with the given URL and links,
+    /// sets the current time as the crawl time, and writes the `Site` to the database. Pages
+    /// reached through redirects are stored under their final URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the site.
+    /// * `links_to` - A reference to a `HashSet` containing the URLs that the site links to,
+    ///   apart from the nofollow and external links of its `metadata`, which are stored along
+    ///   with them.
+    /// * `page` - A reference to the `FetchedPage` the site was parsed from.
+    /// * `stored` - The stored copy of the fetched URL from before the fetch, if there is one.
+    /// * `metadata` - A reference to the `PageMetadata` parsed from the site.
+    /// * `batch` - The worker batch that fetched the site.
+    fn write_site(
+        &self,
+        url: &str,
+        links_to: &HashSet<String>,
+        page: &FetchedPage,
+        stored: Option<&Site>,
+        metadata: &PageMetadata,
+        batch: Batch,
+    ) {
+        trace!("Writing site to database for URL: {}", url);
+        let crawl_time = Utc::now();
+        let discovered_from = self.referrers.lock().unwrap().remove(url);
+        let requested_url = url;
+        let mut url = if page.redirects.is_empty() {
+            url.to_string()
+        } else {
+            self.normalize_url(&page.final_url)
+                .unwrap_or_else(|| page.final_url.clone())
+        };
+
+        // Find what allowed the page under the URL it was fetched from, in compliance mode
+        let (robots_snapshot, robots_rule) = match self.robots_decision(&url) {
+            Ok(decision) => decision,
+            Err(e) => {
+                self.record_fetch_error(&url, &e, Some(batch));
+                return;
+            }
+        };
+        if url != requested_url {
+            self.robots_decisions.lock().unwrap().remove(requested_url);
+        }
+
+        // Store the page under the canonical URL it declares, and the fetched URL as its alias,
+        // unless the canonical URL is out of scope, in which case it is only recorded
+        let mut canonical_url = metadata
+            .canonical_url
+            .clone()
+            .filter(|canonical_url| *canonical_url != url);
+        let canonical_target = canonical_url
+            .as_deref()
+            .filter(|_| self.config.respect_canonical)
+            .and_then(|canonical_url| self.normalize_url(canonical_url));
+        if let Some(canonical_target) = canonical_target {
+            self.write_alias(&url, &canonical_target, page, batch);
+            if !self
+                .canonical_urls
+                .lock()
+                .unwrap()
+                .insert(canonical_target.clone())
+            {
+                trace!(
+                    "Canonical URL {} of {} is already stored",
+                    canonical_target,
+                    url
+                );
+                return;
+            }
+            url = canonical_target;
+            canonical_url = None;
+        }
+        let url = url.as_str();
+
+        // Compare the content of HTML pages with their stored copy, to record when it last
+        // changed. Challenge pages say nothing about the content, and are not hashed. The stored
+        // copy read before the fetch is used, unless the page is stored under another URL.
+        let (content_hash, last_changed) = if page.is_html && !page.bot_challenged {
+            let content_hash = Site::hash_content(&page.html);
+            let read_site;
+            let previous = if url == requested_url {
+                Ok(stored)
+            } else {
+                read_site = self.storage.get_site(url);
+                read_site.as_ref().map(Option::as_ref)
+            };
+            let last_changed = match previous {
+                Ok(Some(Site {
+                    content_hash: Some(previous_hash),
+                    last_changed,
+                    ..
+                })) if *previous_hash == content_hash => {
+                    self.pages_unchanged.fetch_add(1, Ordering::SeqCst);
+                    *last_changed
+                }
+                Ok(Some(Site {
+                    content_hash: None,
+                    last_changed,
+                    ..
+                })) => {
+                    // Copies stored before content hashes were recorded can't be compared
+                    trace!("No stored content hash to compare for URL: {}", url);
+                    *last_changed
+                }
+                Ok(Some(_)) => {
+                    trace!("Content changed for URL: {}", url);
+                    self.pages_changed.fetch_add(1, Ordering::SeqCst);
+                    Some(crawl_time)
+                }
+                Ok(None) => {
+                    self.pages_new.fetch_add(1, Ordering::SeqCst);
+                    Some(crawl_time)
+                }
+                Err(e) => {
+                    warn!("Failed to read the stored content hash of {}: {:#}", url, e);
+                    Some(crawl_time)
+                }
+            };
+            (Some(content_hash), last_changed)
+        } else {
+            (None, None)
+        };
+
+        // Declare a `Site` struct to hold information
+        let site = Site {
+            url: url.to_string(),
+            crawl_time,
+            links_to: links_to
+                .iter()
+                .chain(&metadata.nofollow_links)
+                .chain(&metadata.external_links)
+                .cloned()
+                .collect(),
+            anchor_texts: metadata.anchor_texts.clone(),
+            assets: metadata.assets.clone(),
+            header_anomaly: page.header_anomaly,
+            cookies_set: page.cookies_set,
+            bot_challenged: page.bot_challenged,
+            nofollow_links: metadata.nofollow_links.clone(),
+            external_links: metadata.external_links.clone(),
+            js_dependency_score: metadata.js_dependency_score,
+            status_code: Some(page.status_code),
+            final_url: Some(page.final_url.clone()),
+            is_redirect: false,
+            redirect_hops: Some(page.redirects.len() as u64),
+            canonical_url,
+            content_type: page.content_type.clone(),
+            body_bytes: page.body_bytes,
+            sniffed_type: page.sniffed_type.map(str::to_string),
+            etag: page.etag.clone(),
+            last_modified: page.last_modified.clone(),
+            title: metadata.title.clone(),
+            description: metadata.description.clone(),
+            content_hash,
+            last_changed,
+            generation: Some(batch.generation),
+            batch_id: Some(batch.id),
+            depth: Some(batch.generation),
+            discovered_from,
+            response_ms: Some(page.response_ms),
+            robots_snapshot,
+            robots_rule,
+            run_id: None,
+            text: metadata.text.clone(),
+        };
+
+        // Queue the Site struct to be written to the database
+        self.store_site(site);
+    }
+
+    /// Queues a `Site` to be written to the database, writing the queued sites once
+    /// `write_batch_size` of them are waiting. The site is stamped with the id of the current
+    /// crawl run.
+    ///
+    /// ## Arguments
+    ///
+    /// * `site` - The `Site` to be written.
+    fn store_site(&self, mut site: Site) {
+        site.run_id = *self.run_id.lock().unwrap();
+        let batch = {
+            let mut pending = self.pending_sites.lock().unwrap();
+            pending.push(site);
+            if pending.len() < self.config.write_batch_size {
+                return;
+            }
+            std::mem::take(&mut *pending)
+        };
+        self.write_sites(&batch);
+    }
+
+    /// Writes every queued `Site` to the database, in transactions of `write_batch_size` sites,
+    /// and flushes the storage.
+    fn flush_sites(&self) {
+        let pending = std::mem::take(&mut *self.pending_sites.lock().unwrap());
+        for batch in pending.chunks(self.config.write_batch_size) {
+            self.write_sites(batch);
+        }
+        let skips = std::mem::take(&mut *self.pending_skips.lock().unwrap());
+        for batch in skips.chunks(self.config.write_batch_size) {
+            if let Err(e) = self.storage.put_skip_decisions(batch) {
+                error!("Failed to record {} skipped URLs: {:#}", batch.len(), e);
+            }
+        }
+        if let Err(e) = self.storage.flush() {
+            error!("Failed to flush the storage: {:#}", e);
+        }
+    }
+
+    /// Writes sites to the database in a single transaction, logging them if it is rolled back.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sites` - A slice of the sites to be written.
+    fn write_sites(&self, sites: &[Site]) {
+        trace!("Writing {} sites to the database", sites.len());
+        if let Err(e) = self.storage.put_sites(sites) {
+            let reason = format!("{:#}", e);
+            error!("Failed to store {} sites: {}", sites.len(), reason);
+            for site in sites {
+                warn!(
+                    event = "write_failed", url = site.url.as_str(), error = reason.as_str();
+                    "Site {} was not stored", site.url
+                );
+            }
+            return;
+        }
+
+        let urls: Vec<&str> = sites.iter().map(|site| site.url.as_str()).collect();
+        self.forget_failures(&urls);
+    }
+
+    /// Forgets the earlier failures and skips of sites that could be fetched this time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `urls` - The URLs of the sites.
+    fn forget_failures(&self, urls: &[&str]) {
+        if let Err(e) = self.storage.clear_fetch_failures(urls) {
+            error!(
+                "Failed to clear the failed fetches of {} sites: {:#}",
+                urls.len(),
+                e
+            );
+        }
+        if let Err(e) = self.storage.clear_skip_decisions(urls) {
+            error!(
+                "Failed to clear the skip decisions of {} sites: {:#}",
+                urls.len(),
+                e
+            );
+        }
+    }
+
+    /// Writes a redirect `Site` to the database for every URL a page was redirected through.
+    ///
+    /// Each redirect links to the next URL of the chain, so that the link graph shows what
+    /// redirects where.
+    ///
+    /// ## Arguments
+    ///
+    /// * `page` - A reference to the `FetchedPage` at the end of the redirect chain.
+    /// * `batch` - The worker batch that fetched the page.
+    fn write_redirects(&self, page: &FetchedPage, batch: Batch) {
+        let crawl_time = Utc::now();
+        let hops = page.redirects.len();
+        for (index, (url, status_code)) in page.redirects.iter().enumerate() {
+            let next = match page.redirects.get(index + 1) {
+                Some((next, _)) => next,
+                None => &page.final_url,
+            };
+            trace!("Writing redirect to database: {} -> {}", url, next);
+            let site = Site {
+                url: self.normalize_url(url).unwrap_or_else(|| url.clone()),
+                crawl_time,
+                links_to: HashSet::from([self.normalize_url(next).unwrap_or_else(|| next.clone())]),
+                status_code: Some(*status_code),
+                final_url: Some(page.final_url.clone()),
+                is_redirect: true,
+                redirect_hops: Some((hops - index) as u64),
+                generation: Some(batch.generation),
+                batch_id: Some(batch.id),
+                ..Default::default()
+            };
+            self.store_site(site);
+        }
+    }
+
+    /// Writes an alias `Site` to the database for a URL whose page is stored under the canonical
+    /// URL it declares. The alias only links to the canonical URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - A string slice that holds the URL the page was fetched from.
+    /// * `canonical_url` - A string slice that holds the canonical URL of the page.
+    /// * `page` - A reference to the fetched page.
+    /// * `batch` - The worker batch that fetched the page.
+    fn write_alias(&self, url: &str, canonical_url: &str, page: &FetchedPage, batch: Batch) {
+        trace!("Writing alias to database: {} -> {}", url, canonical_url);
+        let site = Site {
+            url: url.to_string(),
+            crawl_time: Utc::now(),
+            links_to: HashSet::from([canonical_url.to_string()]),
+            status_code: Some(page.status_code),
+            final_url: Some(page.final_url.clone()),
+            canonical_url: Some(canonical_url.to_string()),
+            generation: Some(batch.generation),
+            batch_id: Some(batch.id),
+            ..Default::default()
+        };
+        self.store_site(site);
+    }
+
+    /// Writes a `Domain` to the database.
+    ///
+    /// This function creates a `Domain` instance with the given domain and robots.txt string,
+    /// sets the current time as the crawl time, and writes the `Domain` to the database.
+    ///
+    /// ## Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain of the site. (Fomratted as "example.com")
+    /// * `robots` - A reference to the domain's robots.txt and the URL it was fetched from.
+    /// * `sitemaps` - A `Vec<String>` containing the URLs of the domain's sitemaps.
+    fn write_domain(&self, domain: &str, robots: &RobotsFile, sitemaps: Vec<String>) {
+        trace!("Writing domain to database for domain: {}", domain);
+
+        // Keep the feeds discovered on the domain by earlier crawls
+        let stored_feeds = match self.storage.get_domain(domain) {
+            Ok(stored) => stored.map(|stored| stored.feeds).unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to read domain {} from the database: {}", domain, e);
+                Vec::new()
+            }
+        };
+
+        let domain = Domain {
+            domain: domain.to_string(),
+            crawl_time: Utc::now(),
+            robots: robots.contents.clone(),
+            robots_url: robots.url.clone(),
+            sitemaps,
+            feeds: stored_feeds,
+            robots_override: self
+                .config
+                .robots_override
+                .get(domain)
+                .map(|path| path.display().to_string()),
+            broken_tls_redirect: self.broken_tls_hosts.lock().unwrap().contains(domain),
+        };
+
+        if let Err(e) = self.storage.put_domain(&domain) {
+            error!("Failed to store domain {}: {:#}", domain.domain, e);
+        }
+    }
+
+    /// Lists the URLs whose admission differs between the live robots.txt of their domain and
+    /// its planned override, as found so far when `robots_override_diff` is enabled.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<RobotsDiff>` sorted by URL.
+    pub fn robots_diff(&self) -> Vec<RobotsDiff> {
+        let mut diff = std::mem::take(&mut *self.robots_diff.lock().unwrap());
+        diff.sort();
+        diff.dedup();
+        return diff;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Strategy;
+    use crate::fixture::{FixtureResponse, FixtureServer};
+
+    /// A page linking to the same and other URLs with and without a `rel` asking not to follow.
+    const PAGE: &str = r#"<html><body>
+        <a href="https://example.com/plain">Plain</a>
+        <a href="https://example.com/comment" rel="nofollow">Comment</a>
+        <a href="https://example.com/user" rel="external UGC">User</a>
+        <a href="https://example.com/ad" rel="sponsored">Ad</a>
+        <a href="https://example.com/both" rel="nofollow">Both</a>
+        <a href="https://example.com/both">Both</a>
+        <a href="https://example.com/author" rel="author">Author</a>
+    </body></html>"#;
+
+    /// Creates a builder of a crawler of `https://example.com/` to depth 1 that stores its pages
+    /// in memory, after letting `configure` change its configuration.
+    fn builder(configure: impl FnOnce(&mut Config)) -> crate::builder::CrawlerBuilder {
+        let mut config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            depth: 1,
+            ..Config::default()
+        };
+        configure(&mut config);
+        return crate::builder::CrawlerBuilder::from(config)
+            .storage(Box::new(crate::storage::MemoryStorage::new()));
+    }
+
+    /// Creates a crawler like `builder`, sending its requests over the network.
+    fn crawler(configure: impl FnOnce(&mut Config)) -> Crawler {
+        return builder(configure).build().unwrap();
+    }
+
+    /// Creates a crawler like `builder`, sending its requests with `fetcher`, such as a
+    /// `MapFetcher` shared through an `Arc` to inspect the requests afterwards.
+    fn crawler_with(
+        fetcher: impl Fetcher + Send + Sync + 'static,
+        configure: impl FnOnce(&mut Config),
+    ) -> Crawler {
+        return builder(configure)
+            .fetcher(Box::new(fetcher))
+            .build()
+            .unwrap();
+    }
+
+    /// Gets the URLs of a map of links to their anchor text.
+    fn urls(links: &HashMap<String, String>) -> HashSet<String> {
+        return links.keys().cloned().collect();
+    }
+
+    fn set(urls: &[&str]) -> HashSet<String> {
+        return urls
+            .iter()
+            .map(|url| format!("https://example.com/{}", url))
+            .collect();
+    }
+
+    #[test]
+    fn meta_robots_combines_the_generic_and_the_rustle_tags() {
+        let directives = |head: &str| {
+            return Crawler::get_meta_robots(&Document::from(
+                format!("<html><head>{}</head><body></body></html>", head).as_str(),
+            ));
+        };
+        assert_eq!(directives(""), RobotsDirectives::default());
+        assert!(directives(r#"<meta name="robots" content="noindex">"#).noindex);
+        assert!(directives(r#"<meta name="ROBOTS" content="none">"#).nofollow);
+
+        let mixed = directives(
+            r#"<meta name="robots" content="noindex"><meta name="rustle" content="nofollow">"#,
+        );
+        assert!(mixed.noindex && mixed.nofollow);
+
+        let other_agent = directives(
+            r#"<meta name="robots" content="nofollow"><meta name="googlebot" content="noindex">"#,
+        );
+        assert!(!other_agent.noindex && other_agent.nofollow);
+    }
+
+    #[test]
+    fn is_nofollow_matches_any_listed_rel_value() {
+        let document = Document::from(PAGE);
+        let nofollow: HashSet<String> = document
+            .find(Name("a"))
+            .filter(|anchor| Crawler::is_nofollow(anchor))
+            .filter_map(|anchor| anchor.attr("href").map(str::to_string))
+            .collect();
+        assert_eq!(nofollow, set(&["comment", "user", "ad", "both"]));
+    }
+
+    #[test]
+    fn nofollow_links_are_skipped_by_default() {
+        let crawler = crawler(|_| {});
+        let page_links = crawler.get_links(&Document::from(PAGE), "https://example.com/");
+        assert_eq!(urls(&page_links.links), set(&["plain", "both", "author"]));
+        assert!(page_links.nofollow.is_empty());
+    }
+
+    #[test]
+    fn nofollow_links_are_recorded_apart() {
+        let crawler = crawler(|config| config.record_nofollow_links = true);
+        let page_links = crawler.get_links(&Document::from(PAGE), "https://example.com/");
+        assert_eq!(urls(&page_links.links), set(&["plain", "both", "author"]));
+        assert_eq!(urls(&page_links.nofollow), set(&["comment", "user", "ad"]));
+    }
+
+    #[test]
+    fn nofollow_links_are_followed_when_configured() {
+        let crawler = crawler(|config| {
+            config.follow_nofollow = true;
+            config.record_nofollow_links = true;
+        });
+        let page_links = crawler.get_links(&Document::from(PAGE), "https://example.com/");
+        assert_eq!(
+            urls(&page_links.links),
+            set(&["plain", "comment", "user", "ad", "both", "author"])
+        );
+        assert!(page_links.nofollow.is_empty());
+    }
+
+    #[test]
+    fn external_links_are_recorded_apart_when_configured() {
+        let page = r#"<html><body>
+            <a href="https://example.com/plain">Plain</a>
+            <a href="https://other.org/docs">Other <b>docs</b></a>
+            <a href="https://other.org/docs"></a>
+            <a href="mailto:me@example.com">Mail</a>
+        </body></html>"#;
+        let page_links = crawler(|_| {}).get_links(&Document::from(page), "https://example.com/");
+        assert_eq!(urls(&page_links.links), set(&["plain"]));
+        assert!(page_links.external.is_empty());
+
+        let crawler = crawler(|config| config.record_external_links = true);
+        let page_links = crawler.get_links(&Document::from(page), "https://example.com/");
+        assert_eq!(urls(&page_links.links), set(&["plain"]));
+        assert_eq!(
+            page_links.external,
+            HashMap::from([(
+                "https://other.org/docs".to_string(),
+                "Other docs".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn anchor_text_is_collapsed_from_nested_markup() {
+        let page = r#"<html><body>
+            <a href="/about">  About
+                <span>our <b>team</b></span>
+            </a>
+            <a href="/logo"><img src="/logo.png" alt="Logo"></a>
+            <a href="/docs"><img src="/docs.png"></a>
+            <a href="/docs">Read the <em>docs</em></a>
+            <a href="/docs">Docs again</a>
+        </body></html>"#;
+        let page_links = crawler(|_| {}).get_links(&Document::from(page), "https://example.com/");
+        assert_eq!(
+            page_links.links,
+            HashMap::from([
+                (
+                    "https://example.com/about".to_string(),
+                    "About our team".to_string()
+                ),
+                ("https://example.com/logo".to_string(), String::new()),
+                (
+                    "https://example.com/docs".to_string(),
+                    "Read the docs".to_string()
+                ),
+            ])
+        );
+
+        // The anchor texts are stored along with the page
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .page("https://example.com/", page)
+            .page("https://example.com/about", "<p>About</p>")
+            .page("https://example.com/logo", "<p>Logo</p>")
+            .page("https://example.com/docs", "<p>Docs</p>");
+        let crawler = crawler_with(fetcher, |_| {});
+        crawler.crawl(true).unwrap();
+        let site = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            site.links_detailed(),
+            [
+                ("https://example.com/about", "About our team"),
+                ("https://example.com/docs", "Read the docs"),
+                ("https://example.com/logo", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_beyond_depth_are_not_queued_with_any_strategy() {
+        for strategy in [Strategy::Bfs, Strategy::Dfs, Strategy::Priority] {
+            let crawler = crawler(|config| {
+                config.depth = 2;
+                config.strategy = strategy;
+            });
+            let mut frontier = crawler.start_frontier(&set(&["a", "b"]), 0);
+            crawler.queue_links(&mut frontier, &set(&["a/1", "b"]), 2);
+            crawler.queue_links(&mut frontier, &set(&["a/1/x"]), 3);
+
+            let mut queued = Vec::new();
+            while let Some((url, depth)) = frontier.pop() {
+                queued.push((url, depth));
+            }
+            queued.sort();
+            assert_eq!(
+                queued,
+                vec![
+                    ("https://example.com/a".to_string(), 1),
+                    ("https://example.com/a/1".to_string(), 2),
+                    ("https://example.com/b".to_string(), 1),
+                ],
+                "{strategy}"
+            );
+        }
+    }
+
+    #[test]
+    fn domain_budget_groups_hosts_by_registrable_domain_with_site_scope() {
+        for (scope, blog_admitted) in [(Scope::Host, true), (Scope::Site, false)] {
+            let crawler = crawler(|config| {
+                config.origin_urls = vec!["https://www.example.co.uk".to_string()];
+                config.max_pages_per_domain = Some(2);
+                config.scope = scope;
+            });
+            assert!(crawler.reserve_domain_page("https://www.example.co.uk/a"));
+            assert!(crawler.reserve_domain_page("https://www.example.co.uk/b"));
+            assert!(!crawler.reserve_domain_page("https://www.example.co.uk/c"));
+            assert_eq!(
+                crawler.reserve_domain_page("https://blog.example.co.uk/"),
+                blog_admitted
+            );
+            assert!(crawler.reserve_domain_page("https://other.org/"));
+        }
+    }
+
+    #[test]
+    fn links_past_depth_are_validated_with_head_when_configured() {
+        let crawler = crawler(|config| {
+            config.depth = 1;
+            config.validate_only = true;
+        });
+        let mut frontier = crawler.start_frontier(&set(&["a"]), 0);
+        crawler.queue_links(&mut frontier, &set(&["a/1"]), 2);
+        crawler.queue_links(&mut frontier, &set(&["a/1/x"]), 3);
+        assert_eq!(frontier.len(), 2);
+
+        assert_eq!(
+            crawler.plan_fetch("https://example.com/a", 1).1,
+            reqwest::Method::GET
+        );
+        assert_eq!(
+            crawler.plan_fetch("https://example.com/a/1", 2).1,
+            reqwest::Method::HEAD
+        );
+    }
+
+    #[test]
+    fn proxy_auth_failures_are_not_mistaken_for_the_site_being_down() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let error =
+            Crawler::check_proxy_auth(reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED, &url)
+                .unwrap_err();
+        assert_eq!(Crawler::categorize(&error), ErrorCategory::ProxyAuth);
+        assert!(Crawler::check_proxy_auth(reqwest::StatusCode::UNAUTHORIZED, &url).is_ok());
+
+        // A rejected CONNECT tunnel or SOCKS5 handshake fails the request instead
+        let error =
+            anyhow::anyhow!("Password auth failure, code: 1").context("socks connect error");
+        assert_eq!(Crawler::categorize(&error), ErrorCategory::ProxyAuth);
+        assert!(!ErrorCategory::ProxyAuth.is_transient());
+    }
+
+    #[test]
+    fn configured_headers_are_only_sent_to_matching_hosts() {
+        let secret = BTreeMap::from([
+            ("Authorization".to_string(), "Bearer token".to_string()),
+            ("X-Staging-Key".to_string(), "key".to_string()),
+        ]);
+        let everywhere = BTreeMap::from([("Accept-Language".to_string(), "en".to_string())]);
+        let crawler = crawler(|config| {
+            config.origin_urls = vec!["https://staging.example.com".to_string()];
+            config.headers = BTreeMap::from([
+                ("*".to_string(), everywhere),
+                ("staging.example.com".to_string(), secret),
+            ]);
+        });
+        let mut range = HeaderMap::new();
+        range.insert(RANGE, HeaderValue::from_static("bytes=0-0"));
+
+        let url = Url::parse("https://staging.example.com/page").unwrap();
+        let headers = crawler.request_headers(&url, &range);
+        assert_eq!(headers[reqwest::header::AUTHORIZATION], "Bearer token");
+        assert_eq!(headers["x-staging-key"], "key");
+        assert_eq!(headers[reqwest::header::ACCEPT_LANGUAGE], "en");
+        assert_eq!(headers[RANGE], "bytes=0-0");
+
+        for other in [
+            "https://example.com/",
+            "https://www.staging.example.com/",
+            "https://staging.example.com.evil.test/",
+        ] {
+            let headers = crawler.request_headers(&Url::parse(other).unwrap(), &range);
+            assert!(
+                !headers.contains_key(reqwest::header::AUTHORIZATION),
+                "{}",
+                other
+            );
+            assert!(!headers.contains_key("x-staging-key"), "{}", other);
+            assert_eq!(headers[reqwest::header::ACCEPT_LANGUAGE], "en");
+        }
+    }
+
+    /// Starts serving a site whose `/members` page is only served with the session cookie set
+    /// by `/`.
+    fn start_members_site() -> FixtureServer {
+        return FixtureServer::start(|request| {
+            let has_session = request
+                .header("cookie")
+                .is_some_and(|cookie| cookie.contains("session=abc"));
+            return match request.path.as_str() {
+                "/" => FixtureResponse::html(
+                    200,
+                    "<html><body><a href=\"/members\">Members</a></body></html>",
+                )
+                .header("Set-Cookie", "session=abc; Path=/"),
+                "/members" if has_session => FixtureResponse::html(200, "<html></html>"),
+                "/members" => FixtureResponse::html(403, "<html></html>"),
+                _ => FixtureResponse::html(404, "<html></html>"),
+            };
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn session_cookies_are_sent_back_only_with_the_cookie_store() {
+        let site = start_members_site();
+        let base = site.base();
+
+        for cookies in [false, true] {
+            let config = Config {
+                origin_urls: vec![format!("{}/", base)],
+                depth: 1,
+                cookies,
+                final_retry_limit: 0,
+                ..Config::default()
+            };
+            let crawler = Crawler::with_storage(
+                config,
+                Box::new(crate::database::Database::in_memory().unwrap()),
+            )
+            .unwrap();
+            crawler.crawl(true).unwrap();
+
+            let members = crawler
+                .storage
+                .get_site(&format!("{}/members", base))
+                .unwrap()
+                .unwrap();
+            let expected = if cookies { 200 } else { 403 };
+            assert_eq!(members.status_code, Some(expected), "cookies = {}", cookies);
+        }
+    }
+
+    #[test]
+    fn tls_validation_is_only_relaxed_for_trusted_https_hosts() {
+        let crawler = crawler(|config| {
+            config.origin_urls = vec!["https://staging.example.com".to_string()];
+            config.trusted_hosts = vec!["Staging.Example.com".to_string()];
+        });
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert!(crawler.relaxes_tls(&url("https://staging.example.com/page")));
+        assert!(!crawler.relaxes_tls(&url("http://staging.example.com/page")));
+        assert!(!crawler.relaxes_tls(&url("https://example.com/")));
+        assert!(!crawler.relaxes_tls(&url("https://www.staging.example.com/")));
+        assert_eq!(crawler.relaxed_tls_requests.load(Ordering::SeqCst), 1);
+
+        // The relaxed client is only used for the trusted host
+        crawler.async_client().unwrap();
+        assert!(crawler
+            .relaxed_async_client(&url("https://staging.example.com/"))
+            .is_some());
+        assert!(crawler
+            .relaxed_async_client(&url("https://example.com/"))
+            .is_none());
+    }
+
+    #[test]
+    fn header_and_cookie_floods_are_flagged_and_capped() {
+        let cookies: Vec<String> = (0..500).map(|i| format!("c{}=v", i)).collect();
+        let mut cookie_headers = vec![("content-type", "text/html")];
+        cookie_headers.extend(cookies.iter().map(|cookie| ("set-cookie", cookie.as_str())));
+        let padding = "x".repeat(100_000);
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .page(
+                    "https://example.com/",
+                    r#"<a href="/cookies">Cookies</a> <a href="/padded">Padded</a>"#,
+                )
+                .response(
+                    "https://example.com/cookies",
+                    200,
+                    &cookie_headers,
+                    br#"<a href="/next">Next</a>"#,
+                )
+                .response(
+                    "https://example.com/padded",
+                    200,
+                    &[("content-type", "text/html"), ("x-padding", &padding)],
+                    br#"<a href="/hidden">Hidden</a>"#,
+                ),
+        );
+        let crawler = crate::builder::CrawlerBuilder::new()
+            .origin("https://example.com/")
+            .depth(2)
+            .storage(Box::new(crate::storage::MemoryStorage::new()))
+            .fetcher(Box::new(fetcher.clone()))
+            .build()
+            .unwrap();
+        crawler.crawl(false).unwrap();
+
+        // The page setting 500 cookies is flagged, and only the first 50 are counted
+        let flooded = crawler
+            .storage
+            .get_site("https://example.com/cookies")
+            .unwrap()
+            .unwrap();
+        assert!(flooded.header_anomaly);
+        assert_eq!(flooded.cookies_set, 500);
+        assert_eq!(crawler.cookie_counts.lock().unwrap()["example.com"], 50);
+        assert!(fetcher.requested("https://example.com/next"));
+
+        // The body of the page with oversized headers is never parsed, and the crawl goes on
+        let padded = crawler
+            .storage
+            .get_site("https://example.com/padded")
+            .unwrap()
+            .unwrap();
+        assert!(padded.header_anomaly);
+        assert!(padded.links_to.is_empty());
+        assert!(!fetcher.requested("https://example.com/hidden"));
+    }
+
+    #[test]
+    fn suspect_crawls_are_left_out_of_latest_based_queries() {
+        let path =
+            std::env::temp_dir().join(format!("rustle-spider-{}-suspect.db", std::process::id()));
+        let crawl = |failing: bool| {
+            let links: String = (1..=9)
+                .map(|page| format!(r#"<a href="/{}">{}</a>"#, page, page))
+                .collect();
+            let mut fetcher =
+                crate::fetcher::MapFetcher::new().page("https://example.com/", &links);
+            for page in 1..=9 {
+                let url = format!("https://example.com/{}", page);
+                fetcher = match failing {
+                    true => fetcher.failure(&url, "connection refused"),
+                    false => fetcher.page(&url, "<p>Page</p>"),
+                };
+            }
+            let config = Config {
+                origin_urls: vec!["https://example.com/".to_string()],
+                database: path.display().to_string(),
+                depth: 1,
+                cache_ttl_hours: Some(0),
+                final_retry_limit: 0,
+                ..Config::default()
+            };
+            return crate::builder::CrawlerBuilder::from(config)
+                .fetcher(Box::new(fetcher))
+                .build()
+                .unwrap()
+                .crawl(false)
+                .unwrap();
+        };
+
+        // A healthy crawl, followed by one where the site fails
+        assert_eq!(crawl(false).session.status, SessionStatus::Accepted);
+        let between = Utc::now();
+        assert_eq!(crawl(true).session.status, SessionStatus::Suspect);
+
+        // Only the origin was stored again by the suspect crawl, and it is left out by default
+        let database = crate::database::Database::new(&path.display().to_string()).unwrap();
+        let recent = |include_suspect| {
+            return database
+                .sites_crawled_since(between, include_suspect)
+                .unwrap()
+                .map(|site| site.unwrap().url)
+                .collect::<Vec<String>>();
+        };
+        assert!(recent(false).is_empty());
+        assert_eq!(recent(true), ["https://example.com/"]);
+
+        // The latest run is the accepted one, unless suspect runs are included
+        let latest = |include_suspect| {
+            return crate::crawl_run::CrawlRun::read_latest(1, include_suspect, &database)
+                .unwrap()
+                .remove(0)
+                .status;
+        };
+        assert_eq!(latest(false), Some(SessionStatus::Accepted));
+        assert_eq!(latest(true), Some(SessionStatus::Suspect));
+
+        drop(database);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn scheme_flip_flops_mark_the_domain_without_faking_its_robots_txt() {
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .response(
+                "http://example.com/x",
+                301,
+                &[("location", "https://example.com/x")],
+                b"",
+            )
+            .response(
+                "https://example.com/x",
+                301,
+                &[("location", "http://example.com/x")],
+                b"",
+            );
+        let crawler = crawler_with(fetcher, |config| {
+            config.origin_urls = vec!["http://example.com/x".to_string()];
+            config.respect_robots = false;
+        });
+        crawler.crawl(false).unwrap();
+
+        let domain = crawler.storage.get_domain("example.com").unwrap().unwrap();
+        assert!(domain.broken_tls_redirect);
+
+        // A domain first stored by the flag is dated at the epoch, which a later crawl takes as a
+        // robots.txt to fetch rather than as a fresh one allowing everything
+        let database = crate::database::Database::in_memory().unwrap();
+        database.setup().unwrap();
+        let storages: [Box<dyn Storage>; 2] = [
+            Box::new(crate::storage::MemoryStorage::new()),
+            Box::new(database),
+        ];
+        for storage in storages {
+            storage.mark_broken_tls_redirect("example.com").unwrap();
+            let domain = storage.get_domain("example.com").unwrap().unwrap();
+            assert!(domain.broken_tls_redirect);
+            assert_eq!(domain.crawl_time, DateTime::UNIX_EPOCH);
+        }
+    }
+
+    #[test]
+    fn crawl_requests_exactly_the_in_scope_allowed_pages_within_depth() {
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://example.com/robots.txt",
+                    200,
+                    &[("content-type", "text/plain")],
+                    b"User-agent: *\nDisallow: /private\n",
+                )
+                .page(
+                    "https://example.com/",
+                    r#"<a href="/a">A</a> <a href="/b">B</a> <a href="/private">Private</a>
+                    <a href="https://other.com/">Other</a>"#,
+                )
+                .page("https://example.com/a", r#"<a href="/deep">Deep</a>"#)
+                .page(
+                    "https://example.com/b",
+                    r#"<a href="/">Home</a> <a href="/a">A</a>"#,
+                )
+                .page(
+                    "https://example.com/deep",
+                    r#"<a href="/deeper">Deeper</a>"#,
+                ),
+        );
+        let crawler = crate::builder::CrawlerBuilder::new()
+            .origin("https://example.com/")
+            .depth(2)
+            .storage(Box::new(crate::storage::MemoryStorage::new()))
+            .fetcher(Box::new(fetcher.clone()))
+            .build()
+            .unwrap();
+        crawler.crawl(false).unwrap();
+
+        // The pages of a level are fetched in parallel, so only the robots.txt file comes first.
+        let mut requests: Vec<String> = fetcher
+            .requests()
+            .into_iter()
+            .map(|(method, url)| format!("{} {}", method, url))
+            .collect();
+        assert_eq!(requests[0], "GET https://example.com/robots.txt");
+        requests.sort();
+        assert_eq!(
+            requests,
+            [
+                "GET https://example.com/",
+                "GET https://example.com/a",
+                "GET https://example.com/b",
+                "GET https://example.com/deep",
+                "GET https://example.com/robots.txt",
+            ]
+        );
+    }
+
+    /// Crawls a page linking to a feed at depth 2, with the given feed policy.
+    fn crawl_feed(feeds: FetchPolicy) -> (Crawler, Arc<crate::fetcher::MapFetcher>) {
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .page("https://example.com/", r#"<a href="/news.rss">News</a>"#)
+                .response(
+                    "https://example.com/news.rss",
+                    200,
+                    &[("content-type", "application/rss+xml")],
+                    b"<rss><channel><item><link>/posts/1</link></item></channel></rss>",
+                )
+                .page("https://example.com/posts/1", "<p>First</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.depth = 2;
+            config.respect_robots = false;
+            config.fetch_policy.feeds = feeds;
+        });
+        crawler.crawl(false).unwrap();
+        return (crawler, fetcher);
+    }
+
+    #[test]
+    fn feeds_fetched_in_full_have_their_entries_crawled() {
+        let (_, fetcher) = crawl_feed(FetchPolicy::Full);
+        assert!(fetcher.requests().contains(&(
+            reqwest::Method::GET,
+            "https://example.com/news.rss".to_string()
+        )));
+        assert!(fetcher.requested("https://example.com/posts/1"));
+
+        let (_, fetcher) = crawl_feed(FetchPolicy::Head);
+        assert!(fetcher.requests().contains(&(
+            reqwest::Method::HEAD,
+            "https://example.com/news.rss".to_string()
+        )));
+        assert!(!fetcher.requested("https://example.com/posts/1"));
+
+        let (_, fetcher) = crawl_feed(FetchPolicy::Skip);
+        assert!(!fetcher.requested("https://example.com/news.rss"));
+    }
+
+    #[test]
+    fn bytes_received_are_counted_before_decoding() {
+        // "café" is 4 bytes in Latin-1, but 5 once decoded to UTF-8
+        let body = b"caf\xe9";
+        let fetcher = crate::fetcher::MapFetcher::new().response(
+            "https://example.com/",
+            200,
+            &[("content-type", "text/html; charset=iso-8859-1")],
+            body,
+        );
+        let crawler = crawler_with(fetcher, |config| {
+            config.respect_robots = false;
+        });
+        let report = crawler.crawl(false).unwrap();
+
+        let kind_stats = crawler.kind_stats.lock().unwrap();
+        assert_eq!(kind_stats[&ResourceKind::Page], (1, body.len() as u64));
+        assert_eq!(report.bytes_downloaded, body.len() as u64);
+    }
+
+    #[test]
+    fn latin1_pages_round_trip_their_links() {
+        let fetcher = crate::fetcher::MapFetcher::new().response(
+            "https://example.com/",
+            200,
+            &[("content-type", "text/html; charset=iso-8859-1")],
+            b"<a href=\"/caf\xe9\">Caf\xe9 cr\xe8me</a>",
+        );
+        let crawler = crawler_with(fetcher, |config| {
+            config.respect_robots = false;
+        });
+        crawler.crawl(false).unwrap();
+
+        let site = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            site.links_to,
+            HashSet::from(["https://example.com/caf%C3%A9".to_string()])
+        );
+        assert_eq!(
+            site.anchor_texts["https://example.com/caf%C3%A9"],
+            "Café crème"
+        );
+    }
+
+    #[test]
+    fn disk_limit_first_drops_text_then_truncates_the_crawl() {
+        let path = std::env::temp_dir().join(format!("rustle-disk-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .page("https://example.com/", r#"<a href="/a">A</a>"#)
+                .page("https://example.com/a", "<p>A</p>"),
+        );
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            database: path.display().to_string(),
+            depth: 1,
+            respect_robots: false,
+            max_disk_mb: Some(1),
+            store_text: true,
+            ..Config::default()
+        };
+        let crawler = crate::builder::CrawlerBuilder::from(config)
+            .fetcher(Box::new(fetcher.clone()))
+            .build()
+            .unwrap();
+
+        // Grow the database file behind the crawler's back, measuring it again every time
+        let grow_to = |bytes: u64| {
+            let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            let others = crawler.storage.disk_bytes() - file.metadata().unwrap().len();
+            file.set_len(bytes - others).unwrap();
+            *crawler.last_disk_check.lock().unwrap() = None;
+        };
+        assert!(crawler.check_disk());
+        assert!(crawler.store_text.load(Ordering::SeqCst));
+
+        grow_to(950 * 1024);
+        assert!(crawler.check_disk());
+        assert!(!crawler.store_text.load(Ordering::SeqCst));
+        assert!(!crawler.truncated_for_disk.load(Ordering::SeqCst));
+
+        grow_to(1024 * 1024);
+        assert!(!crawler.check_disk());
+        let report = crawler.crawl(false).unwrap();
+        assert!(report.session.truncated_for_disk);
+        assert!(!fetcher.requested("https://example.com/a"));
+
+        drop(crawler);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn pages_turned_noindex_drop_out_of_search() {
+        let path = std::env::temp_dir().join(format!("rustle-noindex-{}.db", std::process::id()));
+        let crawl = |html: &str| {
+            let config = Config {
+                origin_urls: vec!["https://example.com/".to_string()],
+                database: path.display().to_string(),
+                depth: 1,
+                respect_robots: false,
+                store_text: true,
+                cache_ttl_hours: Some(0),
+                ..Config::default()
+            };
+            let fetcher = crate::fetcher::MapFetcher::new().page("https://example.com/", html);
+            crate::builder::CrawlerBuilder::from(config)
+                .fetcher(Box::new(fetcher))
+                .build()
+                .unwrap()
+                .crawl(false)
+                .unwrap();
+        };
+        let database = crate::database::Database::new(&path.display().to_string()).unwrap();
+
+        crawl("<p>Searchable crawler</p>");
+        assert_eq!(database.search_text("crawler", 10).unwrap().len(), 1);
+        crawl(r#"<meta name="robots" content="noindex"><p>Searchable crawler</p>"#);
+        assert!(database.search_text("crawler", 10).unwrap().is_empty());
+
+        drop(database);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn copies_stored_without_a_hash_are_neither_changed_nor_unchanged() {
+        let storage = crate::storage::MemoryStorage::new();
+        let stored = |url: &str, content_hash: Option<String>| Site {
+            url: url.to_string(),
+            crawl_time: Utc::now(),
+            content_hash,
+            ..Default::default()
+        };
+        let home = r#"<a href="/same">Same</a> <a href="/new">New</a>"#;
+        storage
+            .put_sites(&[
+                stored("https://example.com/", None),
+                stored(
+                    "https://example.com/same",
+                    Some(Site::hash_content("<p>Same</p>")),
+                ),
+            ])
+            .unwrap();
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .page("https://example.com/", home)
+            .page("https://example.com/same", "<p>Same</p>")
+            .page("https://example.com/new", "<p>New</p>");
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            depth: 1,
+            respect_robots: false,
+            cache_ttl_hours: Some(0),
+            ..Config::default()
+        };
+        let crawler = crate::builder::CrawlerBuilder::from(config)
+            .storage(Box::new(storage))
+            .fetcher(Box::new(fetcher))
+            .build()
+            .unwrap();
+        crawler.crawl(false).unwrap();
+
+        assert_eq!(crawler.pages_new.load(Ordering::SeqCst), 1);
+        assert_eq!(crawler.pages_changed.load(Ordering::SeqCst), 0);
+        assert_eq!(crawler.pages_unchanged.load(Ordering::SeqCst), 1);
+        let home = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert!(home.content_hash.is_some());
+        assert_eq!(home.last_changed, None);
+    }
+
+    /// Crawls a page answering `304 Not Modified` with a body, over the given stored copy.
+    fn crawl_not_modified(stored: Option<Site>) -> (Crawler, CrawlReport) {
+        let storage = crate::storage::MemoryStorage::new();
+        if let Some(stored) = stored {
+            storage.put_site(&stored).unwrap();
+        }
+        let fetcher = crate::fetcher::MapFetcher::new().response(
+            "https://example.com/",
+            304,
+            &[("content-type", "text/html")],
+            br#"<a href="/unread">Unread</a>"#,
+        );
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            depth: 1,
+            respect_robots: false,
+            cache_ttl_hours: Some(0),
+            ..Config::default()
+        };
+        let crawler = crate::builder::CrawlerBuilder::from(config)
+            .storage(Box::new(storage))
+            .fetcher(Box::new(fetcher))
+            .build()
+            .unwrap();
+        let report = crawler.crawl(false).unwrap();
+        return (crawler, report);
+    }
+
+    #[test]
+    fn not_modified_pages_keep_their_stored_copy_without_reading_the_body() {
+        let crawled_before = Utc::now() - chrono::Duration::days(1);
+        let (crawler, report) = crawl_not_modified(Some(Site {
+            url: "https://example.com/".to_string(),
+            crawl_time: crawled_before,
+            links_to: HashSet::from(["https://example.com/kept".to_string()]),
+            title: Some("Kept".to_string()),
+            etag: Some("\"v1\"".to_string()),
+            ..Default::default()
+        }));
+
+        assert!(report.fetch_errors.is_empty());
+        let site = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert!(site.crawl_time > crawled_before);
+        assert_eq!(site.title.as_deref(), Some("Kept"));
+        assert_eq!(
+            site.links_to,
+            HashSet::from(["https://example.com/kept".to_string()])
+        );
+        assert_eq!(crawler.kind_stats.lock().unwrap()[&ResourceKind::Page].1, 0);
+    }
+
+    #[test]
+    fn not_modified_pages_without_a_stored_copy_are_failures() {
+        let (crawler, report) = crawl_not_modified(None);
+
+        assert_eq!(report.fetch_errors.len(), 1);
+        assert_eq!(report.fetch_errors[0].url, "https://example.com/");
+        assert!(crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .is_none());
+    }
+
+    /// Serves a page at `/` whose body arrives one byte every `byte_delay`, or stops after its
+    /// first bytes without `byte_delay`, and returns its URL. Other paths are not found.
+    fn serve_slowly(byte_delay: Option<Duration>) -> String {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                std::thread::spawn(move || {
+                    let mut request = [0; 4096];
+                    let read = stream.read(&mut request).unwrap_or_default();
+                    if !request[..read].starts_with(b"GET / ") {
+                        let _ = stream.write_all(
+                            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        );
+                        return;
+                    }
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 100000\r\n\r\n<p>",
+                    );
+                    let _ = stream.flush();
+                    match byte_delay {
+                        Some(byte_delay) => loop {
+                            std::thread::sleep(byte_delay);
+                            if stream.write_all(b"x").and_then(|_| stream.flush()).is_err() {
+                                return;
+                            }
+                        },
+                        None => std::thread::sleep(Duration::from_secs(60)),
+                    }
+                });
+            }
+        });
+        return url;
+    }
+
+    #[test]
+    fn trickling_and_stalled_bodies_are_aborted_in_bounded_time() {
+        for byte_delay in [Some(Duration::from_millis(100)), None] {
+            let url = serve_slowly(byte_delay);
+            let crawler = crawler(|config| {
+                config.origin_urls = vec![url.clone()];
+                config.respect_robots = false;
+                config.min_transfer_rate = 1024;
+                config.slow_transfer_secs = 1;
+                config.final_retry_limit = 0;
+            });
+
+            let start = Instant::now();
+            let report = crawler.crawl(false).unwrap();
+            assert!(start.elapsed() < Duration::from_secs(10));
+            assert_eq!(report.fetch_errors.len(), 1);
+            assert_eq!(report.fetch_errors[0].url, url);
+            assert_eq!(report.fetch_errors[0].category, ErrorCategory::TooSlow);
+            assert!(report.fetch_errors[0].category.is_transient());
+        }
+    }
+
+    #[test]
+    fn readers_of_stalled_bodies_end_with_the_fetcher_timeout() {
+        let url = serve_slowly(None);
+        let crawler = crawler(|config| {
+            config.origin_urls = vec![url.clone()];
+            config.respect_robots = false;
+            config.min_transfer_rate = 1024;
+            config.slow_transfer_secs = 1;
+            config.timeout_secs = 3;
+            config.final_retry_limit = 0;
+        });
+        let report = crawler.crawl(false).unwrap();
+        assert_eq!(report.fetch_errors[0].category, ErrorCategory::TooSlow);
+
+        // The reader given up on is released once its read times out, long before the server
+        // would send anything again
+        let start = Instant::now();
+        while crawler.body_readers.load(Ordering::SeqCst) > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn bodies_are_read_on_the_worker_past_the_stalled_reader_limit() {
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .page("https://example.com/", r#"<a href="/about">About</a>"#);
+        let crawler = crawler_with(fetcher, |config| {
+            config.respect_robots = false;
+        });
+        let stalled = crawler.pool.current_num_threads() + MAX_STALLED_BODY_READERS;
+        crawler.body_readers.store(stalled, Ordering::SeqCst);
+
+        crawler.crawl(false).unwrap();
+        assert_eq!(crawler.body_readers.load(Ordering::SeqCst), stalled);
+        let site = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert!(site.links_to.contains("https://example.com/about"));
+    }
+
+    /// Serves a page at `/` whose body never ends, without announcing its length, until the
+    /// client hangs up, and returns its URL. Other paths are not found.
+    fn serve_endless_body() -> String {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                std::thread::spawn(move || {
+                    let mut request = [0; 4096];
+                    let read = stream.read(&mut request).unwrap_or_default();
+                    if !request[..read].starts_with(b"GET / ") {
+                        let _ = stream.write_all(
+                            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        );
+                        return;
+                    }
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<p>",
+                    );
+                    let chunk = [b'x'; 8192];
+                    while stream.write_all(&chunk).is_ok() {}
+                });
+            }
+        });
+        return url;
+    }
+
+    /// Crawls a page whose body never ends with a limit of 64 KiB, blocking or async, and checks
+    /// that it is aborted as too large once the limit is passed.
+    fn assert_endless_body_is_aborted(run_async: bool) {
+        let url = serve_endless_body();
+        let crawler = crawler(|config| {
+            config.origin_urls = vec![url.clone()];
+            config.respect_robots = false;
+            config.max_body_bytes = 64 * 1024;
+            config.final_retry_limit = 0;
+        });
+
+        let start = Instant::now();
+        let report = if run_async {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(crawler.crawl_async(false))
+                .unwrap()
+        } else {
+            crawler.crawl(false).unwrap()
+        };
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(report.fetch_errors.len(), 1);
+        assert_eq!(report.fetch_errors[0].url, url);
+        assert_eq!(report.fetch_errors[0].category, ErrorCategory::TooLarge);
+        assert!(crawler.storage.get_site(&url).unwrap().is_none());
+    }
+
+    #[test]
+    fn bodies_streamed_past_the_limit_are_aborted() {
+        assert_endless_body_is_aborted(false);
+    }
+
+    #[test]
+    fn bodies_streamed_past_the_limit_are_aborted_async() {
+        assert_endless_body_is_aborted(true);
+    }
+
+    #[test]
+    fn generations_list_the_pages_and_failures_of_the_last_crawl_to_reach_them() {
+        let path =
+            std::env::temp_dir().join(format!("rustle-generation-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let crawl = |fetcher: crate::fetcher::MapFetcher| {
+            let config = Config {
+                origin_urls: vec!["https://example.com/".to_string()],
+                database: path.display().to_string(),
+                depth: 1,
+                respect_robots: false,
+                cache_ttl_hours: Some(0),
+                ..Config::default()
+            };
+            return crate::builder::CrawlerBuilder::from(config)
+                .fetcher(Box::new(fetcher))
+                .build()
+                .unwrap()
+                .crawl(false)
+                .unwrap();
+        };
+        let links = r#"<a href="/a">A</a><a href="/b">B</a><a href="/c">C</a><a href="/d">D</a>"#;
+        let generation = |database: &crate::database::Database| {
+            let pages: Vec<String> = database
+                .sites_in_generation(1)
+                .unwrap()
+                .map(|site| site.unwrap().url)
+                .collect();
+            let failures: Vec<String> = FetchFailure::in_generation(database, 1)
+                .unwrap()
+                .into_iter()
+                .map(|failure| failure.url)
+                .collect();
+            return (pages, failures);
+        };
+
+        crawl(
+            crate::fetcher::MapFetcher::new()
+                .page("https://example.com/", links)
+                .page("https://example.com/a", "<p>A</p>")
+                .page("https://example.com/b", "<p>B</p>")
+                .page("https://example.com/c", "<p>C</p>")
+                .failure("https://example.com/d", "connection reset"),
+        );
+        let database = crate::database::Database::new(&path.display().to_string()).unwrap();
+        assert_eq!(
+            generation(&database),
+            (
+                vec![
+                    "https://example.com/a".to_string(),
+                    "https://example.com/b".to_string(),
+                    "https://example.com/c".to_string()
+                ],
+                vec!["https://example.com/d".to_string()]
+            )
+        );
+        drop(database);
+
+        // The second crawl fails halfway through the generation: the copies of /b and /c stored
+        // by the first crawl must not be listed as part of it
+        let report = crawl(
+            crate::fetcher::MapFetcher::new()
+                .page("https://example.com/", links)
+                .page("https://example.com/a", "<p>A</p>")
+                .failure("https://example.com/b", "connection reset")
+                .failure("https://example.com/c", "connection reset")
+                .page("https://example.com/d", "<p>D</p>"),
+        );
+        assert_eq!(report.fetch_errors.len(), 2);
+        let database = crate::database::Database::new(&path.display().to_string()).unwrap();
+        assert_eq!(
+            generation(&database),
+            (
+                vec![
+                    "https://example.com/a".to_string(),
+                    "https://example.com/d".to_string()
+                ],
+                vec![
+                    "https://example.com/b".to_string(),
+                    "https://example.com/c".to_string()
+                ]
+            )
+        );
+        let failures = FetchFailure::in_generation(&database, 1).unwrap();
+        assert!(failures
+            .iter()
+            .all(|failure| failure.generation == Some(1) && failure.batch_id.is_some()));
+        assert_eq!(
+            failures[0].run_id,
+            database
+                .read_site("https://example.com/a")
+                .unwrap()
+                .unwrap()
+                .run_id
+        );
+        assert!(database.sites_in_generation(2).unwrap().next().is_none());
+
+        drop(database);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Serves the responses of one of two `MapFetcher`s, switching to the second one on demand.
+    struct SwitchingFetcher {
+        fetchers: [crate::fetcher::MapFetcher; 2],
+        current: AtomicU64,
+    }
+
+    impl Fetcher for SwitchingFetcher {
+        fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse> {
+            return self.fetchers[self.current.load(Ordering::SeqCst) as usize].fetch(request);
+        }
+    }
+
+    #[test]
+    fn compliance_records_survive_a_robots_refresh_mid_crawl() {
+        let path =
+            std::env::temp_dir().join(format!("rustle-compliance-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let site = |robots: &str| {
+            return crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://example.com/robots.txt",
+                    200,
+                    &[("content-type", "text/plain")],
+                    robots.as_bytes(),
+                )
+                .page("https://example.com/a", "<p>A</p>")
+                .page("https://example.com/b", "<p>B</p>");
+        };
+        let first = "User-agent: *\nAllow: /a\n";
+        let second = "User-agent: *\nDisallow: /private\n";
+        let fetcher = Arc::new(SwitchingFetcher {
+            fetchers: [site(first), site(second)],
+            current: AtomicU64::new(0),
+        });
+        let config = Config {
+            origin_urls: vec!["https://example.com/".to_string()],
+            database: path.display().to_string(),
+            depth: 1,
+            respect_robots: true,
+            compliance: true,
+            ..Config::default()
+        };
+        let crawler = crate::builder::CrawlerBuilder::from(config)
+            .fetcher(Box::new(fetcher.clone()))
+            .build()
+            .unwrap();
+
+        crawler.storage.setup().unwrap();
+
+        // /a is admitted under the first robots.txt, which then changes and is refreshed before
+        // /b is admitted, and both are fetched after the refresh
+        assert!(crawler
+            .is_allowed_to_scrape("https://example.com/a")
+            .unwrap());
+        fetcher.current.store(1, Ordering::SeqCst);
+        let stored = crawler.storage.get_domain("example.com").unwrap().unwrap();
+        crawler
+            .storage
+            .put_domain(&Domain {
+                crawl_time: DateTime::UNIX_EPOCH,
+                ..stored
+            })
+            .unwrap();
+        crawler.robots_cache.lock().unwrap().clear();
+        assert!(crawler
+            .is_allowed_to_scrape("https://example.com/b")
+            .unwrap());
+        let batch = crawler.next_batch(1);
+        crawler.fetch_and_process_links("https://example.com/a", batch);
+        crawler.fetch_and_process_links("https://example.com/b", batch);
+        crawler.flush_sites();
+
+        let database = crate::database::Database::new(&path.display().to_string()).unwrap();
+        let record = |url: &str| {
+            let site = database.read_site(url).unwrap().unwrap();
+            let snapshot = crate::robots_history::RobotsSnapshot::read_into(
+                site.robots_snapshot.unwrap(),
+                &database,
+            )
+            .unwrap()
+            .unwrap();
+            return (snapshot.robots, site.robots_rule.unwrap());
+        };
+        assert_eq!(
+            record("https://example.com/a"),
+            (first.to_string(), "Allow: /a".to_string())
+        );
+        assert_eq!(
+            record("https://example.com/b"),
+            (second.to_string(), "no rules".to_string())
+        );
+
+        drop(database);
+        drop(crawler);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn redirects_to_urls_disallowed_by_robots_are_not_followed() {
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://example.com/robots.txt",
+                    200,
+                    &[("content-type", "text/plain")],
+                    b"User-agent: *\nDisallow: /private\n",
+                )
+                .page("https://example.com/", r#"<a href="/old">Old</a>"#)
+                .response(
+                    "https://example.com/old",
+                    301,
+                    &[("location", "/private/new")],
+                    b"",
+                )
+                .page("https://example.com/private/new", "<p>Private</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.respect_robots = true;
+        });
+        crawler.crawl(false).unwrap();
+
+        assert!(fetcher.requested("https://example.com/old"));
+        assert!(!fetcher.requested("https://example.com/private/new"));
+        let old = crawler
+            .storage
+            .get_site("https://example.com/old")
+            .unwrap()
+            .unwrap();
+        assert!(old.is_redirect);
+        assert_eq!(old.final_url.as_deref(), Some("https://example.com/old"));
+        assert!(old.links_to.contains("https://example.com/private/new"));
+        assert!(crawler
+            .storage
+            .get_site("https://example.com/private/new")
+            .unwrap()
+            .is_none());
+    }
+
+    /// Crawls an origin that redirects to its `www` subdomain, which links to an SSO page on
+    /// another site that redirects elsewhere, in a scope.
+    fn crawl_apex_redirect(
+        scope: Scope,
+    ) -> (
+        Result<CrawlReport>,
+        Arc<crate::fetcher::MapFetcher>,
+        Crawler,
+    ) {
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://example.com/",
+                    301,
+                    &[("location", "https://www.example.com/")],
+                    b"",
+                )
+                .page("https://www.example.com/", r#"<a href="/login">Log in</a>"#)
+                .response(
+                    "https://www.example.com/login",
+                    302,
+                    &[("location", "https://sso.example.org/")],
+                    b"",
+                )
+                .page("https://sso.example.org/", "<p>SSO</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.respect_robots = false;
+            config.scope = scope;
+        });
+        return (crawler.crawl(false), fetcher, crawler);
+    }
+
+    #[test]
+    fn redirects_within_the_scope_are_followed() {
+        let (report, fetcher, crawler) = crawl_apex_redirect(Scope::Subdomains);
+        report.unwrap();
+        assert!(fetcher.requested("https://www.example.com/"));
+        assert!(fetcher.requested("https://www.example.com/login"));
+        assert!(!fetcher.requested("https://sso.example.org/"));
+
+        // The unfollowed redirect is stored like the redirects of a followed chain
+        let login = crawler
+            .storage
+            .get_site("https://www.example.com/login")
+            .unwrap()
+            .unwrap();
+        assert!(login.is_redirect);
+        assert_eq!(
+            login.final_url.as_deref(),
+            Some("https://www.example.com/login")
+        );
+        assert_eq!(login.redirect_hops, Some(0));
+        assert!(login.links_to.contains("https://sso.example.org/"));
+        let origin = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        assert!(origin.is_redirect);
+        assert_eq!(
+            origin.final_url.as_deref(),
+            Some("https://www.example.com/")
+        );
+        assert_eq!(origin.redirect_hops, Some(1));
+    }
+
+    #[test]
+    fn origins_redirected_out_of_scope_abort_the_crawl() {
+        let (report, fetcher, _) = crawl_apex_redirect(Scope::Host);
+        let error = format!("{:#}", report.err().unwrap());
+        assert!(
+            error.contains("redirects to https://www.example.com/"),
+            "{}",
+            error
+        );
+        assert!(!fetcher.requested("https://www.example.com/"));
+    }
+
+    #[test]
+    fn reports_count_only_what_the_crawl_stored() {
+        let fetcher = crate::fetcher::MapFetcher::new()
+            .page("https://example.com/robots.txt", "User-agent: *\nAllow: /")
+            .page(
+                "https://example.com/",
+                "<a href=\"https://example.com/about\">About</a>",
+            )
+            .page("https://example.com/about", "About");
+        let crawler = crawler_with(fetcher, |config| {
+            config.depth = 2;
+        });
+
+        // A site and a domain stored by an earlier crawl
+        crawler.storage.setup().unwrap();
+        crawler
+            .storage
+            .put_site(&Site {
+                url: "https://example.org/".to_string(),
+                crawl_time: DateTime::UNIX_EPOCH,
+                status_code: Some(404),
+                ..Default::default()
+            })
+            .unwrap();
+        crawler
+            .storage
+            .put_domain(&Domain {
+                domain: "example.org".to_string(),
+                crawl_time: DateTime::UNIX_EPOCH,
+                robots: String::new(),
+                robots_url: None,
+                sitemaps: Vec::new(),
+                feeds: Vec::new(),
+                robots_override: None,
+                broken_tls_redirect: false,
+            })
+            .unwrap();
+
+        let report = crawler.crawl(false).unwrap();
+        assert_eq!(report.stored_sites, [(Some(2), 2)].into());
+        assert_eq!(report.stored_domains, 1);
+        let hosts: Vec<&str> = report
+            .stored_by_domain
+            .iter()
+            .map(|summary| summary.host.as_str())
+            .collect();
+        assert_eq!(hosts, ["example.com"]);
+        assert_eq!(crawler.storage.site_count().unwrap(), 3);
+        assert_eq!(crawler.storage.domain_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn async_crawls_run_their_blocking_work_on_the_worker_threads() {
+        let site = start_members_site();
+        let base = site.base();
+
+        let config = Config {
+            origin_urls: vec![format!("{}/", base)],
+            depth: 1,
+            cookies: true,
+            concurrency: Some(2),
+            final_retry_limit: 0,
+            ..Config::default()
+        };
+        let crawler = Crawler::with_storage(
+            config,
+            Box::new(crate::database::Database::in_memory().unwrap()),
+        )
+        .unwrap();
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // The crawl can be spawned, as it holds no reference across threads that isn't `Sync`
+        let crawl = runtime.spawn(async move {
+            let report = crawler.crawl_async(true).await;
+            return (crawler, report);
+        });
+        let (crawler, report) = runtime.block_on(crawl).unwrap();
+        assert_eq!(report.unwrap().pages_fetched, 2);
+        let members = crawler
+            .storage
+            .get_site(&format!("{}/members", base))
+            .unwrap()
+            .unwrap();
+        assert_eq!(members.status_code, Some(200));
+    }
+
+    #[test]
+    fn async_crawls_refuse_current_thread_runtimes() {
+        let crawler = crawler(|_| {});
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let error = runtime.block_on(crawler.crawl_async(true)).err().unwrap();
+        assert!(error.to_string().contains("multi-threaded"));
+    }
+
+    /// Starts serving a site from its responses by path, answering every request after 50 ms,
+    /// so that concurrent requests overlap. Other paths are not found.
+    fn start_site(pages: HashMap<String, FixtureResponse>) -> FixtureServer {
+        return FixtureServer::start(move |request| {
+            std::thread::sleep(Duration::from_millis(50));
+            return pages
+                .get(&request.path)
+                .cloned()
+                .unwrap_or_else(|| FixtureResponse::html(404, ""));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn requests_in_flight_to_a_host_never_exceed_the_cap() {
+        let mut pages = HashMap::new();
+        let links: String = (0..12)
+            .map(|i| format!("<a href=\"/p{}\">Page {}</a>", i, i))
+            .collect();
+        pages.insert(
+            "/".to_string(),
+            FixtureResponse::html(200, format!("<html><body>{}</body></html>", links)),
+        );
+        for i in 0..12 {
+            pages.insert(
+                format!("/p{}", i),
+                FixtureResponse::html(200, "<html></html>"),
+            );
+        }
+
+        for run_async in [false, true] {
+            let site = start_site(pages.clone());
+            let base = site.base();
+            let crawler = crawler(|config| {
+                config.origin_urls = vec![format!("{}/", base)];
+                config.concurrency = Some(8);
+                config.max_concurrent_per_host = 2;
+                config.final_retry_limit = 0;
+            });
+            let report = if run_async {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(crawler.crawl_async(true))
+                    .unwrap()
+            } else {
+                crawler.crawl(true).unwrap()
+            };
+
+            assert_eq!(report.pages_fetched, 13, "async: {}", run_async);
+            assert_eq!(
+                site.peak(),
+                2,
+                "async: {}, the cap must be reached but never exceeded",
+                run_async
+            );
+        }
+    }
+
+    #[test]
+    fn per_url_failures_are_reported_without_failing_the_crawl() {
+        let mut pages = HashMap::new();
+        pages.insert(
+            "/".to_string(),
+            FixtureResponse::html(200, "<html><body><a href=\"http://[malformed/\">Malformed</a><a href=\"/loop\">Loop</a><a href=\"/ok\">OK</a></body></html>"
+                    .to_string()),
+        );
+        pages.insert(
+            "/loop".to_string(),
+            FixtureResponse::new(301, "").header("Location", "/loop/next"),
+        );
+        pages.insert(
+            "/loop/next".to_string(),
+            FixtureResponse::new(301, "").header("Location", "/loop"),
+        );
+        pages.insert(
+            "/ok".to_string(),
+            FixtureResponse::html(200, "<html></html>"),
+        );
+        let site = start_site(pages);
+        let base = site.base();
+
+        let crawler = crawler(|config| {
+            config.origin_urls = vec![format!("{}/", base)];
+            config.final_retry_limit = 0;
+        });
+        let report = crawler.crawl(true).unwrap();
+
+        let reported = |url: &str, category: ErrorCategory| {
+            return report
+                .fetch_errors
+                .iter()
+                .any(|error| error.url == url && error.category == category);
+        };
+        assert!(reported("http://[malformed/", ErrorCategory::InvalidUrl));
+        assert!(reported(&format!("{}/loop", base), ErrorCategory::Redirect));
+        assert!(crawler
+            .storage
+            .get_site(&format!("{}/ok", base))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn links_without_an_http_host_are_skipped() {
+        let mut pages = HashMap::new();
+        pages.insert(
+            "/".to_string(),
+            FixtureResponse::html(200, "<html><body><a href=\"mailto:team@example.com\">Mail</a><a href=\"tel:+15550100\">Call</a><a href=\"javascript:void(0)\">Menu</a><a href=\"/ok\">OK</a></body></html>"
+                    .to_string()),
+        );
+        pages.insert(
+            "/ok".to_string(),
+            FixtureResponse::html(200, "<html></html>"),
+        );
+        let site = start_site(pages);
+        let base = site.base();
+
+        let crawler = crawler(|config| {
+            config.origin_urls = vec![format!("{}/", base)];
+        });
+        for url in [
+            "mailto:team@example.com",
+            "tel:+15550100",
+            "javascript:void(0)",
+        ] {
+            assert_eq!(crawler.normalize_url(url), None);
+        }
+
+        let report = crawler.crawl(true).unwrap();
+        assert!(report.fetch_errors.is_empty());
+        let origin = crawler
+            .storage
+            .get_site(&format!("{}/", base))
+            .unwrap()
+            .unwrap();
+        let expected: std::collections::HashSet<String> = [format!("{}/ok", base)].into();
+        assert_eq!(origin.links_to, expected);
+        assert!(crawler
+            .storage
+            .get_site(&format!("{}/ok", base))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn stale_robots_are_fetched_again_exactly_once() {
+        let fresh_robots = "User-agent: *\nDisallow: /private\n";
+        let fetcher = Arc::new(crate::fetcher::MapFetcher::new().response(
+            "https://example.com/robots.txt",
+            200,
+            &[("content-type", "text/plain")],
+            fresh_robots.as_bytes(),
+        ));
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.respect_robots = true;
+            config.robots_ttl_hours = 24;
+        });
+
+        // A copy stored two days ago disallows everything
+        let stale_time = Utc::now() - chrono::Duration::hours(48);
+        crawler
+            .storage
+            .put_domain(&Domain {
+                domain: "example.com".to_string(),
+                crawl_time: stale_time,
+                robots: "User-agent: *\nDisallow: /\n".to_string(),
+                robots_url: Some("https://example.com/robots.txt".to_string()),
+                sitemaps: Vec::new(),
+                feeds: Vec::new(),
+                robots_override: None,
+                broken_tls_redirect: false,
+            })
+            .unwrap();
+        let robots_requests = || {
+            return fetcher
+                .requests()
+                .iter()
+                .filter(|(_, url)| url.ends_with("/robots.txt"))
+                .count();
+        };
+
+        // Only the first check fetches robots.txt, and the later ones obey the fresh copy
+        assert!(crawler
+            .is_allowed_to_scrape("https://example.com/a")
+            .unwrap());
+        assert!(!crawler
+            .is_allowed_to_scrape("https://example.com/private/secret")
+            .unwrap());
+        assert!(crawler
+            .is_allowed_to_scrape("https://example.com/b")
+            .unwrap());
+        assert_eq!(robots_requests(), 1);
+
+        // The fresh copy replaced the stored one, so it is read again rather than fetched
+        let stored = crawler.storage.get_domain("example.com").unwrap().unwrap();
+        assert_eq!(stored.robots, fresh_robots);
+        assert!(stored.crawl_time > stale_time);
+        crawler.robots_cache.lock().unwrap().clear();
+        assert!(!crawler
+            .is_allowed_to_scrape("https://example.com/private/other")
+            .unwrap());
+        assert_eq!(robots_requests(), 1);
+    }
+
+    #[test]
+    fn robots_overrides_decide_admission_and_are_diffed_against_the_live_rules() {
+        let path = std::env::temp_dir().join(format!("rustle-override-{}.txt", std::process::id()));
+        std::fs::write(&path, "User-agent: *\nDisallow: /drafts\n").unwrap();
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://example.com/robots.txt",
+                    200,
+                    &[("content-type", "text/plain")],
+                    b"User-agent: *\nDisallow: /private\n",
+                )
+                .page(
+                    "https://example.com/",
+                    r#"<a href="/private">Private</a> <a href="/drafts">Drafts</a>
+                    <a href="/public">Public</a>"#,
+                )
+                .page("https://example.com/private", "<p>Private</p>")
+                .page("https://example.com/drafts", "<p>Drafts</p>")
+                .page("https://example.com/public", "<p>Public</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.respect_robots = true;
+            config
+                .robots_override
+                .insert("example.com".to_string(), path.clone());
+            config.robots_override_diff = true;
+        });
+        crawler.crawl(false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The override allows what the live rules disallow, and the reverse
+        assert!(fetcher.requested("https://example.com/private"));
+        assert!(!fetcher.requested("https://example.com/drafts"));
+        assert!(fetcher.requested("https://example.com/public"));
+        assert_eq!(
+            crawler.robots_diff(),
+            vec![
+                RobotsDiff {
+                    url: "https://example.com/drafts".to_string(),
+                    allowed_live: true,
+                },
+                RobotsDiff {
+                    url: "https://example.com/private".to_string(),
+                    allowed_live: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_of_a_fully_connected_site_are_fetched_once() {
+        let paths = ["/", "/a", "/b", "/c", "/d"];
+        let links: String = paths
+            .iter()
+            .map(|path| format!(r#"<a href="{}">{}</a>"#, path, path))
+            .collect();
+        let fetcher = Arc::new(
+            paths
+                .iter()
+                .fold(crate::fetcher::MapFetcher::new(), |fetcher, path| {
+                    fetcher.page(&format!("https://example.com{}", path), &links)
+                }),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.depth = 4;
+        });
+        let report = crawler.crawl(false).unwrap();
+        assert_eq!(report.pages_fetched, 5);
+
+        // Every page links to every other one at every depth, yet each is requested only once
+        let mut requested: Vec<String> = fetcher
+            .requests()
+            .into_iter()
+            .map(|(_, url)| url)
+            .filter(|url| !url.ends_with("/robots.txt"))
+            .collect();
+        requested.sort();
+        let expected: Vec<String> = paths
+            .iter()
+            .map(|path| format!("https://example.com{}", path))
+            .collect();
+        assert_eq!(requested, expected);
+    }
+
+    #[test]
+    fn urls_differing_in_query_order_are_crawled_once() {
+        let page = r#"<html><body>
+            <a href="/list?b=2&a=1">Unsorted</a>
+            <a href="/list?a=1&b=2">Sorted</a>
+            <a href="/list?a=%31&utm=&b=2">Encoded</a>
+        </body></html>"#;
+
+        // Without normalize_query, the query is left as it is
+        assert_eq!(
+            crawler(|_| {}).normalize_url("https://example.com/list?b=2&a=1"),
+            Some("https://example.com/list?b=2&a=1".to_string())
+        );
+
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .page("https://example.com/", page)
+                .page("https://example.com/list?a=1&b=2", "<p>List</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.normalize_query = true;
+        });
+        assert_eq!(
+            crawler.normalize_url("https://example.com/list?b=2&a=1"),
+            Some("https://example.com/list?a=1&b=2".to_string())
+        );
+
+        let report = crawler.crawl(true).unwrap();
+        assert!(report.fetch_errors.is_empty());
+        let list_requests = fetcher
+            .requests()
+            .iter()
+            .filter(|(_, url)| url.contains("/list"))
+            .count();
+        assert_eq!(list_requests, 1);
+
+        // The canonical form is the key the page is stored and linked under
+        let origin = crawler
+            .storage
+            .get_site("https://example.com/")
+            .unwrap()
+            .unwrap();
+        let expected: HashSet<String> = ["https://example.com/list?a=1&b=2".to_string()].into();
+        assert_eq!(origin.links_to, expected);
+        assert!(crawler
+            .storage
+            .get_site("https://example.com/list?a=1&b=2")
+            .unwrap()
+            .is_some());
+        assert!(crawler
+            .storage
+            .get_site("https://example.com/list?b=2&a=1")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unicode_and_punycode_hosts_are_one_domain() {
+        let page = r#"<html><body>
+            <a href="https://xn--bcher-kva.example/a">Punycode</a>
+            <a href="https://BÜCHER.example/b">Unicode</a>
+            <a href="/c">Relative</a>
+        </body></html>"#;
+        let fetcher = Arc::new(
+            crate::fetcher::MapFetcher::new()
+                .response(
+                    "https://xn--bcher-kva.example/robots.txt",
+                    200,
+                    &[("content-type", "text/plain")],
+                    b"User-agent: *\nDisallow: /c\n",
+                )
+                .page("https://xn--bcher-kva.example/", page)
+                .page("https://xn--bcher-kva.example/a", "<p>A</p>")
+                .page("https://xn--bcher-kva.example/b", "<p>B</p>"),
+        );
+        let crawler = crawler_with(fetcher.clone(), |config| {
+            config.origin_urls = vec!["https://bücher.example/".to_string()];
+            config.respect_robots = true;
+        });
+        let report = crawler.crawl(true).unwrap();
+        assert!(report.fetch_errors.is_empty());
+
+        // Every request, robots.txt included, went to the punycode host, and robots.txt was
+        // fetched once for both forms
+        let requests = fetcher.requests();
+        assert!(requests
+            .iter()
+            .all(|(_, url)| url.starts_with("https://xn--bcher-kva.example/")));
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|(_, url)| url.ends_with("/robots.txt"))
+                .count(),
+            1
+        );
+
+        // The links of both forms are in scope and stored under the punycode host, and the one
+        // robots.txt applies to them all
+        let origin = crawler
+            .storage
+            .get_site("https://xn--bcher-kva.example/")
+            .unwrap()
+            .unwrap();
+        let expected: HashSet<String> = ["a", "b", "c"]
+            .map(|path| format!("https://xn--bcher-kva.example/{}", path))
+            .into();
+        assert_eq!(origin.links_to, expected);
+        for (path, stored) in [("a", true), ("b", true), ("c", false)] {
+            let url = format!("https://xn--bcher-kva.example/{}", path);
+            assert_eq!(
+                crawler.storage.get_site(&url).unwrap().is_some(),
+                stored,
+                "{}",
+                url
+            );
+        }
+        assert!(crawler
+            .storage
+            .get_domain("xn--bcher-kva.example")
+            .unwrap()
+            .is_some());
+        assert!(crawler
+            .storage
+            .get_domain("bücher.example")
+            .unwrap()
+            .is_none());
     }
 }
@@ -0,0 +1,1305 @@
+use crate::config::{Config, StorageKind};
+use crate::crawl_run::CrawlRun;
+use crate::database::Database;
+use crate::decision::SkipDecision;
+use crate::domain::Domain;
+use crate::fetch_failure::FetchFailure;
+use crate::robots_history::RobotsSnapshot;
+use crate::session::{CrawlSession, SessionStatus};
+use crate::site::{DomainSummary, Site};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A backend the crawler stores its sites, domains and crawl sessions in.
+///
+/// `Database` stores them in SQLite, `JsonlStorage` in a JSON Lines log, and `MemoryStorage` in
+/// memory. A storage is shared by the worker threads of a crawl, so it must be `Sync`.
+pub trait Storage: Send + Sync {
+    /// Creates the tables or files the storage needs, if they don't exist yet.
+    fn setup(&self) -> Result<()>;
+
+    /// Reads the stored site of a URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL of the site.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Site>>` which is `Ok(None)` if the URL isn't stored.
+    fn get_site(&self, url: &str) -> Result<Option<Site>>;
+
+    /// Stores sites, replacing the stored sites of the same URLs, either all of them or none.
+    ///
+    /// # Arguments
+    ///
+    /// * `sites` - The sites to store.
+    fn put_sites(&self, sites: &[Site]) -> Result<()>;
+
+    /// Stores a site, replacing the stored site of the same URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `site` - A reference to the site to store.
+    fn put_site(&self, site: &Site) -> Result<()> {
+        return self.put_sites(std::slice::from_ref(site));
+    }
+
+    /// Updates the crawl time of a stored site found unmodified, along with the details of the
+    /// fetch, keeping the rest of the stored site. See `Database::touch_site` for the updated fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `site` - A reference to the site holding the details of the fetch.
+    fn touch_site(&self, site: &Site) -> Result<()> {
+        let Some(stored) = self.get_site(&site.url)? else {
+            return Ok(());
+        };
+        return self.put_site(&Site {
+            crawl_time: site.crawl_time,
+            generation: site.generation,
+            batch_id: site.batch_id,
+            depth: site.depth,
+            discovered_from: site.discovered_from.clone().or(stored.discovered_from),
+            robots_snapshot: site.robots_snapshot,
+            robots_rule: site.robots_rule.clone(),
+            run_id: site.run_id,
+            ..stored
+        });
+    }
+
+    /// Reads the stored domain of a domain name.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<Domain>>` which is `Ok(None)` if the domain isn't stored.
+    fn get_domain(&self, domain: &str) -> Result<Option<Domain>>;
+
+    /// Stores a domain, replacing the stored domain of the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A reference to the domain to store.
+    fn put_domain(&self, domain: &Domain) -> Result<()>;
+
+    /// Flags a domain as redirecting back and forth between `http` and `https`, storing it
+    /// without a robots.txt if it isn't stored yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    fn mark_broken_tls_redirect(&self, domain: &str) -> Result<()>;
+
+    /// Adds feeds discovered on the pages of a domain to the feeds stored for it, storing the
+    /// domain without a robots.txt if it isn't stored yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `feeds` - The URLs of the discovered feeds.
+    fn add_feeds(&self, domain: &str, feeds: &[String]) -> Result<()>;
+
+    /// Counts the stored sites.
+    fn site_count(&self) -> Result<u64>;
+
+    /// Counts the stored domains.
+    fn domain_count(&self) -> Result<u64>;
+
+    /// Counts the sites written since a time by the class of their status code.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The time the counted sites were written at or after, such as the start of a
+    ///   crawl.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<BTreeMap<Option<i64>, u64>>` holding the number of sites of each status class,
+    /// such as `2` for `2xx`, with `None` for the sites stored without a status code.
+    fn summarize_sites(&self, since: DateTime<Utc>) -> Result<BTreeMap<Option<i64>, u64>>;
+
+    /// Counts the domains written since a time, and logs the problems found on the stored ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The time the counted domains were written at or after.
+    fn summarize_domains(&self, since: DateTime<Utc>) -> Result<u64>;
+
+    /// Summarizes the sites written since a time by host.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The time the summarized sites were written at or after.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<DomainSummary>>` holding the number of sites of every host and the times
+    /// its oldest and newest sites were crawled, the host with the most sites first.
+    fn summarize_by_domain(&self, since: DateTime<Utc>) -> Result<Vec<DomainSummary>>;
+
+    /// Reads the latest accepted crawl session from an origin URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_url` - A string slice that holds the origin URL of the crawl.
+    fn latest_accepted_session(&self, origin_url: &str) -> Result<Option<CrawlSession>>;
+
+    /// Stores a finished crawl session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - A reference to the crawl session.
+    fn put_session(&self, session: &CrawlSession) -> Result<()>;
+
+    /// Records the robots.txt applied to a domain, reusing the latest snapshot of the domain if
+    /// it is the same.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - A string slice that holds the domain name.
+    /// * `robots` - A string slice that holds the raw contents of the robots.txt.
+    /// * `fetch_time` - The time the robots.txt was fetched or read.
+    /// * `robots_override` - The path of the local robots.txt used instead of the live one, if any.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<i64>` holding the id of the snapshot.
+    fn record_robots_snapshot(
+        &self,
+        domain: &str,
+        robots: &str,
+        fetch_time: DateTime<Utc>,
+        robots_override: Option<&str>,
+    ) -> Result<i64>;
+
+    /// Checks whether the visible text of pages can be stored for full-text search.
+    fn has_text_search(&self) -> Result<bool>;
+
+    /// Records the start of a crawl run, so that the sites it stores can reference it. Storages
+    /// without a place for runs don't record them.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_url` - A string slice that holds the origin URLs of the run, separated by
+    ///   spaces.
+    /// * `depth` - The depth the run is configured with.
+    /// * `started_at` - The time the run started.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Option<i64>>` holding the id of the run, or `None` if it isn't recorded.
+    fn start_run(
+        &self,
+        _origin_url: &str,
+        _depth: u64,
+        _started_at: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        return Ok(None);
+    }
+
+    /// Records the end of a crawl run started with `start_run`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the run.
+    /// * `finished_at` - The time the run finished.
+    /// * `pages_fetched` - The number of pages successfully fetched.
+    /// * `errors` - The number of fetches that failed.
+    /// * `status` - The verdict given to the crawl session of the run.
+    fn finish_run(
+        &self,
+        _id: i64,
+        _finished_at: DateTime<Utc>,
+        _pages_fetched: u64,
+        _errors: u64,
+        _status: SessionStatus,
+    ) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Records a failed fetch of a URL, so that failures can be audited and retried after the
+    /// crawl. Storages without a place for failures ignore them.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure` - A reference to the failed fetch.
+    fn record_fetch_failure(&self, _failure: &FetchFailure) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Forgets the recorded failures of URLs that were fetched since.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs that were fetched.
+    fn clear_fetch_failures(&self, _urls: &[&str]) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Deletes the stored text of sites, so that full-text searches no longer find them.
+    /// Storages without full-text search ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs of the sites.
+    fn delete_text(&self, _urls: &[&str]) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Records why discovered URLs were skipped instead of fetched, for `rustle explain`.
+    /// Storages without a place for decisions ignore them.
+    ///
+    /// # Arguments
+    ///
+    /// * `decisions` - A slice of the decisions to record.
+    fn put_skip_decisions(&self, _decisions: &[SkipDecision]) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Forgets the recorded skip decisions of URLs that were stored since.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs that were stored.
+    fn clear_skip_decisions(&self, _urls: &[&str]) -> Result<()> {
+        return Ok(());
+    }
+
+    /// Gets the number of bytes the storage takes up on disk.
+    fn disk_bytes(&self) -> u64 {
+        return 0;
+    }
+
+    /// Writes anything the storage buffers to disk. The crawler calls it at the end of every
+    /// depth level.
+    fn flush(&self) -> Result<()> {
+        return Ok(());
+    }
+}
+
+/// Opens the storage selected by a configuration: the SQLite database named by
+/// `database`, or the JSON Lines log at `storage_path`.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` of the crawl.
+///
+/// # Returns
+///
+/// A `Result<Box<dyn Storage>>` holding the opened storage, or an error if it cannot be opened.
+pub fn open(config: &Config) -> Result<Box<dyn Storage>> {
+    return match config.storage {
+        StorageKind::Sqlite => Ok(Box::new(Database::new(&config.database)?)),
+        StorageKind::Jsonl => Ok(Box::new(JsonlStorage::new(&config.jsonl_path())?)),
+    };
+}
+
+impl Storage for Database {
+    fn setup(&self) -> Result<()> {
+        return Database::setup(self);
+    }
+
+    fn get_site(&self, url: &str) -> Result<Option<Site>> {
+        return self.read_site(url);
+    }
+
+    fn put_sites(&self, sites: &[Site]) -> Result<()> {
+        return self.write_sites(sites);
+    }
+
+    fn touch_site(&self, site: &Site) -> Result<()> {
+        return Database::touch_site(self, site);
+    }
+
+    fn get_domain(&self, domain: &str) -> Result<Option<Domain>> {
+        return self.read_domain(domain);
+    }
+
+    fn put_domain(&self, domain: &Domain) -> Result<()> {
+        return self.write_domain(domain);
+    }
+
+    fn mark_broken_tls_redirect(&self, domain: &str) -> Result<()> {
+        return self.mark_broken_tls_redirect(domain);
+    }
+
+    fn add_feeds(&self, domain: &str, feeds: &[String]) -> Result<()> {
+        return self.add_feeds(domain, feeds);
+    }
+
+    fn site_count(&self) -> Result<u64> {
+        return self.count_rows("sites");
+    }
+
+    fn domain_count(&self) -> Result<u64> {
+        return self.count_rows("domains");
+    }
+
+    fn summarize_sites(&self, since: DateTime<Utc>) -> Result<BTreeMap<Option<i64>, u64>> {
+        return self.summarize_site_table(Some(since));
+    }
+
+    fn summarize_domains(&self, since: DateTime<Utc>) -> Result<u64> {
+        return self.summarize_domain_table(Some(since));
+    }
+
+    fn summarize_by_domain(&self, since: DateTime<Utc>) -> Result<Vec<DomainSummary>> {
+        return self.sites_by_domain(Some(since));
+    }
+
+    fn latest_accepted_session(&self, origin_url: &str) -> Result<Option<CrawlSession>> {
+        return CrawlSession::read_latest_accepted(origin_url, self);
+    }
+
+    fn put_session(&self, session: &CrawlSession) -> Result<()> {
+        return session.write_into(self);
+    }
+
+    fn record_robots_snapshot(
+        &self,
+        domain: &str,
+        robots: &str,
+        fetch_time: DateTime<Utc>,
+        robots_override: Option<&str>,
+    ) -> Result<i64> {
+        return RobotsSnapshot::record(domain, robots, fetch_time, robots_override, self);
+    }
+
+    fn has_text_search(&self) -> Result<bool> {
+        return Database::has_text_search(self);
+    }
+
+    fn start_run(
+        &self,
+        origin_url: &str,
+        depth: u64,
+        started_at: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        return CrawlRun::start(origin_url, depth, started_at, self).map(Some);
+    }
+
+    fn finish_run(
+        &self,
+        id: i64,
+        finished_at: DateTime<Utc>,
+        pages_fetched: u64,
+        errors: u64,
+        status: SessionStatus,
+    ) -> Result<()> {
+        return CrawlRun::finish(id, finished_at, pages_fetched, errors, status, self);
+    }
+
+    fn record_fetch_failure(&self, failure: &FetchFailure) -> Result<()> {
+        return FetchFailure::record(failure, self);
+    }
+
+    fn clear_fetch_failures(&self, urls: &[&str]) -> Result<()> {
+        return FetchFailure::clear(urls, self);
+    }
+
+    fn delete_text(&self, urls: &[&str]) -> Result<()> {
+        return self.delete_text(urls);
+    }
+
+    fn put_skip_decisions(&self, decisions: &[SkipDecision]) -> Result<()> {
+        return SkipDecision::write_many(decisions, self);
+    }
+
+    fn clear_skip_decisions(&self, urls: &[&str]) -> Result<()> {
+        return SkipDecision::clear(urls, self);
+    }
+
+    fn disk_bytes(&self) -> u64 {
+        return Database::disk_bytes(self);
+    }
+}
+
+/// A `Storage` that keeps everything in memory, for crawls whose results are only used by the
+/// program running them, and for testing the crawler without touching disk.
+#[derive(Default)]
+pub struct MemoryStorage {
+    /// The stored sites, keyed by URL.
+    sites: Mutex<HashMap<String, Site>>,
+    /// The stored domains, keyed by domain name.
+    domains: Mutex<HashMap<String, Domain>>,
+    /// The stored crawl sessions, in the order they finished.
+    sessions: Mutex<Vec<CrawlSession>>,
+    /// The recorded robots.txt snapshots, whose ids are their positions starting at 1.
+    snapshots: Mutex<Vec<RobotsSnapshot>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new, empty `MemoryStorage`.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Lists every stored site, in no particular order.
+    pub fn sites(&self) -> Vec<Site> {
+        return self.sites.lock().unwrap().values().cloned().collect();
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn setup(&self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn get_site(&self, url: &str) -> Result<Option<Site>> {
+        return Ok(self.sites.lock().unwrap().get(url).cloned());
+    }
+
+    fn put_sites(&self, sites: &[Site]) -> Result<()> {
+        let mut stored = self.sites.lock().unwrap();
+        for site in sites {
+            stored.insert(site.url.clone(), site.clone());
+        }
+        return Ok(());
+    }
+
+    fn get_domain(&self, domain: &str) -> Result<Option<Domain>> {
+        return Ok(self.domains.lock().unwrap().get(domain).cloned());
+    }
+
+    fn put_domain(&self, domain: &Domain) -> Result<()> {
+        self.domains
+            .lock()
+            .unwrap()
+            .insert(domain.domain.clone(), domain.clone());
+        return Ok(());
+    }
+
+    fn mark_broken_tls_redirect(&self, domain: &str) -> Result<()> {
+        self.domains
+            .lock()
+            .unwrap()
+            .entry(domain.to_string())
+            .or_insert_with(|| Domain {
+                domain: domain.to_string(),
+                crawl_time: DateTime::UNIX_EPOCH,
+                robots: String::new(),
+                robots_url: None,
+                sitemaps: Vec::new(),
+                feeds: Vec::new(),
+                robots_override: None,
+                broken_tls_redirect: false,
+            })
+            .broken_tls_redirect = true;
+        return Ok(());
+    }
+
+    fn add_feeds(&self, domain: &str, feeds: &[String]) -> Result<()> {
+        let mut domains = self.domains.lock().unwrap();
+        let stored = domains.entry(domain.to_string()).or_insert_with(|| Domain {
+            domain: domain.to_string(),
+            crawl_time: DateTime::UNIX_EPOCH,
+            robots: String::new(),
+            robots_url: None,
+            sitemaps: Vec::new(),
+            feeds: Vec::new(),
+            robots_override: None,
+            broken_tls_redirect: false,
+        });
+        for feed in feeds {
+            if !stored.feeds.contains(feed) {
+                stored.feeds.push(feed.clone());
+            }
+        }
+        return Ok(());
+    }
+
+    fn site_count(&self) -> Result<u64> {
+        return Ok(self.sites.lock().unwrap().len() as u64);
+    }
+
+    fn domain_count(&self) -> Result<u64> {
+        return Ok(self.domains.lock().unwrap().len() as u64);
+    }
+
+    fn summarize_sites(&self, since: DateTime<Utc>) -> Result<BTreeMap<Option<i64>, u64>> {
+        let mut counts = BTreeMap::new();
+        for site in self.sites.lock().unwrap().values() {
+            if site.crawl_time < since {
+                continue;
+            }
+            let class = site.status_code.map(|status| status as i64 / 100);
+            *counts.entry(class).or_insert(0) += 1;
+        }
+        return Ok(counts);
+    }
+
+    fn summarize_domains(&self, since: DateTime<Utc>) -> Result<u64> {
+        return Ok(self
+            .domains
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|domain| domain.crawl_time >= since)
+            .count() as u64);
+    }
+
+    fn summarize_by_domain(&self, since: DateTime<Utc>) -> Result<Vec<DomainSummary>> {
+        let sites = self.sites.lock().unwrap();
+        return Ok(DomainSummary::from_sites(
+            sites
+                .values()
+                .filter(|site| site.crawl_time >= since)
+                .map(|site| (site.url.as_str(), site.crawl_time)),
+        ));
+    }
+
+    fn latest_accepted_session(&self, origin_url: &str) -> Result<Option<CrawlSession>> {
+        return Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|session| {
+                session.origin_url == origin_url && session.status == SessionStatus::Accepted
+            })
+            .cloned());
+    }
+
+    fn put_session(&self, session: &CrawlSession) -> Result<()> {
+        self.sessions.lock().unwrap().push(session.clone());
+        return Ok(());
+    }
+
+    fn record_robots_snapshot(
+        &self,
+        domain: &str,
+        robots: &str,
+        fetch_time: DateTime<Utc>,
+        robots_override: Option<&str>,
+    ) -> Result<i64> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if let Some(latest) = snapshots.iter().rev().find(|s| s.domain == domain) {
+            if latest.robots == robots && latest.robots_override.as_deref() == robots_override {
+                return Ok(latest.id);
+            }
+        }
+
+        let id = snapshots.len() as i64 + 1;
+        snapshots.push(RobotsSnapshot {
+            id,
+            domain: domain.to_string(),
+            fetch_time,
+            robots: robots.to_string(),
+            robots_override: robots_override.map(str::to_string),
+        });
+        return Ok(id);
+    }
+
+    fn has_text_search(&self) -> Result<bool> {
+        return Ok(false);
+    }
+}
+
+/// A line of the log of a `JsonlStorage`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonlRecord {
+    /// A fetched page.
+    Page {
+        /// The URL of the page.
+        url: String,
+        /// The time the page was crawled, as an RFC 3339 timestamp.
+        crawl_time: String,
+        /// The URLs the page links to, sorted.
+        links_to: Vec<String>,
+        /// The HTTP status code of the response, if it was recorded.
+        status: Option<u16>,
+        /// The URLs of `links_to` that aren't followed because of their `rel` attribute, sorted.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        nofollow_links: Vec<String>,
+        /// Whether the page only redirects to the URL it links to.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_redirect: bool,
+        /// The `ETag` header of the response, if it had one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        etag: Option<String>,
+        /// The `Last-Modified` header of the response, if it had one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_modified: Option<String>,
+        /// The hash of the content of the page, if it was read.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_hash: Option<String>,
+    },
+    /// A crawled domain.
+    Domain {
+        /// The domain name.
+        domain: String,
+        /// The time the robots.txt of the domain was fetched, as an RFC 3339 timestamp.
+        crawl_time: String,
+        /// The raw contents of the robots.txt of the domain.
+        robots: String,
+        /// The URL the robots.txt was fetched from, if it was fetched.
+        robots_url: Option<String>,
+        /// The sitemaps listed in the robots.txt of the domain.
+        sitemaps: Vec<String>,
+        /// The feeds discovered on the pages of the domain.
+        #[serde(default)]
+        feeds: Vec<String>,
+        /// The path of the local robots.txt used instead of the live one, if any.
+        robots_override: Option<String>,
+        /// Whether the domain redirects back and forth between `http` and `https`.
+        broken_tls_redirect: bool,
+    },
+}
+
+impl From<&Site> for JsonlRecord {
+    fn from(site: &Site) -> Self {
+        let mut links_to: Vec<String> = site.links_to.iter().cloned().collect();
+        links_to.sort();
+        let mut nofollow_links: Vec<String> = site.nofollow_links.iter().cloned().collect();
+        nofollow_links.sort();
+        return JsonlRecord::Page {
+            url: site.url.clone(),
+            crawl_time: site.crawl_time.to_rfc3339(),
+            links_to,
+            status: site.status_code,
+            nofollow_links,
+            is_redirect: site.is_redirect,
+            etag: site.etag.clone(),
+            last_modified: site.last_modified.clone(),
+            content_hash: site.content_hash.clone(),
+        };
+    }
+}
+
+impl From<&Domain> for JsonlRecord {
+    fn from(domain: &Domain) -> Self {
+        return JsonlRecord::Domain {
+            domain: domain.domain.clone(),
+            crawl_time: domain.crawl_time.to_rfc3339(),
+            robots: domain.robots.clone(),
+            robots_url: domain.robots_url.clone(),
+            sitemaps: domain.sitemaps.clone(),
+            feeds: domain.feeds.clone(),
+            robots_override: domain.robots_override.clone(),
+            broken_tls_redirect: domain.broken_tls_redirect,
+        };
+    }
+}
+
+/// A `Storage` that appends every stored page and domain to a JSON Lines log, one JSON object
+/// per line, for ad-hoc crawls whose results are read with `grep` or `jq` rather than SQL.
+///
+/// Pages are logged with their URL, crawl time, links, status code, and what later crawls need
+/// to revalidate them: their nofollow links, redirect flag, validators and content hash. A page
+/// or domain stored again is appended again, and the last line of a URL or domain wins. The log
+/// is read back into an in-memory index when the storage is opened, so that cached pages are
+/// skipped across crawls. A last line cut short by a crash is dropped. Crawl sessions and
+/// robots.txt snapshots are only kept in memory.
+///
+/// ```
+/// use rustle::{Domain, JsonlStorage, Storage};
+///
+/// let path = std::env::temp_dir().join(format!("rustle-doc-{}.jsonl", std::process::id()));
+/// let storage = JsonlStorage::new(&path)?;
+/// storage.put_domain(&Domain {
+///     domain: "example.com".to_string(),
+///     crawl_time: chrono::Utc::now(),
+///     robots: "User-agent: *\nDisallow:\n".to_string(),
+///     robots_url: Some("https://example.com/robots.txt".to_string()),
+///     sitemaps: Vec::new(),
+///     feeds: Vec::new(),
+///     robots_override: None,
+///     broken_tls_redirect: false,
+/// })?;
+/// storage.flush()?;
+///
+/// let reopened = JsonlStorage::new(&path)?;
+/// assert_eq!(reopened.domain_count()?, 1);
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), rustle::Error>(())
+/// ```
+pub struct JsonlStorage {
+    /// The path of the log.
+    path: PathBuf,
+    /// The log, opened for appending.
+    log: Mutex<BufWriter<File>>,
+    /// The pages and domains of the log, along with the sessions and snapshots of this run.
+    index: MemoryStorage,
+}
+
+impl JsonlStorage {
+    /// Opens the JSON Lines log at a path, creating it if it doesn't exist, and reads the pages
+    /// and domains it already holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the log, such as `crawl.jsonl`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<JsonlStorage>` holding the opened storage.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the log cannot be opened, or if one of its lines
+    /// is not a valid record. An invalid last line that doesn't end with a newline was cut short
+    /// while being written, and is truncated away instead.
+    pub fn new(path: &Path) -> Result<Self> {
+        let index = MemoryStorage::new();
+        // The length of the log up to its last complete line, and whether the log ends with a
+        // valid record lacking its newline
+        let mut valid_len = 0;
+        let mut unterminated = false;
+        if path.exists() {
+            let file =
+                File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+            let mut reader = BufReader::new(file);
+            let mut line = Vec::new();
+            let mut number = 0;
+            loop {
+                line.clear();
+                let read = reader
+                    .read_until(b'\n', &mut line)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                if read == 0 {
+                    break;
+                }
+                number += 1;
+                let complete = line.ends_with(b"\n");
+                if line.trim_ascii().is_empty() {
+                    valid_len += read as u64;
+                    continue;
+                }
+                let record = match serde_json::from_slice(&line) {
+                    Ok(record) => record,
+                    Err(e) if !complete => {
+                        warn!(
+                            "Dropping the partial record on line {} of {}: {}",
+                            number,
+                            path.display(),
+                            e
+                        );
+                        break;
+                    }
+                    Err(e) => {
+                        return Err(e).with_context(|| {
+                            format!("Invalid record on line {} of {}", number, path.display())
+                        });
+                    }
+                };
+                Self::load_record(record, &index).with_context(|| {
+                    format!("Invalid record on line {} of {}", number, path.display())
+                })?;
+                valid_len += read as u64;
+                unterminated = !complete;
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        // Records are appended after the last complete line, so a torn one never merges with them
+        if file.metadata()?.len() > valid_len {
+            file.set_len(valid_len)
+                .with_context(|| format!("Failed to truncate {}", path.display()))?;
+        }
+        if unterminated {
+            file.write_all(b"\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        return Ok(JsonlStorage {
+            path: path.to_path_buf(),
+            log: Mutex::new(BufWriter::new(file)),
+            index,
+        });
+    }
+
+    /// Adds a record read from the log to the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The `JsonlRecord` read from the log.
+    /// * `index` - A reference to the `MemoryStorage` the record is added to.
+    fn load_record(record: JsonlRecord, index: &MemoryStorage) -> Result<()> {
+        match record {
+            JsonlRecord::Page {
+                url,
+                crawl_time,
+                links_to,
+                status,
+                nofollow_links,
+                is_redirect,
+                etag,
+                last_modified,
+                content_hash,
+            } => {
+                index.put_site(&Site {
+                    url,
+                    crawl_time: DateTime::parse_from_rfc3339(&crawl_time)?.with_timezone(&Utc),
+                    links_to: links_to.into_iter().collect(),
+                    status_code: status,
+                    nofollow_links: nofollow_links.into_iter().collect(),
+                    is_redirect,
+                    etag,
+                    last_modified,
+                    content_hash,
+                    ..Default::default()
+                })?;
+            }
+            JsonlRecord::Domain {
+                domain,
+                crawl_time,
+                robots,
+                robots_url,
+                sitemaps,
+                feeds,
+                robots_override,
+                broken_tls_redirect,
+            } => {
+                index.put_domain(&Domain {
+                    domain,
+                    crawl_time: DateTime::parse_from_rfc3339(&crawl_time)?.with_timezone(&Utc),
+                    robots,
+                    robots_url,
+                    sitemaps,
+                    feeds,
+                    robots_override,
+                    broken_tls_redirect,
+                })?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Appends records to the log, one per line.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The records to append.
+    fn append(&self, records: &[JsonlRecord]) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        for record in records {
+            serde_json::to_writer(&mut *log, record)?;
+            log.write_all(b"\n")?;
+        }
+        return Ok(());
+    }
+}
+
+impl Storage for JsonlStorage {
+    fn setup(&self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn get_site(&self, url: &str) -> Result<Option<Site>> {
+        return self.index.get_site(url);
+    }
+
+    fn put_sites(&self, sites: &[Site]) -> Result<()> {
+        let records: Vec<JsonlRecord> = sites.iter().map(JsonlRecord::from).collect();
+        self.append(&records)?;
+        return self.index.put_sites(sites);
+    }
+
+    fn get_domain(&self, domain: &str) -> Result<Option<Domain>> {
+        return self.index.get_domain(domain);
+    }
+
+    fn put_domain(&self, domain: &Domain) -> Result<()> {
+        self.append(&[JsonlRecord::from(domain)])?;
+        return self.index.put_domain(domain);
+    }
+
+    fn mark_broken_tls_redirect(&self, domain: &str) -> Result<()> {
+        self.index.mark_broken_tls_redirect(domain)?;
+        if let Some(domain) = self.index.get_domain(domain)? {
+            self.append(&[JsonlRecord::from(&domain)])?;
+        }
+        return Ok(());
+    }
+
+    fn add_feeds(&self, domain: &str, feeds: &[String]) -> Result<()> {
+        self.index.add_feeds(domain, feeds)?;
+        if let Some(domain) = self.index.get_domain(domain)? {
+            self.append(&[JsonlRecord::from(&domain)])?;
+        }
+        return Ok(());
+    }
+
+    fn site_count(&self) -> Result<u64> {
+        return self.index.site_count();
+    }
+
+    fn domain_count(&self) -> Result<u64> {
+        return self.index.domain_count();
+    }
+
+    fn summarize_sites(&self, since: DateTime<Utc>) -> Result<BTreeMap<Option<i64>, u64>> {
+        return self.index.summarize_sites(since);
+    }
+
+    fn summarize_domains(&self, since: DateTime<Utc>) -> Result<u64> {
+        return self.index.summarize_domains(since);
+    }
+
+    fn summarize_by_domain(&self, since: DateTime<Utc>) -> Result<Vec<DomainSummary>> {
+        return self.index.summarize_by_domain(since);
+    }
+
+    fn latest_accepted_session(&self, origin_url: &str) -> Result<Option<CrawlSession>> {
+        return self.index.latest_accepted_session(origin_url);
+    }
+
+    fn put_session(&self, session: &CrawlSession) -> Result<()> {
+        self.flush()?;
+        return self.index.put_session(session);
+    }
+
+    fn record_robots_snapshot(
+        &self,
+        domain: &str,
+        robots: &str,
+        fetch_time: DateTime<Utc>,
+        robots_override: Option<&str>,
+    ) -> Result<i64> {
+        return self
+            .index
+            .record_robots_snapshot(domain, robots, fetch_time, robots_override);
+    }
+
+    fn has_text_search(&self) -> Result<bool> {
+        return Ok(false);
+    }
+
+    fn disk_bytes(&self) -> u64 {
+        return std::fs::metadata(&self.path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+    }
+
+    fn flush(&self) -> Result<()> {
+        return self
+            .log
+            .lock()
+            .unwrap()
+            .flush()
+            .with_context(|| format!("Failed to write {}", self.path.display()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::collections::HashSet;
+
+    fn site(url: &str, crawl_time: DateTime<Utc>, status_code: Option<u16>) -> Site {
+        return Site {
+            url: url.to_string(),
+            crawl_time,
+            status_code,
+            ..Default::default()
+        };
+    }
+
+    fn domain(name: &str, crawl_time: DateTime<Utc>) -> Domain {
+        return Domain {
+            domain: name.to_string(),
+            crawl_time,
+            robots: "User-agent: *\nDisallow: /private".to_string(),
+            robots_url: None,
+            sitemaps: Vec::new(),
+            feeds: Vec::new(),
+            robots_override: None,
+            broken_tls_redirect: false,
+        };
+    }
+
+    #[test]
+    fn memory_storage_keeps_the_latest_copy_of_each_site_and_domain() {
+        let storage = MemoryStorage::new();
+        let now = Utc::now();
+        storage
+            .put_sites(&[
+                site("https://example.com/", now, Some(200)),
+                site("https://example.com/a", now, Some(404)),
+            ])
+            .unwrap();
+        storage
+            .put_site(&site("https://example.com/a", now, Some(200)))
+            .unwrap();
+        storage.put_domain(&domain("example.com", now)).unwrap();
+
+        assert_eq!(storage.site_count().unwrap(), 2);
+        assert_eq!(storage.domain_count().unwrap(), 1);
+        let stored = storage.get_site("https://example.com/a").unwrap().unwrap();
+        assert_eq!(stored.status_code, Some(200));
+        assert!(storage.get_site("https://example.org/").unwrap().is_none());
+        let stored = storage.get_domain("example.com").unwrap().unwrap();
+        assert_eq!(stored.robots, "User-agent: *\nDisallow: /private");
+    }
+
+    #[test]
+    fn memory_storage_summaries_only_count_what_was_stored_since() {
+        let storage = MemoryStorage::new();
+        let now = Utc::now();
+        let earlier = now - Duration::hours(1);
+        storage
+            .put_sites(&[
+                site("https://example.com/", earlier, Some(200)),
+                site("https://example.com/a", now, Some(200)),
+                site("https://example.com/b", now, Some(503)),
+                site("https://example.org/", now, None),
+            ])
+            .unwrap();
+        storage.put_domain(&domain("example.com", earlier)).unwrap();
+        storage.put_domain(&domain("example.org", now)).unwrap();
+
+        let counts = storage.summarize_sites(now).unwrap();
+        assert_eq!(
+            counts,
+            BTreeMap::from([(None, 1), (Some(2), 1), (Some(5), 1)])
+        );
+        assert_eq!(storage.summarize_domains(now).unwrap(), 1);
+        assert_eq!(storage.summarize_domains(earlier).unwrap(), 2);
+
+        let summaries = storage.summarize_by_domain(now).unwrap();
+        let pages: Vec<(&str, u64)> = summaries
+            .iter()
+            .map(|summary| (summary.host.as_str(), summary.pages))
+            .collect();
+        assert_eq!(pages, [("example.com", 2), ("example.org", 1)]);
+    }
+
+    #[test]
+    fn memory_storage_touches_only_the_details_of_the_fetch() {
+        let storage = MemoryStorage::new();
+        let earlier = Utc::now() - Duration::hours(1);
+        storage
+            .put_site(&Site {
+                content_hash: Some("abc".to_string()),
+                discovered_from: Some("https://example.com/".to_string()),
+                ..site("https://example.com/a", earlier, Some(200))
+            })
+            .unwrap();
+
+        let now = Utc::now();
+        storage
+            .touch_site(&Site {
+                depth: Some(2),
+                ..site("https://example.com/a", now, Some(304))
+            })
+            .unwrap();
+        let stored = storage.get_site("https://example.com/a").unwrap().unwrap();
+        assert_eq!(stored.crawl_time, now);
+        assert_eq!(stored.depth, Some(2));
+        assert_eq!(stored.status_code, Some(200));
+        assert_eq!(stored.content_hash.as_deref(), Some("abc"));
+        assert_eq!(
+            stored.discovered_from.as_deref(),
+            Some("https://example.com/")
+        );
+
+        // Touching a site that was never stored doesn't store it
+        storage
+            .touch_site(&site("https://example.com/b", now, Some(304)))
+            .unwrap();
+        assert!(storage.get_site("https://example.com/b").unwrap().is_none());
+    }
+
+    #[test]
+    fn memory_storage_updates_domains_it_has_not_stored() {
+        let storage = MemoryStorage::new();
+        storage
+            .add_feeds("example.com", &["https://example.com/feed".to_string()])
+            .unwrap();
+        storage
+            .add_feeds(
+                "example.com",
+                &[
+                    "https://example.com/feed".to_string(),
+                    "https://example.com/atom".to_string(),
+                ],
+            )
+            .unwrap();
+        storage.mark_broken_tls_redirect("example.com").unwrap();
+
+        let stored = storage.get_domain("example.com").unwrap().unwrap();
+        assert_eq!(
+            stored.feeds,
+            ["https://example.com/feed", "https://example.com/atom"]
+        );
+        assert!(stored.broken_tls_redirect);
+    }
+
+    #[test]
+    fn memory_storage_reuses_unchanged_robots_snapshots() {
+        let storage = MemoryStorage::new();
+        let now = Utc::now();
+        let first = storage
+            .record_robots_snapshot("example.com", "Disallow: /a", now, None)
+            .unwrap();
+        let same = storage
+            .record_robots_snapshot("example.com", "Disallow: /a", now, None)
+            .unwrap();
+        let other = storage
+            .record_robots_snapshot("example.org", "Disallow: /a", now, None)
+            .unwrap();
+        let changed = storage
+            .record_robots_snapshot("example.com", "Disallow: /b", now, None)
+            .unwrap();
+        assert_eq!(first, same);
+        assert_ne!(first, other);
+        assert_ne!(other, changed);
+    }
+
+    #[test]
+    fn memory_storage_has_no_text_search() {
+        let storage = MemoryStorage::new();
+        assert!(!storage.has_text_search().unwrap());
+
+        let config = Config {
+            store_text: true,
+            ..Default::default()
+        };
+        let error = crate::spider::Crawler::with_storage(config, Box::new(storage))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("full-text search"));
+    }
+
+    /// Gets a path for a JSON Lines log in the temporary directory, removing any left over.
+    fn log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "rustle-storage-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        return path;
+    }
+
+    #[test]
+    fn jsonl_storage_keeps_what_revalidation_needs() {
+        let path = log_path("fields");
+        let storage = JsonlStorage::new(&path).unwrap();
+        storage
+            .put_site(&Site {
+                links_to: HashSet::from([
+                    "https://example.com/a".to_string(),
+                    "https://example.com/ad".to_string(),
+                ]),
+                nofollow_links: HashSet::from(["https://example.com/ad".to_string()]),
+                etag: Some("\"v1\"".to_string()),
+                last_modified: Some("Wed, 01 May 2024 12:00:00 GMT".to_string()),
+                content_hash: Some("abc".to_string()),
+                ..site("https://example.com/", Utc::now(), Some(200))
+            })
+            .unwrap();
+        storage
+            .put_site(&Site {
+                links_to: HashSet::from(["https://example.com/".to_string()]),
+                is_redirect: true,
+                ..site("http://example.com/", Utc::now(), Some(301))
+            })
+            .unwrap();
+        storage.flush().unwrap();
+
+        let reopened = JsonlStorage::new(&path).unwrap();
+        let page = reopened.get_site("https://example.com/").unwrap().unwrap();
+        assert_eq!(
+            page.nofollow_links,
+            HashSet::from(["https://example.com/ad".to_string()])
+        );
+        assert_eq!(page.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(
+            page.last_modified.as_deref(),
+            Some("Wed, 01 May 2024 12:00:00 GMT")
+        );
+        assert_eq!(page.content_hash.as_deref(), Some("abc"));
+        assert!(!page.is_redirect);
+        let redirect = reopened.get_site("http://example.com/").unwrap().unwrap();
+        assert!(redirect.is_redirect);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn jsonl_storage_drops_a_torn_last_line() {
+        let path = log_path("torn");
+        let storage = JsonlStorage::new(&path).unwrap();
+        storage
+            .put_site(&site("https://example.com/", Utc::now(), Some(200)))
+            .unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(br#"{"type":"page","url":"https://example.com/a","crawl_ti"#)
+            .unwrap();
+        drop(log);
+
+        let reopened = JsonlStorage::new(&path).unwrap();
+        assert_eq!(reopened.site_count().unwrap(), 1);
+        reopened
+            .put_site(&site("https://example.com/b", Utc::now(), Some(200)))
+            .unwrap();
+        reopened.flush().unwrap();
+        drop(reopened);
+
+        let reopened = JsonlStorage::new(&path).unwrap();
+        assert_eq!(reopened.site_count().unwrap(), 2);
+        assert!(reopened
+            .get_site("https://example.com/a")
+            .unwrap()
+            .is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn jsonl_storage_keeps_a_complete_last_line_without_its_newline() {
+        let path = log_path("unterminated");
+        let storage = JsonlStorage::new(&path).unwrap();
+        storage
+            .put_site(&site("https://example.com/", Utc::now(), Some(200)))
+            .unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, log.trim_end()).unwrap();
+
+        let reopened = JsonlStorage::new(&path).unwrap();
+        reopened
+            .put_site(&site("https://example.com/b", Utc::now(), Some(200)))
+            .unwrap();
+        reopened.flush().unwrap();
+        drop(reopened);
+
+        let reopened = JsonlStorage::new(&path).unwrap();
+        assert_eq!(reopened.site_count().unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn jsonl_storage_rejects_an_invalid_complete_line() {
+        let path = log_path("invalid");
+        std::fs::write(
+            &path,
+            "{\"type\":\"page\",\"url\":\n{\"type\":\"domain\"}\n",
+        )
+        .unwrap();
+
+        let error = JsonlStorage::new(&path).err().unwrap();
+        assert!(error.to_string().contains("Invalid record on line 1"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use crate::config::Config;
+use std::fmt;
+use url::Url;
+
+/// The rules that reject the URLs of crawler traps, such as calendars, faceted navigation and
+/// session id generators, whose links lead into endless URL spaces.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TrapRule {
+    /// The URL is longer than `max_url_length`.
+    UrlLength,
+    /// The path of the URL has more segments than `max_path_depth`.
+    PathDepth,
+    /// A segment of the path of the URL repeats more than `max_repeated_segments` times in a
+    /// row.
+    RepeatedSegment,
+}
+
+impl TrapRule {
+    /// Finds the first rule that rejects a URL. A limit of 0 turns its rule off.
+    ///
+    /// ```
+    /// use rustle::{Config, TrapRule};
+    /// use url::Url;
+    ///
+    /// let config = Config {
+    ///     max_url_length: 40,
+    ///     max_path_depth: 4,
+    ///     max_repeated_segments: 2,
+    ///     ..Default::default()
+    /// };
+    /// let check = |url: &str| TrapRule::check(&Url::parse(url).unwrap(), &config);
+    /// assert_eq!(check("https://example.com/a/a/b/"), None);
+    /// assert_eq!(check("https://example.com/a/a/a/"), Some(TrapRule::RepeatedSegment));
+    /// assert_eq!(check("https://example.com/a/b/c/d/e"), Some(TrapRule::PathDepth));
+    /// assert_eq!(
+    ///     check("https://example.com/?session=0123456789abcdef"),
+    ///     Some(TrapRule::UrlLength)
+    /// );
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A reference to the parsed URL.
+    /// * `config` - A reference to the `Config` holding the limits of the rules.
+    ///
+    /// # Returns
+    ///
+    /// The `TrapRule` that rejects the URL, or `None` if it is accepted.
+    pub fn check(url: &Url, config: &Config) -> Option<TrapRule> {
+        if config.max_url_length > 0 && url.as_str().len() > config.max_url_length {
+            return Some(TrapRule::UrlLength);
+        }
+
+        // Empty segments, such as the one after a trailing slash, are not counted
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+            .unwrap_or_default();
+        if config.max_path_depth > 0 && segments.len() > config.max_path_depth {
+            return Some(TrapRule::PathDepth);
+        }
+
+        if config.max_repeated_segments > 0 {
+            let mut repeats = 0;
+            for (index, segment) in segments.iter().enumerate() {
+                if index > 0 && segments[index - 1] == *segment {
+                    repeats += 1;
+                } else {
+                    repeats = 1;
+                }
+                if repeats > config.max_repeated_segments {
+                    return Some(TrapRule::RepeatedSegment);
+                }
+            }
+        }
+        return None;
+    }
+}
+
+impl fmt::Display for TrapRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TrapRule::UrlLength => "URL length",
+            TrapRule::PathDepth => "path depth",
+            TrapRule::RepeatedSegment => "repeated segment",
+        };
+        return write!(f, "{}", name);
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+/// The expected crawl size up to which visited URLs are kept in full.
+pub const EXACT_VISITED_LIMIT: u64 = 1_000_000;
+
+/// The set of URLs a crawl has already visited, checked before every URL is queued so that no URL
+/// is fetched twice.
+///
+/// Small crawls keep every URL in full, so that membership is exact. Crawls expected to visit
+/// more than `EXACT_VISITED_LIMIT` URLs only keep a 64-bit hash of each, which takes a fraction
+/// of the memory of long URL strings. Two URLs with the same hash are then taken for one
+/// another, and the second is skipped as already visited. That is unlikely to happen even once
+/// in a crawl of billions of URLs, and is never an error.
+///
+/// The set grows as URLs are visited rather than being allocated for the expected size up
+/// front, so an overestimate costs nothing. Only the visited set is shrunk this way: the URLs
+/// waiting in the `Frontier` are kept in full, and so are the per-URL records of the `Crawler`,
+/// such as the referrer of every URL not stored yet, the response time of every fetch and the
+/// URLs rejected as traps.
+pub enum VisitedSet {
+    /// Every visited URL, in full.
+    Exact(HashSet<String>),
+    /// The hash of every visited URL.
+    Hashed(HashSet<u64>),
+}
+
+impl VisitedSet {
+    /// Creates an empty `VisitedSet` suited to the expected size of a crawl.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_size` - The number of URLs the crawl is expected to visit, if it is known.
+    ///
+    /// # Returns
+    ///
+    /// A `VisitedSet` keeping URLs in full, unless more than `EXACT_VISITED_LIMIT` are
+    /// expected.
+    pub fn with_expected_size(expected_size: Option<u64>) -> Self {
+        return match expected_size {
+            Some(size) if size > EXACT_VISITED_LIMIT => VisitedSet::Hashed(HashSet::new()),
+            _ => VisitedSet::Exact(HashSet::new()),
+        };
+    }
+
+    /// Adds a URL to the set.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the visited URL.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating whether the URL was not in the set yet.
+    pub fn insert(&mut self, url: &str) -> bool {
+        return match self {
+            VisitedSet::Exact(urls) => urls.insert(url.to_string()),
+            VisitedSet::Hashed(hashes) => hashes.insert(Self::hash(url)),
+        };
+    }
+
+    /// Hashes a URL into the 64 bits kept by a hashed set.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice that holds the URL.
+    pub(crate) fn hash(url: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        return hasher.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visited_urls_are_remembered() {
+        let mut visited = VisitedSet::with_expected_size(Some(50_000_000));
+        assert!(visited.insert("https://example.com/"));
+        assert!(!visited.insert("https://example.com/"));
+        assert!(visited.insert("https://example.com/about"));
+    }
+
+    #[test]
+    fn colliding_urls_are_skipped_as_visited() {
+        // As if another URL with the same hash as the second page had been visited
+        let colliding = VisitedSet::hash("https://example.com/second");
+        let mut visited = VisitedSet::Hashed(HashSet::from([colliding]));
+        assert!(visited.insert("https://example.com/first"));
+        assert!(!visited.insert("https://example.com/second"));
+        assert!(!visited.insert("https://example.com/second"));
+
+        // Small crawls keep URLs in full, and never take one for another
+        let mut visited = VisitedSet::with_expected_size(Some(1_000));
+        assert!(matches!(visited, VisitedSet::Exact(_)));
+        assert!(visited.insert("https://example.com/first"));
+        assert!(visited.insert("https://example.com/second"));
+    }
+
+    #[test]
+    fn huge_expected_sizes_allocate_nothing_up_front() {
+        let visited = VisitedSet::with_expected_size(Some(u64::MAX));
+        match visited {
+            VisitedSet::Hashed(hashes) => assert_eq!(hashes.capacity(), 0),
+            VisitedSet::Exact(_) => panic!("a huge crawl should only keep hashes"),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::config::Config;
+use crate::output::Document;
+use crate::proxy::ProxyConfig;
+use crate::report::CrawlReport;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// The time a webhook is given to answer a notification, connection included.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The time waited before sending a notification the webhook failed to accept once more.
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The outcome of a crawl a webhook can be notified of.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookEvent {
+    /// The crawl finished with an exit code of 0.
+    Success,
+    /// The crawl failed with an error, every one of its fetches failed, or it was marked as
+    /// suspect: its exit code isn't 0.
+    Failure,
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            WebhookEvent::Success => "success",
+            WebhookEvent::Failure => "failure",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// The JSON body posted to `webhook_url` once a crawl finishes or fails, as a versioned document
+/// such as:
+///
+/// ```json
+/// {
+///   "format_version": 1,
+///   "generator": "rustle 0.0.1",
+///   "report": "webhook",
+///   "text": "Rustle crawl of https://example.com succeeded: 120 pages fetched, 3 failed in 42.0s",
+///   "outcome": "success",
+///   "origin_url": "https://example.com",
+///   "pages_fetched": 120,
+///   "fetch_errors": 3,
+///   "duration_secs": 42.0,
+///   "session_status": "accepted",
+///   "error": null
+/// }
+/// ```
+///
+/// `text` is displayed as is by Slack incoming webhooks, while the other fields are meant for
+/// services parsing the payload.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct WebhookPayload {
+    /// A one-line summary of the outcome of the crawl.
+    pub text: String,
+    /// Whether the crawl succeeded or failed.
+    pub outcome: WebhookEvent,
+    /// The origin URL of the crawl, or its origin URLs separated by spaces.
+    pub origin_url: String,
+    /// The number of pages successfully fetched, 0 if the crawl failed with an error.
+    pub pages_fetched: u64,
+    /// The number of URLs that could not be fetched, 0 if the crawl failed with an error.
+    pub fetch_errors: u64,
+    /// The number of seconds the crawl took.
+    pub duration_secs: f64,
+    /// The verdict given to the crawl session, `accepted` or `suspect`, or `None` if the crawl
+    /// failed with an error.
+    pub session_status: Option<String>,
+    /// The error the crawl failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl WebhookPayload {
+    /// The name of the report held by the posted document.
+    pub const NAME: &'static str = "webhook";
+
+    /// Serializes the payload to the compact JSON of its versioned document.
+    pub fn to_json(&self) -> Result<String> {
+        return Ok(serde_json::to_string(&Document::new(Self::NAME, self))?);
+    }
+
+    /// Creates the payload of a crawl that finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - A reference to the `CrawlReport` of the crawl.
+    /// * `outcome` - Whether the crawl succeeded, going by its exit code.
+    pub fn from_report(report: &CrawlReport, outcome: WebhookEvent) -> Self {
+        let duration_secs = round_secs(report.elapsed);
+        let text = format!(
+            "Rustle crawl of {} {}: {} pages fetched, {} failed in {:.1}s",
+            report.session.origin_url,
+            match outcome {
+                WebhookEvent::Success => "succeeded",
+                WebhookEvent::Failure => "failed",
+            },
+            report.pages_fetched,
+            report.fetch_errors.len(),
+            duration_secs
+        );
+        return WebhookPayload {
+            text,
+            outcome,
+            origin_url: report.session.origin_url.clone(),
+            pages_fetched: report.pages_fetched,
+            fetch_errors: report.fetch_errors.len() as u64,
+            duration_secs,
+            session_status: Some(report.session.status.to_string()),
+            error: None,
+        };
+    }
+
+    /// Creates the payload of a crawl that failed with an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_urls` - The origin URLs of the crawl.
+    /// * `error` - A reference to the error the crawl failed with.
+    /// * `elapsed` - The time the crawl ran for before failing.
+    pub fn from_error(origin_urls: &[String], error: &anyhow::Error, elapsed: Duration) -> Self {
+        let origin_url = origin_urls.join(" ");
+        let duration_secs = round_secs(elapsed);
+        return WebhookPayload {
+            text: format!("Rustle crawl of {} failed: {:#}", origin_url, error),
+            outcome: WebhookEvent::Failure,
+            origin_url,
+            pages_fetched: 0,
+            fetch_errors: 0,
+            duration_secs,
+            session_status: None,
+            error: Some(format!("{:#}", error)),
+        };
+    }
+}
+
+/// Rounds a duration to tenths of a second.
+fn round_secs(duration: Duration) -> f64 {
+    return (duration.as_secs_f64() * 10.0).round() / 10.0;
+}
+
+/// The webhook notified of the outcome of every crawl, set by `webhook_url` and `webhook_on`.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    /// The URL the payload is posted to.
+    pub url: String,
+    /// The outcomes the webhook is notified of.
+    pub on: Vec<WebhookEvent>,
+    /// The `User-Agent` header of the notifications.
+    pub user_agent: String,
+    /// The proxy the notifications go through, as every request of the crawl, or `None` for
+    /// the proxy environment variables.
+    pub proxy: Option<ProxyConfig>,
+}
+
+impl Webhook {
+    /// Gets the webhook of a config, if `webhook_url` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A reference to the `Config` of the crawl.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        return config.webhook_url.as_ref().map(|url| Webhook {
+            url: url.clone(),
+            on: config.webhook_on.clone(),
+            user_agent: config.user_agent.clone(),
+            proxy: config.proxy.clone(),
+        });
+    }
+
+    /// Posts a payload to the webhook, unless its outcome isn't one the webhook is notified of.
+    /// A notification the webhook fails to accept is sent once more after a second.
+    ///
+    /// This function blocks, and must not be called from an async context.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - A reference to the `WebhookPayload` to post.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<bool>` holding whether the payload was posted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the proxy URL is invalid, or if both attempts fail
+    /// to connect, time out, or get an error status back.
+    pub fn notify(&self, payload: &WebhookPayload) -> Result<bool> {
+        if !self.on.contains(&payload.outcome) {
+            return Ok(false);
+        }
+        let body = payload.to_json()?;
+        let mut builder = reqwest::blocking::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .user_agent(&self.user_agent);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.build()?);
+        }
+        let client = builder.build()?;
+        let post = || {
+            return client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .and_then(|response| response.error_for_status());
+        };
+
+        if let Err(e) = post() {
+            warn!("Failed to notify webhook {}, retrying: {}", self.url, e);
+            std::thread::sleep(WEBHOOK_RETRY_DELAY);
+            post().with_context(|| format!("Failed to notify webhook {}", self.url))?;
+        }
+        info!(
+            "Notified webhook {} of the {} of the crawl",
+            self.url, payload.outcome
+        );
+        return Ok(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{FORMAT_VERSION, GENERATOR};
+    use serde_json::json;
+
+    #[test]
+    fn payload_is_posted_as_a_versioned_document() {
+        let error = anyhow::anyhow!("connection refused");
+        let payload = WebhookPayload::from_error(
+            &["https://example.com".to_string()],
+            &error,
+            Duration::from_millis(1234),
+        );
+        let document: serde_json::Value =
+            serde_json::from_str(&payload.to_json().unwrap()).unwrap();
+        assert_eq!(
+            document,
+            json!({
+                "format_version": FORMAT_VERSION,
+                "generator": GENERATOR,
+                "report": "webhook",
+                "text": "Rustle crawl of https://example.com failed: connection refused",
+                "outcome": "failure",
+                "origin_url": "https://example.com",
+                "pages_fetched": 0,
+                "fetch_errors": 0,
+                "duration_secs": 1.2,
+                "session_status": null,
+                "error": "connection refused"
+            })
+        );
+
+        // The payload itself can still be read back from the document
+        let read: WebhookPayload = serde_json::from_value(document).unwrap();
+        assert_eq!(read, payload);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::config::{Config, Scope};
+use crate::database::Database;
+use crate::proxy::ProxyConfig;
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+/// The number of pages fetched by the preview crawl offered at the end of the wizard.
+pub const PREVIEW_PAGES: u64 = 50;
+
+/// A function that checks whether a seed URL is reachable.
+pub type UrlCheck = Box<dyn Fn(&str) -> Result<()>>;
+
+/// Represents the answers collected by a completed `Wizard` run.
+pub struct WizardOutcome {
+    /// The configuration built from the user's answers.
+    pub config: Config,
+    /// Whether the user asked for a preview crawl to be run immediately.
+    pub run_preview: bool,
+}
+
+/// An interactive wizard that walks a first-time user through creating a configuration.
+///
+/// The wizard only talks to the generic `input` and `output` streams and checks seed URLs through
+/// the `check_url` function, so it can be driven by a terminal or by scripted answers alike.
+pub struct Wizard<R: BufRead, W: Write> {
+    /// The stream the answers are read from.
+    input: R,
+    /// The stream the prompts are written to.
+    output: W,
+    /// A function that checks whether a seed URL is reachable.
+    check_url: UrlCheck,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    /// Creates a new instance of the `Wizard` struct.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream the answers are read from.
+    /// * `output` - The stream the prompts are written to.
+    /// * `check_url` - A function that checks whether a seed URL is reachable, such as `head_check`.
+    pub fn new(input: R, output: W, check_url: UrlCheck) -> Self {
+        return Wizard {
+            input,
+            output,
+            check_url,
+        };
+    }
+
+    /// Runs the wizard, asking every question in turn.
+    ///
+    /// Each answer is applied to a candidate configuration that is checked with
+    /// `Config::validate`; invalid answers are reported and the question is asked again.
+    ///
+    /// # Returns
+    ///
+    /// A `WizardOutcome` holding the resulting configuration and whether to run a preview crawl.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input ends early or the streams fail.
+    pub fn run(&mut self) -> Result<WizardOutcome> {
+        let mut config = Config::default();
+        writeln!(
+            self.output,
+            "Welcome to Rustle! Press enter to accept the [default]."
+        )?;
+
+        // Seed URL, checked live with a HEAD request
+        loop {
+            self.ask_valid(
+                &mut config,
+                "Seed URL to start crawling from",
+                "",
+                |c, a| {
+                    c.origin_urls = vec![a.to_string()];
+                    return Ok(());
+                },
+            )?;
+            match (self.check_url)(&config.origin_urls[0]) {
+                Ok(()) => break,
+                Err(e) => writeln!(
+                    self.output,
+                    "Could not reach {}: {:#}",
+                    config.origin_urls[0], e
+                )?,
+            }
+        }
+
+        // Depth
+        let default = config.depth.to_string();
+        self.ask_valid(&mut config, "Crawl depth", &default, |c, a| {
+            c.depth = a.parse().context("depth must be a whole number")?;
+            return Ok(());
+        })?;
+
+        // Scope
+        let default = config.scope.to_string();
+        self.ask_valid(
+            &mut config,
+            "Scope (host = same domain, subdomains, site = same registrable domain, any = anywhere)",
+            &default,
+            |c, a| {
+                c.scope = a.parse::<Scope>()?;
+                return Ok(());
+            },
+        )?;
+
+        // Politeness delay
+        let default = config.delay_ms.to_string();
+        self.ask_valid(
+            &mut config,
+            "Politeness delay between requests, in milliseconds",
+            &default,
+            |c, a| {
+                c.delay_ms = a.parse().context("delay must be a whole number")?;
+                return Ok(());
+            },
+        )?;
+
+        // Robots.txt
+        writeln!(
+            self.output,
+            "Sites use robots.txt to tell crawlers which pages they may visit. Ignoring it can get \
+             you blocked, so only do so for sites you own."
+        )?;
+        config.respect_robots = self.ask_bool("Respect robots.txt?", config.respect_robots)?;
+
+        // Database
+        let default = Database::path_for(&config.database).display().to_string();
+        self.ask_valid(&mut config, "Database path", &default, |c, a| {
+            c.database = a.to_string();
+            return Ok(());
+        })?;
+
+        // Preview crawl
+        let run_preview = self.ask_bool(
+            &format!("Run a {}-page preview crawl now?", PREVIEW_PAGES),
+            true,
+        )?;
+
+        return Ok(WizardOutcome {
+            config,
+            run_preview,
+        });
+    }
+
+    /// Asks a question until the answer applies cleanly and the configuration validates.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration the answer is applied to once it is valid.
+    /// * `question` - A string slice that holds the question to ask.
+    /// * `default` - A string slice that holds the answer used when the user just presses enter.
+    /// * `apply` - A function that applies an answer to a candidate configuration.
+    fn ask_valid(
+        &mut self,
+        config: &mut Config,
+        question: &str,
+        default: &str,
+        apply: impl Fn(&mut Config, &str) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            let answer = self.ask(question, default)?;
+            let mut candidate = config.clone();
+            match apply(&mut candidate, &answer).and_then(|_| candidate.validate()) {
+                Ok(()) => {
+                    *config = candidate;
+                    return Ok(());
+                }
+                Err(e) => writeln!(self.output, "{:#}", e)?,
+            }
+        }
+    }
+
+    /// Asks a yes/no question.
+    ///
+    /// # Arguments
+    ///
+    /// * `question` - A string slice that holds the question to ask.
+    /// * `default` - The answer used when the user just presses enter.
+    fn ask_bool(&mut self, question: &str, default: bool) -> Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{} ({})", question, hint), "")?;
+            match answer.to_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(self.output, "Please answer 'y' or 'n'")?,
+            }
+        }
+    }
+
+    /// Asks a question and reads a single line answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `question` - A string slice that holds the question to ask.
+    /// * `default` - A string slice that holds the answer used when the user just presses enter.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input ends before an answer is given.
+    fn ask(&mut self, question: &str, default: &str) -> Result<String> {
+        if default.is_empty() {
+            write!(self.output, "{}: ", question)?;
+        } else {
+            write!(self.output, "{} [{}]: ", question, default)?;
+        }
+        self.output.flush()?;
+
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            bail!("Input ended before the wizard was finished");
+        }
+
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(default.to_string());
+        }
+        return Ok(answer.to_string());
+    }
+}
+
+/// Checks that a URL is reachable by sending it a quick HEAD request.
+///
+/// # Arguments
+///
+/// * `url` - A string slice that holds the URL to check.
+/// * `proxy` - The proxy the request goes through, as the requests of a crawl would, or `None`
+///   for the proxy environment variables.
+///
+/// # Errors
+///
+/// This function will return an error if the proxy URL is invalid, if the request fails or if
+/// the response is an error status.
+pub fn head_check(url: &str, proxy: Option<&ProxyConfig>) -> Result<()> {
+    let mut builder = reqwest::blocking::Client::builder().timeout(Duration::from_secs(10));
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.build()?);
+    }
+    let client = builder.build()?;
+    client.head(url).send()?.error_for_status()?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Runs the wizard on scripted answers, treating every seed URL as reachable but those
+    /// listed in `unreachable`.
+    fn run(answers: &str, unreachable: &'static [&'static str]) -> (Result<WizardOutcome>, String) {
+        let mut output = Vec::new();
+        let check_url: UrlCheck = Box::new(move |url| {
+            if unreachable.contains(&url) {
+                bail!("connection refused");
+            }
+            return Ok(());
+        });
+        let outcome = Wizard::new(Cursor::new(answers), &mut output, check_url).run();
+        return (outcome, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn defaults_are_used_for_empty_answers() {
+        let (outcome, output) = run("https://example.com\n\n\n\n\n\n\n", &[]);
+        let outcome = outcome.unwrap();
+
+        let default = Config::default();
+        assert_eq!(outcome.config.origin_urls, vec!["https://example.com"]);
+        assert_eq!(outcome.config.depth, default.depth);
+        assert_eq!(outcome.config.scope, default.scope);
+        assert_eq!(outcome.config.delay_ms, default.delay_ms);
+        assert!(outcome.config.respect_robots);
+        assert_eq!(outcome.config.database, "crawler.db");
+        assert!(outcome.run_preview);
+        assert!(output.contains("Database path [crawler.db]: "));
+    }
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let answers = [
+            "ftp://example.com",
+            "https://down.example.com",
+            "https://example.com",
+            "0",
+            "deep",
+            "5",
+            "moon",
+            "subdomains",
+            "soon",
+            "250",
+            "maybe",
+            "n",
+            "/tmp/crawls/site.db",
+            "n",
+        ];
+        let (outcome, output) = run(&answers.join("\n"), &["https://down.example.com"]);
+        let outcome = outcome.unwrap();
+
+        assert_eq!(outcome.config.origin_urls, vec!["https://example.com"]);
+        assert_eq!(outcome.config.depth, 5);
+        assert_eq!(outcome.config.scope, Scope::Subdomains);
+        assert_eq!(outcome.config.delay_ms, 250);
+        assert!(!outcome.config.respect_robots);
+        assert_eq!(outcome.config.database, "/tmp/crawls/site.db");
+        assert!(!outcome.run_preview);
+        assert!(output.contains("must use the http or https scheme"));
+        assert!(output.contains("Could not reach https://down.example.com: connection refused"));
+        assert!(output.contains("depth 0 must be between 1 and"));
+        assert!(output.contains("depth must be a whole number"));
+        assert!(output.contains("Unknown scope 'moon'"));
+        assert!(output.contains("delay must be a whole number"));
+        assert!(output.contains("Please answer 'y' or 'n'"));
+    }
+
+    #[test]
+    fn input_ending_early_fails() {
+        let (outcome, _) = run("https://example.com\n3\n", &[]);
+        assert!(outcome.is_err());
+    }
+}
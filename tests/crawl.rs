@@ -0,0 +1,457 @@
+//! Crawls a fixture site served over HTTP on an ephemeral port, through the blocking client and
+//! into a database in a temporary file, and checks the rows stored for it.
+//!
+//! The fixture site has a dozen interlinked pages, a robots.txt excluding a directory, a
+//! redirect, a page in Windows-1252, a slow page and one slower than the timeout of the crawl.
+//! The webhook notified of the outcome of a crawl is served alongside it.
+
+use rustle::{
+    Config, CrawlReport, Crawler, ErrorCategory, FixtureResponse, FixtureServer, ProxyConfig, Site,
+    WebhookEvent, WebhookPayload,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The depth of the crawls of the fixture site.
+const DEPTH: u64 = 2;
+
+/// The robots.txt of the fixture site.
+const ROBOTS: &str = "User-agent: *\nDisallow: /private/\n";
+
+/// The time `/slow` takes to answer, within the timeout of the crawl.
+const SLOW_LATENCY: Duration = Duration::from_millis(300);
+
+/// The time `/stalled` takes to answer, past the timeout of the crawl.
+const STALLED_LATENCY: Duration = Duration::from_millis(2500);
+
+/// The HTML pages of the fixture site, as their path, title and links.
+///
+/// `/old` redirects to `/news`, `/latin1` is served in Windows-1252 and `/stalled` never answers
+/// in time, so they are not listed here. `/team/alumni` is one level deeper than the crawl, and
+/// `/private/prices` both deeper and disallowed.
+const PAGES: &[(&str, &str, &[&str])] = &[
+    (
+        "/",
+        "Home",
+        &[
+            "/about",
+            "/products",
+            "/old",
+            "/private/admin",
+            "/latin1",
+            "/slow",
+            "/stalled",
+            "https://example.org/",
+        ],
+    ),
+    ("/about", "About", &["/team", "/contact", "/"]),
+    ("/team", "Team", &["/team/alumni", "/about"]),
+    ("/team/alumni", "Alumni", &[]),
+    ("/contact", "Contact", &["/"]),
+    (
+        "/products",
+        "Products",
+        &["/products/widget", "/products/gadget"],
+    ),
+    ("/products/widget", "Widget", &["/products/gadget"]),
+    ("/products/gadget", "Gadget", &["/private/prices"]),
+    ("/news", "News", &["/news/archive", "/"]),
+    ("/news/archive", "Archive", &[]),
+    ("/slow", "Slow", &[]),
+    ("/private/admin", "Admin", &[]),
+    ("/private/prices", "Prices", &[]),
+];
+
+/// The body of `/latin1`, whose title is "Café" in Windows-1252.
+const LATIN1_PAGE: &[u8] = b"<html><head><title>Caf\xe9</title></head><body>\
+<a href=\"/contact\">Contact</a></body></html>";
+
+/// Serves the fixture site from a thread of its own, and remembers the paths requested.
+struct FixtureSite {
+    /// The server of the site.
+    server: FixtureServer,
+}
+
+impl FixtureSite {
+    /// Starts serving the fixture site on an ephemeral port.
+    fn start() -> Self {
+        let server = FixtureServer::start(|request| respond(&request.path))
+            .expect("Failed to start the fixture server");
+        return FixtureSite { server };
+    }
+
+    /// Gets the full URL of a path of the site.
+    fn url(&self, path: &str) -> String {
+        return self.server.url(path);
+    }
+
+    /// Gets the paths requested so far, along with the number of times each was.
+    fn requested(&self) -> BTreeMap<String, usize> {
+        let mut requested = BTreeMap::new();
+        for request in self.server.requests() {
+            *requested.entry(request.path).or_insert(0) += 1;
+        }
+        return requested;
+    }
+
+    /// Gets the config of a crawl of the site into a database in a temporary file.
+    fn config(&self, database: &TempDatabase) -> Config {
+        return Config {
+            origin_urls: vec![self.url("/")],
+            database: database.path.to_string_lossy().into_owned(),
+            depth: DEPTH,
+            timeout_secs: 1,
+            final_retry_limit: 0,
+            ..Config::default()
+        };
+    }
+
+    /// Crawls the site into a database in a temporary file.
+    fn crawl(&self, database: &TempDatabase) -> (Crawler, CrawlReport) {
+        let crawler = Crawler::new(self.config(database)).expect("Failed to create the crawler");
+        let report = crawler
+            .crawl(true)
+            .expect("Failed to crawl the fixture site");
+        return (crawler, report);
+    }
+}
+
+/// Answers a request for a path of the fixture site.
+fn respond(path: &str) -> FixtureResponse {
+    let html = |body: Vec<u8>, charset: &str| {
+        return FixtureResponse::new(200, body)
+            .header("Content-Type", &format!("text/html; charset={}", charset));
+    };
+    match path {
+        "/robots.txt" => {
+            return FixtureResponse::new(200, ROBOTS).header("Content-Type", "text/plain");
+        }
+        "/old" => return FixtureResponse::new(301, "").header("Location", "/news"),
+        "/latin1" => return html(LATIN1_PAGE.to_vec(), "windows-1252"),
+        "/slow" => std::thread::sleep(SLOW_LATENCY),
+        "/stalled" => std::thread::sleep(STALLED_LATENCY),
+        _ => {}
+    }
+    return match PAGES.iter().find(|(page, ..)| *page == path) {
+        Some((_, title, links)) => {
+            let anchors: String = links
+                .iter()
+                .map(|link| format!("<a href=\"{}\">{}</a>\n", link, link))
+                .collect();
+            let body = format!(
+                "<html><head><title>{}</title></head><body>\n{}</body></html>",
+                title, anchors
+            );
+            FixtureResponse::html(200, body)
+        }
+        None => FixtureResponse::html(404, "<html><title>Not Found</title></html>"),
+    };
+}
+
+/// Receives the notifications of a webhook on an ephemeral port, and remembers their bodies.
+struct WebhookListener {
+    /// The URL notifications are posted to.
+    url: String,
+    /// The server receiving the notifications.
+    server: FixtureServer,
+}
+
+impl WebhookListener {
+    /// Starts receiving notifications, answering the first `failures` of them with a server
+    /// error.
+    fn start(failures: usize) -> Self {
+        let received = AtomicUsize::new(0);
+        let server = FixtureServer::start(move |_| {
+            let status = if received.fetch_add(1, Ordering::SeqCst) < failures {
+                500
+            } else {
+                200
+            };
+            return FixtureResponse::new(status, "");
+        })
+        .expect("Failed to start the webhook listener");
+        return WebhookListener {
+            url: server.url("/hook"),
+            server,
+        };
+    }
+
+    /// Gets the payloads of the notifications received so far.
+    fn payloads(&self) -> Vec<WebhookPayload> {
+        return self
+            .server
+            .requests()
+            .iter()
+            .map(|request| {
+                serde_json::from_slice(&request.body).expect("The payload is not valid JSON")
+            })
+            .collect();
+    }
+}
+
+/// The path of a database in a temporary file, removed along with its journal once dropped.
+struct TempDatabase {
+    /// The path of the database file.
+    path: PathBuf,
+}
+
+impl TempDatabase {
+    /// Picks the path of a database in the temporary directory, unique to a test.
+    fn new(test: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("rustle-test-{}-{}.db", std::process::id(), test));
+        return TempDatabase { path };
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Reads a stored page of the fixture site, failing the test if it wasn't stored.
+fn site(crawler: &Crawler, fixture: &FixtureSite, path: &str) -> Site {
+    return crawler
+        .storage()
+        .get_site(&fixture.url(path))
+        .unwrap()
+        .unwrap_or_else(|| panic!("{} was not stored", path));
+}
+
+#[test]
+fn pages_within_depth_are_stored_and_requested_once() {
+    let fixture = FixtureSite::start();
+    let database = TempDatabase::new("depths");
+    let (crawler, report) = fixture.crawl(&database);
+
+    let depths: BTreeMap<String, Option<u64>> = PAGES
+        .iter()
+        .map(|(path, ..)| *path)
+        .chain(["/old", "/latin1", "/stalled"])
+        .filter_map(|path| {
+            let site = crawler.storage().get_site(&fixture.url(path)).unwrap()?;
+            return Some((path.to_string(), site.depth));
+        })
+        .collect();
+    let expected: BTreeMap<String, Option<u64>> = [
+        ("/", Some(0)),
+        ("/about", Some(1)),
+        ("/products", Some(1)),
+        ("/old", None),
+        ("/news", Some(1)),
+        ("/latin1", Some(1)),
+        ("/slow", Some(1)),
+        ("/team", Some(2)),
+        ("/contact", Some(2)),
+        ("/products/widget", Some(2)),
+        ("/products/gadget", Some(2)),
+        ("/news/archive", Some(2)),
+    ]
+    .into_iter()
+    .map(|(path, depth)| (path.to_string(), depth))
+    .collect();
+    assert_eq!(depths, expected);
+    assert_eq!(report.max_depth, DEPTH);
+    assert_eq!(report.stored_domains, 1);
+
+    // Every stored page was requested once, along with robots.txt and the page that timed out
+    let requested = fixture.requested();
+    let mut expected: BTreeSet<&str> = expected.keys().map(String::as_str).collect();
+    expected.extend(["/robots.txt", "/stalled"]);
+    assert_eq!(
+        requested
+            .keys()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>(),
+        expected
+    );
+    assert!(
+        requested.values().all(|count| *count == 1),
+        "{:?}",
+        requested
+    );
+
+    let about = site(&crawler, &fixture, "/about");
+    assert_eq!(about.title.as_deref(), Some("About"));
+    assert_eq!(about.discovered_from, Some(fixture.url("/")));
+    assert!(about.links_to.contains(&fixture.url("/team")));
+    assert!(about.links_to.contains(&fixture.url("/contact")));
+}
+
+#[test]
+fn robots_txt_exclusions_are_neither_requested_nor_stored() {
+    let fixture = FixtureSite::start();
+    let database = TempDatabase::new("robots");
+    let (crawler, report) = fixture.crawl(&database);
+
+    let domain = crawler
+        .storage()
+        .get_domain("127.0.0.1")
+        .unwrap()
+        .expect("The host of the fixture site was not stored");
+    assert_eq!(domain.robots, ROBOTS);
+    assert_eq!(domain.robots_url, Some(fixture.url("/robots.txt")));
+
+    assert_eq!(report.pages_blocked_by_robots, 1);
+    assert!(site(&crawler, &fixture, "/")
+        .links_to
+        .contains(&fixture.url("/private/admin")));
+    assert!(crawler
+        .storage()
+        .get_site(&fixture.url("/private/admin"))
+        .unwrap()
+        .is_none());
+    assert!(!fixture
+        .requested()
+        .keys()
+        .any(|path| path.starts_with("/private/")));
+}
+
+#[test]
+fn redirects_are_stored_along_with_their_target() {
+    let fixture = FixtureSite::start();
+    let database = TempDatabase::new("redirects");
+    let (crawler, _) = fixture.crawl(&database);
+
+    let old = site(&crawler, &fixture, "/old");
+    assert_eq!(old.status_code, Some(301));
+    assert!(old.is_redirect);
+    assert_eq!(old.final_url, Some(fixture.url("/news")));
+    assert_eq!(old.redirect_hops, Some(1));
+
+    let news = site(&crawler, &fixture, "/news");
+    assert_eq!(news.status_code, Some(200));
+    assert_eq!(news.title.as_deref(), Some("News"));
+    assert_eq!(news.depth, Some(1));
+}
+
+#[test]
+fn non_utf8_pages_are_decoded_from_their_charset() {
+    let fixture = FixtureSite::start();
+    let database = TempDatabase::new("charset");
+    let (crawler, _) = fixture.crawl(&database);
+
+    let latin1 = site(&crawler, &fixture, "/latin1");
+    assert_eq!(latin1.status_code, Some(200));
+    assert_eq!(latin1.title.as_deref(), Some("Café"));
+    assert!(latin1.links_to.contains(&fixture.url("/contact")));
+}
+
+#[test]
+fn slow_pages_are_stored_and_stalled_pages_time_out() {
+    let fixture = FixtureSite::start();
+    let database = TempDatabase::new("timeouts");
+    let (crawler, report) = fixture.crawl(&database);
+
+    let slow = site(&crawler, &fixture, "/slow");
+    assert_eq!(slow.status_code, Some(200));
+    assert!(slow.response_ms >= Some(SLOW_LATENCY.as_millis() as u64));
+
+    assert!(crawler
+        .storage()
+        .get_site(&fixture.url("/stalled"))
+        .unwrap()
+        .is_none());
+    let errors: Vec<(&str, ErrorCategory)> = report
+        .fetch_errors
+        .iter()
+        .map(|error| (error.url.as_str(), error.category))
+        .collect();
+    assert_eq!(
+        errors,
+        [(fixture.url("/stalled").as_str(), ErrorCategory::Timeout)]
+    );
+}
+
+#[test]
+fn webhook_is_notified_of_the_outcome_of_the_crawl() {
+    let fixture = FixtureSite::start();
+    let webhook = WebhookListener::start(0);
+    let database = TempDatabase::new("webhook");
+    let config = Config {
+        webhook_url: Some(webhook.url.clone()),
+        ..fixture.config(&database)
+    };
+    let exit_code = rustle::cli_crawl(config, true).expect("Failed to crawl the fixture site");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let payloads = webhook.payloads();
+    assert_eq!(payloads.len(), 1);
+    let payload = &payloads[0];
+    assert_eq!(payload.outcome, WebhookEvent::Success);
+    assert_eq!(payload.origin_url, fixture.url("/"));
+    assert_eq!(payload.pages_fetched, 11);
+    assert_eq!(payload.fetch_errors, 1);
+    // The crawl waited for /stalled to time out
+    assert!(payload.duration_secs >= 1.0);
+    assert_eq!(payload.session_status.as_deref(), Some("accepted"));
+    assert_eq!(payload.error, None);
+    assert!(payload
+        .text
+        .contains("succeeded: 11 pages fetched, 1 failed in"));
+}
+
+#[test]
+fn webhook_failures_are_retried_once_and_never_fail_the_crawl() {
+    let fixture = FixtureSite::start();
+    let webhook = WebhookListener::start(usize::MAX);
+    let database = TempDatabase::new("webhook-failures");
+    let config = Config {
+        webhook_url: Some(webhook.url.clone()),
+        ..fixture.config(&database)
+    };
+    let exit_code = rustle::cli_crawl(config, true).expect("Failed to crawl the fixture site");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let payloads = webhook.payloads();
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0], payloads[1]);
+}
+
+#[test]
+fn webhook_is_only_notified_of_the_outcomes_of_webhook_on() {
+    let fixture = FixtureSite::start();
+    let webhook = WebhookListener::start(0);
+    let database = TempDatabase::new("webhook-on");
+    let config = Config {
+        webhook_url: Some(webhook.url.clone()),
+        webhook_on: vec![WebhookEvent::Failure],
+        ..fixture.config(&database)
+    };
+    let exit_code = rustle::cli_crawl(config, true).expect("Failed to crawl the fixture site");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert!(webhook.payloads().is_empty());
+}
+
+#[test]
+fn webhook_is_notified_through_the_configured_proxy() {
+    let fixture = FixtureSite::start();
+    // The listener stands in for the proxy, and the fixture site is reached directly
+    let proxy = WebhookListener::start(0);
+    let database = TempDatabase::new("webhook-proxy");
+    let config = Config {
+        webhook_url: Some("http://webhook.invalid/hook".to_string()),
+        proxy: Some(ProxyConfig {
+            url: proxy.server.base().to_string(),
+            username: None,
+            password: None,
+            no_proxy: vec!["127.0.0.1".to_string()],
+        }),
+        ..fixture.config(&database)
+    };
+    let exit_code = rustle::cli_crawl(config, true).expect("Failed to crawl the fixture site");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let requests = proxy.server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "http://webhook.invalid/hook");
+    assert_eq!(proxy.payloads()[0].outcome, WebhookEvent::Success);
+}